@@ -0,0 +1,104 @@
+//! Benchmarks for the drawing pipeline: scaling the capture for display,
+//! rasterizing annotations and encoding the export.
+//!
+//! Run with `cargo bench`; criterion compares each run with the last one.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gtk4::gdk::RGBA;
+use gtk4::gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use std::hint::black_box;
+
+use screenshot_core::editor::golden::rasterize;
+use screenshot_core::editor::{
+    Annotation, AnnotationList, FreeDrawAnnotation, RectangleAnnotation,
+};
+use screenshot_core::export::{optimize_png, RgbaBuffer};
+
+const WIDTH: i32 = 1920;
+const HEIGHT: i32 = 1080;
+
+/// A screen-sized capture with some structure, so PNG encoding isn't trivial
+fn capture() -> Pixbuf {
+    let pixbuf = Pixbuf::new(Colorspace::Rgb, true, 8, WIDTH, HEIGHT).unwrap();
+    pixbuf.fill(0xf6f5f4ff);
+    for i in 0..40 {
+        let tile = pixbuf.new_subpixbuf(
+            (i * 47) % (WIDTH - 200),
+            (i * 29) % (HEIGHT - 120),
+            200,
+            120,
+        );
+        tile.fill(0x3584e4ff ^ ((i as u32) << 12));
+    }
+    pixbuf
+}
+
+/// One long scribble of `points` points across the whole capture
+fn scribble(points: usize) -> AnnotationList {
+    let mut stroke = FreeDrawAnnotation::new(RGBA::new(0.88, 0.11, 0.14, 1.0), 3.0);
+    for i in 0..points {
+        let t = i as f64 / points as f64;
+        let x = t * WIDTH as f64;
+        let y = HEIGHT as f64 / 2.0 + (t * 200.0).sin() * HEIGHT as f64 / 3.0;
+        stroke.add_point(x, y);
+    }
+
+    let mut list = AnnotationList::new();
+    list.add(Annotation::FreeDraw(stroke));
+    list.add(Annotation::Rectangle(RectangleAnnotation::new(
+        100.0,
+        100.0,
+        600.0,
+        400.0,
+        RGBA::new(0.21, 0.52, 0.89, 1.0),
+        3.0,
+    )));
+    list
+}
+
+fn bench_scaling(c: &mut Criterion) {
+    let pixbuf = capture();
+    let mut group = c.benchmark_group("scale_pixbuf");
+    for (name, interp) in [
+        ("nearest", InterpType::Nearest),
+        ("bilinear", InterpType::Bilinear),
+    ] {
+        group.bench_function(name, |b| {
+            b.iter(|| black_box(pixbuf.scale_simple(WIDTH / 2, HEIGHT / 2, interp)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_annotations(c: &mut Criterion) {
+    let mut group = c.benchmark_group("rasterize_annotations");
+    for points in [1_000, 10_000, 50_000] {
+        let annotations = scribble(points);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(points),
+            &annotations,
+            |b, annotations| {
+                b.iter(|| {
+                    rasterize(WIDTH, HEIGHT, |cr| annotations.draw_all(cr, 1.0, 0.0, 0.0)).unwrap()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn bench_export(c: &mut Criterion) {
+    let pixbuf = capture();
+    let mut group = c.benchmark_group("export");
+    group.sample_size(10);
+    group.bench_function("png", |b| {
+        b.iter(|| pixbuf.save_to_bufferv("png", &[]).unwrap())
+    });
+    group.bench_function("optimized_png", |b| {
+        b.iter(|| optimize_png(&RgbaBuffer::from_pixbuf(&pixbuf)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scaling, bench_annotations, bench_export);
+criterion_main!(benches);
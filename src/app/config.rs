@@ -6,18 +6,26 @@ use std::collections::HashMap;
 pub enum Action {
     Copy,
     Save,
+    /// Repeat the last export of the current image
+    ExportAgain,
+    /// Post the image to the configured chat
+    SendToChat,
     Undo,
+    Redo,
     Cancel,
     Confirm,
     ToolPointer,
     ToolPencil,
     ToolRectangle,
     ToolText,
+    ToolCounter,
     ToolCrop,
     SwitchToSelection,
     SwitchToWindow,
     SwitchToScreen,
     TakeScreenshot,
+    /// Switch the drawing color to a palette slot, counting from zero
+    ColorSlot(usize),
 }
 
 impl Action {
@@ -26,18 +34,23 @@ impl Action {
         match self {
             Action::Copy => "Copy to Clipboard",
             Action::Save => "Save to File",
+            Action::ExportAgain => "Export Again",
+            Action::SendToChat => "Send to Chat",
             Action::Undo => "Undo",
+            Action::Redo => "Redo",
             Action::Cancel => "Cancel / Exit",
             Action::Confirm => "Confirm Selection",
             Action::ToolPointer => "Select Pointer Tool",
             Action::ToolPencil => "Select Pencil Tool",
             Action::ToolRectangle => "Select Rectangle Tool",
             Action::ToolText => "Select Text Tool",
+            Action::ToolCounter => "Select Counter Tool",
             Action::ToolCrop => "Select Crop Tool",
             Action::SwitchToSelection => "Switch to Selection Mode",
             Action::SwitchToWindow => "Switch to Window Mode",
             Action::SwitchToScreen => "Switch to Screen Mode",
             Action::TakeScreenshot => "Take Screenshot",
+            Action::ColorSlot(_) => "Switch to Palette Color",
         }
     }
 }
@@ -72,6 +85,20 @@ impl Default for ShortcutConfig {
                 modifiers: gdk::ModifierType::CONTROL_MASK,
             },
         );
+        bindings.insert(
+            Action::ExportAgain,
+            Shortcut {
+                key: gdk::Key::e,
+                modifiers: gdk::ModifierType::CONTROL_MASK,
+            },
+        );
+        bindings.insert(
+            Action::SendToChat,
+            Shortcut {
+                key: gdk::Key::e,
+                modifiers: gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK,
+            },
+        );
         bindings.insert(
             Action::Undo,
             Shortcut {
@@ -79,6 +106,13 @@ impl Default for ShortcutConfig {
                 modifiers: gdk::ModifierType::CONTROL_MASK,
             },
         );
+        bindings.insert(
+            Action::Redo,
+            Shortcut {
+                key: gdk::Key::z,
+                modifiers: gdk::ModifierType::CONTROL_MASK | gdk::ModifierType::SHIFT_MASK,
+            },
+        );
         bindings.insert(
             Action::Cancel,
             Shortcut {
@@ -123,6 +157,13 @@ impl Default for ShortcutConfig {
                 modifiers: gdk::ModifierType::empty(),
             },
         );
+        bindings.insert(
+            Action::ToolCounter,
+            Shortcut {
+                key: gdk::Key::n,
+                modifiers: gdk::ModifierType::empty(),
+            },
+        );
         bindings.insert(
             Action::ToolCrop,
             Shortcut {
@@ -163,6 +204,28 @@ impl Default for ShortcutConfig {
             },
         );
 
+        // Palette Colors
+        let digits = [
+            gdk::Key::_1,
+            gdk::Key::_2,
+            gdk::Key::_3,
+            gdk::Key::_4,
+            gdk::Key::_5,
+            gdk::Key::_6,
+            gdk::Key::_7,
+            gdk::Key::_8,
+            gdk::Key::_9,
+        ];
+        for (slot, key) in digits.into_iter().enumerate() {
+            bindings.insert(
+                Action::ColorSlot(slot),
+                Shortcut {
+                    key,
+                    modifiers: gdk::ModifierType::empty(),
+                },
+            );
+        }
+
         Self { bindings }
     }
 }
@@ -182,6 +245,8 @@ impl ShortcutConfig {
             | gdk::ModifierType::META_MASK;
 
         let clean_mods = modifiers & mask;
+        // Shift turns letters uppercase, as in Ctrl+Shift+Z
+        let key = key.to_lower();
 
         for (action, shortcut) in &self.bindings {
             if shortcut.key == key && shortcut.modifiers == clean_mods {
@@ -208,6 +273,13 @@ impl ShortcutConfig {
     }
 
     #[allow(dead_code)]
+    /// Key of `action` as shown to the user, such as "Enter"
+    pub fn get_key_label(&self, action: Action) -> Option<String> {
+        self.bindings
+            .get(&action)
+            .map(|sc| gtk::accelerator_get_label(sc.key, sc.modifiers).to_string())
+    }
+
     pub fn set_shortcut(&mut self, action: Action, key: gdk::Key, modifiers: gdk::ModifierType) {
         self.bindings.insert(action, Shortcut { key, modifiers });
     }
@@ -0,0 +1,70 @@
+//! Credentials kept in the system keyring through the Secret Service, using
+//! libsecret's `secret-tool`, so tokens and passwords stay out of the
+//! preferences file.
+
+use gtk4::glib;
+use log::debug;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Attribute telling this application's secrets apart from others'
+const APPLICATION: &str = "screenshot_gnome";
+
+pub fn keyring_available() -> bool {
+    glib::find_program_in_path("secret-tool").is_some()
+}
+
+fn secret_tool(args: &[&str], stdin: &[u8]) -> Result<String, String> {
+    let mut child = Command::new("secret-tool")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run secret-tool: {}", e))?;
+
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin).map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "secret-tool exited with {}: {}",
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Save `secret` under `key`, replacing any earlier one. `label` names it in
+/// keyring managers such as Passwords and Keys.
+pub fn store_secret(key: &str, label: &str, secret: &str) -> Result<(), String> {
+    debug!("Storing {} in the keyring", key);
+    let label = format!("--label=Screenshot: {}", label);
+    secret_tool(
+        &["store", &label, "application", APPLICATION, "key", key],
+        secret.as_bytes(),
+    )
+    .map(|_| ())
+}
+
+/// The secret saved under `key`, if there is one
+pub fn lookup_secret(key: &str) -> Option<String> {
+    // Not finding the secret fails the same as any other error
+    match secret_tool(&["lookup", "application", APPLICATION, "key", key], &[]) {
+        Ok(secret) if !secret.is_empty() => Some(secret),
+        Ok(_) => None,
+        Err(e) => {
+            debug!("No {} in the keyring: {}", key, e);
+            None
+        }
+    }
+}
+
+pub fn clear_secret(key: &str) -> Result<(), String> {
+    debug!("Removing {} from the keyring", key);
+    secret_tool(&["clear", "application", APPLICATION, "key", key], &[]).map(|_| ())
+}
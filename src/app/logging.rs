@@ -0,0 +1,202 @@
+use gtk4::glib;
+use log::{LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Lines kept for the log viewer
+pub const MAX_LOG_LINES: usize = 200;
+
+/// Size at which the log file is moved aside and a new one started
+const MAX_LOG_FILE_BYTES: u64 = 1024 * 1024;
+
+static RECENT_LINES: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Logging choices from the command line
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LogOptions {
+    /// `--verbose`: log debug messages to stderr
+    pub verbose: bool,
+
+    /// `--log-file`: also append to `log_file_path()`
+    pub log_file: bool,
+}
+
+impl LogOptions {
+    pub fn from_args(args: &[String]) -> Self {
+        Self {
+            verbose: args.iter().any(|a| a == "--verbose" || a == "-v"),
+            log_file: args.iter().any(|a| a == "--log-file"),
+        }
+    }
+
+    /// Whether `arg` is one of the logging flags
+    pub fn is_flag(arg: &str) -> bool {
+        matches!(arg, "--verbose" | "-v" | "--log-file")
+    }
+}
+
+/// Log file under the XDG state directory
+pub fn log_file_path() -> PathBuf {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| glib::home_dir().join(".local").join("state"))
+        .join("screenshot_gnome")
+        .join("screenshot_gnome.log")
+}
+
+/// Log file that keeps one previous file as `.log.1` once it grows too big
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { path, file, size })
+    }
+
+    fn write_line(&mut self, line: &str) {
+        if self.size >= MAX_LOG_FILE_BYTES {
+            if let Err(e) = self.rotate() {
+                eprintln!("screenshot_gnome: failed to rotate log file: {}", e);
+            }
+        }
+        if writeln!(self.file, "{}", line).is_ok() {
+            self.size += line.len() as u64 + 1;
+        }
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        std::fs::rename(&self.path, self.path.with_extension("log.1"))?;
+        *self = Self::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+/// Passes records on to `env_logger` and keeps the latest ones in memory,
+/// so they can be shown in the app without a terminal, and in the log file
+/// when there is one
+struct BufferLogger {
+    inner: env_logger::Logger,
+    file: Option<Mutex<LogFile>>,
+}
+
+impl Log for BufferLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LevelFilter::Info || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        self.inner.log(record);
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let time = glib::DateTime::now_local()
+            .and_then(|now| now.format("%H:%M:%S"))
+            .map(|time| time.to_string())
+            .unwrap_or_default();
+        let line = format!(
+            "{} {:<5} {}: {}",
+            time,
+            record.level(),
+            record.target(),
+            record.args()
+        );
+        if let Some(Ok(mut file)) = self.file.as_ref().map(|file| file.lock()) {
+            file.write_line(&line);
+        }
+        if let Ok(mut lines) = RECENT_LINES.lock() {
+            push_line(&mut lines, line);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(Ok(mut file)) = self.file.as_ref().map(|file| file.lock()) {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Log to stderr as `RUST_LOG` says, at debug level with `--verbose`, and
+/// keep info and above for the viewer and the log file whatever it says
+pub fn init(options: LogOptions) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if options.verbose {
+        builder.filter_level(LevelFilter::Debug);
+    }
+    let inner = builder.build();
+
+    let file = if options.log_file {
+        let path = log_file_path();
+        match LogFile::open(path.clone()) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                eprintln!("screenshot_gnome: cannot open log file {:?}: {}", path, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let max_level = inner.filter().max(LevelFilter::Info);
+    if log::set_boxed_logger(Box::new(BufferLogger { inner, file })).is_ok() {
+        log::set_max_level(max_level);
+    }
+}
+
+/// The latest log lines, oldest first
+pub fn recent_lines() -> Vec<String> {
+    RECENT_LINES
+        .lock()
+        .map(|lines| lines.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn push_line(lines: &mut VecDeque<String>, line: String) {
+    if lines.len() == MAX_LOG_LINES {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_options_from_args() {
+        let args: Vec<String> = ["screenshot_gnome", "-v", "--log-file", "-s"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let options = LogOptions::from_args(&args);
+        assert!(options.verbose);
+        assert!(options.log_file);
+        assert!(LogOptions::is_flag("--verbose"));
+        assert!(!LogOptions::is_flag("-s"));
+    }
+
+    #[test]
+    fn test_push_line_keeps_latest() {
+        let mut lines = VecDeque::new();
+        for i in 0..MAX_LOG_LINES + 5 {
+            push_line(&mut lines, i.to_string());
+        }
+        assert_eq!(lines.len(), MAX_LOG_LINES);
+        assert_eq!(lines.front().map(String::as_str), Some("5"));
+        assert_eq!(lines.back().cloned(), Some((MAX_LOG_LINES + 4).to_string()));
+    }
+}
@@ -1,4 +1,10 @@
 pub mod config;
+mod keyring;
+pub mod logging;
+pub mod preferences;
 mod state;
 
-pub use state::{AppState, CaptureMode};
+pub use preferences::{
+    InvocationSource, Preferences, QuickCapture, ToolbarItem, ToolbarLayout, ToolbarPosition,
+};
+pub use state::{AppState, CaptureMode, LastExport, SELECTION_PRESETS};
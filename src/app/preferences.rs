@@ -0,0 +1,805 @@
+use gtk4::glib;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use super::keyring::{clear_secret, keyring_available, lookup_secret, store_secret};
+use super::state::CaptureMode;
+use crate::capture::recording::{RecordingAudio, RecordingFormat, RecordingLimits};
+use crate::capture::TonemapOperator;
+use crate::editor::{PalettePreset, RedactStyle};
+use crate::export::{
+    AnimationSettings, ChatKind, ChatSettings, ForgeSettings, NextcloudSettings, SnippetFormat,
+    TranslationBackend, TranslationSettings, UploadOptions,
+};
+use crate::ui::theme::{Appearance, CanvasBackdrop};
+
+/// Edge of the canvas the floating toolbar sits on
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolbarPosition {
+    Top,
+
+    #[default]
+    Bottom,
+
+    Left,
+
+    Right,
+}
+
+impl ToolbarPosition {
+    pub const ALL: [ToolbarPosition; 4] = [Self::Top, Self::Bottom, Self::Left, Self::Right];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Top => "top",
+            Self::Bottom => "bottom",
+            Self::Left => "left",
+            Self::Right => "right",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Top => "Top",
+            Self::Bottom => "Bottom",
+            Self::Left => "Left",
+            Self::Right => "Right",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|position| position.id() == id)
+    }
+
+    /// Left and right toolbars stack their buttons
+    pub fn is_vertical(&self) -> bool {
+        matches!(self, Self::Left | Self::Right)
+    }
+}
+
+/// Button that can be shown in the editor toolbar
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolbarItem {
+    Pointer,
+    Pencil,
+    Rectangle,
+    Redact,
+    Crop,
+    Text,
+    Counter,
+    ColorPicker,
+    Color,
+    Palette,
+    Undo,
+    Redo,
+    History,
+    Info,
+    Links,
+    Discard,
+    Copy,
+    Export,
+    Save,
+}
+
+impl ToolbarItem {
+    /// Every item, in the default order
+    pub const ALL: [ToolbarItem; 19] = [
+        Self::Pointer,
+        Self::Pencil,
+        Self::Rectangle,
+        Self::Redact,
+        Self::Crop,
+        Self::Text,
+        Self::Counter,
+        Self::ColorPicker,
+        Self::Color,
+        Self::Palette,
+        Self::Undo,
+        Self::Redo,
+        Self::History,
+        Self::Info,
+        Self::Links,
+        Self::Discard,
+        Self::Copy,
+        Self::Export,
+        Self::Save,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Pointer => "Pointer",
+            Self::Pencil => "Free Draw",
+            Self::Rectangle => "Rectangle",
+            Self::Redact => "Redact",
+            Self::Crop => "Crop",
+            Self::Text => "Add Text",
+            Self::Counter => "Counter",
+            Self::ColorPicker => "Pick Color",
+            Self::Color => "Select Color",
+            Self::Palette => "Color Palette",
+            Self::Undo => "Undo",
+            Self::Redo => "Redo",
+            Self::History => "Undo History",
+            Self::Info => "Capture Info",
+            Self::Links => "Find Links",
+            Self::Discard => "Discard Capture",
+            Self::Copy => "Copy to Clipboard",
+            Self::Export => "Export",
+            Self::Save => "Save",
+        }
+    }
+
+    /// Tools and colors are grouped together at the start of the toolbar
+    pub fn is_tool(&self) -> bool {
+        matches!(
+            self,
+            Self::Pointer
+                | Self::Pencil
+                | Self::Rectangle
+                | Self::Redact
+                | Self::Crop
+                | Self::Text
+                | Self::Counter
+                | Self::ColorPicker
+                | Self::Color
+                | Self::Palette
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolbarEntry {
+    pub item: ToolbarItem,
+
+    pub visible: bool,
+}
+
+/// Toolbar buttons in order, hidden ones included so they keep their place
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Vec<ToolbarEntry>", into = "Vec<ToolbarEntry>")]
+pub struct ToolbarLayout(Vec<ToolbarEntry>);
+
+impl Default for ToolbarLayout {
+    fn default() -> Self {
+        Self(
+            ToolbarItem::ALL
+                .into_iter()
+                .map(|item| ToolbarEntry {
+                    item,
+                    visible: true,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<Vec<ToolbarEntry>> for ToolbarLayout {
+    /// Drops duplicates and appends items added since the layout was saved
+    fn from(saved: Vec<ToolbarEntry>) -> Self {
+        let mut entries: Vec<ToolbarEntry> = Vec::new();
+        for entry in saved {
+            if !entries.iter().any(|e| e.item == entry.item) {
+                entries.push(entry);
+            }
+        }
+        for item in ToolbarItem::ALL {
+            if !entries.iter().any(|e| e.item == item) {
+                entries.push(ToolbarEntry {
+                    item,
+                    visible: true,
+                });
+            }
+        }
+        Self(entries)
+    }
+}
+
+impl From<ToolbarLayout> for Vec<ToolbarEntry> {
+    fn from(layout: ToolbarLayout) -> Self {
+        layout.0
+    }
+}
+
+impl ToolbarLayout {
+    pub fn entries(&self) -> &[ToolbarEntry] {
+        &self.0
+    }
+
+    pub fn set_visible(&mut self, item: ToolbarItem, visible: bool) {
+        if let Some(entry) = self.0.iter_mut().find(|e| e.item == item) {
+            entry.visible = visible;
+        }
+    }
+
+    /// Move `item` one place towards the start (`-1`) or the end (`1`)
+    pub fn move_item(&mut self, item: ToolbarItem, offset: isize) {
+        if let Some(index) = self.0.iter().position(|e| e.item == item) {
+            let target = index as isize + offset;
+            if target >= 0 && (target as usize) < self.0.len() {
+                self.0.swap(index, target as usize);
+            }
+        }
+    }
+}
+
+/// How the app was started, each remembering its own capture mode and monitor
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InvocationSource {
+    /// Opened from the launcher, without a mode on the command line
+    #[default]
+    Gui,
+
+    /// Given a mode on the command line, or `--pipe`
+    Cli,
+
+    /// `--hotkey`, for binding to the Print key
+    Hotkey,
+}
+
+impl InvocationSource {
+    /// Mode until one has been used: the Print key grabs the whole screen,
+    /// while the window starts out selecting an area
+    pub fn default_mode(&self) -> CaptureMode {
+        match self {
+            Self::Gui => CaptureMode::Selection,
+            Self::Cli | Self::Hotkey => CaptureMode::Screen,
+        }
+    }
+}
+
+/// What happens to a finished capture: open the editor, or hand the capture
+/// straight over and get out of the way
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuickCapture {
+    #[default]
+    Off,
+
+    Copy,
+
+    Save,
+}
+
+impl QuickCapture {
+    pub const ALL: [QuickCapture; 3] = [Self::Off, Self::Copy, Self::Save];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Copy => "copy",
+            Self::Save => "save",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Off => "Open Editor",
+            Self::Copy => "Copy and Close",
+            Self::Save => "Save and Close",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|quick| quick.id() == id)
+    }
+}
+
+/// Capture mode and monitor last used from one invocation source
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RememberedCapture {
+    pub mode: Option<CaptureMode>,
+
+    /// Connector of the monitor Screen mode last captured
+    pub monitor: Option<String>,
+}
+
+/// Choices remembered between sessions. Missing fields fall back to their
+/// defaults, so older files keep loading as settings are added.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preferences {
+    pub toolbar_position: ToolbarPosition,
+
+    pub toolbar_layout: ToolbarLayout,
+
+    /// Light or dark style, or whichever the desktop prefers
+    pub appearance: Appearance,
+
+    /// How HDR captures are brought down to SDR
+    pub tonemap_operator: TonemapOperator,
+
+    /// What the canvas shows behind the image
+    pub canvas_backdrop: CanvasBackdrop,
+
+    /// Size the main window was last closed at, unmaximized
+    pub window_size: Option<(i32, i32)>,
+
+    pub window_maximized: bool,
+
+    /// Size the window selector was last closed at
+    pub window_selector_size: Option<(i32, i32)>,
+
+    /// Shutter sound and flash when a capture fires
+    pub capture_feedback: bool,
+
+    /// Take the app's own window off screen for Screen captures
+    pub hide_own_window: bool,
+
+    /// Captures of these modes are copied or saved right away instead of
+    /// edited; modes left out open the editor
+    pub quick_captures: BTreeMap<CaptureMode, QuickCapture>,
+
+    /// Size in screenshot pixels of the last confirmed selection, offered as
+    /// a preset
+    pub last_selection_size: Option<(i32, i32)>,
+
+    /// Preset selections follow the pointer until a click puts them down,
+    /// rather than appearing in place
+    pub place_presets_with_pointer: bool,
+
+    /// Losslessly shrink PNGs before they are saved or uploaded
+    pub optimize_png: bool,
+
+    /// Read the image for email addresses, IP addresses and keys before an
+    /// export, and warn about any found
+    pub audit_before_export: bool,
+
+    /// What the redaction tool fills with
+    pub redact_style: RedactStyle,
+
+    /// Colors offered in the palette popover and by the color shortcuts
+    pub palette: PalettePreset,
+
+    /// The redaction tool has explained itself once
+    pub redaction_notice_shown: bool,
+
+    /// Where Translate Text sends recognized text, asked for on first use
+    pub translation: Option<TranslationSettings>,
+
+    /// Markup Copy Snippet writes for the exported image
+    pub snippet_format: SnippetFormat,
+
+    /// Snippets describe the image with the text recognized in it
+    pub snippet_alt_text: bool,
+
+    /// GitHub or GitLab repository offered as an export target
+    pub forge_upload: Option<ForgeSettings>,
+
+    /// Nextcloud server shared to by public link, offered as an export target
+    pub nextcloud: Option<NextcloudSettings>,
+
+    /// Matrix room or webhook screenshots are posted to
+    pub chat: Option<ChatSettings>,
+
+    /// Speed limit and retries of uploads to the targets above
+    pub upload: UploadOptions,
+
+    pub recording_format: RecordingFormat,
+
+    /// Frame rate, length and looping of animated recordings
+    pub animation: AnimationSettings,
+
+    /// Sound recorded with videos
+    pub recording_audio: RecordingAudio,
+
+    /// Length and size at which recordings stop
+    pub recording_limits: RecordingLimits,
+
+    /// The layers panel is shown beside the image
+    pub layers_panel_open: bool,
+
+    pub remembered_captures: BTreeMap<InvocationSource, RememberedCapture>,
+
+    /// Connector of the monitor Screen mode captures when there are several,
+    /// instead of asking which; `None` asks every time
+    pub screen_monitor: Option<String>,
+
+    /// Whether Screen mode captures every monitor as one image, laid out as
+    /// they are arranged, instead of a single one
+    pub all_monitors: bool,
+
+    /// Credentials are kept in the keyring and left blank in the file
+    #[serde(skip)]
+    keyring: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            toolbar_position: ToolbarPosition::default(),
+            toolbar_layout: ToolbarLayout::default(),
+            appearance: Appearance::default(),
+            tonemap_operator: TonemapOperator::default(),
+            canvas_backdrop: CanvasBackdrop::default(),
+            window_size: None,
+            window_maximized: false,
+            window_selector_size: None,
+            capture_feedback: true,
+            hide_own_window: true,
+            quick_captures: BTreeMap::new(),
+            last_selection_size: None,
+            place_presets_with_pointer: true,
+            optimize_png: false,
+            audit_before_export: true,
+            redact_style: RedactStyle::default(),
+            palette: PalettePreset::default(),
+            redaction_notice_shown: false,
+            translation: None,
+            snippet_format: SnippetFormat::default(),
+            snippet_alt_text: true,
+            forge_upload: None,
+            nextcloud: None,
+            chat: None,
+            upload: UploadOptions::default(),
+            recording_format: RecordingFormat::default(),
+            animation: AnimationSettings::default(),
+            recording_audio: RecordingAudio::default(),
+            recording_limits: RecordingLimits::default(),
+            layers_panel_open: false,
+            remembered_captures: BTreeMap::new(),
+            screen_monitor: None,
+            all_monitors: false,
+            keyring: false,
+        }
+    }
+}
+
+/// Keyring entries for the credentials in the settings, by key
+const SECRET_KEYS: [&str; 6] = [
+    "translation-api-key",
+    "forge-token",
+    "nextcloud-app-password",
+    "nextcloud-share-password",
+    "chat-token",
+    "chat-webhook-url",
+];
+
+impl Preferences {
+    fn path() -> PathBuf {
+        glib::user_config_dir()
+            .join("screenshot_gnome")
+            .join("preferences.json")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::path();
+        let mut preferences: Self = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Ignoring invalid preferences in {:?}: {}", path, e);
+                Self::default()
+            }),
+            Err(_) => {
+                debug!("No saved preferences in {:?}", path);
+                Self::default()
+            }
+        };
+        // Even on a first start, so new credentials go in the keyring
+        preferences.load_secrets();
+        preferences
+    }
+
+    /// The credentials in the settings, with their keyring keys and labels.
+    /// Blank ones are looked up in the keyring, where optional ones such as
+    /// the link password may be missing.
+    fn secrets_mut(&mut self) -> Vec<(&'static str, &'static str, &mut String)> {
+        let mut secrets = Vec::new();
+        if let Some(TranslationSettings {
+            backend:
+                TranslationBackend::Endpoint {
+                    api_key: Some(ref mut api_key),
+                    ..
+                },
+            ..
+        }) = self.translation
+        {
+            secrets.push((SECRET_KEYS[0], "Translation API key", api_key));
+        }
+        if let Some(ref mut forge) = self.forge_upload {
+            secrets.push((SECRET_KEYS[1], "Repository upload token", &mut forge.token));
+        }
+        if let Some(ref mut nextcloud) = self.nextcloud {
+            secrets.push((
+                SECRET_KEYS[2],
+                "Nextcloud app password",
+                &mut nextcloud.app_password,
+            ));
+            secrets.push((
+                SECRET_KEYS[3],
+                "Nextcloud link password",
+                &mut nextcloud.share_password,
+            ));
+        }
+        match self.chat {
+            Some(ref mut chat) if chat.kind == ChatKind::Matrix => {
+                secrets.push((SECRET_KEYS[4], "Matrix access token", &mut chat.token));
+            }
+            // Webhook URLs carry their own token
+            Some(ref mut chat) => {
+                secrets.push((SECRET_KEYS[5], "Chat webhook URL", &mut chat.url));
+            }
+            None => {}
+        }
+        secrets
+    }
+
+    /// Fill in the credentials from the keyring. Ones still in the file, from
+    /// before the keyring was used, are moved there.
+    fn load_secrets(&mut self) {
+        if !keyring_available() {
+            warn!("No secret-tool, keeping credentials in the preferences file");
+            return;
+        }
+
+        let mut moved = 0;
+        for (key, label, secret) in self.secrets_mut() {
+            if secret.is_empty() {
+                if let Some(stored) = lookup_secret(key) {
+                    *secret = stored;
+                }
+            } else if let Err(e) = store_secret(key, label, secret) {
+                warn!("Keeping credentials in the preferences file: {}", e);
+                return;
+            } else {
+                moved += 1;
+            }
+        }
+
+        self.keyring = true;
+        if moved > 0 {
+            info!("Moved {} credentials to the keyring", moved);
+            self.save();
+        }
+    }
+
+    /// Whether credentials go in the keyring rather than, unencrypted, in the
+    /// preferences file
+    pub fn uses_keyring(&self) -> bool {
+        self.keyring
+    }
+
+    /// Put the credentials in the keyring after they change, and remove the
+    /// ones no longer used. Falls back to the preferences file if the keyring
+    /// can't be written.
+    pub fn store_secrets(&mut self) {
+        if !self.keyring {
+            return;
+        }
+
+        let mut used = Vec::new();
+        let mut result = Ok(());
+        for (key, label, secret) in self.secrets_mut() {
+            if secret.is_empty() {
+                continue;
+            }
+            result = store_secret(key, label, secret);
+            if result.is_err() {
+                break;
+            }
+            used.push(key);
+        }
+        if let Err(e) = result {
+            warn!("Keeping credentials in the preferences file: {}", e);
+            self.keyring = false;
+            return;
+        }
+
+        for key in SECRET_KEYS.into_iter().filter(|key| !used.contains(key)) {
+            if let Err(e) = clear_secret(key) {
+                debug!("Nothing to remove for {}: {}", key, e);
+            }
+        }
+    }
+
+    /// Remove all credentials from the keyring and the settings. Uploads that
+    /// need them are turned off and have to be set up again.
+    pub fn forget_secrets(&mut self) {
+        if keyring_available() {
+            for key in SECRET_KEYS {
+                if let Err(e) = clear_secret(key) {
+                    debug!("Nothing to remove for {}: {}", key, e);
+                }
+            }
+        }
+
+        if let Some(TranslationSettings {
+            backend:
+                TranslationBackend::Endpoint {
+                    ref mut api_key, ..
+                },
+            ..
+        }) = self.translation
+        {
+            *api_key = None;
+        }
+        self.forge_upload = None;
+        self.nextcloud = None;
+        self.chat = None;
+        info!("Forgot saved credentials");
+    }
+
+    pub fn quick_capture(&self, mode: CaptureMode) -> QuickCapture {
+        self.quick_captures.get(&mode).copied().unwrap_or_default()
+    }
+
+    pub fn set_quick_capture(&mut self, mode: CaptureMode, quick_capture: QuickCapture) {
+        if quick_capture == QuickCapture::Off {
+            self.quick_captures.remove(&mode);
+        } else {
+            self.quick_captures.insert(mode, quick_capture);
+        }
+    }
+
+    /// Mode to capture in when started from `source`
+    pub fn capture_mode(&self, source: InvocationSource) -> CaptureMode {
+        self.remembered_captures
+            .get(&source)
+            .and_then(|remembered| remembered.mode)
+            .unwrap_or_else(|| source.default_mode())
+    }
+
+    /// Monitor Screen mode last captured when started from `source`
+    pub fn capture_monitor(&self, source: InvocationSource) -> Option<&str> {
+        self.remembered_captures
+            .get(&source)
+            .and_then(|remembered| remembered.monitor.as_deref())
+    }
+
+    pub fn remember_capture_mode(&mut self, source: InvocationSource, mode: CaptureMode) {
+        self.remembered_captures.entry(source).or_default().mode = Some(mode);
+    }
+
+    pub fn remember_capture_monitor(
+        &mut self,
+        source: InvocationSource,
+        connector: Option<String>,
+    ) {
+        self.remembered_captures.entry(source).or_default().monitor = connector;
+    }
+
+    pub fn save(&self) {
+        let path = Self::path();
+        let mut saved = self.clone();
+        if self.keyring {
+            for (_, _, secret) in saved.secrets_mut() {
+                secret.clear();
+            }
+        }
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .map_err(|e| e.to_string())
+            .and_then(|_| serde_json::to_string_pretty(&saved).map_err(|e| e.to_string()))
+            .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            warn!("Failed to save preferences to {:?}: {}", path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remembered_capture_per_source() {
+        let mut preferences = Preferences::default();
+        assert_eq!(
+            preferences.capture_mode(InvocationSource::Gui),
+            CaptureMode::Selection
+        );
+        assert_eq!(
+            preferences.capture_mode(InvocationSource::Hotkey),
+            CaptureMode::Screen
+        );
+
+        preferences.remember_capture_mode(InvocationSource::Gui, CaptureMode::Window);
+        preferences.remember_capture_monitor(InvocationSource::Hotkey, Some("DP-1".into()));
+
+        let json = serde_json::to_string(&preferences).unwrap();
+        let loaded: Preferences = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            loaded.capture_mode(InvocationSource::Gui),
+            CaptureMode::Window
+        );
+        assert_eq!(
+            loaded.capture_mode(InvocationSource::Hotkey),
+            CaptureMode::Screen
+        );
+        assert_eq!(
+            loaded.capture_monitor(InvocationSource::Hotkey),
+            Some("DP-1")
+        );
+        assert_eq!(loaded.capture_monitor(InvocationSource::Gui), None);
+    }
+
+    #[test]
+    fn test_quick_capture_per_mode() {
+        let mut preferences = Preferences::default();
+        preferences.set_quick_capture(CaptureMode::Screen, QuickCapture::Save);
+        preferences.set_quick_capture(CaptureMode::Selection, QuickCapture::Copy);
+        preferences.set_quick_capture(CaptureMode::Selection, QuickCapture::Off);
+
+        let json = serde_json::to_string(&preferences).unwrap();
+        let loaded: Preferences = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            loaded.quick_capture(CaptureMode::Screen),
+            QuickCapture::Save
+        );
+        assert_eq!(
+            loaded.quick_capture(CaptureMode::Selection),
+            QuickCapture::Off
+        );
+        assert_eq!(loaded.quick_capture(CaptureMode::Window), QuickCapture::Off);
+        assert_eq!(loaded.quick_captures.len(), 1);
+    }
+
+    #[test]
+    fn test_toolbar_layout_from_saved() {
+        let saved = r#"{"toolbar_layout": [
+            {"item": "save", "visible": true},
+            {"item": "crop", "visible": false},
+            {"item": "save", "visible": false}
+        ]}"#;
+        let preferences: Preferences = serde_json::from_str(saved).unwrap();
+        let entries = preferences.toolbar_layout.entries();
+
+        assert_eq!(entries.len(), ToolbarItem::ALL.len());
+        assert_eq!(
+            entries[0],
+            ToolbarEntry {
+                item: ToolbarItem::Save,
+                visible: true,
+            }
+        );
+        assert_eq!(
+            entries[1],
+            ToolbarEntry {
+                item: ToolbarItem::Crop,
+                visible: false,
+            }
+        );
+        assert_eq!(entries[2].item, ToolbarItem::Pointer);
+        assert_eq!(preferences.toolbar_position, ToolbarPosition::Bottom);
+        assert!(preferences.capture_feedback);
+    }
+
+    #[test]
+    fn test_display_choices_saved() {
+        let preferences: Preferences = serde_json::from_str(r#"{"appearance": "dark"}"#).unwrap();
+        assert_eq!(preferences.appearance, Appearance::Dark);
+        assert_eq!(preferences.tonemap_operator, TonemapOperator::Clip);
+
+        let json = serde_json::to_string(&Preferences::default()).unwrap();
+        let loaded: Preferences = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.appearance, Appearance::System);
+
+        let preferences = Preferences {
+            tonemap_operator: TonemapOperator::Aces,
+            canvas_backdrop: CanvasBackdrop::Checkerboard,
+            ..Preferences::default()
+        };
+        let json = serde_json::to_string(&preferences).unwrap();
+        assert!(json.contains(r#""tonemap_operator":"aces""#));
+        let loaded: Preferences = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.tonemap_operator, TonemapOperator::Aces);
+        assert_eq!(loaded.canvas_backdrop, CanvasBackdrop::Checkerboard);
+    }
+
+    #[test]
+    fn test_toolbar_layout_move() {
+        let mut layout = ToolbarLayout::default();
+        layout.move_item(ToolbarItem::Pointer, -1);
+        layout.move_item(ToolbarItem::Pencil, -1);
+        layout.move_item(ToolbarItem::Save, 1);
+        assert_eq!(layout.entries()[0].item, ToolbarItem::Pencil);
+        assert_eq!(layout.entries()[1].item, ToolbarItem::Pointer);
+        assert_eq!(layout.entries().last().unwrap().item, ToolbarItem::Save);
+    }
+}
@@ -1,10 +1,23 @@
 use gtk4 as gtk;
-use log::debug;
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 use crate::app::config::ShortcutConfig;
-use crate::editor::EditorState;
+use crate::app::preferences::{InvocationSource, Preferences};
+use crate::capture::file_metadata::FileMetadata;
+use crate::capture::metadata::{CaptureMetadata, CaptureSource, DEFAULT_FILENAME_TEMPLATE};
+use crate::capture::recording::{Recording, RecordingArea};
+use crate::capture::window::{window_at, WindowInfo};
+use crate::editor::{
+    clip_rect, load_custom_colors, load_templates, redacted, save_templates, Annotation,
+    AnnotationTemplate, EditorCrop, EditorState, EditorTool, RedactStyle, Snapshot,
+};
+use crate::export::{auto_save, AutoSaveLayout, ExportRegistry};
+use crate::ui::theme::ColorVision;
 
-#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CaptureMode {
     #[default]
     Selection,
@@ -14,6 +27,29 @@ pub enum CaptureMode {
     Screen,
 }
 
+impl CaptureMode {
+    pub const ALL: [CaptureMode; 3] = [Self::Selection, Self::Window, Self::Screen];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Selection => "selection",
+            Self::Window => "window",
+            Self::Screen => "screen",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Selection => "Selection",
+            Self::Window => "Window",
+            Self::Screen => "Screen",
+        }
+    }
+}
+
+/// Fixed selection sizes offered on the selection overlay, in screenshot pixels
+pub const SELECTION_PRESETS: [(i32, i32); 3] = [(800, 600), (1280, 720), (1920, 1080)];
+
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Selection {
     pub start_x: f64,
@@ -32,6 +68,48 @@ impl Selection {
         }
     }
 
+    /// `width` x `height` centered on `(center_x, center_y)`, shifted as
+    /// needed to stay within `bounds`
+    pub fn centered(
+        (center_x, center_y): (f64, f64),
+        (width, height): (f64, f64),
+        bounds: (f64, f64),
+    ) -> Self {
+        let mut selection = Self::new(center_x - width / 2.0, center_y - height / 2.0);
+        selection.update_end(center_x + width / 2.0, center_y + height / 2.0);
+        selection.moved_within(0.0, 0.0, bounds)
+    }
+
+    /// Moved by `(dx, dy)` without leaving `bounds`, keeping its size
+    pub fn moved_within(&self, dx: f64, dy: f64, (bounds_w, bounds_h): (f64, f64)) -> Self {
+        let (left, top) = (self.start_x.min(self.end_x), self.start_y.min(self.end_y));
+        let width = (self.start_x - self.end_x).abs().min(bounds_w);
+        let height = (self.start_y - self.end_y).abs().min(bounds_h);
+        let left = (left + dx).min(bounds_w - width).max(0.0);
+        let top = (top + dy).min(bounds_h - height).max(0.0);
+
+        let mut selection = Self::new(left, top);
+        selection.update_end(left + width, top + height);
+        selection
+    }
+
+    /// Moved by `(dx, dy)`, keeping which corner is the start
+    pub fn shifted(&self, dx: f64, dy: f64) -> Self {
+        Self {
+            start_x: self.start_x + dx,
+            start_y: self.start_y + dy,
+            end_x: self.end_x + dx,
+            end_y: self.end_y + dy,
+        }
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.start_x + self.end_x) / 2.0,
+            (self.start_y + self.end_y) / 2.0,
+        )
+    }
+
     pub fn update_end(&mut self, end_x: f64, end_y: f64) {
         self.end_x = end_x;
         self.end_y = end_y;
@@ -49,6 +127,35 @@ impl Selection {
         let rect = self.rectangle();
         rect.width() > 10 && rect.height() > 10
     }
+
+    /// `(x, y, width, height)` in the pixels of an image shown `scale` image
+    /// pixels per widget pixel from the widget's corner, clamped to
+    /// `image_size`. Rounds outwards, so a fractional scale never loses the
+    /// pixels under the selection border.
+    pub fn image_rect(&self, scale: f64, image_size: (i32, i32)) -> Option<(i32, i32, i32, i32)> {
+        let (image_w, image_h) = image_size;
+        let left = ((self.start_x.min(self.end_x) * scale).floor() as i32).max(0);
+        let top = ((self.start_y.min(self.end_y) * scale).floor() as i32).max(0);
+        let right = ((self.start_x.max(self.end_x) * scale).ceil() as i32).min(image_w);
+        let bottom = ((self.start_y.max(self.end_y) * scale).ceil() as i32).min(image_h);
+
+        if right > left && bottom > top {
+            Some((left, top, right - left, bottom - top))
+        } else {
+            None
+        }
+    }
+}
+
+/// Where an image went when it was exported, so it can be sent there again
+/// after further edits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LastExport {
+    /// Saved as PNG into a folder picked in the file dialog
+    Folder { folder: PathBuf, optimize: bool },
+
+    /// Sent to the export target with this id
+    Target(String),
 }
 
 pub struct AppState {
@@ -66,13 +173,119 @@ pub struct AppState {
 
     pub monitor_y: i32,
 
+    /// Logical width and fractional scale of the fullscreen selection
+    /// overlay, for mapping the selection to screenshot pixels
+    pub overlay_geometry: Option<(i32, f64)>,
+
+    /// A preset-sized selection is centered on the pointer until a click
+    /// puts it down
+    pub placing_selection: bool,
+
+    /// Selection as it was when a drag started moving it
+    pub selection_move_origin: Option<Selection>,
+
+    /// Space is held, so dragging moves the selection instead of resizing it
+    pub selection_space_held: bool,
+
+    /// Pointer position of the selection drag as of its latest update
+    pub selection_drag_point: Option<(f64, f64)>,
+
     pub editor: EditorState,
 
     pub is_crop_mode: bool,
 
     pub delay_seconds: u32,
 
+    /// Underline misspelled words in the text annotation entry
+    pub spell_check: bool,
+
+    /// Type text annotations directly on the canvas instead of in a popover
+    pub text_on_canvas: bool,
+
+    /// Blink phase of the caret while typing on the canvas
+    pub text_caret_visible: bool,
+
+    /// Color blindness simulated on the canvas
+    pub color_vision: ColorVision,
+
+    /// Set while a capture waits out its delay; cancelling it aborts the capture
+    pub capture_cancellable: Option<gtk::gio::Cancellable>,
+
+    /// Opacity of the white flash shown as a capture fires
+    pub capture_flash: Option<f64>,
+
+    /// Window size to restore while shrunk to the capture controls
+    pub compact_size: Option<(i32, i32)>,
+
+    /// Window mode picks a window by clicking it on a frozen screenshot instead of a list
+    pub pick_window_by_click: bool,
+
+    /// Windows on the captured monitor while picking, in screenshot coordinates
+    pub window_targets: Vec<WindowInfo>,
+
+    /// Selection mode hands region picking to the compositor via the screenshot portal
+    pub use_portal_picker: bool,
+
+    /// Details of the capture currently in the editor
+    pub capture_metadata: Option<CaptureMetadata>,
+
+    /// Template for saved and exported file names, see
+    /// [`CaptureMetadata::expand_filename_template`]
+    pub filename_template: String,
+
+    /// Save every new capture below `~/Pictures/Screenshots` with this layout
+    pub auto_save: Option<AutoSaveLayout>,
+
+    /// Where the capture in the editor was auto-saved, so discarding it can trash the file
+    pub auto_saved_path: Option<PathBuf>,
+
     pub shortcuts: ShortcutConfig,
+
+    /// Choices saved to disk whenever they change
+    pub preferences: Preferences,
+
+    /// How the app was started, for remembering its capture mode and monitor
+    pub invocation: InvocationSource,
+
+    pub export_targets: ExportRegistry,
+
+    /// Colors picked in the color dialog, offered below the palette
+    pub custom_colors: Vec<gtk::gdk::RGBA>,
+
+    /// Annotation layouts saved to add to any capture
+    pub templates: Vec<AnnotationTemplate>,
+
+    /// Palette color just switched to by shortcut, or the tool size just
+    /// scrolled to, shown briefly on the canvas
+    pub color_hint: Option<(String, gtk::gdk::RGBA)>,
+
+    pub color_hint_timeout: Option<gtk::glib::SourceId>,
+
+    /// Link returned by the last export of the current image, offered as text on copy
+    pub upload_url: Option<String>,
+
+    /// How the current image was last exported, for Export Again
+    pub last_export: Option<LastExport>,
+
+    /// File the current image was last saved to from the save dialog
+    pub saved_path: Option<PathBuf>,
+
+    /// Details of the file the current image was opened from
+    pub file_metadata: Option<FileMetadata>,
+
+    /// The take button records a video of the chosen area instead of a
+    /// screenshot
+    pub record_mode: bool,
+
+    /// Area confirmed on the selection overlay, recorded once the overlay is
+    /// gone
+    pub pending_recording: Option<RecordingArea>,
+
+    /// Window the pending recording was picked as, which the recorded area
+    /// follows as it moves
+    pub pending_recording_window: Option<WindowInfo>,
+
+    pub recording: Option<Recording>,
 }
 
 impl Default for AppState {
@@ -92,13 +305,58 @@ impl AppState {
             is_active: false,
             monitor_x: 0,
             monitor_y: 0,
+            overlay_geometry: None,
+            placing_selection: false,
+            selection_move_origin: None,
+            selection_space_held: false,
+            selection_drag_point: None,
             editor: EditorState::new(),
             is_crop_mode: false,
             delay_seconds: 0,
+            spell_check: false,
+            text_on_canvas: true,
+            text_caret_visible: true,
+            color_vision: ColorVision::default(),
+            capture_cancellable: None,
+            capture_flash: None,
+            compact_size: None,
+            pick_window_by_click: false,
+            window_targets: Vec::new(),
+            use_portal_picker: false,
+            capture_metadata: None,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            auto_save: None,
+            auto_saved_path: None,
             shortcuts: ShortcutConfig::default(),
+            preferences: Preferences::load(),
+            invocation: InvocationSource::default(),
+            export_targets: ExportRegistry::new(),
+            custom_colors: load_custom_colors(),
+            templates: load_templates(),
+            color_hint: None,
+            color_hint_timeout: None,
+            upload_url: None,
+            last_export: None,
+            saved_path: None,
+            file_metadata: None,
+            record_mode: false,
+            pending_recording: None,
+            pending_recording_window: None,
+            recording: None,
         }
     }
 
+    /// Whether drags on the selection overlay draw the selection, rather
+    /// than annotations with the pencil or rectangle
+    pub fn is_selecting_area(&self) -> bool {
+        self.is_active
+            && self.mode == CaptureMode::Selection
+            && !matches!(
+                self.editor.current_tool(),
+                EditorTool::Pencil | EditorTool::Rectangle
+            )
+    }
+
     pub fn start_selection(&mut self, x: f64, y: f64) {
         debug!("Starting selection at ({}, {})", x, y);
         self.selection = Some(Selection::new(x, y));
@@ -110,27 +368,148 @@ impl AppState {
         }
     }
 
+    /// Follow the selection drag to `(x, y)`: resizing, or with Space held
+    /// moving the whole selection by as much as the pointer moved
+    pub fn drag_selection_to(&mut self, x: f64, y: f64) {
+        match (self.selection_space_held, self.selection_drag_point) {
+            (true, Some((last_x, last_y))) => {
+                self.selection = self
+                    .selection
+                    .map(|sel| sel.shifted(x - last_x, y - last_y));
+            }
+            _ => self.update_selection(x, y),
+        }
+        self.selection_drag_point = Some((x, y));
+    }
+
+    /// Size of the selection overlay in widget pixels
+    fn overlay_size(&self) -> Option<(f64, f64)> {
+        let screenshot = self.original_screenshot.as_ref()?;
+        let scale = self.selection_scale(screenshot.width());
+        Some((
+            screenshot.width() as f64 / scale,
+            screenshot.height() as f64 / scale,
+        ))
+    }
+
+    /// Select `width` x `height` screenshot pixels around `center`, or
+    /// around the current selection, or else the middle of the screen
+    pub fn select_preset(&mut self, (width, height): (i32, i32), center: Option<(f64, f64)>) {
+        let screenshot_width = match self.original_screenshot {
+            Some(ref screenshot) => screenshot.width(),
+            None => return,
+        };
+        let bounds = match self.overlay_size() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let scale = self.selection_scale(screenshot_width);
+        let center = center
+            .or_else(|| self.selection.map(|sel| sel.center()))
+            .unwrap_or((bounds.0 / 2.0, bounds.1 / 2.0));
+        debug!("Selecting {}x{} around {:?}", width, height, center);
+        self.selection = Some(Selection::centered(
+            center,
+            (width as f64 / scale, height as f64 / scale),
+            bounds,
+        ));
+    }
+
+    /// Start moving the whole selection, as by a drag starting at its
+    /// current place
+    pub fn begin_selection_move(&mut self) {
+        self.selection_move_origin = self.selection;
+    }
+
+    /// Move the selection by a drag's offset from where it started
+    pub fn update_selection_move(&mut self, offset_x: f64, offset_y: f64) {
+        if let (Some(origin), Some(bounds)) = (self.selection_move_origin, self.overlay_size()) {
+            self.selection = Some(origin.moved_within(offset_x, offset_y, bounds));
+        }
+    }
+
+    /// Move the selection by `(dx, dy)` widget pixels, staying on screen
+    pub fn nudge_selection(&mut self, dx: f64, dy: f64) {
+        if let (Some(selection), Some(bounds)) = (self.selection, self.overlay_size()) {
+            self.selection = Some(selection.moved_within(dx, dy, bounds));
+        }
+    }
+
+    /// Center the selection on `(x, y)` while it is being placed
+    pub fn place_selection_at(&mut self, x: f64, y: f64) {
+        if let (Some(selection), Some(bounds)) = (self.selection, self.overlay_size()) {
+            let (center_x, center_y) = selection.center();
+            self.selection = Some(selection.moved_within(x - center_x, y - center_y, bounds));
+        }
+    }
+
+    /// Select the window under the pointer while picking a window by clicking
+    pub fn hover_window(&mut self, x: f64, y: f64) -> Option<WindowInfo> {
+        let (img_x, img_y) = self.editor.display_to_image_coords(x, y);
+        let window = window_at(&self.window_targets, img_x as i32, img_y as i32).cloned();
+
+        self.selection = window.as_ref().map(|w| {
+            let (start_x, start_y) = self.editor.image_to_display_coords(w.x as f64, w.y as f64);
+            let (end_x, end_y) = self.editor.image_to_display_coords(
+                (w.x + w.width as i32) as f64,
+                (w.y + w.height as i32) as f64,
+            );
+            let mut selection = Selection::new(start_x, start_y);
+            selection.update_end(end_x, end_y);
+            selection
+        });
+        window
+    }
+
+    /// The selection as an area of the desktop to record. The overlay covers
+    /// the monitor at its logical size, so no scaling is involved.
+    pub fn selection_recording_area(&self) -> Option<RecordingArea> {
+        let rect = self
+            .selection
+            .filter(|sel| sel.is_significant())?
+            .rectangle();
+        Some(RecordingArea {
+            x: self.monitor_x + rect.x(),
+            y: self.monitor_y + rect.y(),
+            width: rect.width(),
+            height: rect.height(),
+        })
+    }
+
     pub fn apply_selection_crop(&mut self) -> bool {
         debug!("Applying selection crop");
         if let Some(sel) = self.selection {
             if sel.is_significant() {
                 if let Some(ref orig) = self.original_screenshot {
-                    let (start_x, start_y) = self
-                        .editor
-                        .display_to_image_coords(sel.start_x, sel.start_y);
-                    let (end_x, end_y) = self.editor.display_to_image_coords(sel.end_x, sel.end_y);
-
-                    let x = start_x.min(end_x).max(0.0) as i32;
-                    let y = start_y.min(end_y).max(0.0) as i32;
-                    let w = (start_x - end_x).abs() as i32;
-                    let h = (start_y - end_y).abs() as i32;
-
-                    let crop_w = w.min(orig.width() - x);
-                    let crop_h = h.min(orig.height() - y);
-
-                    if crop_w > 0 && crop_h > 0 {
+                    let scale = self.selection_scale(orig.width());
+                    if let Some((x, y, crop_w, crop_h)) =
+                        sel.image_rect(scale, (orig.width(), orig.height()))
+                    {
+                        debug!(
+                            "Selection maps to {}x{}+{}+{} at scale {}",
+                            crop_w, crop_h, x, y, scale
+                        );
                         let cropped = orig.new_subpixbuf(x, y, crop_w, crop_h);
                         self.final_image = Some(cropped);
+
+                        // Markup drawn on the overlay moves with the crop;
+                        // whatever lies entirely outside it is dropped
+                        let bounds = (x as f64, y as f64, crop_w as f64, crop_h as f64);
+                        let kept: Vec<_> = self
+                            .editor
+                            .annotations
+                            .take_all()
+                            .into_iter()
+                            .filter(|annotation| annotation.intersects(bounds))
+                            .collect();
+                        for annotation in shift_annotations(kept, -x, -y) {
+                            self.editor.annotations.add(annotation);
+                        }
+
+                        let region = (self.monitor_x + x, self.monitor_y + y, crop_w, crop_h);
+                        if let Some(ref mut metadata) = self.capture_metadata {
+                            metadata.region = Some(region);
+                        }
                         return true;
                     }
                 }
@@ -139,6 +518,23 @@ impl AppState {
         false
     }
 
+    /// Screenshot pixels per overlay pixel: the overlay's own scale when the
+    /// screenshot has the monitor's device resolution, otherwise the scale
+    /// the screenshot was drawn at
+    fn selection_scale(&self, image_width: i32) -> f64 {
+        match self.overlay_geometry {
+            Some((width, scale))
+                if (image_width as f64 - width as f64 * scale).abs() <= scale.ceil() =>
+            {
+                scale
+            }
+            _ => 1.0 / self.editor.display_scale,
+        }
+    }
+
+    /// Crop the image to the rectangle drawn in crop mode. The crop is a view
+    /// into the uncropped image; annotations move with it, and those left
+    /// entirely outside are put aside until [`AppState::reveal_original`].
     pub fn apply_editor_crop(&mut self) -> bool {
         debug!("Applying editor crop");
         if let Some((x, y, w, h)) = self.editor.tool_state.get_drag_rect() {
@@ -150,9 +546,40 @@ impl AppState {
                     let crop_h = (h as i32).min(pixbuf.height() - crop_y);
 
                     if crop_w > 0 && crop_h > 0 {
-                        let cropped = pixbuf.new_subpixbuf(crop_x, crop_y, crop_w, crop_h);
-                        self.final_image = Some(cropped);
-                        self.editor.clear_annotations();
+                        // Cropping again narrows the existing view of the original
+                        let (original, offset_x, offset_y, mut hidden) =
+                            match self.editor.crop.take() {
+                                Some(crop) => (
+                                    crop.original,
+                                    crop.region.0,
+                                    crop.region.1,
+                                    crop.hidden_annotations,
+                                ),
+                                None => (pixbuf.clone(), 0, 0, Vec::new()),
+                            };
+                        let region = (offset_x + crop_x, offset_y + crop_y, crop_w, crop_h);
+
+                        let bounds = (crop_x as f64, crop_y as f64, crop_w as f64, crop_h as f64);
+                        let (kept, outside): (Vec<_>, Vec<_>) = self
+                            .editor
+                            .annotations
+                            .take_all()
+                            .into_iter()
+                            .partition(|annotation| annotation.intersects(bounds));
+                        hidden.extend(shift_annotations(outside, offset_x, offset_y));
+                        let annotation_count = kept.len();
+                        for annotation in shift_annotations(kept, -crop_x, -crop_y) {
+                            self.editor.annotations.add(annotation);
+                        }
+
+                        self.final_image =
+                            Some(original.new_subpixbuf(region.0, region.1, crop_w, crop_h));
+                        self.editor.crop = Some(EditorCrop {
+                            original,
+                            region,
+                            hidden_annotations: hidden,
+                            annotation_count,
+                        });
                         return true;
                     }
                 }
@@ -161,13 +588,230 @@ impl AppState {
         false
     }
 
+    /// Undo the editor crop, bringing back the whole image and the annotations
+    /// put aside. Returns the crop that was undone, in the original's pixels.
+    pub fn reveal_original(&mut self) -> Option<(i32, i32, i32, i32)> {
+        debug!("Revealing the uncropped image");
+        reveal_crop(&mut self.editor, &mut self.final_image)
+    }
+
+    /// Overwrite `rect`, in the image's pixels, with `style`. The uncropped
+    /// image and the screenshot the selection came from are replaced too, and
+    /// the auto-saved file rewritten, so no unredacted copy is kept.
+    pub fn redact(&mut self, rect: (f64, f64, f64, f64), style: RedactStyle) -> bool {
+        let image = match self.final_image {
+            Some(ref pixbuf) => pixbuf.clone(),
+            None => return false,
+        };
+        let (x, y, w, h) = match clip_rect(rect, image.width(), image.height()) {
+            Some(rect) => rect,
+            None => return false,
+        };
+        debug!("Redacting {}x{}+{}+{}", w, h, x, y);
+
+        let full = match self.editor.crop {
+            Some(ref mut crop) => {
+                let (offset_x, offset_y, crop_w, crop_h) = crop.region;
+                let rect = (offset_x + x, offset_y + y, w, h);
+                let original = match redacted(&crop.original, rect, style) {
+                    Some(pixbuf) => pixbuf,
+                    None => return false,
+                };
+                self.final_image = Some(original.new_subpixbuf(offset_x, offset_y, crop_w, crop_h));
+                crop.original = original.clone();
+                original
+            }
+            None => {
+                let pixbuf = match redacted(&image, (x, y, w, h), style) {
+                    Some(pixbuf) => pixbuf,
+                    None => return false,
+                };
+                self.final_image = Some(pixbuf.clone());
+                pixbuf
+            }
+        };
+        self.original_screenshot = None;
+
+        if let Some(ref path) = self.auto_saved_path {
+            if let Err(e) = full.savev(path, "png", &[]) {
+                error!("Failed to rewrite {:?} after redacting: {}", path, e);
+            }
+        }
+        true
+    }
+
+    /// Undo the latest change to the annotations, or the crop once the
+    /// changes made on it are gone
+    pub fn undo(&mut self) -> bool {
+        undo_step(&mut self.editor, &mut self.final_image)
+    }
+
+    /// Make the change last undone again. Undoing a crop can't be redone,
+    /// as it starts the annotation history over.
+    pub fn redo(&mut self) -> bool {
+        self.editor.redo()
+    }
+
+    /// Names of the changes `undo` can take back, oldest first. Annotations
+    /// drawn before a crop come before it, as undoing it brings them back.
+    pub fn history(&self) -> Vec<&'static str> {
+        history_of(&self.editor)
+    }
+
+    /// Undo until only the first `steps` entries of `history` are left.
+    /// Returns whether anything was undone.
+    pub fn undo_to(&mut self, steps: usize) -> bool {
+        let mut changed = false;
+        while self.history().len() > steps && self.undo() {
+            changed = true;
+        }
+        changed
+    }
+
+    /// The image and annotations as they were after the first `steps`
+    /// entries of `history`, for previewing a step without undoing to it
+    pub fn history_preview(
+        &self,
+        steps: usize,
+    ) -> Option<(gtk::gdk_pixbuf::Pixbuf, Vec<Annotation>)> {
+        // Undone on a copy, so moved and deleted annotations show as they were
+        let mut editor = self.editor.clone();
+        let mut image = self.final_image.clone();
+        let mut count = history_of(&editor).len();
+        while count > steps && undo_step(&mut editor, &mut image) {
+            count -= 1;
+        }
+        Some((image?, editor.annotations.iter().cloned().collect()))
+    }
+
+    /// Remember the annotations and crop as `name`, to come back to with
+    /// `restore_snapshot`
+    pub fn take_snapshot(&mut self, name: &str) -> bool {
+        if self.final_image.is_none() {
+            return false;
+        }
+        debug!("Taking snapshot {:?}", name);
+        let snapshot = Snapshot::new(name, &self.editor);
+        self.editor.snapshots.push(snapshot);
+        true
+    }
+
+    /// Replace the annotations and crop with those of snapshot `index`,
+    /// keeping the image's current pixels
+    pub fn restore_snapshot(&mut self, index: usize) -> bool {
+        let snapshot = match self.editor.snapshots.get(index) {
+            Some(snapshot) => snapshot.clone(),
+            None => return false,
+        };
+        let full = match (&self.editor.crop, &self.final_image) {
+            (Some(crop), _) => crop.original.clone(),
+            (None, Some(pixbuf)) => pixbuf.clone(),
+            (None, None) => return false,
+        };
+        debug!("Restoring snapshot {:?}", snapshot.name);
+
+        self.editor.pending_text = None;
+        self.editor.annotations = snapshot.document.annotations;
+        match snapshot.crop {
+            Some(crop) => {
+                let (x, y, width, height) = crop.region;
+                self.final_image = Some(full.new_subpixbuf(x, y, width, height));
+                self.editor.crop = Some(EditorCrop {
+                    original: full,
+                    region: crop.region,
+                    hidden_annotations: crop.hidden_annotations,
+                    annotation_count: crop.annotation_count,
+                });
+            }
+            None => {
+                self.final_image = Some(full);
+                self.editor.crop = None;
+            }
+        }
+        true
+    }
+
+    /// Save the current annotations as template `name`, replacing any
+    /// template of that name
+    pub fn save_template(&mut self, name: &str) -> Result<(), String> {
+        if self.editor.annotations.is_empty() {
+            return Err("There are no annotations to save".to_string());
+        }
+        debug!("Saving annotation template {:?}", name);
+        let template = AnnotationTemplate::new(name, &self.editor);
+        match self.templates.iter_mut().find(|t| t.name == name) {
+            Some(existing) => *existing = template,
+            None => self.templates.push(template),
+        }
+        save_templates(&self.templates)
+    }
+
+    /// Add the annotations of template `index` to the current image.
+    /// Returns how many were added.
+    pub fn apply_template(&mut self, index: usize) -> usize {
+        let (template, pixbuf) = match (self.templates.get(index), &self.final_image) {
+            (Some(template), Some(pixbuf)) => (template, pixbuf),
+            _ => return 0,
+        };
+        let annotations = template.annotations_for(pixbuf.width() as f64, pixbuf.height() as f64);
+        debug!(
+            "Applying template {:?} with {} annotations",
+            template.name,
+            annotations.len()
+        );
+
+        self.editor.pending_text = None;
+        self.editor.annotations.deselect();
+        let count = annotations.len();
+        for annotation in annotations {
+            self.editor.annotations.add(annotation);
+        }
+        count
+    }
+
     pub fn exit_capture_mode(&mut self) {
         debug!("Exiting capture mode");
         self.is_active = false;
         self.selection = None;
+        self.placing_selection = false;
+        self.selection_move_origin = None;
+        self.selection_space_held = false;
+        self.selection_drag_point = None;
+        self.window_targets.clear();
         self.editor.reset();
     }
 
+    /// File name for the current image, from the capture details and template
+    pub fn suggested_file_name(&self) -> String {
+        let (width, height) = self
+            .final_image
+            .as_ref()
+            .map(|p| (p.width(), p.height()))
+            .unwrap_or((0, 0));
+
+        let metadata = self
+            .capture_metadata
+            .clone()
+            .unwrap_or_else(|| CaptureMetadata::new(CaptureSource::Screen));
+        let stem = metadata.expand_filename_template(&self.filename_template, width, height);
+        format!("{}.png", stem)
+    }
+
+    /// Auto-save the capture that was just loaded into the editor, if enabled
+    pub fn auto_save_capture(&mut self) {
+        self.auto_saved_path = None;
+        if let (Some(layout), Some(pixbuf)) = (self.auto_save, self.final_image.as_ref()) {
+            let metadata = self
+                .capture_metadata
+                .clone()
+                .unwrap_or_else(|| CaptureMetadata::new(CaptureSource::Screen));
+            match auto_save(pixbuf, &metadata, &self.filename_template, layout) {
+                Ok(path) => self.auto_saved_path = Some(path),
+                Err(e) => error!("Failed to auto-save capture: {}", e),
+            }
+        }
+    }
+
     pub fn exit_crop_mode(&mut self) {
         debug!("Exiting crop mode");
         self.is_crop_mode = false;
@@ -188,3 +832,278 @@ impl AppState {
         }
     }
 }
+
+/// Undo the crop of `editor`, setting `image` back to the original and
+/// bringing back the annotations put aside
+fn reveal_crop(
+    editor: &mut EditorState,
+    image: &mut Option<gtk::gdk_pixbuf::Pixbuf>,
+) -> Option<(i32, i32, i32, i32)> {
+    let crop = editor.crop.take()?;
+    let (x, y, _, _) = crop.region;
+
+    let mut annotations = crop.hidden_annotations;
+    annotations.extend(shift_annotations(editor.annotations.take_all(), x, y));
+    for annotation in annotations {
+        editor.annotations.add(annotation);
+    }
+    *image = Some(crop.original);
+    Some(crop.region)
+}
+
+fn undo_step(editor: &mut EditorState, image: &mut Option<gtk::gdk_pixbuf::Pixbuf>) -> bool {
+    let crop_is_latest = editor
+        .crop
+        .as_ref()
+        .is_some_and(|crop| editor.annotations.undo_count() <= crop.annotation_count);
+    if crop_is_latest {
+        reveal_crop(editor, image).is_some()
+    } else {
+        editor.undo()
+    }
+}
+
+fn history_of(editor: &EditorState) -> Vec<&'static str> {
+    let mut labels = editor.annotations.history();
+    let crop = match editor.crop {
+        Some(ref crop) => crop,
+        None => return labels.collect(),
+    };
+
+    let mut history: Vec<&'static str> = crop
+        .hidden_annotations
+        .iter()
+        .map(Annotation::label)
+        .collect();
+    history.extend(labels.by_ref().take(crop.annotation_count));
+    history.push("Crop");
+    history.extend(labels);
+    history
+}
+
+/// Move annotations by `(x, y)`, between a crop at that offset and the
+/// image it was cropped from
+fn shift_annotations(annotations: Vec<Annotation>, x: i32, y: i32) -> Vec<Annotation> {
+    annotations
+        .into_iter()
+        .map(|mut annotation| {
+            annotation.move_by(x as f64, y as f64);
+            annotation
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{pick_color_from_pixbuf, RectangleAnnotation};
+    use gtk::gdk::RGBA;
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+
+    fn crop_to(state: &mut AppState, x: f64, y: f64, w: f64, h: f64) -> bool {
+        state.editor.tool_state.start_drag(x, y);
+        state.editor.tool_state.update_drag(x + w, y + h);
+        state.apply_editor_crop()
+    }
+
+    fn rectangle(x: f64, y: f64) -> Annotation {
+        let red = RGBA::new(1.0, 0.0, 0.0, 1.0);
+        Annotation::Rectangle(RectangleAnnotation::new(x, y, 20.0, 20.0, red, 3.0))
+    }
+
+    fn positions(state: &AppState) -> Vec<(f64, f64)> {
+        let mut annotations = state.editor.annotations.clone();
+        annotations
+            .take_all()
+            .iter()
+            .map(Annotation::position)
+            .collect()
+    }
+
+    #[test]
+    fn test_selection_image_rect() {
+        let mut sel = Selection::new(100.3, 40.0);
+        sel.update_end(20.0, 200.6);
+
+        // 125% fractional scaling: widget pixels cover 1.25 image pixels
+        assert_eq!(sel.image_rect(1.25, (2560, 1600)), Some((25, 50, 101, 201)));
+        assert_eq!(sel.image_rect(1.0, (2560, 1600)), Some((20, 40, 81, 161)));
+        assert_eq!(sel.image_rect(2.0, (100, 100)), Some((40, 80, 60, 20)));
+        assert_eq!(sel.image_rect(1.0, (10, 10)), None);
+    }
+
+    #[test]
+    fn test_selection_centered_and_moved() {
+        let bounds = (1000.0, 800.0);
+        let selection = Selection::centered((500.0, 400.0), (200.0, 100.0), bounds);
+        assert_eq!(
+            selection.rectangle(),
+            gtk::gdk::Rectangle::new(400, 350, 200, 100)
+        );
+
+        // Near an edge it is pushed back inside, keeping its size
+        let selection = Selection::centered((20.0, 790.0), (200.0, 100.0), bounds);
+        assert_eq!(
+            selection.rectangle(),
+            gtk::gdk::Rectangle::new(0, 700, 200, 100)
+        );
+
+        let moved = selection.moved_within(5000.0, -50.0, bounds);
+        assert_eq!(
+            moved.rectangle(),
+            gtk::gdk::Rectangle::new(800, 650, 200, 100)
+        );
+    }
+
+    #[test]
+    fn test_drag_selection_with_space() {
+        let mut state = AppState::new();
+        state.start_selection(10.0, 10.0);
+        state.selection_drag_point = Some((10.0, 10.0));
+        state.drag_selection_to(50.0, 30.0);
+
+        // Holding Space carries the rectangle along instead of resizing it
+        state.selection_space_held = true;
+        state.drag_selection_to(70.0, 40.0);
+        state.selection_space_held = false;
+        state.drag_selection_to(90.0, 40.0);
+
+        let sel = state.selection.unwrap();
+        assert_eq!((sel.start_x, sel.start_y), (30.0, 20.0));
+        assert_eq!((sel.end_x, sel.end_y), (90.0, 40.0));
+    }
+
+    #[test]
+    fn test_selection_crop_keeps_overlay_annotations() {
+        let mut state = AppState::new();
+        state.original_screenshot = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300);
+        state.overlay_geometry = Some((400, 1.0));
+        state.editor.annotations.add(rectangle(110.0, 60.0));
+        state.editor.annotations.add(rectangle(10.0, 10.0));
+
+        let mut selection = Selection::new(100.0, 50.0);
+        selection.update_end(300.0, 250.0);
+        state.selection = Some(selection);
+
+        assert!(state.apply_selection_crop());
+        assert_eq!(state.final_image.as_ref().unwrap().width(), 200);
+        assert_eq!(positions(&state), vec![(10.0, 10.0)]);
+    }
+
+    #[test]
+    fn test_crop_and_reveal_original() {
+        let mut state = AppState::new();
+        state.final_image = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300);
+        state.editor.annotations.add(rectangle(10.0, 10.0));
+        state.editor.annotations.add(rectangle(150.0, 100.0));
+
+        assert!(crop_to(&mut state, 100.0, 50.0, 200.0, 200.0));
+        assert!(crop_to(&mut state, 20.0, 20.0, 100.0, 100.0));
+        let crop = state.editor.crop.as_ref().unwrap();
+        assert_eq!(crop.region, (120, 70, 100, 100));
+        assert_eq!(state.final_image.as_ref().unwrap().width(), 100);
+        // Only the one inside the crop is left, moved with it
+        assert_eq!(positions(&state), vec![(30.0, 30.0)]);
+
+        state.editor.annotations.add(rectangle(5.0, 5.0));
+        assert_eq!(state.reveal_original(), Some((120, 70, 100, 100)));
+        assert_eq!(state.final_image.as_ref().unwrap().width(), 400);
+        assert_eq!(
+            positions(&state),
+            vec![(10.0, 10.0), (150.0, 100.0), (125.0, 75.0)]
+        );
+        assert_eq!(state.reveal_original(), None);
+    }
+
+    #[test]
+    fn test_redact_through_crop() {
+        let red_at = |state: &AppState, x, y| {
+            let pixbuf = state.final_image.as_ref().unwrap();
+            pick_color_from_pixbuf(pixbuf, x, y).unwrap().color.red()
+        };
+        let mut state = AppState::new();
+        let image = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300).unwrap();
+        image.fill(0xffffffff);
+        state.final_image = Some(image);
+
+        assert!(crop_to(&mut state, 100.0, 100.0, 200.0, 100.0));
+        assert!(state.redact((10.0, 10.0, 20.0, 20.0), RedactStyle::Black));
+        assert!(!state.redact((500.0, 10.0, 20.0, 20.0), RedactStyle::Black));
+        assert_eq!(red_at(&state, 15, 15), 0.0);
+
+        // Undoing the crop doesn't bring the pixels back
+        state.reveal_original();
+        assert_eq!(red_at(&state, 115, 115), 0.0);
+        assert_eq!(red_at(&state, 5, 5), 1.0);
+    }
+
+    #[test]
+    fn test_undo_crop() {
+        let mut state = AppState::new();
+        state.final_image = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300);
+        state.editor.annotations.add(rectangle(10.0, 10.0));
+
+        assert!(crop_to(&mut state, 100.0, 100.0, 200.0, 100.0));
+        assert!(state.editor.annotations.is_empty());
+        state.editor.annotations.add(rectangle(5.0, 5.0));
+
+        // The annotation drawn on the crop goes first, then the crop itself
+        assert!(state.undo());
+        assert!(state.editor.crop.is_some());
+        assert!(state.undo());
+        assert!(state.editor.crop.is_none());
+        assert_eq!(positions(&state), vec![(10.0, 10.0)]);
+    }
+
+    #[test]
+    fn test_history() {
+        let mut state = AppState::new();
+        state.final_image = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300);
+        state.editor.annotations.add(rectangle(10.0, 10.0));
+        state.editor.annotations.add(rectangle(150.0, 100.0));
+        assert!(crop_to(&mut state, 100.0, 50.0, 200.0, 200.0));
+        state.editor.annotations.add(rectangle(5.0, 5.0));
+        assert_eq!(
+            state.history(),
+            vec!["Rectangle", "Rectangle", "Crop", "Rectangle"]
+        );
+
+        let (image, annotations) = state.history_preview(4).unwrap();
+        assert_eq!((image.width(), annotations.len()), (200, 2));
+        let (image, annotations) = state.history_preview(1).unwrap();
+        assert_eq!(image.width(), 400);
+        assert_eq!(annotations[0].position(), (10.0, 10.0));
+
+        assert!(state.undo_to(2));
+        assert!(state.editor.crop.is_none());
+        assert_eq!(positions(&state), vec![(10.0, 10.0), (150.0, 100.0)]);
+        assert!(!state.undo_to(2));
+        assert!(state.undo_to(0));
+        assert!(state.history().is_empty());
+    }
+
+    #[test]
+    fn test_restore_snapshot_keeps_redaction() {
+        let mut state = AppState::new();
+        let image = Pixbuf::new(Colorspace::Rgb, false, 8, 400, 300).unwrap();
+        image.fill(0xffffffff);
+        state.final_image = Some(image);
+        state.editor.annotations.add(rectangle(10.0, 10.0));
+        assert!(crop_to(&mut state, 100.0, 100.0, 200.0, 100.0));
+        state.editor.annotations.add(rectangle(5.0, 5.0));
+        assert!(state.take_snapshot("Cropped"));
+
+        state.reveal_original();
+        state.editor.annotations.add(rectangle(50.0, 50.0));
+        assert!(state.redact((110.0, 110.0, 10.0, 10.0), RedactStyle::Black));
+
+        assert!(state.restore_snapshot(0));
+        let crop = state.editor.crop.as_ref().unwrap();
+        assert_eq!(crop.region, (100, 100, 200, 100));
+        assert_eq!(positions(&state), vec![(5.0, 5.0)]);
+        let pixbuf = state.final_image.as_ref().unwrap();
+        let color = pick_color_from_pixbuf(pixbuf, 15, 15).unwrap().color;
+        assert_eq!(color.red(), 0.0);
+        assert!(!state.restore_snapshot(1));
+    }
+}
@@ -1,6 +1,9 @@
 use log::debug;
 use std::env;
-use std::process::Command;
+
+use super::hyprland::Hyprland;
+use super::mock::{mock_backend_enabled, BACKEND_ENV};
+use super::tools::{missing_screen_capture_tools_for, portal_available};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DisplayServer {
@@ -97,16 +100,86 @@ impl DesktopSession {
         self.desktop_environment == DesktopEnvironment::Sway
     }
 
+    /// Whether a minimized window can still be captured. KWin on X11 keeps
+    /// minimized windows' contents around for its thumbnails; elsewhere they
+    /// are unmapped and have nothing to grab, and on Wayland no available tool
+    /// can address a window that is not on screen.
+    pub fn captures_minimized_windows(&self) -> bool {
+        self.window_list_backend() == WindowListBackend::X11
+            && self.desktop_environment == DesktopEnvironment::Kde
+    }
+
     pub fn window_list_backend(&self) -> WindowListBackend {
+        if mock_backend_enabled() {
+            return WindowListBackend::Mock;
+        }
+
         match (&self.desktop_environment, &self.display_server) {
             (DesktopEnvironment::Hyprland, DisplayServer::Wayland) => WindowListBackend::Hyprland,
             (DesktopEnvironment::Sway, DisplayServer::Wayland) => WindowListBackend::Sway,
             (DesktopEnvironment::Gnome, DisplayServer::Wayland) => WindowListBackend::GnomeWayland,
             (DesktopEnvironment::Kde, DisplayServer::Wayland) => WindowListBackend::KdeWayland,
+            (_, DisplayServer::Wayland) => WindowListBackend::ForeignToplevel,
             (_, DisplayServer::X11) => WindowListBackend::X11,
             _ => WindowListBackend::Xcap,
         }
     }
+
+    /// How screen and region captures are taken in this session
+    pub fn screen_capture_backend(&self) -> ScreenCaptureBackend {
+        if mock_backend_enabled() {
+            return ScreenCaptureBackend::Mock;
+        }
+
+        let requested = env::var(BACKEND_ENV).ok();
+        select_screen_capture_backend(
+            requested.as_deref(),
+            is_sandboxed(),
+            self.display_server,
+            missing_screen_capture_tools_for(self).is_none(),
+            portal_available(),
+        )
+    }
+
+    /// Where screen recordings get their frames in this session
+    pub fn recording_backend(&self) -> RecordingBackend {
+        if mock_backend_enabled() {
+            RecordingBackend::Mock
+        } else if self.display_server == DisplayServer::X11 && !is_sandboxed() {
+            RecordingBackend::X11
+        } else {
+            RecordingBackend::Portal
+        }
+    }
+}
+
+fn select_screen_capture_backend(
+    requested: Option<&str>,
+    sandboxed: bool,
+    display_server: DisplayServer,
+    tools_installed: bool,
+    portal_available: bool,
+) -> ScreenCaptureBackend {
+    if requested.is_some_and(|backend| backend.eq_ignore_ascii_case("portal")) {
+        return ScreenCaptureBackend::Portal;
+    }
+    // Inside Flatpak the compositor's tools can't reach the screen, and a
+    // Wayland session without them has nothing else to ask
+    let tools_unusable =
+        sandboxed || (display_server == DisplayServer::Wayland && !tools_installed);
+    if tools_unusable && portal_available {
+        return ScreenCaptureBackend::Portal;
+    }
+
+    match display_server {
+        DisplayServer::X11 => ScreenCaptureBackend::Xcap,
+        DisplayServer::Wayland | DisplayServer::Unknown => ScreenCaptureBackend::Compositor,
+    }
+}
+
+/// Whether the app runs in a Flatpak sandbox
+fn is_sandboxed() -> bool {
+    env::var("FLATPAK_ID").is_ok() || std::path::Path::new("/.flatpak-info").exists()
 }
 
 impl std::fmt::Display for DesktopSession {
@@ -121,19 +194,97 @@ pub enum WindowListBackend {
     Sway,
     GnomeWayland,
     KdeWayland,
+
+    /// The wlr-foreign-toplevel-management protocol of other wlroots
+    /// compositors, see `capture::foreign_toplevel`
+    ForeignToplevel,
+
     X11,
     Xcap,
+
+    /// Synthetic windows, see `capture::mock`
+    Mock,
+}
+
+impl WindowListBackend {
+    /// Whether window captures may fall back to cropping the window's area
+    /// from a screenshot, picking up anything stacked over it
+    pub fn crops_by_geometry(&self) -> bool {
+        matches!(
+            self,
+            WindowListBackend::GnomeWayland | WindowListBackend::KdeWayland
+        )
+    }
+
+    /// Whether the window is raised before its area is grabbed
+    pub fn raises_window(&self) -> bool {
+        matches!(
+            self,
+            WindowListBackend::Hyprland
+                | WindowListBackend::Sway
+                | WindowListBackend::ForeignToplevel
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenCaptureBackend {
+    /// `org.freedesktop.portal.Screenshot`, which works inside Flatpak
+    Portal,
+
+    /// grim, gnome-screenshot or spectacle, whichever suits the compositor
+    Compositor,
+
+    Xcap,
+
+    /// Synthetic screen, see `capture::mock`
+    Mock,
+}
+
+impl std::fmt::Display for ScreenCaptureBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScreenCaptureBackend::Portal => write!(f, "XDG Desktop Portal"),
+            ScreenCaptureBackend::Compositor => write!(f, "Compositor tools"),
+            ScreenCaptureBackend::Xcap => write!(f, "xcap"),
+            ScreenCaptureBackend::Mock => write!(f, "Mock (synthetic)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordingBackend {
+    /// `org.freedesktop.portal.ScreenCast`, streamed over PipeWire
+    Portal,
+
+    /// GStreamer's `ximagesrc`
+    X11,
+
+    /// GStreamer's test pattern, see `capture::mock`
+    Mock,
+}
+
+impl std::fmt::Display for RecordingBackend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingBackend::Portal => write!(f, "XDG Desktop Portal (PipeWire)"),
+            RecordingBackend::X11 => write!(f, "X11"),
+            RecordingBackend::Mock => write!(f, "Mock (test pattern)"),
+        }
+    }
 }
 
 impl std::fmt::Display for WindowListBackend {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            WindowListBackend::Hyprland => write!(f, "Hyprland (hyprctl)"),
-            WindowListBackend::Sway => write!(f, "Sway (swaymsg)"),
+            WindowListBackend::Hyprland => write!(f, "Hyprland (IPC)"),
+            WindowListBackend::Sway => write!(f, "Sway (IPC)"),
             WindowListBackend::GnomeWayland => write!(f, "GNOME Wayland (D-Bus)"),
             WindowListBackend::KdeWayland => write!(f, "KDE Wayland (D-Bus)"),
-            WindowListBackend::X11 => write!(f, "X11"),
+            WindowListBackend::ForeignToplevel => write!(f, "wlr foreign toplevel (Wayland)"),
+            WindowListBackend::X11 => write!(f, "X11 (EWMH)"),
             WindowListBackend::Xcap => write!(f, "xcap (fallback)"),
+            WindowListBackend::Mock => write!(f, "Mock (synthetic)"),
         }
     }
 }
@@ -244,10 +395,44 @@ fn detect_desktop_environment(display_server: &DisplayServer) -> DesktopEnvironm
     DesktopEnvironment::Other(None)
 }
 
+/// Whether Hyprland answers on its socket, which a stale
+/// `HYPRLAND_INSTANCE_SIGNATURE` left from a nested session wouldn't
 fn is_hyprland_running() -> bool {
-    Command::new("hyprctl")
-        .arg("version")
-        .output()
-        .map(|output| output.status.success())
-        .unwrap_or(false)
+    Hyprland::connect()
+        .and_then(|hyprland| hyprland.monitors())
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_screen_capture_backend() {
+        let select = select_screen_capture_backend;
+        assert_eq!(
+            select(None, false, DisplayServer::Wayland, true, true),
+            ScreenCaptureBackend::Compositor
+        );
+        assert_eq!(
+            select(None, false, DisplayServer::X11, false, true),
+            ScreenCaptureBackend::Xcap
+        );
+        assert_eq!(
+            select(None, true, DisplayServer::X11, true, true),
+            ScreenCaptureBackend::Portal
+        );
+        assert_eq!(
+            select(None, false, DisplayServer::Wayland, false, true),
+            ScreenCaptureBackend::Portal
+        );
+        assert_eq!(
+            select(None, false, DisplayServer::Wayland, false, false),
+            ScreenCaptureBackend::Compositor
+        );
+        assert_eq!(
+            select(Some("Portal"), false, DisplayServer::X11, true, false),
+            ScreenCaptureBackend::Portal
+        );
+    }
 }
@@ -0,0 +1,426 @@
+//! Details of an image file opened in the editor, and what saving it again
+//! loses.
+//!
+//! Saving writes a plain PNG through GdkPixbuf, which keeps the pixels and
+//! nothing else: EXIF data, color profiles and the resolution are left out.
+//! Only the few EXIF tags worth showing are read, from the first IFD.
+
+use image::{ColorType, ImageDecoder, ImageFormat, ImageReader};
+use std::io::Cursor;
+use std::path::Path;
+
+/// Meters per inch, for PNG's pixels-per-meter resolution
+const METERS_PER_INCH: f64 = 0.0254;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Text EXIF tags shown, by tag number
+const EXIF_TEXT_TAGS: [(u16, &str); 6] = [
+    (0x010f, "Camera Make"),
+    (0x0110, "Camera Model"),
+    (0x0131, "Software"),
+    (0x0132, "Date Taken"),
+    (0x013b, "Artist"),
+    (0x8298, "Copyright"),
+];
+
+const EXIF_ORIENTATION: u16 = 0x0112;
+const EXIF_X_RESOLUTION: u16 = 0x011a;
+const EXIF_RESOLUTION_UNIT: u16 = 0x0128;
+const EXIF_GPS_IFD: u16 = 0x8825;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileMetadata {
+    /// Name of the file format, such as "JPEG"
+    pub format: String,
+
+    pub width: u32,
+
+    pub height: u32,
+
+    /// Channels and bit depth, such as "RGBA, 8 bits per channel"
+    pub color: String,
+
+    /// Horizontal resolution in dots per inch
+    pub dpi: Option<f64>,
+
+    /// Label/value pairs of the EXIF tags worth showing
+    pub exif: Vec<(&'static str, String)>,
+
+    /// The file has EXIF data, shown or not
+    pub has_exif: bool,
+
+    /// The EXIF data includes GPS coordinates
+    pub has_location: bool,
+
+    /// Description of the embedded color profile, if there is one
+    pub color_profile: Option<String>,
+}
+
+impl FileMetadata {
+    /// Label/value pairs for display, skipping anything unknown
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = vec![
+            ("Format", self.format.clone()),
+            ("Size", format!("{} × {}", self.width, self.height)),
+            ("Color", self.color.clone()),
+        ];
+        if let Some(dpi) = self.dpi {
+            rows.push(("DPI", format!("{:.0}", dpi)));
+        }
+        if let Some(ref profile) = self.color_profile {
+            rows.push(("Color Profile", profile.clone()));
+        }
+        rows.extend(self.exif.iter().cloned());
+        if self.has_location {
+            rows.push(("Location", "Included".to_string()));
+        }
+        rows
+    }
+
+    /// What saving the image as PNG would drop or change, one sentence each
+    pub fn save_warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.format != "PNG" {
+            warnings.push(format!("Saving converts the {} file to PNG.", self.format));
+        }
+        if self.has_location {
+            warnings.push("Saving drops the EXIF data, including the location.".to_string());
+        } else if self.has_exif {
+            warnings.push("Saving drops the EXIF data.".to_string());
+        }
+        if let Some(ref profile) = self.color_profile {
+            warnings.push(format!(
+                "Saving drops the “{}” color profile, so colors may look different.",
+                profile
+            ));
+        }
+        if let Some(dpi) = self.dpi {
+            warnings.push(format!("Saving drops the resolution of {:.0} DPI.", dpi));
+        }
+        warnings
+    }
+}
+
+/// Read the format, size, resolution, EXIF data and color profile of the
+/// image at `path` without decoding its pixels
+pub fn read_file_metadata(path: &Path) -> Result<FileMetadata, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let reader = ImageReader::new(Cursor::new(&bytes))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    let format = reader.format();
+    let mut decoder = reader.into_decoder().map_err(|e| e.to_string())?;
+
+    let (width, height) = decoder.dimensions();
+    let color_type = decoder.color_type();
+    let mut metadata = FileMetadata {
+        format: format
+            .map(format_name)
+            .unwrap_or_else(|| "Unknown".to_string()),
+        width,
+        height,
+        color: color_label(color_type),
+        ..Default::default()
+    };
+
+    if let Ok(Some(exif)) = decoder.exif_metadata() {
+        metadata.has_exif = true;
+        let exif = parse_exif(&exif);
+        metadata.exif = exif.fields;
+        metadata.has_location = exif.has_location;
+        metadata.dpi = exif.dpi;
+    }
+    if let Ok(Some(profile)) = decoder.icc_profile() {
+        metadata.color_profile =
+            Some(icc_description(&profile).unwrap_or_else(|| "Unnamed".to_string()));
+    }
+    metadata.dpi = match format {
+        Some(ImageFormat::Png) => png_dpi(&bytes).or(metadata.dpi),
+        Some(ImageFormat::Jpeg) => jfif_dpi(&bytes).or(metadata.dpi),
+        _ => metadata.dpi,
+    };
+    Ok(metadata)
+}
+
+fn format_name(format: ImageFormat) -> String {
+    format
+        .extensions_str()
+        .first()
+        .map(|ext| match *ext {
+            "jpg" => "JPEG".to_string(),
+            "tif" => "TIFF".to_string(),
+            ext => ext.to_uppercase(),
+        })
+        .unwrap_or_else(|| format!("{:?}", format))
+}
+
+fn color_label(color_type: ColorType) -> String {
+    let channels = match (color_type.has_color(), color_type.has_alpha()) {
+        (true, true) => "RGBA",
+        (true, false) => "RGB",
+        (false, true) => "Grayscale with alpha",
+        (false, false) => "Grayscale",
+    };
+    let bits = color_type.bits_per_pixel() / color_type.channel_count() as u16;
+    format!("{}, {} bits per channel", channels, bits)
+}
+
+/// Resolution from a PNG's `pHYs` chunk, when given in pixels per meter
+fn png_dpi(bytes: &[u8]) -> Option<f64> {
+    if !bytes.starts_with(&PNG_SIGNATURE) {
+        return None;
+    }
+    let mut offset = PNG_SIGNATURE.len();
+    while offset + 8 <= bytes.len() {
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().ok()?) as usize;
+        let kind = &bytes[offset + 4..offset + 8];
+        let data = bytes.get(offset + 8..offset + 8 + length)?;
+        match kind {
+            b"pHYs" if length == 9 && data[8] == 1 => {
+                let per_meter = u32::from_be_bytes(data[0..4].try_into().ok()?);
+                return Some(per_meter as f64 * METERS_PER_INCH);
+            }
+            // The resolution has to come before the image data
+            b"IDAT" | b"IEND" => return None,
+            _ => {}
+        }
+        // Length, type, data and CRC
+        offset += 12 + length;
+    }
+    None
+}
+
+/// Resolution from a JPEG's JFIF header, when given in dots per inch or
+/// per centimeter
+fn jfif_dpi(bytes: &[u8]) -> Option<f64> {
+    // SOI, then an APP0 segment: length, "JFIF\0", version, unit, density
+    let app0 = bytes.get(0..18)?;
+    if app0[0..4] != [0xff, 0xd8, 0xff, 0xe0] || &app0[6..11] != b"JFIF\0" {
+        return None;
+    }
+    let density = u16::from_be_bytes([app0[14], app0[15]]) as f64;
+    match app0[13] {
+        1 => Some(density),
+        2 => Some(density * 2.54),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+struct ExifSummary {
+    fields: Vec<(&'static str, String)>,
+    dpi: Option<f64>,
+    has_location: bool,
+}
+
+/// Reads values out of a TIFF structure in either byte order
+struct TiffReader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl TiffReader<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+/// The tags worth showing from the first IFD of a raw EXIF chunk, which
+/// starts with the TIFF header
+fn parse_exif(chunk: &[u8]) -> ExifSummary {
+    let mut summary = ExifSummary::default();
+    let big_endian = match chunk.get(0..4) {
+        Some([b'I', b'I', 42, 0]) => false,
+        Some([b'M', b'M', 0, 42]) => true,
+        _ => return summary,
+    };
+    let tiff = TiffReader {
+        data: chunk,
+        big_endian,
+    };
+    let ifd = match tiff.u32_at(4) {
+        Some(offset) => offset as usize,
+        None => return summary,
+    };
+    let count = tiff.u16_at(ifd).unwrap_or(0) as usize;
+
+    let mut x_resolution = None;
+    let mut per_centimeter = false;
+    for index in 0..count {
+        let entry = ifd + 2 + index * 12;
+        let (tag, kind, length) = match (
+            tiff.u16_at(entry),
+            tiff.u16_at(entry + 2),
+            tiff.u32_at(entry + 4),
+        ) {
+            (Some(tag), Some(kind), Some(length)) => (tag, kind, length as usize),
+            _ => break,
+        };
+        // Values of four bytes or less are stored in place of their offset
+        let value = entry + 8;
+
+        match (tag, kind) {
+            (EXIF_ORIENTATION, 3) => {
+                if let Some(orientation) = tiff.u16_at(value) {
+                    summary
+                        .fields
+                        .push(("Orientation", orientation_label(orientation).to_string()));
+                }
+            }
+            (EXIF_X_RESOLUTION, 5) => {
+                let offset = tiff.u32_at(value).unwrap_or(0) as usize;
+                if let (Some(numerator), Some(denominator)) =
+                    (tiff.u32_at(offset), tiff.u32_at(offset + 4))
+                {
+                    if denominator > 0 {
+                        x_resolution = Some(numerator as f64 / denominator as f64);
+                    }
+                }
+            }
+            (EXIF_RESOLUTION_UNIT, 3) => per_centimeter = tiff.u16_at(value) == Some(3),
+            (EXIF_GPS_IFD, _) => summary.has_location = true,
+            (tag, 2) => {
+                let label = match EXIF_TEXT_TAGS.iter().find(|(t, _)| *t == tag) {
+                    Some((_, label)) => *label,
+                    None => continue,
+                };
+                let start = if length <= 4 {
+                    value
+                } else {
+                    tiff.u32_at(value).unwrap_or(0) as usize
+                };
+                if let Some(text) = chunk.get(start..start + length) {
+                    let text = String::from_utf8_lossy(text);
+                    let text = text.trim_end_matches('\0').trim();
+                    if !text.is_empty() {
+                        summary.fields.push((label, text.to_string()));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    summary.dpi = x_resolution.map(|resolution| {
+        if per_centimeter {
+            resolution * 2.54
+        } else {
+            resolution
+        }
+    });
+    summary
+}
+
+fn orientation_label(orientation: u16) -> &'static str {
+    match orientation {
+        1 => "Normal",
+        2 => "Mirrored",
+        3 => "Rotated 180°",
+        4 => "Flipped",
+        5 => "Mirrored, rotated 270°",
+        6 => "Rotated 90°",
+        7 => "Mirrored, rotated 90°",
+        8 => "Rotated 270°",
+        _ => "Unknown",
+    }
+}
+
+/// The `desc` tag of an ICC profile, in the version 2 `desc` or version 4
+/// `mluc` encoding
+fn icc_description(profile: &[u8]) -> Option<String> {
+    let be_u32 = |offset: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(profile.get(offset..offset + 4)?.try_into().ok()?) as usize)
+    };
+    // The tag table follows the 128 byte header
+    let tags = be_u32(128)?;
+    let start = (0..tags)
+        .map(|index| 132 + index * 12)
+        .find(|entry| profile.get(*entry..*entry + 4) == Some(b"desc"))
+        .and_then(|entry| be_u32(entry + 4))?;
+
+    let text = match profile.get(start..start + 4)? {
+        b"desc" => {
+            let length = be_u32(start + 8)?;
+            String::from_utf8_lossy(profile.get(start + 12..start + 12 + length)?).into_owned()
+        }
+        b"mluc" => {
+            // The first record's length and offset, in UTF-16 bytes
+            let length = be_u32(start + 20)?;
+            let offset = be_u32(start + 24)?;
+            let units: Vec<u16> = profile
+                .get(start + offset..start + offset + length)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exif() {
+        // Little-endian TIFF header, then an IFD of four entries at offset 8
+        let mut chunk = vec![b'I', b'I', 42, 0, 8, 0, 0, 0, 4, 0];
+        let mut entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            chunk.extend(tag.to_le_bytes());
+            chunk.extend(kind.to_le_bytes());
+            chunk.extend(count.to_le_bytes());
+            chunk.extend(value.to_le_bytes());
+        };
+        entry(0x010f, 2, 4, u32::from_le_bytes(*b"ACM\0"));
+        entry(0x0110, 2, 9, 62);
+        entry(0x0112, 3, 1, 6);
+        entry(0x011a, 5, 1, 71);
+        chunk.extend(0u32.to_le_bytes());
+        chunk.extend(b"Model X1\0");
+        chunk.extend(300u32.to_le_bytes());
+        chunk.extend(1u32.to_le_bytes());
+
+        let summary = parse_exif(&chunk);
+        assert_eq!(
+            summary.fields,
+            vec![
+                ("Camera Make", "ACM".to_string()),
+                ("Camera Model", "Model X1".to_string()),
+                ("Orientation", "Rotated 90°".to_string()),
+            ]
+        );
+        assert_eq!(summary.dpi, Some(300.0));
+        assert!(!summary.has_location);
+    }
+
+    #[test]
+    fn test_png_dpi() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(9u32.to_be_bytes());
+        png.extend(b"pHYs");
+        png.extend(3780u32.to_be_bytes());
+        png.extend(3780u32.to_be_bytes());
+        png.push(1);
+        png.extend([0; 4]);
+        assert_eq!(png_dpi(&png).map(f64::round), Some(96.0));
+        assert_eq!(png_dpi(&PNG_SIGNATURE), None);
+    }
+}
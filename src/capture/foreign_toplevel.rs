@@ -0,0 +1,480 @@
+//! Client for the wlr-foreign-toplevel-management Wayland protocol, which
+//! wlroots compositors such as river, labwc and Wayfire offer to taskbars, so
+//! windows can be listed where the compositor has no IPC of its own.
+//!
+//! The Wayland wire format is spoken directly over the compositor's socket:
+//! every message is the id of the object it is for, then its size and opcode
+//! packed into one 32-bit word, then its arguments, each padded to 32 bits.
+//! The protocol gives each window's title, app id, state and outputs, but not
+//! where on them it is.
+
+use log::debug;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const MANAGER_INTERFACE: &str = "zwlr_foreign_toplevel_manager_v1";
+
+/// The `wl_display` singleton every client starts with
+const DISPLAY_ID: u32 = 1;
+
+// Requests
+const DISPLAY_SYNC: u16 = 0;
+const DISPLAY_GET_REGISTRY: u16 = 1;
+const REGISTRY_BIND: u16 = 0;
+const HANDLE_ACTIVATE: u16 = 4;
+
+// Events
+const DISPLAY_ERROR: u16 = 0;
+const REGISTRY_GLOBAL: u16 = 0;
+const CALLBACK_DONE: u16 = 0;
+const OUTPUT_NAME: u16 = 4;
+const MANAGER_TOPLEVEL: u16 = 0;
+const HANDLE_TITLE: u16 = 0;
+const HANDLE_APP_ID: u16 = 1;
+const HANDLE_OUTPUT_ENTER: u16 = 2;
+const HANDLE_OUTPUT_LEAVE: u16 = 3;
+const HANDLE_STATE: u16 = 4;
+const HANDLE_CLOSED: u16 = 6;
+
+// Values in a handle's state array
+const STATE_MAXIMIZED: u32 = 0;
+const STATE_MINIMIZED: u32 = 1;
+const STATE_ACTIVATED: u32 = 2;
+const STATE_FULLSCREEN: u32 = 3;
+
+/// Newest versions spoken: outputs have names from 4 on, and the manager's
+/// version 3 only adds events that are skipped
+const OUTPUT_VERSION: u32 = 4;
+const MANAGER_VERSION: u32 = 3;
+
+/// Longest wait for the compositor to answer
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Path of the compositor's socket, from `$WAYLAND_DISPLAY`, which is either
+/// absolute or relative to `$XDG_RUNTIME_DIR`
+pub fn socket_path() -> Option<PathBuf> {
+    let display = std::env::var("WAYLAND_DISPLAY").unwrap_or_else(|_| "wayland-0".to_string());
+    let display = PathBuf::from(display);
+    if display.is_absolute() {
+        return Some(display);
+    }
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")?;
+    Some(PathBuf::from(runtime_dir).join(display))
+}
+
+/// A window as the compositor's taskbar interface describes it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Toplevel {
+    /// Object id of the window's handle, valid on the connection that listed
+    /// it only
+    handle: u32,
+
+    pub title: String,
+
+    pub app_id: String,
+
+    /// Names of the outputs the window is shown on, such as `DP-1`
+    pub outputs: Vec<String>,
+
+    pub is_maximized: bool,
+
+    pub is_minimized: bool,
+
+    pub is_activated: bool,
+
+    pub is_fullscreen: bool,
+}
+
+impl Toplevel {
+    fn set_states(&mut self, states: &[u32]) {
+        self.is_maximized = states.contains(&STATE_MAXIMIZED);
+        self.is_minimized = states.contains(&STATE_MINIMIZED);
+        self.is_activated = states.contains(&STATE_ACTIVATED);
+        self.is_fullscreen = states.contains(&STATE_FULLSCREEN);
+    }
+}
+
+/// Arguments of a request, in wire format
+#[derive(Default)]
+struct Arguments(Vec<u8>);
+
+impl Arguments {
+    fn uint(mut self, value: u32) -> Self {
+        self.0.extend_from_slice(&value.to_ne_bytes());
+        self
+    }
+
+    /// Length including the terminating NUL, the bytes, then padding
+    fn string(mut self, value: &str) -> Self {
+        self.0
+            .extend_from_slice(&(value.len() as u32 + 1).to_ne_bytes());
+        self.0.extend_from_slice(value.as_bytes());
+        self.0.push(0);
+        self.pad();
+        self
+    }
+
+    fn pad(&mut self) {
+        while self.0.len() % 4 != 0 {
+            self.0.push(0);
+        }
+    }
+}
+
+/// Reader over the arguments of an event
+struct EventReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> EventReader<'a> {
+    fn uint(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// The bytes of an array, or of a string with its NUL
+    fn array(&mut self) -> Result<&'a [u8], String> {
+        let length = self.uint()? as usize;
+        let bytes = self.take(length)?;
+        self.take((4 - length % 4) % 4)?;
+        Ok(bytes)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let bytes = self.array()?;
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        if self.data.len() < length {
+            return Err("Truncated message from the compositor".to_string());
+        }
+        let (taken, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(taken)
+    }
+}
+
+fn message(object: u32, opcode: u16, arguments: Arguments) -> Vec<u8> {
+    let size = 8 + arguments.0.len() as u32;
+    let mut message = object.to_ne_bytes().to_vec();
+    message.extend_from_slice(&((size << 16) | opcode as u32).to_ne_bytes());
+    message.extend_from_slice(&arguments.0);
+    message
+}
+
+/// Values of a `state` array
+fn parse_states(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// A global the compositor advertises
+struct Global {
+    name: u32,
+    interface: String,
+    version: u32,
+}
+
+/// What each object this connection knows of is
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Object {
+    Registry,
+    Callback,
+    Output,
+    Seat,
+    Manager,
+    Handle,
+}
+
+/// A connection to the compositor, with its windows listed
+pub struct ForeignToplevels {
+    stream: UnixStream,
+    next_id: u32,
+    objects: HashMap<u32, Object>,
+    globals: Vec<Global>,
+    output_names: HashMap<u32, String>,
+    toplevels: Vec<Toplevel>,
+
+    /// Output objects each window is on, by handle
+    toplevel_outputs: HashMap<u32, Vec<u32>>,
+    seat: Option<u32>,
+}
+
+impl ForeignToplevels {
+    /// Connect and list the windows, failing when the compositor doesn't
+    /// offer the protocol
+    pub fn connect() -> Result<Self, String> {
+        let path = socket_path().ok_or("XDG_RUNTIME_DIR is not set")?;
+        let stream = UnixStream::connect(&path)
+            .map_err(|e| format!("Failed to connect to {}: {}", path.display(), e))?;
+        stream
+            .set_read_timeout(Some(REPLY_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+
+        let mut connection = Self {
+            stream,
+            next_id: DISPLAY_ID + 1,
+            objects: HashMap::new(),
+            globals: Vec::new(),
+            output_names: HashMap::new(),
+            toplevels: Vec::new(),
+            toplevel_outputs: HashMap::new(),
+            seat: None,
+        };
+        let registry = connection.new_object(Object::Registry);
+        connection.send(
+            DISPLAY_ID,
+            DISPLAY_GET_REGISTRY,
+            Arguments::default().uint(registry),
+        )?;
+        connection.roundtrip()?;
+
+        let manager = connection
+            .globals
+            .iter()
+            .find(|global| global.interface == MANAGER_INTERFACE)
+            .map(|global| (global.name, global.version.min(MANAGER_VERSION)))
+            .ok_or_else(|| format!("The compositor doesn't offer {}", MANAGER_INTERFACE))?;
+        let outputs: Vec<(u32, u32)> = connection
+            .globals
+            .iter()
+            .filter(|global| global.interface == "wl_output")
+            .map(|global| (global.name, global.version.min(OUTPUT_VERSION)))
+            .collect();
+        let seat = connection
+            .globals
+            .iter()
+            .find(|global| global.interface == "wl_seat")
+            .map(|global| global.name);
+
+        // Outputs are bound first, as windows are only said to enter outputs
+        // the client knows of
+        for (name, version) in outputs {
+            connection.bind(registry, name, "wl_output", version, Object::Output)?;
+        }
+        if let Some(name) = seat {
+            connection.seat = Some(connection.bind(registry, name, "wl_seat", 1, Object::Seat)?);
+        }
+        connection.bind(
+            registry,
+            manager.0,
+            MANAGER_INTERFACE,
+            manager.1,
+            Object::Manager,
+        )?;
+        connection.roundtrip()?;
+        debug!(
+            "The compositor listed {} toplevels",
+            connection.toplevels.len()
+        );
+        Ok(connection)
+    }
+
+    /// The open windows, in the order they were mapped
+    pub fn toplevels(&self) -> Vec<Toplevel> {
+        self.toplevels
+            .iter()
+            .map(|toplevel| Toplevel {
+                outputs: self
+                    .toplevel_outputs
+                    .get(&toplevel.handle)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|output| self.output_names.get(output).cloned())
+                    .collect(),
+                ..toplevel.clone()
+            })
+            .collect()
+    }
+
+    /// Focus `toplevel` and bring it to the front
+    pub fn activate(&mut self, toplevel: &Toplevel) -> Result<(), String> {
+        let seat = self
+            .seat
+            .ok_or("The compositor has no seat to focus with")?;
+        debug!("Activating toplevel {:?}", toplevel.title);
+        self.send(
+            toplevel.handle,
+            HANDLE_ACTIVATE,
+            Arguments::default().uint(seat),
+        )?;
+        self.roundtrip()
+    }
+
+    fn new_object(&mut self, object: Object) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.objects.insert(id, object);
+        id
+    }
+
+    fn bind(
+        &mut self,
+        registry: u32,
+        name: u32,
+        interface: &str,
+        version: u32,
+        object: Object,
+    ) -> Result<u32, String> {
+        let id = self.new_object(object);
+        // The new id is untyped, so its interface and version come first
+        let arguments = Arguments::default()
+            .uint(name)
+            .string(interface)
+            .uint(version)
+            .uint(id);
+        self.send(registry, REGISTRY_BIND, arguments)?;
+        Ok(id)
+    }
+
+    fn send(&mut self, object: u32, opcode: u16, arguments: Arguments) -> Result<(), String> {
+        self.stream
+            .write_all(&message(object, opcode, arguments))
+            .map_err(|e| format!("Failed to send to the compositor: {}", e))
+    }
+
+    /// Handle events until the compositor has answered everything sent
+    fn roundtrip(&mut self) -> Result<(), String> {
+        let callback = self.new_object(Object::Callback);
+        self.send(
+            DISPLAY_ID,
+            DISPLAY_SYNC,
+            Arguments::default().uint(callback),
+        )?;
+        loop {
+            let mut header = [0u8; 8];
+            self.stream
+                .read_exact(&mut header)
+                .map_err(|e| format!("Failed to read from the compositor: {}", e))?;
+            let object = u32::from_ne_bytes([header[0], header[1], header[2], header[3]]);
+            let word = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+            let (size, opcode) = ((word >> 16) as usize, (word & 0xffff) as u16);
+            let mut data = vec![0u8; size.saturating_sub(8)];
+            self.stream
+                .read_exact(&mut data)
+                .map_err(|e| format!("Failed to read from the compositor: {}", e))?;
+
+            if object == callback && opcode == CALLBACK_DONE {
+                self.objects.remove(&callback);
+                return Ok(());
+            }
+            self.handle_event(object, opcode, &mut EventReader { data: &data })?;
+        }
+    }
+
+    fn handle_event(
+        &mut self,
+        object: u32,
+        opcode: u16,
+        args: &mut EventReader,
+    ) -> Result<(), String> {
+        if object == DISPLAY_ID {
+            if opcode == DISPLAY_ERROR {
+                let (failed, code) = (args.uint()?, args.uint()?);
+                return Err(format!(
+                    "The compositor reported error {} on object {}: {}",
+                    code,
+                    failed,
+                    args.string()?
+                ));
+            }
+            return Ok(());
+        }
+
+        match (self.objects.get(&object).copied(), opcode) {
+            (Some(Object::Registry), REGISTRY_GLOBAL) => {
+                let global = Global {
+                    name: args.uint()?,
+                    interface: args.string()?,
+                    version: args.uint()?,
+                };
+                self.globals.push(global);
+            }
+            (Some(Object::Output), OUTPUT_NAME) => {
+                self.output_names.insert(object, args.string()?);
+            }
+            (Some(Object::Manager), MANAGER_TOPLEVEL) => {
+                let handle = args.uint()?;
+                self.objects.insert(handle, Object::Handle);
+                self.toplevels.push(Toplevel {
+                    handle,
+                    ..Default::default()
+                });
+            }
+            (Some(Object::Handle), HANDLE_OUTPUT_ENTER) => {
+                let output = args.uint()?;
+                self.toplevel_outputs
+                    .entry(object)
+                    .or_default()
+                    .push(output);
+            }
+            (Some(Object::Handle), HANDLE_OUTPUT_LEAVE) => {
+                let output = args.uint()?;
+                if let Some(outputs) = self.toplevel_outputs.get_mut(&object) {
+                    outputs.retain(|o| *o != output);
+                }
+            }
+            (Some(Object::Handle), HANDLE_CLOSED) => {
+                self.toplevels.retain(|toplevel| toplevel.handle != object);
+                self.toplevel_outputs.remove(&object);
+            }
+            (Some(Object::Handle), _) => {
+                let toplevel = match self.toplevels.iter_mut().find(|t| t.handle == object) {
+                    Some(toplevel) => toplevel,
+                    None => return Ok(()),
+                };
+                match opcode {
+                    HANDLE_TITLE => toplevel.title = args.string()?,
+                    HANDLE_APP_ID => toplevel.app_id = args.string()?,
+                    HANDLE_STATE => toplevel.set_states(&parse_states(args.array()?)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format() {
+        let arguments = Arguments::default().uint(7).string("wl_output").uint(4);
+        let bytes = message(2, REGISTRY_BIND, arguments);
+        // 8 header bytes, the name, the length, "wl_output\0" padded to 12
+        // and the version
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(
+            u32::from_ne_bytes(bytes[4..8].try_into().unwrap()),
+            32 << 16
+        );
+
+        let mut args = EventReader { data: &bytes[8..] };
+        assert_eq!(args.uint(), Ok(7));
+        assert_eq!(args.string(), Ok("wl_output".to_string()));
+        assert_eq!(args.uint(), Ok(4));
+        assert!(args.uint().is_err());
+    }
+
+    #[test]
+    fn test_states() {
+        let mut toplevel = Toplevel::default();
+        let bytes: Vec<u8> = [STATE_ACTIVATED, STATE_MAXIMIZED]
+            .iter()
+            .flat_map(|state| state.to_ne_bytes())
+            .collect();
+        toplevel.set_states(&parse_states(&bytes));
+        assert!(toplevel.is_activated && toplevel.is_maximized);
+        assert!(!toplevel.is_minimized && !toplevel.is_fullscreen);
+    }
+}
@@ -0,0 +1,255 @@
+//! Client for GNOME Shell's own D-Bus interfaces: `org.gnome.Shell.Introspect`
+//! for the window list and its changes, and `org.gnome.Shell.Screenshot` for
+//! window captures.
+//!
+//! Calls go over GIO's connection to the session bus, so replies come back as
+//! typed values rather than `gdbus` output to scrape. Recent Shells answer
+//! only callers they trust, such as their own portal; the errors they give
+//! everyone else are passed on for the backends to fall back from.
+//!
+//! Calls block until the Shell answers or `CALL_TIMEOUT_MS` runs out, so
+//! they are only made from workers and the window watcher's own thread,
+//! never the GTK main loop.
+
+use gtk4::gio;
+use gtk4::glib::{self, ToVariant, VariantDict, VariantTy};
+use log::debug;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const INTROSPECT_DEST: &str = "org.gnome.Shell.Introspect";
+const INTROSPECT_PATH: &str = "/org/gnome/Shell/Introspect";
+const INTROSPECT_INTERFACE: &str = "org.gnome.Shell.Introspect";
+
+const SCREENSHOT_DEST: &str = "org.gnome.Shell.Screenshot";
+const SCREENSHOT_PATH: &str = "/org/gnome/Shell/Screenshot";
+const SCREENSHOT_INTERFACE: &str = "org.gnome.Shell.Screenshot";
+
+/// Longest wait for the Shell to answer a call, in milliseconds
+const CALL_TIMEOUT_MS: i32 = 5000;
+
+/// A window as the introspection API describes it, which gives its size but
+/// not its position
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShellWindow {
+    /// Mutter's id for the window, kept for its lifetime
+    pub id: u64,
+
+    pub title: String,
+
+    pub wm_class: String,
+
+    /// Desktop file id of the application, when the Shell knows it
+    pub app_id: String,
+
+    pub pid: u32,
+
+    pub width: u32,
+
+    pub height: u32,
+
+    pub has_focus: bool,
+
+    /// Minimized, or otherwise not shown
+    pub is_hidden: bool,
+}
+
+impl ShellWindow {
+    fn from_properties(id: u64, properties: &VariantDict) -> Self {
+        let string = |key: &str| {
+            properties
+                .lookup::<String>(key)
+                .ok()
+                .flatten()
+                .unwrap_or_default()
+        };
+        let number = |key: &str| properties.lookup::<u32>(key).ok().flatten().unwrap_or(0);
+        let flag = |key: &str| {
+            properties
+                .lookup::<bool>(key)
+                .ok()
+                .flatten()
+                .unwrap_or(false)
+        };
+
+        Self {
+            id,
+            title: string("title"),
+            wm_class: string("wm-class"),
+            app_id: string("app-id"),
+            pid: number("pid"),
+            width: number("width"),
+            height: number("height"),
+            has_focus: flag("has-focus"),
+            is_hidden: flag("is-hidden"),
+        }
+    }
+}
+
+/// GNOME Shell on the session bus
+pub struct GnomeShell {
+    connection: gio::DBusConnection,
+}
+
+impl GnomeShell {
+    pub fn connect() -> Result<Self, String> {
+        let connection = gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+            .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+        Ok(Self { connection })
+    }
+
+    /// Whether the Shell's introspection API is on the bus at all
+    pub fn introspect_available(&self) -> bool {
+        self.connection
+            .call_sync(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "NameHasOwner",
+                Some(&(INTROSPECT_DEST,).to_variant()),
+                Some(VariantTy::new("(b)").unwrap()),
+                gio::DBusCallFlags::NONE,
+                CALL_TIMEOUT_MS,
+                gio::Cancellable::NONE,
+            )
+            .ok()
+            .and_then(|reply| reply.get::<(bool,)>())
+            .map(|(owned,)| owned)
+            .unwrap_or(false)
+    }
+
+    /// The open windows, in the order of their ids
+    pub fn windows(&self) -> Result<Vec<ShellWindow>, String> {
+        let reply = self
+            .connection
+            .call_sync(
+                Some(INTROSPECT_DEST),
+                INTROSPECT_PATH,
+                INTROSPECT_INTERFACE,
+                "GetWindows",
+                None,
+                Some(VariantTy::new("(a{ta{sv}})").unwrap()),
+                gio::DBusCallFlags::NONE,
+                CALL_TIMEOUT_MS,
+                gio::Cancellable::NONE,
+            )
+            .map_err(|e| format!("GNOME Shell did not list its windows: {}", e))?;
+        let windows = parse_windows(&reply)?;
+        debug!("GNOME Shell listed {} windows", windows.len());
+        Ok(windows)
+    }
+
+    /// Call `callback` whenever windows open, close or change, for as long as
+    /// the subscription is kept. It runs in the thread-default main context
+    /// this is called from.
+    pub fn subscribe_windows_changed(
+        &self,
+        callback: impl Fn() + 'static,
+    ) -> gio::SignalSubscription {
+        self.connection.subscribe_to_signal(
+            Some(INTROSPECT_DEST),
+            Some(INTROSPECT_INTERFACE),
+            Some("WindowsChanged"),
+            Some(INTROSPECT_PATH),
+            None,
+            gio::DBusSignalFlags::NONE,
+            move |_| callback(),
+        )
+    }
+
+    /// Save the focused window to `path` as a PNG, returning where the Shell
+    /// actually wrote it
+    pub fn screenshot_window(
+        &self,
+        path: &Path,
+        include_frame: bool,
+        include_cursor: bool,
+    ) -> Result<PathBuf, String> {
+        let flash = false;
+        let reply = self
+            .connection
+            .call_sync(
+                Some(SCREENSHOT_DEST),
+                SCREENSHOT_PATH,
+                SCREENSHOT_INTERFACE,
+                "ScreenshotWindow",
+                Some(
+                    &(
+                        include_frame,
+                        include_cursor,
+                        flash,
+                        path.to_string_lossy().as_ref(),
+                    )
+                        .to_variant(),
+                ),
+                Some(VariantTy::new("(bs)").unwrap()),
+                gio::DBusCallFlags::NONE,
+                CALL_TIMEOUT_MS,
+                gio::Cancellable::NONE,
+            )
+            .map_err(|e| format!("GNOME Shell did not take the screenshot: {}", e))?;
+
+        match reply.get::<(bool, String)>() {
+            Some((true, written)) => Ok(PathBuf::from(written)),
+            Some((false, _)) => Err("GNOME Shell could not take the screenshot".to_string()),
+            None => Err("Unexpected reply from GNOME Shell".to_string()),
+        }
+    }
+}
+
+/// The windows in a `GetWindows` reply
+fn parse_windows(reply: &glib::Variant) -> Result<Vec<ShellWindow>, String> {
+    let (windows,) = reply
+        .get::<(BTreeMap<u64, VariantDict>,)>()
+        .ok_or("Unexpected window list from GNOME Shell")?;
+    Ok(windows
+        .iter()
+        .map(|(id, properties)| ShellWindow::from_properties(*id, properties))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_windows() {
+        let terminal = VariantDict::new(None);
+        terminal.insert("title", "~/src");
+        terminal.insert("wm-class", "org.gnome.Console");
+        terminal.insert("app-id", "org.gnome.Console.desktop");
+        terminal.insert("pid", 4242u32);
+        terminal.insert("width", 960u32);
+        terminal.insert("height", 600u32);
+        terminal.insert("has-focus", true);
+        // Properties missing from older Shells fall back to defaults
+        let browser = VariantDict::new(None);
+        browser.insert("title", "News");
+
+        let mut windows = BTreeMap::new();
+        windows.insert(7u64, terminal);
+        windows.insert(3u64, browser);
+        let reply = (windows,).to_variant();
+
+        let parsed = parse_windows(&reply).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].id, 3);
+        assert_eq!(parsed[0].title, "News");
+        assert_eq!(parsed[0].width, 0);
+        assert_eq!(
+            parsed[1],
+            ShellWindow {
+                id: 7,
+                title: "~/src".to_string(),
+                wm_class: "org.gnome.Console".to_string(),
+                app_id: "org.gnome.Console.desktop".to_string(),
+                pid: 4242,
+                width: 960,
+                height: 600,
+                has_focus: true,
+                is_hidden: false,
+            }
+        );
+        assert!(parse_windows(&("not a window list",).to_variant()).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Client for Hyprland's IPC socket, which `hyprctl` is itself a front end
+//! to, so listing and focusing windows works without it installed.
+//!
+//! Every request is a connection of its own: the command is written, prefixed
+//! with `j/` for a JSON reply, and the reply read until Hyprland closes the
+//! socket.
+
+use log::debug;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Longest wait for Hyprland to answer a request
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Path of the running instance's socket called `name`: `.socket.sock` for
+/// requests or `.socket2.sock` for events. Hyprland keeps them in
+/// `$XDG_RUNTIME_DIR/hypr`, and in `/tmp/hypr` before 0.40.
+pub fn socket_path(name: &str) -> Option<PathBuf> {
+    let signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE").ok()?;
+    let runtime_dir = std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
+
+    [
+        PathBuf::from(runtime_dir).join("hypr"),
+        PathBuf::from("/tmp/hypr"),
+    ]
+    .into_iter()
+    .map(|dir| dir.join(&signature).join(name))
+    .find(|path| path.exists())
+}
+
+/// The running Hyprland instance
+pub struct Hyprland {
+    path: PathBuf,
+}
+
+impl Hyprland {
+    pub fn connect() -> Result<Self, String> {
+        let path = socket_path(".socket.sock").ok_or("Hyprland's IPC socket not found")?;
+        Ok(Self { path })
+    }
+
+    /// The clients, as the JSON array `hyprctl clients -j` prints
+    pub fn clients(&self) -> Result<String, String> {
+        self.request("j/clients")
+    }
+
+    /// The focused client, as a JSON object; `{}` when none is
+    pub fn active_window(&self) -> Result<String, String> {
+        self.request("j/activewindow")
+    }
+
+    /// The monitors and the workspace each shows, as a JSON array
+    pub fn monitors(&self) -> Result<String, String> {
+        self.request("j/monitors")
+    }
+
+    /// Run `dispatcher` with `argument`, as `hyprctl dispatch` does
+    pub fn dispatch(&self, dispatcher: &str, argument: &str) -> Result<(), String> {
+        // The request succeeds even when the dispatcher fails, and the reply
+        // is "ok" only when it didn't
+        let reply = self.request(&format!("dispatch {} {}", dispatcher, argument))?;
+        if reply.trim() == "ok" {
+            Ok(())
+        } else {
+            Err(reply.trim().to_string())
+        }
+    }
+
+    fn request(&self, command: &str) -> Result<String, String> {
+        debug!("Hyprland IPC request: {}", command);
+        let mut stream = UnixStream::connect(&self.path)
+            .map_err(|e| format!("Failed to connect to {}: {}", self.path.display(), e))?;
+        stream
+            .set_read_timeout(Some(REPLY_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        stream
+            .write_all(command.as_bytes())
+            .map_err(|e| format!("Failed to send {:?} to Hyprland: {}", command, e))?;
+
+        let mut reply = String::new();
+        stream
+            .read_to_string(&mut reply)
+            .map_err(|e| format!("Failed to read Hyprland's reply to {:?}: {}", command, e))?;
+        Ok(reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    #[test]
+    fn test_request() {
+        let path = std::env::temp_dir().join(format!("hyprland-test-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        let server = thread::spawn(move || {
+            let mut commands = Vec::new();
+            for reply in ["[]", "Invalid dispatcher"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut command = [0; 64];
+                let read = stream.read(&mut command).unwrap();
+                commands.push(String::from_utf8_lossy(&command[..read]).to_string());
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            commands
+        });
+
+        let hyprland = Hyprland { path: path.clone() };
+        assert_eq!(hyprland.clients().unwrap(), "[]");
+        assert_eq!(
+            hyprland.dispatch("focuswindow", "address:0x1"),
+            Err("Invalid dispatcher".to_string())
+        );
+        assert_eq!(
+            server.join().unwrap(),
+            ["j/clients", "dispatch focuswindow address:0x1"]
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
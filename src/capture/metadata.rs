@@ -0,0 +1,285 @@
+use gtk4::glib;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::window::WindowInfo;
+
+/// Filename template matching the historical `screenshot_<unix time>.png` names
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "screenshot_{timestamp}";
+
+/// Longest a single substituted value may get inside a file name
+const MAX_FIELD_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureSource {
+    Screen,
+
+    Region,
+
+    Window,
+
+    Portal,
+}
+
+impl CaptureSource {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Screen => "Screen",
+            Self::Region => "Region",
+            Self::Window => "Window",
+            Self::Portal => "System Picker",
+        }
+    }
+
+    fn id(&self) -> &'static str {
+        match self {
+            Self::Screen => "screen",
+            Self::Region => "region",
+            Self::Window => "window",
+            Self::Portal => "portal",
+        }
+    }
+}
+
+/// Where and when a capture was taken, shown in the info popover and
+/// available to filename templates and export targets
+#[derive(Debug, Clone)]
+pub struct CaptureMetadata {
+    pub source: CaptureSource,
+
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+
+    pub monitor: Option<String>,
+
+    pub scale_factor: Option<f64>,
+
+    pub dpi: Option<f64>,
+
+    pub window_title: Option<String>,
+
+    pub window_app: Option<String>,
+
+    /// Captured area as `(x, y, width, height)` in screen pixels
+    pub region: Option<(i32, i32, i32, i32)>,
+}
+
+impl CaptureMetadata {
+    pub fn new(source: CaptureSource) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            source,
+            timestamp,
+            monitor: None,
+            scale_factor: None,
+            dpi: None,
+            window_title: None,
+            window_app: None,
+            region: None,
+        }
+    }
+
+    /// Record the window's title and application; the region is set separately
+    /// because window geometry is not always in screen coordinates
+    pub fn with_window(mut self, window: &WindowInfo) -> Self {
+        self.window_title = Some(window.title.clone()).filter(|t| !t.is_empty());
+        self.window_app = Some(window.app_name.clone()).filter(|a| !a.is_empty());
+        self
+    }
+
+    pub fn with_region(mut self, x: i32, y: i32, width: i32, height: i32) -> Self {
+        self.region = Some((x, y, width, height));
+        self
+    }
+
+    pub fn with_monitor(
+        mut self,
+        name: Option<String>,
+        scale_factor: Option<f64>,
+        dpi: Option<f64>,
+    ) -> Self {
+        self.monitor = name;
+        self.scale_factor = scale_factor;
+        self.dpi = dpi;
+        self
+    }
+
+    /// Format the capture time in local time with a `strftime`-style format
+    fn format_local_time(&self, format: &str) -> String {
+        glib::DateTime::from_unix_local(self.timestamp as i64)
+            .ok()
+            .and_then(|dt| dt.format(format).ok())
+            .map(|s| s.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Environment variables describing the capture, for export scripts
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("SCREENSHOT_TIMESTAMP", self.timestamp.to_string()),
+            ("SCREENSHOT_MODE", self.source.id().to_string()),
+        ];
+        if let Some(ref app) = self.window_app {
+            vars.push(("SCREENSHOT_APP", app.clone()));
+        }
+        if let Some(ref title) = self.window_title {
+            vars.push(("SCREENSHOT_WINDOW_TITLE", title.clone()));
+        }
+        if let Some(ref monitor) = self.monitor {
+            vars.push(("SCREENSHOT_MONITOR", monitor.clone()));
+        }
+        if let Some((x, y, w, h)) = self.region {
+            vars.push(("SCREENSHOT_REGION", format!("{},{} {}x{}", x, y, w, h)));
+        }
+        vars
+    }
+
+    /// Label/value pairs for display, skipping anything unknown
+    pub fn rows(&self) -> Vec<(&'static str, String)> {
+        let mut rows = vec![
+            ("Captured", self.format_local_time("%Y-%m-%d %H:%M:%S")),
+            ("Mode", self.source.label().to_string()),
+        ];
+
+        if let Some(ref monitor) = self.monitor {
+            rows.push(("Monitor", monitor.clone()));
+        }
+        if let Some(ref app) = self.window_app {
+            rows.push(("Application", app.clone()));
+        }
+        if let Some(ref title) = self.window_title {
+            rows.push(("Window", title.clone()));
+        }
+        if let Some((x, y, w, h)) = self.region {
+            rows.push(("Region", format!("{}×{} at {},{}", w, h, x, y)));
+        }
+        if let Some(scale) = self.scale_factor {
+            rows.push(("Scale", format!("{}×", scale)));
+        }
+        if let Some(dpi) = self.dpi {
+            rows.push(("DPI", format!("{:.0}", dpi)));
+        }
+
+        rows
+    }
+
+    /// Expand a filename template (without extension) into a relative path.
+    ///
+    /// Supported placeholders: `{timestamp}`, `{date}`, `{time}`, `{year}`,
+    /// `{month}`, `{day}`, `{mode}`, `{app}`, `{title}`, `{monitor}`, `{width}`
+    /// and `{height}`. Unknown ones are kept literally. A `/` in the template
+    /// starts a subfolder, e.g. `{year}/{month}/{time}`; values themselves can
+    /// never add folders or climb out of the target folder.
+    pub fn expand_filename_template(&self, template: &str, width: i32, height: i32) -> String {
+        let fields = [
+            ("timestamp", self.timestamp.to_string()),
+            ("date", self.format_local_time("%Y-%m-%d")),
+            ("time", self.format_local_time("%H-%M-%S")),
+            ("year", self.format_local_time("%Y")),
+            ("month", self.format_local_time("%m")),
+            ("day", self.format_local_time("%d")),
+            ("mode", self.source.id().to_string()),
+            ("app", self.window_app.clone().unwrap_or_default()),
+            ("title", self.window_title.clone().unwrap_or_default()),
+            ("monitor", self.monitor.clone().unwrap_or_default()),
+            ("width", width.to_string()),
+            ("height", height.to_string()),
+        ];
+
+        let mut name = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            name.push_str(&rest[..start]);
+            let after = &rest[start + 1..];
+            let value = after.find('}').and_then(|end| {
+                let key = &after[..end];
+                fields
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| (sanitize_value(v), end))
+            });
+
+            match value {
+                Some((value, end)) => {
+                    name.push_str(&value);
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    name.push('{');
+                    rest = after;
+                }
+            }
+        }
+        name.push_str(rest);
+
+        let name = name
+            .split('/')
+            .map(sanitize_component)
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>()
+            .join("/");
+        if name.is_empty() {
+            format!("screenshot_{}", self.timestamp)
+        } else {
+            name
+        }
+    }
+}
+
+/// Replace path separators and control characters in a substituted value
+fn sanitize_value(value: &str) -> String {
+    value
+        .chars()
+        .take(MAX_FIELD_LEN)
+        .map(|c| if c == '/' || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+/// Clean one path segment, so it can never climb out of the target folder or
+/// produce a hidden file
+fn sanitize_component(segment: &str) -> String {
+    segment
+        .chars()
+        .map(|c| if c.is_control() { '_' } else { c })
+        .collect::<String>()
+        .trim()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> CaptureMetadata {
+        let mut meta = CaptureMetadata::new(CaptureSource::Window);
+        meta.timestamp = 1_700_000_000;
+        meta.window_app = Some("firefox".to_string());
+        meta.window_title = Some("Docs / Drafts".to_string());
+        meta
+    }
+
+    #[test]
+    fn test_default_template_matches_old_names() {
+        assert_eq!(
+            metadata().expand_filename_template(DEFAULT_FILENAME_TEMPLATE, 10, 10),
+            "screenshot_1700000000"
+        );
+    }
+
+    #[test]
+    fn test_template_fields_are_sanitized() {
+        let name =
+            metadata().expand_filename_template("{app}-{title}-{width}x{height}-{nope}", 800, 600);
+        assert_eq!(name, "firefox-Docs _ Drafts-800x600-{nope}");
+    }
+
+    #[test]
+    fn test_template_subfolders_cannot_escape() {
+        let name = metadata().expand_filename_template("../{app}//./.{title}", 1, 1);
+        assert_eq!(name, "firefox/Docs _ Drafts");
+    }
+}
@@ -0,0 +1,182 @@
+//! Synthetic capture backend for running without a compositor.
+//!
+//! With `SCREENSHOT_GNOME_BACKEND=mock` every capture returns the same
+//! generated screen and window list, so the UI flows and the `--pipe` CLI can
+//! be exercised in CI or a container.
+
+use gtk4::gdk_pixbuf::Pixbuf;
+use log::debug;
+
+use super::screen::{image_to_pixbuf, CaptureResult, MonitorInfo, MonitorTarget};
+use super::window::{WindowCaptureError, WindowCaptureResult, WindowInfo};
+use super::window_backends::{WindowCaptureBackendResult, WindowListResult};
+
+/// Environment variable selecting the capture backend
+pub const BACKEND_ENV: &str = "SCREENSHOT_GNOME_BACKEND";
+
+pub const MOCK_SCREEN_WIDTH: u32 = 1280;
+pub const MOCK_SCREEN_HEIGHT: u32 = 800;
+
+/// Spacing of the grid drawn over the synthetic screen
+const GRID_SPACING: u32 = 64;
+
+/// Whether `SCREENSHOT_GNOME_BACKEND=mock` is set
+pub fn mock_backend_enabled() -> bool {
+    std::env::var(BACKEND_ENV)
+        .map(|backend| backend.eq_ignore_ascii_case("mock"))
+        .unwrap_or(false)
+}
+
+/// Returns a fixed monitor, screen and set of windows instead of asking the
+/// compositor
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MockBackend;
+
+impl MockBackend {
+    pub fn monitors(&self) -> Vec<MonitorInfo> {
+        vec![MonitorInfo { x: 0, y: 0 }]
+    }
+
+    /// The synthetic screen as the one monitor of the desktop
+    pub fn monitor_targets(&self) -> Vec<MonitorTarget> {
+        vec![MonitorTarget {
+            connector: Some("MOCK-1".to_string()),
+            x: 0,
+            y: 0,
+            width: MOCK_SCREEN_WIDTH as i32,
+            height: MOCK_SCREEN_HEIGHT as i32,
+        }]
+    }
+
+    pub fn capture_screen(&self) -> Result<CaptureResult, String> {
+        debug!("Capturing the synthetic screen");
+        Ok(CaptureResult {
+            pixbuf: image_to_pixbuf(synthetic_screen())?,
+            monitor_info: self.monitors().remove(0),
+        })
+    }
+
+    pub fn list_windows(&self) -> WindowListResult {
+        Ok(synthetic_windows())
+    }
+
+    pub fn capture_window(&self, window_info: &WindowInfo) -> WindowCaptureBackendResult {
+        let window = synthetic_windows()
+            .into_iter()
+            .find(|w| w.id == window_info.id)
+            .ok_or(WindowCaptureError::WindowNotFound)?;
+        if window.is_minimized {
+            return Err(WindowCaptureError::WindowMinimized);
+        }
+
+        let screen =
+            image_to_pixbuf(synthetic_screen()).map_err(WindowCaptureError::ConversionFailed)?;
+        let pixbuf: Pixbuf = screen
+            .new_subpixbuf(
+                window.x,
+                window.y,
+                window.width as i32,
+                window.height as i32,
+            )
+            .copy()
+            .ok_or_else(|| WindowCaptureError::ConversionFailed("copy failed".to_string()))?;
+
+        Ok(WindowCaptureResult {
+            pixbuf,
+            window_info: window,
+        })
+    }
+}
+
+fn synthetic_window(
+    id: u32,
+    app_name: &str,
+    title: &str,
+    (x, y, width, height): (i32, i32, u32, u32),
+) -> WindowInfo {
+    WindowInfo {
+        id,
+        pid: 1000 + id,
+        app_name: app_name.to_string(),
+        title: title.to_string(),
+        x,
+        y,
+        z: id as i32,
+        width,
+        height,
+        is_minimized: false,
+        is_maximized: false,
+        is_focused: false,
+        address: None,
+    }
+}
+
+/// Overlapping windows, the last one focused and on top
+fn synthetic_windows() -> Vec<WindowInfo> {
+    let mut windows = vec![
+        synthetic_window(1, "org.gnome.Nautilus", "Files", (40, 40, 640, 480)),
+        synthetic_window(2, "org.gnome.TextEditor", "notes.txt", (360, 200, 720, 520)),
+        synthetic_window(3, "org.gnome.Console", "Terminal", (800, 80, 420, 300)),
+    ];
+    if let Some(top) = windows.last_mut() {
+        top.is_focused = true;
+    }
+    windows
+}
+
+/// Gradient with a grid, and each window as a flat block of its own color,
+/// so crops and window captures are easy to tell apart
+fn synthetic_screen() -> image::RgbaImage {
+    let mut screen = image::RgbaImage::from_fn(MOCK_SCREEN_WIDTH, MOCK_SCREEN_HEIGHT, |x, y| {
+        if x % GRID_SPACING == 0 || y % GRID_SPACING == 0 {
+            image::Rgba([255, 255, 255, 255])
+        } else {
+            image::Rgba([
+                (x * 255 / MOCK_SCREEN_WIDTH) as u8,
+                (y * 255 / MOCK_SCREEN_HEIGHT) as u8,
+                160,
+                255,
+            ])
+        }
+    });
+
+    let colors = [[224, 27, 36], [46, 194, 126], [53, 132, 228]];
+    for (window, color) in synthetic_windows().iter().zip(colors) {
+        for y in window.y..window.y + window.height as i32 {
+            for x in window.x..window.x + window.width as i32 {
+                if let Some(pixel) = screen.get_pixel_mut_checked(x as u32, y as u32) {
+                    *pixel = image::Rgba([color[0], color[1], color[2], 255]);
+                }
+            }
+        }
+    }
+    screen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_backend() {
+        let backend = MockBackend;
+
+        let screen = backend.capture_screen().unwrap();
+        assert_eq!(screen.pixbuf.width(), MOCK_SCREEN_WIDTH as i32);
+        assert_eq!(screen.pixbuf.height(), MOCK_SCREEN_HEIGHT as i32);
+
+        let windows = backend.list_windows().unwrap();
+        assert_eq!(windows.iter().filter(|w| w.is_focused).count(), 1);
+
+        let result = backend.capture_window(&windows[1]).unwrap();
+        assert_eq!(result.pixbuf.width(), windows[1].width as i32);
+        assert_eq!(result.window_info.title, "notes.txt");
+
+        let mut gone = windows[0].clone();
+        gone.id = 99;
+        assert!(matches!(
+            backend.capture_window(&gone),
+            Err(WindowCaptureError::WindowNotFound)
+        ));
+    }
+}
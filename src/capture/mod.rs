@@ -1,6 +1,23 @@
 pub mod desktop;
+pub mod file_metadata;
+pub mod foreign_toplevel;
+pub mod gnome_shell;
+pub mod hyprland;
+pub mod metadata;
+pub mod mock;
+pub mod portal;
+pub mod recording;
 pub mod screen;
+pub mod sway;
+pub mod tonemap;
+pub mod tools;
 pub mod window;
 pub mod window_backends;
+pub mod window_events;
+pub mod x11;
 
-pub use screen::capture_primary_monitor;
+pub use screen::{
+    capture_all_monitors, capture_area, capture_monitor, capture_primary_monitor,
+    desktop_monitors, MonitorTarget,
+};
+pub use tonemap::{set_tonemap_operator, tonemap_operator, TonemapOperator};
@@ -0,0 +1,218 @@
+use gtk4 as gtk;
+use log::{debug, info};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::screen::{load_pixbuf_from_file, CaptureResult, MonitorInfo};
+use super::window::{WindowCaptureError, WindowCaptureResult, WindowInfo};
+use super::window_backends::WindowCaptureBackendResult;
+
+const PORTAL_DEST: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const RESPONSE_SIGNAL: &str = "org.freedesktop.portal.Request.Response (uint32 ";
+
+static REQUEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+/// Outcome of an `org.freedesktop.portal.Request.Response` signal
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PortalResponse {
+    Success(String),
+    Cancelled,
+    Failed(u32),
+}
+
+/// Ask the screenshot portal for an interactive capture.
+///
+/// The compositor shows its own picker (GNOME's region/window/screen UI), and
+/// this blocks until the user finishes or cancels it, so call it off the main
+/// thread. Returns the path of the image the portal wrote.
+pub fn request_interactive_screenshot() -> Result<PathBuf, String> {
+    request_screenshot(true)
+}
+
+/// Capture the whole desktop through the portal, without the picker. The
+/// portal may still ask once whether the app is allowed to take screenshots.
+pub fn capture_screen() -> Result<CaptureResult, String> {
+    let path = request_screenshot(false)?;
+    Ok(CaptureResult {
+        pixbuf: load_portal_screenshot(&path)?,
+        monitor_info: MonitorInfo::default_wayland(),
+    })
+}
+
+/// Capture `window_info`'s area of a portal screenshot, including anything
+/// stacked over the window
+pub fn capture_window_area(window_info: &WindowInfo) -> WindowCaptureBackendResult {
+    let screen = capture_screen().map_err(WindowCaptureError::CaptureFailed)?;
+    let pixbuf = &screen.pixbuf;
+
+    let x = window_info.x.max(0);
+    let y = window_info.y.max(0);
+    let width = (window_info.x + window_info.width as i32).min(pixbuf.width()) - x;
+    let height = (window_info.y + window_info.height as i32).min(pixbuf.height()) - y;
+    if width <= 0 || height <= 0 {
+        return Err(WindowCaptureError::CaptureFailed(
+            "Window is outside the captured screen".to_string(),
+        ));
+    }
+
+    Ok(WindowCaptureResult {
+        pixbuf: pixbuf.new_subpixbuf(x, y, width, height),
+        window_info: window_info.clone(),
+    })
+}
+
+fn request_screenshot(interactive: bool) -> Result<PathBuf, String> {
+    let token = format!(
+        "screenshot_gnome_{}_{}",
+        std::process::id(),
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    );
+
+    // Subscribe before calling, otherwise a fast response could be missed
+    let mut monitor = Command::new("gdbus")
+        .args([
+            "monitor",
+            "--session",
+            "--dest",
+            PORTAL_DEST,
+            "--object-path",
+            PORTAL_PATH,
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Failed to run gdbus monitor: {}", e))?;
+
+    let result = wait_for_response(&mut monitor, &token, interactive);
+    let _ = monitor.kill();
+    let _ = monitor.wait();
+
+    match result? {
+        PortalResponse::Success(uri) => {
+            let (path, _) = gtk::glib::filename_from_uri(&uri)
+                .map_err(|e| format!("Portal returned an invalid URI {}: {}", uri, e))?;
+            info!("Portal screenshot saved to {:?}", path);
+            Ok(path)
+        }
+        PortalResponse::Cancelled => Err("Screenshot was cancelled".to_string()),
+        PortalResponse::Failed(code) => Err(format!("Screenshot portal failed (code {})", code)),
+    }
+}
+
+/// Load the image returned by [`request_interactive_screenshot`]
+pub fn load_portal_screenshot(path: &std::path::Path) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
+    load_pixbuf_from_file(&path.to_string_lossy())
+}
+
+fn wait_for_response(
+    monitor: &mut std::process::Child,
+    token: &str,
+    interactive: bool,
+) -> Result<PortalResponse, String> {
+    let stdout = monitor.stdout.take().ok_or("gdbus monitor has no stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+
+    // The first line confirms the match rule is installed
+    lines
+        .next()
+        .ok_or("gdbus monitor exited")?
+        .map_err(|e| e.to_string())?;
+
+    let options = format!(
+        "{{'interactive': <{}>, 'modal': <true>, 'handle_token': <'{}'>}}",
+        interactive, token
+    );
+    debug!(
+        "Requesting {} screenshot from portal ({})",
+        if interactive { "interactive" } else { "full" },
+        token
+    );
+
+    let output = Command::new("gdbus")
+        .args([
+            "call",
+            "--session",
+            "--dest",
+            PORTAL_DEST,
+            "--object-path",
+            PORTAL_PATH,
+            "--method",
+            "org.freedesktop.portal.Screenshot.Screenshot",
+            "",
+            &options,
+        ])
+        .output()
+        .map_err(|e| format!("Failed to run gdbus: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Screenshot portal call failed: {}", stderr.trim()));
+    }
+
+    for line in lines {
+        let line = line.map_err(|e| e.to_string())?;
+        if let Some(response) = parse_response(&line, token) {
+            return Ok(response);
+        }
+    }
+
+    Err("gdbus monitor exited before the portal responded".to_string())
+}
+
+/// Parse a `gdbus monitor` line for the response to our request
+fn parse_response(line: &str, token: &str) -> Option<PortalResponse> {
+    let path = line.split(':').next()?;
+    if !path.ends_with(&format!("/{}", token)) {
+        return None;
+    }
+
+    let rest = &line[line.find(RESPONSE_SIGNAL)? + RESPONSE_SIGNAL.len()..];
+    let code: u32 = rest.split(',').next()?.trim().parse().ok()?;
+
+    Some(match code {
+        0 => {
+            let start = rest.find("'uri': <'")? + "'uri': <'".len();
+            let end = rest[start..].find("'>")?;
+            PortalResponse::Success(rest[start..start + end].to_string())
+        }
+        1 => PortalResponse::Cancelled,
+        code => PortalResponse::Failed(code),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "screenshot_gnome_42_0";
+
+    #[test]
+    fn test_parse_success_response() {
+        let line = "/org/freedesktop/portal/desktop/request/1_87/screenshot_gnome_42_0: \
+            org.freedesktop.portal.Request.Response (uint32 0, \
+            {'uri': <'file:///home/me/Pictures/Screenshot.png'>})";
+        assert_eq!(
+            parse_response(line, TOKEN),
+            Some(PortalResponse::Success(
+                "file:///home/me/Pictures/Screenshot.png".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_cancel_and_other_requests() {
+        let cancelled = "/org/freedesktop/portal/desktop/request/1_87/screenshot_gnome_42_0: \
+            org.freedesktop.portal.Request.Response (uint32 1, @a{sv} {})";
+        assert_eq!(
+            parse_response(cancelled, TOKEN),
+            Some(PortalResponse::Cancelled)
+        );
+
+        let other = "/org/freedesktop/portal/desktop/request/1_87/other_app: \
+            org.freedesktop.portal.Request.Response (uint32 0, {'uri': <'file:///x.png'>})";
+        assert_eq!(parse_response(other, TOKEN), None);
+    }
+}
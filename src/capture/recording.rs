@@ -0,0 +1,1594 @@
+//! Recording an area of the screen to MP4, WebM or an animated GIF, PNG or
+//! WebP.
+//!
+//! Frames come from `ximagesrc` on X11 and from the ScreenCast portal's
+//! PipeWire stream elsewhere; a `gst-launch-1.0` process encodes them. Pausing
+//! ends the current segment file and resuming starts the next, so stopping
+//! joins the segments into the output. Animations are recorded as MP4 and
+//! converted by `export::animation` at the end.
+//!
+//! Videos can have sound: what the computer plays and a microphone, both
+//! taken from PipeWire and mixed into one track. Muting a track mid-recording
+//! starts a new segment, as the recorder's pipeline can't change once running.
+//!
+//! Annotations drawn during a recording are rendered by the editor's own
+//! drawing code into an image the recorder reads again for every frame and
+//! lays over it, so each change shows up in the video as it is made.
+//!
+//! Everything here runs on the calling thread's main context: the portal
+//! session belongs to the D-Bus connection that opened it, and the recording
+//! processes are waited on asynchronously.
+
+use gtk4::gio;
+use gtk4::gio::prelude::*;
+use gtk4::glib;
+use gtk4::glib::{ToVariant, Variant, VariantDict};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::os::fd::{BorrowedFd, OwnedFd};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::desktop::{DesktopSession, RecordingBackend};
+use crate::editor::golden::rasterize;
+use crate::editor::AnnotationList;
+use crate::export::animation::{encode_animation, AnimationFormat, AnimationSettings};
+
+pub const FRAME_RATE: u32 = 30;
+
+const GST_LAUNCH: &str = "gst-launch-1.0";
+
+/// GStreamer Editing Services' command line, which trims recordings
+const GES_LAUNCH: &str = "ges-launch-1.0";
+
+const PORTAL_DEST: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SCREENCAST_INTERFACE: &str = "org.freedesktop.portal.ScreenCast";
+
+/// File descriptor the PipeWire remote is handed to the recorder as
+const PIPEWIRE_FD: i32 = 3;
+
+/// `SIGINT`, which makes `gst-launch-1.0 -e` finish the file and exit
+const SIGINT: i32 = 2;
+
+/// Format every audio track is converted to before mixing
+const AUDIO_CAPS: &str = "audio/x-raw,rate=48000,channels=2";
+
+static REQUEST_COUNTER: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    #[default]
+    Mp4,
+
+    WebM,
+
+    /// Animated GIF, limited in length by [`AnimationSettings`]
+    Gif,
+
+    /// Animated PNG, in full colour unlike GIFs
+    Apng,
+
+    /// Animated lossless WebP, in full colour and usually the smallest
+    WebP,
+}
+
+impl RecordingFormat {
+    pub const ALL: [RecordingFormat; 5] =
+        [Self::Mp4, Self::WebM, Self::Gif, Self::Apng, Self::WebP];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Mp4 => "mp4",
+            Self::WebM => "webm",
+            Self::Gif => "gif",
+            Self::Apng => "apng",
+            Self::WebP => "webp",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Mp4 => "MP4 (H.264)",
+            Self::WebM => "WebM (VP8)",
+            Self::Gif => "Animated GIF",
+            Self::Apng => "Animated PNG",
+            Self::WebP => "Animated WebP",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.id() == id)
+    }
+
+    /// APNGs are PNG files, which anything showing PNGs can open
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Apng => "png",
+            format => format.id(),
+        }
+    }
+
+    pub fn is_animation(&self) -> bool {
+        self.animation_format().is_some()
+    }
+
+    /// What a recording in this format is converted to at the end, if it is
+    /// an animation
+    fn animation_format(&self) -> Option<AnimationFormat> {
+        match self {
+            Self::Mp4 | Self::WebM => None,
+            Self::Gif => Some(AnimationFormat::Gif),
+            Self::Apng => Some(AnimationFormat::Apng),
+            Self::WebP => Some(AnimationFormat::WebP),
+        }
+    }
+
+    /// The format frames are recorded in before any conversion
+    fn video_format(&self) -> RecordingFormat {
+        match self {
+            Self::Mp4 | Self::WebM => *self,
+            _ => Self::Mp4,
+        }
+    }
+
+    /// Elements from raw frames to encoded ones, ready for the container
+    fn encoder_elements(&self) -> &'static [&'static str] {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => &[
+                "video/x-raw,format=I420",
+                "!",
+                "x264enc",
+                "tune=zerolatency",
+                "speed-preset=veryfast",
+                "!",
+                "h264parse",
+            ],
+            Self::WebM => &["vp8enc", "deadline=1"],
+        }
+    }
+
+    /// Elements from mixed sound to encoded audio, ready for the container
+    fn audio_encoder_elements(&self) -> &'static [&'static str] {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => &["avenc_aac", "!", "aacparse"],
+            Self::WebM => &["opusenc"],
+        }
+    }
+
+    fn demuxer(&self) -> &'static str {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => "qtdemux",
+            Self::WebM => "matroskademux",
+        }
+    }
+
+    /// Parser encoded frames go through after the demuxer, if any
+    fn video_parser(&self) -> Option<&'static str> {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => Some("h264parse"),
+            Self::WebM => None,
+        }
+    }
+
+    fn audio_parser(&self) -> &'static str {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => "aacparse",
+            Self::WebM => "opusparse",
+        }
+    }
+
+    fn muxer(&self) -> &'static str {
+        match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => "mp4mux",
+            Self::WebM => "webmmux",
+        }
+    }
+
+    /// Container, video and audio caps as `ges-launch-1.0 --format` takes
+    /// them, to encode into this format again
+    fn encoding_profile(&self, has_audio: bool) -> String {
+        let (container, video, audio) = match self.video_format() {
+            Self::Mp4 | Self::Gif | Self::Apng | Self::WebP => (
+                "video/quicktime,variant=iso",
+                "video/x-h264",
+                "audio/mpeg,mpegversion=4",
+            ),
+            Self::WebM => ("video/webm", "video/x-vp8", "audio/x-opus"),
+        };
+        if has_audio {
+            format!("{}:{}:{}", container, video, audio)
+        } else {
+            format!("{}:{}", container, video)
+        }
+    }
+}
+
+/// A source of sound a recording can take in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioTrack {
+    /// What the computer plays, from the default output
+    System,
+
+    Microphone,
+}
+
+impl AudioTrack {
+    pub const ALL: [AudioTrack; 2] = [Self::System, Self::Microphone];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::System => "System Audio",
+            Self::Microphone => "Microphone",
+        }
+    }
+}
+
+/// Sound recorded along with the screen. GIFs have none.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingAudio {
+    pub system: bool,
+
+    pub microphone: bool,
+
+    /// PipeWire node name of the microphone; `None` for the default input
+    pub microphone_device: Option<String>,
+}
+
+impl RecordingAudio {
+    pub fn tracks(&self) -> Vec<AudioTrack> {
+        AudioTrack::ALL
+            .into_iter()
+            .filter(|track| match track {
+                AudioTrack::System => self.system,
+                AudioTrack::Microphone => self.microphone,
+            })
+            .collect()
+    }
+}
+
+/// How long and how big recordings may get before they are stopped, 0 for
+/// no limit. GIFs also stop at their own maximum length.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecordingLimits {
+    pub max_duration_mins: u32,
+
+    pub max_size_mib: u32,
+}
+
+impl RecordingLimits {
+    /// Longest limit offered, a day
+    pub const MAX_DURATION_MINS: u32 = 24 * 60;
+
+    /// Largest limit offered, 64 GiB
+    pub const MAX_SIZE_MIB: u32 = 64 * 1024;
+}
+
+/// The limit a recording ran into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingLimit {
+    Duration,
+    Size,
+}
+
+/// A microphone or other sound input
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AudioDevice {
+    /// PipeWire node name
+    pub name: String,
+
+    pub description: String,
+}
+
+/// The sound inputs PipeWire has, as `pw-dump` lists them. Empty when it
+/// can't be asked.
+pub fn list_microphones() -> Vec<AudioDevice> {
+    match Command::new("pw-dump").output() {
+        Ok(output) if output.status.success() => {
+            parse_pw_dump(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            warn!("pw-dump exited with {}", output.status);
+            Vec::new()
+        }
+        Err(e) => {
+            debug!("Failed to run pw-dump: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn parse_pw_dump(json: &str) -> Vec<AudioDevice> {
+    let objects: Vec<serde_json::Value> = serde_json::from_str(json).unwrap_or_default();
+    objects
+        .iter()
+        .filter(|object| object["type"] == "PipeWire:Interface:Node")
+        .map(|object| &object["info"]["props"])
+        .filter(|props| props["media.class"] == "Audio/Source")
+        .filter_map(|props| {
+            let name = props["node.name"].as_str()?;
+            let description = props["node.description"]
+                .as_str()
+                .or_else(|| props["node.nick"].as_str())
+                .unwrap_or(name);
+            Some(AudioDevice {
+                name: name.to_string(),
+                description: description.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Recorded rectangle in desktop coordinates, in logical pixels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecordingArea {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl RecordingArea {
+    /// The area shrunk to an even size, which the encoders need
+    pub fn even(&self) -> Self {
+        Self {
+            width: self.width & !1,
+            height: self.height & !1,
+            ..*self
+        }
+    }
+}
+
+/// Whether recordings can be made, which needs GStreamer's `gst-launch-1.0`
+pub fn recording_available() -> bool {
+    glib::find_program_in_path(GST_LAUNCH).is_some()
+}
+
+/// A new file in the Videos folder for a recording started now
+pub fn default_output_path(format: RecordingFormat) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    glib::user_special_dir(glib::UserDirectory::Videos)
+        .unwrap_or_else(glib::home_dir)
+        .join(format!("recording_{}.{}", timestamp, format.extension()))
+}
+
+/// `elapsed` as `m:ss`, or `h:mm:ss` from an hour on
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let seconds = elapsed.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+/// The PipeWire stream of a monitor shared through the ScreenCast portal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PortalStream {
+    node: u32,
+
+    /// Where the monitor is on the desktop, in logical pixels
+    position: (i32, i32),
+    size: (i32, i32),
+}
+
+enum FrameSource {
+    X11,
+    Mock,
+    Portal {
+        connection: gio::DBusConnection,
+        session: String,
+        remote: OwnedFd,
+        stream: PortalStream,
+    },
+}
+
+impl FrameSource {
+    /// Elements producing frames of exactly `area`
+    fn elements(&self, area: RecordingArea) -> Vec<String> {
+        match self {
+            FrameSource::X11 => vec![
+                "ximagesrc".to_string(),
+                format!("startx={}", area.x),
+                format!("starty={}", area.y),
+                // The end coordinates are inclusive
+                format!("endx={}", area.x + area.width - 1),
+                format!("endy={}", area.y + area.height - 1),
+                "use-damage=false".to_string(),
+            ],
+            FrameSource::Mock => vec![
+                "videotestsrc".to_string(),
+                "is-live=true".to_string(),
+                "pattern=ball".to_string(),
+                "!".to_string(),
+                format!("video/x-raw,width={},height={}", area.width, area.height),
+            ],
+            FrameSource::Portal { stream, .. } => {
+                let (left, top, right, bottom) = crop_margins(area, stream);
+                vec![
+                    "pipewiresrc".to_string(),
+                    format!("fd={}", PIPEWIRE_FD),
+                    format!("path={}", stream.node),
+                    "do-timestamp=true".to_string(),
+                    "!".to_string(),
+                    "videoconvert".to_string(),
+                    "!".to_string(),
+                    "videoscale".to_string(),
+                    "!".to_string(),
+                    // Scaled to logical pixels, the units the area is in
+                    format!(
+                        "video/x-raw,width={},height={}",
+                        stream.size.0, stream.size.1
+                    ),
+                    "!".to_string(),
+                    "videocrop".to_string(),
+                    format!("left={}", left),
+                    format!("top={}", top),
+                    format!("right={}", right),
+                    format!("bottom={}", bottom),
+                ]
+            }
+        }
+    }
+}
+
+/// Draw `annotations` on a transparent image of `size` and save it
+/// to `path` as a PNG. It is written beside `path` and renamed into place, so
+/// the recorder never reads half of it.
+fn write_overlay(
+    annotations: &AnnotationList,
+    size: (i32, i32),
+    path: &Path,
+) -> Result<(), String> {
+    // Selection outlines are left out, as they aren't part of the markup
+    let image = rasterize(size.0, size.1, |cr| {
+        for annotation in annotations.iter() {
+            annotation.draw(cr, 1.0, 0.0, 0.0);
+        }
+        if let Some(current) = annotations.current() {
+            current.draw(cr, 1.0, 0.0, 0.0);
+        }
+    })
+    .map_err(|e| format!("Failed to draw the annotations: {}", e))?;
+
+    let partial = path.with_extension("png.part");
+    image
+        .save_with_format(&partial, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to save the annotations: {}", e))?;
+    std::fs::rename(&partial, path).map_err(|e| format!("Failed to save the annotations: {}", e))
+}
+
+/// Elements bringing frames of `area` to the `frame` size, keeping their
+/// aspect ratio with borders, so segments of an area that moved and resized
+/// can still be joined; none when it is that size already
+fn scale_elements(area: RecordingArea, frame: (i32, i32)) -> Vec<String> {
+    if (area.width, area.height) == frame {
+        return Vec::new();
+    }
+    vec![
+        "!".to_string(),
+        "videoscale".to_string(),
+        "add-borders=true".to_string(),
+        "!".to_string(),
+        format!(
+            "video/x-raw,width={},height={},pixel-aspect-ratio=1/1",
+            frame.0, frame.1
+        ),
+    ]
+}
+
+/// Elements producing the sound of `track`, or silence in mock sessions
+fn audio_source_elements(track: AudioTrack, audio: &RecordingAudio, mock: bool) -> Vec<String> {
+    let mut elements = if mock {
+        vec![
+            "audiotestsrc".to_string(),
+            "is-live=true".to_string(),
+            "wave=silence".to_string(),
+        ]
+    } else {
+        vec!["pipewiresrc".to_string()]
+    };
+    match track {
+        _ if mock => {}
+        // Records the default output rather than an input
+        AudioTrack::System => {
+            elements.push("stream-properties=props,stream.capture.sink=true".to_string())
+        }
+        AudioTrack::Microphone => {
+            if let Some(ref device) = audio.microphone_device {
+                elements.push(format!("target-object={}", device));
+            }
+        }
+    }
+    elements
+}
+
+/// How much to cut off each side of `stream` to leave `area`, as
+/// `(left, top, right, bottom)`
+fn crop_margins(area: RecordingArea, stream: &PortalStream) -> (i32, i32, i32, i32) {
+    let (x, y) = stream.position;
+    let (width, height) = stream.size;
+    let left = (area.x - x).clamp(0, width);
+    let top = (area.y - y).clamp(0, height);
+    let right = (x + width - area.x - area.width).clamp(0, width - left);
+    let bottom = (y + height - area.y - area.height).clamp(0, height - top);
+    (left, top, right, bottom)
+}
+
+/// Arguments for `gst-launch-1.0` recording `source` into `location`, with
+/// the sound of the `audio` sources mixed in, each muted or not, and the
+/// image at `overlay` laid over the frames. Each element and property is its
+/// own argument, so paths need no quoting.
+fn segment_args(
+    source: Vec<String>,
+    audio: Vec<(Vec<String>, bool)>,
+    overlay: Option<&Path>,
+    format: RecordingFormat,
+    location: &Path,
+) -> Vec<String> {
+    let mut args = vec![GST_LAUNCH.to_string(), "-e".to_string()];
+    args.extend(source);
+    let rate = format!("video/x-raw,framerate={}/1", FRAME_RATE);
+    for element in ["!", "videorate", "!", &rate, "!", "videoconvert", "!"] {
+        args.push(element.to_string());
+    }
+    if overlay.is_some() {
+        for element in ["compositor", "name=overlay", "!", "videoconvert", "!"] {
+            args.push(element.to_string());
+        }
+    }
+    args.extend(format.encoder_elements().iter().map(|e| e.to_string()));
+    args.push("!".to_string());
+    args.push(format.muxer().to_string());
+    if !audio.is_empty() {
+        args.push("name=mux".to_string());
+    }
+    args.push("!".to_string());
+    args.push("filesink".to_string());
+    args.push(format!("location={}", location.display()));
+
+    if let Some(overlay) = overlay {
+        // With no %d in its location, multifilesrc reads the same file for
+        // every frame, picking up each new version of it
+        args.push("multifilesrc".to_string());
+        args.push(format!("location={}", overlay.display()));
+        args.push("loop=true".to_string());
+        args.push(format!("caps=image/png,framerate={}/1", FRAME_RATE));
+        for element in ["!", "pngdec", "!", "videoconvert", "!", "overlay."] {
+            args.push(element.to_string());
+        }
+    }
+    if audio.is_empty() {
+        return args;
+    }
+
+    for element in ["audiomixer", "name=mix", "!", "audioconvert", "!"] {
+        args.push(element.to_string());
+    }
+    args.extend(
+        format
+            .audio_encoder_elements()
+            .iter()
+            .map(|e| e.to_string()),
+    );
+    args.push("!".to_string());
+    args.push("mux.".to_string());
+    for (source, muted) in audio {
+        args.extend(source);
+        let mute = format!("mute={}", muted);
+        for element in [
+            "!",
+            "audioconvert",
+            "!",
+            "audioresample",
+            "!",
+            AUDIO_CAPS,
+            "!",
+            "volume",
+            &mute,
+            "!",
+            "mix.",
+        ] {
+            args.push(element.to_string());
+        }
+    }
+    args
+}
+
+/// Arguments for `gst-launch-1.0` joining `segments` into `location`, along
+/// with their sound if they have it
+fn join_args(
+    segments: &[PathBuf],
+    format: RecordingFormat,
+    has_audio: bool,
+    location: &Path,
+) -> Vec<String> {
+    let mut args: Vec<String> = [GST_LAUNCH, "-e", "concat", "name=c", "!"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    if let Some(parser) = format.video_parser() {
+        args.extend([parser.to_string(), "!".to_string()]);
+    }
+    args.push(format.muxer().to_string());
+    if has_audio {
+        args.push("name=mux".to_string());
+    }
+    args.push("!".to_string());
+    args.push("filesink".to_string());
+    args.push(format!("location={}", location.display()));
+    if has_audio {
+        for element in ["concat", "name=a", "!", "mux."] {
+            args.push(element.to_string());
+        }
+    }
+
+    for (index, segment) in segments.iter().enumerate() {
+        args.push("filesrc".to_string());
+        args.push(format!("location={}", segment.display()));
+        args.push("!".to_string());
+        args.push(format.demuxer().to_string());
+        let demuxer = format!("d{}", index);
+        if has_audio {
+            args.push(format!("name={}", demuxer));
+            args.push(format!("{}.video_0", demuxer));
+        }
+        args.push("!".to_string());
+        if let Some(parser) = format.video_parser() {
+            args.extend([parser.to_string(), "!".to_string()]);
+        }
+        args.push("c.".to_string());
+        if has_audio {
+            args.push(format!("{}.audio_0", demuxer));
+            for element in ["!", format.audio_parser(), "!", "a."] {
+                args.push(element.to_string());
+            }
+        }
+    }
+    args
+}
+
+/// Arguments for `ges-launch-1.0` writing what lies between `start` and
+/// `end` of `recording` to `location`, along with its sound if it has it
+fn trim_args(
+    recording: &Path,
+    format: RecordingFormat,
+    (start, end): (Duration, Duration),
+    has_audio: bool,
+    location: &Path,
+) -> Result<Vec<String>, String> {
+    let uri = |path: &Path| {
+        glib::filename_to_uri(path, None)
+            .map(|uri| uri.to_string())
+            .map_err(|e| format!("Invalid path {:?}: {}", path, e))
+    };
+    let track_types = if has_audio { "audio+video" } else { "video" };
+    Ok(vec![
+        GES_LAUNCH.to_string(),
+        "+clip".to_string(),
+        uri(recording)?,
+        format!("inpoint={:.3}", start.as_secs_f64()),
+        format!("duration={:.3}", end.saturating_sub(start).as_secs_f64()),
+        format!("--track-types={}", track_types),
+        format!("--format={}", format.encoding_profile(has_audio)),
+        "-o".to_string(),
+        uri(location)?,
+    ])
+}
+
+/// Whether recordings can be trimmed, which needs `ges-launch-1.0`
+pub fn trim_available() -> bool {
+    glib::find_program_in_path(GES_LAUNCH).is_some()
+}
+
+/// Cut the finished `recording` down to what lies between `start` and
+/// `end`, in its place. It is encoded again, so this takes a while for long
+/// recordings.
+pub async fn trim_recording(
+    recording: &Path,
+    start: Duration,
+    end: Duration,
+    has_audio: bool,
+) -> Result<(), String> {
+    let format = recording
+        .extension()
+        .and_then(|extension| RecordingFormat::from_id(&extension.to_string_lossy()))
+        .filter(|format| !format.is_animation())
+        .ok_or("Only MP4 and WebM recordings can be trimmed")?;
+    if end <= start {
+        return Err("Nothing is left to keep".to_string());
+    }
+
+    let stem = recording
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let trimmed = recording.with_file_name(format!(".{}.trimmed.{}", stem, format.extension()));
+    info!(
+        "Trimming {:?} to {:.1}s–{:.1}s",
+        recording,
+        start.as_secs_f64(),
+        end.as_secs_f64()
+    );
+
+    let args = trim_args(recording, format, (start, end), has_audio, &trimmed)?;
+    let argv: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+    let result = match gio::Subprocess::newv(&argv, gio::SubprocessFlags::STDOUT_SILENCE) {
+        Ok(process) => process
+            .wait_check_future()
+            .await
+            .map_err(|e| format!("Failed to trim the recording: {}", e)),
+        Err(e) => Err(format!("Failed to run {}: {}", GES_LAUNCH, e)),
+    };
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&trimmed);
+        return Err(e);
+    }
+    std::fs::rename(&trimmed, recording)
+        .map_err(|e| format!("Failed to save the trimmed recording: {}", e))
+}
+
+/// A recording in progress, running or paused
+pub struct Recording {
+    source: FrameSource,
+    area: RecordingArea,
+
+    /// Size of the recorded frames, that of the area recording started with
+    frame_size: (i32, i32),
+
+    format: RecordingFormat,
+    output: PathBuf,
+    segments: Vec<PathBuf>,
+
+    /// Recorder of the current segment, `None` while paused
+    process: Option<gio::Subprocess>,
+
+    /// Recorders of earlier segments, which may still be finishing their files
+    finishing: Vec<gio::Subprocess>,
+
+    /// Length of the finished segments
+    recorded: Duration,
+    segment_started: Option<Instant>,
+
+    animation: AnimationSettings,
+
+    audio: RecordingAudio,
+
+    /// Tracks recorded as silence from now on
+    muted: Vec<AudioTrack>,
+
+    /// Image of the annotations laid over the frames, once any are drawn
+    overlay: Option<PathBuf>,
+
+    limits: RecordingLimits,
+}
+
+impl Recording {
+    /// Start recording `area` into `output`, with the sound in `audio`. On
+    /// Wayland the portal first asks which monitor to share, and the area is
+    /// cut from that monitor.
+    pub async fn start(
+        area: RecordingArea,
+        format: RecordingFormat,
+        audio: RecordingAudio,
+        output: PathBuf,
+    ) -> Result<Self, String> {
+        let area = area.even();
+        if area.width <= 0 || area.height <= 0 {
+            return Err("The area to record is empty".to_string());
+        }
+
+        if let Some(folder) = output.parent() {
+            std::fs::create_dir_all(folder)
+                .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+        }
+
+        let backend = DesktopSession::detect().recording_backend();
+        info!("Recording {:?} with {} to {:?}", area, backend, output);
+        let source = match backend {
+            RecordingBackend::X11 => FrameSource::X11,
+            RecordingBackend::Mock => FrameSource::Mock,
+            RecordingBackend::Portal => open_portal_stream().await?,
+        };
+
+        let mut recording = Self {
+            source,
+            area,
+            frame_size: (area.width, area.height),
+            format,
+            output,
+            segments: Vec::new(),
+            process: None,
+            finishing: Vec::new(),
+            recorded: Duration::ZERO,
+            segment_started: None,
+            animation: AnimationSettings::default(),
+            audio,
+            muted: Vec::new(),
+            overlay: None,
+            limits: RecordingLimits::default(),
+        };
+        recording.start_segment()?;
+        Ok(recording)
+    }
+
+    pub fn area(&self) -> RecordingArea {
+        self.area
+    }
+
+    /// Width and height of the recorded frames
+    pub fn frame_size(&self) -> (i32, i32) {
+        self.frame_size
+    }
+
+    /// Record `area` from now on, such as when the window being recorded
+    /// moves. Frames keep their size, the area is scaled to fit them. A
+    /// running recording moves on to a new segment, which takes the change.
+    pub fn set_area(&mut self, area: RecordingArea) -> Result<(), String> {
+        let area = area.even();
+        if area == self.area || area.width <= 0 || area.height <= 0 {
+            return Ok(());
+        }
+        debug!("Recording {:?} from now on", area);
+        self.area = area;
+
+        if self.is_paused() {
+            return Ok(());
+        }
+        self.pause();
+        self.resume()
+    }
+
+    pub fn format(&self) -> RecordingFormat {
+        self.format
+    }
+
+    /// The sound recorded, none for GIFs
+    pub fn audio_tracks(&self) -> Vec<AudioTrack> {
+        if self.format.is_animation() {
+            Vec::new()
+        } else {
+            self.audio.tracks()
+        }
+    }
+
+    pub fn is_muted(&self, track: AudioTrack) -> bool {
+        self.muted.contains(&track)
+    }
+
+    /// Record `track` as silence from now on, or stop doing so. A running
+    /// recording moves on to a new segment, which takes the change.
+    pub fn set_muted(&mut self, track: AudioTrack, muted: bool) -> Result<(), String> {
+        if self.is_muted(track) == muted || !self.audio_tracks().contains(&track) {
+            return Ok(());
+        }
+        debug!(
+            "{} {}",
+            if muted { "Muting" } else { "Unmuting" },
+            track.label()
+        );
+        if muted {
+            self.muted.push(track);
+        } else {
+            self.muted.retain(|t| *t != track);
+        }
+
+        if self.is_paused() {
+            return Ok(());
+        }
+        self.pause();
+        self.resume()
+    }
+
+    /// Whether annotations are laid over the frames
+    pub fn has_overlay(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    /// Lay annotations over the frames from now on, starting with none. A
+    /// running recording moves on to a new segment, which reads them.
+    pub fn start_overlay(&mut self) -> Result<(), String> {
+        if self.overlay.is_some() {
+            return Ok(());
+        }
+        let path = self.hidden_path("overlay").with_extension("png");
+        debug!("Laying annotations over the recording from {:?}", path);
+        write_overlay(&AnnotationList::new(), self.frame_size, &path)?;
+        self.overlay = Some(path);
+
+        if self.is_paused() {
+            return Ok(());
+        }
+        self.pause();
+        self.resume()
+    }
+
+    /// Show `annotations` over the frames recorded from now on
+    pub fn update_overlay(&self, annotations: &AnnotationList) -> Result<(), String> {
+        match self.overlay {
+            Some(ref path) => write_overlay(annotations, self.frame_size, path),
+            None => Ok(()),
+        }
+    }
+
+    /// Frame rate and length of a GIF recording
+    pub fn set_animation_settings(&mut self, settings: AnimationSettings) {
+        self.animation = settings;
+    }
+
+    pub fn set_limits(&mut self, limits: RecordingLimits) {
+        self.limits = limits;
+    }
+
+    /// How long the recording may get, past which it should be stopped
+    pub fn max_duration(&self) -> Option<Duration> {
+        let animation = self
+            .format
+            .is_animation()
+            .then(|| Duration::from_secs(self.animation.max_duration_secs as u64));
+        let limit = (self.limits.max_duration_mins > 0)
+            .then(|| Duration::from_secs(self.limits.max_duration_mins as u64 * 60));
+        match (animation, limit) {
+            (Some(animation), Some(limit)) => Some(animation.min(limit)),
+            (animation, limit) => animation.or(limit),
+        }
+    }
+
+    /// Bytes the segments take up so far. The one being recorded grows as
+    /// the recorder writes it out.
+    pub fn size(&self) -> u64 {
+        self.segments
+            .iter()
+            .filter_map(|segment| std::fs::metadata(segment).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// The limit the recording has reached, if any, after which it should be
+    /// stopped
+    pub fn limit_reached(&self) -> Option<RecordingLimit> {
+        if self.max_duration().is_some_and(|max| self.elapsed() >= max) {
+            return Some(RecordingLimit::Duration);
+        }
+        let max_size = self.limits.max_size_mib as u64 * 1024 * 1024;
+        if max_size > 0 && self.size() >= max_size {
+            return Some(RecordingLimit::Size);
+        }
+        None
+    }
+
+    /// Time recorded so far, not counting pauses
+    pub fn elapsed(&self) -> Duration {
+        self.recorded
+            + self
+                .segment_started
+                .map(|started| started.elapsed())
+                .unwrap_or_default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.process.is_none()
+    }
+
+    pub fn pause(&mut self) {
+        if let Some(process) = self.process.take() {
+            debug!("Pausing recording");
+            process.send_signal(SIGINT);
+            self.finishing.push(process);
+        }
+        if let Some(started) = self.segment_started.take() {
+            self.recorded += started.elapsed();
+        }
+    }
+
+    pub fn resume(&mut self) -> Result<(), String> {
+        if self.is_paused() {
+            debug!("Resuming recording");
+            self.start_segment()?;
+        }
+        Ok(())
+    }
+
+    /// Finish recording and write the output, returning its path
+    pub async fn stop(mut self) -> Result<PathBuf, String> {
+        self.pause();
+
+        let mut result = Ok(());
+        for process in std::mem::take(&mut self.finishing) {
+            if let Err(e) = process.wait_check_future().await {
+                result = Err(format!("Recording failed: {}", e));
+            }
+        }
+        if let FrameSource::Portal {
+            connection,
+            session,
+            ..
+        } = &self.source
+        {
+            close_portal_session(connection, session).await;
+        }
+
+        let segments = std::mem::take(&mut self.segments);
+        let video = if self.format.is_animation() {
+            self.hidden_path("video")
+        } else {
+            self.output.clone()
+        };
+        let joined = match result {
+            Ok(()) => self.join(&segments, &video).await,
+            Err(e) => Err(e),
+        };
+        for segment in &segments {
+            let _ = std::fs::remove_file(segment);
+        }
+        if let Some(ref overlay) = self.overlay {
+            let _ = std::fs::remove_file(overlay);
+        }
+        joined?;
+
+        if let Some(animation) = self.format.animation_format() {
+            let converted = self.convert_to_animation(&video, animation).await;
+            let _ = std::fs::remove_file(&video);
+            converted?;
+        }
+
+        info!("Recording saved to {:?}", self.output);
+        Ok(self.output.clone())
+    }
+
+    async fn join(&self, segments: &[PathBuf], location: &Path) -> Result<(), String> {
+        match segments {
+            [] => Err("Nothing was recorded".to_string()),
+            [segment] => std::fs::rename(segment, location)
+                .map_err(|e| format!("Failed to save the recording: {}", e)),
+            _ => {
+                debug!("Joining {} recording segments", segments.len());
+                let has_audio = !self.audio_tracks().is_empty();
+                let args = join_args(segments, self.format, has_audio, location);
+                let argv: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+                let process = gio::Subprocess::newv(&argv, gio::SubprocessFlags::STDOUT_SILENCE)
+                    .map_err(|e| format!("Failed to run {}: {}", GST_LAUNCH, e))?;
+                process
+                    .wait_check_future()
+                    .await
+                    .map_err(|e| format!("Failed to join the recording: {}", e))
+            }
+        }
+    }
+
+    async fn convert_to_animation(
+        &self,
+        video: &Path,
+        format: AnimationFormat,
+    ) -> Result<(), String> {
+        let (video, output) = (video.to_path_buf(), self.output.clone());
+        let size = (self.frame_size.0 as u32, self.frame_size.1 as u32);
+        let settings = self.animation;
+        gio::spawn_blocking(move || encode_animation(&video, size, format, &settings, &output))
+            .await
+            .map_err(|_| format!("Converting to {:?} panicked", format))?
+            .map(|frames| debug!("Wrote {} {:?} frames", frames, format))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Segments and the video an animation is made from are hidden files next to
+    /// the output, so a crash leaves them where the recording was going
+    fn hidden_path(&self, part: &str) -> PathBuf {
+        let stem = self
+            .output
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.output.with_file_name(format!(
+            ".{}.{}.{}",
+            stem,
+            part,
+            self.format.video_format().extension()
+        ))
+    }
+
+    fn start_segment(&mut self) -> Result<(), String> {
+        let location = self.hidden_path(&format!("part{}", self.segments.len()));
+        let mock = matches!(self.source, FrameSource::Mock);
+        let audio = self
+            .audio_tracks()
+            .into_iter()
+            .map(|track| {
+                (
+                    audio_source_elements(track, &self.audio, mock),
+                    self.is_muted(track),
+                )
+            })
+            .collect();
+        let mut source = self.source.elements(self.area);
+        source.extend(scale_elements(self.area, self.frame_size));
+        let args = segment_args(
+            source,
+            audio,
+            self.overlay.as_deref(),
+            self.format,
+            &location,
+        );
+        let argv: Vec<&OsStr> = args.iter().map(OsStr::new).collect();
+
+        let launcher = gio::SubprocessLauncher::new(gio::SubprocessFlags::STDOUT_SILENCE);
+        if let FrameSource::Portal { remote, .. } = &self.source {
+            let remote = remote
+                .try_clone()
+                .map_err(|e| format!("Failed to share the PipeWire remote: {}", e))?;
+            // SAFETY: only names the descriptor number in the child, the
+            // parent never uses it
+            let target = unsafe { BorrowedFd::borrow_raw(PIPEWIRE_FD) };
+            launcher.take_fd(remote, target);
+        }
+        let process = launcher
+            .spawn(&argv)
+            .map_err(|e| format!("Failed to run {}: {}", GST_LAUNCH, e))?;
+
+        self.segments.push(location);
+        self.process = Some(process);
+        self.segment_started = Some(Instant::now());
+        Ok(())
+    }
+}
+
+impl Drop for Recording {
+    /// Leave the segment recorded so far readable if the recording is never
+    /// stopped, such as when the app quits
+    fn drop(&mut self) {
+        if let Some(process) = self.process.take() {
+            warn!("Recording dropped while running");
+            process.send_signal(SIGINT);
+        }
+    }
+}
+
+/// Ask the ScreenCast portal for a monitor and open its PipeWire stream
+async fn open_portal_stream() -> Result<FrameSource, String> {
+    let connection = gio::bus_get_future(gio::BusType::Session)
+        .await
+        .map_err(|e| format!("Failed to connect to the session bus: {}", e))?;
+
+    let options = VariantDict::new(None);
+    options.insert("session_handle_token", request_token());
+    let results = portal_request(&connection, "CreateSession", Vec::new(), options).await?;
+    let session: String = results
+        .lookup("session_handle")
+        .ok()
+        .flatten()
+        .ok_or("The portal did not create a session")?;
+    let session_path = glib::variant::ObjectPath::try_from(session.clone())
+        .map_err(|e| format!("Invalid portal session {}: {}", session, e))?;
+
+    let options = VariantDict::new(None);
+    // Monitors only, since the area is cut from the monitor it is on
+    options.insert("types", 1u32);
+    options.insert("multiple", false);
+    // Cursor drawn into the frames
+    options.insert("cursor_mode", 2u32);
+    portal_request(
+        &connection,
+        "SelectSources",
+        vec![session_path.to_variant()],
+        options,
+    )
+    .await?;
+
+    let results = portal_request(
+        &connection,
+        "Start",
+        vec![session_path.to_variant(), "".to_variant()],
+        VariantDict::new(None),
+    )
+    .await?;
+    let streams: Vec<(u32, VariantDict)> =
+        results.lookup("streams").ok().flatten().unwrap_or_default();
+    let (node, properties) = match streams.into_iter().next() {
+        Some(stream) => stream,
+        None => return Err("No monitor was shared".to_string()),
+    };
+    let stream = PortalStream {
+        node,
+        position: properties
+            .lookup("position")
+            .ok()
+            .flatten()
+            .unwrap_or((0, 0)),
+        size: properties
+            .lookup("size")
+            .ok()
+            .flatten()
+            .ok_or("The portal did not report the monitor's size")?,
+    };
+    debug!("Portal shared {:?}", stream);
+
+    let (_, fds) = connection
+        .call_with_unix_fd_list_future(
+            Some(PORTAL_DEST),
+            PORTAL_PATH,
+            SCREENCAST_INTERFACE,
+            "OpenPipeWireRemote",
+            Some(&(session_path, VariantDict::new(None)).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            None::<&gio::UnixFDList>,
+        )
+        .await
+        .map_err(|e| format!("Failed to open the PipeWire remote: {}", e))?;
+    // The reply's only handle
+    let remote = fds
+        .ok_or("The portal sent no PipeWire remote")?
+        .get(0)
+        .map_err(|e| format!("Failed to open the PipeWire remote: {}", e))?;
+
+    Ok(FrameSource::Portal {
+        connection,
+        session,
+        remote,
+        stream,
+    })
+}
+
+async fn close_portal_session(connection: &gio::DBusConnection, session: &str) {
+    let result = connection
+        .call_future(
+            Some(PORTAL_DEST),
+            session,
+            "org.freedesktop.portal.Session",
+            "Close",
+            None,
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+        )
+        .await;
+    if let Err(e) = result {
+        warn!("Failed to close the portal session: {}", e);
+    }
+}
+
+fn request_token() -> String {
+    format!(
+        "screenshot_gnome_{}_{}",
+        std::process::id(),
+        REQUEST_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Call a ScreenCast `method` and wait for the results of the request it
+/// starts
+async fn portal_request(
+    connection: &gio::DBusConnection,
+    method: &str,
+    mut args: Vec<Variant>,
+    options: VariantDict,
+) -> Result<VariantDict, String> {
+    let token = request_token();
+    options.insert("handle_token", token.as_str());
+    let sender = connection
+        .unique_name()
+        .ok_or("The session bus connection has no name")?;
+    let request_path = format!(
+        "{}/request/{}/{}",
+        PORTAL_PATH,
+        sender.trim_start_matches(':').replace('.', "_"),
+        token
+    );
+
+    // Subscribe before calling, otherwise a fast response could be missed
+    let response: Rc<RefCell<Option<(u32, VariantDict)>>> = Rc::new(RefCell::new(None));
+    let responded = gio::Cancellable::new();
+    let _subscription = connection.subscribe_to_signal(
+        Some(PORTAL_DEST),
+        Some("org.freedesktop.portal.Request"),
+        Some("Response"),
+        Some(&request_path),
+        None,
+        gio::DBusSignalFlags::NONE,
+        {
+            let response = response.clone();
+            let responded = responded.clone();
+            move |signal| {
+                *response.borrow_mut() = signal.parameters.get();
+                responded.cancel();
+            }
+        },
+    );
+
+    debug!("Calling ScreenCast.{} ({})", method, token);
+    args.push(options.end());
+    connection
+        .call_future(
+            Some(PORTAL_DEST),
+            PORTAL_PATH,
+            SCREENCAST_INTERFACE,
+            method,
+            Some(&Variant::tuple_from_iter(args)),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+        )
+        .await
+        .map_err(|e| format!("ScreenCast portal call {} failed: {}", method, e))?;
+    responded.future().await;
+
+    match response.borrow_mut().take() {
+        Some((0, results)) => Ok(results),
+        Some((1, _)) => Err("Recording was cancelled".to_string()),
+        Some((code, _)) => Err(format!("ScreenCast portal failed (code {})", code)),
+        None => Err("Unexpected response from the ScreenCast portal".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_elapsed() {
+        assert_eq!(format_elapsed(Duration::from_millis(59_900)), "0:59");
+        assert_eq!(format_elapsed(Duration::from_secs(61)), "1:01");
+        assert_eq!(format_elapsed(Duration::from_secs(3723)), "1:02:03");
+    }
+
+    #[test]
+    fn test_crop_margins() {
+        let stream = PortalStream {
+            node: 42,
+            position: (1920, 0),
+            size: (2560, 1440),
+        };
+        let area = RecordingArea {
+            x: 2000,
+            y: 100,
+            width: 800,
+            height: 600,
+        };
+        assert_eq!(crop_margins(area, &stream), (80, 100, 1680, 740));
+
+        // Reaching past the monitor's edge
+        let area = RecordingArea {
+            x: 1800,
+            y: -50,
+            width: 400,
+            height: 300,
+        };
+        assert_eq!(crop_margins(area, &stream), (0, 0, 2280, 1190));
+    }
+
+    #[test]
+    fn test_pipeline_args() {
+        let area = RecordingArea {
+            x: 10,
+            y: 20,
+            width: 101,
+            height: 51,
+        }
+        .even();
+        let args = segment_args(
+            FrameSource::X11.elements(area),
+            Vec::new(),
+            None,
+            RecordingFormat::WebM,
+            Path::new("/home/me/My Videos/.clip.part0.webm"),
+        );
+        assert_eq!(
+            args.join(" "),
+            "gst-launch-1.0 -e ximagesrc startx=10 starty=20 endx=109 endy=69 \
+             use-damage=false ! videorate ! video/x-raw,framerate=30/1 ! videoconvert ! \
+             vp8enc deadline=1 ! webmmux ! filesink location=/home/me/My Videos/.clip.part0.webm"
+        );
+        assert_eq!(
+            args.last().map(String::as_str),
+            Some("location=/home/me/My Videos/.clip.part0.webm")
+        );
+
+        let segments = [PathBuf::from("/tmp/a.mp4"), PathBuf::from("/tmp/b.mp4")];
+        assert_eq!(
+            join_args(
+                &segments,
+                RecordingFormat::Mp4,
+                false,
+                Path::new("/tmp/out.mp4")
+            )
+            .join(" "),
+            "gst-launch-1.0 -e concat name=c ! h264parse ! mp4mux ! filesink \
+             location=/tmp/out.mp4 filesrc location=/tmp/a.mp4 ! qtdemux ! h264parse ! c. \
+             filesrc location=/tmp/b.mp4 ! qtdemux ! h264parse ! c."
+        );
+        // GIFs are joined as the MP4 they are made from
+        assert_eq!(
+            join_args(
+                &segments,
+                RecordingFormat::Gif,
+                false,
+                Path::new("/tmp/out.mp4")
+            ),
+            join_args(
+                &segments,
+                RecordingFormat::Mp4,
+                false,
+                Path::new("/tmp/out.mp4")
+            )
+        );
+    }
+
+    #[test]
+    fn test_audio_pipeline_args() {
+        let area = RecordingArea {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 48,
+        };
+        let audio = RecordingAudio {
+            system: true,
+            microphone: true,
+            microphone_device: Some("alsa_input.usb-mic".to_string()),
+        };
+        let sources = audio
+            .tracks()
+            .into_iter()
+            .map(|track| {
+                (
+                    audio_source_elements(track, &audio, false),
+                    track == AudioTrack::Microphone,
+                )
+            })
+            .collect();
+        let args = segment_args(
+            FrameSource::X11.elements(area),
+            sources,
+            None,
+            RecordingFormat::WebM,
+            Path::new("/tmp/.clip.part0.webm"),
+        );
+        let pipeline = args.join(" ");
+        assert!(pipeline.contains(
+            "vp8enc deadline=1 ! webmmux name=mux ! filesink location=/tmp/.clip.part0.webm \
+             audiomixer name=mix ! audioconvert ! opusenc ! mux."
+        ));
+        assert!(pipeline.contains(
+            "pipewiresrc stream-properties=props,stream.capture.sink=true ! audioconvert ! \
+             audioresample ! audio/x-raw,rate=48000,channels=2 ! volume mute=false ! mix."
+        ));
+        assert!(pipeline.ends_with(
+            "pipewiresrc target-object=alsa_input.usb-mic ! audioconvert ! audioresample ! \
+             audio/x-raw,rate=48000,channels=2 ! volume mute=true ! mix."
+        ));
+
+        let segments = [PathBuf::from("/tmp/a.webm"), PathBuf::from("/tmp/b.webm")];
+        assert_eq!(
+            join_args(
+                &segments,
+                RecordingFormat::WebM,
+                true,
+                Path::new("/tmp/out.webm")
+            )
+            .join(" "),
+            "gst-launch-1.0 -e concat name=c ! webmmux name=mux ! filesink \
+             location=/tmp/out.webm concat name=a ! mux. \
+             filesrc location=/tmp/a.webm ! matroskademux name=d0 d0.video_0 ! c. \
+             d0.audio_0 ! opusparse ! a. \
+             filesrc location=/tmp/b.webm ! matroskademux name=d1 d1.video_0 ! c. \
+             d1.audio_0 ! opusparse ! a."
+        );
+    }
+
+    #[test]
+    fn test_overlay_pipeline_args() {
+        let area = RecordingArea {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 48,
+        };
+        let args = segment_args(
+            FrameSource::Mock.elements(area),
+            Vec::new(),
+            Some(Path::new("/tmp/.clip.overlay.png")),
+            RecordingFormat::Mp4,
+            Path::new("/tmp/.clip.part1.mp4"),
+        );
+        assert_eq!(
+            args.join(" "),
+            "gst-launch-1.0 -e videotestsrc is-live=true pattern=ball ! \
+             video/x-raw,width=64,height=48 ! videorate ! video/x-raw,framerate=30/1 ! \
+             videoconvert ! compositor name=overlay ! videoconvert ! video/x-raw,format=I420 ! \
+             x264enc tune=zerolatency speed-preset=veryfast ! h264parse ! mp4mux ! \
+             filesink location=/tmp/.clip.part1.mp4 \
+             multifilesrc location=/tmp/.clip.overlay.png loop=true \
+             caps=image/png,framerate=30/1 ! pngdec ! videoconvert ! overlay."
+        );
+    }
+
+    #[test]
+    fn test_trim_args() {
+        let args = trim_args(
+            Path::new("/tmp/clip.webm"),
+            RecordingFormat::WebM,
+            (Duration::from_millis(1500), Duration::from_secs(4)),
+            false,
+            Path::new("/tmp/.clip.trimmed.webm"),
+        )
+        .unwrap();
+        assert_eq!(
+            args.join(" "),
+            "ges-launch-1.0 +clip file:///tmp/clip.webm inpoint=1.500 duration=2.500 \
+             --track-types=video --format=video/webm:video/x-vp8 \
+             -o file:///tmp/.clip.trimmed.webm"
+        );
+
+        let args = trim_args(
+            Path::new("/tmp/clip.mp4"),
+            RecordingFormat::Mp4,
+            (Duration::ZERO, Duration::from_secs(2)),
+            true,
+            Path::new("/tmp/.clip.trimmed.mp4"),
+        )
+        .unwrap();
+        assert!(args.contains(&"--track-types=audio+video".to_string()));
+        assert!(args.contains(
+            &"--format=video/quicktime,variant=iso:video/x-h264:audio/mpeg,mpegversion=4"
+                .to_string()
+        ));
+    }
+
+    #[test]
+    fn test_scale_elements() {
+        let area = RecordingArea {
+            x: 300,
+            y: 200,
+            width: 800,
+            height: 500,
+        };
+        assert!(scale_elements(area, (800, 500)).is_empty());
+        assert_eq!(
+            scale_elements(area, (640, 480)).join(" "),
+            "! videoscale add-borders=true ! \
+             video/x-raw,width=640,height=480,pixel-aspect-ratio=1/1"
+        );
+    }
+
+    #[test]
+    fn test_parse_pw_dump() {
+        let json = r#"[
+            {"id": 30, "type": "PipeWire:Interface:Node", "info": {"props": {
+                "media.class": "Audio/Source", "node.name": "alsa_input.usb-mic",
+                "node.description": "USB Microphone"}}},
+            {"id": 31, "type": "PipeWire:Interface:Node", "info": {"props": {
+                "media.class": "Audio/Sink", "node.name": "alsa_output.speakers"}}},
+            {"id": 32, "type": "PipeWire:Interface:Node", "info": {"props": {
+                "media.class": "Audio/Source", "node.name": "echo-cancel-source"}}},
+            {"id": 33, "type": "PipeWire:Interface:Port", "info": {}}
+        ]"#;
+        assert_eq!(
+            parse_pw_dump(json),
+            vec![
+                AudioDevice {
+                    name: "alsa_input.usb-mic".to_string(),
+                    description: "USB Microphone".to_string(),
+                },
+                AudioDevice {
+                    name: "echo-cancel-source".to_string(),
+                    description: "echo-cancel-source".to_string(),
+                },
+            ]
+        );
+        assert!(parse_pw_dump("not json").is_empty());
+    }
+}
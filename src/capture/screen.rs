@@ -3,7 +3,10 @@ use log::{debug, info};
 use std::process::Command;
 use xcap::Monitor;
 
-use super::desktop::{DesktopEnvironment, DesktopSession, DisplayServer};
+use super::desktop::{DesktopEnvironment, DesktopSession, DisplayServer, ScreenCaptureBackend};
+use super::hyprland::Hyprland;
+use super::mock::{mock_backend_enabled, MockBackend};
+use super::sway::SwayIpc;
 
 #[derive(Debug, Clone)]
 pub struct MonitorInfo {
@@ -20,11 +23,24 @@ impl MonitorInfo {
     }
 
     /// Create a default MonitorInfo for Wayland when we can't get detailed info
-    fn default_wayland() -> Self {
+    pub(super) fn default_wayland() -> Self {
         Self { x: 0, y: 0 }
     }
 }
 
+/// A monitor to capture, in the desktop's logical coordinates as GDK
+/// reports them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorTarget {
+    /// Output name such as `DP-1`, as `grim -o` takes it
+    pub connector: Option<String>,
+
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
 pub struct CaptureResult {
     pub pixbuf: gtk::gdk_pixbuf::Pixbuf,
     pub monitor_info: MonitorInfo,
@@ -32,9 +48,20 @@ pub struct CaptureResult {
 
 /// Capture the primary monitor, using the appropriate backend for the current session
 pub fn capture_primary_monitor() -> Result<CaptureResult, String> {
+    if mock_backend_enabled() {
+        return MockBackend.capture_screen();
+    }
+
     let session = DesktopSession::detect();
-    info!("Capturing primary monitor on {}", session.display_server);
+    let backend = session.screen_capture_backend();
+    info!(
+        "Capturing primary monitor on {} with {}",
+        session.display_server, backend
+    );
 
+    if backend == ScreenCaptureBackend::Portal {
+        return super::portal::capture_screen();
+    }
     match session.display_server {
         DisplayServer::Wayland => capture_screen_wayland(&session),
         DisplayServer::X11 => capture_screen_xcap(),
@@ -45,6 +72,363 @@ pub fn capture_primary_monitor() -> Result<CaptureResult, String> {
     }
 }
 
+/// Capture the monitor `target`, one of the `monitors` making up the desktop
+pub fn capture_monitor(
+    target: &MonitorTarget,
+    monitors: &[MonitorTarget],
+) -> Result<CaptureResult, String> {
+    if mock_backend_enabled() {
+        return MockBackend.capture_screen();
+    }
+
+    let session = DesktopSession::detect();
+    info!(
+        "Capturing monitor {} at {},{} on {}",
+        target.connector.as_deref().unwrap_or("(unnamed)"),
+        target.x,
+        target.y,
+        session.display_server
+    );
+
+    let backend = session.screen_capture_backend();
+    if backend == ScreenCaptureBackend::Xcap {
+        return capture_monitor_xcap(target);
+    }
+
+    let monitor_info = MonitorInfo {
+        x: target.x,
+        y: target.y,
+    };
+
+    // wlroots compositors capture a single output directly
+    if backend == ScreenCaptureBackend::Compositor
+        && matches!(
+            session.desktop_environment,
+            DesktopEnvironment::Hyprland | DesktopEnvironment::Sway
+        )
+    {
+        if let Some(ref connector) = target.connector {
+            let temp_path = format!("/tmp/screenshot_gnome_screen_{}.png", std::process::id());
+            let result = capture_with_grim(&temp_path, Some(connector));
+            if result.is_err() {
+                let _ = std::fs::remove_file(&temp_path);
+            }
+            return result.map(|result| CaptureResult {
+                pixbuf: result.pixbuf,
+                monitor_info,
+            });
+        }
+    }
+
+    // The other tools capture the whole desktop, which is cut down to the monitor
+    let desktop = capture_whole_desktop()?;
+    let (x, y, width, height) = monitor_rect(
+        target,
+        monitors,
+        (desktop.pixbuf.width(), desktop.pixbuf.height()),
+    )
+    .ok_or("Monitor is outside the captured desktop")?;
+    debug!("Cropping monitor to {},{} {}x{}", x, y, width, height);
+
+    Ok(CaptureResult {
+        pixbuf: desktop.pixbuf.new_subpixbuf(x, y, width, height),
+        monitor_info,
+    })
+}
+
+/// Where `target` lies in a capture of the whole desktop, of `image_size`
+/// pixels and spanning the bounding box of all `monitors`
+fn monitor_rect(
+    target: &MonitorTarget,
+    monitors: &[MonitorTarget],
+    (image_width, image_height): (i32, i32),
+) -> Option<(i32, i32, i32, i32)> {
+    let left = monitors.iter().map(|m| m.x).min()?;
+    let top = monitors.iter().map(|m| m.y).min()?;
+    let right = monitors.iter().map(|m| m.x + m.width).max()?;
+    let bottom = monitors.iter().map(|m| m.y + m.height).max()?;
+    if right <= left || bottom <= top {
+        return None;
+    }
+
+    let scale_x = image_width as f64 / (right - left) as f64;
+    let scale_y = image_height as f64 / (bottom - top) as f64;
+    let x = (((target.x - left) as f64 * scale_x).round() as i32).clamp(0, image_width);
+    let y = (((target.y - top) as f64 * scale_y).round() as i32).clamp(0, image_height);
+    let end_x =
+        (((target.x + target.width - left) as f64 * scale_x).round() as i32).clamp(0, image_width);
+    let end_y =
+        (((target.y + target.height - top) as f64 * scale_y).round() as i32).clamp(0, image_height);
+
+    if end_x > x && end_y > y {
+        Some((x, y, end_x - x, end_y - y))
+    } else {
+        None
+    }
+}
+
+/// Capture every one of the `monitors` making up the desktop as a single
+/// image, laid out as they are arranged. Only xcap captures monitors one at
+/// a time, so its captures are stitched together here; the other tools
+/// capture the whole desktop at once already.
+pub fn capture_all_monitors(monitors: &[MonitorTarget]) -> Result<CaptureResult, String> {
+    if mock_backend_enabled() {
+        return MockBackend.capture_screen();
+    }
+
+    let session = DesktopSession::detect();
+    info!(
+        "Capturing all {} monitors on {}",
+        monitors.len(),
+        session.display_server
+    );
+    if session.screen_capture_backend() != ScreenCaptureBackend::Xcap {
+        return capture_whole_desktop();
+    }
+
+    let available = Monitor::all().map_err(|e| format!("Failed to get monitors: {}", e))?;
+    let mut parts = Vec::new();
+    for target in monitors {
+        let image = find_xcap_monitor(&available, target)
+            .ok_or("Monitor not found")?
+            .capture_image()
+            .map_err(|e| format!("Failed to capture screen: {}", e))?;
+        parts.push((target.clone(), image));
+    }
+
+    let (left, top) = match desktop_origin(parts.iter().map(|(target, _)| target)) {
+        Some(origin) => origin,
+        None => return Err("No monitors available".to_string()),
+    };
+    let stitched = stitch_monitors(&parts).ok_or("No monitors available")?;
+    debug!(
+        "Stitched {} monitors into {}x{}",
+        parts.len(),
+        stitched.width(),
+        stitched.height()
+    );
+
+    Ok(CaptureResult {
+        pixbuf: image_to_pixbuf(stitched)?,
+        monitor_info: MonitorInfo { x: left, y: top },
+    })
+}
+
+/// Capture the rectangle `area`, as `(x, y, width, height)` in the
+/// desktop's logical coordinates, from a capture of all the `monitors`.
+/// Fails when part of it is off the desktop, rather than giving less.
+pub fn capture_area(
+    (x, y, width, height): (i32, i32, i32, i32),
+    monitors: &[MonitorTarget],
+) -> Result<CaptureResult, String> {
+    let area = MonitorTarget {
+        connector: None,
+        x,
+        y,
+        width,
+        height,
+    };
+    let right = monitors.iter().map(|m| m.x + m.width).max();
+    let bottom = monitors.iter().map(|m| m.y + m.height).max();
+    let inside = match (desktop_origin(monitors.iter()), right, bottom) {
+        (Some((left, top)), Some(right), Some(bottom)) => {
+            x >= left && y >= top && x + width <= right && y + height <= bottom
+        }
+        _ => false,
+    };
+    if !inside {
+        return Err(format!(
+            "Area {},{} {}x{} is not inside the desktop",
+            x, y, width, height
+        ));
+    }
+
+    let desktop = capture_all_monitors(monitors)?;
+    let (crop_x, crop_y, crop_width, crop_height) = monitor_rect(
+        &area,
+        monitors,
+        (desktop.pixbuf.width(), desktop.pixbuf.height()),
+    )
+    .ok_or("Area is outside the captured desktop")?;
+    debug!(
+        "Cropping area to {},{} {}x{}",
+        crop_x, crop_y, crop_width, crop_height
+    );
+
+    Ok(CaptureResult {
+        pixbuf: desktop
+            .pixbuf
+            .new_subpixbuf(crop_x, crop_y, crop_width, crop_height),
+        monitor_info: MonitorInfo { x, y },
+    })
+}
+
+/// The monitors making up the desktop, asked of the compositor or xcap
+/// rather than GDK, so that the layout is known without GTK connecting to
+/// the display
+pub fn desktop_monitors() -> Result<Vec<MonitorTarget>, String> {
+    if mock_backend_enabled() {
+        return Ok(MockBackend.monitor_targets());
+    }
+
+    let session = DesktopSession::detect();
+    let compositor = match session.desktop_environment {
+        DesktopEnvironment::Hyprland => Some(
+            Hyprland::connect()
+                .and_then(|hyprland| hyprland.monitors())
+                .and_then(|reply| parse_hyprland_monitors(&reply)),
+        ),
+        DesktopEnvironment::Sway => Some(
+            SwayIpc::connect()
+                .and_then(|mut sway| sway.outputs())
+                .and_then(|reply| parse_sway_outputs(&reply)),
+        ),
+        _ => None,
+    };
+    match compositor {
+        Some(Ok(monitors)) => return Ok(monitors),
+        Some(Err(e)) => debug!("Asking xcap for the monitors instead: {}", e),
+        None => {}
+    }
+
+    let monitors = Monitor::all().map_err(|e| format!("Failed to get monitors: {}", e))?;
+    monitors
+        .iter()
+        .map(|monitor| {
+            Ok(MonitorTarget {
+                connector: monitor.name().ok(),
+                x: monitor.x().map_err(|e| e.to_string())?,
+                y: monitor.y().map_err(|e| e.to_string())?,
+                width: monitor.width().map_err(|e| e.to_string())? as i32,
+                height: monitor.height().map_err(|e| e.to_string())? as i32,
+            })
+        })
+        .collect()
+}
+
+/// Monitors from Hyprland's `j/monitors` reply. Their size is given in
+/// pixels, before scaling and rotation, while positions are logical.
+fn parse_hyprland_monitors(json: &str) -> Result<Vec<MonitorTarget>, String> {
+    let monitors: Vec<serde_json::Value> = serde_json::from_str(json)
+        .map_err(|e| format!("Unexpected monitor list from Hyprland: {}", e))?;
+    Ok(monitors
+        .iter()
+        .filter(|monitor| monitor["disabled"] != true)
+        .map(|monitor| {
+            let scale = monitor["scale"]
+                .as_f64()
+                .filter(|s| *s > 0.0)
+                .unwrap_or(1.0);
+            let logical = |key: &str| (monitor[key].as_f64().unwrap_or(0.0) / scale).round() as i32;
+            let (mut width, mut height) = (logical("width"), logical("height"));
+            // Odd transforms turn the monitor on its side
+            if monitor["transform"].as_i64().unwrap_or(0) % 2 == 1 {
+                std::mem::swap(&mut width, &mut height);
+            }
+            MonitorTarget {
+                connector: monitor["name"].as_str().map(str::to_string),
+                x: monitor["x"].as_i64().unwrap_or(0) as i32,
+                y: monitor["y"].as_i64().unwrap_or(0) as i32,
+                width,
+                height,
+            }
+        })
+        .collect())
+}
+
+/// Active outputs from Sway's `GET_OUTPUTS` reply, whose `rect` is logical
+fn parse_sway_outputs(json: &str) -> Result<Vec<MonitorTarget>, String> {
+    let outputs: Vec<serde_json::Value> = serde_json::from_str(json)
+        .map_err(|e| format!("Unexpected output list from Sway: {}", e))?;
+    Ok(outputs
+        .iter()
+        .filter(|output| output["active"] != false)
+        .map(|output| {
+            let rect = &output["rect"];
+            let field = |key: &str| rect[key].as_i64().unwrap_or(0) as i32;
+            MonitorTarget {
+                connector: output["name"].as_str().map(str::to_string),
+                x: field("x"),
+                y: field("y"),
+                width: field("width"),
+                height: field("height"),
+            }
+        })
+        .collect())
+}
+
+/// The top left corner of the desktop the `monitors` make up
+fn desktop_origin<'a>(
+    monitors: impl Iterator<Item = &'a MonitorTarget> + Clone,
+) -> Option<(i32, i32)> {
+    Some((
+        monitors.clone().map(|m| m.x).min()?,
+        monitors.map(|m| m.y).min()?,
+    ))
+}
+
+/// One image of the desktop from the captures of each of its monitors, each
+/// placed at its offset. Monitors captured at a lower scale than the
+/// sharpest one are enlarged to match, and gaps between them are left
+/// transparent.
+fn stitch_monitors(parts: &[(MonitorTarget, image::RgbaImage)]) -> Option<image::RgbaImage> {
+    let (left, top) = desktop_origin(parts.iter().map(|(target, _)| target))?;
+    let right = parts.iter().map(|(m, _)| m.x + m.width).max()?;
+    let bottom = parts.iter().map(|(m, _)| m.y + m.height).max()?;
+    let scale = parts
+        .iter()
+        .filter(|(m, _)| m.width > 0)
+        .map(|(m, image)| image.width() as f64 / m.width as f64)
+        .fold(1.0, f64::max);
+    let scaled = |length: i32| (length as f64 * scale).round() as u32;
+
+    let mut desktop = image::RgbaImage::new(scaled(right - left), scaled(bottom - top));
+    for (target, image) in parts {
+        let (width, height) = (scaled(target.width), scaled(target.height));
+        let x = scaled(target.x - left) as i64;
+        let y = scaled(target.y - top) as i64;
+        if image.dimensions() == (width, height) {
+            image::imageops::replace(&mut desktop, image, x, y);
+        } else {
+            let resized = image::imageops::resize(
+                image,
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            );
+            image::imageops::replace(&mut desktop, &resized, x, y);
+        }
+    }
+    Some(desktop)
+}
+
+/// Capture the whole desktop in one image with the session's tool. Every
+/// backend but xcap takes all monitors at once, so on them this is what
+/// `capture_primary_monitor` gives.
+fn capture_whole_desktop() -> Result<CaptureResult, String> {
+    capture_primary_monitor()
+}
+
+/// Capture the xcap monitor at the same position as `target`
+fn capture_monitor_xcap(target: &MonitorTarget) -> Result<CaptureResult, String> {
+    let monitors = Monitor::all().map_err(|e| format!("Failed to get monitors: {}", e))?;
+    let monitor = find_xcap_monitor(&monitors, target).ok_or("Monitor not found")?;
+    capture_monitor_internal(monitor)
+}
+
+/// The one of xcap's `monitors` at the same position as `target`, or else
+/// with the same connector
+fn find_xcap_monitor<'a>(monitors: &'a [Monitor], target: &MonitorTarget) -> Option<&'a Monitor> {
+    monitors
+        .iter()
+        .find(|m| m.x().ok() == Some(target.x) && m.y().ok() == Some(target.y))
+        .or_else(|| {
+            monitors
+                .iter()
+                .find(|m| target.connector.is_some() && m.name().ok() == target.connector)
+        })
+}
+
 /// Capture screen using xcap (works on X11)
 fn capture_screen_xcap() -> Result<CaptureResult, String> {
     debug!("Using xcap backend for screen capture");
@@ -68,16 +452,17 @@ fn capture_screen_wayland(session: &DesktopSession) -> Result<CaptureResult, Str
     let temp_path = format!("/tmp/screenshot_gnome_screen_{}.png", std::process::id());
 
     let result = match &session.desktop_environment {
-        DesktopEnvironment::Hyprland | DesktopEnvironment::Sway => capture_with_grim(&temp_path),
-        DesktopEnvironment::Gnome => {
-            capture_with_gnome_screenshot(&temp_path).or_else(|_| capture_with_grim(&temp_path))
+        DesktopEnvironment::Hyprland | DesktopEnvironment::Sway => {
+            capture_with_grim(&temp_path, None)
         }
+        DesktopEnvironment::Gnome => capture_with_gnome_screenshot(&temp_path)
+            .or_else(|_| capture_with_grim(&temp_path, None)),
         DesktopEnvironment::Kde => {
-            capture_with_spectacle(&temp_path).or_else(|_| capture_with_grim(&temp_path))
+            capture_with_spectacle(&temp_path).or_else(|_| capture_with_grim(&temp_path, None))
         }
         _ => {
             // Try common tools in order of preference
-            capture_with_grim(&temp_path)
+            capture_with_grim(&temp_path, None)
                 .or_else(|_| capture_with_gnome_screenshot(&temp_path))
                 .or_else(|_| capture_with_spectacle(&temp_path))
         }
@@ -91,10 +476,15 @@ fn capture_screen_wayland(session: &DesktopSession) -> Result<CaptureResult, Str
     result
 }
 
-/// Capture using grim (wlroots-based compositors: Hyprland, Sway, etc.)
-fn capture_with_grim(temp_path: &str) -> Result<CaptureResult, String> {
+/// Capture using grim (wlroots-based compositors: Hyprland, Sway, etc.),
+/// all outputs or only the one named `output_name`
+fn capture_with_grim(temp_path: &str, output_name: Option<&str>) -> Result<CaptureResult, String> {
     debug!("Capturing with grim to {}", temp_path);
-    let output = Command::new("grim")
+    let mut command = Command::new("grim");
+    if let Some(output_name) = output_name {
+        command.args(["-o", output_name]);
+    }
+    let output = command
         .arg(temp_path)
         .output()
         .map_err(|e| format!("Failed to run grim: {}. Is grim installed?", e))?;
@@ -169,7 +559,11 @@ fn capture_with_spectacle(temp_path: &str) -> Result<CaptureResult, String> {
 }
 
 /// Load a pixbuf from a file path
-fn load_pixbuf_from_file(path: &str) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
+pub(super) fn load_pixbuf_from_file(path: &str) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
+    if let Some(image) = super::tonemap::load_high_bit_depth_png(path)? {
+        return image_to_pixbuf(image);
+    }
+
     gtk::gdk_pixbuf::Pixbuf::from_file(path)
         .map_err(|e| format!("Failed to load screenshot image: {}", e))
 }
@@ -189,7 +583,7 @@ fn capture_monitor_internal(monitor: &Monitor) -> Result<CaptureResult, String>
     })
 }
 
-fn image_to_pixbuf(image: image::RgbaImage) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
+pub(super) fn image_to_pixbuf(image: image::RgbaImage) -> Result<gtk::gdk_pixbuf::Pixbuf, String> {
     let width = image.width() as i32;
     let height = image.height() as i32;
     let stride = width * 4;
@@ -214,6 +608,99 @@ fn image_to_pixbuf(image: image::RgbaImage) -> Result<gtk::gdk_pixbuf::Pixbuf, S
 mod tests {
     use super::*;
 
+    fn monitor(x: i32, y: i32, width: i32, height: i32) -> MonitorTarget {
+        MonitorTarget {
+            connector: None,
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_monitor_rect() {
+        // A 1920x1080 monitor with a 1280x1024 one to its right, at scale 2
+        let left = monitor(0, 0, 1920, 1080);
+        let right = monitor(1920, 0, 1280, 1024);
+        let monitors = [left.clone(), right.clone()];
+        let image = (6400, 2160);
+
+        assert_eq!(
+            monitor_rect(&left, &monitors, image),
+            Some((0, 0, 3840, 2160))
+        );
+        assert_eq!(
+            monitor_rect(&right, &monitors, image),
+            Some((3840, 0, 2560, 2048))
+        );
+        assert_eq!(monitor_rect(&left, &[], image), None);
+    }
+
+    #[test]
+    fn test_parse_hyprland_monitors() {
+        let json = r#"[
+            {"name": "eDP-1", "x": 0, "y": 0, "width": 2880, "height": 1800,
+             "scale": 2.0, "transform": 0},
+            {"name": "DP-1", "x": 1440, "y": 0, "width": 1920, "height": 1080,
+             "scale": 1.0, "transform": 1},
+            {"name": "HDMI-A-1", "x": 0, "y": 0, "width": 1920, "height": 1080,
+             "scale": 1.0, "transform": 0, "disabled": true}
+        ]"#;
+        let monitors = parse_hyprland_monitors(json).unwrap();
+        assert_eq!(monitors.len(), 2);
+        assert_eq!(monitors[0].connector.as_deref(), Some("eDP-1"));
+        assert_eq!((monitors[0].width, monitors[0].height), (1440, 900));
+        assert_eq!(
+            (monitors[1].x, monitors[1].width, monitors[1].height),
+            (1440, 1080, 1920)
+        );
+        assert!(parse_hyprland_monitors("ok").is_err());
+    }
+
+    #[test]
+    fn test_parse_sway_outputs() {
+        let json = r#"[
+            {"name": "DP-2", "active": true,
+             "rect": {"x": 1920, "y": 0, "width": 1280, "height": 720}},
+            {"name": "HDMI-A-2", "active": false,
+             "rect": {"x": 0, "y": 0, "width": 0, "height": 0}}
+        ]"#;
+        assert_eq!(
+            parse_sway_outputs(json).unwrap(),
+            [MonitorTarget {
+                connector: Some("DP-2".to_string()),
+                x: 1920,
+                y: 0,
+                width: 1280,
+                height: 720,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_stitch_monitors() {
+        // A 200x100 monitor at scale 2 with a 100x80 one above and right of it
+        let red = image::RgbaImage::from_pixel(400, 200, image::Rgba([255, 0, 0, 255]));
+        let blue = image::RgbaImage::from_pixel(100, 80, image::Rgba([0, 0, 255, 255]));
+        let parts = [
+            (monitor(0, 20, 200, 100), red),
+            (monitor(200, 0, 100, 80), blue),
+        ];
+
+        let desktop = stitch_monitors(&parts).unwrap();
+        assert_eq!(desktop.dimensions(), (600, 240));
+        assert_eq!(desktop.get_pixel(0, 40).0, [255, 0, 0, 255]);
+        assert_eq!(desktop.get_pixel(399, 239).0, [255, 0, 0, 255]);
+        assert_eq!(desktop.get_pixel(400, 0).0, [0, 0, 255, 255]);
+        assert_eq!(desktop.get_pixel(599, 159).0, [0, 0, 255, 255]);
+        // Nothing is below the smaller monitor, nor above the larger one
+        assert_eq!(desktop.get_pixel(500, 200).0, [0, 0, 0, 0]);
+        assert_eq!(desktop.get_pixel(0, 0).0, [0, 0, 0, 0]);
+
+        assert!(stitch_monitors(&[]).is_none());
+    }
+
     #[test]
     fn test_capture_primary_monitor() {
         let session = DesktopSession::detect();
@@ -0,0 +1,148 @@
+//! Client for the i3 IPC protocol that Sway speaks on `$SWAYSOCK`, and i3
+//! itself on `$I3SOCK`, so windows can be listed and focused without
+//! running `swaymsg`.
+//!
+//! Every message is the magic string `i3-ipc`, the payload's length and the
+//! message type, both as native-endian 32-bit integers, then the payload.
+//! Replies have the same layout and a JSON payload.
+
+use log::debug;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const MAGIC: &[u8] = b"i3-ipc";
+
+pub const RUN_COMMAND: u32 = 0;
+pub const GET_WORKSPACES: u32 = 1;
+pub const SUBSCRIBE: u32 = 2;
+pub const GET_OUTPUTS: u32 = 3;
+pub const GET_TREE: u32 = 4;
+
+/// Magic string and the two integers after it
+pub const HEADER_LENGTH: usize = 14;
+
+/// Longest wait for a reply
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub fn socket_path() -> Option<PathBuf> {
+    std::env::var_os("SWAYSOCK")
+        .or_else(|| std::env::var_os("I3SOCK"))
+        .map(PathBuf::from)
+}
+
+pub fn message(message_type: u32, payload: &str) -> Vec<u8> {
+    let mut message = MAGIC.to_vec();
+    message.extend_from_slice(&(payload.len() as u32).to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    message.extend_from_slice(payload.as_bytes());
+    message
+}
+
+/// Type and payload of the message whose header is `header`, reading the
+/// payload from `stream`
+pub fn read_payload(
+    header: &[u8; HEADER_LENGTH],
+    stream: &mut impl Read,
+) -> Result<(u32, Vec<u8>), String> {
+    if &header[..6] != MAGIC {
+        return Err("Unexpected reply from the window manager's IPC".to_string());
+    }
+    let length = u32::from_ne_bytes([header[6], header[7], header[8], header[9]]) as usize;
+    let message_type = u32::from_ne_bytes([header[10], header[11], header[12], header[13]]);
+    let mut payload = vec![0u8; length];
+    stream
+        .read_exact(&mut payload)
+        .map_err(|e| format!("Failed to read from the window manager's IPC: {}", e))?;
+    Ok((message_type, payload))
+}
+
+/// A connection to the running Sway or i3
+pub struct SwayIpc {
+    stream: UnixStream,
+}
+
+impl SwayIpc {
+    pub fn connect() -> Result<Self, String> {
+        let path = socket_path().ok_or("Neither SWAYSOCK nor I3SOCK is set")?;
+        let stream = UnixStream::connect(&path)
+            .map_err(|e| format!("Failed to connect to {}: {}", path.display(), e))?;
+        stream
+            .set_read_timeout(Some(REPLY_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        Ok(Self { stream })
+    }
+
+    /// The layout tree, from the root through outputs and workspaces down to
+    /// the windows, as JSON
+    pub fn tree(&mut self) -> Result<String, String> {
+        self.request(GET_TREE, "")
+    }
+
+    /// The workspaces, as a JSON array
+    pub fn workspaces(&mut self) -> Result<String, String> {
+        self.request(GET_WORKSPACES, "")
+    }
+
+    /// The outputs and where they lie on the desktop, as a JSON array
+    pub fn outputs(&mut self) -> Result<String, String> {
+        self.request(GET_OUTPUTS, "")
+    }
+
+    /// Run `command` as `swaymsg` would, failing with the error of the first
+    /// part of it that failed
+    pub fn run_command(&mut self, command: &str) -> Result<(), String> {
+        let reply = self.request(RUN_COMMAND, command)?;
+        let results: Vec<serde_json::Value> = serde_json::from_str(&reply)
+            .map_err(|e| format!("Unexpected reply to {:?}: {}", command, e))?;
+        match results.iter().find(|result| result["success"] != true) {
+            Some(failed) => Err(failed["error"]
+                .as_str()
+                .unwrap_or("the command failed")
+                .to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn request(&mut self, message_type: u32, payload: &str) -> Result<String, String> {
+        debug!("Sway IPC request {}: {:?}", message_type, payload);
+        self.stream
+            .write_all(&message(message_type, payload))
+            .map_err(|e| format!("Failed to send to the window manager's IPC: {}", e))?;
+
+        let mut header = [0u8; HEADER_LENGTH];
+        self.stream
+            .read_exact(&mut header)
+            .map_err(|e| format!("Failed to read from the window manager's IPC: {}", e))?;
+        let (reply_type, reply) = read_payload(&header, &mut self.stream)?;
+        if reply_type != message_type {
+            return Err(format!(
+                "Expected a reply of type {}, got {}",
+                message_type, reply_type
+            ));
+        }
+        Ok(String::from_utf8_lossy(&reply).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_layout() {
+        let message = message(SUBSCRIBE, r#"["window"]"#);
+        assert_eq!(&message[..6], b"i3-ipc");
+        assert_eq!(u32::from_ne_bytes(message[6..10].try_into().unwrap()), 10);
+        assert_eq!(u32::from_ne_bytes(message[10..14].try_into().unwrap()), 2);
+        assert_eq!(&message[14..], br#"["window"]"#);
+
+        let mut header = [0u8; HEADER_LENGTH];
+        header.copy_from_slice(&message[..HEADER_LENGTH]);
+        let (message_type, payload) = read_payload(&header, &mut &message[14..]).unwrap();
+        assert_eq!(message_type, SUBSCRIBE);
+        assert_eq!(payload, br#"["window"]"#);
+        assert!(read_payload(&[0; HEADER_LENGTH], &mut &message[..]).is_err());
+    }
+}
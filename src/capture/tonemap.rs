@@ -0,0 +1,307 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::io::BufReader;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How HDR luminance above SDR white is squeezed into the 8-bit output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TonemapOperator {
+    /// Hard clip at SDR white; keeps SDR content exact
+    #[default]
+    Clip,
+
+    /// Extended Reinhard, rolling highlights off towards the image peak
+    Reinhard,
+
+    /// ACES filmic curve (Narkowicz fit), more contrast in the highlights
+    Aces,
+}
+
+impl TonemapOperator {
+    pub const ALL: [TonemapOperator; 3] = [Self::Clip, Self::Reinhard, Self::Aces];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Clip => "clip",
+            Self::Reinhard => "reinhard",
+            Self::Aces => "aces",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Clip => "Clip",
+            Self::Reinhard => "Reinhard",
+            Self::Aces => "ACES Filmic",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.id() == id)
+    }
+
+    fn apply(&self, value: f32, white: f32) -> f32 {
+        let v = value.max(0.0);
+        match self {
+            Self::Clip => v.min(1.0),
+            Self::Reinhard => (v * (1.0 + v / (white * white)) / (1.0 + v)).min(1.0),
+            Self::Aces => {
+                ((v * (2.51 * v + 0.03)) / (v * (2.43 * v + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+static OPERATOR: AtomicU8 = AtomicU8::new(0);
+
+/// Select the operator used by all capture backends from now on
+pub fn set_tonemap_operator(operator: TonemapOperator) {
+    let index = TonemapOperator::ALL
+        .iter()
+        .position(|op| *op == operator)
+        .unwrap_or(0);
+    OPERATOR.store(index as u8, Ordering::Relaxed);
+}
+
+pub fn tonemap_operator() -> TonemapOperator {
+    TonemapOperator::ALL
+        .get(OPERATOR.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or_default()
+}
+
+/// Transfer characteristics from the PNG cICP chunk (ITU-T H.273 table 3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Transfer {
+    Srgb,
+    Linear,
+    Pq,
+    Hlg,
+}
+
+impl Transfer {
+    fn from_cicp(code: u8) -> Self {
+        match code {
+            8 => Self::Linear,
+            16 => Self::Pq,
+            18 => Self::Hlg,
+            _ => Self::Srgb,
+        }
+    }
+}
+
+/// Reference white of SDR content inside an HDR signal (ITU-R BT.2408)
+const SDR_WHITE_NITS: f32 = 203.0;
+
+/// Load a PNG with more than 8 bits per channel and tonemap it to 8-bit sRGB.
+///
+/// Returns `Ok(None)` for regular 8-bit files (and non-PNGs), which the caller
+/// should load as usual; gdk-pixbuf would otherwise truncate the extra bits
+/// and show PQ/HLG content washed out.
+pub fn load_high_bit_depth_png(path: &str) -> Result<Option<image::RgbaImage>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND);
+
+    let mut reader = match decoder.read_info() {
+        Ok(reader) => reader,
+        Err(_) => return Ok(None),
+    };
+
+    if reader.info().bit_depth != png::BitDepth::Sixteen {
+        return Ok(None);
+    }
+
+    let (transfer, bt2020) = match reader.info().coding_independent_code_points {
+        Some(cicp) => (
+            Transfer::from_cicp(cicp.transfer_function),
+            cicp.color_primaries == 9,
+        ),
+        None => (Transfer::Srgb, false),
+    };
+
+    let size = reader
+        .output_buffer_size()
+        .ok_or("PNG image is too large")?;
+    let mut buf = vec![0; size];
+    let info = reader
+        .next_frame(&mut buf)
+        .map_err(|e| format!("Failed to decode PNG: {}", e))?;
+    buf.truncate(info.buffer_size());
+
+    let channels = info.color_type.samples();
+    let samples: Vec<f32> = buf
+        .chunks_exact(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / 65535.0)
+        .collect();
+
+    let operator = tonemap_operator();
+    debug!(
+        "Tonemapping 16-bit capture ({:?}, bt2020={}) with {:?}",
+        transfer, bt2020, operator
+    );
+
+    Ok(Some(tonemap_samples(
+        &samples,
+        info.width,
+        info.height,
+        channels,
+        transfer,
+        bt2020,
+        operator,
+    )))
+}
+
+fn tonemap_samples(
+    samples: &[f32],
+    width: u32,
+    height: u32,
+    channels: usize,
+    transfer: Transfer,
+    bt2020: bool,
+    operator: TonemapOperator,
+) -> image::RgbaImage {
+    let pixels: Vec<([f32; 3], f32)> = samples
+        .chunks_exact(channels)
+        .map(|p| {
+            let (rgb, alpha) = match channels {
+                1 => ([p[0]; 3], 1.0),
+                2 => ([p[0]; 3], p[1]),
+                3 => ([p[0], p[1], p[2]], 1.0),
+                _ => ([p[0], p[1], p[2]], p[3]),
+            };
+            (to_linear(rgb, transfer, bt2020), alpha)
+        })
+        .collect();
+
+    // Reinhard rolls off towards the brightest value actually present
+    let white = pixels
+        .iter()
+        .flat_map(|(rgb, _)| rgb.iter().copied())
+        .fold(1.0f32, f32::max);
+
+    let mut out = image::RgbaImage::new(width, height);
+    for (dst, (rgb, alpha)) in out.pixels_mut().zip(pixels) {
+        let encode = |v: f32| (srgb_oetf(operator.apply(v, white)) * 255.0).round() as u8;
+        dst.0 = [
+            encode(rgb[0]),
+            encode(rgb[1]),
+            encode(rgb[2]),
+            (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ];
+    }
+    out
+}
+
+/// Convert an encoded sample to linear light, where 1.0 is SDR white
+fn to_linear(rgb: [f32; 3], transfer: Transfer, bt2020: bool) -> [f32; 3] {
+    let linear = match transfer {
+        Transfer::Srgb => rgb.map(srgb_eotf),
+        Transfer::Linear => rgb,
+        Transfer::Pq => rgb.map(|v| pq_eotf(v) / SDR_WHITE_NITS),
+        Transfer::Hlg => {
+            let scene = rgb.map(hlg_inverse_oetf);
+            let luma = 0.2627 * scene[0] + 0.6780 * scene[1] + 0.0593 * scene[2];
+            // BT.2100 OOTF for a 1000 nit display
+            let gain = 1000.0 * luma.max(1e-6).powf(0.2) / SDR_WHITE_NITS;
+            scene.map(|v| v * gain)
+        }
+    };
+
+    if bt2020 {
+        bt2020_to_bt709(linear)
+    } else {
+        linear
+    }
+}
+
+fn srgb_eotf(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_oetf(v: f32) -> f32 {
+    let v = v.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// SMPTE ST 2084 EOTF, returning absolute luminance in nits
+fn pq_eotf(v: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+
+    let p = v.max(0.0).powf(1.0 / M2);
+    let num = (p - C1).max(0.0);
+    let den = C2 - C3 * p;
+    10000.0 * (num / den).powf(1.0 / M1)
+}
+
+fn hlg_inverse_oetf(v: f32) -> f32 {
+    const A: f32 = 0.178_832_77;
+    const B: f32 = 0.284_668_92;
+    const C: f32 = 0.559_910_7;
+
+    if v <= 0.5 {
+        v * v / 3.0
+    } else {
+        (((v - C) / A).exp() + B) / 12.0
+    }
+}
+
+fn bt2020_to_bt709(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb;
+    [
+        1.6605 * r - 0.5876 * g - 0.0728 * b,
+        -0.1246 * r + 1.1329 * g - 0.0083 * b,
+        -0.0182 * r - 0.1006 * g + 1.1187 * b,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sdr_content_is_preserved() {
+        let samples = [0.0, 0.5, 1.0, 1.0];
+        let image = tonemap_samples(
+            &samples,
+            1,
+            1,
+            4,
+            Transfer::Srgb,
+            false,
+            TonemapOperator::Clip,
+        );
+        assert_eq!(image.get_pixel(0, 0).0, [0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn test_pq_highlights_are_compressed() {
+        // PQ code value for roughly 1000 nits, well above SDR white
+        let samples = [0.75, 0.75, 0.75];
+        for operator in TonemapOperator::ALL {
+            let image = tonemap_samples(&samples, 1, 1, 3, Transfer::Pq, false, operator);
+            let [r, g, b, a] = image.get_pixel(0, 0).0;
+            assert!(r > 200 && r == g && g == b && a == 255, "{:?}", operator);
+        }
+    }
+
+    #[test]
+    fn test_operator_ids_roundtrip() {
+        for operator in TonemapOperator::ALL {
+            assert_eq!(TonemapOperator::from_id(operator.id()), Some(operator));
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! External programs the Wayland screen capture relies on.
+//!
+//! Checked before capturing, so a missing tool can be reported by name, with
+//! the screenshot portal offered instead, rather than as a failed command.
+
+use gtk4::glib;
+
+use super::desktop::{DesktopEnvironment, DesktopSession, DisplayServer, ScreenCaptureBackend};
+use super::mock::mock_backend_enabled;
+
+/// Tools `capture_primary_monitor` tries on Wayland for `environment`, in order
+pub fn screen_capture_tools(environment: &DesktopEnvironment) -> &'static [&'static str] {
+    match environment {
+        DesktopEnvironment::Hyprland | DesktopEnvironment::Sway => &["grim"],
+        DesktopEnvironment::Gnome => &["gnome-screenshot", "grim"],
+        DesktopEnvironment::Kde => &["spectacle", "grim"],
+        _ => &["grim", "gnome-screenshot", "spectacle"],
+    }
+}
+
+/// What a capture tool is for, shown next to its name
+pub fn tool_description(tool: &str) -> &'static str {
+    match tool {
+        "grim" => "wlroots compositors such as Sway and Hyprland",
+        "gnome-screenshot" => "GNOME",
+        "spectacle" => "KDE Plasma",
+        _ => "",
+    }
+}
+
+/// The tools that could take the screenshot in this session when none of
+/// them is installed, `None` when capturing can go ahead
+pub fn missing_screen_capture_tools() -> Option<&'static [&'static str]> {
+    if mock_backend_enabled() {
+        return None;
+    }
+
+    let session = DesktopSession::detect();
+    if session.screen_capture_backend() == ScreenCaptureBackend::Portal {
+        return None;
+    }
+    missing_screen_capture_tools_for(&session)
+}
+
+/// The tools `session` would need when none of them is installed, whichever
+/// backend is selected
+pub(super) fn missing_screen_capture_tools_for(
+    session: &DesktopSession,
+) -> Option<&'static [&'static str]> {
+    // X11 captures in-process, and an unknown session falls back to that
+    if session.display_server != DisplayServer::Wayland {
+        return None;
+    }
+
+    let tools = screen_capture_tools(&session.desktop_environment);
+    missing_tools(tools, |tool| glib::find_program_in_path(tool).is_some())
+}
+
+/// Whether the screenshot portal can be asked instead, which needs `gdbus`
+pub fn portal_available() -> bool {
+    glib::find_program_in_path("gdbus").is_some()
+}
+
+fn missing_tools(
+    tools: &'static [&'static str],
+    installed: impl Fn(&str) -> bool,
+) -> Option<&'static [&'static str]> {
+    if tools.iter().any(|tool| installed(tool)) {
+        None
+    } else {
+        Some(tools)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_tools() {
+        let tools = screen_capture_tools(&DesktopEnvironment::Kde);
+        assert_eq!(missing_tools(tools, |tool| tool == "grim"), None);
+        assert_eq!(missing_tools(tools, |_| false), Some(tools));
+    }
+}
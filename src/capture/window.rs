@@ -1,5 +1,5 @@
 use gtk4::gdk_pixbuf::Pixbuf;
-use log::{debug, info};
+use log::{debug, info, warn};
 
 use super::desktop::DesktopSession;
 use super::window_backends;
@@ -29,6 +29,9 @@ pub struct WindowInfo {
     pub is_maximized: bool,
 
     pub is_focused: bool,
+
+    /// Hyprland client address, as its dispatchers expect it
+    pub address: Option<String>,
 }
 
 impl WindowInfo {
@@ -107,15 +110,25 @@ pub fn list_capturable_windows() -> Result<Vec<WindowInfo>, WindowCaptureError>
     let windows = window_backends::list_windows_for_session(&session)?;
     debug!("Found {} windows in total", windows.len());
 
-    let capturable: Vec<WindowInfo> = windows.into_iter().filter(|w| !w.is_minimized).collect();
+    let minimized_ok = session.captures_minimized_windows();
+    let capturable: Vec<WindowInfo> = windows
+        .into_iter()
+        .filter(|w| minimized_ok || !w.is_minimized)
+        .collect();
     debug!(
-        "{} windows are capturable (not minimized)",
-        capturable.len()
+        "{} windows are capturable (minimized included: {})",
+        capturable.len(),
+        minimized_ok
     );
 
     Ok(capturable)
 }
 
+/// Capture a window picked from an earlier listing.
+///
+/// The listing may be stale by now: the window could have moved, resized or
+/// closed. The list is refreshed first so geometry-based backends grab the
+/// right region, and a failed capture refreshes and retries once more.
 pub fn capture_window(window_info: &WindowInfo) -> Result<WindowCaptureResult, WindowCaptureError> {
     let session = DesktopSession::detect();
     info!(
@@ -123,7 +136,261 @@ pub fn capture_window(window_info: &WindowInfo) -> Result<WindowCaptureResult, W
         window_info.display_label(),
         session.window_list_backend()
     );
-    debug!("Window details: {:?}", window_info);
 
-    window_backends::capture_window_for_session(&session, window_info)
+    let current = refresh_window_info(&session, window_info)?;
+    debug!("Window details: {:?}", current);
+
+    match window_backends::capture_window_for_session(&session, &current) {
+        Ok(result) => Ok(result),
+        Err(WindowCaptureError::WindowMinimized) => Err(WindowCaptureError::WindowMinimized),
+        Err(e) => {
+            warn!(
+                "Capturing '{}' failed ({}), refreshing window list and retrying",
+                current.display_label(),
+                e
+            );
+            let retry = refresh_window_info(&session, &current)?;
+            window_backends::capture_window_for_session(&session, &retry)
+        }
+    }
+}
+
+/// The window under a point, in the same coordinates as the window geometry.
+/// Minimized windows are not on screen and never match.
+///
+/// Most Wayland backends report no stacking order, so the focused window wins
+/// first, then the higher `z`, then the smaller window (a dialog over its parent).
+pub fn window_at(windows: &[WindowInfo], x: i32, y: i32) -> Option<&WindowInfo> {
+    windows
+        .iter()
+        .filter(|w| !w.is_minimized)
+        .filter(|w| x >= w.x && y >= w.y && x < w.x + w.width as i32 && y < w.y + w.height as i32)
+        .max_by_key(|w| {
+            (
+                w.is_focused,
+                w.z,
+                std::cmp::Reverse(w.width as u64 * w.height as u64),
+            )
+        })
+}
+
+/// Windows stacked above `target` that cover part of it. A capture cropped
+/// from the screen by geometry shows them instead of the target.
+///
+/// Without stacking data only the focused window counts as above the others,
+/// so unfocused windows overlapping each other are not reported.
+pub fn occluding_windows<'a>(
+    windows: &'a [WindowInfo],
+    target: &WindowInfo,
+) -> Vec<&'a WindowInfo> {
+    windows
+        .iter()
+        .filter(|w| !w.is_minimized && !(w.id == target.id && w.pid == target.pid))
+        .filter(|w| (w.is_focused, w.z) > (target.is_focused, target.z))
+        .filter(|w| {
+            let left = w.x.max(target.x);
+            let top = w.y.max(target.y);
+            let right = (w.x + w.width as i32).min(target.x + target.width as i32);
+            let bottom = (w.y + w.height as i32).min(target.y + target.height as i32);
+            left < right && top < bottom
+        })
+        .collect()
+}
+
+/// Look the window up again to pick up its current geometry
+fn refresh_window_info(
+    session: &DesktopSession,
+    window_info: &WindowInfo,
+) -> Result<WindowInfo, WindowCaptureError> {
+    let windows = match window_backends::list_windows_for_session(session) {
+        Ok(windows) => windows,
+        Err(e) => {
+            warn!(
+                "Could not refresh window list, using cached geometry: {}",
+                e
+            );
+            return Ok(window_info.clone());
+        }
+    };
+
+    let current = find_matching_window(&windows, window_info)
+        .cloned()
+        .ok_or(WindowCaptureError::WindowNotFound)?;
+
+    if current.is_minimized && !session.captures_minimized_windows() {
+        return Err(WindowCaptureError::WindowMinimized);
+    }
+
+    let moved = current.x != window_info.x
+        || current.y != window_info.y
+        || current.width != window_info.width
+        || current.height != window_info.height;
+    if moved {
+        debug!(
+            "Window '{}' moved to {},{} {}x{}",
+            current.display_label(),
+            current.x,
+            current.y,
+            current.width,
+            current.height
+        );
+    }
+
+    Ok(current)
+}
+
+/// The window picked from an earlier listing as it is now, such as to follow
+/// it while it is recorded
+pub fn locate_window(window_info: &WindowInfo) -> Result<WindowInfo, WindowCaptureError> {
+    let session = DesktopSession::detect();
+    let windows = window_backends::list_windows_for_session(&session)?;
+    find_matching_window(&windows, window_info)
+        .cloned()
+        .ok_or(WindowCaptureError::WindowNotFound)
+}
+
+/// Find the same window in a fresh listing.
+///
+/// Ids are tried first, then pid and title, then app and title where either
+/// pid is unknown; as a last resort a pid with a single window matches even
+/// if its title changed.
+fn find_matching_window<'a>(
+    windows: &'a [WindowInfo],
+    target: &WindowInfo,
+) -> Option<&'a WindowInfo> {
+    let same_pid = |w: &WindowInfo| target.pid == 0 || w.pid == 0 || w.pid == target.pid;
+
+    if target.id != 0 {
+        if let Some(w) = windows.iter().find(|w| w.id == target.id && same_pid(w)) {
+            return Some(w);
+        }
+    }
+
+    if let Some(w) = windows
+        .iter()
+        .find(|w| target.pid != 0 && w.pid == target.pid && w.title == target.title)
+    {
+        return Some(w);
+    }
+
+    if let Some(w) = windows
+        .iter()
+        .find(|w| w.app_name == target.app_name && w.title == target.title && same_pid(w))
+    {
+        return Some(w);
+    }
+
+    let mut by_pid = windows
+        .iter()
+        .filter(|w| target.pid != 0 && w.pid == target.pid && w.app_name == target.app_name);
+    match (by_pid.next(), by_pid.next()) {
+        (Some(w), None) => Some(w),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, pid: u32, title: &str, x: i32) -> WindowInfo {
+        WindowInfo {
+            id,
+            pid,
+            app_name: "firefox".to_string(),
+            title: title.to_string(),
+            x,
+            y: 0,
+            z: 0,
+            width: 800,
+            height: 600,
+            is_minimized: false,
+            is_maximized: false,
+            is_focused: false,
+            address: None,
+        }
+    }
+
+    #[test]
+    fn test_match_picks_up_new_geometry() {
+        let stale = window(7, 100, "Docs", 0);
+        let fresh = vec![window(3, 100, "Mail", 50), window(7, 100, "Docs", 400)];
+
+        let found = find_matching_window(&fresh, &stale).unwrap();
+        assert_eq!(found.x, 400);
+    }
+
+    #[test]
+    fn test_match_falls_back_to_title_and_pid() {
+        let stale = window(7, 100, "Docs", 0);
+        let fresh = vec![window(9, 100, "Docs", 10), window(7, 200, "Other", 0)];
+
+        assert_eq!(find_matching_window(&fresh, &stale).unwrap().id, 9);
+    }
+
+    #[test]
+    fn test_window_at_prefers_focused_then_smaller() {
+        let mut parent = window(1, 100, "Editor", 0);
+        let mut dialog = window(2, 100, "Save As", 100);
+        dialog.width = 300;
+        dialog.height = 200;
+
+        let windows = vec![parent.clone(), dialog.clone()];
+        assert_eq!(window_at(&windows, 150, 50).unwrap().id, 2);
+        assert_eq!(window_at(&windows, 10, 10).unwrap().id, 1);
+        assert!(window_at(&windows, 900, 10).is_none());
+
+        parent.is_focused = true;
+        dialog.is_focused = false;
+        assert_eq!(window_at(&[parent.clone(), dialog], 150, 50).unwrap().id, 1);
+
+        parent.is_minimized = true;
+        assert!(window_at(&[parent], 150, 50).is_none());
+    }
+
+    #[test]
+    fn test_occluding_windows() {
+        let target = window(1, 100, "Docs", 0);
+        let mut over = window(2, 200, "Chat", 700);
+        over.z = 1;
+        let mut beside = window(3, 300, "Mail", 900);
+        beside.z = 2;
+        let below = window(4, 400, "Music", 100);
+
+        let windows = vec![target.clone(), over, beside, below];
+        let ids: Vec<u32> = occluding_windows(&windows, &target)
+            .iter()
+            .map(|w| w.id)
+            .collect();
+        assert_eq!(ids, vec![2]);
+
+        // Without stacking data only the focused window is above
+        let mut focused = window(5, 500, "Term", 400);
+        focused.is_focused = true;
+        let windows = vec![target.clone(), window(6, 600, "Notes", 200), focused];
+        assert_eq!(occluding_windows(&windows, &target).len(), 1);
+        assert!(occluding_windows(&windows, &windows[2]).is_empty());
+    }
+
+    #[test]
+    fn test_closed_window_is_not_matched() {
+        let stale = window(7, 100, "Docs", 0);
+        let fresh = vec![window(3, 200, "Mail", 0), window(4, 300, "Chat", 0)];
+
+        assert!(find_matching_window(&fresh, &stale).is_none());
+    }
+
+    #[test]
+    fn test_same_title_from_another_process_is_not_matched() {
+        let stale = window(7, 100, "Docs", 0);
+        let others = vec![window(8, 200, "Docs", 0), window(9, 300, "Docs", 50)];
+        assert!(find_matching_window(&others, &stale).is_none());
+
+        let fresh = vec![window(8, 200, "Docs", 0), window(9, 100, "Docs", 50)];
+        assert_eq!(find_matching_window(&fresh, &stale).unwrap().id, 9);
+
+        // Without a pid the title is all there is to go on
+        let unknown = window(7, 0, "Docs", 0);
+        assert_eq!(find_matching_window(&others, &unknown).unwrap().id, 8);
+    }
 }
@@ -1,14 +1,32 @@
 use super::desktop::{DesktopSession, WindowListBackend};
+use super::foreign_toplevel::{ForeignToplevels, Toplevel};
+use super::gnome_shell::GnomeShell;
+use super::hyprland::Hyprland;
+use super::mock::MockBackend;
+use super::sway::SwayIpc;
+use super::tools::portal_available;
 use super::window::{WindowCaptureError, WindowCaptureResult, WindowInfo};
+use super::x11::X11;
 use gtk4::gdk_pixbuf::{Colorspace, Pixbuf};
 use gtk4::glib;
 use log::{debug, warn};
+use std::path::Path;
 use std::process::Command;
+use std::time::Duration;
 
 pub type WindowListResult = Result<Vec<WindowInfo>, WindowCaptureError>;
 
 pub type WindowCaptureBackendResult = Result<WindowCaptureResult, WindowCaptureError>;
 
+/// Time for Hyprland to switch workspace and redraw after focusing a client
+const HYPRLAND_RAISE_SETTLE: Duration = Duration::from_millis(150);
+
+/// Time for Sway to switch workspace and redraw after focusing a container
+const SWAY_FOCUS_SETTLE: Duration = Duration::from_millis(150);
+
+/// Time for a wlroots compositor to raise and redraw an activated window
+const FOREIGN_TOPLEVEL_SETTLE: Duration = Duration::from_millis(150);
+
 pub fn list_windows_for_session(session: &DesktopSession) -> WindowListResult {
     let backend = session.window_list_backend();
     list_windows_with_backend(backend)
@@ -21,7 +39,10 @@ pub fn list_windows_with_backend(backend: WindowListBackend) -> WindowListResult
         WindowListBackend::Sway => list_windows_sway(),
         WindowListBackend::GnomeWayland => list_windows_gnome_wayland(),
         WindowListBackend::KdeWayland => list_windows_kde_wayland(),
-        WindowListBackend::X11 | WindowListBackend::Xcap => list_windows_xcap(),
+        WindowListBackend::ForeignToplevel => list_windows_foreign_toplevel(),
+        WindowListBackend::X11 => list_windows_x11(),
+        WindowListBackend::Xcap => list_windows_xcap(),
+        WindowListBackend::Mock => MockBackend.list_windows(),
     }
 }
 
@@ -47,39 +68,40 @@ pub fn capture_window_with_backend(
         WindowListBackend::Sway => capture_window_sway(window_info),
         WindowListBackend::GnomeWayland => capture_window_gnome_wayland(window_info),
         WindowListBackend::KdeWayland => capture_window_kde_wayland(window_info),
-        WindowListBackend::X11 | WindowListBackend::Xcap => capture_window_xcap(window_info),
+        WindowListBackend::ForeignToplevel => capture_window_foreign_toplevel(window_info),
+        WindowListBackend::X11 => capture_window_x11(window_info),
+        WindowListBackend::Xcap => capture_window_xcap(window_info),
+        WindowListBackend::Mock => MockBackend.capture_window(window_info),
     }
 }
 
 fn list_windows_hyprland() -> WindowListResult {
-    let output = Command::new("hyprctl")
-        .args(["clients", "-j"])
-        .output()
-        .map_err(|e| {
-            WindowCaptureError::EnumerationFailed(format!("Failed to run hyprctl: {}", e))
-        })?;
-
-    if !output.status.success() {
-        return Err(WindowCaptureError::EnumerationFailed(
-            "hyprctl returned non-zero exit code".to_string(),
-        ));
-    }
-
-    let json_str = String::from_utf8_lossy(&output.stdout);
+    let json_str = Hyprland::connect()
+        .and_then(|hyprland| hyprland.clients())
+        .map_err(WindowCaptureError::EnumerationFailed)?;
     parse_hyprland_json(&json_str)
 }
 
 fn parse_hyprland_json(json_str: &str) -> WindowListResult {
-    let mut windows = Vec::new();
+    let objects = json_array_objects(json_str).ok_or_else(|| {
+        WindowCaptureError::EnumerationFailed("Invalid JSON from Hyprland".to_string())
+    })?;
 
+    Ok(objects
+        .into_iter()
+        .filter_map(parse_hyprland_client_object)
+        .collect())
+}
+
+/// The top-level objects of a JSON array, as text
+fn json_array_objects(json_str: &str) -> Option<Vec<&str>> {
     let trimmed = json_str.trim();
     if !trimmed.starts_with('[') || !trimmed.ends_with(']') {
-        return Err(WindowCaptureError::EnumerationFailed(
-            "Invalid JSON from hyprctl".to_string(),
-        ));
+        return None;
     }
 
     let content = &trimmed[1..trimmed.len() - 1];
+    let mut objects = Vec::new();
     let mut depth = 0;
     let mut start = 0;
     let mut in_string = false;
@@ -103,22 +125,20 @@ fn parse_hyprland_json(json_str: &str) -> WindowListResult {
             '}' if !in_string => {
                 depth -= 1;
                 if depth == 0 {
-                    let obj_str = &content[start..=i];
-                    if let Some(info) = parse_hyprland_client_object(obj_str) {
-                        windows.push(info);
-                    }
+                    objects.push(&content[start..=i]);
                 }
             }
             _ => {}
         }
     }
 
-    Ok(windows)
+    Some(objects)
 }
 
 fn parse_hyprland_client_object(obj_str: &str) -> Option<WindowInfo> {
     let address = extract_json_hex_value(obj_str, "address")?;
-    let id = u32::from_str_radix(address.trim_start_matches("0x"), 16).unwrap_or(0);
+    // Addresses are pointers; the low bits are enough to tell clients apart
+    let id = u64::from_str_radix(address.trim_start_matches("0x"), 16).unwrap_or(0) as u32;
 
     let pid = extract_json_number(obj_str, "pid").unwrap_or(0);
     let title = extract_json_string(obj_str, "title").unwrap_or_default();
@@ -148,10 +168,84 @@ fn parse_hyprland_client_object(obj_str: &str) -> Option<WindowInfo> {
         is_minimized,
         is_maximized,
         is_focused,
+        address: Some(address),
     })
 }
 
+/// Capture a Hyprland client by its address.
+///
+/// grim can only grab a region of the screen, so the client is focused first,
+/// which switches to its workspace, and floating clients are raised, so
+/// nothing else covers it. Focus then goes back to where it was.
 fn capture_window_hyprland(window_info: &WindowInfo) -> WindowCaptureBackendResult {
+    let address = match window_info.address {
+        Some(ref address) => address,
+        None => return capture_region_with_grim(window_info),
+    };
+    let hyprland = match Hyprland::connect() {
+        Ok(hyprland) => hyprland,
+        Err(e) => {
+            warn!("Capturing {} without raising it: {}", address, e);
+            return capture_region_with_grim(window_info);
+        }
+    };
+
+    let previous = hyprland_active_address(&hyprland);
+    let raised = raise_hyprland_client(&hyprland, address);
+    if raised {
+        std::thread::sleep(HYPRLAND_RAISE_SETTLE);
+    }
+
+    // Focusing can move the client, e.g. out of a hidden special workspace
+    let target = if raised {
+        list_windows_hyprland()
+            .ok()
+            .and_then(|windows| {
+                windows
+                    .into_iter()
+                    .find(|w| w.address.as_deref() == Some(address.as_str()))
+            })
+            .unwrap_or_else(|| window_info.clone())
+    } else {
+        window_info.clone()
+    };
+
+    let result = capture_region_with_grim(&target);
+
+    if raised {
+        if let Some(previous) = previous.filter(|previous| previous != address) {
+            if let Err(e) = hyprland.dispatch("focuswindow", &format!("address:{}", previous)) {
+                warn!("Could not give focus back to {}: {}", previous, e);
+            }
+        }
+    }
+    result
+}
+
+/// Focus the client and put it above other floating clients. Returns whether
+/// it could be focused.
+fn raise_hyprland_client(hyprland: &Hyprland, address: &str) -> bool {
+    let selector = format!("address:{}", address);
+    if let Err(e) = hyprland.dispatch("focuswindow", &selector) {
+        warn!("Could not focus {} before capturing it: {}", address, e);
+        return false;
+    }
+    // Only matters for floating clients, and older Hyprland lacks it
+    if let Err(e) = hyprland.dispatch("alterzorder", &format!("top,{}", selector)) {
+        debug!("Could not raise {}: {}", address, e);
+    }
+    true
+}
+
+/// Address of the focused client
+fn hyprland_active_address(hyprland: &Hyprland) -> Option<String> {
+    let json = hyprland.active_window().ok()?;
+    extract_json_hex_value(&json, "address")
+}
+
+/// Grab the window's rectangle of the screen with grim, including anything
+/// that covers it
+fn capture_region_with_grim(window_info: &WindowInfo) -> WindowCaptureBackendResult {
     let geometry = format!(
         "{},{} {}x{}",
         window_info.x, window_info.y, window_info.width, window_info.height
@@ -301,134 +395,109 @@ fn extract_json_size(json: &str, key: &str) -> Option<(u32, u32)> {
 }
 
 fn list_windows_sway() -> WindowListResult {
-    let output = Command::new("swaymsg")
-        .args(["-t", "get_tree"])
-        .output()
-        .map_err(|e| {
-            WindowCaptureError::EnumerationFailed(format!("Failed to run swaymsg: {}", e))
-        })?;
-
-    if !output.status.success() {
-        return Err(WindowCaptureError::EnumerationFailed(
-            "swaymsg returned non-zero exit code".to_string(),
-        ));
-    }
-
-    let json_str = String::from_utf8_lossy(&output.stdout);
-    parse_sway_tree(&json_str)
+    let tree = SwayIpc::connect()
+        .and_then(|mut sway| sway.tree())
+        .map_err(WindowCaptureError::EnumerationFailed)?;
+    parse_sway_tree(&tree)
 }
 
 fn parse_sway_tree(json_str: &str) -> WindowListResult {
+    let root: serde_json::Value = serde_json::from_str(json_str).map_err(|e| {
+        WindowCaptureError::EnumerationFailed(format!("Invalid layout tree from Sway: {}", e))
+    })?;
     let mut windows = Vec::new();
-    extract_sway_windows(json_str, &mut windows);
+    collect_sway_windows(&root, true, false, &mut windows);
     Ok(windows)
 }
 
-fn extract_sway_windows(json_str: &str, windows: &mut Vec<WindowInfo>) {
-    let mut search_pos = 0;
-
-    while let Some(start) = json_str[search_pos..].find("\"pid\":") {
-        let abs_pos = search_pos + start;
-
-        if let Some(obj_start) = find_object_start(json_str, abs_pos) {
-            if let Some(obj_end) = find_object_end(json_str, obj_start) {
-                let obj_str = &json_str[obj_start..=obj_end];
-
-                if obj_str.contains("\"app_id\"") || obj_str.contains("\"window_properties\"") {
-                    if let Some(info) = parse_sway_node(obj_str) {
-                        if !windows.iter().any(|w| w.id == info.id) {
-                            windows.push(info);
-                        }
-                    }
-                }
-
-                search_pos = obj_end + 1;
-                continue;
+/// Add the windows in `node` and below it to `windows`. `shown` is whether
+/// the node is on screen as far as its ancestors go, and `floating` whether
+/// it floats.
+///
+/// Only the shown workspace of an output is on screen, and only the focused
+/// child of a tabbed or stacked container, so windows hidden behind others
+/// get a lower `z` than those showing. Sway's scratchpad lives on the `__i3`
+/// output and is never shown.
+fn collect_sway_windows(
+    node: &serde_json::Value,
+    shown: bool,
+    floating: bool,
+    windows: &mut Vec<WindowInfo>,
+) {
+    let tiled = node["nodes"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let floated = node["floating_nodes"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+
+    // Sway's windows have a pid, i3's an X11 window id
+    let is_window = !node["pid"].is_null() || node["window"].as_u64().is_some_and(|w| w > 0);
+    if is_window && tiled.is_empty() && floated.is_empty() {
+        if let Some(window) = parse_sway_window(node, shown, floating) {
+            if !windows.iter().any(|w| w.id == window.id) {
+                windows.push(window);
             }
         }
-
-        search_pos = abs_pos + 6;
+        return;
     }
-}
 
-fn find_object_start(json: &str, pos: usize) -> Option<usize> {
-    let bytes = json.as_bytes();
-    let mut depth = 0;
-
-    for i in (0..pos).rev() {
-        match bytes[i] {
-            b'}' => depth += 1,
-            b'{' => {
-                if depth == 0 {
-                    return Some(i);
-                }
-                depth -= 1;
-            }
-            _ => {}
+    let child_shown = |child: &serde_json::Value| -> bool {
+        match node["type"].as_str() {
+            Some("root") => child["name"] != "__i3",
+            // i3 doesn't say which workspace an output shows in its tree
+            Some("output") => match node["current_workspace"].as_str() {
+                Some(current) => child["name"] == current,
+                None => true,
+            },
+            _ => match node["layout"].as_str() {
+                Some("tabbed") | Some("stacked") => node["focus"][0] == child["id"],
+                _ => true,
+            },
         }
+    };
+    for child in tiled {
+        collect_sway_windows(child, shown && child_shown(child), floating, windows);
     }
-
-    None
-}
-
-fn find_object_end(json: &str, start: usize) -> Option<usize> {
-    let bytes = json.as_bytes();
-    let mut depth = 0;
-    let mut in_string = false;
-    let mut escape_next = false;
-
-    for (i, &byte) in bytes[start..].iter().enumerate() {
-        if escape_next {
-            escape_next = false;
-            continue;
-        }
-
-        match byte {
-            b'\\' if in_string => escape_next = true,
-            b'"' => in_string = !in_string,
-            b'{' if !in_string => depth += 1,
-            b'}' if !in_string => {
-                depth -= 1;
-                if depth == 0 {
-                    return Some(start + i);
-                }
-            }
-            _ => {}
-        }
+    for child in floated {
+        collect_sway_windows(child, shown, true, windows);
     }
-
-    None
 }
 
-fn parse_sway_node(obj_str: &str) -> Option<WindowInfo> {
-    let id = extract_json_number(obj_str, "id")?;
-    let pid = extract_json_number(obj_str, "pid").unwrap_or(0);
-
-    let title = extract_json_string(obj_str, "name").unwrap_or_default();
-
-    let app_name = extract_json_string(obj_str, "app_id")
-        .unwrap_or_else(|| extract_json_string(obj_str, "class").unwrap_or_default());
-
-    let (x, y, width, height) = parse_sway_rect(obj_str).unwrap_or((0, 0, 0, 0));
-
-    let is_focused = extract_json_bool_field(obj_str, "focused").unwrap_or(false);
-    let is_maximized = extract_json_bool_field(obj_str, "fullscreen_mode")
-        .map(|_| true)
-        .unwrap_or(false);
+fn parse_sway_window(node: &serde_json::Value, shown: bool, floating: bool) -> Option<WindowInfo> {
+    let id = node["id"].as_u64()? as u32;
+    let pid = node["pid"].as_u64().unwrap_or(0) as u32;
+    let title = node["name"].as_str().unwrap_or_default().to_string();
+    let app_name = node["app_id"]
+        .as_str()
+        .or_else(|| node["window_properties"]["class"].as_str())
+        .unwrap_or_default()
+        .to_string();
+
+    let rect = &node["rect"];
+    let coordinate = |key: &str| rect[key].as_i64().unwrap_or(0) as i32;
+    let length = |key: &str| rect[key].as_u64().unwrap_or(0) as u32;
+
+    // Floating windows are stacked above the tiled ones
+    let z = match (shown, floating) {
+        (false, _) => 0,
+        (true, false) => 1,
+        (true, true) => 2,
+    };
 
     Some(WindowInfo {
         id,
         pid,
         app_name,
         title,
-        x,
-        y,
-        z: 0,
-        width,
-        height,
+        x: coordinate("x"),
+        y: coordinate("y"),
+        z,
+        width: length("width"),
+        height: length("height"),
         is_minimized: false,
-        is_maximized,
-        is_focused,
+        is_maximized: node["fullscreen_mode"].as_u64().unwrap_or(0) > 0,
+        is_focused: node["focused"].as_bool().unwrap_or(false),
+        address: None,
     })
 }
 
@@ -447,212 +516,174 @@ fn parse_sway_rect(obj_str: &str) -> Option<(i32, i32, u32, u32)> {
     Some((x, y, width, height))
 }
 
+/// Capture a Sway container by its con id.
+///
+/// The container is focused first, which shows its workspace and raises it
+/// if floating, then its current rectangle is grabbed, clipped to the
+/// workspace it is on so no neighbouring output ends up in the image. Focus
+/// then goes back to where it was.
 fn capture_window_sway(window_info: &WindowInfo) -> WindowCaptureBackendResult {
-    let geometry = format!(
-        "{},{} {}x{}",
-        window_info.x, window_info.y, window_info.width, window_info.height
-    );
-
-    let temp_path = format!("/tmp/screenshot_gnome_{}.png", std::process::id());
-
-    let output = Command::new("grim")
-        .args(["-g", &geometry, &temp_path])
-        .output()
-        .map_err(|e| WindowCaptureError::CaptureFailed(format!("Failed to run grim: {}", e)))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(WindowCaptureError::CaptureFailed(format!(
-            "grim failed: {}",
-            stderr
-        )));
+    let mut sway = match SwayIpc::connect() {
+        Ok(sway) => sway,
+        Err(e) => {
+            warn!(
+                "Capturing con {} without focusing it: {}",
+                window_info.id, e
+            );
+            return capture_region_with_grim(window_info);
+        }
+    };
+    let previous = sway_focused_window(&mut sway).map(|w| w.id);
+
+    let focused = match sway.run_command(&format!("[con_id={}] focus", window_info.id)) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!(
+                "Could not focus con {} before capturing it: {}",
+                window_info.id, e
+            );
+            false
+        }
+    };
+    if focused {
+        std::thread::sleep(SWAY_FOCUS_SETTLE);
     }
 
-    let pixbuf = load_pixbuf_from_file(&temp_path)?;
-    let _ = std::fs::remove_file(&temp_path);
-
-    Ok(WindowCaptureResult {
-        pixbuf,
-        window_info: window_info.clone(),
-    })
-}
+    let mut target = if focused {
+        sway.tree()
+            .ok()
+            .and_then(|tree| parse_sway_tree(&tree).ok())
+            .and_then(|windows| windows.into_iter().find(|w| w.id == window_info.id))
+            .unwrap_or_else(|| window_info.clone())
+    } else {
+        window_info.clone()
+    };
+    let rect = (target.x, target.y, target.width, target.height);
+    if let Some((x, y, width, height)) = clip_to_areas(rect, &visible_sway_workspaces(&mut sway)) {
+        target.x = x;
+        target.y = y;
+        target.width = width;
+        target.height = height;
+    }
 
-fn list_windows_gnome_wayland() -> WindowListResult {
-    let output = Command::new("gdbus")
-        .args([
-            "call",
-            "--session",
-            "--dest",
-            "org.gnome.Shell.Introspect",
-            "--object-path",
-            "/org/gnome/Shell/Introspect",
-            "--method",
-            "org.gnome.Shell.Introspect.GetWindows",
-        ])
-        .output();
+    let result = capture_region_with_grim(&target);
 
-    match output {
-        Ok(output) if output.status.success() => {
-            let result_str = String::from_utf8_lossy(&output.stdout);
-            parse_gnome_introspect_output(&result_str)
-        }
-        _ => {
-            warn!("GNOME Shell Introspect not available, falling back to xcap");
-            list_windows_xcap()
+    if focused {
+        if let Some(previous) = previous.filter(|previous| *previous != window_info.id) {
+            if let Err(e) = sway.run_command(&format!("[con_id={}] focus", previous)) {
+                warn!("Could not give focus back to con {}: {}", previous, e);
+            }
         }
     }
+    result
 }
 
-fn parse_gnome_introspect_output(output: &str) -> WindowListResult {
-    let mut windows = Vec::new();
-
-    let mut search_pos = 0;
-    let mut window_id: u32 = 1;
-
-    while let Some(start) = output[search_pos..].find("'wm-class':") {
-        let abs_pos = search_pos + start;
-
-        let wm_class =
-            extract_gvariant_string(&output[abs_pos..], "'wm-class':").unwrap_or_default();
-
-        let title = if let Some(title_pos) = output[search_pos..].find("'title':") {
-            extract_gvariant_string(&output[search_pos + title_pos..], "'title':")
-                .unwrap_or_default()
-        } else {
-            String::new()
-        };
-
-        let pid = if let Some(pid_pos) = output[search_pos..].find("'pid':") {
-            extract_gvariant_number(&output[search_pos + pid_pos..]).unwrap_or(0)
-        } else {
-            0
-        };
-
-        let (width, height) = extract_gnome_dimensions(&output[search_pos..]).unwrap_or((0, 0));
-
-        windows.push(WindowInfo {
-            id: window_id,
-            pid,
-            app_name: wm_class,
-            title,
-            x: 0,
-            y: 0,
-            z: 0,
-            width,
-            height,
-            is_minimized: false,
-            is_maximized: false,
-            is_focused: false,
-        });
-
-        window_id += 1;
-        search_pos = abs_pos + 10;
-    }
+/// The window with keyboard focus, if any has it
+fn sway_focused_window(sway: &mut SwayIpc) -> Option<WindowInfo> {
+    let tree = sway.tree().ok()?;
+    parse_sway_tree(&tree)
+        .ok()?
+        .into_iter()
+        .find(|w| w.is_focused)
+}
 
-    if windows.is_empty() {
-        list_windows_xcap()
-    } else {
-        Ok(windows)
+/// Rectangles of the workspaces currently shown on an output
+fn visible_sway_workspaces(sway: &mut SwayIpc) -> Vec<(i32, i32, u32, u32)> {
+    match sway.workspaces() {
+        Ok(json) => parse_visible_sway_workspaces(&json),
+        Err(e) => {
+            debug!("Could not list Sway workspaces: {}", e);
+            Vec::new()
+        }
     }
 }
 
-fn extract_gvariant_string(text: &str, prefix: &str) -> Option<String> {
-    let start = text.find(prefix)? + prefix.len();
-    let rest = text[start..].trim_start();
-
-    let quote_char = if rest.starts_with('<') {
-        rest.find('\'')?;
-        '\''
-    } else if rest.starts_with('\'') {
-        '\''
-    } else {
-        return None;
-    };
-
-    let content_start = rest.find(quote_char)? + 1;
-    let content = &rest[content_start..];
-    let end = content.find(quote_char)?;
-
-    Some(content[..end].to_string())
+fn parse_visible_sway_workspaces(json_str: &str) -> Vec<(i32, i32, u32, u32)> {
+    json_array_objects(json_str)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|workspace| extract_json_bool_field(workspace, "visible").unwrap_or(false))
+        .filter_map(parse_sway_rect)
+        .collect()
 }
 
-fn extract_gvariant_number(text: &str) -> Option<u32> {
-    let start = text.find("'pid':")? + 6;
-    let rest = text[start..].trim_start();
-
-    let number_part = rest.strip_prefix('<').unwrap_or(rest);
-
-    let number_str = number_part
-        .split_whitespace()
-        .find(|s| s.chars().all(|c| c.is_ascii_digit()))?;
-
-    number_str.parse().ok()
+/// `rect` cut down to the area it overlaps most, `None` if it overlaps none
+fn clip_to_areas(
+    rect: (i32, i32, u32, u32),
+    areas: &[(i32, i32, u32, u32)],
+) -> Option<(i32, i32, u32, u32)> {
+    let (x, y, width, height) = rect;
+    areas
+        .iter()
+        .filter_map(|&(area_x, area_y, area_width, area_height)| {
+            let left = x.max(area_x);
+            let top = y.max(area_y);
+            let right = (x + width as i32).min(area_x + area_width as i32);
+            let bottom = (y + height as i32).min(area_y + area_height as i32);
+            if right > left && bottom > top {
+                Some((left, top, (right - left) as u32, (bottom - top) as u32))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|&(_, _, width, height)| width as u64 * height as u64)
 }
 
-fn extract_gnome_dimensions(text: &str) -> Option<(u32, u32)> {
-    let width = if let Some(pos) = text.find("'width':") {
-        extract_gvariant_dimension(&text[pos..]).unwrap_or(0)
-    } else {
-        0
-    };
-
-    let height = if let Some(pos) = text.find("'height':") {
-        extract_gvariant_dimension(&text[pos..]).unwrap_or(0)
-    } else {
-        0
+fn list_windows_gnome_wayland() -> WindowListResult {
+    let windows = match GnomeShell::connect().and_then(|shell| shell.windows()) {
+        Ok(windows) if !windows.is_empty() => windows,
+        Ok(_) => return list_windows_xcap(),
+        Err(e) => {
+            warn!("{}, falling back to xcap", e);
+            return list_windows_xcap();
+        }
     };
 
-    if width > 0 && height > 0 {
-        Some((width, height))
-    } else {
-        None
-    }
-}
-
-fn extract_gvariant_dimension(text: &str) -> Option<u32> {
-    let colon_pos = text.find(':')?;
-    let rest = text[colon_pos + 1..].trim_start();
-
-    let end = rest
-        .find(|c: char| !c.is_ascii_digit())
-        .unwrap_or(rest.len());
-
-    if end > 0 {
-        rest[..end].parse().ok()
-    } else {
-        None
-    }
+    Ok(windows
+        .into_iter()
+        .enumerate()
+        .map(|(index, window)| WindowInfo {
+            // Mutter's ids count up from 1, so they fit unless the session
+            // has been very long
+            id: u32::try_from(window.id).unwrap_or(index as u32 + 1),
+            pid: window.pid,
+            app_name: if window.wm_class.is_empty() {
+                window.app_id.trim_end_matches(".desktop").to_string()
+            } else {
+                window.wm_class
+            },
+            title: window.title,
+            // The Shell doesn't say where windows are
+            x: 0,
+            y: 0,
+            z: 0,
+            width: window.width,
+            height: window.height,
+            is_minimized: window.is_hidden,
+            is_maximized: false,
+            is_focused: window.has_focus,
+            address: None,
+        })
+        .collect())
 }
 
 fn capture_window_gnome_wayland(window_info: &WindowInfo) -> WindowCaptureBackendResult {
     let temp_path = format!("/tmp/screenshot_gnome_{}.png", std::process::id());
 
-    let portal_result = Command::new("gdbus")
-        .args([
-            "call",
-            "--session",
-            "--dest",
-            "org.gnome.Shell.Screenshot",
-            "--object-path",
-            "/org/gnome/Shell/Screenshot",
-            "--method",
-            "org.gnome.Shell.Screenshot.ScreenshotWindow",
-            "true",
-            "true",
-            &temp_path,
-        ])
-        .output();
-
-    if let Ok(output) = portal_result {
-        if output.status.success() {
-            if let Ok(pixbuf) = load_pixbuf_from_file(&temp_path) {
-                let _ = std::fs::remove_file(&temp_path);
+    let shell_result = GnomeShell::connect()
+        .and_then(|shell| shell.screenshot_window(Path::new(&temp_path), true, true));
+    match shell_result {
+        Ok(written) => {
+            let loaded = load_pixbuf_from_file(&written.to_string_lossy());
+            let _ = std::fs::remove_file(&written);
+            if let Ok(pixbuf) = loaded {
                 return Ok(WindowCaptureResult {
                     pixbuf,
                     window_info: window_info.clone(),
                 });
             }
         }
+        Err(e) => debug!("{}", e),
     }
 
     let geometry = format!(
@@ -701,6 +732,12 @@ fn capture_window_gnome_wayland(window_info: &WindowInfo) -> WindowCaptureBacken
         }
     }
 
+    if portal_available() {
+        if let Ok(result) = super::portal::capture_window_area(window_info) {
+            return Ok(result);
+        }
+    }
+
     capture_window_xcap(window_info)
 }
 
@@ -771,6 +808,7 @@ fn parse_kdotool_output(output: &str) -> WindowListResult {
                 is_minimized: false,
                 is_maximized: false,
                 is_focused: false,
+                address: None,
             });
         }
     }
@@ -831,9 +869,167 @@ fn capture_window_kde_wayland(window_info: &WindowInfo) -> WindowCaptureBackendR
     }
 
     let _ = spectacle_result;
+    if portal_available() {
+        if let Ok(result) = super::portal::capture_window_area(window_info) {
+            return Ok(result);
+        }
+    }
     capture_window_xcap(window_info)
 }
 
+fn list_windows_foreign_toplevel() -> WindowListResult {
+    let toplevels = match ForeignToplevels::connect() {
+        Ok(connection) => connection.toplevels(),
+        Err(e) => {
+            warn!("{}, falling back to xcap", e);
+            return list_windows_xcap();
+        }
+    };
+
+    Ok(toplevels
+        .into_iter()
+        .map(|toplevel| WindowInfo {
+            // Handles are per connection, so windows are told apart by their
+            // app id and title
+            id: 0,
+            pid: 0,
+            app_name: toplevel.app_id,
+            title: toplevel.title,
+            // The protocol doesn't say where windows are
+            x: 0,
+            y: 0,
+            z: if toplevel.is_activated { 1 } else { 0 },
+            width: 0,
+            height: 0,
+            is_minimized: toplevel.is_minimized,
+            is_maximized: toplevel.is_maximized,
+            is_focused: toplevel.is_activated,
+            address: None,
+        })
+        .collect())
+}
+
+/// The window a listing was made from, by app id and title, or the only
+/// window of its app if its title changed since
+fn find_toplevel<'a>(toplevels: &'a [Toplevel], window_info: &WindowInfo) -> Option<&'a Toplevel> {
+    let same_app: Vec<&Toplevel> = toplevels
+        .iter()
+        .filter(|toplevel| toplevel.app_id == window_info.app_name)
+        .collect();
+    let titled = same_app
+        .iter()
+        .copied()
+        .find(|toplevel| toplevel.title == window_info.title);
+    match titled {
+        Some(toplevel) => Some(toplevel),
+        None if same_app.len() == 1 => Some(same_app[0]),
+        None => None,
+    }
+}
+
+/// Bring the window to the front and capture the output it is on, as the
+/// protocol can raise windows but not say where they are
+fn capture_window_foreign_toplevel(window_info: &WindowInfo) -> WindowCaptureBackendResult {
+    let mut connection = match ForeignToplevels::connect() {
+        Ok(connection) => connection,
+        Err(e) => {
+            warn!("{}, capturing with xcap", e);
+            return capture_window_xcap(window_info);
+        }
+    };
+    let toplevels = connection.toplevels();
+    let toplevel =
+        find_toplevel(&toplevels, window_info).ok_or(WindowCaptureError::WindowNotFound)?;
+    if toplevel.is_minimized {
+        return Err(WindowCaptureError::WindowMinimized);
+    }
+
+    match connection.activate(toplevel) {
+        Ok(()) => std::thread::sleep(FOREIGN_TOPLEVEL_SETTLE),
+        Err(e) => warn!(
+            "Could not activate {:?} before capturing it: {}",
+            toplevel.title, e
+        ),
+    }
+
+    let temp_path = format!("/tmp/screenshot_gnome_{}.png", std::process::id());
+    let mut grim = Command::new("grim");
+    if let Some(output) = toplevel.outputs.first() {
+        grim.args(["-o", output]);
+    }
+    let output = grim
+        .arg(&temp_path)
+        .output()
+        .map_err(|e| WindowCaptureError::CaptureFailed(format!("Failed to run grim: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(WindowCaptureError::CaptureFailed(format!(
+            "grim failed: {}",
+            stderr
+        )));
+    }
+
+    let pixbuf = load_pixbuf_from_file(&temp_path)?;
+    let _ = std::fs::remove_file(&temp_path);
+
+    Ok(WindowCaptureResult {
+        pixbuf,
+        window_info: window_info.clone(),
+    })
+}
+
+fn list_windows_x11() -> WindowListResult {
+    let windows = match X11::connect().and_then(|x11| x11.windows()) {
+        Ok(windows) => windows,
+        Err(e) => {
+            warn!("{}, falling back to xcap", e);
+            return list_windows_xcap();
+        }
+    };
+
+    Ok(windows
+        .into_iter()
+        .map(|window| WindowInfo {
+            id: window.id,
+            pid: window.pid,
+            app_name: window.class,
+            title: window.title,
+            x: window.x,
+            y: window.y,
+            z: window.z,
+            width: window.width,
+            height: window.height,
+            is_minimized: window.is_minimized,
+            is_maximized: window.is_maximized,
+            is_focused: window.is_focused,
+            address: None,
+        })
+        .collect())
+}
+
+fn capture_window_x11(window_info: &WindowInfo) -> WindowCaptureBackendResult {
+    let x11 = match X11::connect() {
+        Ok(x11) => x11,
+        Err(e) => {
+            warn!("{}, capturing with xcap", e);
+            return capture_window_xcap(window_info);
+        }
+    };
+    match x11.capture(window_info.id) {
+        Ok(image) => Ok(WindowCaptureResult {
+            pixbuf: rgba_image_to_pixbuf(image)?,
+            window_info: window_info.clone(),
+        }),
+        // Unmapped windows have no contents to read, but xcap may find them
+        // where the window manager keeps them, as KWin does
+        Err(e) if window_info.is_minimized => {
+            debug!("Minimized window could not be read: {}", e);
+            capture_window_xcap(window_info)
+        }
+        Err(e) => Err(WindowCaptureError::CaptureFailed(e)),
+    }
+}
+
 fn list_windows_xcap() -> WindowListResult {
     use xcap::Window;
 
@@ -857,6 +1053,7 @@ fn list_windows_xcap() -> WindowListResult {
             is_minimized: window.is_minimized().unwrap_or(false),
             is_maximized: window.is_maximized().unwrap_or(false),
             is_focused: window.is_focused().unwrap_or(false),
+            address: None,
         };
 
         window_infos.push(info);
@@ -892,13 +1089,17 @@ fn capture_window_xcap(window_info: &WindowInfo) -> WindowCaptureBackendResult {
 
     let window = window.ok_or(WindowCaptureError::WindowNotFound)?;
 
-    if window.is_minimized().unwrap_or(false) {
-        return Err(WindowCaptureError::WindowMinimized);
-    }
-
-    let image = window
-        .capture_image()
-        .map_err(|e| WindowCaptureError::CaptureFailed(e.to_string()))?;
+    // Minimized windows are tried too; only some compositors keep their
+    // contents, and the others fail the grab
+    let minimized = window.is_minimized().unwrap_or(false);
+    let image = window.capture_image().map_err(|e| {
+        if minimized {
+            debug!("Minimized window could not be grabbed: {}", e);
+            WindowCaptureError::WindowMinimized
+        } else {
+            WindowCaptureError::CaptureFailed(e.to_string())
+        }
+    })?;
 
     let pixbuf = rgba_image_to_pixbuf(image)?;
 
@@ -909,6 +1110,12 @@ fn capture_window_xcap(window_info: &WindowInfo) -> WindowCaptureBackendResult {
 }
 
 fn load_pixbuf_from_file(path: &str) -> Result<Pixbuf, WindowCaptureError> {
+    if let Some(image) = super::tonemap::load_high_bit_depth_png(path)
+        .map_err(WindowCaptureError::ConversionFailed)?
+    {
+        return rgba_image_to_pixbuf(image);
+    }
+
     Pixbuf::from_file(path)
         .map_err(|e| WindowCaptureError::ConversionFailed(format!("Failed to load image: {}", e)))
 }
@@ -986,6 +1193,102 @@ mod tests {
         assert_eq!(windows[0].title, "Mozilla Firefox");
         assert_eq!(windows[0].app_name, "firefox");
         assert_eq!(windows[0].pid, 1234);
+        assert_eq!(windows[0].address.as_deref(), Some("0x12345678"));
+        assert_eq!(windows[0].id, 0x12345678);
+    }
+
+    #[test]
+    fn test_parse_sway_tree() {
+        let json = r#"{
+            "id": 1, "type": "root", "nodes": [
+                {"id": 2, "type": "output", "name": "__i3", "nodes": [
+                    {"id": 3, "type": "workspace", "name": "__i3_scratch", "nodes": [],
+                     "floating_nodes": [
+                        {"id": 30, "type": "floating_con", "pid": 300, "app_id": "notes",
+                         "name": "Notes", "focused": false, "fullscreen_mode": 0,
+                         "rect": {"x": 0, "y": 0, "width": 400, "height": 300},
+                         "nodes": [], "floating_nodes": []}
+                    ]}
+                ]},
+                {"id": 4, "type": "output", "name": "DP-1", "current_workspace": "1", "nodes": [
+                    {"id": 5, "type": "workspace", "name": "1", "layout": "splith", "nodes": [
+                        {"id": 6, "type": "con", "layout": "tabbed", "focus": [8, 7], "nodes": [
+                            {"id": 7, "type": "con", "pid": 700, "app_id": null,
+                             "window_properties": {"class": "Firefox"}, "name": "News",
+                             "focused": false, "fullscreen_mode": 0,
+                             "rect": {"x": 0, "y": 30, "width": 960, "height": 1050},
+                             "nodes": [], "floating_nodes": []},
+                            {"id": 8, "type": "con", "pid": 800, "app_id": "foot",
+                             "name": "~", "focused": true, "fullscreen_mode": 0,
+                             "rect": {"x": 0, "y": 30, "width": 960, "height": 1050},
+                             "nodes": [], "floating_nodes": []}
+                        ]}
+                    ], "floating_nodes": [
+                        {"id": 9, "type": "floating_con", "pid": 900, "app_id": "pavucontrol",
+                         "name": "Volume", "focused": false, "fullscreen_mode": 0,
+                         "rect": {"x": 300, "y": 200, "width": 600, "height": 400},
+                         "nodes": [], "floating_nodes": []}
+                    ]},
+                    {"id": 10, "type": "workspace", "name": "2", "layout": "splith", "nodes": [
+                        {"id": 11, "type": "con", "pid": 1100, "app_id": "mpv",
+                         "name": "Video", "focused": false, "fullscreen_mode": 1,
+                         "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080},
+                         "nodes": [], "floating_nodes": []}
+                    ], "floating_nodes": []}
+                ]}
+            ]
+        }"#;
+
+        let windows = parse_sway_tree(json).unwrap();
+        let z_of = |id: u32| windows.iter().find(|w| w.id == id).map(|w| w.z);
+        assert_eq!(windows.len(), 5);
+        // Scratchpad, the hidden tab and the other workspace are not shown
+        assert_eq!(z_of(30), Some(0));
+        assert_eq!(z_of(7), Some(0));
+        assert_eq!(z_of(8), Some(1));
+        assert_eq!(z_of(9), Some(2));
+        assert_eq!(z_of(11), Some(0));
+
+        let tab = windows.iter().find(|w| w.id == 7).unwrap();
+        assert_eq!(tab.app_name, "Firefox");
+        assert_eq!((tab.x, tab.y, tab.width, tab.height), (0, 30, 960, 1050));
+        assert!(windows.iter().find(|w| w.id == 8).unwrap().is_focused);
+        assert!(windows.iter().find(|w| w.id == 11).unwrap().is_maximized);
+        assert!(!tab.is_maximized);
+        assert!(parse_sway_tree("not json").is_err());
+    }
+
+    #[test]
+    fn test_parse_visible_sway_workspaces() {
+        let json = r#"[
+            {"id": 4, "name": "1", "visible": true, "focused": true,
+             "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+            {"id": 5, "name": "2", "visible": false, "focused": false,
+             "rect": {"x": 0, "y": 0, "width": 1920, "height": 1080}},
+            {"id": 6, "name": "3", "visible": true, "focused": false,
+             "rect": {"x": 1920, "y": 0, "width": 2560, "height": 1440}}
+        ]"#;
+
+        assert_eq!(
+            parse_visible_sway_workspaces(json),
+            vec![(0, 0, 1920, 1080), (1920, 0, 2560, 1440)]
+        );
+    }
+
+    #[test]
+    fn test_clip_to_areas() {
+        let outputs = [(0, 0, 1920, 1080), (1920, 0, 2560, 1440)];
+
+        // Mostly on the second output, so the part on the first is cut off
+        assert_eq!(
+            clip_to_areas((1800, 100, 800, 600), &outputs),
+            Some((1920, 100, 680, 600))
+        );
+        assert_eq!(
+            clip_to_areas((100, 100, 400, 300), &outputs),
+            Some((100, 100, 400, 300))
+        );
+        assert_eq!(clip_to_areas((5000, 0, 100, 100), &outputs), None);
     }
 
     #[test]
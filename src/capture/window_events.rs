@@ -0,0 +1,237 @@
+use gtk4::glib;
+use log::{debug, warn};
+use std::cell::Cell;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::desktop::{DesktopSession, WindowListBackend};
+use super::gnome_shell::GnomeShell;
+use super::hyprland;
+use super::sway;
+
+/// How often the blocking readers wake up to check whether they should stop
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Re-list interval for backends without an event stream
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Hyprland socket2 events that change the set or geometry of windows
+const HYPRLAND_WINDOW_EVENTS: &[&str] = &[
+    "openwindow",
+    "closewindow",
+    "movewindow",
+    "movewindowv2",
+    "windowtitle",
+    "windowtitlev2",
+    "changefloatingmode",
+    "minimized",
+    "fullscreen",
+];
+
+/// Watches the compositor for windows opening, closing or changing, so lists
+/// built from [`super::window::list_capturable_windows`] can be refreshed.
+///
+/// Hyprland is followed through socket2, Sway through an IPC subscription and
+/// GNOME through the `WindowsChanged` signal of the Shell introspection API.
+/// Other sessions fall back to polling. The background thread stops when the
+/// watcher is dropped.
+pub struct WindowWatcher {
+    stop: Arc<AtomicBool>,
+    receiver: Receiver<()>,
+}
+
+impl WindowWatcher {
+    pub fn start(session: &DesktopSession) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (sender, receiver) = mpsc::channel();
+        let backend = session.window_list_backend();
+
+        debug!("Watching window changes with {:?} backend", backend);
+
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            let result = match backend {
+                WindowListBackend::Hyprland => watch_hyprland(&sender, &thread_stop),
+                WindowListBackend::Sway => watch_sway(&sender, &thread_stop),
+                WindowListBackend::GnomeWayland => watch_gnome(&sender, &thread_stop),
+                _ => Err("no event stream for this session".to_string()),
+            };
+
+            if let Err(e) = result {
+                if !thread_stop.load(Ordering::Relaxed) {
+                    warn!("Window events unavailable ({}), polling instead", e);
+                    poll(&sender, &thread_stop);
+                }
+            }
+        });
+
+        Self { stop, receiver }
+    }
+
+    /// Whether anything changed since the last call; bursts of events are
+    /// coalesced into a single `true`
+    pub fn poll_changed(&self) -> bool {
+        self.receiver.try_iter().count() > 0
+    }
+}
+
+impl Drop for WindowWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn poll(sender: &Sender<()>, stop: &AtomicBool) {
+    while !stop.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+        if sender.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+fn is_hyprland_window_event(line: &str) -> bool {
+    line.split_once(">>")
+        .map(|(event, _)| HYPRLAND_WINDOW_EVENTS.contains(&event))
+        .unwrap_or(false)
+}
+
+fn watch_hyprland(sender: &Sender<()>, stop: &AtomicBool) -> Result<(), String> {
+    let path = hyprland::socket_path(".socket2.sock").ok_or("Hyprland event socket not found")?;
+    let stream = UnixStream::connect(&path)
+        .map_err(|e| format!("Failed to connect to {}: {}", path.display(), e))?;
+    stream
+        .set_read_timeout(Some(STOP_CHECK_INTERVAL))
+        .map_err(|e| e.to_string())?;
+
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Err("Hyprland closed the event socket".to_string()),
+            Ok(_) => {
+                if is_hyprland_window_event(line.trim_end()) && sender.send(()).is_err() {
+                    return Ok(());
+                }
+                line.clear();
+            }
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+    Ok(())
+}
+
+fn watch_sway(sender: &Sender<()>, stop: &AtomicBool) -> Result<(), String> {
+    let path = sway::socket_path().ok_or("Neither SWAYSOCK nor I3SOCK is set")?;
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| format!("Failed to connect to {}: {}", path.display(), e))?;
+    stream
+        .write_all(&sway::message(sway::SUBSCRIBE, r#"["window"]"#))
+        .map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(STOP_CHECK_INTERVAL))
+        .map_err(|e| e.to_string())?;
+
+    let mut header = [0u8; sway::HEADER_LENGTH];
+    let mut subscribed = false;
+    while !stop.load(Ordering::Relaxed) {
+        // Wait for the first byte with a timeout, then read the rest of the
+        // message blocking so a timeout can never split it
+        match stream.read(&mut header[..1]) {
+            Ok(0) => return Err("sway closed the IPC socket".to_string()),
+            Ok(_) => {}
+            Err(e) if is_timeout(&e) => continue,
+            Err(e) => return Err(e.to_string()),
+        }
+
+        stream.set_read_timeout(None).map_err(|e| e.to_string())?;
+        stream
+            .read_exact(&mut header[1..])
+            .map_err(|e| e.to_string())?;
+        let (_, payload) = sway::read_payload(&header, &mut stream)?;
+        stream
+            .set_read_timeout(Some(STOP_CHECK_INTERVAL))
+            .map_err(|e| e.to_string())?;
+
+        if !subscribed {
+            let reply = String::from_utf8_lossy(&payload);
+            if !reply.contains("\"success\": true") && !reply.contains("\"success\":true") {
+                return Err(format!("sway refused the subscription: {}", reply));
+            }
+            subscribed = true;
+            continue;
+        }
+
+        if sender.send(()).is_err() {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+fn watch_gnome(sender: &Sender<()>, stop: &AtomicBool) -> Result<(), String> {
+    let shell = GnomeShell::connect()?;
+    if !shell.introspect_available() {
+        return Err("GNOME Shell introspection is not on the bus".to_string());
+    }
+
+    // Signals are delivered through the main context of this thread, run
+    // until the watcher is dropped or nobody is listening
+    let context = glib::MainContext::new();
+    context
+        .with_thread_default(|| {
+            let closed = Rc::new(Cell::new(false));
+            let _subscription = shell.subscribe_windows_changed({
+                let sender = sender.clone();
+                let closed = closed.clone();
+                move || {
+                    if sender.send(()).is_err() {
+                        closed.set(true);
+                    }
+                }
+            });
+
+            // Wakes the loop up to check whether it should stop
+            let wakeup = glib::timeout_source_new(
+                STOP_CHECK_INTERVAL,
+                None,
+                glib::Priority::DEFAULT,
+                || glib::ControlFlow::Continue,
+            );
+            wakeup.attach(Some(&context));
+            while !stop.load(Ordering::Relaxed) && !closed.get() {
+                context.iteration(true);
+            }
+            wakeup.destroy();
+        })
+        .map_err(|e| e.to_string())
+}
+
+fn is_timeout(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hyprland_event_filter() {
+        assert!(is_hyprland_window_event(
+            "openwindow>>80e62df0,2,jetbrains-goland,win430"
+        ));
+        assert!(is_hyprland_window_event("closewindow>>80e62df0"));
+        assert!(!is_hyprland_window_event("workspace>>2"));
+        assert!(!is_hyprland_window_event("garbage"));
+    }
+}
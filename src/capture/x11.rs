@@ -0,0 +1,337 @@
+//! Window listing and capture on X11 straight from the X server, through
+//! the EWMH properties the window manager keeps on the root window and on
+//! each client.
+//!
+//! Windows are listed from `_NET_CLIENT_LIST_STACKING`, bottom to top, so
+//! their stacking order is the window manager's own, and their ids are the
+//! client windows' XIDs. Captures read the client window's contents with
+//! `GetImage`, which a compositing window manager keeps even where other
+//! windows cover it.
+
+use image::RgbaImage;
+use log::debug;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt as _, GetPropertyReply, ImageFormat, ImageOrder, Window,
+};
+use x11rb::rust_connection::RustConnection;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        _NET_CLIENT_LIST_STACKING,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_NAME,
+        _NET_WM_PID,
+        _NET_FRAME_EXTENTS,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        _NET_WM_STATE_MAXIMIZED_VERT,
+        _NET_WM_STATE_MAXIMIZED_HORZ,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_WINDOW_TYPE_DESKTOP,
+        _NET_WM_WINDOW_TYPE_DOCK,
+        UTF8_STRING,
+    }
+}
+
+/// A client window as the window manager describes it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct X11Window {
+    pub id: u32,
+
+    pub pid: u32,
+
+    pub title: String,
+
+    /// Class part of `WM_CLASS`, naming the application
+    pub class: String,
+
+    /// Where the window is on the screen, with the frame the window manager
+    /// draws around it
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+
+    /// Place in the stacking order, from 0 at the bottom
+    pub z: i32,
+
+    pub is_minimized: bool,
+
+    pub is_maximized: bool,
+
+    pub is_focused: bool,
+}
+
+/// `rect` as `(x, y, width, height)` grown by the frame `extents`, which
+/// `_NET_FRAME_EXTENTS` gives as left, right, top and bottom
+fn with_frame(rect: (i32, i32, u32, u32), extents: &[u32]) -> (i32, i32, u32, u32) {
+    let (x, y, width, height) = rect;
+    match *extents {
+        [left, right, top, bottom] => (
+            x - left as i32,
+            y - top as i32,
+            width + left + right,
+            height + top + bottom,
+        ),
+        _ => rect,
+    }
+}
+
+/// Pixels of a `ZPixmap` image as RGBA. Only 32 bits per pixel, with
+/// 24-bit colour and alpha at depth 32, is understood, which is what every
+/// current X server uses for true colour.
+fn zpixmap_to_rgba(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    depth: u8,
+    bits_per_pixel: u8,
+    byte_order: ImageOrder,
+) -> Result<RgbaImage, String> {
+    if bits_per_pixel != 32 || depth < 24 {
+        return Err(format!(
+            "Unsupported image format: depth {} at {} bits per pixel",
+            depth, bits_per_pixel
+        ));
+    }
+    let stride = width as usize * 4;
+    if data.len() < stride * height as usize {
+        return Err("The X server sent a truncated image".to_string());
+    }
+
+    let mut rgba = Vec::with_capacity(stride * height as usize);
+    for pixel in data[..stride * height as usize].chunks_exact(4) {
+        let value = if byte_order == ImageOrder::MSB_FIRST {
+            u32::from_be_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])
+        } else {
+            u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]])
+        };
+        let alpha = if depth == 32 {
+            (value >> 24) as u8
+        } else {
+            255
+        };
+        rgba.extend_from_slice(&[(value >> 16) as u8, (value >> 8) as u8, value as u8, alpha]);
+    }
+    RgbaImage::from_raw(width, height, rgba).ok_or_else(|| "Invalid image size".to_string())
+}
+
+/// A connection to the X server
+pub struct X11 {
+    connection: RustConnection,
+    root: Window,
+    atoms: Atoms,
+}
+
+impl X11 {
+    pub fn connect() -> Result<Self, String> {
+        let (connection, screen) = x11rb::connect(None)
+            .map_err(|e| format!("Failed to connect to the X server: {}", e))?;
+        let root = connection.setup().roots[screen].root;
+        let atoms = Atoms::new(&connection)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            connection,
+            root,
+            atoms,
+        })
+    }
+
+    /// The client windows, from the bottom of the stack to the top, leaving
+    /// out desktops and docks
+    pub fn windows(&self) -> Result<Vec<X11Window>, String> {
+        let stacking = self
+            .property(
+                self.root,
+                self.atoms._NET_CLIENT_LIST_STACKING,
+                AtomEnum::WINDOW,
+            )?
+            .value32()
+            .ok_or("The window manager keeps no _NET_CLIENT_LIST_STACKING")?
+            .collect::<Vec<_>>();
+        let active = self
+            .property(self.root, self.atoms._NET_ACTIVE_WINDOW, AtomEnum::WINDOW)?
+            .value32()
+            .and_then(|mut value| value.next());
+
+        let mut windows = Vec::new();
+        for (z, id) in stacking.into_iter().enumerate() {
+            match self.window(id, z as i32, active == Some(id)) {
+                Ok(Some(window)) => windows.push(window),
+                Ok(None) => {}
+                // Windows closing while they are looked at are skipped
+                Err(e) => debug!("Skipping window {:#x}: {}", id, e),
+            }
+        }
+        debug!("The window manager lists {} windows", windows.len());
+        Ok(windows)
+    }
+
+    /// The contents of window `id`, without the window manager's frame
+    pub fn capture(&self, id: u32) -> Result<RgbaImage, String> {
+        let geometry = self
+            .connection
+            .get_geometry(id)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("Window {:#x} is gone: {}", id, e))?;
+        let image = self
+            .connection
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                id,
+                0,
+                0,
+                geometry.width,
+                geometry.height,
+                !0,
+            )
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| format!("Failed to read window {:#x}: {}", id, e))?;
+
+        let setup = self.connection.setup();
+        let bits_per_pixel = setup
+            .pixmap_formats
+            .iter()
+            .find(|format| format.depth == image.depth)
+            .map(|format| format.bits_per_pixel)
+            .unwrap_or(image.depth);
+        zpixmap_to_rgba(
+            &image.data,
+            geometry.width as u32,
+            geometry.height as u32,
+            image.depth,
+            bits_per_pixel,
+            setup.image_byte_order,
+        )
+    }
+
+    /// Window `id`, or `None` when it is a desktop or dock
+    fn window(&self, id: u32, z: i32, is_focused: bool) -> Result<Option<X11Window>, String> {
+        let window_types = self.atoms32(id, self.atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM)?;
+        if window_types.contains(&self.atoms._NET_WM_WINDOW_TYPE_DESKTOP)
+            || window_types.contains(&self.atoms._NET_WM_WINDOW_TYPE_DOCK)
+        {
+            return Ok(None);
+        }
+
+        let geometry = self
+            .connection
+            .get_geometry(id)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        // Reparenting window managers put clients in a frame, so the
+        // position has to be translated to the root
+        let position = self
+            .connection
+            .translate_coordinates(id, self.root, 0, 0)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())?;
+        let extents = self.atoms32(id, self.atoms._NET_FRAME_EXTENTS, AtomEnum::CARDINAL)?;
+        let (x, y, width, height) = with_frame(
+            (
+                position.dst_x as i32,
+                position.dst_y as i32,
+                geometry.width as u32,
+                geometry.height as u32,
+            ),
+            &extents,
+        );
+
+        let states = self.atoms32(id, self.atoms._NET_WM_STATE, AtomEnum::ATOM)?;
+        let title = match self.string(id, self.atoms._NET_WM_NAME, self.atoms.UTF8_STRING)? {
+            title if !title.is_empty() => title,
+            _ => self.string(id, AtomEnum::WM_NAME.into(), AtomEnum::STRING.into())?,
+        };
+        // WM_CLASS holds the instance and then the class, each ending in NUL
+        let class = self
+            .string(id, AtomEnum::WM_CLASS.into(), AtomEnum::STRING.into())?
+            .split('\0')
+            .nth(1)
+            .unwrap_or_default()
+            .to_string();
+
+        Ok(Some(X11Window {
+            id,
+            pid: self
+                .atoms32(id, self.atoms._NET_WM_PID, AtomEnum::CARDINAL)?
+                .first()
+                .copied()
+                .unwrap_or(0),
+            title,
+            class,
+            x,
+            y,
+            width,
+            height,
+            z,
+            is_minimized: states.contains(&self.atoms._NET_WM_STATE_HIDDEN),
+            is_maximized: states.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_VERT)
+                && states.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_HORZ),
+            is_focused,
+        }))
+    }
+
+    fn property(
+        &self,
+        window: Window,
+        property: u32,
+        kind: impl Into<u32>,
+    ) -> Result<GetPropertyReply, String> {
+        self.connection
+            .get_property(false, window, property, kind.into(), 0, u32::MAX / 4)
+            .map_err(|e| e.to_string())?
+            .reply()
+            .map_err(|e| e.to_string())
+    }
+
+    fn atoms32(&self, window: Window, property: u32, kind: AtomEnum) -> Result<Vec<u32>, String> {
+        Ok(self
+            .property(window, property, kind)?
+            .value32()
+            .map(|value| value.collect())
+            .unwrap_or_default())
+    }
+
+    fn string(&self, window: Window, property: u32, kind: u32) -> Result<String, String> {
+        let value = self.property(window, property, kind)?.value;
+        Ok(String::from_utf8_lossy(&value)
+            .trim_end_matches('\0')
+            .to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_frame() {
+        assert_eq!(
+            with_frame((100, 80, 640, 480), &[4, 4, 28, 4]),
+            (96, 52, 648, 512)
+        );
+        assert_eq!(with_frame((100, 80, 640, 480), &[]), (100, 80, 640, 480));
+    }
+
+    #[test]
+    fn test_zpixmap_to_rgba() {
+        // One orange pixel and one translucent blue one, as BGRA in memory
+        let data = [0x00, 0x80, 0xff, 0x00, 0xff, 0x00, 0x00, 0x80];
+        let opaque = zpixmap_to_rgba(&data, 2, 1, 24, 32, ImageOrder::LSB_FIRST).unwrap();
+        assert_eq!(opaque.get_pixel(0, 0).0, [0xff, 0x80, 0x00, 0xff]);
+        assert_eq!(opaque.get_pixel(1, 0).0, [0x00, 0x00, 0xff, 0xff]);
+
+        let argb = zpixmap_to_rgba(&data, 2, 1, 32, 32, ImageOrder::LSB_FIRST).unwrap();
+        assert_eq!(argb.get_pixel(1, 0).0, [0x00, 0x00, 0xff, 0x80]);
+
+        assert!(zpixmap_to_rgba(&data, 2, 1, 16, 16, ImageOrder::LSB_FIRST).is_err());
+        assert!(zpixmap_to_rgba(&data, 2, 2, 24, 32, ImageOrder::LSB_FIRST).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use log::{error, info};
+use std::io::Write;
+use std::time::Duration;
+
+use crate::app::{CaptureMode, InvocationSource, Preferences};
+use crate::capture::window::{capture_window, list_capturable_windows};
+use crate::capture::{
+    capture_area, capture_primary_monitor, desktop_monitors, set_tonemap_operator,
+};
+
+/// Region in global screen coordinates, as printed by `slurp` ("X,Y WxH")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Geometry {
+    pub fn parse(text: &str) -> Option<Self> {
+        let (pos, size) = text.trim().split_once(' ')?;
+        let (x, y) = pos.split_once(',')?;
+        let (w, h) = size.trim().split_once('x')?;
+
+        let geometry = Self {
+            x: x.trim().parse().ok()?,
+            y: y.trim().parse().ok()?,
+            width: w.trim().parse().ok()?,
+            height: h.trim().parse().ok()?,
+        };
+
+        if geometry.width > 0 && geometry.height > 0 {
+            Some(geometry)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeTarget {
+    Screen,
+    Area(Geometry),
+    FocusedWindow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipeOptions {
+    /// `None` without a target flag, to capture what the last `--pipe` did
+    pub target: Option<PipeTarget>,
+    pub delay_seconds: u64,
+}
+
+impl PipeOptions {
+    pub fn from_args(args: &[String]) -> Result<Self, String> {
+        let mut target = None;
+        let mut delay_seconds = 0;
+
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--pipe" | "--verbose" | "-v" | "--log-file" => {}
+                "--screen" => target = Some(PipeTarget::Screen),
+                "--window" | "-w" => target = Some(PipeTarget::FocusedWindow),
+                "--area" | "-a" => {
+                    let value = iter.next().ok_or("--area requires a geometry")?;
+                    let geometry = Geometry::parse(value).ok_or_else(|| {
+                        format!("Invalid geometry '{}', expected 'X,Y WxH'", value)
+                    })?;
+                    target = Some(PipeTarget::Area(geometry));
+                }
+                "--delay" | "-d" => {
+                    let value = iter.next().ok_or("--delay requires a number of seconds")?;
+                    delay_seconds = value
+                        .parse()
+                        .map_err(|_| format!("Invalid delay '{}'", value))?;
+                }
+                "--selection" | "-s" => {
+                    return Err(
+                        "Interactive selection is not available with --pipe, use --area".into(),
+                    );
+                }
+                other => return Err(format!("Unknown argument '{}'", other)),
+            }
+        }
+
+        Ok(Self {
+            target,
+            delay_seconds,
+        })
+    }
+}
+
+/// Capture according to the command line and write the PNG to stdout.
+///
+/// Runs without GTK: there is no application or display connection, and the
+/// monitor layout `--area` needs comes from the capture backend, so it works
+/// from scripts and keeps stdout free of anything but image data. Returns the
+/// exit code.
+pub fn run_pipe(args: &[String]) -> i32 {
+    let options = match PipeOptions::from_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("screenshot_gnome: {}", e);
+            return 2;
+        }
+    };
+
+    let mut preferences = Preferences::load();
+    set_tonemap_operator(preferences.tonemap_operator);
+    let target = match options.target {
+        Some(target) => target,
+        None => match preferences.capture_mode(InvocationSource::Cli) {
+            CaptureMode::Window => PipeTarget::FocusedWindow,
+            _ => PipeTarget::Screen,
+        },
+    };
+    // An area can't be repeated without its geometry
+    let mode = match target {
+        PipeTarget::Screen => Some(CaptureMode::Screen),
+        PipeTarget::FocusedWindow => Some(CaptureMode::Window),
+        PipeTarget::Area(_) => None,
+    };
+    if let Some(mode) = mode {
+        preferences.remember_capture_mode(InvocationSource::Cli, mode);
+        preferences.save();
+    }
+
+    if options.delay_seconds > 0 {
+        std::thread::sleep(Duration::from_secs(options.delay_seconds));
+    }
+
+    let png = match capture_png(target) {
+        Ok(png) => png,
+        Err(e) => {
+            error!("Capture failed: {}", e);
+            eprintln!("screenshot_gnome: {}", e);
+            return 1;
+        }
+    };
+
+    let mut stdout = std::io::stdout().lock();
+    if let Err(e) = stdout.write_all(&png).and_then(|_| stdout.flush()) {
+        eprintln!("screenshot_gnome: failed to write to stdout: {}", e);
+        return 1;
+    }
+
+    info!("Wrote {} bytes of PNG data to stdout", png.len());
+    0
+}
+
+fn capture_png(target: PipeTarget) -> Result<Vec<u8>, String> {
+    let pixbuf = match target {
+        PipeTarget::Screen => capture_primary_monitor()?.pixbuf,
+        PipeTarget::Area(geometry) => {
+            let area = (geometry.x, geometry.y, geometry.width, geometry.height);
+            capture_area(area, &desktop_monitors()?)?.pixbuf
+        }
+        PipeTarget::FocusedWindow => {
+            let windows = list_capturable_windows().map_err(|e| e.to_string())?;
+            let focused = windows
+                .iter()
+                .find(|w| w.is_focused)
+                .ok_or("No focused window found")?;
+            capture_window(focused).map_err(|e| e.to_string())?.pixbuf
+        }
+    };
+
+    pixbuf
+        .save_to_bufferv("png", &[])
+        .map_err(|e| format!("Failed to encode PNG: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_geometry() {
+        assert_eq!(
+            Geometry::parse("10,20 300x200"),
+            Some(Geometry {
+                x: 10,
+                y: 20,
+                width: 300,
+                height: 200
+            })
+        );
+        assert_eq!(Geometry::parse("-5,0 10x0"), None);
+        assert_eq!(Geometry::parse("garbage"), None);
+    }
+
+    #[test]
+    fn test_pipe_options() {
+        let args: Vec<String> = [
+            "screenshot_gnome",
+            "--pipe",
+            "--area",
+            "0,0 10x10",
+            "-d",
+            "2",
+            "--verbose",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let options = PipeOptions::from_args(&args).unwrap();
+        assert_eq!(options.delay_seconds, 2);
+        assert!(matches!(options.target, Some(PipeTarget::Area(_))));
+
+        let args = vec![
+            "screenshot_gnome".to_string(),
+            "--pipe".to_string(),
+            "-s".to_string(),
+        ];
+        assert!(PipeOptions::from_args(&args).is_err());
+    }
+
+    #[test]
+    fn test_capture_area_with_mock_backend() {
+        std::env::set_var(crate::capture::mock::BACKEND_ENV, "mock");
+
+        let area = |x, y, width, height| {
+            PipeTarget::Area(Geometry {
+                x,
+                y,
+                width,
+                height,
+            })
+        };
+        let png = capture_png(area(100, 50, 200, 120)).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (200, 120));
+        // Inside the first synthetic window
+        assert_eq!(image.get_pixel(0, 0).0, [224, 27, 36, 255]);
+
+        assert!(capture_png(area(1200, 700, 200, 200)).is_err());
+        assert!(capture_png(area(-10, 0, 100, 100)).is_err());
+    }
+}
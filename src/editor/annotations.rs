@@ -1,6 +1,26 @@
 use gtk4::gdk::RGBA;
+use gtk4::{cairo, pango};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-#[derive(Clone, Debug)]
+use super::serialize::rgba_serde;
+
+/// Font family for text annotations. Pango falls back to other installed fonts
+/// for anything it lacks, so emoji and CJK render instead of showing boxes.
+const TEXT_FONT_FAMILY: &str = "Sans";
+
+/// Font family for the numbers on counter badges
+const COUNTER_FONT_FAMILY: &str = "Sans Bold";
+
+/// Freehand points closer than this to the previous one, in image pixels,
+/// are dropped as they come in
+pub const MIN_POINT_DISTANCE: f64 = 1.5;
+
+/// Largest deviation, in image pixels, allowed when a finished stroke is
+/// simplified
+pub const SIMPLIFY_TOLERANCE: f64 = 0.75;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -12,15 +32,19 @@ impl Point {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RectangleAnnotation {
     pub x: f64,
     pub y: f64,
     pub width: f64,
     pub height: f64,
+    #[serde(with = "rgba_serde")]
     pub color: RGBA,
     pub line_width: f64,
+    #[serde(default)]
     pub filled: bool,
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl RectangleAnnotation {
@@ -33,6 +57,7 @@ impl RectangleAnnotation {
             color,
             line_width,
             filled: false,
+            hidden: false,
         }
     }
 
@@ -77,11 +102,14 @@ impl RectangleAnnotation {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FreeDrawAnnotation {
     pub points: Vec<Point>,
+    #[serde(with = "rgba_serde")]
     pub color: RGBA,
     pub line_width: f64,
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl FreeDrawAnnotation {
@@ -90,13 +118,57 @@ impl FreeDrawAnnotation {
             points: Vec::new(),
             color,
             line_width,
+            hidden: false,
         }
     }
 
+    /// Append a point, unless it is within `MIN_POINT_DISTANCE` of the last one
     pub fn add_point(&mut self, x: f64, y: f64) {
+        if let Some(last) = self.points.last() {
+            let (dx, dy) = (x - last.x, y - last.y);
+            if dx * dx + dy * dy < MIN_POINT_DISTANCE * MIN_POINT_DISTANCE {
+                return;
+            }
+        }
         self.points.push(Point::new(x, y));
     }
 
+    /// Drop points that deviate less than `tolerance` from the line through
+    /// their neighbours (Ramer–Douglas–Peucker), keeping both ends
+    pub fn simplify(&mut self, tolerance: f64) {
+        let count = self.points.len();
+        if count < 3 {
+            return;
+        }
+
+        let mut keep = vec![false; count];
+        keep[0] = true;
+        keep[count - 1] = true;
+
+        // Explicit stack, as long strokes would recurse too deep
+        let mut ranges = vec![(0, count - 1)];
+        while let Some((first, last)) = ranges.pop() {
+            let (a, b) = (&self.points[first], &self.points[last]);
+            let farthest = (first + 1..last)
+                .map(|i| {
+                    let p = &self.points[i];
+                    (i, point_to_segment_distance(p.x, p.y, a.x, a.y, b.x, b.y))
+                })
+                .max_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+
+            if let Some((index, distance)) = farthest {
+                if distance > tolerance {
+                    keep[index] = true;
+                    ranges.push((first, index));
+                    ranges.push((index, last));
+                }
+            }
+        }
+
+        let mut kept = keep.into_iter();
+        self.points.retain(|_| kept.next().unwrap_or(false));
+    }
+
     pub fn hit_test(&self, px: f64, py: f64) -> bool {
         let margin = self.line_width.max(8.0);
 
@@ -147,13 +219,16 @@ impl FreeDrawAnnotation {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TextAnnotation {
     pub x: f64,
     pub y: f64,
     pub text: String,
+    #[serde(with = "rgba_serde")]
     pub color: RGBA,
     pub font_size: f64,
+    #[serde(default)]
+    pub hidden: bool,
 }
 
 impl TextAnnotation {
@@ -164,19 +239,75 @@ impl TextAnnotation {
             text,
             color,
             font_size,
+            hidden: false,
         }
     }
 
+    /// Layout for this text at `font_size` pixels; `y` is the baseline, so
+    /// draw it `baseline` pixels higher
+    fn layout(&self, cr: &cairo::Context, font_size: f64) -> (pango::Layout, f64) {
+        let layout = pangocairo::functions::create_layout(cr);
+
+        let mut font = pango::FontDescription::new();
+        font.set_family(TEXT_FONT_FAMILY);
+        font.set_absolute_size(font_size * pango::SCALE as f64);
+        layout.set_font_description(Some(&font));
+        layout.set_text(&self.text);
+
+        let baseline = layout.baseline() as f64 / pango::SCALE as f64;
+        (layout, baseline)
+    }
+
+    /// Lay the text out at its own size for measuring, on a scratch surface
+    fn measure<T>(&self, f: impl FnOnce(&pango::Layout, f64) -> T) -> Option<T> {
+        let cr = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+            .and_then(|surface| cairo::Context::new(&surface))
+            .ok()?;
+        let (layout, baseline) = self.layout(&cr, self.font_size);
+        Some(f(&layout, baseline))
+    }
+
+    /// `(x, y, width, height)` of the rendered text in image coordinates
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        let measured = self.measure(|layout, baseline| {
+            let (_, logical) = layout.pixel_extents();
+            (
+                self.x + logical.x() as f64,
+                self.y - baseline + logical.y() as f64,
+                logical.width() as f64,
+                logical.height() as f64,
+            )
+        });
+
+        // Rough estimate if cairo cannot even allocate a 1×1 surface
+        measured.unwrap_or_else(|| {
+            let width = self.text.chars().count() as f64 * self.font_size * 0.6;
+            (self.x, self.y - self.font_size, width, self.font_size)
+        })
+    }
+
+    /// Caret before byte `index` of the text as `(x, y, height)` in image coordinates
+    pub fn caret_position(&self, index: usize) -> (f64, f64, f64) {
+        let scale = pango::SCALE as f64;
+        self.measure(|layout, baseline| {
+            let pos = layout.index_to_pos(index as i32);
+            (
+                self.x + pos.x() as f64 / scale,
+                self.y - baseline + pos.y() as f64 / scale,
+                pos.height() as f64 / scale,
+            )
+        })
+        .unwrap_or((self.x, self.y - self.font_size, self.font_size))
+    }
+
     pub fn hit_test(&self, px: f64, py: f64) -> bool {
-        let approx_char_width = self.font_size * 0.6;
-        let text_width = self.text.len() as f64 * approx_char_width;
-        let text_height = self.font_size;
+        let (x, y, width, height) = self.bounds();
 
         let margin = 5.0;
-        px >= self.x - margin
-            && px <= self.x + text_width + margin
-            && py >= self.y - text_height - margin
-            && py <= self.y + margin
+        px >= x - margin
+            && px <= x + width + margin
+            && py >= y - margin
+            && py <= y + height + margin
     }
 
     pub fn move_by(&mut self, dx: f64, dy: f64) {
@@ -185,6 +316,95 @@ impl TextAnnotation {
     }
 }
 
+/// Numbered badge marking a step, a filled circle centered on `(x, y)`
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CounterAnnotation {
+    pub x: f64,
+    pub y: f64,
+    pub number: u32,
+    #[serde(with = "rgba_serde")]
+    pub color: RGBA,
+    pub radius: f64,
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+impl CounterAnnotation {
+    pub fn new(x: f64, y: f64, number: u32, color: RGBA, radius: f64) -> Self {
+        Self {
+            x,
+            y,
+            number,
+            color,
+            radius,
+            hidden: false,
+        }
+    }
+
+    pub fn bounds(&self) -> (f64, f64, f64, f64) {
+        (
+            self.x - self.radius,
+            self.y - self.radius,
+            self.radius * 2.0,
+            self.radius * 2.0,
+        )
+    }
+
+    pub fn hit_test(&self, px: f64, py: f64) -> bool {
+        let (dx, dy) = (px - self.x, py - self.y);
+        let reach = self.radius + 3.0;
+        dx * dx + dy * dy <= reach * reach
+    }
+
+    pub fn move_by(&mut self, dx: f64, dy: f64) {
+        self.x += dx;
+        self.y += dy;
+    }
+
+    /// Black or white, whichever reads better on the badge color
+    fn number_color(&self) -> (f64, f64, f64) {
+        let luminance = 0.299 * self.color.red() as f64
+            + 0.587 * self.color.green() as f64
+            + 0.114 * self.color.blue() as f64;
+        if luminance > 0.6 {
+            (0.0, 0.0, 0.0)
+        } else {
+            (1.0, 1.0, 1.0)
+        }
+    }
+
+    fn draw(&self, cr: &cairo::Context, scale: f64, offset_x: f64, offset_y: f64) {
+        let x = offset_x + self.x * scale;
+        let y = offset_y + self.y * scale;
+        let radius = self.radius * scale;
+
+        cr.set_source_rgba(
+            self.color.red() as f64,
+            self.color.green() as f64,
+            self.color.blue() as f64,
+            self.color.alpha() as f64,
+        );
+        cr.arc(x, y, radius, 0.0, std::f64::consts::TAU);
+        let _ = cr.fill();
+
+        let layout = pangocairo::functions::create_layout(cr);
+        let mut font = pango::FontDescription::from_string(COUNTER_FONT_FAMILY);
+        // Two digits still fit inside the circle
+        font.set_absolute_size(radius * 1.1 * pango::SCALE as f64);
+        layout.set_font_description(Some(&font));
+        layout.set_text(&self.number.to_string());
+
+        let (_, logical) = layout.pixel_extents();
+        let (red, green, blue) = self.number_color();
+        cr.set_source_rgba(red, green, blue, self.color.alpha() as f64);
+        cr.move_to(
+            x - logical.width() as f64 / 2.0 - logical.x() as f64,
+            y - logical.height() as f64 / 2.0 - logical.y() as f64,
+        );
+        pangocairo::functions::show_layout(cr, &layout);
+    }
+}
+
 fn point_to_segment_distance(px: f64, py: f64, x1: f64, y1: f64, x2: f64, y2: f64) -> f64 {
     let dx = x2 - x1;
     let dy = y2 - y1;
@@ -207,11 +427,13 @@ fn point_to_segment_distance(px: f64, py: f64, x1: f64, y1: f64, x2: f64, y2: f6
     (dpx * dpx + dpy * dpy).sqrt()
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Annotation {
     Rectangle(RectangleAnnotation),
     FreeDraw(FreeDrawAnnotation),
     Text(TextAnnotation),
+    Counter(CounterAnnotation),
 }
 
 impl Annotation {
@@ -220,6 +442,7 @@ impl Annotation {
             Annotation::Rectangle(rect) => rect.hit_test(px, py),
             Annotation::FreeDraw(draw) => draw.hit_test(px, py),
             Annotation::Text(text) => text.hit_test(px, py),
+            Annotation::Counter(counter) => counter.hit_test(px, py),
         }
     }
 
@@ -228,6 +451,57 @@ impl Annotation {
             Annotation::Rectangle(rect) => rect.move_by(dx, dy),
             Annotation::FreeDraw(draw) => draw.move_by(dx, dy),
             Annotation::Text(text) => text.move_by(dx, dy),
+            Annotation::Counter(counter) => counter.move_by(dx, dy),
+        }
+    }
+
+    /// Hidden from the layers panel, and left out of the canvas and exports
+    pub fn is_hidden(&self) -> bool {
+        match self {
+            Annotation::Rectangle(rect) => rect.hidden,
+            Annotation::FreeDraw(draw) => draw.hidden,
+            Annotation::Text(text) => text.hidden,
+            Annotation::Counter(counter) => counter.hidden,
+        }
+    }
+
+    fn set_hidden(&mut self, hidden: bool) {
+        match self {
+            Annotation::Rectangle(rect) => rect.hidden = hidden,
+            Annotation::FreeDraw(draw) => draw.hidden = hidden,
+            Annotation::Text(text) => text.hidden = hidden,
+            Annotation::Counter(counter) => counter.hidden = hidden,
+        }
+    }
+
+    /// Name of the annotation kind, as the undo history lists it
+    pub fn label(&self) -> &'static str {
+        match self {
+            Annotation::Rectangle(_) => "Rectangle",
+            Annotation::FreeDraw(_) => "Free Draw",
+            Annotation::Text(_) => "Text",
+            Annotation::Counter(_) => "Counter",
+        }
+    }
+
+    /// `(x, y, width, height)` covered by the annotation, `None` for an
+    /// empty stroke
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        match self {
+            Annotation::Rectangle(rect) => Some((rect.x, rect.y, rect.width, rect.height)),
+            Annotation::FreeDraw(draw) => draw.bounding_box(),
+            Annotation::Text(text) => Some(text.bounds()),
+            Annotation::Counter(counter) => Some(counter.bounds()),
+        }
+    }
+
+    /// Whether any of the annotation lies within `(x, y, width, height)`
+    pub fn intersects(&self, (x, y, width, height): (f64, f64, f64, f64)) -> bool {
+        match self.bounds() {
+            Some((ax, ay, aw, ah)) => {
+                ax <= x + width && ax + aw >= x && ay <= y + height && ay + ah >= y
+            }
+            None => false,
         }
     }
 
@@ -242,10 +516,14 @@ impl Annotation {
                 }
             }
             Annotation::Text(text) => (text.x, text.y),
+            Annotation::Counter(counter) => (counter.x, counter.y),
         }
     }
 
     pub fn draw(&self, cr: &gtk4::cairo::Context, scale: f64, offset_x: f64, offset_y: f64) {
+        if self.is_hidden() {
+            return;
+        }
         match self {
             Annotation::Rectangle(rect) => {
                 cr.set_source_rgba(
@@ -301,15 +579,15 @@ impl Annotation {
                     text.color.alpha() as f64,
                 );
 
-                let font_size = text.font_size * scale;
-                cr.set_font_size(font_size);
+                let (layout, baseline) = text.layout(cr, text.font_size * scale);
 
                 let x = offset_x + text.x * scale;
                 let y = offset_y + text.y * scale;
 
-                cr.move_to(x, y);
-                let _ = cr.show_text(&text.text);
+                cr.move_to(x, y - baseline);
+                pangocairo::functions::show_layout(cr, &layout);
             }
+            Annotation::Counter(counter) => counter.draw(cr, scale, offset_x, offset_y),
         }
     }
 
@@ -331,11 +609,8 @@ impl Annotation {
                     return;
                 }
             }
-            Annotation::Text(text) => {
-                let approx_char_width = text.font_size * 0.6;
-                let text_width = text.text.len() as f64 * approx_char_width;
-                (text.x, text.y - text.font_size, text_width, text.font_size)
-            }
+            Annotation::Text(text) => text.bounds(),
+            Annotation::Counter(counter) => counter.bounds(),
         };
 
         let margin = 4.0;
@@ -386,12 +661,143 @@ impl Annotation {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+/// A change to the committed annotations, by position in the list
+#[derive(Clone, Debug)]
+enum Edit {
+    Insert {
+        index: usize,
+        annotation: Annotation,
+    },
+    Remove {
+        index: usize,
+    },
+    Move {
+        index: usize,
+        dx: f64,
+        dy: f64,
+    },
+    Reorder {
+        from: usize,
+        to: usize,
+    },
+    SetHidden {
+        index: usize,
+        hidden: bool,
+    },
+}
+
+impl Edit {
+    /// Make the change, returning the edit that takes it back, or `None` if
+    /// `index` is out of range
+    fn apply(self, annotations: &mut Vec<Annotation>) -> Option<Edit> {
+        match self {
+            Edit::Insert { index, annotation } => {
+                if index > annotations.len() {
+                    return None;
+                }
+                annotations.insert(index, annotation);
+                Some(Edit::Remove { index })
+            }
+            Edit::Remove { index } => {
+                if index >= annotations.len() {
+                    return None;
+                }
+                let annotation = annotations.remove(index);
+                Some(Edit::Insert { index, annotation })
+            }
+            Edit::Move { index, dx, dy } => {
+                annotations.get_mut(index)?.move_by(dx, dy);
+                Some(Edit::Move {
+                    index,
+                    dx: -dx,
+                    dy: -dy,
+                })
+            }
+            Edit::Reorder { from, to } => {
+                if from >= annotations.len() || to >= annotations.len() {
+                    return None;
+                }
+                let annotation = annotations.remove(from);
+                annotations.insert(to, annotation);
+                Some(Edit::Reorder { from: to, to: from })
+            }
+            Edit::SetHidden { index, hidden } => {
+                let annotation = annotations.get_mut(index)?;
+                let was_hidden = annotation.is_hidden();
+                annotation.set_hidden(hidden);
+                Some(Edit::SetHidden {
+                    index,
+                    hidden: was_hidden,
+                })
+            }
+        }
+    }
+}
+
+/// Where the annotation at `index` ends up when the one at `from` is moved
+/// to `to`
+fn reordered_index(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+/// Source of `AnnotationList::revision`, shared so that no two lists, or two
+/// states of one, have the same revision
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(1);
+
+/// An entry of the undo or redo history: what it is called, and the edit
+/// that undoes it (or, on the redo side, does it again)
+#[derive(Clone, Debug)]
+struct Step {
+    label: &'static str,
+    edit: Edit,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(from = "SavedAnnotationList")]
 pub struct AnnotationList {
     annotations: Vec<Annotation>,
+    #[serde(skip)]
     current_annotation: Option<Annotation>,
 
+    #[serde(skip)]
     selected_index: Option<usize>,
+
+    /// Changes that can be undone, oldest first
+    #[serde(skip)]
+    undo_steps: Vec<Step>,
+
+    /// Changes undone that can be redone, most recently undone last
+    #[serde(skip)]
+    redo_steps: Vec<Step>,
+
+    #[serde(skip)]
+    revision: u64,
+}
+
+/// Annotations as saved, without their history
+#[derive(Deserialize)]
+struct SavedAnnotationList {
+    annotations: Vec<Annotation>,
+}
+
+impl From<SavedAnnotationList> for AnnotationList {
+    /// Each loaded annotation is a step of its own, as if it had just been
+    /// drawn
+    fn from(saved: SavedAnnotationList) -> Self {
+        let mut list = Self::new();
+        for annotation in saved.annotations {
+            list.add(annotation);
+        }
+        list
+    }
 }
 
 impl AnnotationList {
@@ -400,49 +806,201 @@ impl AnnotationList {
             annotations: Vec::new(),
             current_annotation: None,
             selected_index: None,
+            undo_steps: Vec::new(),
+            redo_steps: Vec::new(),
+            revision: 0,
         }
     }
 
+    /// Changes whenever the annotations, their order or visibility or the
+    /// selection do, so views of the list can tell when to update. Moving an
+    /// annotation while dragging it leaves it as it is.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn touch(&mut self) {
+        self.revision = NEXT_REVISION.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn add(&mut self, annotation: Annotation) {
+        self.push_step(
+            annotation.label(),
+            Edit::Remove {
+                index: self.annotations.len(),
+            },
+        );
         self.annotations.push(annotation);
     }
 
+    /// Remember a change just made, which starts a new branch of history
+    fn push_step(&mut self, label: &'static str, edit: Edit) {
+        self.undo_steps.push(Step { label, edit });
+        self.redo_steps.clear();
+        self.touch();
+    }
+
     pub fn set_current(&mut self, annotation: Option<Annotation>) {
         self.current_annotation = annotation;
     }
 
     pub fn commit_current(&mut self) {
         if let Some(annotation) = self.current_annotation.take() {
-            self.annotations.push(annotation);
+            self.add(annotation);
         }
     }
 
+    /// Take back the latest change: an annotation added, moved or deleted
     pub fn undo(&mut self) -> bool {
         self.selected_index = None;
-        self.annotations.pop().is_some()
+        self.touch();
+        match self.undo_steps.pop() {
+            Some(step) => match step.edit.apply(&mut self.annotations) {
+                Some(edit) => {
+                    self.redo_steps.push(Step {
+                        label: step.label,
+                        edit,
+                    });
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Make the change last undone again
+    pub fn redo(&mut self) -> bool {
+        self.selected_index = None;
+        self.touch();
+        match self.redo_steps.pop() {
+            Some(step) => match step.edit.apply(&mut self.annotations) {
+                Some(edit) => {
+                    self.undo_steps.push(Step {
+                        label: step.label,
+                        edit,
+                    });
+                    true
+                }
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Names of the changes `undo` can take back, oldest first
+    pub fn history(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.undo_steps.iter().map(|step| step.label)
+    }
+
+    /// Number of changes `undo` can take back
+    pub fn undo_count(&self) -> usize {
+        self.undo_steps.len()
+    }
+
+    /// Note that annotation `index` was moved by `(dx, dy)`, already applied
+    pub fn record_move(&mut self, index: usize, dx: f64, dy: f64) {
+        if index < self.annotations.len() && (dx != 0.0 || dy != 0.0) {
+            self.push_step(
+                "Move",
+                Edit::Move {
+                    index,
+                    dx: -dx,
+                    dy: -dy,
+                },
+            );
+        }
+    }
+
+    /// Remove the selected annotation, as a change that can be undone
+    pub fn delete_selected(&mut self) -> bool {
+        let index = match self.selected_index.take() {
+            Some(index) if index < self.annotations.len() => index,
+            _ => return false,
+        };
+        let annotation = self.annotations.remove(index);
+        self.push_step("Delete", Edit::Insert { index, annotation });
+        true
+    }
+
+    /// Draw annotation `from` at position `to` in the stacking order
+    /// instead, shifting the ones between by one. The selection stays on the
+    /// same annotations.
+    pub fn reorder(&mut self, from: usize, to: usize) -> bool {
+        if from == to || from >= self.annotations.len() || to >= self.annotations.len() {
+            return false;
+        }
+        let selected = self
+            .selected_index
+            .map(|index| reordered_index(index, from, to));
+        if let Some(edit) = (Edit::Reorder { from, to }).apply(&mut self.annotations) {
+            self.push_step("Reorder", edit);
+        }
+        self.selected_index = selected;
+        true
+    }
+
+    /// Hide or show annotation `index`, as a change that can be undone. A
+    /// hidden annotation can't be selected.
+    pub fn set_hidden(&mut self, index: usize, hidden: bool) -> bool {
+        match self.annotations.get(index) {
+            Some(annotation) if annotation.is_hidden() != hidden => {}
+            _ => return false,
+        }
+        if let Some(edit) = (Edit::SetHidden { index, hidden }).apply(&mut self.annotations) {
+            self.push_step(if hidden { "Hide" } else { "Show" }, edit);
+        }
+        if hidden && self.selected_index == Some(index) {
+            self.selected_index = None;
+        }
+        true
     }
 
     pub fn clear(&mut self) {
         self.annotations.clear();
         self.current_annotation = None;
         self.selected_index = None;
+        self.undo_steps.clear();
+        self.redo_steps.clear();
+        self.touch();
+    }
+
+    /// Remove all committed annotations, oldest first, along with their
+    /// history
+    pub fn take_all(&mut self) -> Vec<Annotation> {
+        self.selected_index = None;
+        self.undo_steps.clear();
+        self.redo_steps.clear();
+        self.touch();
+        std::mem::take(&mut self.annotations)
     }
 
     pub fn current(&self) -> Option<&Annotation> {
         self.current_annotation.as_ref()
     }
 
+    pub fn current_mut(&mut self) -> Option<&mut Annotation> {
+        self.current_annotation.as_mut()
+    }
+
     pub fn set_selected(&mut self, index: Option<usize>) {
-        self.selected_index = index;
+        if self.selected_index != index {
+            self.selected_index = index;
+            self.touch();
+        }
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected_index
     }
 
     pub fn deselect(&mut self) {
-        self.selected_index = None;
+        self.set_selected(None);
     }
 
     pub fn hit_test(&self, px: f64, py: f64) -> Option<usize> {
         for (i, annotation) in self.annotations.iter().enumerate().rev() {
-            if annotation.hit_test(px, py) {
+            if !annotation.is_hidden() && annotation.hit_test(px, py) {
                 return Some(i);
             }
         }
@@ -469,7 +1027,7 @@ impl AnnotationList {
 
     pub fn draw_all(&self, cr: &gtk4::cairo::Context, scale: f64, offset_x: f64, offset_y: f64) {
         for (i, annotation) in self.annotations.iter().enumerate() {
-            if Some(i) == self.selected_index {
+            if Some(i) == self.selected_index && !annotation.is_hidden() {
                 annotation.draw_selected(cr, scale, offset_x, offset_y);
             } else {
                 annotation.draw(cr, scale, offset_x, offset_y);
@@ -484,4 +1042,167 @@ impl AnnotationList {
     pub fn len(&self) -> usize {
         self.annotations.len()
     }
+
+    /// Committed annotations, oldest first
+    pub fn iter(&self) -> std::slice::Iter<'_, Annotation> {
+        self.annotations.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    /// Number for the next counter badge: one past the highest on the
+    /// image, so it starts over on every capture and follows undo
+    pub fn next_counter_number(&self) -> u32 {
+        self.annotations
+            .iter()
+            .filter_map(|annotation| match annotation {
+                Annotation::Counter(counter) => Some(counter.number),
+                _ => None,
+            })
+            .max()
+            .map_or(1, |number| number + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_free_draw_decimation() {
+        let mut draw = FreeDrawAnnotation::new(RGBA::BLACK, 3.0);
+        for i in 0..100 {
+            draw.add_point(i as f64 * 0.5, 0.0);
+        }
+        assert_eq!(draw.points.len(), 34);
+
+        // A straight run collapses to its ends, a corner survives
+        for i in 1..=50 {
+            draw.add_point(49.5, i as f64 * 2.0);
+        }
+        draw.simplify(SIMPLIFY_TOLERANCE);
+        let points: Vec<(f64, f64)> = draw.points.iter().map(|p| (p.x, p.y)).collect();
+        assert_eq!(points, vec![(0.0, 0.0), (49.5, 0.0), (49.5, 100.0)]);
+    }
+
+    #[test]
+    fn test_undo_redo() {
+        let rectangle = |x| {
+            Annotation::Rectangle(RectangleAnnotation::new(
+                x,
+                0.0,
+                10.0,
+                10.0,
+                RGBA::BLACK,
+                3.0,
+            ))
+        };
+        let positions =
+            |list: &AnnotationList| list.iter().map(Annotation::position).collect::<Vec<_>>();
+
+        let mut list = AnnotationList::new();
+        list.add(rectangle(0.0));
+        list.add(rectangle(50.0));
+        list.set_selected(Some(0));
+        list.move_selected(5.0, 5.0);
+        list.record_move(0, 5.0, 5.0);
+        list.set_selected(Some(1));
+        assert!(list.delete_selected());
+        assert_eq!(
+            list.history().collect::<Vec<_>>(),
+            vec!["Rectangle", "Rectangle", "Move", "Delete"]
+        );
+
+        assert!(list.undo());
+        assert!(list.undo());
+        assert_eq!(positions(&list), vec![(0.0, 0.0), (50.0, 0.0)]);
+        assert!(list.redo());
+        assert_eq!(positions(&list), vec![(5.0, 5.0), (50.0, 0.0)]);
+        assert!(list.redo());
+        assert_eq!(positions(&list), vec![(5.0, 5.0)]);
+        assert!(!list.redo());
+
+        // A new change drops what was undone
+        assert!(list.undo());
+        list.add(rectangle(90.0));
+        assert!(!list.redo());
+        assert_eq!(list.undo_count(), 4);
+    }
+
+    #[test]
+    fn test_reorder_and_hide() {
+        let rectangle = |x| {
+            Annotation::Rectangle(RectangleAnnotation::new(
+                x,
+                0.0,
+                10.0,
+                10.0,
+                RGBA::BLACK,
+                3.0,
+            ))
+        };
+        let positions = |list: &AnnotationList| {
+            list.iter()
+                .map(|annotation| annotation.position().0)
+                .collect::<Vec<_>>()
+        };
+
+        let mut list = AnnotationList::new();
+        for x in [0.0, 20.0, 40.0] {
+            list.add(rectangle(x));
+        }
+        list.set_selected(Some(1));
+        let revision = list.revision();
+        assert!(list.reorder(0, 2));
+        assert_ne!(list.revision(), revision);
+        assert_eq!(positions(&list), vec![20.0, 40.0, 0.0]);
+        assert_eq!(list.selected(), Some(0));
+
+        // Hidden annotations can't be picked
+        assert_eq!(list.hit_test(25.0, 5.0), Some(0));
+        assert!(list.set_hidden(0, true));
+        assert!(!list.set_hidden(0, true));
+        assert_eq!(list.selected(), None);
+        assert_eq!(list.hit_test(25.0, 5.0), None);
+
+        assert_eq!(
+            list.history().skip(3).collect::<Vec<_>>(),
+            vec!["Reorder", "Hide"]
+        );
+        assert!(list.undo());
+        assert!(!list.iter().next().unwrap().is_hidden());
+        assert!(list.undo());
+        assert_eq!(positions(&list), vec![0.0, 20.0, 40.0]);
+    }
+
+    #[test]
+    fn test_next_counter_number() {
+        let mut list = AnnotationList::new();
+        assert_eq!(list.next_counter_number(), 1);
+
+        for number in [1, 2, 5] {
+            list.add(Annotation::Counter(CounterAnnotation::new(
+                0.0,
+                0.0,
+                number,
+                RGBA::BLACK,
+                12.0,
+            )));
+        }
+        list.add(Annotation::Rectangle(RectangleAnnotation::new(
+            0.0,
+            0.0,
+            10.0,
+            10.0,
+            RGBA::BLACK,
+            3.0,
+        )));
+        assert_eq!(list.next_counter_number(), 6);
+
+        list.undo();
+        list.undo();
+        assert_eq!(list.next_counter_number(), 3);
+    }
 }
@@ -1,31 +1,135 @@
-use gtk4::gdk::Texture;
-use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::gdk::{ContentProvider, Texture};
+use gtk4::gdk_pixbuf::{Pixbuf, PixbufLoader};
+use gtk4::glib;
 use gtk4::prelude::*;
+use log::debug;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub type ClipboardResult<T> = Result<T, ClipboardError>;
 
 #[derive(Debug)]
-pub enum ClipboardError {}
+pub enum ClipboardError {
+    TempFile(String),
+
+    SetContent(String),
+
+    ReadContent(String),
+}
 
 impl std::fmt::Display for ClipboardError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Clipboard error")
+        match self {
+            Self::TempFile(msg) => write!(f, "Clipboard error: failed to write temp file: {}", msg),
+            Self::SetContent(msg) => write!(f, "Clipboard error: {}", msg),
+            Self::ReadContent(msg) => write!(f, "Clipboard error: failed to read image: {}", msg),
+        }
     }
 }
 
 impl std::error::Error for ClipboardError {}
 
+/// Put the image on the clipboard in several formats at once.
+///
+/// Image editors get `image/png` (and the texture itself), file managers get a
+/// `text/uri-list` pointing at a copy in the cache directory, and when the
+/// image was uploaded, chat apps get the link as plain text.
 pub fn copy_pixbuf_to_clipboard(
     pixbuf: &Pixbuf,
+    upload_url: Option<&str>,
     display: &gtk4::gdk::Display,
 ) -> ClipboardResult<()> {
     let clipboard = display.clipboard();
 
     let texture = Texture::for_pixbuf(pixbuf);
+    let png = texture.save_to_png_bytes();
 
-    clipboard.set_texture(&texture);
+    let mut providers = vec![
+        ContentProvider::for_bytes("image/png", &png),
+        ContentProvider::for_value(&texture.to_value()),
+    ];
+
+    match write_temp_png(&png) {
+        Ok(path) => {
+            let uri = glib::filename_to_uri(&path, None)
+                .map_err(|e| ClipboardError::TempFile(e.to_string()))?;
+            let uri_list = format!("{}\r\n", uri);
+            providers.push(ContentProvider::for_bytes(
+                "text/uri-list",
+                &glib::Bytes::from_owned(uri_list.into_bytes()),
+            ));
+        }
+        Err(e) => debug!("Skipping text/uri-list clipboard target: {}", e),
+    }
 
-    Ok(())
+    if let Some(url) = upload_url {
+        providers.push(ContentProvider::for_bytes(
+            "text/plain;charset=utf-8",
+            &glib::Bytes::from_owned(url.as_bytes().to_vec()),
+        ));
+    }
+
+    clipboard
+        .set_content(Some(&ContentProvider::new_union(&providers)))
+        .map_err(|e| ClipboardError::SetContent(e.to_string()))
+}
+
+/// Directory holding the files referenced by copied `text/uri-list` entries
+pub fn clipboard_cache_dir() -> PathBuf {
+    glib::user_cache_dir()
+        .join("screenshot_gnome")
+        .join("clipboard")
+}
+
+/// Write the copied image to the cache directory, removing the copies made
+/// before it: the clipboard holds one image at a time, so only the newest
+/// file can still be pasted
+fn write_temp_png(png: &glib::Bytes) -> ClipboardResult<PathBuf> {
+    let dir = clipboard_cache_dir();
+    std::fs::create_dir_all(&dir).map_err(|e| ClipboardError::TempFile(e.to_string()))?;
+    remove_old_copies(&dir);
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let path = dir.join(format!("screenshot_{}.png", timestamp));
+
+    std::fs::write(&path, png).map_err(|e| ClipboardError::TempFile(e.to_string()))?;
+    Ok(path)
+}
+
+fn remove_old_copies(dir: &Path) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("");
+        if name.starts_with("screenshot_") && name.ends_with(".png") {
+            if let Err(e) = std::fs::remove_file(&path) {
+                debug!("Failed to remove old clipboard copy {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+fn texture_to_pixbuf(texture: &Texture) -> ClipboardResult<Pixbuf> {
+    let loader =
+        PixbufLoader::with_type("png").map_err(|e| ClipboardError::ReadContent(e.to_string()))?;
+    loader
+        .write(&texture.save_to_png_bytes())
+        .and_then(|_| loader.close())
+        .map_err(|e| ClipboardError::ReadContent(e.to_string()))?;
+    loader
+        .pixbuf()
+        .ok_or_else(|| ClipboardError::ReadContent("no image decoded".to_string()))
 }
 
 pub struct ClipboardManager {
@@ -39,7 +143,22 @@ impl ClipboardManager {
         }
     }
 
-    pub fn copy_image(&self, pixbuf: &Pixbuf) -> ClipboardResult<()> {
-        copy_pixbuf_to_clipboard(pixbuf, &self.display)
+    pub fn copy_image(&self, pixbuf: &Pixbuf, upload_url: Option<&str>) -> ClipboardResult<()> {
+        copy_pixbuf_to_clipboard(pixbuf, upload_url, &self.display)
+    }
+
+    /// The image currently on the clipboard, or `None` if it holds no image
+    pub async fn read_image(&self) -> ClipboardResult<Option<Pixbuf>> {
+        let texture = self
+            .display
+            .clipboard()
+            .read_texture_future()
+            .await
+            .map_err(|e| ClipboardError::ReadContent(e.to_string()))?;
+
+        match texture {
+            Some(texture) => texture_to_pixbuf(&texture).map(Some),
+            None => Ok(None),
+        }
     }
 }
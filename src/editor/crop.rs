@@ -0,0 +1,196 @@
+//! Adjusting the crop rectangle after it has been drawn, and keeping the
+//! uncropped image around once it is applied.
+//!
+//! Rectangles are `(x, y, width, height)` in image coordinates, as returned by
+//! [`ToolState::get_drag_rect`](super::ToolState::get_drag_rect).
+
+use gtk4::gdk_pixbuf::Pixbuf;
+
+use super::Annotation;
+
+/// Distance from a handle, in display pixels, that still grabs it
+pub const CROP_HANDLE_RADIUS: f64 = 8.0;
+
+/// Smallest crop, in image pixels, resizing with the handles leaves
+pub const MIN_CROP_SIZE: f64 = 16.0;
+
+/// Applied crop. The editor shows a view into `original`, so the crop can be
+/// undone or adjusted later without losing any pixels.
+#[derive(Clone, Debug)]
+pub struct EditorCrop {
+    pub original: Pixbuf,
+
+    /// Cropped area in the original's pixels
+    pub region: (i32, i32, i32, i32),
+
+    /// Annotations left entirely outside the crop, in the original's
+    /// coordinates
+    pub hidden_annotations: Vec<Annotation>,
+
+    /// Steps in the annotation history right after cropping, one for each
+    /// annotation kept; undoing past them undoes the crop
+    pub annotation_count: usize,
+}
+
+/// Part of the crop rectangle grabbed by a drag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CropHandle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+
+    /// The interior, moving the whole rectangle
+    Move,
+}
+
+impl CropHandle {
+    /// Handles drawn on the rectangle, corners first so they win where an
+    /// edge handle overlaps them on a small crop
+    pub const RESIZE: [CropHandle; 8] = [
+        CropHandle::TopLeft,
+        CropHandle::TopRight,
+        CropHandle::BottomRight,
+        CropHandle::BottomLeft,
+        CropHandle::Top,
+        CropHandle::Right,
+        CropHandle::Bottom,
+        CropHandle::Left,
+    ];
+
+    /// Center of the handle on `rect`
+    pub fn position(self, (x, y, w, h): (f64, f64, f64, f64)) -> (f64, f64) {
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+        match self {
+            CropHandle::TopLeft => (x, y),
+            CropHandle::Top => (cx, y),
+            CropHandle::TopRight => (x + w, y),
+            CropHandle::Right => (x + w, cy),
+            CropHandle::BottomRight => (x + w, y + h),
+            CropHandle::Bottom => (cx, y + h),
+            CropHandle::BottomLeft => (x, y + h),
+            CropHandle::Left => (x, cy),
+            CropHandle::Move => (cx, cy),
+        }
+    }
+
+    pub fn cursor_name(self) -> &'static str {
+        match self {
+            CropHandle::TopLeft => "nw-resize",
+            CropHandle::Top => "n-resize",
+            CropHandle::TopRight => "ne-resize",
+            CropHandle::Right => "e-resize",
+            CropHandle::BottomRight => "se-resize",
+            CropHandle::Bottom => "s-resize",
+            CropHandle::BottomLeft => "sw-resize",
+            CropHandle::Left => "w-resize",
+            CropHandle::Move => "move",
+        }
+    }
+
+    /// Handle of `rect` at `(px, py)`, grabbing handles within `radius`
+    pub fn at(rect: (f64, f64, f64, f64), px: f64, py: f64, radius: f64) -> Option<CropHandle> {
+        let handle = Self::RESIZE.into_iter().find(|handle| {
+            let (hx, hy) = handle.position(rect);
+            (px - hx).abs() <= radius && (py - hy).abs() <= radius
+        });
+        if handle.is_some() {
+            return handle;
+        }
+
+        let (x, y, w, h) = rect;
+        if px > x && px < x + w && py > y && py < y + h {
+            Some(CropHandle::Move)
+        } else {
+            None
+        }
+    }
+
+    /// `rect` with this handle dragged by `(dx, dy)`, kept within an image of
+    /// size `bounds` and no smaller than [`MIN_CROP_SIZE`]
+    pub fn adjust(
+        self,
+        (x, y, w, h): (f64, f64, f64, f64),
+        dx: f64,
+        dy: f64,
+        (bounds_w, bounds_h): (f64, f64),
+    ) -> (f64, f64, f64, f64) {
+        if self == CropHandle::Move {
+            let new_x = (x + dx).min(bounds_w - w).max(0.0);
+            let new_y = (y + dy).min(bounds_h - h).max(0.0);
+            return (new_x, new_y, w, h);
+        }
+
+        let (mut left, mut top, mut right, mut bottom) = (x, y, x + w, y + h);
+        match self {
+            CropHandle::TopLeft | CropHandle::Left | CropHandle::BottomLeft => {
+                left = (left + dx).min(right - MIN_CROP_SIZE).max(0.0);
+            }
+            CropHandle::TopRight | CropHandle::Right | CropHandle::BottomRight => {
+                right = (right + dx).max(left + MIN_CROP_SIZE).min(bounds_w);
+            }
+            _ => {}
+        }
+        match self {
+            CropHandle::TopLeft | CropHandle::Top | CropHandle::TopRight => {
+                top = (top + dy).min(bottom - MIN_CROP_SIZE).max(0.0);
+            }
+            CropHandle::BottomLeft | CropHandle::Bottom | CropHandle::BottomRight => {
+                bottom = (bottom + dy).max(top + MIN_CROP_SIZE).min(bounds_h);
+            }
+            _ => {}
+        }
+        (left, top, right - left, bottom - top)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RECT: (f64, f64, f64, f64) = (100.0, 100.0, 200.0, 100.0);
+    const BOUNDS: (f64, f64) = (400.0, 300.0);
+
+    #[test]
+    fn test_crop_handle_at() {
+        assert_eq!(
+            CropHandle::at(RECT, 103.0, 98.0, 8.0),
+            Some(CropHandle::TopLeft)
+        );
+        assert_eq!(
+            CropHandle::at(RECT, 200.0, 205.0, 8.0),
+            Some(CropHandle::Bottom)
+        );
+        assert_eq!(
+            CropHandle::at(RECT, 150.0, 150.0, 8.0),
+            Some(CropHandle::Move)
+        );
+        assert_eq!(CropHandle::at(RECT, 50.0, 150.0, 8.0), None);
+    }
+
+    #[test]
+    fn test_crop_handle_adjust() {
+        assert_eq!(
+            CropHandle::BottomRight.adjust(RECT, 20.0, -10.0, BOUNDS),
+            (100.0, 100.0, 220.0, 90.0)
+        );
+        // Edges stop at the image and at the minimum size
+        assert_eq!(
+            CropHandle::Left.adjust(RECT, -500.0, 0.0, BOUNDS),
+            (0.0, 100.0, 300.0, 100.0)
+        );
+        assert_eq!(
+            CropHandle::Top.adjust(RECT, 0.0, 500.0, BOUNDS),
+            (100.0, 200.0 - MIN_CROP_SIZE, 200.0, MIN_CROP_SIZE)
+        );
+        // Moving keeps the size and stays inside the image
+        assert_eq!(
+            CropHandle::Move.adjust(RECT, 500.0, -500.0, BOUNDS),
+            (200.0, 0.0, 200.0, 100.0)
+        );
+    }
+}
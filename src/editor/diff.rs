@@ -0,0 +1,160 @@
+use crate::export::RgbaBuffer;
+
+/// Largest per-channel difference still treated as equal, enough to absorb
+/// dithering and compression noise
+pub const DEFAULT_DIFF_THRESHOLD: u8 = 16;
+
+const CHANGED_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Result of comparing two images pixel by pixel
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    /// Pixels that differ, including those only one of the images covers
+    pub changed_pixels: u64,
+
+    /// Smallest `(x, y, width, height)` containing every changed pixel
+    pub bounds: Option<(u32, u32, u32, u32)>,
+
+    pub size_mismatch: bool,
+
+    /// The first image faded to grey with changed pixels in magenta, sized to
+    /// cover both images
+    pub highlight: RgbaBuffer,
+}
+
+impl ImageDiff {
+    pub fn total_pixels(&self) -> u64 {
+        self.highlight.width as u64 * self.highlight.height as u64
+    }
+
+    pub fn changed_percent(&self) -> f64 {
+        if self.total_pixels() == 0 {
+            return 0.0;
+        }
+        self.changed_pixels as f64 * 100.0 / self.total_pixels() as f64
+    }
+
+    pub fn is_identical(&self) -> bool {
+        self.changed_pixels == 0
+    }
+
+    pub fn summary(&self) -> String {
+        if self.is_identical() {
+            return "Images are identical".to_string();
+        }
+
+        let mut summary = format!(
+            "{} pixels differ ({:.2}%)",
+            self.changed_pixels,
+            self.changed_percent()
+        );
+        if let Some((x, y, w, h)) = self.bounds {
+            summary.push_str(&format!(" within {}×{} at {},{}", w, h, x, y));
+        }
+        if self.size_mismatch {
+            summary.push_str(", sizes differ");
+        }
+        summary
+    }
+}
+
+/// Compare `current` against `reference`, both anchored at the top left.
+///
+/// Two pixels are equal when no RGBA channel differs by more than `threshold`.
+pub fn diff_images(current: &RgbaBuffer, reference: &RgbaBuffer, threshold: u8) -> ImageDiff {
+    let width = current.width.max(reference.width);
+    let height = current.height.max(reference.height);
+
+    let mut highlight = Vec::with_capacity(width as usize * height as usize * 4);
+    let mut changed_pixels = 0;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+
+    for y in 0..height {
+        for x in 0..width {
+            let a = pixel_at(current, x, y);
+            let b = pixel_at(reference, x, y);
+
+            let changed = match (a, b) {
+                (Some(a), Some(b)) => a.iter().zip(b).any(|(p, q)| p.abs_diff(*q) > threshold),
+                _ => true,
+            };
+
+            if changed {
+                changed_pixels += 1;
+                bounds = Some(match bounds {
+                    Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+                    None => (x, y, x, y),
+                });
+                highlight.extend_from_slice(&CHANGED_COLOR);
+            } else if let Some(a) = a {
+                let luma = (a[0] as u32 * 299 + a[1] as u32 * 587 + a[2] as u32 * 114) / 1000;
+                let faded = (128 + luma / 2) as u8;
+                highlight.extend_from_slice(&[faded, faded, faded, 255]);
+            } else {
+                highlight.extend_from_slice(&[0, 0, 0, 0]);
+            }
+        }
+    }
+
+    ImageDiff {
+        changed_pixels,
+        bounds: bounds.map(|(x0, y0, x1, y1)| (x0, y0, x1 - x0 + 1, y1 - y0 + 1)),
+        size_mismatch: current.width != reference.width || current.height != reference.height,
+        highlight: RgbaBuffer {
+            width,
+            height,
+            pixels: highlight,
+        },
+    }
+}
+
+fn pixel_at(buffer: &RgbaBuffer, x: u32, y: u32) -> Option<&[u8]> {
+    if x >= buffer.width || y >= buffer.height {
+        return None;
+    }
+    let offset = (y as usize * buffer.width as usize + x as usize) * 4;
+    buffer.pixels.get(offset..offset + 4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, rgba: [u8; 4]) -> RgbaBuffer {
+        RgbaBuffer {
+            width,
+            height,
+            pixels: rgba.repeat((width * height) as usize),
+        }
+    }
+
+    #[test]
+    fn test_threshold_and_bounds() {
+        let reference = solid(8, 8, [100, 100, 100, 255]);
+        let mut current = solid(8, 8, [110, 100, 100, 255]);
+        for (x, y) in [(2, 3), (5, 6)] {
+            let offset = (y * 8 + x) * 4;
+            current.pixels[offset] = 200;
+        }
+
+        let diff = diff_images(&current, &reference, DEFAULT_DIFF_THRESHOLD);
+        assert_eq!(diff.changed_pixels, 2);
+        assert_eq!(diff.bounds, Some((2, 3, 4, 4)));
+        assert!(!diff.size_mismatch);
+
+        assert!(diff_images(&reference, &reference, 0).is_identical());
+    }
+
+    #[test]
+    fn test_size_mismatch_counts_uncovered_pixels() {
+        let current = solid(4, 2, [0, 0, 0, 255]);
+        let reference = solid(2, 3, [0, 0, 0, 255]);
+
+        let diff = diff_images(&current, &reference, 0);
+        assert!(diff.size_mismatch);
+        assert_eq!((diff.highlight.width, diff.highlight.height), (4, 3));
+        // Right half of the current image plus the reference's extra row
+        assert_eq!(diff.changed_pixels, 4 + 4);
+        assert_eq!(diff.bounds, Some((0, 0, 4, 3)));
+    }
+}
@@ -0,0 +1,223 @@
+//! Offscreen rendering for golden image tests.
+//!
+//! Drawing code is rasterized onto a cairo `ImageSurface`, so no display
+//! server is needed, and compared with a reference PNG in `tests/golden/`.
+//! A missing reference is written from the current rendering; set
+//! `UPDATE_GOLDEN=1` to rewrite all of them after an intended change.
+
+use gtk4::cairo;
+use log::info;
+use std::path::PathBuf;
+
+/// Largest per-channel difference still counted as the same pixel, to allow
+/// for antialiasing differences between cairo and pango versions
+pub const CHANNEL_TOLERANCE: u8 = 8;
+
+/// Share of pixels that may differ before two renderings count as different
+pub const MAX_DIFFERING_RATIO: f64 = 0.005;
+
+pub fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+}
+
+/// Run `draw` on a transparent `width` x `height` surface and return the
+/// pixels as straight (not premultiplied) RGBA
+pub fn rasterize(
+    width: i32,
+    height: i32,
+    draw: impl FnOnce(&cairo::Context),
+) -> Result<image::RgbaImage, String> {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+        .map_err(|e| format!("Failed to create surface: {}", e))?;
+    {
+        let cr = cairo::Context::new(&surface)
+            .map_err(|e| format!("Failed to create context: {}", e))?;
+        draw(&cr);
+    }
+    surface.flush();
+
+    let stride = surface.stride() as usize;
+    let mut image = image::RgbaImage::new(width as u32, height as u32);
+    surface
+        .with_data(|data| {
+            for (x, y, pixel) in image.enumerate_pixels_mut() {
+                // ARGB32 is a native-endian u32, premultiplied
+                let offset = y as usize * stride + x as usize * 4;
+                let argb = u32::from_ne_bytes([
+                    data[offset],
+                    data[offset + 1],
+                    data[offset + 2],
+                    data[offset + 3],
+                ]);
+                let alpha = (argb >> 24) as u8;
+                let unpremultiply = |channel: u32| -> u8 {
+                    let channel = (channel & 0xff) as u8;
+                    if alpha == 0 {
+                        0
+                    } else {
+                        ((channel as u32 * 255 + alpha as u32 / 2) / alpha as u32).min(255) as u8
+                    }
+                };
+                *pixel = image::Rgba([
+                    unpremultiply(argb >> 16),
+                    unpremultiply(argb >> 8),
+                    unpremultiply(argb),
+                    alpha,
+                ]);
+            }
+        })
+        .map_err(|e| format!("Failed to read surface: {}", e))?;
+
+    Ok(image)
+}
+
+/// Compare two renderings within `CHANNEL_TOLERANCE` and `MAX_DIFFERING_RATIO`
+pub fn compare_images(
+    actual: &image::RgbaImage,
+    expected: &image::RgbaImage,
+) -> Result<(), String> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(format!(
+            "Size differs: got {:?}, expected {:?}",
+            actual.dimensions(),
+            expected.dimensions()
+        ));
+    }
+
+    let differing = actual
+        .pixels()
+        .zip(expected.pixels())
+        .filter(|(a, e)| {
+            a.0.iter()
+                .zip(e.0.iter())
+                .any(|(a, e)| a.abs_diff(*e) > CHANNEL_TOLERANCE)
+        })
+        .count();
+    let total = (actual.width() * actual.height()).max(1) as usize;
+
+    if differing as f64 / total as f64 > MAX_DIFFERING_RATIO {
+        Err(format!("{} of {} pixels differ", differing, total))
+    } else {
+        Ok(())
+    }
+}
+
+/// Compare `actual` with the golden image `name`. On a mismatch the
+/// rendering is written to the system temp dir for inspection.
+pub fn check_golden(name: &str, actual: &image::RgbaImage) -> Result<(), String> {
+    let path = golden_dir().join(format!("{}.png", name));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() || !path.exists() {
+        std::fs::create_dir_all(golden_dir()).map_err(|e| e.to_string())?;
+        actual.save(&path).map_err(|e| e.to_string())?;
+        info!("Wrote golden image {:?}", path);
+        return Ok(());
+    }
+
+    let expected = image::open(&path)
+        .map_err(|e| format!("Failed to load {:?}: {}", path, e))?
+        .to_rgba8();
+
+    compare_images(actual, &expected).map_err(|e| {
+        let actual_path = std::env::temp_dir().join(format!("{}.actual.png", name));
+        let _ = actual.save(&actual_path);
+        format!(
+            "{} does not match {:?} ({}), see {:?}",
+            name, path, e, actual_path
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::{
+        Annotation, AnnotationList, FreeDrawAnnotation, RectangleAnnotation, TextAnnotation,
+    };
+    use gtk4::gdk::RGBA;
+
+    const WIDTH: i32 = 160;
+    const HEIGHT: i32 = 120;
+
+    fn render(annotations: &AnnotationList) -> image::RgbaImage {
+        rasterize(WIDTH, HEIGHT, |cr| {
+            cr.set_source_rgb(1.0, 1.0, 1.0);
+            let _ = cr.paint();
+            annotations.draw_all(cr, 1.0, 0.0, 0.0);
+        })
+        .unwrap()
+    }
+
+    fn single(annotation: Annotation) -> AnnotationList {
+        let mut list = AnnotationList::new();
+        list.add(annotation);
+        list
+    }
+
+    fn red() -> RGBA {
+        RGBA::new(0.88, 0.11, 0.14, 1.0)
+    }
+
+    fn zigzag() -> FreeDrawAnnotation {
+        let mut draw = FreeDrawAnnotation::new(RGBA::new(0.21, 0.52, 0.89, 1.0), 3.0);
+        for (i, x) in (10..150).step_by(20).enumerate() {
+            draw.add_point(x as f64, if i % 2 == 0 { 30.0 } else { 90.0 });
+        }
+        draw
+    }
+
+    #[test]
+    fn test_compare_images() {
+        let base = image::RgbaImage::from_pixel(10, 10, image::Rgba([10, 20, 30, 255]));
+        let mut close = base.clone();
+        close.put_pixel(0, 0, image::Rgba([14, 20, 30, 255]));
+        assert!(compare_images(&close, &base).is_ok());
+
+        let mut off = base.clone();
+        off.put_pixel(0, 0, image::Rgba([200, 20, 30, 255]));
+        assert!(compare_images(&off, &base).is_err());
+
+        let smaller = image::RgbaImage::new(5, 5);
+        assert!(compare_images(&smaller, &base).is_err());
+    }
+
+    #[test]
+    fn test_golden_rectangle() {
+        let list = single(Annotation::Rectangle(RectangleAnnotation::new(
+            20.0,
+            20.0,
+            120.0,
+            80.0,
+            red(),
+            3.0,
+        )));
+        check_golden("rectangle", &render(&list)).unwrap();
+    }
+
+    #[test]
+    fn test_golden_free_draw() {
+        let list = single(Annotation::FreeDraw(zigzag()));
+        check_golden("free_draw", &render(&list)).unwrap();
+    }
+
+    #[test]
+    fn test_golden_text() {
+        let list = single(Annotation::Text(TextAnnotation::new(
+            10.0,
+            40.0,
+            "Hello\nGolden".to_string(),
+            red(),
+            20.0,
+        )));
+        check_golden("text", &render(&list)).unwrap();
+    }
+
+    #[test]
+    fn test_golden_selected() {
+        let mut list = single(Annotation::FreeDraw(zigzag()));
+        list.set_selected(Some(0));
+        check_golden("selected", &render(&list)).unwrap();
+    }
+}
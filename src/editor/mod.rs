@@ -1,18 +1,39 @@
 pub mod annotations;
 pub mod clipboard;
 pub mod color_picker;
+pub mod crop;
+pub mod diff;
+pub mod golden;
+pub mod palette;
+pub mod redact;
+pub mod serialize;
+pub mod snapshot;
+pub mod spelling;
+pub mod template;
 pub mod tools;
 
 pub use annotations::{
-    Annotation, AnnotationList, FreeDrawAnnotation, RectangleAnnotation, TextAnnotation,
+    Annotation, AnnotationList, CounterAnnotation, FreeDrawAnnotation, RectangleAnnotation,
+    TextAnnotation, SIMPLIFY_TOLERANCE,
 };
 pub use clipboard::ClipboardManager;
 pub use color_picker::{pick_color_from_pixbuf, ColorPickerState};
+pub use crop::{CropHandle, EditorCrop, CROP_HANDLE_RADIUS};
+pub use diff::{diff_images, ImageDiff, DEFAULT_DIFF_THRESHOLD};
+pub use palette::{
+    load_custom_colors, palette_colors, remember_custom_color, save_custom_colors, PalettePreset,
+};
+pub use redact::{clip_rect, redacted, RedactStyle};
+pub use snapshot::{Snapshot, SnapshotCrop};
+pub use template::{load_templates, save_templates, AnnotationTemplate};
 pub use tools::{EditorTool, ToolState};
 
 use gtk4::gdk::RGBA;
 use log::debug;
 
+/// Counter badge radius relative to the font size
+const COUNTER_RADIUS_RATIO: f64 = 0.6;
+
 #[derive(Clone, Debug)]
 pub struct EditorState {
     pub tool_state: ToolState,
@@ -25,6 +46,12 @@ pub struct EditorState {
 
     pub last_drag_moved: bool,
 
+    /// Crop applied to the image, if any
+    pub crop: Option<EditorCrop>,
+
+    /// Checkpoints taken of this image, oldest first
+    pub snapshots: Vec<Snapshot>,
+
     pub display_scale: f64,
     pub display_offset_x: f64,
     pub display_offset_y: f64,
@@ -34,6 +61,81 @@ pub struct EditorState {
 pub struct PendingText {
     pub x: f64,
     pub y: f64,
+
+    /// What has been typed so far, previewed on the canvas
+    pub text: String,
+
+    /// Caret position as a byte index into `text`, when typing on the canvas
+    pub cursor: usize,
+
+    /// Uncommitted input method composition shown at the caret
+    pub preedit: String,
+}
+
+impl PendingText {
+    pub fn new(x: f64, y: f64) -> Self {
+        Self {
+            x,
+            y,
+            text: String::new(),
+            cursor: 0,
+            preedit: String::new(),
+        }
+    }
+
+    /// Text including the input method composition, as it is drawn
+    pub fn display_text(&self) -> String {
+        let mut text = self.text.clone();
+        text.insert_str(self.cursor, &self.preedit);
+        text
+    }
+
+    /// Caret position in [`PendingText::display_text`]
+    pub fn display_cursor(&self) -> usize {
+        self.cursor + self.preedit.len()
+    }
+
+    pub fn insert(&mut self, s: &str) {
+        self.text.insert_str(self.cursor, s);
+        self.cursor += s.len();
+    }
+
+    pub fn delete_backward(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn delete_forward(&mut self) {
+        if self.cursor < self.text.len() {
+            self.text.remove(self.cursor);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(c) = self.text[..self.cursor].chars().next_back() {
+            self.cursor -= c.len_utf8();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(c) = self.text[self.cursor..].chars().next() {
+            self.cursor += c.len_utf8();
+        }
+    }
+
+    /// Start of the current line
+    pub fn move_home(&mut self) {
+        self.cursor = self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1);
+    }
+
+    /// End of the current line
+    pub fn move_end(&mut self) {
+        self.cursor = self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i);
+    }
 }
 
 impl Default for EditorState {
@@ -44,6 +146,8 @@ impl Default for EditorState {
             color_picker: ColorPickerState::new(),
             pending_text: None,
             last_drag_moved: false,
+            crop: None,
+            snapshots: Vec::new(),
             display_scale: 1.0,
             display_offset_x: 0.0,
             display_offset_y: 0.0,
@@ -112,6 +216,63 @@ impl EditorState {
         }
     }
 
+    /// Put down the next numbered badge at `(x, y)`, sized to go with text
+    /// at the current font size
+    pub fn place_counter(&mut self, x: f64, y: f64) {
+        let number = self.annotations.next_counter_number();
+        debug!("Placing counter {} at ({}, {})", number, x, y);
+        let counter = CounterAnnotation::new(
+            x,
+            y,
+            number,
+            self.tool_state.color,
+            self.tool_state.font_size * COUNTER_RADIUS_RATIO,
+        );
+        self.annotations.add(Annotation::Counter(counter));
+    }
+
+    pub fn update_pending_text(&mut self, text: String) {
+        if let Some(ref mut pending) = self.pending_text {
+            pending.cursor = text.len();
+            pending.text = text;
+        }
+    }
+
+    /// Add the text typed on the canvas as an annotation
+    pub fn commit_pending_text(&mut self) {
+        let text = self
+            .pending_text
+            .as_ref()
+            .map(|pending| pending.text.trim_end().to_string())
+            .unwrap_or_default();
+        self.commit_text(text);
+    }
+
+    fn pending_text_annotation(&self) -> Option<TextAnnotation> {
+        self.pending_text.as_ref().map(|pending| {
+            TextAnnotation::new(
+                pending.x,
+                pending.y,
+                pending.display_text(),
+                self.tool_state.color,
+                self.tool_state.font_size,
+            )
+        })
+    }
+
+    /// The text being typed as it would look once committed
+    pub fn pending_text_preview(&self) -> Option<TextAnnotation> {
+        self.pending_text_annotation()
+            .filter(|annotation| !annotation.text.is_empty())
+    }
+
+    /// Caret of the text being typed as `(x, y, height)` in image coordinates
+    pub fn pending_text_caret(&self) -> Option<(f64, f64, f64)> {
+        let pending = self.pending_text.as_ref()?;
+        let annotation = self.pending_text_annotation()?;
+        Some(annotation.caret_position(pending.display_cursor()))
+    }
+
     pub fn cancel_text(&mut self) {
         self.pending_text = None;
     }
@@ -121,6 +282,16 @@ impl EditorState {
         self.annotations.undo()
     }
 
+    pub fn redo(&mut self) -> bool {
+        debug!("Redo operation requested");
+        self.annotations.redo()
+    }
+
+    pub fn delete_selected(&mut self) -> bool {
+        debug!("Deleting the selected annotation");
+        self.annotations.delete_selected()
+    }
+
     pub fn clear_annotations(&mut self) {
         self.annotations.clear();
     }
@@ -139,6 +310,8 @@ impl EditorState {
         self.annotations.clear();
         self.color_picker.clear();
         self.pending_text = None;
+        self.crop = None;
+        self.snapshots.clear();
         self.tool_state.reset_drag();
     }
 
@@ -185,6 +358,51 @@ impl EditorState {
     pub fn pointer_drag_end(&mut self) {
         debug!("Pointer drag end");
         self.last_drag_moved = self.tool_state.moved_annotation;
+
+        // The whole drag is one step of the history
+        let origin = match (
+            self.tool_state.drag_start,
+            self.tool_state.pointer_drag_offset,
+        ) {
+            (Some((x, y)), Some((offset_x, offset_y))) => Some((x - offset_x, y - offset_y)),
+            _ => None,
+        };
+        if let (true, Some(index), Some((start_x, start_y)), Some((x, y))) = (
+            self.last_drag_moved,
+            self.annotations.selected(),
+            origin,
+            self.annotations.selected_position(),
+        ) {
+            self.annotations
+                .record_move(index, x - start_x, y - start_y);
+        }
         self.tool_state.end_annotation_drag();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_text_editing() {
+        let mut pending = PendingText::new(0.0, 0.0);
+        pending.insert("héllo\nwörld");
+        pending.move_home();
+        pending.move_right();
+        pending.delete_forward();
+        pending.delete_backward();
+        assert_eq!(pending.text, "héllo\nrld");
+
+        pending.move_left();
+        pending.move_left();
+        pending.delete_backward();
+        pending.move_end();
+        pending.insert("!");
+        assert_eq!(pending.text, "hélo!\nrld");
+
+        pending.preedit = "ü".to_string();
+        assert_eq!(pending.display_text(), "hélo!ü\nrld");
+        assert_eq!(pending.display_cursor(), "hélo!ü".len());
+    }
+}
@@ -0,0 +1,183 @@
+use gtk4::gdk::RGBA;
+use gtk4::glib;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::serialize::rgba_serde;
+
+/// How many user-defined colors are remembered
+pub const MAX_CUSTOM_COLORS: usize = 9;
+
+/// High-contrast annotation colors from the GNOME HIG palette, as
+/// `(name, hex)`. The first one is the default drawing color.
+pub const GNOME_PALETTE: [(&str, &str); 9] = [
+    ("Red", "#e01b24"),
+    ("Orange", "#ff7800"),
+    ("Yellow", "#f6d32d"),
+    ("Green", "#33d17a"),
+    ("Blue", "#3584e4"),
+    ("Purple", "#9141ac"),
+    ("Brown", "#986a44"),
+    ("White", "#ffffff"),
+    ("Black", "#000000"),
+];
+
+/// The Okabe–Ito colors, which stay apart for readers with red-green color
+/// blindness, as `(name, hex)`
+pub const COLOR_BLIND_PALETTE: [(&str, &str); 9] = [
+    ("Vermillion", "#d55e00"),
+    ("Orange", "#e69f00"),
+    ("Yellow", "#f0e442"),
+    ("Bluish Green", "#009e73"),
+    ("Sky Blue", "#56b4e9"),
+    ("Blue", "#0072b2"),
+    ("Reddish Purple", "#cc79a7"),
+    ("White", "#ffffff"),
+    ("Black", "#000000"),
+];
+
+/// Set of named colors offered for annotations
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PalettePreset {
+    #[default]
+    Gnome,
+
+    ColorBlindSafe,
+}
+
+impl PalettePreset {
+    pub const ALL: [PalettePreset; 2] = [Self::Gnome, Self::ColorBlindSafe];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Gnome => "gnome",
+            Self::ColorBlindSafe => "color-blind-safe",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Gnome => "GNOME",
+            Self::ColorBlindSafe => "Color-Blind Safe",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|preset| preset.id() == id)
+    }
+
+    fn entries(&self) -> &'static [(&'static str, &'static str); 9] {
+        match self {
+            Self::Gnome => &GNOME_PALETTE,
+            Self::ColorBlindSafe => &COLOR_BLIND_PALETTE,
+        }
+    }
+}
+
+/// Named colors offered next to the color button
+pub fn palette_colors(preset: PalettePreset) -> Vec<(&'static str, RGBA)> {
+    preset
+        .entries()
+        .iter()
+        .filter_map(|(name, hex)| RGBA::parse(*hex).ok().map(|color| (*name, color)))
+        .collect()
+}
+
+/// Whether `color` is in any of the presets, which are always at hand and
+/// so not worth remembering as custom colors
+pub fn is_palette_color(color: &RGBA) -> bool {
+    PalettePreset::ALL
+        .iter()
+        .any(|preset| palette_colors(*preset).iter().any(|(_, c)| c == color))
+}
+
+#[derive(Serialize, Deserialize)]
+struct CustomColorsFile {
+    colors: Vec<Color>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Color(#[serde(with = "rgba_serde")] RGBA);
+
+fn custom_colors_path() -> PathBuf {
+    glib::user_config_dir()
+        .join("screenshot_gnome")
+        .join("colors.json")
+}
+
+/// Colors the user picked in the color dialog, most recent first
+pub fn load_custom_colors() -> Vec<RGBA> {
+    let path = custom_colors_path();
+    let json = match std::fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(_) => {
+            debug!("No custom colors in {:?}", path);
+            return Vec::new();
+        }
+    };
+
+    match serde_json::from_str::<CustomColorsFile>(&json) {
+        Ok(file) => file.colors.into_iter().map(|c| c.0).collect(),
+        Err(e) => {
+            warn!("Ignoring invalid custom colors in {:?}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn save_custom_colors(colors: &[RGBA]) -> Result<(), String> {
+    let path = custom_colors_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let file = CustomColorsFile {
+        colors: colors.iter().map(|c| Color(*c)).collect(),
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| e.to_string())
+}
+
+/// Move `color` to the front of `colors`, dropping the oldest beyond
+/// [`MAX_CUSTOM_COLORS`]. Returns false when nothing changed.
+pub fn remember_custom_color(colors: &mut Vec<RGBA>, color: RGBA) -> bool {
+    if colors.first() == Some(&color) || is_palette_color(&color) {
+        return false;
+    }
+
+    colors.retain(|c| *c != color);
+    colors.insert(0, color);
+    colors.truncate(MAX_CUSTOM_COLORS);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remember_custom_color() {
+        let teal = RGBA::new(0.0, 0.5, 0.5, 0.8);
+        let pink = RGBA::new(1.0, 0.4, 0.7, 1.0);
+        let mut colors = Vec::new();
+
+        assert!(remember_custom_color(&mut colors, teal));
+        assert!(remember_custom_color(&mut colors, pink));
+        assert!(!remember_custom_color(&mut colors, pink));
+        assert!(remember_custom_color(&mut colors, teal));
+        assert_eq!(colors, vec![teal, pink]);
+
+        let (_, red) = palette_colors(PalettePreset::Gnome)[0];
+        assert!(!remember_custom_color(&mut colors, red));
+        let (_, sky_blue) = palette_colors(PalettePreset::ColorBlindSafe)[4];
+        assert!(!remember_custom_color(&mut colors, sky_blue));
+
+        for i in 0..MAX_CUSTOM_COLORS {
+            remember_custom_color(&mut colors, RGBA::new(i as f32 / 10.0, 0.1, 0.2, 1.0));
+        }
+        assert_eq!(colors.len(), MAX_CUSTOM_COLORS);
+        assert!(!colors.contains(&pink));
+    }
+}
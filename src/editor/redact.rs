@@ -0,0 +1,136 @@
+//! Redaction that replaces pixels outright, rather than drawing over them.
+//!
+//! Unlike an annotation it can't be moved or undone, and unlike blurring or
+//! pixelating it leaves nothing of the original behind to be reconstructed.
+
+use gtk4::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk4::glib;
+use serde::{Deserialize, Serialize};
+
+/// What redacted pixels are replaced with
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactStyle {
+    #[default]
+    Black,
+
+    /// Random gray noise, which reads as "removed" rather than as a black box
+    Noise,
+}
+
+impl RedactStyle {
+    pub const ALL: [RedactStyle; 2] = [Self::Black, Self::Noise];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Black => "black",
+            Self::Noise => "noise",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Black => "Solid Black",
+            Self::Noise => "Noise",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.id() == id)
+    }
+}
+
+/// `rect` in image pixels, clipped to an image of `width` x `height`; `None`
+/// when nothing of it is left
+pub fn clip_rect(
+    (x, y, w, h): (f64, f64, f64, f64),
+    width: i32,
+    height: i32,
+) -> Option<(i32, i32, i32, i32)> {
+    let left = (x.floor() as i32).clamp(0, width);
+    let top = (y.floor() as i32).clamp(0, height);
+    let right = ((x + w).ceil() as i32).clamp(0, width);
+    let bottom = ((y + h).ceil() as i32).clamp(0, height);
+    if right > left && bottom > top {
+        Some((left, top, right - left, bottom - top))
+    } else {
+        None
+    }
+}
+
+/// A copy of `pixbuf` with `rect` overwritten. The copy shares no pixels with
+/// `pixbuf`, so no view of the original shows through.
+pub fn redacted(pixbuf: &Pixbuf, rect: (i32, i32, i32, i32), style: RedactStyle) -> Option<Pixbuf> {
+    let copy = pixbuf.copy()?;
+    let (x, y, w, h) = rect;
+    match style {
+        RedactStyle::Black => copy.new_subpixbuf(x, y, w, h).fill(0x000000ff),
+        RedactStyle::Noise => {
+            let noise = noise_pixbuf(w, h, glib::random_int() as u64 | 1);
+            noise.copy_area(0, 0, w, h, &copy, x, y);
+        }
+    }
+    Some(copy)
+}
+
+/// Gray noise from a xorshift generator seeded with `seed`, which must not
+/// be zero
+fn noise_pixbuf(width: i32, height: i32, seed: u64) -> Pixbuf {
+    let mut state = seed;
+    let pixels: Vec<u8> = (0..width as usize * height as usize)
+        .flat_map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let gray = (state >> 56) as u8;
+            [gray, gray, gray]
+        })
+        .collect();
+    Pixbuf::from_bytes(
+        &glib::Bytes::from_owned(pixels),
+        Colorspace::Rgb,
+        false,
+        8,
+        width,
+        height,
+        width * 3,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::pick_color_from_pixbuf;
+
+    #[test]
+    fn test_clip_rect() {
+        assert_eq!(
+            clip_rect((10.4, 20.6, 30.0, 40.0), 100, 100),
+            Some((10, 20, 31, 41))
+        );
+        assert_eq!(
+            clip_rect((-10.0, 90.0, 50.0, 50.0), 100, 100),
+            Some((0, 90, 40, 10))
+        );
+        assert_eq!(clip_rect((120.0, 0.0, 10.0, 10.0), 100, 100), None);
+    }
+
+    #[test]
+    fn test_redacted_leaves_original_untouched() {
+        let original = Pixbuf::new(Colorspace::Rgb, false, 8, 40, 30).unwrap();
+        original.fill(0xffffffff);
+        // A view into the original, as the editor keeps after a crop
+        let view = original.new_subpixbuf(10, 10, 20, 10);
+
+        let result = redacted(&view, (0, 0, 5, 5), RedactStyle::Black).unwrap();
+        let black = pick_color_from_pixbuf(&result, 2, 2).unwrap().color;
+        assert_eq!((black.red(), black.green(), black.blue()), (0.0, 0.0, 0.0));
+        let outside = pick_color_from_pixbuf(&result, 10, 5).unwrap().color;
+        assert_eq!(outside.red(), 1.0);
+        let untouched = pick_color_from_pixbuf(&original, 12, 12).unwrap().color;
+        assert_eq!(untouched.red(), 1.0);
+
+        let noise = redacted(&view, (0, 0, 20, 10), RedactStyle::Noise).unwrap();
+        assert_eq!((noise.width(), noise.height()), (20, 10));
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+
+use super::annotations::AnnotationList;
+use super::tools::ToolStyle;
+
+/// Current version of the annotation document format.
+///
+/// Bump this when a change cannot be read by older builds. Additive changes
+/// (new optional fields) keep the version, since unknown fields are ignored
+/// and missing ones fall back to their defaults.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum SerializeError {
+    Json(String),
+
+    UnsupportedVersion(u32),
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(msg) => write!(f, "Invalid annotation data: {}", msg),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "Annotation format version {} is newer than supported version {}",
+                v, FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {}
+
+/// Versioned container for annotations, used by project files, session
+/// recovery and the annotation clipboard
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnnotationDocument {
+    pub version: u32,
+
+    #[serde(default)]
+    pub style: Option<ToolStyle>,
+
+    pub annotations: AnnotationList,
+}
+
+#[allow(dead_code)]
+impl AnnotationDocument {
+    pub fn new(annotations: AnnotationList, style: Option<ToolStyle>) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            style,
+            annotations,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, SerializeError> {
+        serde_json::to_string_pretty(self).map_err(|e| SerializeError::Json(e.to_string()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, SerializeError> {
+        #[derive(Deserialize)]
+        struct VersionProbe {
+            version: u32,
+        }
+
+        let probe: VersionProbe =
+            serde_json::from_str(json).map_err(|e| SerializeError::Json(e.to_string()))?;
+        if probe.version > FORMAT_VERSION {
+            return Err(SerializeError::UnsupportedVersion(probe.version));
+        }
+
+        serde_json::from_str(json).map_err(|e| SerializeError::Json(e.to_string()))
+    }
+}
+
+/// Serde adapter storing `gdk::RGBA` as `[red, green, blue, alpha]`
+pub mod rgba_serde {
+    use gtk4::gdk::RGBA;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &RGBA, serializer: S) -> Result<S::Ok, S::Error> {
+        [color.red(), color.green(), color.blue(), color.alpha()].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RGBA, D::Error> {
+        let [r, g, b, a] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(RGBA::new(r, g, b, a))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::annotations::{Annotation, RectangleAnnotation, TextAnnotation};
+    use gtk4::gdk::RGBA;
+
+    #[test]
+    fn test_document_roundtrip() {
+        let mut list = AnnotationList::new();
+        list.add(Annotation::Rectangle(RectangleAnnotation::new(
+            10.0,
+            20.0,
+            30.0,
+            40.0,
+            RGBA::new(1.0, 0.0, 0.0, 1.0),
+            3.0,
+        )));
+        list.add(Annotation::Text(TextAnnotation::new(
+            5.0,
+            6.0,
+            "Hello".to_string(),
+            RGBA::new(0.0, 0.0, 1.0, 0.5),
+            24.0,
+        )));
+
+        let json = AnnotationDocument::new(list, None).to_json().unwrap();
+        assert!(json.contains("\"type\": \"rectangle\""));
+
+        let doc = AnnotationDocument::from_json(&json).unwrap();
+        assert_eq!(doc.version, FORMAT_VERSION);
+        assert_eq!(doc.annotations.len(), 2);
+    }
+
+    #[test]
+    fn test_rejects_newer_version() {
+        let json = format!(
+            "{{\"version\": {}, \"annotations\": {{\"annotations\": []}}}}",
+            FORMAT_VERSION + 1
+        );
+        assert!(matches!(
+            AnnotationDocument::from_json(&json),
+            Err(SerializeError::UnsupportedVersion(_))
+        ));
+    }
+}
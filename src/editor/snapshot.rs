@@ -0,0 +1,49 @@
+//! Named checkpoints of an edit session, for trying different markup on the
+//! same capture and going back to any of them.
+//!
+//! A snapshot keeps the annotations and the crop, not the pixels. Restoring
+//! one applies it to the image as it is now, so a redaction made after the
+//! snapshot was taken stays in place.
+
+use super::annotations::Annotation;
+use super::serialize::AnnotationDocument;
+use super::EditorState;
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub name: String,
+
+    /// Annotations on the image as it was shown, cropped or not
+    pub document: AnnotationDocument,
+
+    pub crop: Option<SnapshotCrop>,
+}
+
+/// The crop applied when a snapshot was taken, as in
+/// [`EditorCrop`](super::EditorCrop) without the uncropped image
+#[derive(Clone, Debug)]
+pub struct SnapshotCrop {
+    pub region: (i32, i32, i32, i32),
+
+    pub hidden_annotations: Vec<Annotation>,
+
+    pub annotation_count: usize,
+}
+
+impl Snapshot {
+    pub fn new(name: &str, editor: &EditorState) -> Self {
+        let mut annotations = editor.annotations.clone();
+        annotations.deselect();
+        annotations.set_current(None);
+
+        Self {
+            name: name.to_string(),
+            document: AnnotationDocument::new(annotations, Some(editor.tool_state.style())),
+            crop: editor.crop.as_ref().map(|crop| SnapshotCrop {
+                region: crop.region,
+                hidden_annotations: crop.hidden_annotations.clone(),
+                annotation_count: crop.annotation_count,
+            }),
+        }
+    }
+}
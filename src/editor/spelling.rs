@@ -0,0 +1,194 @@
+use log::{debug, info};
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Programs speaking the ispell pipe protocol, in order of preference.
+/// Both pick the dictionary from the locale.
+const BACKENDS: [&str; 2] = ["enchant-2", "hunspell"];
+
+/// A misspelled word and what the dictionary suggests instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Misspelling {
+    pub word: String,
+
+    pub suggestions: Vec<String>,
+}
+
+/// Long-running `enchant-2 -a` / `hunspell -a` process used to check text
+pub struct SpellChecker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl SpellChecker {
+    pub fn start() -> Result<Self, String> {
+        let mut last_error = String::new();
+        for backend in BACKENDS {
+            match Self::spawn(backend) {
+                Ok(checker) => {
+                    info!("Spell checking with {}", backend);
+                    return Ok(checker);
+                }
+                Err(e) => {
+                    debug!("Spell checker {} unavailable: {}", backend, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(format!("No spell checker available: {}", last_error))
+    }
+
+    fn spawn(backend: &str) -> Result<Self, String> {
+        let mut child = Command::new(backend)
+            .arg("-a")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+
+        let stdin = child.stdin.take().ok_or("no stdin")?;
+        let mut stdout = BufReader::new(child.stdout.take().ok_or("no stdout")?);
+
+        // The first line is the version banner
+        let mut banner = String::new();
+        if stdout.read_line(&mut banner).map_err(|e| e.to_string())? == 0 {
+            let _ = child.wait();
+            return Err("exited on startup".to_string());
+        }
+
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Misspelled words in `text`, each listed once
+    pub fn check(&mut self, text: &str) -> Result<Vec<Misspelling>, String> {
+        let mut misspellings: Vec<Misspelling> = Vec::new();
+
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            // `^` keeps the line from being read as a pipe command
+            writeln!(self.stdin, "^{}", line).map_err(|e| e.to_string())?;
+            self.stdin.flush().map_err(|e| e.to_string())?;
+
+            loop {
+                let mut result = String::new();
+                let read = self
+                    .stdout
+                    .read_line(&mut result)
+                    .map_err(|e| e.to_string())?;
+                if read == 0 {
+                    return Err("Spell checker exited".to_string());
+                }
+                if result.trim().is_empty() {
+                    break;
+                }
+                if let Some(misspelling) = parse_result(result.trim_end()) {
+                    if !misspellings.iter().any(|m| m.word == misspelling.word) {
+                        misspellings.push(misspelling);
+                    }
+                }
+            }
+        }
+
+        Ok(misspellings)
+    }
+
+    /// Accept `word` from now on and remember it in the personal dictionary
+    pub fn add_word(&mut self, word: &str) -> Result<(), String> {
+        writeln!(self.stdin, "*{}\n#", word).map_err(|e| e.to_string())?;
+        self.stdin.flush().map_err(|e| e.to_string())
+    }
+}
+
+impl Drop for SpellChecker {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Byte ranges of the words in `text`, the same way the checker splits them
+pub fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+
+    for (i, c) in text.char_indices() {
+        let in_word = c.is_alphanumeric() || (c == '\'' && start.is_some());
+        match (in_word, start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                ranges.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        ranges.push((s, text.len()));
+    }
+
+    // A trailing apostrophe belongs to the sentence, not the word
+    ranges
+        .into_iter()
+        .map(|(s, e)| (s, s + text[s..e].trim_end_matches('\'').len()))
+        .collect()
+}
+
+/// Parse one ispell pipe result line; only `&` and `#` lines are misspellings
+fn parse_result(line: &str) -> Option<Misspelling> {
+    let mut parts = line.splitn(2, ' ');
+    let kind = parts.next()?;
+    let rest = parts.next()?;
+
+    match kind {
+        "&" => {
+            // & <word> <count> <offset>: <suggestion>, <suggestion>, ...
+            let (head, suggestions) = rest.split_once(": ")?;
+            Some(Misspelling {
+                word: head.split(' ').next()?.to_string(),
+                suggestions: suggestions.split(", ").map(str::to_string).collect(),
+            })
+        }
+        "#" => Some(Misspelling {
+            word: rest.split(' ').next()?.to_string(),
+            suggestions: Vec::new(),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_results() {
+        assert_eq!(
+            parse_result("& teh 3 4: the, tech, ten"),
+            Some(Misspelling {
+                word: "teh".to_string(),
+                suggestions: vec!["the".to_string(), "tech".to_string(), "ten".to_string()],
+            })
+        );
+        assert_eq!(
+            parse_result("# xyzzyq 10"),
+            Some(Misspelling {
+                word: "xyzzyq".to_string(),
+                suggestions: Vec::new(),
+            })
+        );
+        assert_eq!(parse_result("*"), None);
+        assert_eq!(parse_result("+ click"), None);
+    }
+
+    #[test]
+    fn test_words() {
+        let text = "Click 'Save' — don't wait, café!";
+        let found: Vec<&str> = words(text).into_iter().map(|(s, e)| &text[s..e]).collect();
+        assert_eq!(found, ["Click", "Save", "don't", "wait", "café"]);
+    }
+}
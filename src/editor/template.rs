@@ -0,0 +1,133 @@
+//! Annotation layouts saved for reuse on other captures, such as a review
+//! stamp and a title box that go in the same place on every screenshot.
+//!
+//! Unlike snapshots, templates outlive the image they were made on: they are
+//! kept in the config folder, and their annotations keep their positions in
+//! image pixels, measured from the top left corner.
+
+use gtk4::glib;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use super::annotations::Annotation;
+use super::serialize::{AnnotationDocument, FORMAT_VERSION};
+use super::EditorState;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AnnotationTemplate {
+    pub name: String,
+
+    pub document: AnnotationDocument,
+}
+
+impl AnnotationTemplate {
+    pub fn new(name: &str, editor: &EditorState) -> Self {
+        let mut annotations = editor.annotations.clone();
+        annotations.deselect();
+        annotations.set_current(None);
+
+        Self {
+            name: name.to_string(),
+            document: AnnotationDocument::new(annotations, Some(editor.tool_state.style())),
+        }
+    }
+
+    /// The template's annotations that land on an image of `width` x
+    /// `height`, leaving out any that would be entirely outside it
+    pub fn annotations_for(&self, width: f64, height: f64) -> Vec<Annotation> {
+        self.document
+            .annotations
+            .iter()
+            .filter(|annotation| annotation.intersects((0.0, 0.0, width, height)))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TemplatesFile {
+    templates: Vec<AnnotationTemplate>,
+}
+
+fn templates_path() -> PathBuf {
+    glib::user_config_dir()
+        .join("screenshot_gnome")
+        .join("templates.json")
+}
+
+/// Templates saved in earlier sessions, in the order they were saved
+pub fn load_templates() -> Vec<AnnotationTemplate> {
+    let path = templates_path();
+    let json = match std::fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(_) => {
+            debug!("No annotation templates in {:?}", path);
+            return Vec::new();
+        }
+    };
+
+    match serde_json::from_str::<TemplatesFile>(&json) {
+        Ok(file) => file
+            .templates
+            .into_iter()
+            .filter(|template| {
+                let supported = template.document.version <= FORMAT_VERSION;
+                if !supported {
+                    warn!("Skipping template {:?} from a newer version", template.name);
+                }
+                supported
+            })
+            .collect(),
+        Err(e) => {
+            warn!("Ignoring invalid annotation templates in {:?}: {}", path, e);
+            Vec::new()
+        }
+    }
+}
+
+pub fn save_templates(templates: &[AnnotationTemplate]) -> Result<(), String> {
+    let path = templates_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+
+    let file = TemplatesFile {
+        templates: templates.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    std::fs::write(&path, json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::RectangleAnnotation;
+    use gtk4::gdk::RGBA;
+
+    #[test]
+    fn test_annotations_for() {
+        let mut editor = EditorState::default();
+        let red = RGBA::new(1.0, 0.0, 0.0, 1.0);
+        for x in [10.0, 500.0] {
+            editor
+                .annotations
+                .add(Annotation::Rectangle(RectangleAnnotation::new(
+                    x, 10.0, 50.0, 20.0, red, 3.0,
+                )));
+        }
+        let template = AnnotationTemplate::new("Review", &editor);
+
+        let json = serde_json::to_string(&TemplatesFile {
+            templates: vec![template],
+        })
+        .unwrap();
+        let template = serde_json::from_str::<TemplatesFile>(&json)
+            .unwrap()
+            .templates
+            .remove(0);
+        assert_eq!(template.name, "Review");
+        assert_eq!(template.annotations_for(800.0, 600.0).len(), 2);
+        assert_eq!(template.annotations_for(300.0, 200.0).len(), 1);
+    }
+}
@@ -1,6 +1,11 @@
 use gtk4::gdk::RGBA;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+use super::crop::CropHandle;
+use super::serialize::rgba_serde;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum EditorTool {
     #[default]
     Pointer,
@@ -9,6 +14,29 @@ pub enum EditorTool {
     Crop,
     Text,
     ColorPicker,
+
+    /// Replaces the pixels in a dragged rectangle for good
+    Redact,
+
+    /// Places numbered badges, counting up with each click
+    Counter,
+}
+
+/// Range the line width can be adjusted within, in image pixels
+pub const LINE_WIDTH_RANGE: (f64, f64) = (1.0, 50.0);
+
+/// Range the font size can be adjusted within, in image pixels
+pub const FONT_SIZE_RANGE: (f64, f64) = (8.0, 200.0);
+
+/// Drawing style shared by new annotations; persisted alongside them
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolStyle {
+    #[serde(with = "rgba_serde")]
+    pub color: RGBA,
+
+    pub line_width: f64,
+
+    pub font_size: f64,
 }
 
 #[derive(Clone, Debug)]
@@ -32,6 +60,10 @@ pub struct ToolState {
     pub is_dragging_annotation: bool,
 
     pub moved_annotation: bool,
+
+    /// Handle of the crop rectangle being dragged, with where the drag started
+    /// and the rectangle at that point
+    pub crop_adjust: Option<(CropHandle, (f64, f64), (f64, f64, f64, f64))>,
 }
 
 impl Default for ToolState {
@@ -47,6 +79,7 @@ impl Default for ToolState {
             pointer_drag_offset: None,
             is_dragging_annotation: false,
             moved_annotation: false,
+            crop_adjust: None,
         }
     }
 }
@@ -61,6 +94,43 @@ impl ToolState {
         self.color = color;
     }
 
+    /// Grow or shrink what the active tool draws with by `steps`: the line
+    /// width for lines and rectangles, the font size for text and counter
+    /// badges. Returns a readout of the new value, or `None` when the tool
+    /// has no size.
+    pub fn adjust_size(&mut self, steps: f64) -> Option<String> {
+        match self.active_tool {
+            EditorTool::Pencil | EditorTool::Rectangle => {
+                let (min, max) = LINE_WIDTH_RANGE;
+                self.line_width = (self.line_width.round() + steps).clamp(min, max);
+                Some(format!("Line width {} px", self.line_width))
+            }
+            EditorTool::Text | EditorTool::Counter => {
+                // Font sizes change by two, to get anywhere in a few notches
+                let (min, max) = FONT_SIZE_RANGE;
+                self.font_size = (self.font_size.round() + steps * 2.0).clamp(min, max);
+                Some(format!("Font size {} px", self.font_size))
+            }
+            _ => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn style(&self) -> ToolStyle {
+        ToolStyle {
+            color: self.color,
+            line_width: self.line_width,
+            font_size: self.font_size,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn apply_style(&mut self, style: &ToolStyle) {
+        self.color = style.color;
+        self.line_width = style.line_width;
+        self.font_size = style.font_size;
+    }
+
     pub fn start_drag(&mut self, x: f64, y: f64) {
         self.is_drawing = true;
         self.drag_start = Some((x, y));
@@ -90,6 +160,24 @@ impl ToolState {
         self.pointer_drag_offset = None;
         self.is_dragging_annotation = false;
         self.moved_annotation = false;
+        self.crop_adjust = None;
+    }
+
+    /// Grab `handle` of the current crop rectangle at `(x, y)`
+    pub fn start_crop_adjust(&mut self, handle: CropHandle, x: f64, y: f64) {
+        if let Some(rect) = self.get_drag_rect() {
+            self.crop_adjust = Some((handle, (x, y), rect));
+        }
+    }
+
+    /// Resize or move the crop rectangle for the pointer now at `(x, y)`,
+    /// within an image of size `bounds`
+    pub fn update_crop_adjust(&mut self, x: f64, y: f64, bounds: (f64, f64)) {
+        if let Some((handle, (start_x, start_y), rect)) = self.crop_adjust {
+            let (new_x, new_y, w, h) = handle.adjust(rect, x - start_x, y - start_y, bounds);
+            self.drag_start = Some((new_x, new_y));
+            self.drag_current = Some((new_x + w, new_y + h));
+        }
     }
 
     pub fn start_annotation_drag(
@@ -145,3 +233,24 @@ impl ToolState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adjust_size() {
+        let mut tools = ToolState::default();
+        assert_eq!(tools.adjust_size(1.0), None);
+
+        tools.set_tool(EditorTool::Pencil);
+        assert_eq!(tools.adjust_size(2.0).as_deref(), Some("Line width 5 px"));
+        tools.adjust_size(-10.0);
+        assert_eq!(tools.line_width, LINE_WIDTH_RANGE.0);
+
+        tools.set_tool(EditorTool::Text);
+        assert_eq!(tools.adjust_size(-1.0).as_deref(), Some("Font size 22 px"));
+        tools.adjust_size(100.0);
+        assert_eq!(tools.font_size, FONT_SIZE_RANGE.1);
+    }
+}
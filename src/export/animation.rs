@@ -0,0 +1,472 @@
+//! Animated GIFs, PNGs and WebPs made from screen recordings.
+//!
+//! `gst-launch-1.0` decodes the video to raw RGBA frames at the chosen frame
+//! rate, and they are encoded here, so no animation plugin for GStreamer is
+//! needed. GIFs are quantized and encoded by the `image` crate. APNGs and
+//! WebPs keep every colour: frames that repeat the one before are merged
+//! into it, and the rest are cut down to the part that changed, which keeps
+//! recordings of mostly still windows small.
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::codecs::webp::WebPEncoder;
+use image::{Delay, ExtendedColorType, Frame, RgbaImage};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use super::target::ExportError;
+
+/// Quantizer speed from 1 to 30; 10 is the gif crate's own default, much
+/// faster than the best quality for little visible difference
+const QUANTIZE_SPEED: i32 = 10;
+
+/// File formats animations are written in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationFormat {
+    Gif,
+
+    Apng,
+
+    WebP,
+}
+
+/// Frame rate, length and looping of animations made from recordings
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnimationSettings {
+    pub fps: u32,
+
+    /// Anything recorded after this is left out, and recording stops here
+    pub max_duration_secs: u32,
+
+    /// How many times the animation plays, or 0 to loop forever
+    pub plays: u32,
+}
+
+impl Default for AnimationSettings {
+    fn default() -> Self {
+        Self {
+            fps: 10,
+            max_duration_secs: 30,
+            plays: 0,
+        }
+    }
+}
+
+impl AnimationSettings {
+    pub const MAX_FPS: u32 = 50;
+
+    pub const MAX_DURATION_SECS: u32 = 300;
+
+    pub const MAX_PLAYS: u32 = 100;
+
+    fn max_frames(&self) -> usize {
+        (self.fps * self.max_duration_secs) as usize
+    }
+
+    /// Time each frame is shown for
+    fn frame_delay(&self) -> Delay {
+        Delay::from_numer_denom_ms(1000, self.fps.max(1))
+    }
+}
+
+/// Arguments for `gst-launch-1.0` writing `video` to stdout as raw RGBA
+/// frames of `width` x `height` at `fps`
+fn decode_args(video: &Path, (width, height): (u32, u32), fps: u32) -> Vec<String> {
+    vec![
+        "-q".to_string(),
+        "filesrc".to_string(),
+        format!("location={}", video.display()),
+        "!".to_string(),
+        "decodebin".to_string(),
+        "!".to_string(),
+        "videoconvert".to_string(),
+        "!".to_string(),
+        "videorate".to_string(),
+        "!".to_string(),
+        "videoscale".to_string(),
+        "!".to_string(),
+        format!(
+            "video/x-raw,format=RGBA,width={},height={},framerate={}/1",
+            width, height, fps
+        ),
+        "!".to_string(),
+        "fdsink".to_string(),
+        "fd=1".to_string(),
+    ]
+}
+
+/// Convert `video` into an animation at `output`, `size` pixels large.
+/// Blocks until done, so call it off the main thread. Returns the number of
+/// frames written.
+pub fn encode_animation(
+    video: &Path,
+    size: (u32, u32),
+    format: AnimationFormat,
+    settings: &AnimationSettings,
+    output: &Path,
+) -> Result<usize, ExportError> {
+    debug!(
+        "Converting {:?} to {:?} at {} fps",
+        video, format, settings.fps
+    );
+    let mut child = Command::new("gst-launch-1.0")
+        .args(decode_args(video, size, settings.fps))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| ExportError::Failed(format!("Failed to run gst-launch-1.0: {}", e)))?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| ExportError::Failed("gst-launch-1.0 has no stdout".to_string()))?;
+
+    let (width, height) = size;
+    let frame_size = width as usize * height as usize * 4;
+    let frames = std::iter::from_fn(|| {
+        let mut pixels = vec![0; frame_size];
+        match stdout.read_exact(&mut pixels) {
+            Ok(()) => RgbaImage::from_raw(width, height, pixels),
+            Err(_) => None,
+        }
+    })
+    .take(settings.max_frames());
+
+    let file = File::create(output).map_err(|e| ExportError::Io(e.to_string()))?;
+    let writer = BufWriter::new(file);
+    let result = match format {
+        AnimationFormat::Gif => write_gif(frames, settings, writer),
+        AnimationFormat::Apng => write_apng(&changed_frames(frames), size, settings, writer),
+        AnimationFormat::WebP => write_webp(&changed_frames(frames), size, settings, writer),
+    };
+
+    // Past the maximum duration the rest of the video is not needed
+    let _ = child.kill();
+    let _ = child.wait();
+    match result {
+        Ok(0) => Err(ExportError::Failed(
+            "No frames could be read from the recording".to_string(),
+        )),
+        result => result,
+    }
+}
+
+fn write_gif(
+    frames: impl Iterator<Item = RgbaImage>,
+    settings: &AnimationSettings,
+    writer: impl Write,
+) -> Result<usize, ExportError> {
+    let mut encoder = GifEncoder::new_with_speed(writer, QUANTIZE_SPEED);
+    // The loop count is how often it repeats after playing once, and a GIF
+    // without one plays once
+    let repeat = match settings.plays {
+        0 => Some(Repeat::Infinite),
+        1 => None,
+        plays => Some(Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16)),
+    };
+    if let Some(repeat) = repeat {
+        encoder
+            .set_repeat(repeat)
+            .map_err(|e| ExportError::Encoding(e.to_string()))?;
+    }
+
+    let mut count = 0;
+    for frame in frames {
+        encoder
+            .encode_frame(Frame::from_parts(frame, 0, 0, settings.frame_delay()))
+            .map_err(|e| ExportError::Encoding(e.to_string()))?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// The part of a frame that differs from the frame before it
+#[derive(Debug)]
+struct ChangedFrame {
+    x: u32,
+    y: u32,
+    image: RgbaImage,
+
+    /// How many frames of the recording it stands for, the ones after it
+    /// being the same
+    length: u32,
+}
+
+/// The smallest rectangle, as `(x, y, width, height)`, holding every pixel
+/// that differs between two frames of the same size, grown to start at even
+/// coordinates as WebP places frames
+fn changed_rect(previous: &RgbaImage, frame: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for (x, y, pixel) in frame.enumerate_pixels() {
+        if previous.get_pixel(x, y) == pixel {
+            continue;
+        }
+        bounds = Some(match bounds {
+            Some((left, top, right, bottom)) => {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            }
+            None => (x, y, x, y),
+        });
+    }
+    let (left, top, right, bottom) = bounds?;
+    let (left, top) = (left & !1, top & !1);
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+/// `frames` with repeated frames merged into the one before them and the
+/// others cut down to what changed. The first frame is always whole.
+fn changed_frames(frames: impl Iterator<Item = RgbaImage>) -> Vec<ChangedFrame> {
+    let mut changed: Vec<ChangedFrame> = Vec::new();
+    let mut previous: Option<RgbaImage> = None;
+    for frame in frames {
+        let rect = match previous {
+            Some(ref previous) => match changed_rect(previous, &frame) {
+                Some(rect) => Some(rect),
+                None => {
+                    if let Some(last) = changed.last_mut() {
+                        last.length += 1;
+                    }
+                    continue;
+                }
+            },
+            None => None,
+        };
+        let (x, y, width, height) = rect.unwrap_or((0, 0, frame.width(), frame.height()));
+        let image = image::imageops::crop_imm(&frame, x, y, width, height).to_image();
+        changed.push(ChangedFrame {
+            x,
+            y,
+            image,
+            length: 1,
+        });
+        previous = Some(frame);
+    }
+    changed
+}
+
+fn write_apng(
+    frames: &[ChangedFrame],
+    (width, height): (u32, u32),
+    settings: &AnimationSettings,
+    writer: impl Write,
+) -> Result<usize, ExportError> {
+    if frames.is_empty() {
+        return Ok(0);
+    }
+    let encoding = |e: png::EncodingError| ExportError::Encoding(e.to_string());
+
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .set_animated(frames.len() as u32, settings.plays)
+        .map_err(encoding)?;
+    let mut writer = encoder.write_header().map_err(encoding)?;
+    for frame in frames {
+        writer.reset_frame_position().map_err(encoding)?;
+        writer
+            .set_frame_dimension(frame.image.width(), frame.image.height())
+            .map_err(encoding)?;
+        writer
+            .set_frame_position(frame.x, frame.y)
+            .map_err(encoding)?;
+        writer
+            .set_frame_delay(
+                frame.length.min(u16::MAX as u32) as u16,
+                settings.fps.max(1) as u16,
+            )
+            .map_err(encoding)?;
+        // Each frame replaces its part of the one before
+        writer
+            .set_blend_op(png::BlendOp::Source)
+            .map_err(encoding)?;
+        writer
+            .write_image_data(frame.image.as_raw())
+            .map_err(encoding)?;
+    }
+    writer.finish().map_err(encoding)?;
+    Ok(frames.len())
+}
+
+/// The low three bytes of `value`, as WebP stores sizes and times
+fn u24(value: u32) -> [u8; 3] {
+    let [low, middle, high, _] = value.to_le_bytes();
+    [low, middle, high]
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// `image` as a lossless `VP8L` chunk, header included
+fn webp_frame(image: &RgbaImage) -> Result<Vec<u8>, ExportError> {
+    let mut file = Vec::new();
+    WebPEncoder::new_lossless(&mut file)
+        .encode(
+            image.as_raw(),
+            image.width(),
+            image.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(|e| ExportError::Encoding(e.to_string()))?;
+
+    // A still lossless WebP is `RIFF`, its size and `WEBP`, then the chunk
+    match file.get(12..16) {
+        Some(b"VP8L") => Ok(file.split_off(12)),
+        _ => Err(ExportError::Encoding(
+            "Unexpected WebP frame layout".to_string(),
+        )),
+    }
+}
+
+fn write_webp(
+    frames: &[ChangedFrame],
+    (width, height): (u32, u32),
+    settings: &AnimationSettings,
+    mut writer: impl Write,
+) -> Result<usize, ExportError> {
+    if frames.is_empty() {
+        return Ok(0);
+    }
+
+    let mut body = Vec::new();
+    // Flags for an animation with alpha, then the canvas size
+    let mut header = vec![0x10 | 0x02, 0, 0, 0];
+    header.extend_from_slice(&u24(width - 1));
+    header.extend_from_slice(&u24(height - 1));
+    write_chunk(&mut body, b"VP8X", &header);
+
+    // A transparent background, and how many times it plays
+    let mut animation = vec![0, 0, 0, 0];
+    animation.extend_from_slice(&(settings.plays.min(u16::MAX as u32) as u16).to_le_bytes());
+    write_chunk(&mut body, b"ANIM", &animation);
+
+    for frame in frames {
+        let duration = frame.length * 1000 / settings.fps.max(1);
+        let mut data = Vec::new();
+        data.extend_from_slice(&u24(frame.x / 2));
+        data.extend_from_slice(&u24(frame.y / 2));
+        data.extend_from_slice(&u24(frame.image.width() - 1));
+        data.extend_from_slice(&u24(frame.image.height() - 1));
+        data.extend_from_slice(&u24(duration.min(0xff_ffff)));
+        // Each frame replaces its part of the one before instead of being
+        // blended over it
+        data.push(0x02);
+        data.extend_from_slice(&webp_frame(&frame.image)?);
+        write_chunk(&mut body, b"ANMF", &data);
+    }
+
+    let io = |e: std::io::Error| ExportError::Io(e.to_string());
+    writer.write_all(b"RIFF").map_err(io)?;
+    writer
+        .write_all(&(body.len() as u32 + 4).to_le_bytes())
+        .map_err(io)?;
+    writer.write_all(b"WEBP").map_err(io)?;
+    writer.write_all(&body).map_err(io)?;
+    writer.flush().map_err(io)?;
+    Ok(frames.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::codecs::webp::WebPDecoder;
+    use image::AnimationDecoder;
+
+    #[test]
+    fn test_write_gif() {
+        let settings = AnimationSettings {
+            fps: 20,
+            max_duration_secs: 1,
+            plays: 0,
+        };
+        let frames =
+            (0..30u8).map(|i| RgbaImage::from_pixel(8, 6, image::Rgba([i * 8, 0, 0, 255])));
+
+        let mut gif = Vec::new();
+        let count = write_gif(frames.take(settings.max_frames()), &settings, &mut gif).unwrap();
+        assert_eq!(count, 20);
+
+        let decoded = GifDecoder::new(std::io::Cursor::new(gif))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(decoded.len(), 20);
+        assert_eq!(decoded[0].delay().numer_denom_ms(), (50, 1));
+        assert_eq!(decoded[0].buffer().dimensions(), (8, 6));
+    }
+
+    /// Three frames of a 16x12 screen where a 3x2 box appears and then
+    /// stays put for two more frames
+    fn box_frames() -> Vec<RgbaImage> {
+        let blank = RgbaImage::from_pixel(16, 12, image::Rgba([255, 255, 255, 255]));
+        let mut with_box = blank.clone();
+        for (x, y) in [(5, 3), (6, 3), (7, 3), (5, 4), (6, 4), (7, 4)] {
+            with_box.put_pixel(x, y, image::Rgba([200, 0, 0, 255]));
+        }
+        vec![blank, with_box.clone(), with_box.clone(), with_box]
+    }
+
+    #[test]
+    fn test_changed_frames() {
+        let frames = changed_frames(box_frames().into_iter());
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].image.dimensions(), (16, 12));
+        assert_eq!(frames[0].length, 1);
+        // Grown to start at an even column
+        assert_eq!((frames[1].x, frames[1].y), (4, 2));
+        assert_eq!(frames[1].image.dimensions(), (4, 3));
+        assert_eq!(frames[1].length, 3);
+    }
+
+    #[test]
+    fn test_write_apng_and_webp() {
+        let settings = AnimationSettings {
+            fps: 10,
+            max_duration_secs: 1,
+            plays: 2,
+        };
+        let frames = changed_frames(box_frames().into_iter());
+
+        let mut apng = Vec::new();
+        assert_eq!(
+            write_apng(&frames, (16, 12), &settings, &mut apng).unwrap(),
+            2
+        );
+        let decoded = PngDecoder::new(std::io::Cursor::new(apng))
+            .unwrap()
+            .apng()
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].delay().numer_denom_ms(), (300, 1));
+        assert_eq!(decoded[1].buffer().get_pixel(6, 4).0, [200, 0, 0, 255]);
+        assert_eq!(decoded[1].buffer().get_pixel(0, 0).0, [255, 255, 255, 255]);
+
+        let mut webp = Vec::new();
+        assert_eq!(
+            write_webp(&frames, (16, 12), &settings, &mut webp).unwrap(),
+            2
+        );
+        let decoder = WebPDecoder::new(std::io::Cursor::new(webp)).unwrap();
+        assert!(decoder.has_animation());
+        let decoded = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].delay().numer_denom_ms(), (300, 1));
+        assert_eq!(decoded[1].buffer().get_pixel(6, 4).0, [200, 0, 0, 255]);
+        assert_eq!(decoded[1].buffer().get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
+}
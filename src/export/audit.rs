@@ -0,0 +1,176 @@
+//! Spotting text that probably shouldn't be shared, in what OCR reads from an
+//! image about to be exported.
+//!
+//! The checks are deliberately simple word-by-word heuristics: a warning is
+//! only a prompt to look again, so a missed or extra match costs little.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Tokens shorter than this are not taken for keys unless they have a known
+/// prefix
+const MIN_TOKEN_LENGTH: usize = 24;
+
+/// Prefixes of API keys and tokens issued by common services
+const TOKEN_PREFIXES: [&str; 9] = [
+    "ghp_",
+    "gho_",
+    "github_pat_",
+    "glpat-",
+    "xoxb-",
+    "xoxp-",
+    "sk-",
+    "AKIA",
+    "AIza",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SensitiveKind {
+    Email,
+
+    IpAddress,
+
+    /// API key, access token or similar secret
+    Token,
+}
+
+impl SensitiveKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Email => "Email address",
+            Self::IpAddress => "IP address",
+            Self::Token => "Key or token",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SensitiveMatch {
+    pub kind: SensitiveKind,
+
+    pub text: String,
+}
+
+/// Email addresses, IP addresses and key-like tokens in `text`, in order and
+/// without repeats
+pub fn find_sensitive_text(text: &str) -> Vec<SensitiveMatch> {
+    let mut matches: Vec<SensitiveMatch> = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_matches(|c: char| "()[]<>{}\"'`,;!?".contains(c));
+        let word = word.trim_end_matches(['.', ':']);
+
+        let found = if is_email(word) {
+            Some(SensitiveKind::Email)
+        } else if is_ip_address(word) {
+            Some(SensitiveKind::IpAddress)
+        } else if word
+            .split(['=', ':'])
+            .any(|part| is_token(part.trim_matches(['"', '\''])))
+        {
+            Some(SensitiveKind::Token)
+        } else {
+            None
+        };
+
+        if let Some(kind) = found {
+            if !matches.iter().any(|m| m.text == word) {
+                matches.push(SensitiveMatch {
+                    kind,
+                    text: word.to_string(),
+                });
+            }
+        }
+    }
+    matches
+}
+
+fn is_email(word: &str) -> bool {
+    let (local, domain) = match word.split_once('@') {
+        Some(parts) => parts,
+        None => return false,
+    };
+    let local_ok = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._%+-".contains(c));
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    local_ok && domain_ok
+}
+
+/// An IPv4 address, optionally with a port, or an IPv6 address
+fn is_ip_address(word: &str) -> bool {
+    let host = match word.rsplit_once(':') {
+        Some((host, port)) if host.contains('.') && port.parse::<u16>().is_ok() => host,
+        _ => word,
+    };
+    if host.parse::<Ipv4Addr>().is_ok() {
+        return true;
+    }
+    // "::" alone, or a clock time, is not worth a warning
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    host.matches(':').count() >= 2 && host != "::" && host.parse::<Ipv6Addr>().is_ok()
+}
+
+fn is_token(part: &str) -> bool {
+    if part.contains("://") || part.contains('/') {
+        return false;
+    }
+    if TOKEN_PREFIXES
+        .iter()
+        .any(|prefix| part.starts_with(prefix) && part.len() >= prefix.len() + 16)
+    {
+        return true;
+    }
+
+    // Long random-looking strings: letters in both cases and digits, and
+    // nothing but key characters
+    part.len() >= MIN_TOKEN_LENGTH
+        && part
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_+".contains(c))
+        && part.chars().any(|c| c.is_ascii_digit())
+        && part.chars().any(|c| c.is_ascii_lowercase())
+        && part.chars().any(|c| c.is_ascii_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(SensitiveKind, String)> {
+        find_sensitive_text(text)
+            .into_iter()
+            .map(|m| (m.kind, m.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_sensitive_text() {
+        let text = "Contact <jane.doe+ops@example.co.uk>, server 192.168.1.20:8080 and fe80::1.\n\
+                    TOKEN=ghp_a1B2c3D4e5F6g7H8i9J0 again jane.doe+ops@example.co.uk\n\
+                    key: Zx8Kq2LmN4pR7sT1vW3yA5bC9dE\n\
+                    Harmless: 12:30:00 version 1.2.3 user@localhost https://example.com/a1B2c3D4e5F6g7H8i9J0k";
+        assert_eq!(
+            kinds(text),
+            vec![
+                (
+                    SensitiveKind::Email,
+                    "jane.doe+ops@example.co.uk".to_string()
+                ),
+                (SensitiveKind::IpAddress, "192.168.1.20:8080".to_string()),
+                (SensitiveKind::IpAddress, "fe80::1".to_string()),
+                (
+                    SensitiveKind::Token,
+                    "TOKEN=ghp_a1B2c3D4e5F6g7H8i9J0".to_string()
+                ),
+                (
+                    SensitiveKind::Token,
+                    "Zx8Kq2LmN4pR7sT1vW3yA5bC9dE".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use log::info;
+use std::path::{Path, PathBuf};
+
+use super::target::ExportError;
+use crate::capture::metadata::CaptureMetadata;
+
+/// How automatically saved captures are laid out below [`auto_save_directory`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoSaveLayout {
+    /// Everything in one folder
+    Flat,
+
+    /// `2025/`
+    ByYear,
+
+    /// `2025/06/`
+    ByMonth,
+
+    /// `2025/06/14/`
+    ByDay,
+}
+
+impl AutoSaveLayout {
+    pub const ALL: [AutoSaveLayout; 4] = [Self::Flat, Self::ByYear, Self::ByMonth, Self::ByDay];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::ByYear => "year",
+            Self::ByMonth => "month",
+            Self::ByDay => "day",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Flat => "Screenshots Folder",
+            Self::ByYear => "Folder per Year",
+            Self::ByMonth => "Folder per Month",
+            Self::ByDay => "Folder per Day",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|layout| layout.id() == id)
+    }
+
+    /// Folder part prepended to the filename template
+    fn folder_template(&self) -> &'static str {
+        match self {
+            Self::Flat => "",
+            Self::ByYear => "{year}/",
+            Self::ByMonth => "{year}/{month}/",
+            Self::ByDay => "{year}/{month}/{day}/",
+        }
+    }
+}
+
+/// Root folder for automatically saved captures, `~/Pictures/Screenshots`
+pub fn auto_save_directory() -> PathBuf {
+    glib::user_special_dir(glib::UserDirectory::Pictures)
+        .unwrap_or_else(glib::home_dir)
+        .join("Screenshots")
+}
+
+/// Save a fresh capture below [`auto_save_directory`], creating dated
+/// folders as needed. Existing files are never overwritten.
+pub fn auto_save(
+    pixbuf: &Pixbuf,
+    metadata: &CaptureMetadata,
+    template: &str,
+    layout: AutoSaveLayout,
+) -> Result<PathBuf, ExportError> {
+    let relative = metadata.expand_filename_template(
+        &format!("{}{}", layout.folder_template(), template),
+        pixbuf.width(),
+        pixbuf.height(),
+    );
+    let path = unique_path(&auto_save_directory().join(format!("{}.png", relative)));
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| ExportError::Io(format!("{}: {}", parent.display(), e)))?;
+    }
+
+    let png = pixbuf
+        .save_to_bufferv("png", &[])
+        .map_err(|e| ExportError::Encoding(e.to_string()))?;
+    std::fs::write(&path, png)
+        .map_err(|e| ExportError::Io(format!("{}: {}", path.display(), e)))?;
+
+    info!("Capture auto-saved to {:?}", path);
+    Ok(path)
+}
+
+/// Move a saved capture to the trash instead of deleting it outright
+pub fn trash_capture(path: &Path) -> Result<(), ExportError> {
+    gio::File::for_path(path)
+        .trash(None::<&gio::Cancellable>)
+        .map_err(|e| ExportError::Io(format!("{}: {}", path.display(), e)))?;
+    info!("Moved {:?} to the trash", path);
+    Ok(())
+}
+
+/// Put a capture trashed by [`trash_capture`] back where it was. When it was
+/// trashed more than once, the most recent copy wins.
+pub fn restore_from_trash(path: &Path) -> Result<(), ExportError> {
+    let io_error = |e: glib::Error| ExportError::Io(format!("{}: {}", path.display(), e));
+
+    let trash = gio::File::for_uri("trash:///");
+    let entries = trash
+        .enumerate_children(
+            "standard::name,trash::orig-path,trash::deletion-date",
+            gio::FileQueryInfoFlags::NONE,
+            None::<&gio::Cancellable>,
+        )
+        .map_err(io_error)?;
+
+    let mut latest: Option<gio::FileInfo> = None;
+    for entry in entries {
+        let entry = entry.map_err(io_error)?;
+        let orig_path = entry.attribute_byte_string("trash::orig-path");
+        if orig_path.as_deref().map(Path::new) != Some(path) {
+            continue;
+        }
+        let newer = match latest {
+            Some(ref best) => {
+                entry.attribute_as_string("trash::deletion-date")
+                    > best.attribute_as_string("trash::deletion-date")
+            }
+            None => true,
+        };
+        if newer {
+            latest = Some(entry);
+        }
+    }
+
+    match latest {
+        Some(entry) => {
+            trash
+                .child(entry.name())
+                .move_(
+                    &gio::File::for_path(path),
+                    gio::FileCopyFlags::NONE,
+                    None::<&gio::Cancellable>,
+                    None,
+                )
+                .map_err(io_error)?;
+            info!("Restored {:?} from the trash", path);
+            Ok(())
+        }
+        None => Err(ExportError::Io(format!(
+            "{}: not found in the trash",
+            path.display()
+        ))),
+    }
+}
+
+/// `name.png`, or `name_2.png`, `name_3.png`, ... when it already exists
+fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{}_{}.png", stem, n)))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
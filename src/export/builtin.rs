@@ -0,0 +1,247 @@
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::glib;
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use super::ocr::{run_tesseract, tesseract_available};
+use super::process::output_with_input;
+use super::target::{
+    ExportError, ExportJob, ExportMetadata, ExportOutcome, ExportRegistry, ExportTarget, PendingPng,
+};
+
+/// Register the targets that ship with the application plus any user scripts
+pub fn register_builtin_targets(registry: &mut ExportRegistry) {
+    registry.register(Box::new(PicturesFolderTarget));
+    if tesseract_available() {
+        registry.register(Box::new(SearchablePdfTarget));
+    } else {
+        debug!("tesseract not found, no searchable PDF export");
+    }
+
+    for target in discover_script_targets() {
+        registry.register(Box::new(target));
+    }
+}
+
+fn encode_png(pixbuf: &Pixbuf) -> Result<Vec<u8>, ExportError> {
+    pixbuf
+        .save_to_bufferv("png", &[])
+        .map_err(|e| ExportError::Encoding(e.to_string()))
+}
+
+/// Saves straight into the user's Pictures folder without asking
+pub struct PicturesFolderTarget;
+
+impl ExportTarget for PicturesFolderTarget {
+    fn id(&self) -> &str {
+        "pictures-folder"
+    }
+
+    fn name(&self) -> &str {
+        "Save to Pictures"
+    }
+
+    fn icon_name(&self) -> &str {
+        "folder-pictures-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let image = PendingPng::new(pixbuf, metadata);
+        let file_name = PathBuf::from(&metadata.file_name);
+
+        Ok(Box::new(move || {
+            let path = pictures_path(&file_name)?;
+            std::fs::write(&path, image.encode()?)
+                .map_err(|e| ExportError::Io(format!("{}: {}", path.display(), e)))?;
+
+            info!("Image saved to {:?}", path);
+            Ok(ExportOutcome::new(format!("Saved to {}", path.display())))
+        }))
+    }
+}
+
+/// `file_name` in the user's Pictures folder, with any folders it names
+/// created
+fn pictures_path(file_name: &Path) -> Result<PathBuf, ExportError> {
+    let folder =
+        glib::user_special_dir(glib::UserDirectory::Pictures).unwrap_or_else(glib::home_dir);
+    let path = folder.join(file_name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| ExportError::Io(format!("{}: {}", parent.display(), e)))?;
+    }
+    Ok(path)
+}
+
+/// Saves a PDF of the image to the Pictures folder, with the text tesseract
+/// recognizes laid invisibly over it so the PDF can be searched and copied
+/// from
+pub struct SearchablePdfTarget;
+
+impl ExportTarget for SearchablePdfTarget {
+    fn id(&self) -> &str {
+        "searchable-pdf"
+    }
+
+    fn name(&self) -> &str {
+        "Save as Searchable PDF"
+    }
+
+    fn icon_name(&self) -> &str {
+        "x-office-document-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let png = encode_png(pixbuf)?;
+        let file_name = Path::new(&metadata.file_name).with_extension("pdf");
+
+        Ok(Box::new(move || {
+            debug!("Recognizing text for {:?}", file_name);
+            let pdf = run_tesseract(&png, "pdf")?;
+            if pdf.is_empty() {
+                return Err(ExportError::Failed("tesseract wrote no PDF".to_string()));
+            }
+
+            let path = pictures_path(&file_name)?;
+            std::fs::write(&path, pdf)
+                .map_err(|e| ExportError::Io(format!("{}: {}", path.display(), e)))?;
+
+            info!("Searchable PDF saved to {:?}", path);
+            Ok(ExportOutcome::new(format!("Saved to {}", path.display())))
+        }))
+    }
+}
+
+/// Runs an executable from the user's export script folder with the PNG on
+/// stdin. The first line the script prints becomes the result, and is treated
+/// as a link when it looks like one.
+pub struct ScriptTarget {
+    id: String,
+    name: String,
+    path: PathBuf,
+}
+
+impl ScriptTarget {
+    pub fn new(path: PathBuf) -> Self {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self {
+            id: format!("script:{}", stem),
+            name: stem.replace(['-', '_'], " "),
+            path,
+        }
+    }
+}
+
+impl ExportTarget for ScriptTarget {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn icon_name(&self) -> &str {
+        "utilities-terminal-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let png = encode_png(pixbuf)?;
+        let env_vars = metadata
+            .capture
+            .as_ref()
+            .map(|c| c.env_vars())
+            .unwrap_or_default();
+        let file_name = metadata.file_name.clone();
+        let name = self.name.clone();
+        let path = self.path.clone();
+
+        Ok(Box::new(move || {
+            debug!("Running export script {:?}", path);
+            let child = Command::new(&path)
+                .arg(&file_name)
+                .envs(env_vars)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| ExportError::Failed(format!("Failed to run {:?}: {}", path, e)))?;
+
+            let output = output_with_input(child, &png)?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(ExportError::Failed(format!(
+                    "{} exited with {}: {}",
+                    name,
+                    output.status,
+                    stderr.trim()
+                )));
+            }
+
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let first_line = stdout.lines().next().unwrap_or("").trim().to_string();
+
+            if first_line.starts_with("http://") || first_line.starts_with("https://") {
+                Ok(ExportOutcome::new(format!("{}: {}", name, first_line)).with_url(first_line))
+            } else if first_line.is_empty() {
+                Ok(ExportOutcome::new(format!("{} finished", name)))
+            } else {
+                Ok(ExportOutcome::new(first_line))
+            }
+        }))
+    }
+}
+
+/// Folder scanned for user export scripts
+pub fn script_directory() -> PathBuf {
+    glib::user_config_dir()
+        .join("screenshot_gnome")
+        .join("export")
+}
+
+fn discover_script_targets() -> Vec<ScriptTarget> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = script_directory();
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => {
+            debug!("No export scripts in {:?}", dir);
+            return Vec::new();
+        }
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.metadata()
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        })
+        .collect();
+    paths.sort();
+
+    if paths.is_empty() {
+        warn!("Export script folder {:?} has no executables", dir);
+    }
+
+    paths.into_iter().map(ScriptTarget::new).collect()
+}
@@ -0,0 +1,314 @@
+//! Posts the screenshot into a team chat: a Matrix room, or any channel with
+//! an incoming webhook that takes file uploads, such as Discord's.
+//!
+//! Matrix needs two requests, one uploading the image to the homeserver's
+//! media repository and one sending a message that shows it. Both are made
+//! with `curl`, from a worker thread with the upload's progress shown.
+
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::glib;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::http::{parse_json, CurlConfig};
+use super::target::{
+    ExportError, ExportJob, ExportMetadata, ExportOutcome, ExportTarget, PendingPng,
+};
+use super::upload::{send, Retry, Transfer, UploadJob};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatKind {
+    /// A multipart form with the image as `file` and the message as
+    /// `content`
+    #[default]
+    Webhook,
+
+    Matrix,
+}
+
+impl ChatKind {
+    pub const ALL: [ChatKind; 2] = [Self::Webhook, Self::Matrix];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Webhook => "webhook",
+            Self::Matrix => "matrix",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Webhook => "Webhook",
+            Self::Matrix => "Matrix",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.id() == id)
+    }
+}
+
+/// Where screenshots are posted, and what is said with them
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    pub kind: ChatKind,
+
+    /// The webhook's URL, or the Matrix homeserver's, e.g.
+    /// `https://matrix.example.org`
+    pub url: String,
+
+    /// Matrix room ID, such as `!abc:example.org`
+    pub room: String,
+
+    /// Matrix access token
+    pub token: String,
+
+    /// Sent along with the image; empty for the file name on Matrix and
+    /// nothing on webhooks
+    pub message: String,
+}
+
+impl ChatSettings {
+    fn url(&self) -> &str {
+        self.url.trim().trim_end_matches('/')
+    }
+}
+
+/// Posts the image to the chat in [`ChatSettings`]
+pub struct ChatTarget {
+    name: String,
+    settings: ChatSettings,
+}
+
+impl ChatTarget {
+    pub const ID: &'static str = "chat";
+
+    pub fn new(settings: ChatSettings) -> Self {
+        let name = match settings.kind {
+            ChatKind::Webhook => "Post to Webhook",
+            ChatKind::Matrix => "Send to Matrix Room",
+        };
+        Self {
+            name: name.to_string(),
+            settings,
+        }
+    }
+
+    /// The job encoding the image and posting it
+    fn prepare(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<UploadJob, ExportError> {
+        let image = PendingPng::new(pixbuf, metadata);
+        // Folders named by the filename template are left out
+        let file_name = Path::new(&metadata.file_name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| metadata.file_name.clone());
+        let settings = self.settings.clone();
+        let metadata = metadata.clone();
+
+        Ok(Box::new(move |transfer| {
+            let png = image.encode()?;
+            // Webhook URLs hold their secret, so they are not logged
+            debug!("Posting {:?} to {}", file_name, settings.kind.label());
+            transfer.set_total(png.len() as u64);
+            let summary = match settings.kind {
+                ChatKind::Webhook => {
+                    post_to_webhook(&settings, &png, &file_name, transfer)?;
+                    "Posted to the webhook"
+                }
+                ChatKind::Matrix => {
+                    send_to_matrix(&settings, &png, &file_name, &metadata, transfer)?;
+                    "Sent to the Matrix room"
+                }
+            };
+
+            info!("{}", summary);
+            Ok(ExportOutcome::new(summary))
+        }))
+    }
+}
+
+impl ExportTarget for ChatTarget {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn icon_name(&self) -> &str {
+        "mail-send-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let job = self.prepare(pixbuf, metadata)?;
+        Ok(Box::new(move || job(&Transfer::default())))
+    }
+
+    fn upload_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Option<Result<UploadJob, ExportError>> {
+        Some(self.prepare(pixbuf, metadata))
+    }
+}
+
+fn post_to_webhook(
+    settings: &ChatSettings,
+    png: &[u8],
+    file_name: &str,
+    transfer: &Transfer,
+) -> Result<(), ExportError> {
+    let file_name = file_name.replace(['"', ';'], "_");
+    let file_field = format!("file=@-;filename=\"{}\";type=image/png", file_name);
+    let message_field = format!("content={}", settings.message.trim());
+    // Anyone with the webhook's URL can post to it
+    let config = CurlConfig::new(&[("url", settings.url())])?;
+    let mut args = vec![
+        "--request",
+        "POST",
+        "--config",
+        config.path(),
+        "--form",
+        file_field.as_str(),
+    ];
+    if !settings.message.trim().is_empty() {
+        // --form-string keeps a leading @ or < from being read as a file
+        args.extend(["--form-string", message_field.as_str()]);
+    }
+
+    let response = send(&args, png, Retry::BeforeSent, transfer)?;
+    if response.is_success() {
+        Ok(())
+    } else {
+        Err(ExportError::Failed(format!(
+            "The webhook answered with HTTP status {}",
+            response.status
+        )))
+    }
+}
+
+fn send_to_matrix(
+    settings: &ChatSettings,
+    png: &[u8],
+    file_name: &str,
+    metadata: &ExportMetadata,
+    transfer: &Transfer,
+) -> Result<(), ExportError> {
+    let authorization = format!("Authorization: Bearer {}", settings.token.trim());
+    let config = CurlConfig::new(&[("header", &authorization)])?;
+
+    let upload_url = format!(
+        "{}/_matrix/media/v3/upload?filename={}",
+        settings.url(),
+        glib::Uri::escape_string(file_name, None, false)
+    );
+    let response = send(
+        &[
+            "--request",
+            "POST",
+            "--config",
+            config.path(),
+            "--header",
+            "Content-Type: image/png",
+            "--data-binary",
+            "@-",
+            &upload_url,
+        ],
+        png,
+        Retry::BeforeSent,
+        transfer,
+    )?;
+    let content_uri = parse_matrix_response(&response.body, "content_uri")?;
+
+    // A transaction ID seen before marks the request as a retry, which the
+    // server ignores
+    let transaction = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let send_url = format!(
+        "{}/_matrix/client/v3/rooms/{}/send/m.room.message/screenshot-{}",
+        settings.url(),
+        glib::Uri::escape_string(settings.room.trim(), None, false),
+        transaction
+    );
+    let caption = settings.message.trim();
+    let event = serde_json::json!({
+        "msgtype": "m.image",
+        "body": if caption.is_empty() { file_name } else { caption },
+        "filename": file_name,
+        "url": content_uri,
+        "info": {
+            "mimetype": "image/png",
+            "size": png.len(),
+            "w": metadata.width,
+            "h": metadata.height,
+        },
+    });
+    let response = send(
+        &[
+            "--request",
+            "PUT",
+            "--config",
+            config.path(),
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            &send_url,
+        ],
+        event.to_string().as_bytes(),
+        Retry::Always,
+        transfer,
+    )?;
+    parse_matrix_response(&response.body, "event_id").map(|_| ())
+}
+
+/// The string `field` of a Matrix response, or the error it gives instead
+fn parse_matrix_response(body: &str, field: &str) -> Result<String, ExportError> {
+    let response = parse_json(body)?;
+    match response[field].as_str() {
+        Some(value) => Ok(value.to_string()),
+        None => {
+            let message = response["error"]
+                .as_str()
+                .unwrap_or("unexpected response from the homeserver");
+            Err(ExportError::Failed(format!("Matrix: {}", message)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_matrix_response() {
+        assert_eq!(
+            parse_matrix_response(r#"{"content_uri": "mxc://example.org/AbC"}"#, "content_uri")
+                .unwrap(),
+            "mxc://example.org/AbC"
+        );
+        assert!(parse_matrix_response(
+            r#"{"errcode": "M_FORBIDDEN", "error": "You are not in this room"}"#,
+            "event_id"
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("not in this room"));
+    }
+}
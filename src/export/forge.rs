@@ -0,0 +1,373 @@
+//! Uploads to a GitHub or GitLab repository, giving the screenshot a
+//! permanent URL to paste into issues and merge requests.
+//!
+//! GitHub has no API for issue attachments, so the image is committed to a
+//! branch of the repository set aside for assets. GitLab stores it as a
+//! project upload, the same as an image dropped into an issue. Both are
+//! called with `curl` and a personal access token, from a worker thread with
+//! the upload's progress shown.
+
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::glib;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use super::http::{parse_json, CurlConfig};
+use super::target::{
+    ExportError, ExportJob, ExportMetadata, ExportOutcome, ExportTarget, PendingPng,
+};
+use super::upload::{send, Retry, Transfer, UploadJob};
+
+/// Folder of the assets branch GitHub uploads are committed to
+const GITHUB_FOLDER: &str = "screenshots";
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    #[default]
+    GitHub,
+    GitLab,
+}
+
+impl ForgeKind {
+    pub const ALL: [ForgeKind; 2] = [Self::GitHub, Self::GitLab];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::GitHub => "github",
+            Self::GitLab => "gitlab",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::GitHub => "GitHub",
+            Self::GitLab => "GitLab",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.id() == id)
+    }
+
+    pub fn default_host(&self) -> &'static str {
+        match self {
+            Self::GitHub => "https://github.com",
+            Self::GitLab => "https://gitlab.com",
+        }
+    }
+}
+
+/// Repository screenshots are uploaded to, and the token to do it with
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ForgeSettings {
+    pub kind: ForgeKind,
+
+    /// Web address of the server, e.g. `https://gitlab.example.com` for a
+    /// self-hosted GitLab; empty for the public site
+    #[serde(default)]
+    pub host: String,
+
+    /// `owner/name`, or the full project path on GitLab
+    pub repository: String,
+
+    pub token: String,
+
+    /// GitHub branch the images are committed to; empty for the default
+    /// branch
+    #[serde(default)]
+    pub branch: String,
+}
+
+impl ForgeSettings {
+    fn host(&self) -> &str {
+        let host = self.host.trim().trim_end_matches('/');
+        if host.is_empty() {
+            self.kind.default_host()
+        } else {
+            host
+        }
+    }
+
+    /// Base URL of the REST API
+    fn api_url(&self) -> String {
+        match self.kind {
+            ForgeKind::GitHub if self.host.trim().is_empty() => {
+                "https://api.github.com".to_string()
+            }
+            // GitHub Enterprise serves the API under the site itself
+            ForgeKind::GitHub => format!("{}/api/v3", self.host()),
+            ForgeKind::GitLab => format!("{}/api/v4", self.host()),
+        }
+    }
+}
+
+/// Uploads the image to the repository in [`ForgeSettings`]
+pub struct ForgeTarget {
+    name: String,
+    settings: ForgeSettings,
+}
+
+impl ForgeTarget {
+    pub const ID: &'static str = "forge-upload";
+
+    pub fn new(settings: ForgeSettings) -> Self {
+        Self {
+            name: format!("Upload to {}", settings.kind.label()),
+            settings,
+        }
+    }
+
+    /// The job encoding the image and uploading it
+    fn prepare(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<UploadJob, ExportError> {
+        let image = PendingPng::new(pixbuf, metadata);
+        // Folders named by the filename template are left out
+        let file_name = Path::new(&metadata.file_name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| metadata.file_name.clone());
+        let settings = self.settings.clone();
+        let metadata = metadata.clone();
+
+        Ok(Box::new(move |transfer| {
+            let png = image.encode()?;
+            debug!(
+                "Uploading {:?} to {} {}",
+                file_name,
+                settings.kind.label(),
+                settings.repository
+            );
+            let url = match settings.kind {
+                ForgeKind::GitHub => {
+                    upload_to_github(&settings, &png, &file_name, &metadata, transfer)?
+                }
+                ForgeKind::GitLab => upload_to_gitlab(&settings, &png, &file_name, transfer)?,
+            };
+
+            info!("Uploaded to {}", url);
+            Ok(ExportOutcome::new(format!("Uploaded to {}", url)).with_url(url))
+        }))
+    }
+}
+
+impl ExportTarget for ForgeTarget {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn icon_name(&self) -> &str {
+        "send-to-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let job = self.prepare(pixbuf, metadata)?;
+        Ok(Box::new(move || job(&Transfer::default())))
+    }
+
+    fn upload_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Option<Result<UploadJob, ExportError>> {
+        Some(self.prepare(pixbuf, metadata))
+    }
+}
+
+/// Commit the image to the assets branch. The timestamp keeps names from
+/// clashing, as GitHub refuses to replace a file without its hash.
+fn upload_to_github(
+    settings: &ForgeSettings,
+    png: &[u8],
+    file_name: &str,
+    metadata: &ExportMetadata,
+    transfer: &Transfer,
+) -> Result<String, ExportError> {
+    let path = format!("{}/{}-{}", GITHUB_FOLDER, metadata.timestamp, file_name);
+    let url = format!(
+        "{}/repos/{}/contents/{}",
+        settings.api_url(),
+        settings.repository.trim(),
+        glib::Uri::escape_string(&path, Some("/"), false)
+    );
+
+    let mut request = serde_json::json!({
+        "message": format!("Add screenshot {}", file_name),
+        "content": glib::base64_encode(png).as_str(),
+    });
+    if !settings.branch.trim().is_empty() {
+        request["branch"] = settings.branch.trim().into();
+    }
+
+    let request = request.to_string();
+    let authorization = format!("Authorization: Bearer {}", settings.token.trim());
+    let config = CurlConfig::new(&[("header", &authorization)])?;
+    transfer.set_total(request.len() as u64);
+    let response = send(
+        &[
+            "--request",
+            "PUT",
+            "--config",
+            config.path(),
+            "--header",
+            "Accept: application/vnd.github+json",
+            "--header",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+            &url,
+        ],
+        request.as_bytes(),
+        // Committing the file again fails once the first commit went through
+        Retry::BeforeSent,
+        transfer,
+    )?;
+    parse_github_response(&response.body)
+}
+
+/// The link to the committed image in a contents API response
+fn parse_github_response(body: &str) -> Result<String, ExportError> {
+    let response = parse_json(body)?;
+    match response["content"]["html_url"].as_str() {
+        // Served as the image itself to anyone who can see the repository
+        Some(html_url) => Ok(format!("{}?raw=true", html_url)),
+        None => Err(response_error(&response)),
+    }
+}
+
+fn upload_to_gitlab(
+    settings: &ForgeSettings,
+    png: &[u8],
+    file_name: &str,
+    transfer: &Transfer,
+) -> Result<String, ExportError> {
+    let project = settings.repository.trim().trim_matches('/');
+    let url = format!(
+        "{}/projects/{}/uploads",
+        settings.api_url(),
+        glib::Uri::escape_string(project, None, false)
+    );
+    let file_name = file_name.replace(['"', ';'], "_");
+
+    let token = format!("PRIVATE-TOKEN: {}", settings.token.trim());
+    let config = CurlConfig::new(&[("header", &token)])?;
+    transfer.set_total(png.len() as u64);
+    let response = send(
+        &[
+            "--request",
+            "POST",
+            "--config",
+            config.path(),
+            "--form",
+            &format!("file=@-;filename=\"{}\";type=image/png", file_name),
+            &url,
+        ],
+        png,
+        Retry::BeforeSent,
+        transfer,
+    )?;
+    parse_gitlab_response(&response.body, settings.host(), project)
+}
+
+/// Absolute link to the upload. Newer servers give its full path, older
+/// ones a path relative to the project page.
+fn parse_gitlab_response(body: &str, host: &str, project: &str) -> Result<String, ExportError> {
+    let response = parse_json(body)?;
+    if let Some(full_path) = response["full_path"].as_str() {
+        Ok(format!("{}{}", host, full_path))
+    } else if let Some(url) = response["url"].as_str() {
+        Ok(format!("{}/{}{}", host, project, url))
+    } else {
+        Err(response_error(&response))
+    }
+}
+
+/// The reason given in an error response from either API
+fn response_error(response: &serde_json::Value) -> ExportError {
+    let message = match (&response["message"], &response["error"]) {
+        (serde_json::Value::String(message), _) => message.clone(),
+        (serde_json::Value::Null, serde_json::Value::String(error)) => error.clone(),
+        (serde_json::Value::Null, _) => "no link in the response".to_string(),
+        // GitLab lists validation errors by field
+        (message, _) => message.to_string(),
+    };
+    ExportError::Failed(format!("Upload failed: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(kind: ForgeKind, host: &str) -> ForgeSettings {
+        ForgeSettings {
+            kind,
+            host: host.to_string(),
+            repository: "me/app".to_string(),
+            token: "token".to_string(),
+            branch: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_api_url() {
+        assert_eq!(
+            settings(ForgeKind::GitHub, "").api_url(),
+            "https://api.github.com"
+        );
+        assert_eq!(
+            settings(ForgeKind::GitHub, "https://git.example.com/").api_url(),
+            "https://git.example.com/api/v3"
+        );
+        assert_eq!(
+            settings(ForgeKind::GitLab, "").api_url(),
+            "https://gitlab.com/api/v4"
+        );
+    }
+
+    #[test]
+    fn test_parse_responses() {
+        assert_eq!(
+            parse_github_response(
+                r#"{"content": {"html_url": "https://github.com/me/app/blob/assets/screenshots/1-a.png"}}"#
+            )
+            .unwrap(),
+            "https://github.com/me/app/blob/assets/screenshots/1-a.png?raw=true"
+        );
+        assert!(parse_github_response(r#"{"message": "Bad credentials"}"#)
+            .unwrap_err()
+            .to_string()
+            .contains("Bad credentials"));
+
+        assert_eq!(
+            parse_gitlab_response(
+                r#"{"url": "/uploads/abc/a.png", "full_path": "/-/project/7/uploads/abc/a.png"}"#,
+                "https://gitlab.com",
+                "me/app"
+            )
+            .unwrap(),
+            "https://gitlab.com/-/project/7/uploads/abc/a.png"
+        );
+        assert_eq!(
+            parse_gitlab_response(
+                r#"{"url": "/uploads/abc/a.png"}"#,
+                "https://gitlab.com",
+                "me/app"
+            )
+            .unwrap(),
+            "https://gitlab.com/me/app/uploads/abc/a.png"
+        );
+        assert!(parse_gitlab_response("<html>", "https://gitlab.com", "me/app").is_err());
+    }
+}
@@ -0,0 +1,144 @@
+//! Web requests for uploads, made by running `curl` rather than linking an
+//! HTTP client.
+//!
+//! Tokens and passwords never go on curl's command line, where any user can
+//! read them from the process list. They are written to a config file only
+//! the user can open, which curl reads with `--config`.
+
+use gtk4::glib;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use super::process::output_with_input;
+use super::target::ExportError;
+
+/// Options for curl that hold secrets, kept in a private file for as long as
+/// it lives. Pass `--config` and [`CurlConfig::path`] to curl.
+pub struct CurlConfig {
+    path: PathBuf,
+}
+
+impl CurlConfig {
+    /// Write `options`, as pairs of long option name without its dashes and
+    /// value, e.g. `("header", "Authorization: Bearer …")`
+    pub fn new(options: &[(&str, &str)]) -> Result<Self, ExportError> {
+        static COUNT: AtomicU32 = AtomicU32::new(0);
+        // The runtime directory is the user's own and lives in memory
+        let folder = glib::user_runtime_dir();
+        std::fs::create_dir_all(&folder).map_err(|e| ExportError::Io(e.to_string()))?;
+        let path = folder.join(format!(
+            "screenshot_gnome-curl-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)
+            .map_err(|e| ExportError::Io(format!("Failed to create {:?}: {}", path, e)))?;
+        // Removed on drop from here on, even if writing fails
+        let config = Self { path };
+        let contents: String = options
+            .iter()
+            .map(|(option, value)| config_line(option, value))
+            .collect();
+        file.write_all(contents.as_bytes())
+            .map_err(|e| ExportError::Io(e.to_string()))?;
+        Ok(config)
+    }
+
+    pub fn path(&self) -> &str {
+        // The runtime directory and the name are plain ASCII in practice
+        self.path.to_str().unwrap_or_default()
+    }
+}
+
+impl Drop for CurlConfig {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// `option = "value"`, quoted the way curl's config files expect
+fn config_line(option: &str, value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    format!("{} = \"{}\"\n", option, quoted)
+}
+
+/// Run `curl` with `args`, sending `stdin`, and return what it prints
+pub fn curl(args: &[&str], stdin: &[u8]) -> Result<String, ExportError> {
+    let output = run_curl(args, stdin)?;
+    Ok(String::from_utf8_lossy(&output).to_string())
+}
+
+/// Run `curl` with `args`, sending `stdin`, and return the HTTP status of
+/// the response, for requests whose body doesn't matter
+pub fn curl_status(args: &[&str], stdin: &[u8]) -> Result<u32, ExportError> {
+    let mut args = args.to_vec();
+    args.extend(["--output", "/dev/null", "--write-out", "%{http_code}"]);
+    let output = curl(&args, stdin)?;
+    output
+        .trim()
+        .parse()
+        .map_err(|_| ExportError::Failed(format!("Unexpected HTTP status {:?}", output)))
+}
+
+fn run_curl(args: &[&str], stdin: &[u8]) -> Result<Vec<u8>, ExportError> {
+    let child = Command::new("curl")
+        .args(["--silent", "--show-error"])
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ExportError::Failed(format!("Failed to run curl: {}", e)))?;
+
+    let output = output_with_input(child, stdin)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ExportError::Failed(format!(
+            "curl exited with {}: {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+pub fn parse_json(body: &str) -> Result<serde_json::Value, ExportError> {
+    serde_json::from_str(body)
+        .map_err(|e| ExportError::Failed(format!("Unexpected response: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_line() {
+        assert_eq!(
+            config_line("header", "Authorization: Bearer abc"),
+            "header = \"Authorization: Bearer abc\"\n"
+        );
+        assert_eq!(
+            config_line("user", "me:pa\"ss\\word"),
+            "user = \"me:pa\\\"ss\\\\word\"\n"
+        );
+    }
+}
@@ -0,0 +1,31 @@
+pub mod animation;
+pub mod audit;
+pub mod autosave;
+pub mod builtin;
+pub mod chat;
+pub mod forge;
+pub mod http;
+pub mod nextcloud;
+pub mod ocr;
+pub mod optimize;
+pub mod process;
+pub mod snippet;
+pub mod target;
+pub mod translate;
+pub mod upload;
+
+pub use animation::{encode_animation, AnimationFormat, AnimationSettings};
+pub use audit::{find_sensitive_text, SensitiveKind, SensitiveMatch};
+pub use autosave::{auto_save, restore_from_trash, trash_capture, AutoSaveLayout};
+pub use builtin::register_builtin_targets;
+pub use chat::{ChatKind, ChatSettings, ChatTarget};
+pub use forge::{ForgeKind, ForgeSettings, ForgeTarget};
+pub use nextcloud::{NextcloudSettings, NextcloudTarget};
+pub use ocr::{find_urls, recognize_text, tesseract_available};
+pub use optimize::{encode_rgba, optimize_png, optimize_with_report, OptimizeReport, RgbaBuffer};
+pub use snippet::{alt_text_from_ocr, image_snippet, SnippetFormat};
+pub use target::{
+    ExportError, ExportJob, ExportMetadata, ExportOutcome, ExportRegistry, ExportTarget, PendingPng,
+};
+pub use translate::{TranslationBackend, TranslationSettings};
+pub use upload::{Transfer, UploadJob, UploadOptions};
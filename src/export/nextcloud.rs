@@ -0,0 +1,744 @@
+//! Uploads to a Nextcloud server and shares the file by public link.
+//!
+//! The image is put in a folder of the user's files over WebDAV, then the
+//! sharing API makes a link for it, optionally expiring or protected by a
+//! password. An app password is used rather than the account's own, so it can
+//! be revoked from the server's security settings.
+//!
+//! Large files, such as recordings, go up in chunks through the server's
+//! chunking API, each chunk retried on its own, so a dropped connection only
+//! costs the chunk it interrupted. An upload that fails altogether is
+//! resumed the next time the same file is shared.
+
+use gtk4::gdk_pixbuf::Pixbuf;
+use gtk4::glib;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::http::{curl, curl_status, parse_json, CurlConfig};
+use super::target::{
+    ExportError, ExportJob, ExportMetadata, ExportOutcome, ExportTarget, PendingPng,
+};
+use super::upload::{send, Retry, Transfer, UploadJob};
+
+/// `shareType` of a public link in the sharing API
+const PUBLIC_LINK_SHARE: &str = "3";
+
+/// Files larger than this are uploaded in chunks
+const CHUNKED_UPLOAD_THRESHOLD: usize = 10 * 1024 * 1024;
+
+/// Size of every chunk but the last, the smallest the server accepts
+const CHUNK_SIZE: usize = 5 * 1024 * 1024;
+
+/// Server, account and link options for Nextcloud sharing
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NextcloudSettings {
+    /// Web address of the server, e.g. `https://cloud.example.com`
+    pub server: String,
+
+    pub user: String,
+
+    pub app_password: String,
+
+    /// Folder of the user's files the images go in, created if missing
+    pub folder: String,
+
+    /// Days until the link stops working; 0 for never
+    pub expire_days: u32,
+
+    /// Password asked for when opening the link; empty for none
+    pub share_password: String,
+}
+
+impl Default for NextcloudSettings {
+    fn default() -> Self {
+        Self {
+            server: String::new(),
+            user: String::new(),
+            app_password: String::new(),
+            folder: "Screenshots".to_string(),
+            expire_days: 0,
+            share_password: String::new(),
+        }
+    }
+}
+
+impl NextcloudSettings {
+    /// Longest expiry offered, a year
+    pub const MAX_EXPIRE_DAYS: u32 = 365;
+
+    fn server(&self) -> &str {
+        self.server.trim().trim_end_matches('/')
+    }
+
+    /// `user:app_password`, for curl's `--user`
+    fn user_option(&self) -> String {
+        format!("{}:{}", self.user.trim(), self.app_password.trim())
+    }
+
+    /// The login, in a private config file for curl
+    fn credentials(&self) -> Result<CurlConfig, ExportError> {
+        CurlConfig::new(&[("user", &self.user_option())])
+    }
+
+    /// Path of `file_name` in the user's files, from their root
+    fn file_path(&self, file_name: &str) -> String {
+        folder_segments(&self.folder)
+            .into_iter()
+            .chain([file_name])
+            .fold(String::new(), |path, segment| path + "/" + segment)
+    }
+
+    /// WebDAV URL of `path` in the user's files
+    fn dav_url(&self, path: &str) -> String {
+        format!(
+            "{}/remote.php/dav/files/{}{}",
+            self.server(),
+            glib::Uri::escape_string(self.user.trim(), None, false),
+            glib::Uri::escape_string(path, Some("/"), false)
+        )
+    }
+
+    /// WebDAV URL of the folder chunks of upload `id` are put in
+    fn chunk_folder_url(&self, id: &str) -> String {
+        format!(
+            "{}/remote.php/dav/uploads/{}/{}",
+            self.server(),
+            glib::Uri::escape_string(self.user.trim(), None, false),
+            glib::Uri::escape_string(id, None, false)
+        )
+    }
+}
+
+fn folder_segments(folder: &str) -> Vec<&str> {
+    folder
+        .split('/')
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// What is uploaded
+enum Source {
+    /// An image encoded in memory
+    Bytes(Vec<u8>),
+
+    /// A recording, read a chunk at a time as it may not fit in memory
+    File {
+        path: PathBuf,
+        len: u64,
+        modified: SystemTime,
+    },
+}
+
+impl Source {
+    fn file(path: &Path) -> Result<Self, ExportError> {
+        let metadata = std::fs::metadata(path)
+            .map_err(|e| ExportError::Io(format!("Failed to read {:?}: {}", path, e)))?;
+        Ok(Self::File {
+            path: path.to_path_buf(),
+            len: metadata.len(),
+            modified: metadata.modified().unwrap_or(UNIX_EPOCH),
+        })
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            Self::Bytes(data) => data.len() as u64,
+            Self::File { len, .. } => *len,
+        }
+    }
+
+    /// `length` bytes from `offset` on
+    fn chunk(&self, offset: u64, length: u64) -> Result<Cow<'_, [u8]>, ExportError> {
+        match self {
+            Self::Bytes(data) => Ok(Cow::Borrowed(
+                &data[offset as usize..(offset + length) as usize],
+            )),
+            Self::File { path, .. } => {
+                let io = |e: std::io::Error| ExportError::Io(format!("{:?}: {}", path, e));
+                let mut file = File::open(path).map_err(io)?;
+                file.seek(SeekFrom::Start(offset)).map_err(io)?;
+                let mut chunk = vec![0; length as usize];
+                file.read_exact(&mut chunk).map_err(io)?;
+                Ok(Cow::Owned(chunk))
+            }
+        }
+    }
+
+    /// Changes whenever the contents do. Files are told apart by size and
+    /// modification time rather than read through.
+    fn fingerprint(&self) -> String {
+        match self {
+            Self::Bytes(data) => glib::compute_checksum_for_data(glib::ChecksumType::Sha256, data)
+                .map(|checksum| checksum.to_string())
+                .unwrap_or_default(),
+            Self::File { len, modified, .. } => {
+                let modified = modified
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_nanos())
+                    .unwrap_or(0);
+                format!("{}:{}", len, modified)
+            }
+        }
+    }
+}
+
+/// Name of the folder chunks of a file are put in, the same every time the
+/// file with `fingerprint` is uploaded to `destination_url`
+fn upload_id(destination_url: &str, fingerprint: &str) -> String {
+    let key = format!("{}\n{}", destination_url, fingerprint);
+    // Half of a SHA-256 is plenty to keep uploads apart
+    let checksum = glib::compute_checksum_for_string(glib::ChecksumType::Sha256, key.as_str())
+        .map(|checksum| checksum[..32].to_string())
+        .unwrap_or_default();
+    format!("screenshot-{}", checksum)
+}
+
+/// Media type of a recording, by its extension
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("gif") => "image/gif",
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Uploads the image to the Nextcloud server in [`NextcloudSettings`] and
+/// copies a share link to it
+pub struct NextcloudTarget {
+    settings: NextcloudSettings,
+}
+
+impl NextcloudTarget {
+    pub const ID: &'static str = "nextcloud";
+
+    pub fn new(settings: NextcloudSettings) -> Self {
+        Self { settings }
+    }
+
+    /// Create the upload folder and any missing parents
+    fn create_folder(&self) -> Result<(), ExportError> {
+        let credentials = self.settings.credentials()?;
+        let mut path = String::new();
+        for segment in folder_segments(&self.settings.folder) {
+            path = path + "/" + segment;
+            let status = curl_status(
+                &[
+                    "--request",
+                    "MKCOL",
+                    "--config",
+                    credentials.path(),
+                    &self.settings.dav_url(&path),
+                ],
+                &[],
+            )?;
+            // 405 means the folder is already there
+            if status != 201 && status != 405 {
+                return Err(status_error(status, &format!("create folder {}", path)));
+            }
+        }
+        Ok(())
+    }
+
+    fn upload(
+        &self,
+        path: &str,
+        source: &Source,
+        content_type: &str,
+        transfer: &Transfer,
+    ) -> Result<(), ExportError> {
+        transfer.set_total(source.len());
+        if source.len() > CHUNKED_UPLOAD_THRESHOLD as u64 {
+            return self.upload_chunks(path, source, transfer);
+        }
+
+        let data = source.chunk(0, source.len())?;
+        let credentials = self.settings.credentials()?;
+        let content_type = format!("Content-Type: {}", content_type);
+        let response = send(
+            &[
+                "--request",
+                "PUT",
+                "--config",
+                credentials.path(),
+                // Never replace a file, which may already be shared
+                "--header",
+                "If-None-Match: *",
+                "--header",
+                &content_type,
+                "--data-binary",
+                "@-",
+                &self.settings.dav_url(path),
+            ],
+            &data,
+            Retry::Always,
+            transfer,
+        )?;
+        match response.status {
+            201 | 204 => Ok(()),
+            412 => Err(already_exists(path)),
+            status => Err(status_error(status, "upload")),
+        }
+    }
+
+    /// Upload `source` in chunks to a folder of its own, then have the server
+    /// join them into the file at `path`.
+    ///
+    /// The folder is named after the file and where it goes, so when an
+    /// upload fails the next export of the same file finds the chunks
+    /// already sent and carries on from there. It is only removed when the
+    /// upload is cancelled; the server clears out folders left unfinished.
+    fn upload_chunks(
+        &self,
+        path: &str,
+        source: &Source,
+        transfer: &Transfer,
+    ) -> Result<(), ExportError> {
+        let destination_url = self.settings.dav_url(path);
+        let id = upload_id(&destination_url, &source.fingerprint());
+        let folder_url = self.settings.chunk_folder_url(&id);
+        let credentials = self.settings.credentials()?;
+        // Named on every request, so the server can check for space early
+        let destination = format!("Destination: {}", destination_url);
+
+        debug!(
+            "Uploading {} bytes in chunks of {} to {}",
+            source.len(),
+            CHUNK_SIZE,
+            folder_url
+        );
+        let status = curl_status(
+            &[
+                "--request",
+                "MKCOL",
+                "--config",
+                credentials.path(),
+                "--header",
+                &destination,
+                &folder_url,
+            ],
+            &[],
+        )?;
+        let uploaded = match status {
+            201 => BTreeMap::new(),
+            // Left by an earlier attempt at the same upload
+            405 => self.list_chunks(&credentials, &folder_url)?,
+            status => return Err(status_error(status, "start the upload")),
+        };
+        if !uploaded.is_empty() {
+            info!("Resuming the upload with {} parts sent", uploaded.len());
+        }
+
+        let result = self
+            .send_chunks(
+                &credentials,
+                &folder_url,
+                &destination,
+                source,
+                &uploaded,
+                transfer,
+            )
+            .and_then(|()| self.assemble(&credentials, &folder_url, path, &destination));
+        match result {
+            Err(ExportError::Cancelled) => {
+                let removed = curl_status(
+                    &[
+                        "--request",
+                        "DELETE",
+                        "--config",
+                        credentials.path(),
+                        &folder_url,
+                    ],
+                    &[],
+                );
+                debug!("Removed the cancelled upload: {:?}", removed);
+            }
+            Err(_) => debug!("Keeping {} to resume the upload", folder_url),
+            Ok(()) => {}
+        }
+        result
+    }
+
+    /// The chunks already in the upload folder at `folder_url`, by name
+    fn list_chunks(
+        &self,
+        credentials: &CurlConfig,
+        folder_url: &str,
+    ) -> Result<BTreeMap<String, u64>, ExportError> {
+        let body = curl(
+            &[
+                "--request",
+                "PROPFIND",
+                "--config",
+                credentials.path(),
+                "--header",
+                "Depth: 1",
+                folder_url,
+            ],
+            &[],
+        )?;
+        Ok(parse_chunk_listing(&body))
+    }
+
+    /// Upload the chunks of `source` not `uploaded` yet
+    fn send_chunks(
+        &self,
+        credentials: &CurlConfig,
+        folder_url: &str,
+        destination: &str,
+        source: &Source,
+        uploaded: &BTreeMap<String, u64>,
+        transfer: &Transfer,
+    ) -> Result<(), ExportError> {
+        let size = source.len();
+        let chunk_size = CHUNK_SIZE as u64;
+        for index in 0..size.div_ceil(chunk_size) {
+            // Chunks are numbered from 1, padded so they sort as text too
+            let name = format!("{:05}", index + 1);
+            let offset = index * chunk_size;
+            let length = chunk_size.min(size - offset);
+            if uploaded.get(&name) == Some(&length) {
+                transfer.skip(length);
+                continue;
+            }
+
+            let chunk = source.chunk(offset, length)?;
+            let chunk_url = format!("{}/{}", folder_url, name);
+            let response = send(
+                &[
+                    "--request",
+                    "PUT",
+                    "--config",
+                    credentials.path(),
+                    "--header",
+                    destination,
+                    "--data-binary",
+                    "@-",
+                    &chunk_url,
+                ],
+                &chunk,
+                Retry::Always,
+                transfer,
+            )?;
+            if !response.is_success() {
+                return Err(status_error(
+                    response.status,
+                    &format!("upload part {}", index + 1),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Have the server join the chunks into the file at `path`
+    fn assemble(
+        &self,
+        credentials: &CurlConfig,
+        folder_url: &str,
+        path: &str,
+        destination: &str,
+    ) -> Result<(), ExportError> {
+        let assembled_url = format!("{}/.file", folder_url);
+        let status = curl_status(
+            &[
+                "--request",
+                "MOVE",
+                "--config",
+                credentials.path(),
+                "--header",
+                destination,
+                // Never replace a file, which may already be shared
+                "--header",
+                "Overwrite: F",
+                &assembled_url,
+            ],
+            &[],
+        )?;
+        match status {
+            201 | 204 => Ok(()),
+            412 => Err(already_exists(path)),
+            status => Err(status_error(status, "finish the upload")),
+        }
+    }
+
+    fn share(&self, path: &str, expire_date: Option<&str>) -> Result<String, ExportError> {
+        let url = format!(
+            "{}/ocs/v2.php/apps/files_sharing/api/v1/shares",
+            self.settings.server()
+        );
+        let mut fields = vec![
+            format!("path={}", path),
+            format!("shareType={}", PUBLIC_LINK_SHARE),
+        ];
+        if let Some(date) = expire_date {
+            fields.push(format!("expireDate={}", date));
+        }
+        // The share password goes with the login, out of sight
+        let user = self.settings.user_option();
+        let password = format!("password={}", self.settings.share_password);
+        let mut secrets = vec![("user", user.as_str())];
+        if !self.settings.share_password.is_empty() {
+            secrets.push(("data-urlencode", password.as_str()));
+        }
+        let config = CurlConfig::new(&secrets)?;
+
+        let mut args = vec![
+            "--request",
+            "POST",
+            "--config",
+            config.path(),
+            "--header",
+            "OCS-APIRequest: true",
+            "--header",
+            "Accept: application/json",
+        ];
+        for field in &fields {
+            args.extend(["--data-urlencode", field.as_str()]);
+        }
+        args.push(url.as_str());
+
+        let body = curl(&args, &[])?;
+        parse_share_response(&body)
+    }
+
+    /// The job encoding the image, then uploading and sharing it
+    fn prepare(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<UploadJob, ExportError> {
+        let image = PendingPng::new(pixbuf, metadata);
+        // Folders named by the filename template are left out
+        let file_name = Path::new(&metadata.file_name)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| metadata.file_name.clone());
+        let target = Self::new(self.settings.clone());
+        let timestamp = metadata.timestamp;
+        Ok(Box::new(move |transfer| {
+            let png = image.encode()?;
+            let upload = target.job(Source::Bytes(png), file_name, "image/png", timestamp);
+            upload(transfer)
+        }))
+    }
+
+    /// The job uploading and sharing the recording at `path`, read from disk
+    /// a chunk at a time
+    pub fn recording_job(&self, path: &Path) -> Result<UploadJob, ExportError> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| ExportError::Failed(format!("{:?} is not a file", path)))?;
+        let source = Source::file(path)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        Ok(self.job(source, file_name, content_type(path), timestamp))
+    }
+
+    fn job(
+        &self,
+        source: Source,
+        file_name: String,
+        content_type: &'static str,
+        timestamp: u64,
+    ) -> UploadJob {
+        let target = Self::new(self.settings.clone());
+        Box::new(move |transfer| {
+            let path = target.settings.file_path(&file_name);
+            debug!("Uploading {:?} to {}", path, target.settings.server());
+            target.create_folder()?;
+            target.upload(&path, &source, content_type, transfer)?;
+
+            let expire_date = match target.settings.expire_days {
+                0 => None,
+                days => expire_date(timestamp, days),
+            };
+            let url = target.share(&path, expire_date.as_deref())?;
+
+            info!("Shared {:?} as {}", path, url);
+            Ok(ExportOutcome::new("Share link copied").with_copied_url(url))
+        })
+    }
+}
+
+impl ExportTarget for NextcloudTarget {
+    fn id(&self) -> &str {
+        Self::ID
+    }
+
+    fn name(&self) -> &str {
+        "Share with Nextcloud"
+    }
+
+    fn icon_name(&self) -> &str {
+        "emblem-shared-symbolic"
+    }
+
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let job = self.prepare(pixbuf, metadata)?;
+        Ok(Box::new(move || job(&Transfer::default())))
+    }
+
+    fn upload_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Option<Result<UploadJob, ExportError>> {
+        Some(self.prepare(pixbuf, metadata))
+    }
+}
+
+/// `YYYY-MM-DD`, `days` after `timestamp`, in local time
+fn expire_date(timestamp: u64, days: u32) -> Option<String> {
+    glib::DateTime::from_unix_local(timestamp as i64)
+        .and_then(|dt| dt.add_days(days as i32))
+        .and_then(|dt| dt.format("%Y-%m-%d"))
+        .map(|date| date.to_string())
+        .ok()
+}
+
+/// The public link in a sharing API response
+fn parse_share_response(body: &str) -> Result<String, ExportError> {
+    let response = parse_json(body)?;
+    match response["ocs"]["data"]["url"].as_str() {
+        Some(url) => Ok(url.to_string()),
+        None => {
+            let message = response["ocs"]["meta"]["message"]
+                .as_str()
+                .filter(|message| !message.is_empty())
+                .unwrap_or("no link in the response");
+            Err(ExportError::Failed(format!("Sharing failed: {}", message)))
+        }
+    }
+}
+
+/// Names and sizes of the files in a `PROPFIND` listing of an upload
+/// folder. The folder itself is listed too, but without a size.
+fn parse_chunk_listing(xml: &str) -> BTreeMap<String, u64> {
+    let mut chunks = BTreeMap::new();
+    // Every other piece is what one <d:response> element holds
+    for response in xml.split("response>") {
+        let href = element_text(response, "href");
+        let size = element_text(response, "getcontentlength").and_then(|s| s.trim().parse().ok());
+        if let (Some(href), Some(size)) = (href, size) {
+            if let Some(name) = href.trim().trim_end_matches('/').rsplit('/').next() {
+                chunks.insert(name.to_string(), size);
+            }
+        }
+    }
+    chunks
+}
+
+/// Text of the first element called `name`, whatever its namespace prefix
+fn element_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("{}>", name))? + name.len() + 1;
+    let end = start + xml[start..].find('<')?;
+    Some(&xml[start..end])
+}
+
+fn already_exists(path: &str) -> ExportError {
+    ExportError::Failed(format!("{} is already on the server", path))
+}
+
+fn status_error(status: u32, action: &str) -> ExportError {
+    let reason = match status {
+        401 => "the user name or app password was refused".to_string(),
+        403 => "not allowed".to_string(),
+        404 => "no Nextcloud server at that address".to_string(),
+        507 => "out of storage".to_string(),
+        status => format!("HTTP status {}", status),
+    };
+    ExportError::Failed(format!("Failed to {}: {}", action, reason))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dav_url() {
+        let settings = NextcloudSettings {
+            server: "https://cloud.example.com/".to_string(),
+            user: "ana maria".to_string(),
+            folder: " /Pictures/ Screens / ".to_string(),
+            ..Default::default()
+        };
+        let path = settings.file_path("a b.png");
+        assert_eq!(path, "/Pictures/Screens/a b.png");
+        assert_eq!(
+            settings.dav_url(&path),
+            "https://cloud.example.com/remote.php/dav/files/ana%20maria/Pictures/Screens/a%20b.png"
+        );
+        assert_eq!(
+            settings.chunk_folder_url("screenshot-1"),
+            "https://cloud.example.com/remote.php/dav/uploads/ana%20maria/screenshot-1"
+        );
+    }
+
+    #[test]
+    fn test_parse_chunk_listing() {
+        let listing = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+ <d:response>
+  <d:href>/remote.php/dav/uploads/ana/screenshot-1/</d:href>
+  <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/uploads/ana/screenshot-1/00001</d:href>
+  <d:propstat><d:prop><d:getcontentlength>5242880</d:getcontentlength></d:prop></d:propstat>
+ </d:response>
+ <d:response>
+  <d:href>/remote.php/dav/uploads/ana/screenshot-1/00002</d:href>
+  <d:propstat><d:prop><d:getcontentlength>1024</d:getcontentlength></d:prop></d:propstat>
+ </d:response>
+</d:multistatus>"#;
+        let chunks = parse_chunk_listing(listing);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks.get("00001"), Some(&5242880));
+        assert_eq!(chunks.get("00002"), Some(&1024));
+        assert!(parse_chunk_listing("<html>").is_empty());
+    }
+
+    #[test]
+    fn test_upload_id() {
+        let id = upload_id("https://cloud.example.com/a.webm", "1024:5");
+        assert!(id.starts_with("screenshot-"));
+        assert_eq!(id, upload_id("https://cloud.example.com/a.webm", "1024:5"));
+        assert_ne!(id, upload_id("https://cloud.example.com/a.webm", "1024:6"));
+        assert_ne!(id, upload_id("https://cloud.example.com/b.webm", "1024:5"));
+    }
+
+    #[test]
+    fn test_parse_share_response() {
+        assert_eq!(
+            parse_share_response(
+                r#"{"ocs": {"meta": {"statuscode": 200}, "data": {"url": "https://cloud.example.com/s/AbC"}}}"#
+            )
+            .unwrap(),
+            "https://cloud.example.com/s/AbC"
+        );
+        assert!(parse_share_response(
+            r#"{"ocs": {"meta": {"statuscode": 400, "message": "Invalid date"}, "data": []}}"#
+        )
+        .unwrap_err()
+        .to_string()
+        .contains("Invalid date"));
+    }
+}
@@ -0,0 +1,92 @@
+//! Text recognition through the `tesseract` command.
+//!
+//! The image goes in as PNG bytes rather than a `Pixbuf`, so recognition can
+//! run on a worker thread.
+
+use gtk4::glib;
+use log::debug;
+use std::process::{Command, Stdio};
+
+use super::process::output_with_input;
+use super::target::ExportError;
+
+/// Whether `tesseract` is installed
+pub fn tesseract_available() -> bool {
+    glib::find_program_in_path("tesseract").is_some()
+}
+
+/// Run tesseract on `png` and return what it writes for the output config
+/// `format`, such as `"txt"` or `"pdf"`
+pub fn run_tesseract(png: &[u8], format: &str) -> Result<Vec<u8>, ExportError> {
+    debug!("Running tesseract for {}", format);
+
+    // Reads the image from stdin and writes the result to stdout
+    let child = Command::new("tesseract")
+        .args(["-", "-", format])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ExportError::Failed(format!("Failed to run tesseract: {}", e)))?;
+
+    let output = output_with_input(child, png)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ExportError::Failed(format!(
+            "tesseract exited with {}: {}",
+            output.status,
+            stderr.trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+/// Text recognized in `png`, empty when there is none
+pub fn recognize_text(png: &[u8]) -> Result<String, ExportError> {
+    let text = run_tesseract(png, "txt")?;
+    Ok(String::from_utf8_lossy(&text).trim().to_string())
+}
+
+/// Web addresses in recognized `text`, in order and without repeats.
+/// Addresses starting with `www.` get an `https://` scheme.
+pub fn find_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        // Brackets, quotes and sentence punctuation around the address
+        let word = word.trim_start_matches(['(', '[', '<', '"', '\'']);
+        let word = word.trim_end_matches([')', ']', '>', '"', '\'', '.', ',', ';', ':', '!', '?']);
+
+        let url = if word.starts_with("http://") || word.starts_with("https://") {
+            word.to_string()
+        } else if word.starts_with("www.") {
+            format!("https://{}", word)
+        } else {
+            continue;
+        };
+        // A bare scheme is not an address
+        if !url.ends_with("://") && !urls.contains(&url) {
+            urls.push(url);
+        }
+    }
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_urls() {
+        let text = "See https://example.com/docs?page=2, or (www.example.org).\n\
+                    Mirror: <http://mirror.example.net/> and https://example.com/docs?page=2\n\
+                    Not links: https:// www. example.com mailto:me@example.com";
+        assert_eq!(
+            find_urls(text),
+            vec![
+                "https://example.com/docs?page=2",
+                "https://www.example.org",
+                "http://mirror.example.net/",
+            ]
+        );
+    }
+}
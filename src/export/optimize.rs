@@ -0,0 +1,374 @@
+use gtk4::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk4::glib;
+use log::debug;
+use std::collections::HashMap;
+
+/// Size comparison between the regular encoder output and the optimized PNG
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeReport {
+    pub original_bytes: usize,
+    pub optimized_bytes: usize,
+}
+
+impl OptimizeReport {
+    pub fn saved_bytes(&self) -> usize {
+        self.original_bytes.saturating_sub(self.optimized_bytes)
+    }
+
+    pub fn saved_percent(&self) -> f64 {
+        if self.original_bytes == 0 {
+            return 0.0;
+        }
+        self.saved_bytes() as f64 * 100.0 / self.original_bytes as f64
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "Optimized PNG: {} → {} (saved {:.0}%)",
+            format_size(self.original_bytes),
+            format_size(self.optimized_bytes),
+            self.saved_percent()
+        )
+    }
+}
+
+/// Raw RGBA pixels copied out of a pixbuf so they can be sent to a worker thread
+#[derive(Debug, Clone)]
+pub struct RgbaBuffer {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaBuffer {
+    pub fn from_pixbuf(pixbuf: &Pixbuf) -> Self {
+        let width = pixbuf.width().max(0) as usize;
+        let height = pixbuf.height().max(0) as usize;
+        let n_channels = pixbuf.n_channels() as usize;
+        let rowstride = pixbuf.rowstride() as usize;
+        let has_alpha = pixbuf.has_alpha() && n_channels >= 4;
+        let bytes = pixbuf.read_pixel_bytes();
+        let data: &[u8] = &bytes;
+
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            let row = &data[y * rowstride..];
+            for x in 0..width {
+                let offset = x * n_channels;
+                pixels.push(row[offset]);
+                pixels.push(row[offset + 1]);
+                pixels.push(row[offset + 2]);
+                pixels.push(if has_alpha { row[offset + 3] } else { 255 });
+            }
+        }
+
+        Self {
+            width: width as u32,
+            height: height as u32,
+            pixels,
+        }
+    }
+
+    pub fn to_pixbuf(&self) -> Pixbuf {
+        Pixbuf::from_bytes(
+            &glib::Bytes::from(&self.pixels),
+            Colorspace::Rgb,
+            true,
+            8,
+            self.width as i32,
+            self.height as i32,
+            self.width as i32 * 4,
+        )
+    }
+}
+
+/// Encode an image as it is, without looking for a smaller form
+pub fn encode_rgba(buffer: &RgbaBuffer) -> Result<Vec<u8>, String> {
+    encode(
+        buffer.width,
+        buffer.height,
+        png::ColorType::Rgba,
+        png::BitDepth::Eight,
+        &buffer.pixels,
+        png::Filter::Adaptive,
+        |_| {},
+    )
+}
+
+/// Optimize an image, comparing the result with the plain encoding. Both
+/// encodes are slow on large screenshots, so this belongs on a worker thread.
+pub fn optimize_with_report(buffer: &RgbaBuffer) -> Result<(Vec<u8>, OptimizeReport), String> {
+    let original_bytes = encode_rgba(buffer)?.len();
+    let data = optimize_png(buffer)?;
+    let report = OptimizeReport {
+        original_bytes,
+        optimized_bytes: data.len(),
+    };
+    Ok((data, report))
+}
+
+/// Losslessly re-encode an image, returning the smallest PNG we can produce.
+///
+/// Flat UI screenshots usually have few distinct colors, so a palette image is
+/// tried first; otherwise the alpha channel is dropped when fully opaque.
+pub fn optimize_png(buffer: &RgbaBuffer) -> Result<Vec<u8>, String> {
+    let mut best: Option<Vec<u8>> = None;
+
+    let mut keep_smallest = |candidate: Vec<u8>| {
+        if best.as_ref().is_none_or(|b| candidate.len() < b.len()) {
+            best = Some(candidate);
+        }
+    };
+
+    if let Some(indexed) = build_palette(&buffer.pixels) {
+        debug!(
+            "Trying palette encoding with {} colors",
+            indexed.palette.len()
+        );
+        for filter in [png::Filter::NoFilter, png::Filter::Adaptive] {
+            keep_smallest(encode_indexed(
+                buffer.width,
+                buffer.height,
+                &indexed,
+                filter,
+            )?);
+        }
+    }
+
+    let opaque = buffer.pixels.chunks_exact(4).all(|p| p[3] == 255);
+    let (color, data) = if opaque {
+        let rgb: Vec<u8> = buffer
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect();
+        (png::ColorType::Rgb, rgb)
+    } else {
+        (png::ColorType::Rgba, buffer.pixels.clone())
+    };
+
+    keep_smallest(encode(
+        buffer.width,
+        buffer.height,
+        color,
+        png::BitDepth::Eight,
+        &data,
+        png::Filter::Adaptive,
+        |_| {},
+    )?);
+
+    best.ok_or_else(|| "No PNG encoding produced output".to_string())
+}
+
+struct IndexedImage {
+    palette: Vec<[u8; 4]>,
+    indices: Vec<u8>,
+}
+
+fn build_palette(pixels: &[u8]) -> Option<IndexedImage> {
+    let mut lookup: HashMap<[u8; 4], u8> = HashMap::new();
+    let mut palette = Vec::new();
+    let mut indices = Vec::with_capacity(pixels.len() / 4);
+
+    for p in pixels.chunks_exact(4) {
+        let color = [p[0], p[1], p[2], p[3]];
+        let index = match lookup.get(&color) {
+            Some(&index) => index,
+            None => {
+                if palette.len() == 256 {
+                    return None;
+                }
+                let index = palette.len() as u8;
+                lookup.insert(color, index);
+                palette.push(color);
+                index
+            }
+        };
+        indices.push(index);
+    }
+
+    Some(IndexedImage { palette, indices })
+}
+
+fn encode_indexed(
+    width: u32,
+    height: u32,
+    image: &IndexedImage,
+    filter: png::Filter,
+) -> Result<Vec<u8>, String> {
+    let depth = match image.palette.len() {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    };
+
+    let rgb: Vec<u8> = image
+        .palette
+        .iter()
+        .flat_map(|c| [c[0], c[1], c[2]])
+        .collect();
+
+    // tRNS only needs to cover entries up to the last translucent one
+    let trns_len = image
+        .palette
+        .iter()
+        .rposition(|c| c[3] != 255)
+        .map_or(0, |i| i + 1);
+    let trns: Vec<u8> = image.palette[..trns_len].iter().map(|c| c[3]).collect();
+
+    let packed = pack_indices(&image.indices, width as usize, depth as u8 as usize);
+
+    encode(
+        width,
+        height,
+        png::ColorType::Indexed,
+        depth,
+        &packed,
+        filter,
+        |encoder| {
+            encoder.set_palette(rgb.clone());
+            if !trns.is_empty() {
+                encoder.set_trns(trns.clone());
+            }
+        },
+    )
+}
+
+fn pack_indices(indices: &[u8], width: usize, bits: usize) -> Vec<u8> {
+    if bits == 8 || width == 0 {
+        return indices.to_vec();
+    }
+
+    let per_byte = 8 / bits;
+    let row_bytes = width.div_ceil(per_byte);
+    let mut packed = Vec::with_capacity(row_bytes * (indices.len() / width));
+
+    for row in indices.chunks(width) {
+        for group in row.chunks(per_byte) {
+            let mut byte = 0u8;
+            for (i, &index) in group.iter().enumerate() {
+                byte |= index << (8 - bits * (i + 1));
+            }
+            packed.push(byte);
+        }
+    }
+
+    packed
+}
+
+fn encode(
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    data: &[u8],
+    filter: png::Filter,
+    configure: impl FnOnce(&mut png::Encoder<&mut Vec<u8>>),
+) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.set_compression(png::Compression::High);
+        encoder.set_filter(filter);
+        configure(&mut encoder);
+
+        let mut writer = encoder
+            .write_header()
+            .map_err(|e| format!("Failed to write PNG header: {}", e))?;
+        writer
+            .write_image_data(data)
+            .map_err(|e| format!("Failed to write PNG data: {}", e))?;
+    }
+    Ok(out)
+}
+
+pub fn format_size(bytes: usize) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(data: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(data));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::ALPHA);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        (info, buf)
+    }
+
+    #[test]
+    fn test_optimize_with_report() {
+        let pixels: Vec<u8> = (0..64 * 16)
+            .flat_map(|i| [0, 0, 0, (i % 2) as u8 * 255])
+            .collect();
+        let buffer = RgbaBuffer {
+            width: 64,
+            height: 16,
+            pixels: pixels.clone(),
+        };
+
+        let (data, report) = optimize_with_report(&buffer).unwrap();
+        assert_eq!(report.original_bytes, encode_rgba(&buffer).unwrap().len());
+        assert_eq!(report.optimized_bytes, data.len());
+        assert!(report.optimized_bytes <= report.original_bytes);
+        assert_eq!(decode(&data).1, pixels);
+    }
+
+    #[test]
+    fn test_flat_image_uses_palette_losslessly() {
+        let mut pixels = Vec::new();
+        for i in 0..(33 * 7) {
+            let color: [u8; 4] = match i % 3 {
+                0 => [255, 255, 255, 255],
+                1 => [30, 30, 30, 255],
+                _ => [0, 120, 215, 128],
+            };
+            pixels.extend_from_slice(&color);
+        }
+        let buffer = RgbaBuffer {
+            width: 33,
+            height: 7,
+            pixels: pixels.clone(),
+        };
+
+        let encoded = optimize_png(&buffer).unwrap();
+        let (info, decoded) = decode(&encoded);
+        assert_eq!((info.width, info.height), (33, 7));
+        assert_eq!(decoded, pixels);
+    }
+
+    #[test]
+    fn test_many_colors_roundtrip() {
+        let pixels: Vec<u8> = (0..(40 * 40))
+            .flat_map(|i: u32| {
+                [
+                    (i % 251) as u8,
+                    (i / 7 % 256) as u8,
+                    (i * 3 % 256) as u8,
+                    255,
+                ]
+            })
+            .collect();
+        let buffer = RgbaBuffer {
+            width: 40,
+            height: 40,
+            pixels: pixels.clone(),
+        };
+
+        let (_, decoded) = decode(&optimize_png(&buffer).unwrap());
+        assert_eq!(decoded, pixels);
+    }
+}
@@ -0,0 +1,34 @@
+//! Helper programs fed through stdin, such as `tesseract`, `curl` and user
+//! export scripts.
+
+use log::debug;
+use std::io::Write;
+use std::process::{Child, Output};
+use std::thread;
+
+use super::target::ExportError;
+
+/// Wait for `child` to finish, writing `input` to its stdin from another
+/// thread meanwhile. A program that prints before it has read everything
+/// would otherwise fill its output pipe and stall while its input is still
+/// being written.
+pub fn output_with_input(mut child: Child, input: &[u8]) -> Result<Output, ExportError> {
+    thread::scope(|scope| {
+        let writer = child
+            .stdin
+            .take()
+            .map(|mut stdin| scope.spawn(move || stdin.write_all(input)));
+        let output = child
+            .wait_with_output()
+            .map_err(|e| ExportError::Io(e.to_string()))?;
+
+        if let Some(writer) = writer {
+            // A program may exit without reading all of it, which its exit
+            // status tells better than the broken pipe
+            if let Ok(Err(e)) = writer.join() {
+                debug!("Program stopped reading its input: {}", e);
+            }
+        }
+        Ok(output)
+    })
+}
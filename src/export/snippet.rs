@@ -0,0 +1,129 @@
+//! Markdown and HTML snippets embedding an exported image, for pasting into
+//! issue trackers, wikis and chat.
+
+use serde::{Deserialize, Serialize};
+
+/// Longest alt text taken from recognized text, in characters
+const MAX_ALT_TEXT_LENGTH: usize = 120;
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnippetFormat {
+    /// `![alt](url)`
+    #[default]
+    Markdown,
+
+    /// `<img src="url" alt="alt">`
+    Html,
+}
+
+impl SnippetFormat {
+    pub const ALL: [SnippetFormat; 2] = [Self::Markdown, Self::Html];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Markdown => "Markdown",
+            Self::Html => "HTML",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|format| format.id() == id)
+    }
+}
+
+/// A snippet showing the image at `url`, described by `alt`
+pub fn image_snippet(format: SnippetFormat, url: &str, alt: &str) -> String {
+    match format {
+        SnippetFormat::Markdown => {
+            let alt = alt
+                .replace('\\', "\\\\")
+                .replace('[', "\\[")
+                .replace(']', "\\]");
+            // Spaces and parentheses would end the link early
+            let url = url
+                .replace(' ', "%20")
+                .replace('(', "%28")
+                .replace(')', "%29");
+            format!("![{}]({})", alt, url)
+        }
+        SnippetFormat::Html => format!(
+            "<img src=\"{}\" alt=\"{}\">",
+            escape_html(url),
+            escape_html(alt)
+        ),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Alt text from the text recognized in an image: its words on one line,
+/// cut at a word boundary if long
+pub fn alt_text_from_ocr(text: &str) -> String {
+    let mut alt = String::new();
+    for word in text.split_whitespace() {
+        let length = alt.chars().count() + word.chars().count() + 1;
+        if length > MAX_ALT_TEXT_LENGTH {
+            if alt.is_empty() {
+                alt = word.chars().take(MAX_ALT_TEXT_LENGTH).collect();
+            }
+            alt.push('…');
+            break;
+        }
+        if !alt.is_empty() {
+            alt.push(' ');
+        }
+        alt.push_str(word);
+    }
+    alt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_snippet() {
+        assert_eq!(
+            image_snippet(
+                SnippetFormat::Markdown,
+                "file:///home/me/Shots/a (1).png",
+                "Error [500]"
+            ),
+            "![Error \\[500\\]](file:///home/me/Shots/a%20%281%29.png)"
+        );
+        assert_eq!(
+            image_snippet(
+                SnippetFormat::Html,
+                "https://example.com/i.png?a=1&b=2",
+                "Say \"hi\" <now>"
+            ),
+            "<img src=\"https://example.com/i.png?a=1&amp;b=2\" \
+             alt=\"Say &quot;hi&quot; &lt;now&gt;\">"
+        );
+    }
+
+    #[test]
+    fn test_alt_text_from_ocr() {
+        assert_eq!(
+            alt_text_from_ocr("  Settings\n\nDark   mode\n"),
+            "Settings Dark mode"
+        );
+        let long = "word ".repeat(40);
+        let alt = alt_text_from_ocr(&long);
+        assert!(alt.ends_with("word…"));
+        assert!(alt.chars().count() <= MAX_ALT_TEXT_LENGTH + 1);
+    }
+}
@@ -0,0 +1,258 @@
+use gtk4::gdk_pixbuf::Pixbuf;
+use log::{debug, info, warn};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::optimize::{encode_rgba, optimize_with_report, RgbaBuffer};
+use super::upload::UploadJob;
+use crate::capture::metadata::CaptureMetadata;
+
+/// Information about the image being exported, shared by all targets
+#[derive(Debug, Clone)]
+pub struct ExportMetadata {
+    pub file_name: String,
+
+    pub timestamp: u64,
+
+    pub width: i32,
+
+    pub height: i32,
+
+    /// How the image was captured, when known
+    pub capture: Option<CaptureMetadata>,
+
+    /// Run the lossless PNG optimization before sending the image
+    pub optimize: bool,
+}
+
+impl ExportMetadata {
+    pub fn for_pixbuf(pixbuf: &Pixbuf) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        Self {
+            file_name: format!("screenshot_{}.png", timestamp),
+            timestamp,
+            width: pixbuf.width(),
+            height: pixbuf.height(),
+            capture: None,
+            optimize: false,
+        }
+    }
+
+    pub fn with_optimize(mut self, optimize: bool) -> Self {
+        self.optimize = optimize;
+        self
+    }
+
+    /// Name the file from `template` and attach the capture details
+    pub fn with_capture(mut self, capture: &CaptureMetadata, template: &str) -> Self {
+        self.file_name = format!(
+            "{}.png",
+            capture.expand_filename_template(template, self.width, self.height)
+        );
+        self.timestamp = capture.timestamp;
+        self.capture = Some(capture.clone());
+        self
+    }
+}
+
+/// An image copied out of its pixbuf, for a worker thread to encode as PNG
+pub struct PendingPng {
+    buffer: RgbaBuffer,
+    optimize: bool,
+}
+
+impl PendingPng {
+    pub fn new(pixbuf: &Pixbuf, metadata: &ExportMetadata) -> Self {
+        Self {
+            buffer: RgbaBuffer::from_pixbuf(pixbuf),
+            optimize: metadata.optimize,
+        }
+    }
+
+    /// The PNG, optimized when the export asked for it
+    pub fn encode(&self) -> Result<Vec<u8>, ExportError> {
+        if !self.optimize {
+            return encode_rgba(&self.buffer).map_err(ExportError::Encoding);
+        }
+        let (png, report) = optimize_with_report(&self.buffer).map_err(ExportError::Encoding)?;
+        info!("{}", report.summary());
+        Ok(png)
+    }
+}
+
+/// What a successful export produced, shown to the user afterwards
+#[derive(Debug, Clone)]
+pub struct ExportOutcome {
+    pub summary: String,
+
+    pub url: Option<String>,
+
+    /// The URL is put on the clipboard as soon as the export finishes
+    pub copy_url: bool,
+}
+
+impl ExportOutcome {
+    pub fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            url: None,
+            copy_url: false,
+        }
+    }
+
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn with_copied_url(mut self, url: impl Into<String>) -> Self {
+        self.copy_url = true;
+        self.with_url(url)
+    }
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Encoding(String),
+
+    Io(String),
+
+    Failed(String),
+
+    /// The user stopped an upload
+    Cancelled,
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Encoding(msg) => write!(f, "Failed to encode image: {}", msg),
+            Self::Io(msg) => write!(f, "I/O error: {}", msg),
+            Self::Failed(msg) => write!(f, "Export failed: {}", msg),
+            Self::Cancelled => write!(f, "Export cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+/// An export with the image already encoded, run on a worker thread so that
+/// slow targets, such as user scripts and OCR, leave the window responsive
+pub type ExportJob = Box<dyn FnOnce() -> Result<ExportOutcome, ExportError> + Send>;
+
+/// A destination the edited image can be sent to.
+///
+/// Targets are registered once at startup and listed in the toolbar's export
+/// menu, so uploads, OCR and user scripts all go through the same dispatch.
+pub trait ExportTarget {
+    /// Stable identifier used as the action target, e.g. `"pictures-folder"`
+    fn id(&self) -> &str;
+
+    /// Human readable name shown in the menu
+    fn name(&self) -> &str;
+
+    /// Symbolic icon name shown next to the menu entry
+    fn icon_name(&self) -> &str {
+        "document-send-symbolic"
+    }
+
+    /// Encode the image, giving the job that exports it
+    fn export_job(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError>;
+
+    /// Export on the spot, blocking until done
+    fn export(
+        &self,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportOutcome, ExportError> {
+        self.export_job(pixbuf, metadata)?()
+    }
+
+    /// For targets that send the image over the network, the sending as a
+    /// job for a worker thread, with the image already encoded. `None` for
+    /// targets exported on the spot.
+    fn upload_job(
+        &self,
+        _pixbuf: &Pixbuf,
+        _metadata: &ExportMetadata,
+    ) -> Option<Result<UploadJob, ExportError>> {
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct ExportRegistry {
+    targets: Vec<Box<dyn ExportTarget>>,
+}
+
+impl ExportRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a target; a later registration with the same id replaces the earlier one
+    pub fn register(&mut self, target: Box<dyn ExportTarget>) {
+        debug!("Registering export target '{}'", target.id());
+        if let Some(existing) = self.targets.iter_mut().find(|t| t.id() == target.id()) {
+            warn!("Export target '{}' registered twice", target.id());
+            *existing = target;
+        } else {
+            self.targets.push(target);
+        }
+    }
+
+    /// Remove the target with `id`, if one is registered
+    pub fn unregister(&mut self, id: &str) {
+        self.targets.retain(|t| t.id() != id);
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn ExportTarget> {
+        self.targets
+            .iter()
+            .find(|t| t.id() == id)
+            .map(|t| t.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn ExportTarget> {
+        self.targets.iter().map(|t| t.as_ref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// The export job of target `id`, for a worker thread
+    pub fn export_job(
+        &self,
+        id: &str,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Result<ExportJob, ExportError> {
+        let target = self
+            .get(id)
+            .ok_or_else(|| ExportError::Failed(format!("Unknown export target '{}'", id)))?;
+        debug!("Exporting with target '{}'", target.id());
+        target.export_job(pixbuf, metadata)
+    }
+
+    /// The upload job of target `id`, if it sends the image over the network
+    pub fn upload_job(
+        &self,
+        id: &str,
+        pixbuf: &Pixbuf,
+        metadata: &ExportMetadata,
+    ) -> Option<Result<UploadJob, ExportError>> {
+        self.get(id)?.upload_job(pixbuf, metadata)
+    }
+}
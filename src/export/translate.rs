@@ -0,0 +1,218 @@
+//! Translating recognized text, through a command such as translate-shell or
+//! a LibreTranslate compatible endpoint.
+
+use gtk4::glib;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Placeholder in a translation command replaced by the target language
+pub const LANGUAGE_PLACEHOLDER: &str = "{lang}";
+
+/// Where recognized text is sent to be translated
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase", tag = "kind")]
+pub enum TranslationBackend {
+    /// Program reading the text on stdin and printing the translation, e.g.
+    /// `trans -brief -t {lang}`
+    Command { command: String },
+
+    /// LibreTranslate compatible API, local or hosted, called with `curl`
+    Endpoint {
+        url: String,
+        api_key: Option<String>,
+    },
+}
+
+impl TranslationBackend {
+    /// An endpoint for an http(s) URL and a command otherwise; `None` when
+    /// `input` is blank
+    pub fn parse(input: &str, api_key: Option<String>) -> Option<Self> {
+        let input = input.trim();
+        if input.is_empty() {
+            None
+        } else if input.starts_with("http://") || input.starts_with("https://") {
+            Some(Self::Endpoint {
+                url: input.to_string(),
+                api_key: api_key.filter(|key| !key.trim().is_empty()),
+            })
+        } else {
+            Some(Self::Command {
+                command: input.to_string(),
+            })
+        }
+    }
+
+    /// The URL or command, as `parse` takes it
+    pub fn input(&self) -> &str {
+        match self {
+            Self::Command { command } => command,
+            Self::Endpoint { url, .. } => url,
+        }
+    }
+
+    pub fn api_key(&self) -> Option<&str> {
+        match self {
+            Self::Endpoint { api_key, .. } => api_key.as_deref(),
+            Self::Command { .. } => None,
+        }
+    }
+
+    /// Translate `text` into `language`, an ISO 639 code such as `"en"`.
+    /// Blocks until the backend answers.
+    pub fn translate(&self, text: &str, language: &str) -> Result<String, String> {
+        match self {
+            Self::Command { command } => translate_with_command(command, text, language),
+            Self::Endpoint { url, api_key } => {
+                translate_with_endpoint(url, api_key.as_deref(), text, language)
+            }
+        }
+    }
+}
+
+/// Backend and target language chosen for the Translate action
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranslationSettings {
+    pub backend: TranslationBackend,
+
+    pub target_language: String,
+}
+
+/// Arguments of `command` with the language placeholder filled in
+fn command_args(command: &str, language: &str) -> Result<Vec<String>, String> {
+    let argv = glib::shell_parse_argv(command).map_err(|e| e.to_string())?;
+    Ok(argv
+        .iter()
+        .map(|arg| {
+            arg.to_string_lossy()
+                .replace(LANGUAGE_PLACEHOLDER, language)
+        })
+        .collect())
+}
+
+fn run_with_stdin(args: &[String], stdin: &[u8]) -> Result<Vec<u8>, String> {
+    let (program, args) = match args.split_first() {
+        Some(split) => split,
+        None => return Err("No translation command set".to_string()),
+    };
+    debug!("Running {:?} to translate", program);
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    if let Some(mut pipe) = child.stdin.take() {
+        pipe.write_all(stdin).map_err(|e| e.to_string())?;
+    }
+
+    let output = child.wait_with_output().map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(output.stdout)
+}
+
+fn translate_with_command(command: &str, text: &str, language: &str) -> Result<String, String> {
+    let output = run_with_stdin(&command_args(command, language)?, text.as_bytes())?;
+    Ok(String::from_utf8_lossy(&output).trim().to_string())
+}
+
+fn translate_with_endpoint(
+    url: &str,
+    api_key: Option<&str>,
+    text: &str,
+    language: &str,
+) -> Result<String, String> {
+    let mut request = serde_json::json!({
+        "q": text,
+        "source": "auto",
+        "target": language,
+        "format": "text",
+    });
+    if let Some(api_key) = api_key {
+        request["api_key"] = api_key.into();
+    }
+
+    let args: Vec<String> = [
+        "curl",
+        "--silent",
+        "--show-error",
+        "--request",
+        "POST",
+        "--header",
+        "Content-Type: application/json",
+        "--data-binary",
+        "@-",
+        url,
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    let body = run_with_stdin(&args, request.to_string().as_bytes())?;
+    parse_endpoint_response(&String::from_utf8_lossy(&body))
+}
+
+/// The translation in a LibreTranslate response, or the error it reports
+fn parse_endpoint_response(body: &str) -> Result<String, String> {
+    let response: serde_json::Value =
+        serde_json::from_str(body).map_err(|e| format!("Unexpected response: {}", e))?;
+
+    if let Some(text) = response["translatedText"].as_str() {
+        Ok(text.trim().to_string())
+    } else if let Some(error) = response["error"].as_str() {
+        Err(error.to_string())
+    } else {
+        Err("Unexpected response: no translatedText".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend() {
+        assert_eq!(TranslationBackend::parse("  ", None), None);
+        assert_eq!(
+            TranslationBackend::parse("trans -b -t {lang}", Some("key".to_string())),
+            Some(TranslationBackend::Command {
+                command: "trans -b -t {lang}".to_string()
+            })
+        );
+        assert_eq!(
+            TranslationBackend::parse("http://localhost:5000/translate", Some(" ".to_string())),
+            Some(TranslationBackend::Endpoint {
+                url: "http://localhost:5000/translate".to_string(),
+                api_key: None
+            })
+        );
+        assert_eq!(
+            command_args("trans -b -t '{lang}'", "de").unwrap(),
+            vec!["trans", "-b", "-t", "de"]
+        );
+    }
+
+    #[test]
+    fn test_parse_endpoint_response() {
+        assert_eq!(
+            parse_endpoint_response(r#"{"translatedText": "File not found\n"}"#),
+            Ok("File not found".to_string())
+        );
+        assert_eq!(
+            parse_endpoint_response(r#"{"error": "Invalid API key"}"#),
+            Err("Invalid API key".to_string())
+        );
+        assert!(parse_endpoint_response("<html>").is_err());
+    }
+}
@@ -0,0 +1,507 @@
+//! Sending exports over the network from a worker thread, so the window
+//! stays responsive while they go out.
+//!
+//! Each request is a `curl` run whose progress meter is read to report how
+//! much of the body has been sent. A run can be cancelled by killing it, and
+//! one that fails for a reason that may pass, such as a dropped connection or
+//! a busy server, is made again after a growing wait. Requests that could act
+//! twice, such as posting a message, are only made again when they never
+//! reached the server.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::target::{ExportError, ExportOutcome};
+
+/// How often a running request checks whether it was cancelled
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Wait before the first retry, doubled for each one after it
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// curl exit codes for failures worth trying again: the name or host not
+/// reachable, a timeout, or the connection dropped midway
+const TRANSIENT_CURL_ERRORS: [i32; 8] = [5, 6, 7, 28, 35, 52, 55, 56];
+
+/// HTTP statuses for a server that may answer differently a little later
+const TRANSIENT_STATUSES: [u32; 6] = [408, 429, 500, 502, 503, 504];
+
+/// curl exit codes for requests that never reached the server: the name
+/// not resolved, or no connection or TLS session set up
+const UNSENT_CURL_ERRORS: [i32; 4] = [5, 6, 7, 35];
+
+/// HTTP statuses for requests the server turned away without acting on them
+const UNSENT_STATUSES: [u32; 1] = [429];
+
+/// When a failed request may be made again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Retry {
+    /// Making it twice does what making it once does, like a PUT of the same
+    /// file, so it is made again after any failure that may pass
+    Always,
+
+    /// Making it twice could act twice, like posting a message or committing
+    /// a file, so it is only made again when it never reached the server
+    BeforeSent,
+}
+
+impl Retry {
+    fn curl_errors(&self) -> &'static [i32] {
+        match self {
+            Self::Always => &TRANSIENT_CURL_ERRORS,
+            Self::BeforeSent => &UNSENT_CURL_ERRORS,
+        }
+    }
+
+    fn statuses(&self) -> &'static [u32] {
+        match self {
+            Self::Always => &TRANSIENT_STATUSES,
+            Self::BeforeSent => &UNSENT_STATUSES,
+        }
+    }
+}
+
+/// Speed limit and retries for uploads
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadOptions {
+    /// Fastest the body is sent, in KiB/s; 0 for no limit
+    pub rate_limit_kib: u32,
+
+    /// Times a request is made before its failure is reported
+    pub max_attempts: u32,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        Self {
+            rate_limit_kib: 0,
+            max_attempts: 4,
+        }
+    }
+}
+
+impl UploadOptions {
+    pub const MAX_ATTEMPTS: u32 = 10;
+
+    /// Highest speed limit offered, 100 MiB/s
+    pub const MAX_RATE_LIMIT_KIB: u32 = 100 * 1024;
+}
+
+/// An upload under way, shared between the thread sending it and the UI
+/// showing its progress
+#[derive(Clone, Default)]
+pub struct Transfer {
+    inner: Arc<TransferState>,
+}
+
+#[derive(Default)]
+struct TransferState {
+    options: UploadOptions,
+    sent: AtomicU64,
+    total: AtomicU64,
+    cancelled: AtomicBool,
+}
+
+impl Transfer {
+    pub fn new(options: UploadOptions) -> Self {
+        Self {
+            inner: Arc::new(TransferState {
+                options,
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn options(&self) -> UploadOptions {
+        self.inner.options
+    }
+
+    /// Bytes the whole upload sends, over all its requests
+    pub fn set_total(&self, bytes: u64) {
+        self.inner.total.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Count `bytes` sent by an earlier attempt, such as the parts of a
+    /// resumed upload already on the server
+    pub fn skip(&self, bytes: u64) {
+        self.inner.sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Share of the upload sent so far, once its size is known
+    pub fn fraction(&self) -> Option<f64> {
+        let total = self.inner.total.load(Ordering::Relaxed);
+        if total == 0 {
+            return None;
+        }
+        let sent = self.inner.sent.load(Ordering::Relaxed).min(total);
+        Some(sent as f64 / total as f64)
+    }
+
+    /// Stop the request running and any still to come
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn sent(&self) -> u64 {
+        self.inner.sent.load(Ordering::Relaxed)
+    }
+
+    fn set_sent(&self, bytes: u64) {
+        self.inner.sent.store(bytes, Ordering::Relaxed);
+    }
+}
+
+/// The sending part of an export, run on a worker thread once the image has
+/// been encoded
+pub type UploadJob = Box<dyn FnOnce(&Transfer) -> Result<ExportOutcome, ExportError> + Send>;
+
+/// What the server answered
+#[derive(Debug)]
+pub struct Response {
+    pub status: u32,
+
+    pub body: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+enum Failure {
+    /// Worth trying again
+    Transient(String),
+
+    Fatal(ExportError),
+}
+
+/// Run `curl` with `args`, sending `body`, retrying with backoff while it
+/// fails for a reason that may pass and `retry` allows it. The last response
+/// is returned once the attempts run out, so error statuses can be explained
+/// by the caller.
+pub fn send(
+    args: &[&str],
+    body: &[u8],
+    retry: Retry,
+    transfer: &Transfer,
+) -> Result<Response, ExportError> {
+    let max_attempts = transfer.options().max_attempts.max(1);
+    // Where this request's progress starts, when it is one of several
+    let start = transfer.sent();
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        transfer.set_sent(start);
+        let reason = match send_once(args, body, retry, transfer, start) {
+            Ok(response)
+                if attempt < max_attempts && retry.statuses().contains(&response.status) =>
+            {
+                format!("HTTP status {}", response.status)
+            }
+            Ok(response) => {
+                transfer.set_sent(start + body.len() as u64);
+                return Ok(response);
+            }
+            Err(Failure::Transient(reason)) if attempt < max_attempts => reason,
+            Err(Failure::Transient(reason)) => return Err(ExportError::Failed(reason)),
+            Err(Failure::Fatal(e)) => return Err(e),
+        };
+
+        warn!(
+            "Upload attempt {} of {} failed, trying again in {:?}: {}",
+            attempt, max_attempts, delay, reason
+        );
+        wait(delay, transfer)?;
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// Sleep for `delay`, or until the transfer is cancelled
+fn wait(delay: Duration, transfer: &Transfer) -> Result<(), ExportError> {
+    let mut waited = Duration::ZERO;
+    while waited < delay {
+        if transfer.is_cancelled() {
+            return Err(ExportError::Cancelled);
+        }
+        thread::sleep(POLL_INTERVAL);
+        waited += POLL_INTERVAL;
+    }
+    Ok(())
+}
+
+fn send_once(
+    args: &[&str],
+    body: &[u8],
+    retry: Retry,
+    transfer: &Transfer,
+    start: u64,
+) -> Result<Response, Failure> {
+    if transfer.is_cancelled() {
+        return Err(Failure::Fatal(ExportError::Cancelled));
+    }
+
+    let options = transfer.options();
+    let rate_limit = format!("{}K", options.rate_limit_kib);
+    let mut command = Command::new("curl");
+    // The progress meter is left on, written to stderr as the body goes out
+    command.args(["--show-error", "--write-out", "\n%{http_code}"]);
+    if options.rate_limit_kib > 0 {
+        command.args(["--limit-rate", &rate_limit]);
+    }
+    let mut child = command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Failure::Fatal(ExportError::Failed(format!("Failed to run curl: {}", e))))?;
+
+    // Every pipe has its own thread, so none fills up while another is
+    // waited on
+    let writer = child.stdin.take().map(|mut pipe| {
+        let body = body.to_vec();
+        thread::spawn(move || pipe.write_all(&body))
+    });
+    let reader = child.stdout.take().map(|mut pipe| {
+        thread::spawn(move || {
+            let mut output = String::new();
+            pipe.read_to_string(&mut output).map(|_| output)
+        })
+    });
+    let meter = child.stderr.take().map(|pipe| {
+        let transfer = transfer.clone();
+        let size = body.len() as u64;
+        thread::spawn(move || read_meter(pipe, &transfer, start, size))
+    });
+
+    let status = wait_for_curl(&mut child, transfer)?;
+    if let Some(writer) = writer {
+        // A server refusing the request may close it before the body is sent
+        if let Ok(Err(e)) = writer.join() {
+            debug!("curl stopped reading the body: {}", e);
+        }
+    }
+    let errors = meter
+        .and_then(|meter| meter.join().ok())
+        .unwrap_or_default();
+    let output = match reader.map(|reader| reader.join()) {
+        Some(Ok(Ok(output))) => output,
+        _ => {
+            return Err(Failure::Fatal(ExportError::Io(
+                "Failed to read the response".to_string(),
+            )))
+        }
+    };
+
+    match status.code() {
+        Some(0) => parse_output(&output).map_err(Failure::Fatal),
+        Some(code) => {
+            let reason = format!("curl exited with {}: {}", status, errors.trim());
+            if retry.curl_errors().contains(&code) {
+                Err(Failure::Transient(reason))
+            } else {
+                Err(Failure::Fatal(ExportError::Failed(reason)))
+            }
+        }
+        None => Err(Failure::Fatal(ExportError::Failed(format!(
+            "curl was stopped: {}",
+            status
+        )))),
+    }
+}
+
+/// Wait for curl to exit, killing it if the transfer is cancelled first
+fn wait_for_curl(
+    child: &mut Child,
+    transfer: &Transfer,
+) -> Result<std::process::ExitStatus, Failure> {
+    let io_error = |e: std::io::Error| Failure::Fatal(ExportError::Io(e.to_string()));
+    loop {
+        if let Some(status) = child.try_wait().map_err(io_error)? {
+            return Ok(status);
+        }
+        if transfer.is_cancelled() {
+            debug!("Cancelling the upload");
+            if let Err(e) = child.kill() {
+                warn!("Failed to stop curl: {}", e);
+            }
+            child.wait().map_err(io_error)?;
+            return Err(Failure::Fatal(ExportError::Cancelled));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Follow curl's progress meter, adding the part of the `size` byte body sent
+/// to the transfer's `start`. Lines that aren't the meter, such as error
+/// messages, are returned.
+fn read_meter(pipe: impl Read, transfer: &Transfer, start: u64, size: u64) -> String {
+    let mut errors = String::new();
+    let mut reader = BufReader::new(pipe);
+    let mut line = Vec::new();
+    // The meter rewrites its line with a carriage return
+    while let Ok(read) = reader.read_until(b'\r', &mut line) {
+        if read == 0 {
+            break;
+        }
+        for text in String::from_utf8_lossy(&line).lines() {
+            match meter_percent(text) {
+                Some(percent) => transfer.set_sent(start + size * percent / 100),
+                None if text.starts_with("curl:") => {
+                    errors.push_str(text);
+                    errors.push('\n');
+                }
+                None => {}
+            }
+        }
+        line.clear();
+    }
+    errors
+}
+
+/// Percentage of the request body sent, in a line of curl's progress meter:
+/// `% Total, Total, % Received, Received, % Xferd, Xferd` and then speeds
+/// and times
+fn meter_percent(line: &str) -> Option<u64> {
+    let columns: Vec<&str> = line.split_whitespace().collect();
+    if columns.len() < 12 {
+        return None;
+    }
+    columns[4].parse().ok().filter(|percent| *percent <= 100)
+}
+
+/// Split the body from the status `--write-out` put after it
+fn parse_output(output: &str) -> Result<Response, ExportError> {
+    let (body, status) = output.rsplit_once('\n').unwrap_or(("", output));
+    let status = status
+        .trim()
+        .parse()
+        .map_err(|_| ExportError::Failed(format!("Unexpected HTTP status {:?}", status)))?;
+    Ok(Response {
+        status,
+        body: body.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Answer one request with an empty 200 once its whole body is in
+    fn serve_once(listener: TcpListener) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            let read = stream.read(&mut chunk).unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&chunk[..read]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some(end) = text.find("\r\n\r\n") {
+                let length = text[..end]
+                    .lines()
+                    .find_map(|line| {
+                        let line = line.to_ascii_lowercase();
+                        line.strip_prefix("content-length:")
+                            .and_then(|value| value.trim().parse::<usize>().ok())
+                    })
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break;
+                }
+            }
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_meter_percent() {
+        assert_eq!(
+            meter_percent(
+                " 45 1024k    0     0   45  460k      0   230k  0:00:04  0:00:02  0:00:02  230k"
+            ),
+            Some(45)
+        );
+        assert_eq!(
+            meter_percent(
+                "  % Total    % Received % Xferd  Average Speed   Time    Time     Time  Current"
+            ),
+            None
+        );
+        assert_eq!(meter_percent("curl: (7) Failed to connect"), None);
+    }
+
+    /// The meter is read from curl's human readable output, which it doesn't
+    /// promise to keep, so a real run pins the layout `meter_percent` expects
+    #[test]
+    fn test_meter_of_real_curl() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve_once(listener));
+
+        let body = vec![0u8; 300 * 1024];
+        let spawned = Command::new("curl")
+            .args(["--show-error", "--limit-rate", "200K"])
+            .args(["--data-binary", "@-", &url])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            // Nothing to pin without curl
+            Err(_) => return,
+        };
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = thread::spawn(move || stdin.write_all(&body));
+
+        let transfer = Transfer::default();
+        transfer.set_total(300 * 1024);
+        let errors = read_meter(child.stderr.take().unwrap(), &transfer, 0, 300 * 1024);
+        assert!(child.wait().unwrap().success(), "{}", errors);
+        writer.join().unwrap().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(errors, "");
+        assert_eq!(transfer.fraction(), Some(1.0));
+    }
+
+    #[test]
+    fn test_parse_output() {
+        let response = parse_output("{\"ok\": true}\n201").unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.body, "{\"ok\": true}");
+        assert!(response.is_success());
+        assert_eq!(parse_output("\n404").unwrap().status, 404);
+        assert!(parse_output("<html>").is_err());
+    }
+
+    #[test]
+    fn test_fraction() {
+        let transfer = Transfer::default();
+        assert_eq!(transfer.fraction(), None);
+        transfer.set_total(200);
+        transfer.set_sent(50);
+        assert_eq!(transfer.fraction(), Some(0.25));
+        transfer.set_sent(300);
+        assert_eq!(transfer.fraction(), Some(1.0));
+    }
+}
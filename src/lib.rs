@@ -0,0 +1,10 @@
+//! Core of the screenshot tool, usable without the GUI.
+//!
+//! Contains the capture backends, the annotation model and the export
+//! pipeline. Nothing here needs a running GTK main loop: images are plain
+//! `gdk_pixbuf::Pixbuf` values and annotations render onto any cairo context,
+//! so the `--pipe` CLI and other Rust programs can use it directly.
+
+pub mod capture;
+pub mod editor;
+pub mod export;
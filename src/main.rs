@@ -1,37 +1,39 @@
 use libadwaita as adw;
 
 use adw::prelude::*;
+use gtk4::{gio, glib};
+use log::info;
+use std::cell::RefCell;
+use std::rc::Rc;
 
-use crate::app::CaptureMode;
+use crate::app::logging::LogOptions;
+use crate::app::{CaptureMode, InvocationSource};
+use screenshot_core::{capture, editor, export};
 
 mod app;
-mod capture;
-mod editor;
+mod cli;
 mod ui;
 
 const APP_ID: &str = "org.example.ScreenshotGnome";
 
 fn main() {
-    env_logger::init();
-    
-    let bytes = gtk4::glib::Bytes::from_static(include_bytes!("resources.gresource"));
-    let resource = gtk4::gio::Resource::from_data(&bytes).unwrap();
-    gtk4::gio::resources_register(&resource);
-
     let args: Vec<String> = std::env::args().collect();
+    app::logging::init(LogOptions::from_args(&args));
 
-    let start_mode =
-        if args.contains(&"--selection".to_string()) || args.contains(&"-s".to_string()) {
-            Some(CaptureMode::Selection)
-        } else if args.contains(&"--screen".to_string()) {
-            Some(CaptureMode::Screen)
-        } else if args.contains(&"--window".to_string()) || args.contains(&"-w".to_string()) {
-            Some(CaptureMode::Window)
-        } else {
-            None
-        };
+    if args.iter().any(|a| a == "--pipe") {
+        std::process::exit(cli::run_pipe(&args));
+    }
+
+    let bytes = glib::Bytes::from_static(include_bytes!("resources.gresource"));
+    let resource = gio::Resource::from_data(&bytes).unwrap();
+    gio::resources_register(&resource);
 
-    let app = adw::Application::builder().application_id(APP_ID).build();
+    // Later launches forward their command line to the running instance,
+    // which raises its window instead of opening another
+    let app = adw::Application::builder()
+        .application_id(APP_ID)
+        .flags(gio::ApplicationFlags::HANDLES_COMMAND_LINE)
+        .build();
 
     app.connect_startup(|_| {
         if let Some(display) = gtk4::gdk::Display::default() {
@@ -40,19 +42,64 @@ fn main() {
         }
     });
 
-    app.connect_activate(move |app| {
-        ui::build_ui(app, start_mode);
+    let main_window: Rc<RefCell<Option<ui::MainWindow>>> = Rc::new(RefCell::new(None));
+    app.connect_command_line(move |app, command_line| {
+        let request = launch_request(command_line);
+        info!("Launched with {:?}", request);
+
+        let existing = main_window.borrow().clone().filter(|w| w.is_open());
+        let window = match existing {
+            Some(window) => window,
+            None => {
+                let window = ui::build_ui(app);
+                *main_window.borrow_mut() = Some(window.clone());
+                window
+            }
+        };
+        window.launch(&request);
+        glib::ExitCode::SUCCESS
     });
 
-    let gtk_args: Vec<String> = args
+    app.run_with_args(&args);
+}
+
+/// Read the capture mode, invocation and image to open from a command line,
+/// which may come from another process
+fn launch_request(command_line: &gio::ApplicationCommandLine) -> ui::LaunchRequest {
+    let args: Vec<String> = command_line
+        .arguments()
         .into_iter()
-        .filter(|a| {
-            !matches!(
-                a.as_str(),
-                "--selection" | "-s" | "--screen" | "--window" | "-w"
-            )
-        })
+        .skip(1)
+        .map(|a| a.to_string_lossy().into_owned())
         .collect();
+    let has = |flag: &str| args.iter().any(|a| a == flag);
+
+    let start_mode = if has("--selection") || has("-s") {
+        Some(CaptureMode::Selection)
+    } else if has("--screen") {
+        Some(CaptureMode::Screen)
+    } else if has("--window") || has("-w") {
+        Some(CaptureMode::Window)
+    } else {
+        None
+    };
+    let invocation = if has("--hotkey") {
+        InvocationSource::Hotkey
+    } else if start_mode.is_some() {
+        InvocationSource::Cli
+    } else {
+        InvocationSource::Gui
+    };
+
+    // Relative paths are resolved against the launching process's directory
+    let file = args
+        .iter()
+        .find(|a| !a.starts_with('-'))
+        .and_then(|a| command_line.create_file_for_arg(a).path());
 
-    app.run_with_args(&gtk_args);
+    ui::LaunchRequest {
+        invocation,
+        start_mode,
+        file,
+    }
 }
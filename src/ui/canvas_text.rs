@@ -0,0 +1,208 @@
+use gtk4 as gtk;
+
+use gtk::gdk;
+use gtk4::glib;
+use gtk4::prelude::*;
+use log::debug;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::app::AppState;
+use crate::editor::PendingText;
+use crate::ui::drawing::DrawingComponents;
+
+const CARET_BLINK_INTERVAL: Duration = Duration::from_millis(530);
+
+fn is_typing_on_canvas(state: &Rc<RefCell<AppState>>) -> bool {
+    let s = state.borrow();
+    s.text_on_canvas && s.editor.pending_text.is_some()
+}
+
+/// Start typing a text annotation at a point on the canvas, first adding any
+/// text that was already being typed
+pub fn begin_canvas_text(
+    state: &Rc<RefCell<AppState>>,
+    drawing: &DrawingComponents,
+    x: f64,
+    y: f64,
+) {
+    // Flushes any composition into the text that is about to be added
+    drawing.im_context.reset();
+    {
+        let mut s = state.borrow_mut();
+        s.editor.commit_pending_text();
+        let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
+        debug!("Typing text on the canvas at ({}, {})", img_x, img_y);
+        s.editor.pending_text = Some(PendingText::new(img_x, img_y));
+    }
+
+    drawing.drawing_area.grab_focus();
+    drawing.im_context.focus_in();
+    text_changed(state, drawing);
+}
+
+/// Add (or drop) the text being typed on the canvas and stop editing
+fn finish_canvas_text(state: &Rc<RefCell<AppState>>, drawing: &DrawingComponents, commit: bool) {
+    if !is_typing_on_canvas(state) {
+        return;
+    }
+    drawing.im_context.reset();
+
+    let mut s = state.borrow_mut();
+    if commit {
+        s.editor.commit_pending_text();
+    } else {
+        s.editor.cancel_text();
+    }
+    drop(s);
+
+    drawing.drawing_area.queue_draw();
+}
+
+/// Show the caret solid again and keep the input method popup next to it
+fn text_changed(state: &Rc<RefCell<AppState>>, drawing: &DrawingComponents) {
+    let caret = {
+        let mut s = state.borrow_mut();
+        s.text_caret_visible = true;
+        s.editor.pending_text_caret().map(|(x, y, height)| {
+            let (dx, dy) = s.editor.image_to_display_coords(x, y);
+            (dx, dy, height * s.editor.display_scale)
+        })
+    };
+
+    if let Some((x, y, height)) = caret {
+        drawing.im_context.set_cursor_location(&gdk::Rectangle::new(
+            x as i32,
+            y as i32,
+            1,
+            height.ceil() as i32,
+        ));
+    }
+    drawing.drawing_area.queue_draw();
+}
+
+fn handle_key(
+    state: &Rc<RefCell<AppState>>,
+    drawing: &DrawingComponents,
+    key: gdk::Key,
+    modifiers: gdk::ModifierType,
+) -> glib::Propagation {
+    if !is_typing_on_canvas(state) {
+        return glib::Propagation::Proceed;
+    }
+
+    let enter = key == gdk::Key::Return || key == gdk::Key::KP_Enter;
+    if enter && !modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+        finish_canvas_text(state, drawing, true);
+        return glib::Propagation::Stop;
+    }
+    if key == gdk::Key::Escape {
+        finish_canvas_text(state, drawing, false);
+        return glib::Propagation::Stop;
+    }
+
+    {
+        let mut s = state.borrow_mut();
+        let pending = match s.editor.pending_text {
+            Some(ref mut pending) => pending,
+            None => return glib::Propagation::Proceed,
+        };
+
+        if enter {
+            pending.insert("\n");
+        } else if key == gdk::Key::BackSpace {
+            pending.delete_backward();
+        } else if key == gdk::Key::Delete || key == gdk::Key::KP_Delete {
+            pending.delete_forward();
+        } else if key == gdk::Key::Left || key == gdk::Key::KP_Left {
+            pending.move_left();
+        } else if key == gdk::Key::Right || key == gdk::Key::KP_Right {
+            pending.move_right();
+        } else if key == gdk::Key::Home || key == gdk::Key::KP_Home {
+            pending.move_home();
+        } else if key == gdk::Key::End || key == gdk::Key::KP_End {
+            pending.move_end();
+        } else {
+            return glib::Propagation::Proceed;
+        }
+    }
+
+    text_changed(state, drawing);
+    glib::Propagation::Stop
+}
+
+/// Type text annotations straight onto the canvas: keys go through an input
+/// method context, so compose sequences and IMEs work, and a blinking caret
+/// marks the insertion point
+pub fn connect_canvas_text(state: &Rc<RefCell<AppState>>, drawing: &DrawingComponents) {
+    // The drawing area has to hold the keyboard focus for the keys to arrive
+    drawing.drawing_area.set_focusable(true);
+
+    let im_context = &drawing.im_context;
+    im_context.set_client_widget(Some(&drawing.drawing_area));
+
+    im_context.connect_commit({
+        let state = state.clone();
+        let drawing = drawing.clone();
+        move |_, text| {
+            if !is_typing_on_canvas(&state) {
+                return;
+            }
+            if let Some(ref mut pending) = state.borrow_mut().editor.pending_text {
+                pending.insert(text);
+            }
+            text_changed(&state, &drawing);
+        }
+    });
+
+    im_context.connect_preedit_changed({
+        let state = state.clone();
+        let drawing = drawing.clone();
+        move |context| {
+            if !is_typing_on_canvas(&state) {
+                return;
+            }
+            let (preedit, _, _) = context.preedit_string();
+            if let Some(ref mut pending) = state.borrow_mut().editor.pending_text {
+                pending.preedit = preedit.to_string();
+            }
+            text_changed(&state, &drawing);
+        }
+    });
+
+    let keys = gtk::EventControllerKey::new();
+    keys.set_im_context(Some(im_context));
+    keys.connect_key_pressed({
+        let state = state.clone();
+        let drawing = drawing.clone();
+        move |_, key, _, modifiers| handle_key(&state, &drawing, key, modifiers)
+    });
+    drawing.drawing_area.add_controller(keys);
+
+    // Clicking a toolbar button or leaving the window keeps what was typed
+    let focus = gtk::EventControllerFocus::new();
+    focus.connect_leave({
+        let state = state.clone();
+        let drawing = drawing.clone();
+        move |_| {
+            drawing.im_context.focus_out();
+            finish_canvas_text(&state, &drawing, true);
+        }
+    });
+    drawing.drawing_area.add_controller(focus);
+
+    glib::timeout_add_local(CARET_BLINK_INTERVAL, {
+        let state = state.clone();
+        let drawing_area = drawing.drawing_area.clone();
+        move || {
+            if is_typing_on_canvas(&state) {
+                let mut s = state.borrow_mut();
+                s.text_caret_visible = !s.text_caret_visible;
+                drop(s);
+                drawing_area.queue_draw();
+            }
+            glib::ControlFlow::Continue
+        }
+    });
+}
@@ -0,0 +1,75 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::Orientation;
+
+/// Small undecorated window counting down a delayed capture while the main
+/// window is hidden
+#[derive(Clone)]
+pub struct CountdownOverlay {
+    pub window: gtk::Window,
+    pub seconds_label: gtk::Label,
+    pub status_label: gtk::Label,
+    pub cancel_btn: gtk::Button,
+}
+
+pub fn create_countdown_overlay(parent: &impl IsA<gtk::Window>) -> CountdownOverlay {
+    let seconds_label = gtk::Label::new(None);
+    seconds_label.add_css_class("countdown-seconds");
+
+    let status_label = gtk::Label::new(Some("Capturing…"));
+    status_label.add_css_class("dim-label");
+
+    let cancel_btn = gtk::Button::builder()
+        .label("Cancel")
+        .halign(gtk::Align::Center)
+        .build();
+    cancel_btn.add_css_class("pill");
+
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(12)
+        .margin_top(24)
+        .margin_bottom(24)
+        .margin_start(36)
+        .margin_end(36)
+        .build();
+    content.append(&seconds_label);
+    content.append(&status_label);
+    content.append(&cancel_btn);
+
+    let window = gtk::Window::builder()
+        .transient_for(parent)
+        .decorated(false)
+        .resizable(false)
+        .title("Capture Countdown")
+        .child(&content)
+        .build();
+    window.add_css_class("osd");
+    window.add_css_class("countdown");
+
+    CountdownOverlay {
+        window,
+        seconds_label,
+        status_label,
+        cancel_btn,
+    }
+}
+
+impl CountdownOverlay {
+    pub fn show_remaining(&self, seconds: u32) {
+        self.seconds_label.set_label(&seconds.to_string());
+        self.status_label.set_label("Capturing…");
+        self.cancel_btn.set_sensitive(true);
+        self.window.present();
+    }
+
+    pub fn show_cancelling(&self) {
+        self.status_label.set_label("Cancelling…");
+        self.cancel_btn.set_sensitive(false);
+    }
+
+    pub fn hide(&self) {
+        self.window.set_visible(false);
+    }
+}
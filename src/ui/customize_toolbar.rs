@@ -0,0 +1,118 @@
+use adw::prelude::*;
+use gtk4 as gtk;
+use libadwaita as adw;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::app::{AppState, ToolbarLayout};
+use crate::ui::toolbar::ToolbarComponents;
+
+pub fn show_customize_toolbar_dialog(
+    state: &Rc<RefCell<AppState>>,
+    toolbar: &ToolbarComponents,
+    parent: &impl IsA<gtk::Window>,
+) {
+    let window = adw::PreferencesWindow::builder()
+        .transient_for(parent)
+        .modal(true)
+        .title("Customize Toolbar")
+        .default_width(450)
+        .default_height(600)
+        .build();
+
+    let page = adw::PreferencesPage::new();
+    window.add(&page);
+
+    let group = adw::PreferencesGroup::builder()
+        .title("Toolbar Buttons")
+        .description("Choose the buttons to show and their order. Tools are always grouped first.")
+        .build();
+    page.add(&group);
+
+    let rows = Rc::new(RefCell::new(Vec::new()));
+    populate_rows(state, toolbar, &group, &rows);
+
+    window.present();
+}
+
+/// (Re)build one row per toolbar item, in the current order
+fn populate_rows(
+    state: &Rc<RefCell<AppState>>,
+    toolbar: &ToolbarComponents,
+    group: &adw::PreferencesGroup,
+    rows: &Rc<RefCell<Vec<adw::ActionRow>>>,
+) {
+    for row in rows.borrow_mut().drain(..) {
+        group.remove(&row);
+    }
+
+    let entries = state.borrow().preferences.toolbar_layout.entries().to_vec();
+    let count = entries.len();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let row = adw::ActionRow::builder().title(entry.item.label()).build();
+
+        let up_btn = gtk::Button::builder()
+            .icon_name("go-up-symbolic")
+            .tooltip_text("Move Up")
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .sensitive(index > 0)
+            .build();
+        let down_btn = gtk::Button::builder()
+            .icon_name("go-down-symbolic")
+            .tooltip_text("Move Down")
+            .valign(gtk::Align::Center)
+            .css_classes(["flat"])
+            .sensitive(index + 1 < count)
+            .build();
+        let switch = gtk::Switch::builder()
+            .active(entry.visible)
+            .valign(gtk::Align::Center)
+            .build();
+
+        for (button, offset) in [(&up_btn, -1), (&down_btn, 1)] {
+            button.connect_clicked({
+                let state = state.clone();
+                let toolbar = toolbar.clone();
+                let group = group.clone();
+                let rows = rows.clone();
+                let item = entry.item;
+                move |_| {
+                    update_layout(&state, &toolbar, |layout| layout.move_item(item, offset));
+                    populate_rows(&state, &toolbar, &group, &rows);
+                }
+            });
+        }
+
+        switch.connect_active_notify({
+            let state = state.clone();
+            let toolbar = toolbar.clone();
+            let item = entry.item;
+            move |switch| {
+                let visible = switch.is_active();
+                update_layout(&state, &toolbar, |layout| layout.set_visible(item, visible));
+            }
+        });
+
+        row.add_suffix(&up_btn);
+        row.add_suffix(&down_btn);
+        row.add_suffix(&switch);
+        row.set_activatable_widget(Some(&switch));
+        group.add(&row);
+        rows.borrow_mut().push(row);
+    }
+}
+
+fn update_layout(
+    state: &Rc<RefCell<AppState>>,
+    toolbar: &ToolbarComponents,
+    change: impl FnOnce(&mut ToolbarLayout),
+) {
+    let mut s = state.borrow_mut();
+    change(&mut s.preferences.toolbar_layout);
+    s.preferences.save();
+    let layout = s.preferences.toolbar_layout.clone();
+    drop(s);
+
+    toolbar.arrange(&layout);
+}
@@ -1,39 +1,84 @@
 use gtk4 as gtk;
+use gtk4::{gio, glib, pango};
 use libadwaita as adw;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
+use adw::prelude::*;
 use gtk::{Align, Orientation};
 use gtk4::prelude::*;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 
 use crate::app::AppState;
 use crate::capture::desktop::DesktopSession;
-use crate::capture::window::{capture_window, list_capturable_windows, WindowInfo};
+use crate::capture::metadata::{CaptureMetadata, CaptureSource};
+use crate::capture::window::{
+    capture_window, list_capturable_windows, occluding_windows, WindowCaptureError,
+    WindowCaptureResult, WindowInfo,
+};
+use crate::capture::window_events::WindowWatcher;
+use crate::editor::spelling::{words, Misspelling, SpellChecker};
+use crate::ui::feedback::capture_feedback;
+
+/// Most suggestions offered in the text view's context menu
+const MAX_SPELLING_SUGGESTIONS: usize = 5;
+
+const MISSPELLED_TAG: &str = "misspelled";
 
 #[derive(Clone)]
 pub struct TextPopoverComponents {
     pub text_popover: gtk::Popover,
-    pub text_entry: gtk::Entry,
+    pub text_view: gtk::TextView,
     pub text_confirm_btn: gtk::Button,
     pub text_cancel_btn: gtk::Button,
 }
 
 pub fn create_text_popover(drawing_area: &gtk::DrawingArea) -> TextPopoverComponents {
-    let text_entry = gtk::Entry::builder()
-        .placeholder_text("Enter text...")
-        .width_chars(20)
+    let text_view = gtk::TextView::builder()
+        .accepts_tab(false)
+        .wrap_mode(gtk::WrapMode::None)
+        .top_margin(6)
+        .bottom_margin(6)
+        .left_margin(6)
+        .right_margin(6)
+        .tooltip_text("Shift+Enter for a new line")
+        .build();
+
+    let text_scroll = gtk::ScrolledWindow::builder()
+        .child(&text_view)
+        .min_content_width(220)
+        .max_content_height(160)
+        .propagate_natural_height(true)
+        .css_classes(["card"])
+        .build();
+
+    let emoji_chooser = gtk::EmojiChooser::new();
+    emoji_chooser.connect_emoji_picked({
+        let text_view = text_view.clone();
+        move |_, emoji| {
+            text_view.buffer().insert_at_cursor(emoji);
+            text_view.grab_focus();
+        }
+    });
+
+    let emoji_btn = gtk::MenuButton::builder()
+        .icon_name("face-smile-symbolic")
+        .tooltip_text("Insert Emoji")
+        .popover(&emoji_chooser)
+        .valign(Align::Start)
         .build();
 
     let text_confirm_btn = gtk::Button::builder()
         .icon_name("app-object-select-symbolic")
         .tooltip_text("Add Text")
+        .valign(Align::Start)
         .build();
     text_confirm_btn.add_css_class("suggested-action");
 
     let text_cancel_btn = gtk::Button::builder()
         .icon_name("app-process-stop-symbolic")
         .tooltip_text("Cancel")
+        .valign(Align::Start)
         .build();
 
     let text_input_box = gtk::Box::builder()
@@ -44,7 +89,8 @@ pub fn create_text_popover(drawing_area: &gtk::DrawingArea) -> TextPopoverCompon
         .margin_start(6)
         .margin_end(6)
         .build();
-    text_input_box.append(&text_entry);
+    text_input_box.append(&text_scroll);
+    text_input_box.append(&emoji_btn);
     text_input_box.append(&text_confirm_btn);
     text_input_box.append(&text_cancel_btn);
 
@@ -56,7 +102,7 @@ pub fn create_text_popover(drawing_area: &gtk::DrawingArea) -> TextPopoverCompon
 
     TextPopoverComponents {
         text_popover,
-        text_entry,
+        text_view,
         text_confirm_btn,
         text_cancel_btn,
     }
@@ -71,45 +117,277 @@ pub fn connect_text_popover(
     components.text_confirm_btn.connect_clicked({
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        let text_popover = components.text_popover.clone();
-        let text_entry = components.text_entry.clone();
+        let components = components.clone();
         move |_| {
-            let text = text_entry.text().to_string();
-            let mut s = state.borrow_mut();
-            s.editor.commit_text(text);
-            drop(s);
-            text_popover.popdown();
-            drawing_area.queue_draw();
+            commit_popover_text(&state, &drawing_area, &components);
         }
     });
 
     components.text_cancel_btn.connect_clicked({
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        let text_popover = components.text_popover.clone();
+        let components = components.clone();
         move |_| {
-            let mut s = state.borrow_mut();
-            s.editor.cancel_text();
-            drop(s);
-            text_popover.popdown();
-            drawing_area.queue_draw();
+            cancel_popover_text(&state, &drawing_area, &components);
         }
     });
 
-    components.text_entry.connect_activate({
+    // Enter adds the text, Shift+Enter starts a new line and Escape cancels
+    let keys = gtk::EventControllerKey::new();
+    keys.set_propagation_phase(gtk::PropagationPhase::Capture);
+    keys.connect_key_pressed({
         let state = state.clone();
         let drawing_area = drawing_area.clone();
-        let text_popover = components.text_popover.clone();
-        let text_entry = components.text_entry.clone();
-        move |_| {
-            let text = text_entry.text().to_string();
-            let mut s = state.borrow_mut();
-            s.editor.commit_text(text);
-            drop(s);
-            text_popover.popdown();
+        let components = components.clone();
+        move |_, key, _, modifiers| {
+            let enter = key == gtk::gdk::Key::Return || key == gtk::gdk::Key::KP_Enter;
+            if enter && !modifiers.contains(gtk::gdk::ModifierType::SHIFT_MASK) {
+                commit_popover_text(&state, &drawing_area, &components);
+                glib::Propagation::Stop
+            } else if key == gtk::gdk::Key::Escape {
+                cancel_popover_text(&state, &drawing_area, &components);
+                glib::Propagation::Stop
+            } else {
+                glib::Propagation::Proceed
+            }
+        }
+    });
+    components.text_view.add_controller(keys);
+
+    components.text_view.buffer().connect_changed({
+        let state = state.clone();
+        let drawing_area = drawing_area.clone();
+        move |buffer| {
+            let text = buffer
+                .text(&buffer.start_iter(), &buffer.end_iter(), false)
+                .to_string();
+            state.borrow_mut().editor.update_pending_text(text);
             drawing_area.queue_draw();
         }
     });
+
+    connect_spell_checking(state, &components.text_view);
+}
+
+fn commit_popover_text(
+    state: &Rc<RefCell<AppState>>,
+    drawing_area: &gtk::DrawingArea,
+    components: &TextPopoverComponents,
+) {
+    let buffer = components.text_view.buffer();
+    let text = buffer
+        .text(&buffer.start_iter(), &buffer.end_iter(), false)
+        .trim_end()
+        .to_string();
+    let mut s = state.borrow_mut();
+    s.editor.commit_text(text);
+    drop(s);
+    components.text_popover.popdown();
+    drawing_area.queue_draw();
+}
+
+fn cancel_popover_text(
+    state: &Rc<RefCell<AppState>>,
+    drawing_area: &gtk::DrawingArea,
+    components: &TextPopoverComponents,
+) {
+    let mut s = state.borrow_mut();
+    s.editor.cancel_text();
+    drop(s);
+    components.text_popover.popdown();
+    drawing_area.queue_draw();
+}
+
+/// Spell checker for the text view, started the first time it is needed
+#[derive(Default)]
+struct SpellingState {
+    checker: Option<SpellChecker>,
+
+    /// Set once no backend could be started, so we stop retrying on every key
+    unavailable: bool,
+
+    misspellings: Vec<Misspelling>,
+}
+
+/// Underline misspelled words in the text view and offer the dictionary's
+/// suggestions in its context menu while spell checking is enabled
+fn connect_spell_checking(state: &Rc<RefCell<AppState>>, text_view: &gtk::TextView) {
+    let spelling = Rc::new(RefCell::new(SpellingState::default()));
+    let buffer = text_view.buffer();
+    buffer.tag_table().add(
+        &gtk::TextTag::builder()
+            .name(MISSPELLED_TAG)
+            .underline(pango::Underline::Error)
+            .build(),
+    );
+
+    buffer.connect_changed({
+        let state = state.clone();
+        let spelling = spelling.clone();
+        let text_view = text_view.clone();
+        move |_| {
+            check_text_spelling(&state, &spelling, &text_view);
+        }
+    });
+
+    buffer.connect_notify_local(Some("cursor-position"), {
+        let spelling = spelling.clone();
+        let text_view = text_view.clone();
+        move |_, _| {
+            update_spelling_menu(&spelling, &text_view);
+        }
+    });
+
+    let actions = gio::SimpleActionGroup::new();
+
+    let action_replace = gio::SimpleAction::new("replace", Some(glib::VariantTy::STRING));
+    action_replace.connect_activate({
+        let spelling = spelling.clone();
+        let text_view = text_view.clone();
+        move |_, param| {
+            let replacement = param.and_then(|v| v.get::<String>());
+            let target = misspelled_word_at_cursor(&spelling, &text_view);
+            if let (Some(replacement), Some((start, end, _))) = (replacement, target) {
+                let buffer = text_view.buffer();
+                let mut start = buffer.iter_at_offset(start);
+                let mut end = buffer.iter_at_offset(end);
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &replacement);
+            }
+        }
+    });
+    actions.add_action(&action_replace);
+
+    let action_add = gio::SimpleAction::new("add", None);
+    action_add.connect_activate({
+        let state = state.clone();
+        let spelling = spelling.clone();
+        let text_view = text_view.clone();
+        move |_, _| {
+            if let Some((_, _, misspelling)) = misspelled_word_at_cursor(&spelling, &text_view) {
+                if let Some(ref mut checker) = spelling.borrow_mut().checker {
+                    if let Err(e) = checker.add_word(&misspelling.word) {
+                        warn!(
+                            "Failed to add {:?} to the dictionary: {}",
+                            misspelling.word, e
+                        );
+                    }
+                }
+                check_text_spelling(&state, &spelling, &text_view);
+            }
+        }
+    });
+    actions.add_action(&action_add);
+
+    text_view.insert_action_group("spelling", Some(&actions));
+}
+
+fn check_text_spelling(
+    state: &Rc<RefCell<AppState>>,
+    spelling: &Rc<RefCell<SpellingState>>,
+    text_view: &gtk::TextView,
+) {
+    let buffer = text_view.buffer();
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+    let mut spelling_state = spelling.borrow_mut();
+
+    spelling_state.misspellings = Vec::new();
+    if state.borrow().spell_check && !spelling_state.unavailable {
+        if spelling_state.checker.is_none() {
+            match SpellChecker::start() {
+                Ok(checker) => spelling_state.checker = Some(checker),
+                Err(e) => {
+                    warn!("{}", e);
+                    spelling_state.unavailable = true;
+                }
+            }
+        }
+
+        if let Some(ref mut checker) = spelling_state.checker {
+            match checker.check(&text) {
+                Ok(misspellings) => spelling_state.misspellings = misspellings,
+                Err(e) => {
+                    // Restarted on the next change
+                    warn!("Spell check failed: {}", e);
+                    spelling_state.checker = None;
+                }
+            }
+        }
+    }
+
+    buffer.remove_tag_by_name(MISSPELLED_TAG, &buffer.start_iter(), &buffer.end_iter());
+    for (start, end) in words(&text) {
+        if spelling_state
+            .misspellings
+            .iter()
+            .any(|m| m.word == text[start..end])
+        {
+            buffer.apply_tag_by_name(
+                MISSPELLED_TAG,
+                &buffer.iter_at_offset(text[..start].chars().count() as i32),
+                &buffer.iter_at_offset(text[..end].chars().count() as i32),
+            );
+        }
+    }
+    drop(spelling_state);
+
+    update_spelling_menu(spelling, text_view);
+}
+
+/// Character range of the misspelled word under the cursor
+fn misspelled_word_at_cursor(
+    spelling: &Rc<RefCell<SpellingState>>,
+    text_view: &gtk::TextView,
+) -> Option<(i32, i32, Misspelling)> {
+    let buffer = text_view.buffer();
+    let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+    let cursor = text
+        .char_indices()
+        .nth(buffer.cursor_position().max(0) as usize)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len());
+
+    let (start, end) = words(&text)
+        .into_iter()
+        .find(|&(start, end)| start <= cursor && cursor <= end)?;
+    let misspelling = spelling
+        .borrow()
+        .misspellings
+        .iter()
+        .find(|m| m.word == text[start..end])
+        .cloned()?;
+
+    Some((
+        text[..start].chars().count() as i32,
+        text[..end].chars().count() as i32,
+        misspelling,
+    ))
+}
+
+fn update_spelling_menu(spelling: &Rc<RefCell<SpellingState>>, text_view: &gtk::TextView) {
+    let misspelling = match misspelled_word_at_cursor(spelling, text_view) {
+        Some((_, _, misspelling)) => misspelling,
+        None => {
+            text_view.set_extra_menu(None::<&gio::MenuModel>);
+            return;
+        }
+    };
+
+    let suggestions = gio::Menu::new();
+    for suggestion in misspelling
+        .suggestions
+        .iter()
+        .take(MAX_SPELLING_SUGGESTIONS)
+    {
+        let item = gio::MenuItem::new(Some(suggestion), None);
+        item.set_action_and_target_value(Some("spelling.replace"), Some(&suggestion.to_variant()));
+        suggestions.append_item(&item);
+    }
+
+    let menu = gio::Menu::new();
+    menu.append_section(None, &suggestions);
+    menu.append(Some("Add to Dictionary"), Some("spelling.add"));
+    text_view.set_extra_menu(Some(&menu));
 }
 
 pub fn show_window_selector(
@@ -118,18 +396,50 @@ pub fn show_window_selector(
     drawing_area: &gtk::DrawingArea,
     placeholder_icon: &gtk::Image,
     tools_box: &gtk::Box,
+    on_captured: Rc<dyn Fn()>,
 ) {
-    let window_selector = gtk::Window::builder()
+    let (width, height) = state
+        .borrow()
+        .preferences
+        .window_selector_size
+        .unwrap_or((400, 500));
+    let window_selector = adw::Dialog::builder()
         .title("Select Window")
-        .modal(true)
-        .transient_for(parent_window)
-        .default_width(400)
-        .default_height(500)
+        .content_width(width)
+        .content_height(height)
         .build();
 
+    let closed = Rc::new(Cell::new(false));
+    window_selector.connect_closed({
+        let state = state.clone();
+        let closed = closed.clone();
+        move |dialog| {
+            closed.set(true);
+            let mut s = state.borrow_mut();
+            s.preferences.window_selector_size = Some((dialog.width(), dialog.height()));
+            s.preferences.save();
+        }
+    });
+
+    // Before the search entry, which would only clear itself
+    let escape = gtk::EventControllerKey::new();
+    escape.set_propagation_phase(gtk::PropagationPhase::Capture);
+    escape.connect_key_pressed({
+        let window_selector = window_selector.clone();
+        move |_, key, _, _| {
+            if key == gtk::gdk::Key::Escape {
+                window_selector.close();
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        }
+    });
+    window_selector.add_controller(escape);
+
     let list_box = gtk::ListBox::builder()
         .selection_mode(gtk::SelectionMode::Single)
         .css_classes(["boxed-list"])
+        .valign(Align::Start)
         .build();
 
     let scrolled_window = gtk::ScrolledWindow::builder()
@@ -137,6 +447,30 @@ pub fn show_window_selector(
         .vexpand(true)
         .build();
 
+    // Listing windows can take a while on backends that shell out
+    let spinner = gtk::Spinner::builder()
+        .spinning(true)
+        .width_request(32)
+        .height_request(32)
+        .halign(Align::Center)
+        .build();
+    let loading_page = adw::StatusPage::builder()
+        .title("Looking for Windows…")
+        .child(&spinner)
+        .build();
+    loading_page.add_css_class("compact");
+    let empty_page = adw::StatusPage::builder()
+        .icon_name("window-new-symbolic")
+        .title("No Windows Found")
+        .build();
+    empty_page.add_css_class("compact");
+
+    let stack = gtk::Stack::builder().vexpand(true).build();
+    stack.add_named(&loading_page, Some("loading"));
+    stack.add_named(&scrolled_window, Some("list"));
+    stack.add_named(&empty_page, Some("empty"));
+    stack.set_visible_child_name("loading");
+
     let vbox = gtk::Box::builder()
         .orientation(Orientation::Vertical)
         .spacing(12)
@@ -156,40 +490,71 @@ pub fn show_window_selector(
         .css_classes(["dim-label"])
         .halign(Align::Start)
         .build();
+    let minimized_label = gtk::Label::builder()
+        .label(if session.captures_minimized_windows() {
+            "Minimized windows are listed and can be captured"
+        } else {
+            "Minimized windows cannot be captured in this session"
+        })
+        .css_classes(["dim-label", "caption"])
+        .halign(Align::Start)
+        .build();
 
-    vbox.append(&gtk::Label::new(Some("Select a window to capture:")));
-    vbox.append(&session_label);
-    vbox.append(&scrolled_window);
-    window_selector.set_child(Some(&vbox));
+    // Typing anywhere in the selector searches
+    let search_entry = gtk::SearchEntry::builder()
+        .placeholder_text("Search windows")
+        .build();
+    search_entry.set_key_capture_widget(Some(&window_selector));
 
-    let window_infos: Rc<RefCell<Vec<WindowInfo>>> = Rc::new(RefCell::new(Vec::new()));
+    vbox.append(&session_label);
+    vbox.append(&minimized_label);
+    vbox.append(&search_entry);
+    vbox.append(&stack);
 
-    if let Ok(windows) = list_capturable_windows() {
-        for win_info in windows {
-            let row = gtk::Box::builder()
-                .orientation(Orientation::Horizontal)
-                .spacing(12)
-                .build();
+    let toolbar_view = adw::ToolbarView::new();
+    toolbar_view.add_top_bar(&adw::HeaderBar::new());
+    toolbar_view.set_content(Some(&vbox));
+    window_selector.set_child(Some(&toolbar_view));
 
-            let icon = gtk::Image::builder()
-                .icon_name(win_info.icon_name_hint().to_lowercase())
-                .pixel_size(32)
-                .build();
+    let window_infos: Rc<RefCell<Vec<WindowInfo>>> = Rc::new(RefCell::new(Vec::new()));
 
-            let label = gtk::Label::builder()
-                .label(win_info.display_label())
-                .halign(Align::Start)
-                .ellipsize(gtk::pango::EllipsizeMode::End)
-                .build();
+    list_box.set_filter_func({
+        let window_infos = window_infos.clone();
+        let search_entry = search_entry.clone();
+        move |row| {
+            let query = search_entry.text().to_lowercase();
+            query.is_empty()
+                || window_infos
+                    .borrow()
+                    .get(row.index() as usize)
+                    .map(|w| w.display_label().to_lowercase().contains(&query))
+                    .unwrap_or(true)
+        }
+    });
+    connect_window_search(&search_entry, &list_box);
 
-            row.append(&icon);
-            row.append(&label);
+    let loading = Rc::new(Cell::new(false));
+    load_window_list(&stack, &empty_page, &list_box, &window_infos, &loading);
 
-            list_box.append(&row);
+    // Keep the list in sync with the compositor while the selector is open;
+    // the watcher is dropped, stopping its thread, once the timeout ends
+    let watcher = WindowWatcher::start(&session);
+    glib::timeout_add_local(std::time::Duration::from_millis(250), {
+        let stack = stack.clone();
+        let list_box = list_box.clone();
+        let window_infos = window_infos.clone();
+        move || {
+            if closed.get() {
+                debug!("Window selector closed, stopping window watcher");
+                return glib::ControlFlow::Break;
+            }
 
-            window_infos.borrow_mut().push(win_info);
+            if watcher.poll_changed() {
+                load_window_list(&stack, &empty_page, &list_box, &window_infos, &loading);
+            }
+            glib::ControlFlow::Continue
         }
-    }
+    });
 
     list_box.connect_row_activated({
         let state = state.clone();
@@ -200,48 +565,369 @@ pub fn show_window_selector(
         let tools_box = tools_box.clone();
         move |_lb, row| {
             let idx = row.index();
-            if idx >= 0 {
-                let infos = window_infos.borrow();
-                if let Some(window_info) = infos.get(idx as usize) {
-                    match capture_window(window_info) {
-                        Ok(result) => {
-                            info!("Captured window: {}", result.window_info.debug_info());
-                            let mut s = state.borrow_mut();
-                            s.final_image = Some(result.pixbuf);
-                            s.is_active = false;
-                            s.editor.reset();
-
-                            placeholder_icon.set_visible(false);
-                            drawing_area.queue_draw();
-                            tools_box.set_visible(true);
-                            window_selector.close();
-                        }
-                        Err(e) => {
-                            error!("Failed to capture window: {}", e);
-
-                            let error_dialog = gtk::AlertDialog::builder()
-                                .modal(true)
-                                .message("Failed to Capture Window")
-                                .detail(format!(
-                                    "Could not capture the selected window.\n\nError: {}\n\n\
-                                    Tip: Make sure the required screenshot tool is installed:\n\
-                                    • Hyprland/Sway: grim\n\
-                                    • GNOME: gnome-screenshot\n\
-                                    • KDE: spectacle",
-                                    e
-                                ))
-                                .buttons(["OK"])
-                                .build();
-
-                            error_dialog.show(Some(&window_selector));
-                        }
-                    }
+            if idx < 0 {
+                return;
+            }
+            let infos = window_infos.borrow();
+            let window_info = match infos.get(idx as usize) {
+                Some(window_info) => window_info.clone(),
+                None => return,
+            };
+
+            let occluders: Vec<String> = if DesktopSession::detect()
+                .window_list_backend()
+                .crops_by_geometry()
+            {
+                occluding_windows(&infos, &window_info)
+                    .iter()
+                    .map(|w| w.display_label())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            drop(infos);
+
+            if occluders.is_empty() {
+                capture_selected_window(
+                    &state,
+                    &drawing_area,
+                    &placeholder_icon,
+                    &tools_box,
+                    &window_selector,
+                    &window_info,
+                    on_captured.clone(),
+                );
+                return;
+            }
+
+            warn!(
+                "'{}' is covered by {:?}",
+                window_info.display_label(),
+                occluders
+            );
+            let body = format!(
+                "Part of \"{}\" is covered by:\n\n{}\n\n\
+                This desktop captures a window by its area of the screen, so \
+                the covering windows will be in the screenshot. Bring the \
+                window to the front first, or capture it anyway.",
+                window_info.display_label(),
+                occluders
+                    .iter()
+                    .map(|label| format!("• {}", label))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+            let dialog = adw::AlertDialog::new(Some("Window Is Covered"), Some(&body));
+            dialog.add_response("cancel", "Cancel");
+            dialog.add_response("capture", "Capture Anyway");
+            dialog.set_close_response("cancel");
+            dialog.set_default_response(Some("cancel"));
+
+            let state = state.clone();
+            let drawing_area = drawing_area.clone();
+            let placeholder_icon = placeholder_icon.clone();
+            let tools_box = tools_box.clone();
+            let window_selector = window_selector.clone();
+            let on_captured = on_captured.clone();
+            glib::spawn_future_local(async move {
+                if dialog.choose_future(&window_selector).await == "capture" {
+                    capture_selected_window(
+                        &state,
+                        &drawing_area,
+                        &placeholder_icon,
+                        &tools_box,
+                        &window_selector,
+                        &window_info,
+                        on_captured,
+                    );
                 }
+            });
+        }
+    });
+
+    let parent: &gtk::Window = parent_window.as_ref();
+    window_selector.present(Some(parent));
+}
+
+/// Capture the window picked in the selector off the main thread, as raising
+/// it first can take a while, then load it into the editor and run
+/// `on_captured`
+fn capture_selected_window(
+    state: &Rc<RefCell<AppState>>,
+    drawing_area: &gtk::DrawingArea,
+    placeholder_icon: &gtk::Image,
+    tools_box: &gtk::Box,
+    window_selector: &adw::Dialog,
+    window_info: &WindowInfo,
+    on_captured: Rc<dyn Fn()>,
+) {
+    // Another row activated meanwhile would start a second capture
+    window_selector.set_sensitive(false);
+
+    let state = state.clone();
+    let drawing_area = drawing_area.clone();
+    let placeholder_icon = placeholder_icon.clone();
+    let tools_box = tools_box.clone();
+    let window_selector = window_selector.clone();
+    let window_info = window_info.clone();
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(move || capture_window(&window_info)).await;
+        window_selector.set_sensitive(true);
+        match result {
+            Ok(result) => show_captured_window(
+                &state,
+                &drawing_area,
+                &placeholder_icon,
+                &tools_box,
+                &window_selector,
+                result,
+                &*on_captured,
+            ),
+            Err(_) => error!("Window capture task panicked"),
+        }
+    });
+}
+
+/// Load a capture from the selector into the editor and close the selector,
+/// or explain why it failed
+fn show_captured_window(
+    state: &Rc<RefCell<AppState>>,
+    drawing_area: &gtk::DrawingArea,
+    placeholder_icon: &gtk::Image,
+    tools_box: &gtk::Box,
+    window_selector: &adw::Dialog,
+    result: Result<WindowCaptureResult, WindowCaptureError>,
+    on_captured: &dyn Fn(),
+) {
+    match result {
+        Ok(result) => {
+            info!("Captured window: {}", result.window_info.debug_info());
+            let mut s = state.borrow_mut();
+            s.final_image = Some(result.pixbuf);
+            s.upload_url = None;
+            s.last_export = None;
+            s.saved_path = None;
+            s.file_metadata = None;
+            let info = &result.window_info;
+            s.capture_metadata = Some(
+                CaptureMetadata::new(CaptureSource::Window)
+                    .with_window(info)
+                    .with_region(info.x, info.y, info.width as i32, info.height as i32),
+            );
+            s.is_active = false;
+            s.editor.reset();
+            s.auto_save_capture();
+
+            drop(s);
+
+            placeholder_icon.set_visible(false);
+            drawing_area.queue_draw();
+            tools_box.set_visible(true);
+            window_selector.close();
+            capture_feedback(state, drawing_area);
+            on_captured();
+        }
+        Err(e) => {
+            error!("Failed to capture window: {}", e);
+
+            let error_dialog = adw::AlertDialog::builder()
+                .heading("Failed to Capture Window")
+                .body(format!(
+                    "Could not capture the selected window.\n\nError: {}\n\n\
+                    Tip: Make sure the required screenshot tool is installed:\n\
+                    • Hyprland/Sway: grim\n\
+                    • GNOME: gnome-screenshot\n\
+                    • KDE: spectacle",
+                    e
+                ))
+                .build();
+            error_dialog.add_response("ok", "OK");
+
+            error_dialog.present(Some(window_selector));
+        }
+    }
+}
+
+/// List the windows off the main thread, then show them, or why there are none
+fn load_window_list(
+    stack: &gtk::Stack,
+    empty_page: &adw::StatusPage,
+    list_box: &gtk::ListBox,
+    window_infos: &Rc<RefCell<Vec<WindowInfo>>>,
+    loading: &Rc<Cell<bool>>,
+) {
+    // A refresh while listing would only repeat the same work
+    if loading.replace(true) {
+        return;
+    }
+
+    let stack = stack.clone();
+    let empty_page = empty_page.clone();
+    let list_box = list_box.clone();
+    let window_infos = window_infos.clone();
+    let loading = loading.clone();
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(list_capturable_windows).await;
+        loading.set(false);
+
+        match result {
+            Ok(Ok(windows)) if !windows.is_empty() => {
+                populate_window_list(&list_box, &window_infos, windows);
+                stack.set_visible_child_name("list");
+            }
+            Ok(Ok(_)) => {
+                populate_window_list(&list_box, &window_infos, Vec::new());
+                empty_page.set_description(None);
+                stack.set_visible_child_name("empty");
             }
+            Ok(Err(e)) => {
+                warn!("Failed to list windows: {}", e);
+                populate_window_list(&list_box, &window_infos, Vec::new());
+                empty_page.set_description(Some(&e.to_string()));
+                stack.set_visible_child_name("empty");
+            }
+            Err(_) => error!("Window listing task panicked"),
         }
     });
+}
+
+/// Fill the selector with `windows`, keeping the selected window selected
+fn populate_window_list(
+    list_box: &gtk::ListBox,
+    window_infos: &Rc<RefCell<Vec<WindowInfo>>>,
+    windows: Vec<WindowInfo>,
+) {
+    let selected_id = list_box.selected_row().and_then(|row| {
+        window_infos
+            .borrow()
+            .get(row.index() as usize)
+            .map(|w| w.id)
+    });
+
+    let unchanged = {
+        let current = window_infos.borrow();
+        current.len() == windows.len()
+            && current.iter().zip(&windows).all(|(a, b)| {
+                a.id == b.id
+                    && a.title == b.title
+                    && a.app_name == b.app_name
+                    && a.is_minimized == b.is_minimized
+            })
+    };
+    if unchanged {
+        // Same rows, only geometry may differ
+        *window_infos.borrow_mut() = windows;
+        return;
+    }
+
+    list_box.remove_all();
+    window_infos.borrow_mut().clear();
+
+    for win_info in windows {
+        let row = gtk::Box::builder()
+            .orientation(Orientation::Horizontal)
+            .spacing(12)
+            .build();
+
+        let icon = gtk::Image::builder()
+            .icon_name(win_info.icon_name_hint().to_lowercase())
+            .pixel_size(32)
+            .build();
+
+        let label = gtk::Label::builder()
+            .label(win_info.display_label())
+            .halign(Align::Start)
+            .hexpand(true)
+            .ellipsize(gtk::pango::EllipsizeMode::End)
+            .build();
+
+        row.append(&icon);
+        row.append(&label);
+        if win_info.is_minimized {
+            row.append(
+                &gtk::Label::builder()
+                    .label("Minimized")
+                    .css_classes(["dim-label", "caption"])
+                    .build(),
+            );
+        }
 
-    window_selector.present();
+        list_box.append(&row);
+
+        if Some(win_info.id) == selected_id {
+            if let Some(list_row) = list_box.row_at_index(window_infos.borrow().len() as i32) {
+                list_box.select_row(Some(&list_row));
+            }
+        }
+
+        window_infos.borrow_mut().push(win_info);
+    }
+    list_box.invalidate_filter();
+}
+
+/// Rows left visible by the search, in order
+fn visible_rows(list_box: &gtk::ListBox) -> Vec<gtk::ListBoxRow> {
+    let mut rows = Vec::new();
+    let mut index = 0;
+    while let Some(row) = list_box.row_at_index(index) {
+        if row.is_child_visible() {
+            rows.push(row);
+        }
+        index += 1;
+    }
+    rows
+}
+
+/// Up/Down move through the matching windows and Enter captures the
+/// selected one, without leaving the search entry
+fn connect_window_search(search_entry: &gtk::SearchEntry, list_box: &gtk::ListBox) {
+    search_entry.connect_search_changed({
+        let list_box = list_box.clone();
+        move |_| {
+            list_box.invalidate_filter();
+            let first = visible_rows(&list_box).into_iter().next();
+            list_box.select_row(first.as_ref());
+        }
+    });
+
+    search_entry.connect_activate({
+        let list_box = list_box.clone();
+        move |_| {
+            if let Some(row) = list_box.selected_row() {
+                row.activate();
+            }
+        }
+    });
+
+    let keys = gtk::EventControllerKey::new();
+    keys.connect_key_pressed({
+        let list_box = list_box.clone();
+        move |_, key, _, _| {
+            let offset: isize = if key == gtk::gdk::Key::Down {
+                1
+            } else if key == gtk::gdk::Key::Up {
+                -1
+            } else {
+                return glib::Propagation::Proceed;
+            };
+
+            let rows = visible_rows(&list_box);
+            let current = list_box
+                .selected_row()
+                .and_then(|selected| rows.iter().position(|row| *row == selected));
+            let next = match current {
+                Some(i) => (i as isize + offset).clamp(0, rows.len() as isize - 1) as usize,
+                None => 0,
+            };
+            if let Some(row) = rows.get(next) {
+                list_box.select_row(Some(row));
+                // The list takes over navigation; typing still goes to the search
+                row.grab_focus();
+            }
+            glib::Propagation::Stop
+        }
+    });
+    search_entry.add_controller(keys);
 }
 
 pub fn show_about_dialog(parent_window: &impl IsA<gtk::Window>) {
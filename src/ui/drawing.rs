@@ -5,13 +5,25 @@ use gtk4::prelude::*;
 use std::cell::RefCell;
 use std::rc::Rc;
 
+use crate::app::config::Action;
 use crate::app::{AppState, CaptureMode};
+use crate::editor::{Annotation, CropHandle};
+use crate::ui::theme::{CanvasBackdrop, CanvasChrome, ColorVision};
+
+/// Side of one checkerboard square, in display pixels
+const CHECKER_SIZE: i32 = 8;
+
+/// Side of the crop resize handles, in display pixels
+const CROP_HANDLE_SIZE: f64 = 8.0;
 
 #[derive(Clone)]
 pub struct DrawingComponents {
     pub drawing_area: DrawingArea,
     pub placeholder_icon: gtk::Image,
     pub picked_color_label: gtk::Label,
+
+    /// Input method for typing text annotations on the canvas
+    pub im_context: gtk::IMMulticontext,
 }
 
 pub fn create_drawing_area(state: &Rc<RefCell<AppState>>) -> DrawingComponents {
@@ -44,24 +56,72 @@ pub fn create_drawing_area(state: &Rc<RefCell<AppState>>) -> DrawingComponents {
         drawing_area,
         placeholder_icon,
         picked_color_label,
+        im_context: gtk::IMMulticontext::new(),
     }
 }
 
 fn setup_draw_function(drawing_area: &DrawingArea, state: &Rc<RefCell<AppState>>) {
     drawing_area.set_draw_func({
         let state = state.clone();
-        move |_, cr, width, height| {
-            draw_content(&state, cr, width, height);
+        move |area, cr, width, height| {
+            let vision = state.borrow().color_vision;
+            if vision == ColorVision::Normal
+                || !draw_simulated(&state, cr, width, height, area.scale_factor(), vision)
+            {
+                draw_content(&state, cr, width, height);
+            }
         }
     });
 }
 
+/// Draw the canvas off screen and show it as seen with `vision`. Returns
+/// false, having drawn nothing, when there is no memory for the surface.
+fn draw_simulated(
+    state: &Rc<RefCell<AppState>>,
+    cr: &gtk::cairo::Context,
+    width: i32,
+    height: i32,
+    scale_factor: i32,
+    vision: ColorVision,
+) -> bool {
+    let mut surface = match gtk::cairo::ImageSurface::create(
+        gtk::cairo::Format::ARgb32,
+        width * scale_factor,
+        height * scale_factor,
+    ) {
+        Ok(surface) => surface,
+        Err(_) => return false,
+    };
+    surface.set_device_scale(scale_factor as f64, scale_factor as f64);
+    {
+        let surface_cr = match gtk::cairo::Context::new(&surface) {
+            Ok(surface_cr) => surface_cr,
+            Err(_) => return false,
+        };
+        draw_content(state, &surface_cr, width, height);
+    }
+
+    surface.flush();
+    match surface.data() {
+        Ok(mut data) => vision.simulate(&mut data),
+        Err(_) => return false,
+    }
+    surface.mark_dirty();
+
+    cr.set_source_surface(&surface, 0.0, 0.0)
+        .expect("Failed to set simulated canvas as source");
+    cr.paint().expect("Failed to paint simulated canvas");
+    true
+}
+
 fn draw_content(state: &Rc<RefCell<AppState>>, cr: &gtk::cairo::Context, width: i32, height: i32) {
     let mut state = state.borrow_mut();
     let da_width = width as f64;
     let da_height = height as f64;
+    let chrome = CanvasChrome::new(state.preferences.canvas_backdrop);
 
-    cr.set_source_rgb(0.14, 0.14, 0.14);
+    let (r, g, b) = chrome.background;
+    cr.set_source_rgb(r, g, b);
     cr.paint().expect("Invalid cairo surface state");
 
     let pixbuf_opt = if state.is_active {
@@ -93,6 +153,16 @@ fn draw_content(state: &Rc<RefCell<AppState>>, cr: &gtk::cairo::Context, width:
             .editor
             .update_display_transform(scale, offset_x, offset_y);
 
+        if !state.is_active && state.preferences.canvas_backdrop == CanvasBackdrop::Checkerboard {
+            draw_checkerboard(
+                cr,
+                offset_x,
+                offset_y,
+                img_width * scale,
+                img_height * scale,
+            );
+        }
+
         cr.save().expect("Failed to save cairo context");
         cr.translate(offset_x, offset_y);
         cr.scale(scale, scale);
@@ -100,12 +170,17 @@ fn draw_content(state: &Rc<RefCell<AppState>>, cr: &gtk::cairo::Context, width:
         cr.paint().expect("Failed to paint pixbuf");
         cr.restore().expect("Failed to restore cairo context");
 
-        if state.is_active && state.mode == CaptureMode::Selection {
+        if state.is_active {
+            // Markup drawn while selecting, dimmed along with the rest outside
+            // the selection
+            if state.mode == CaptureMode::Selection {
+                state.editor.draw_annotations(cr);
+            }
             draw_selection_overlay(&state, cr, da_width, da_height);
         }
 
         if state.is_crop_mode {
-            draw_crop_overlay(&state, cr, da_width, da_height, scale);
+            draw_crop_overlay(&state, cr, &chrome, da_width, da_height, scale);
         }
 
         if state.editor.current_tool() == crate::editor::EditorTool::Rectangle
@@ -114,12 +189,64 @@ fn draw_content(state: &Rc<RefCell<AppState>>, cr: &gtk::cairo::Context, width:
             draw_rectangle_preview(&state, cr, scale);
         }
 
+        if state.editor.current_tool() == crate::editor::EditorTool::Redact
+            && state.editor.tool_state.is_drawing
+        {
+            draw_redact_preview(&state, cr, scale);
+        }
+
         if !state.is_active {
             state.editor.draw_annotations(cr);
         }
 
-        draw_pending_text_cursor(&state, cr);
+        draw_pending_text_cursor(&state, cr, &chrome);
+
+        if !state.is_active {
+            draw_color_hint(&state, cr, &chrome, da_width);
+        } else {
+            draw_overlay_hints(&state, cr, da_width);
+        }
+    }
+
+    if let Some(alpha) = state.capture_flash {
+        cr.set_source_rgba(1.0, 1.0, 1.0, alpha);
+        cr.paint().expect("Failed to paint capture flash");
+    }
+}
+
+fn draw_checkerboard(cr: &gtk::cairo::Context, x: f64, y: f64, width: f64, height: f64) {
+    let tile = match gtk::cairo::ImageSurface::create(
+        gtk::cairo::Format::Rgb24,
+        CHECKER_SIZE * 2,
+        CHECKER_SIZE * 2,
+    ) {
+        Ok(tile) => tile,
+        Err(_) => return,
+    };
+    {
+        let tile_cr = match gtk::cairo::Context::new(&tile) {
+            Ok(tile_cr) => tile_cr,
+            Err(_) => return,
+        };
+        let size = CHECKER_SIZE as f64;
+        tile_cr.set_source_rgb(0.8, 0.8, 0.8);
+        let _ = tile_cr.paint();
+        tile_cr.set_source_rgb(0.6, 0.6, 0.6);
+        tile_cr.rectangle(0.0, 0.0, size, size);
+        tile_cr.rectangle(size, size, size, size);
+        let _ = tile_cr.fill();
     }
+
+    let pattern = gtk::cairo::SurfacePattern::create(&tile);
+    pattern.set_extend(gtk::cairo::Extend::Repeat);
+    // Squares start at the image corner, not the widget's
+    pattern.set_matrix(gtk::cairo::Matrix::new(1.0, 0.0, 0.0, 1.0, -x, -y));
+
+    cr.save().expect("Failed to save cairo context");
+    let _ = cr.set_source(&pattern);
+    cr.rectangle(x, y, width, height);
+    let _ = cr.fill();
+    cr.restore().expect("Failed to restore cairo context");
 }
 
 fn draw_selection_overlay(
@@ -156,6 +283,7 @@ fn draw_selection_overlay(
 fn draw_crop_overlay(
     state: &AppState,
     cr: &gtk::cairo::Context,
+    chrome: &CanvasChrome,
     da_width: f64,
     da_height: f64,
     scale: f64,
@@ -165,7 +293,8 @@ fn draw_crop_overlay(
         let dw = w * scale;
         let dh = h * scale;
 
-        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+        let (r, g, b, a) = chrome.dim;
+        cr.set_source_rgba(r, g, b, a);
 
         cr.rectangle(0.0, 0.0, da_width, dy);
 
@@ -176,13 +305,64 @@ fn draw_crop_overlay(
         cr.rectangle(dx + dw, dy, da_width - (dx + dw), dh);
         let _ = cr.fill();
 
-        cr.set_source_rgb(1.0, 1.0, 1.0);
+        let (r, g, b) = chrome.foreground;
+        cr.set_source_rgb(r, g, b);
         cr.set_line_width(2.0);
         cr.rectangle(dx, dy, dw, dh);
         let _ = cr.stroke();
+
+        let (br, bg, bb, ba) = chrome.hint_background;
+        let half = CROP_HANDLE_SIZE / 2.0;
+        for handle in CropHandle::RESIZE {
+            let (hx, hy) = handle.position((dx, dy, dw, dh));
+            cr.rectangle(hx - half, hy - half, CROP_HANDLE_SIZE, CROP_HANDLE_SIZE);
+        }
+        cr.set_source_rgb(r, g, b);
+        let _ = cr.fill_preserve();
+        cr.set_source_rgba(br, bg, bb, ba);
+        cr.set_line_width(1.0);
+        let _ = cr.stroke();
+
+        draw_crop_size(cr, chrome, (dx, dy, dw, dh), w, h, da_height);
     }
 }
 
+/// Label with the crop size in image pixels, below the crop or inside it
+/// when there is no room
+fn draw_crop_size(
+    cr: &gtk::cairo::Context,
+    chrome: &CanvasChrome,
+    (dx, dy, dw, dh): (f64, f64, f64, f64),
+    w: f64,
+    h: f64,
+    da_height: f64,
+) {
+    let layout = pangocairo::functions::create_layout(cr);
+    layout.set_text(&format!("{} × {}", w.round(), h.round()));
+    let (text_width, text_height) = layout.pixel_size();
+
+    let padding = 4.0;
+    let width = text_width as f64 + padding * 2.0;
+    let height = text_height as f64 + padding * 2.0;
+    let x = dx + (dw - width) / 2.0;
+    let below = dy + dh + CROP_HANDLE_SIZE;
+    let y = if below + height <= da_height {
+        below
+    } else {
+        dy + dh - height - CROP_HANDLE_SIZE
+    };
+
+    let (r, g, b, a) = chrome.hint_background;
+    cr.set_source_rgba(r, g, b, a);
+    cr.rectangle(x, y, width, height);
+    let _ = cr.fill();
+
+    let (r, g, b) = chrome.foreground;
+    cr.set_source_rgb(r, g, b);
+    cr.move_to(x + padding, y + padding);
+    pangocairo::functions::show_layout(cr, &layout);
+}
+
 fn draw_rectangle_preview(state: &AppState, cr: &gtk::cairo::Context, scale: f64) {
     if let Some((x, y, w, h)) = state.editor.tool_state.get_drag_rect() {
         let (dx, dy) = state.editor.image_to_display_coords(x, y);
@@ -202,13 +382,255 @@ fn draw_rectangle_preview(state: &AppState, cr: &gtk::cairo::Context, scale: f64
     }
 }
 
-fn draw_pending_text_cursor(state: &AppState, cr: &gtk::cairo::Context) {
-    if let Some(ref pending) = state.editor.pending_text {
-        let (dx, dy) = state.editor.image_to_display_coords(pending.x, pending.y);
+/// The area about to be redacted, dimmed and outlined
+fn draw_redact_preview(state: &AppState, cr: &gtk::cairo::Context, scale: f64) {
+    if let Some((x, y, w, h)) = state.editor.tool_state.get_drag_rect() {
+        let (dx, dy) = state.editor.image_to_display_coords(x, y);
+        cr.rectangle(dx, dy, w * scale, h * scale);
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.6);
+        let _ = cr.fill_preserve();
         cr.set_source_rgba(1.0, 1.0, 1.0, 0.8);
-        cr.set_line_width(2.0);
-        cr.move_to(dx, dy - 20.0);
-        cr.line_to(dx, dy + 5.0);
+        cr.set_line_width(1.0);
+        cr.set_dash(&[4.0, 4.0], 0.0);
         let _ = cr.stroke();
+        cr.set_dash(&[], 0.0);
+    }
+}
+
+/// `pixbuf` with `annotations` on it, fitted into `width` x `height`, for the
+/// undo history's previews
+pub fn draw_history_preview(
+    cr: &gtk::cairo::Context,
+    pixbuf: &gtk::gdk_pixbuf::Pixbuf,
+    annotations: &[Annotation],
+    width: i32,
+    height: i32,
+) {
+    let img_width = pixbuf.width() as f64;
+    let img_height = pixbuf.height() as f64;
+    let scale = (width as f64 / img_width).min(height as f64 / img_height);
+    let offset_x = (width as f64 - img_width * scale) / 2.0;
+    let offset_y = (height as f64 - img_height * scale) / 2.0;
+
+    cr.save().expect("Failed to save cairo context");
+    cr.translate(offset_x, offset_y);
+    cr.scale(scale, scale);
+    cr.set_source_pixbuf(pixbuf, 0.0, 0.0);
+    cr.paint().expect("Failed to paint pixbuf");
+    cr.restore().expect("Failed to restore cairo context");
+
+    for annotation in annotations {
+        annotation.draw(cr, scale, offset_x, offset_y);
+    }
+}
+
+fn draw_color_hint(
+    state: &AppState,
+    cr: &gtk::cairo::Context,
+    chrome: &CanvasChrome,
+    da_width: f64,
+) {
+    let (name, color) = match state.color_hint {
+        Some(ref hint) => hint,
+        None => return,
+    };
+
+    let layout = pangocairo::functions::create_layout(cr);
+    layout.set_text(name);
+    let (text_width, text_height) = layout.pixel_size();
+
+    let padding = 10.0;
+    let swatch = text_height as f64;
+    let width = swatch + padding * 3.0 + text_width as f64;
+    let height = text_height as f64 + padding * 2.0;
+    let x = (da_width - width) / 2.0;
+    let y = 24.0;
+
+    cr.new_sub_path();
+    cr.arc(
+        x + width - height / 2.0,
+        y + height / 2.0,
+        height / 2.0,
+        -std::f64::consts::FRAC_PI_2,
+        std::f64::consts::FRAC_PI_2,
+    );
+    cr.arc(
+        x + height / 2.0,
+        y + height / 2.0,
+        height / 2.0,
+        std::f64::consts::FRAC_PI_2,
+        3.0 * std::f64::consts::FRAC_PI_2,
+    );
+    cr.close_path();
+    let (r, g, b, a) = chrome.hint_background;
+    cr.set_source_rgba(r, g, b, a);
+    let _ = cr.fill();
+
+    cr.arc(
+        x + padding + swatch / 2.0,
+        y + height / 2.0,
+        swatch / 2.0,
+        0.0,
+        2.0 * std::f64::consts::PI,
+    );
+    cr.set_source_rgba(
+        color.red() as f64,
+        color.green() as f64,
+        color.blue() as f64,
+        color.alpha() as f64,
+    );
+    let _ = cr.fill_preserve();
+    let (r, g, b) = chrome.foreground;
+    cr.set_source_rgba(r, g, b, 0.8);
+    cr.set_line_width(1.0);
+    let _ = cr.stroke();
+
+    cr.set_source_rgb(r, g, b);
+    cr.move_to(x + padding * 2.0 + swatch, y + padding);
+    pangocairo::functions::show_layout(cr, &layout);
+}
+
+/// Keys for getting through the fullscreen overlay
+fn overlay_hints(state: &AppState) -> Vec<String> {
+    let key = |action: Action, fallback: &str| {
+        state
+            .shortcuts
+            .get_key_label(action)
+            .unwrap_or_else(|| fallback.to_string())
+    };
+    let confirm = format!("{} confirm", key(Action::Confirm, "Enter"));
+    let cancel = format!("{} cancel", key(Action::Cancel, "Esc"));
+
+    match state.mode {
+        CaptureMode::Window => vec!["Click a window".to_string(), cancel],
+        _ if state.selection.is_none() => vec!["Drag to select".to_string(), cancel],
+        _ if state.selection_drag_point.is_some() => {
+            vec!["Hold Space to move".to_string(), cancel]
+        }
+        _ => vec![confirm, cancel, "Arrows move".to_string()],
+    }
+}
+
+/// Hint bar along the top of the capture overlay, faint so it does not get
+/// in the way of the screenshot under it
+fn draw_overlay_hints(state: &AppState, cr: &gtk::cairo::Context, da_width: f64) {
+    let layout = pangocairo::functions::create_layout(cr);
+    layout.set_text(&overlay_hints(state).join("  ·  "));
+    let (text_width, text_height) = layout.pixel_size();
+
+    let padding = 10.0;
+    let width = text_width as f64 + padding * 2.0;
+    let height = text_height as f64 + padding;
+    let x = (da_width - width) / 2.0;
+    let y = 16.0;
+
+    cr.new_sub_path();
+    cr.arc(
+        x + width - height / 2.0,
+        y + height / 2.0,
+        height / 2.0,
+        -std::f64::consts::FRAC_PI_2,
+        std::f64::consts::FRAC_PI_2,
+    );
+    cr.arc(
+        x + height / 2.0,
+        y + height / 2.0,
+        height / 2.0,
+        std::f64::consts::FRAC_PI_2,
+        3.0 * std::f64::consts::FRAC_PI_2,
+    );
+    cr.close_path();
+    cr.set_source_rgba(0.0, 0.0, 0.0, 0.45);
+    let _ = cr.fill();
+
+    cr.set_source_rgba(1.0, 1.0, 1.0, 0.85);
+    cr.move_to(x + padding, y + padding / 2.0);
+    pangocairo::functions::show_layout(cr, &layout);
+}
+
+fn draw_pending_text_cursor(state: &AppState, cr: &gtk::cairo::Context, chrome: &CanvasChrome) {
+    let (r, g, b) = chrome.foreground;
+    let preview = state.editor.pending_text_preview();
+    let has_preview = preview.is_some();
+    if let Some(preview) = preview {
+        Annotation::Text(preview).draw(
+            cr,
+            state.editor.display_scale,
+            state.editor.display_offset_x,
+            state.editor.display_offset_y,
+        );
+    }
+
+    if state.text_on_canvas {
+        if let Some((x, y, height)) = state.editor.pending_text_caret() {
+            if state.text_caret_visible {
+                let (dx, dy) = state.editor.image_to_display_coords(x, y);
+                cr.set_source_rgba(r, g, b, 0.9);
+                cr.set_line_width(2.0);
+                cr.move_to(dx, dy);
+                cr.line_to(dx, dy + height * state.editor.display_scale);
+                let _ = cr.stroke();
+            }
+        }
+    } else if !has_preview {
+        if let Some(ref pending) = state.editor.pending_text {
+            let (dx, dy) = state.editor.image_to_display_coords(pending.x, pending.y);
+            cr.set_source_rgba(r, g, b, 0.8);
+            cr.set_line_width(2.0);
+            cr.move_to(dx, dy - 20.0);
+            cr.line_to(dx, dy + 5.0);
+            let _ = cr.stroke();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::golden::{check_golden, rasterize};
+    use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+
+    const WIDTH: i32 = 160;
+    const HEIGHT: i32 = 120;
+
+    /// State holding a flat blue capture; the dark backdrop keeps the chrome
+    /// independent of the desktop style
+    fn state_with_capture() -> Rc<RefCell<AppState>> {
+        let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, WIDTH, HEIGHT).unwrap();
+        pixbuf.fill(0x3584e4ff);
+
+        let mut state = AppState::new();
+        state.preferences.canvas_backdrop = CanvasBackdrop::Dark;
+        state.original_screenshot = Some(pixbuf.clone());
+        state.final_image = Some(pixbuf);
+        Rc::new(RefCell::new(state))
+    }
+
+    fn render(state: &Rc<RefCell<AppState>>) -> image::RgbaImage {
+        rasterize(WIDTH, HEIGHT, |cr| draw_content(state, cr, WIDTH, HEIGHT)).unwrap()
+    }
+
+    #[test]
+    fn test_golden_selection_overlay() {
+        let state = state_with_capture();
+        {
+            let mut s = state.borrow_mut();
+            s.is_active = true;
+            s.start_selection(20.0, 20.0);
+            s.update_selection(120.0, 90.0);
+        }
+        check_golden("selection_overlay", &render(&state)).unwrap();
+    }
+
+    #[test]
+    fn test_golden_crop_overlay() {
+        let state = state_with_capture();
+        {
+            let mut s = state.borrow_mut();
+            s.is_crop_mode = true;
+            s.editor.tool_state.start_drag(30.0, 20.0);
+            s.editor.tool_state.update_drag(130.0, 100.0);
+        }
+        check_golden("crop_overlay", &render(&state)).unwrap();
     }
 }
@@ -0,0 +1,73 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use log::debug;
+use std::cell::{Cell, RefCell};
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+
+use crate::app::AppState;
+
+/// How long the white flash takes to fade out
+const FLASH_DURATION_US: i64 = 250_000;
+
+/// Opacity the flash starts at
+const FLASH_PEAK: f64 = 0.8;
+
+/// Confirm that a capture fired with a shutter sound and a flash over the
+/// canvas, unless turned off in the preferences
+pub fn capture_feedback(state: &Rc<RefCell<AppState>>, drawing_area: &gtk::DrawingArea) {
+    if !state.borrow().preferences.capture_feedback {
+        return;
+    }
+
+    play_shutter_sound();
+    flash(state, drawing_area);
+}
+
+/// Play the theme's camera shutter through libcanberra, without waiting for it
+fn play_shutter_sound() {
+    std::thread::spawn(|| {
+        let result = Command::new("canberra-gtk-play")
+            .args(["--id=screen-capture", "--description=Screenshot taken"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match result {
+            Ok(status) if !status.success() => {
+                debug!("Shutter sound not played: {}", status);
+            }
+            Err(e) => debug!("canberra-gtk-play unavailable: {}", e),
+            Ok(_) => {}
+        }
+    });
+}
+
+/// Fade a white wash over the canvas, one step per frame
+fn flash(state: &Rc<RefCell<AppState>>, drawing_area: &gtk::DrawingArea) {
+    state.borrow_mut().capture_flash = Some(FLASH_PEAK);
+    drawing_area.queue_draw();
+
+    let start = Cell::new(None);
+    drawing_area.add_tick_callback({
+        let state = state.clone();
+        move |widget, clock| {
+            let now = clock.frame_time();
+            let started = start.get().unwrap_or(now);
+            start.set(Some(started));
+            let elapsed = now - started;
+            let remaining = 1.0 - elapsed as f64 / FLASH_DURATION_US as f64;
+
+            let done = remaining <= 0.0;
+            state.borrow_mut().capture_flash = (!done).then_some(FLASH_PEAK * remaining);
+            widget.queue_draw();
+
+            if done {
+                gtk::glib::ControlFlow::Break
+            } else {
+                gtk::glib::ControlFlow::Continue
+            }
+        }
+    });
+}
@@ -1,28 +1,97 @@
 use gtk::glib;
 use gtk4 as gtk;
 use libadwaita as adw;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
+use adw::prelude::*;
 use gtk::gio;
 use gtk::prelude::*;
 use gtk::{EventControllerKey, GestureClick, GestureDrag};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::path::Path;
 use std::rc::Rc;
 use std::time::Duration;
-use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::app::config::Action;
-use crate::app::{AppState, CaptureMode};
-use crate::capture::capture_primary_monitor;
+use crate::app::{
+    AppState, CaptureMode, InvocationSource, LastExport, QuickCapture, ToolbarPosition,
+    SELECTION_PRESETS,
+};
+use crate::capture::desktop::DesktopSession;
+use crate::capture::file_metadata::read_file_metadata;
+use crate::capture::metadata::{CaptureMetadata, CaptureSource, DEFAULT_FILENAME_TEMPLATE};
+use crate::capture::portal::{load_portal_screenshot, request_interactive_screenshot};
+use crate::capture::recording::{
+    default_output_path, format_elapsed, list_microphones, recording_available, trim_recording,
+    AudioTrack, Recording, RecordingArea, RecordingAudio, RecordingFormat, RecordingLimit,
+    RecordingLimits,
+};
+use crate::capture::tools::{missing_screen_capture_tools, portal_available, tool_description};
+use crate::capture::window::{
+    capture_window, list_capturable_windows, locate_window, occluding_windows, WindowInfo,
+};
+use crate::capture::window_events::WindowWatcher;
+use crate::capture::{
+    capture_all_monitors, capture_monitor, capture_primary_monitor, set_tonemap_operator,
+    MonitorTarget, TonemapOperator,
+};
 use crate::editor::{
-    pick_color_from_pixbuf, Annotation, ClipboardManager, EditorTool, FreeDrawAnnotation,
-    RectangleAnnotation,
+    diff_images, palette_colors, pick_color_from_pixbuf, save_templates, Annotation,
+    ClipboardManager, CropHandle, EditorTool, FreeDrawAnnotation, PalettePreset,
+    RectangleAnnotation, RedactStyle, CROP_HANDLE_RADIUS, DEFAULT_DIFF_THRESHOLD,
+    SIMPLIFY_TOLERANCE,
 };
+use crate::export::{
+    alt_text_from_ocr, image_snippet, ChatKind, ChatSettings, ChatTarget, ForgeKind, ForgeSettings,
+    ForgeTarget, NextcloudSettings, NextcloudTarget, SnippetFormat,
+};
+use crate::export::{
+    auto_save, find_sensitive_text, find_urls, optimize_with_report, recognize_text,
+    restore_from_trash, tesseract_available, trash_capture, AnimationSettings, AutoSaveLayout,
+    ExportError, ExportMetadata, ExportOutcome, RgbaBuffer, Transfer, TranslationBackend,
+    TranslationSettings, UploadJob, UploadOptions,
+};
+use crate::ui::canvas_text::{begin_canvas_text, connect_canvas_text};
+use crate::ui::countdown::CountdownOverlay;
+use crate::ui::customize_toolbar::show_customize_toolbar_dialog;
 use crate::ui::dialogs::{show_about_dialog, show_window_selector, TextPopoverComponents};
-use crate::ui::drawing::DrawingComponents;
-use crate::ui::header::HeaderComponents;
+use crate::ui::drawing::{draw_history_preview, DrawingComponents};
+use crate::ui::feedback::capture_feedback;
+use crate::ui::header::{HeaderComponents, ALL_MONITORS, ASK_FOR_MONITOR};
+use crate::ui::layers::{connect_layers_panel, LayersPanel};
+use crate::ui::log_viewer::show_log_viewer;
+use crate::ui::monitor_picker::{display_monitors, monitor_label, monitor_target, MonitorPicker};
+use crate::ui::recording_indicator::RecordingIndicator;
+use crate::ui::recording_preview::create_recording_preview;
 use crate::ui::shortcuts;
-use crate::ui::toolbar::{CropToolbarComponents, SelectionToolbarComponents, ToolbarComponents};
+use crate::ui::theme::{Appearance, CanvasBackdrop, ColorVision};
+use crate::ui::toolbar::{
+    set_toolbar_position, CropToolbarComponents, RecordingToolbarComponents,
+    SelectionToolbarComponents, ToolbarComponents,
+};
+use crate::ui::DEFAULT_WINDOW_SIZE;
+
+/// Distance from the floating toolbar, in pixels, at which it gets out of
+/// the way of drawing
+const TOOLBAR_FADE_MARGIN: f32 = 48.0;
+
+/// Widget pixels Shift+arrow moves the selection by
+const SELECTION_NUDGE_STEP_LARGE: f64 = 10.0;
+
+/// Longest wait for the compositor to take a hidden window off screen
+const HIDE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Id of the desktop notification left by a quick capture, replaced by the next
+const QUICK_CAPTURE_NOTIFICATION: &str = "quick-capture";
+
+/// How often the upload banner's percentage is updated
+const UPLOAD_PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Language translations go into until another is chosen
+const DEFAULT_TRANSLATION_LANGUAGE: &str = "en";
+
+/// Longest list of findings the audit warning shows
+const AUDIT_MAX_SHOWN: usize = 8;
 
 #[derive(Clone)]
 pub struct UiComponents {
@@ -31,8 +100,14 @@ pub struct UiComponents {
     pub toolbar: ToolbarComponents,
     pub crop_toolbar: CropToolbarComponents,
     pub selection_toolbar: SelectionToolbarComponents,
+    pub recording_toolbar: RecordingToolbarComponents,
     pub drawing: DrawingComponents,
     pub text_popover: TextPopoverComponents,
+    pub layers: LayersPanel,
+    pub toast_overlay: adw::ToastOverlay,
+    pub upload_banner: adw::Banner,
+    pub countdown: CountdownOverlay,
+    pub recording_indicator: RecordingIndicator,
 }
 
 // Helper functions for actions
@@ -40,46 +115,255 @@ fn perform_copy(state: &Rc<RefCell<AppState>>, window: &impl IsA<gtk::Widget>) {
     let s = state.borrow();
     if let Some(ref pixbuf) = s.final_image {
         let clipboard_manager = ClipboardManager::from_widget(window);
-        if clipboard_manager.copy_image(pixbuf).is_ok() {
-            info!("Image copied to clipboard");
+        match clipboard_manager.copy_image(pixbuf, s.upload_url.as_deref()) {
+            Ok(()) => info!("Image copied to clipboard"),
+            Err(e) => error!("{}", e),
         }
     }
 }
 
 fn perform_undo(state: &Rc<RefCell<AppState>>, drawing_area: &gtk::DrawingArea) {
     let mut s = state.borrow_mut();
-    if s.editor.undo() {
+    if s.undo() {
+        drop(s);
+        drawing_area.queue_draw();
+    }
+}
+
+fn perform_redo(state: &Rc<RefCell<AppState>>, drawing_area: &gtk::DrawingArea) {
+    let mut s = state.borrow_mut();
+    if s.redo() {
         drop(s);
         drawing_area.queue_draw();
     }
 }
 
-fn perform_save(state: Rc<RefCell<AppState>>, window: impl IsA<gtk::Window> + Clone + 'static) {
+fn perform_save(
+    state: Rc<RefCell<AppState>>,
+    window: impl IsA<gtk::Window> + Clone + 'static,
+    toast_overlay: adw::ToastOverlay,
+) {
     glib::spawn_future_local(async move {
         let dialog = gtk::FileDialog::new();
-        if let Ok(folder) = dialog.select_folder_future(Some(&window)).await {
-            if let Some(folder_path) = folder.path() {
-                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH);
-                let value_in_secs_timestamp = match timestamp {
-                    Ok(dur) => dur.as_secs(),
-                    Err(_) => 0,
-                };
+        let folder = match dialog.select_folder_future(Some(&window)).await {
+            Ok(folder) => folder.path(),
+            Err(_) => None,
+        };
+        if let Some(folder) = folder {
+            let optimize = state.borrow().preferences.optimize_png;
+            save_to_folder(&state, folder, optimize, &toast_overlay).await;
+        }
+    });
+}
 
-                let mut path = folder_path;
-                path.push(format!("screenshot_{}.png", value_in_secs_timestamp));
-                let s = state.borrow();
-                if let Some(ref pixbuf) = s.final_image {
-                    if let Err(e) = pixbuf.savev(path.to_str().unwrap(), "png", &[]) {
-                        error!("Failed to save image: {}", e);
-                    } else {
-                        info!("Image saved to {:?}", path);
-                    }
+/// Save the current image into `folder` under its suggested name, and
+/// remember the folder for Export Again
+async fn save_to_folder(
+    state: &Rc<RefCell<AppState>>,
+    folder: std::path::PathBuf,
+    optimize: bool,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let (pixbuf, path) = {
+        let s = state.borrow();
+        match s.final_image {
+            Some(ref pixbuf) => (pixbuf.clone(), folder.join(s.suggested_file_name())),
+            None => return,
+        }
+    };
+    if let Some(parent) = path.parent() {
+        if let Err(e) = std::fs::create_dir_all(parent) {
+            error!("Failed to create {:?}: {}", parent, e);
+        }
+    }
+    {
+        let mut s = state.borrow_mut();
+        s.last_export = Some(LastExport::Folder { folder, optimize });
+        s.saved_path = Some(path.clone());
+    }
+
+    if optimize {
+        save_optimized(&pixbuf, path, toast_overlay).await;
+    } else if let Err(e) = pixbuf.savev(&path, "png", &[]) {
+        error!("Failed to save image: {}", e);
+        toast_overlay.add_toast(adw::Toast::new("Failed to save image"));
+    } else {
+        info!("Image saved to {:?}", path);
+        let title = format!("Saved to {}", path.display());
+        toast_overlay.add_toast(snippet_toast(state, toast_overlay, &title));
+    }
+}
+
+/// Where snippets link the current image: its upload, or else the file it
+/// was saved to
+fn snippet_url(state: &AppState) -> Option<String> {
+    if let Some(ref url) = state.upload_url {
+        return Some(url.clone());
+    }
+    let path = state
+        .saved_path
+        .as_ref()
+        .or(state.auto_saved_path.as_ref())?;
+    glib::filename_to_uri(path, None).ok().map(String::from)
+}
+
+/// Toast titled `title` offering to copy a snippet of the image
+fn snippet_toast(
+    state: &Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+    title: &str,
+) -> adw::Toast {
+    let toast = adw::Toast::new(title);
+    toast.set_button_label(Some("Copy Snippet"));
+    toast.connect_button_clicked({
+        let state = state.clone();
+        let toast_overlay = toast_overlay.clone();
+        move |_| copy_snippet(&state, &toast_overlay)
+    });
+    toast
+}
+
+/// Copy a Markdown or HTML snippet embedding the uploaded or saved image,
+/// with the text recognized in it as alt text if that is turned on
+fn copy_snippet(state: &Rc<RefCell<AppState>>, toast_overlay: &adw::ToastOverlay) {
+    let (url, format, png) = {
+        let s = state.borrow();
+        let url = match snippet_url(&s) {
+            Some(url) => url,
+            None => {
+                drop(s);
+                toast_overlay.add_toast(adw::Toast::new("Save or upload the image first"));
+                return;
+            }
+        };
+        let png = match s.final_image {
+            Some(ref pixbuf) if s.preferences.snippet_alt_text && tesseract_available() => {
+                pixbuf.save_to_bufferv("png", &[]).ok()
+            }
+            _ => None,
+        };
+        (url, s.preferences.snippet_format, png)
+    };
+
+    let toast_overlay = toast_overlay.clone();
+    glib::spawn_future_local(async move {
+        let alt = match png {
+            Some(png) => match gio::spawn_blocking(move || recognize_text(&png)).await {
+                Ok(Ok(text)) => alt_text_from_ocr(&text),
+                Ok(Err(e)) => {
+                    warn!("No alt text for the snippet: {}", e);
+                    String::new()
+                }
+                Err(_) => {
+                    error!("Text recognition for the snippet panicked");
+                    String::new()
                 }
+            },
+            None => String::new(),
+        };
+        let snippet = image_snippet(format, &url, &alt);
+        debug!("Copying snippet {}", snippet);
+        toast_overlay.clipboard().set_text(&snippet);
+        toast_overlay.add_toast(adw::Toast::new(&format!(
+            "Copied {} snippet",
+            format.label()
+        )));
+    });
+}
+
+/// Export the current image again the way it was last exported, so edits
+/// made since reach the same place. Asks for a folder when it hasn't been
+/// exported yet.
+fn perform_export_again(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let last_export = {
+        let s = state.borrow();
+        if s.is_active || s.final_image.is_none() {
+            return;
+        }
+        s.last_export.clone()
+    };
+    debug!("Exporting again: {:?}", last_export);
+    match last_export {
+        Some(LastExport::Folder { folder, optimize }) => {
+            let state = state.clone();
+            let toast_overlay = components.toast_overlay.clone();
+            glib::spawn_future_local(async move {
+                save_to_folder(&state, folder, optimize, &toast_overlay).await;
+            });
+        }
+        Some(LastExport::Target(id)) => perform_export(state.clone(), components.clone(), id),
+        None => perform_save(
+            state.clone(),
+            components.window.clone(),
+            components.toast_overlay.clone(),
+        ),
+    }
+}
+
+/// Post the image to the chat, asking where first if none is set up
+fn send_to_chat(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let configured = {
+        let s = state.borrow();
+        if s.is_active || s.final_image.is_none() {
+            return;
+        }
+        s.preferences.chat.is_some()
+    };
+    if configured {
+        perform_export(
+            state.clone(),
+            components.clone(),
+            ChatTarget::ID.to_string(),
+        );
+        return;
+    }
+
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        if ask_chat_settings(&state, &components.window).await {
+            register_chat_target(&state);
+            update_export_menu(&state, &components);
+            if state.borrow().preferences.chat.is_some() {
+                perform_export(
+                    state.clone(),
+                    components.clone(),
+                    ChatTarget::ID.to_string(),
+                );
             }
         }
     });
 }
 
+async fn save_optimized(
+    pixbuf: &gtk::gdk_pixbuf::Pixbuf,
+    path: std::path::PathBuf,
+    toast_overlay: &adw::ToastOverlay,
+) {
+    let buffer = RgbaBuffer::from_pixbuf(pixbuf);
+
+    let result = gio::spawn_blocking(move || {
+        let (data, report) = optimize_with_report(&buffer)?;
+        std::fs::write(&path, &data).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+        Ok::<_, String>((path, report))
+    })
+    .await;
+
+    match result {
+        Ok(Ok((path, report))) => {
+            info!("Image saved to {:?} ({})", path, report.summary());
+            toast_overlay.add_toast(adw::Toast::new(&report.summary()));
+        }
+        Ok(Err(e)) => {
+            error!("Failed to save optimized image: {}", e);
+            toast_overlay.add_toast(adw::Toast::new("Failed to save image"));
+        }
+        Err(_) => {
+            error!("PNG optimization task panicked");
+        }
+    }
+}
+
 pub fn connect_undo_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
     components.toolbar.undo_btn.connect_clicked({
         let state = state.clone();
@@ -88,560 +372,4645 @@ pub fn connect_undo_handler(state: &Rc<RefCell<AppState>>, components: &UiCompon
             perform_undo(&state, &drawing_area);
         }
     });
-}
 
-pub fn connect_copy_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    components.toolbar.copy_btn.connect_clicked({
+    components.toolbar.redo_btn.connect_clicked({
         let state = state.clone();
-        let window = components.window.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
         move |_| {
-            perform_copy(&state, &window);
+            perform_redo(&state, &drawing_area);
         }
     });
 }
 
-pub fn connect_save_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    components.toolbar.save_btn.connect_clicked({
+/// Size of the previews in the undo history, in display pixels
+const HISTORY_PREVIEW_SIZE: (i32, i32) = (64, 40);
+
+/// Row of the undo history for the state after its first `steps` entries,
+/// previewing it; the current state is marked rather than activatable
+fn history_row(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    title: &str,
+    steps: usize,
+    current: bool,
+) -> adw::ActionRow {
+    let (width, height) = HISTORY_PREVIEW_SIZE;
+    let preview = gtk::DrawingArea::builder()
+        .content_width(width)
+        .content_height(height)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    preview.set_draw_func({
         let state = state.clone();
-        let window = components.window.clone();
-        move |_| {
-            perform_save(state.clone(), window.clone());
+        move |_, cr, width, height| {
+            if let Some((pixbuf, annotations)) = state.borrow().history_preview(steps) {
+                draw_history_preview(cr, &pixbuf, &annotations, width, height);
+            }
         }
     });
-}
 
-pub fn connect_drag_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    let drag = GestureDrag::new();
-    drag.set_button(1); // Left mouse button
+    let row = adw::ActionRow::builder()
+        .title(title)
+        .activatable(!current)
+        .build();
+    row.add_prefix(&preview);
+    if current {
+        row.add_suffix(&gtk::Image::from_icon_name("object-select-symbolic"));
+        return row;
+    }
 
-    drag.connect_drag_begin({
+    row.connect_activated({
         let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        move |gesture, x, y| {
-            handle_drag_begin(&state, gesture, x, y);
-            drawing_area.queue_draw();
+        let components = components.clone();
+        move |_| {
+            if let Some(popover) = components.toolbar.history_btn.popover() {
+                popover.popdown();
+            }
+            let changed = state.borrow_mut().undo_to(steps);
+            if changed {
+                debug!("Undid to step {}", steps);
+                components.drawing.drawing_area.queue_draw();
+            }
         }
     });
+    row
+}
 
-    drag.connect_drag_update({
+pub fn connect_history_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let popover = match components.toolbar.history_btn.popover() {
+        Some(popover) => popover,
+        None => return,
+    };
+
+    popover.connect_show({
         let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        move |gesture, x, y| {
-            handle_drag_update(&state, gesture, x, y);
-            drawing_area.queue_draw();
+        let components = components.clone();
+        move |_| {
+            let list = components.toolbar.history_box.clone();
+            list.remove_all();
+            if state.borrow().final_image.is_none() {
+                return;
+            }
+
+            let history = state.borrow().history();
+            list.append(&history_row(
+                &state,
+                &components,
+                "Original Image",
+                0,
+                history.is_empty(),
+            ));
+            for (index, label) in history.iter().enumerate() {
+                let current = index + 1 == history.len();
+                list.append(&history_row(&state, &components, label, index + 1, current));
+            }
         }
     });
+}
 
-    drag.connect_drag_end({
+pub fn connect_copy_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.toolbar.copy_btn.connect_clicked({
         let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        move |gesture, x, y| {
-            handle_drag_end(&state, gesture, x, y);
-            drawing_area.queue_draw();
+        let window = components.window.clone();
+        move |_| {
+            perform_copy(&state, &window);
         }
     });
-
-    components.drawing.drawing_area.add_controller(drag);
 }
 
-fn handle_drag_begin(
-    state: &Rc<RefCell<AppState>>,
-    _gesture: &GestureDrag,
-    start_x: f64,
-    start_y: f64,
-) {
-    let mut s = state.borrow_mut();
-    if s.is_active && s.mode == CaptureMode::Selection {
-        s.start_selection(start_x, start_y);
-    } else if s.final_image.is_some() {
-        if s.editor.pointer_drag_start(start_x, start_y) {
+/// Clear the editor and move the auto-saved file, if any, to the trash. The
+/// toast's undo brings both back as long as no new capture was taken.
+fn perform_discard(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let path = {
+        let s = state.borrow();
+        if s.final_image.is_none() {
             return;
         }
+        s.auto_saved_path.clone()
+    };
 
-        let (img_x, img_y) = s.editor.display_to_image_coords(start_x, start_y);
-
-        match s.editor.current_tool() {
-            EditorTool::Pencil => {
-                s.editor.tool_state.start_drag(img_x, img_y);
-                let mut free_draw = FreeDrawAnnotation::new(
-                    s.editor.tool_state.color,
-                    s.editor.tool_state.line_width,
-                );
-                free_draw.add_point(img_x, img_y);
-                s.editor
-                    .annotations
-                    .set_current(Some(Annotation::FreeDraw(free_draw)));
+    // Trash first, so a failure leaves the capture in the editor
+    let trashed = match path {
+        Some(ref path) => match trash_capture(path) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to move capture to trash: {}", e);
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("Failed to move capture to trash"));
+                return;
             }
-            EditorTool::Rectangle => {
-                s.editor.tool_state.start_drag(img_x, img_y);
-            }
-            EditorTool::Crop => {
-                // For crop, reset any existing selection when starting a new one
-                s.editor.tool_state.reset_drag();
-                s.editor.tool_state.start_drag(img_x, img_y);
-            }
-            _ => {}
-        }
-    }
-}
+        },
+        None => false,
+    };
 
-fn handle_drag_update(
-    state: &Rc<RefCell<AppState>>,
-    gesture: &GestureDrag,
-    offset_x: f64,
-    offset_y: f64,
-) {
     let mut s = state.borrow_mut();
-
-    let (start_x, start_y) = match gesture.start_point() {
-        Some(p) => p,
+    let pixbuf = match s.final_image.take() {
+        Some(pixbuf) => pixbuf,
         None => return,
     };
-    let current_x = start_x + offset_x;
-    let current_y = start_y + offset_y;
-
-    if s.is_active && s.mode == CaptureMode::Selection {
-        s.update_selection(current_x, current_y);
-    } else if s.final_image.is_some() {
-        let (img_x, img_y) = s.editor.display_to_image_coords(current_x, current_y);
+    let metadata = s.capture_metadata.take();
+    let file_metadata = s.file_metadata.take();
+    let annotations = s.editor.annotations.clone();
+    s.auto_saved_path = None;
+    s.original_screenshot = None;
+    s.upload_url = None;
+    s.last_export = None;
+    s.saved_path = None;
+    s.editor.reset();
+    drop(s);
 
-        if s.editor.tool_state.is_dragging_annotation {
-            s.editor.pointer_drag_update(current_x, current_y);
-        } else if s.editor.tool_state.is_drawing {
-            s.editor.tool_state.update_drag(img_x, img_y);
+    components.toolbar.tools_box.set_visible(false);
+    components.drawing.placeholder_icon.set_visible(true);
+    components.drawing.drawing_area.queue_draw();
 
-            if s.editor.current_tool() == EditorTool::Pencil {
-                if let Some(Annotation::FreeDraw(ref draw)) =
-                    s.editor.annotations.current().cloned()
-                {
-                    let mut draw = draw.clone();
-                    draw.add_point(img_x, img_y);
-                    s.editor
-                        .annotations
-                        .set_current(Some(Annotation::FreeDraw(draw)));
+    let toast = adw::Toast::builder()
+        .title(if trashed {
+            "Capture moved to trash"
+        } else {
+            "Capture discarded"
+        })
+        .button_label("Undo")
+        .build();
+    toast.connect_button_clicked({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| {
+            if trashed {
+                if let Some(ref path) = path {
+                    if let Err(e) = restore_from_trash(path) {
+                        error!("Failed to restore capture: {}", e);
+                        components
+                            .toast_overlay
+                            .add_toast(adw::Toast::new("Failed to restore capture from trash"));
+                    }
                 }
             }
+
+            let mut s = state.borrow_mut();
+            if s.final_image.is_some() || s.is_active {
+                return;
+            }
+            s.original_screenshot = Some(pixbuf.clone());
+            s.final_image = Some(pixbuf.clone());
+            s.capture_metadata = metadata.clone();
+            s.file_metadata = file_metadata.clone();
+            s.auto_saved_path = path.clone();
+            s.editor.annotations = annotations.clone();
+            drop(s);
+
+            components.drawing.placeholder_icon.set_visible(false);
+            components.toolbar.tools_box.set_visible(true);
+            components.drawing.drawing_area.queue_draw();
         }
-    }
+    });
+    components.toast_overlay.add_toast(toast);
 }
 
-fn handle_drag_end(
-    state: &Rc<RefCell<AppState>>,
-    gesture: &GestureDrag,
-    offset_x: f64,
-    offset_y: f64,
-) {
-    let mut s = state.borrow_mut();
-    let (start_x, start_y) = match gesture.start_point() {
-        Some(p) => p,
-        None => return,
-    };
-    let current_x = start_x + offset_x;
-    let current_y = start_y + offset_y;
-
-    if s.is_active && s.mode == CaptureMode::Selection {
-        s.update_selection(current_x, current_y);
-    } else if s.final_image.is_some() {
-        if s.editor.tool_state.is_dragging_annotation {
-            s.editor.pointer_drag_end();
-        } else if s.editor.tool_state.is_drawing {
-            let tool = s.editor.current_tool();
+/// Capture `picked` again once raised, off the main loop as raising it can
+/// take a while, and load it into the editor
+fn raise_and_capture(state: &Rc<RefCell<AppState>>, components: &UiComponents, picked: WindowInfo) {
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        let result = match gio::spawn_blocking(move || capture_window(&picked)).await {
+            Ok(result) => result,
+            Err(_) => {
+                error!("Window capture task panicked");
+                return;
+            }
+        };
+        match result {
+            Ok(result) => {
+                info!("Captured window: {}", result.window_info.debug_info());
+                let mut s = state.borrow_mut();
+                s.final_image = Some(result.pixbuf);
+                s.upload_url = None;
+                s.last_export = None;
+                s.saved_path = None;
+                s.file_metadata = None;
+                let info = &result.window_info;
+                s.capture_metadata = Some(
+                    CaptureMetadata::new(CaptureSource::Window)
+                        .with_window(info)
+                        .with_region(info.x, info.y, info.width as i32, info.height as i32),
+                );
+                s.editor.reset();
+                s.auto_save_capture();
+                drop(s);
 
-            if tool == EditorTool::Pencil {
-                s.editor.tool_state.end_drag();
-                s.editor.annotations.commit_current();
-            } else if tool == EditorTool::Rectangle {
-                let drag_result = s.editor.tool_state.end_drag();
-                if let Some((start, end)) = drag_result {
-                    let color = s.editor.tool_state.color;
-                    let rect = RectangleAnnotation::new(
-                        start.0,
-                        start.1,
-                        (end.0 - start.0).abs(),
-                        (end.1 - start.1).abs(),
-                        color,
-                        3.0,
-                    );
-                    s.editor.annotations.add(Annotation::Rectangle(rect));
-                }
-            } else if tool == EditorTool::Crop {
-                // For crop, we keep the drag coordinates in ToolState but stop drawing
-                s.editor.tool_state.is_drawing = false;
+                components.drawing.drawing_area.queue_draw();
+            }
+            Err(e) => {
+                error!("Failed to capture window: {}", e);
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("Failed to capture window"));
             }
         }
-    }
+    });
 }
 
-pub fn connect_click_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    let click = GestureClick::new();
-    click.connect_pressed({
+pub fn connect_discard_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.toolbar.discard_btn.connect_clicked({
         let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        let text_popover = components.text_popover.text_popover.clone();
-        let text_entry = components.text_popover.text_entry.clone();
-        move |_gesture, _n_press, x, y| {
-            let mut s = state.borrow_mut();
-            if s.final_image.is_some() {
-                if s.editor.current_tool() == EditorTool::Text {
-                    let rect = gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1);
-                    text_popover.set_pointing_to(Some(&rect));
-                    text_popover.popup();
-                    text_entry.set_text("");
-                    text_entry.grab_focus();
+        let components = components.clone();
+        move |_| {
+            perform_discard(&state, &components);
+        }
+    });
+}
 
-                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
-                    s.editor.pending_text = Some(crate::editor::PendingText { x: img_x, y: img_y });
-                } else if s.editor.current_tool() == EditorTool::ColorPicker {
-                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
-                    if let Some(ref pixbuf) = s.final_image {
-                        if let Ok(picked) =
-                            pick_color_from_pixbuf(pixbuf, img_x as i32, img_y as i32)
-                        {
-                            s.editor.set_color(picked.color);
-                        }
-                    }
-                }
-            }
-            drawing_area.queue_draw();
+pub fn connect_save_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.toolbar.save_btn.connect_clicked({
+        let state = state.clone();
+        let window = components.window.clone();
+        let toast_overlay = components.toast_overlay.clone();
+        move |_| {
+            perform_save(state.clone(), window.clone(), toast_overlay.clone());
         }
     });
-    components.drawing.drawing_area.add_controller(click);
 }
 
-fn confirm_selection(
-    state: &mut AppState,
-    window: &adw::ApplicationWindow,
-    header_bar: &adw::HeaderBar,
-    tools_box: &gtk::Box,
-    crop_tools_box: &gtk::Box,
-) -> bool {
-    if state.apply_selection_crop() {
-        state.is_active = false;
-        state.selection = None;
-        window.unfullscreen();
-        header_bar.set_visible(true);
-        tools_box.set_visible(true);
-        crop_tools_box.set_visible(false);
-        return true;
+fn run_export(state: &Rc<RefCell<AppState>>, components: &UiComponents, id: &str) {
+    let s = state.borrow();
+    let pixbuf = match s.final_image.clone() {
+        Some(pixbuf) => pixbuf,
+        None => return,
+    };
+    let mut metadata =
+        ExportMetadata::for_pixbuf(&pixbuf).with_optimize(s.preferences.optimize_png);
+    if let Some(ref capture) = s.capture_metadata {
+        metadata = metadata.with_capture(capture, &s.filename_template);
     }
-    false
+
+    let upload_job = s.export_targets.upload_job(id, &pixbuf, &metadata);
+    let job = match upload_job {
+        Some(Ok(job)) => {
+            drop(s);
+            start_upload(state, components, id, pixbuf, job);
+            return;
+        }
+        Some(Err(e)) => Err(e),
+        None => s.export_targets.export_job(id, &pixbuf, &metadata),
+    };
+    drop(s);
+    let job = match job {
+        Ok(job) => job,
+        Err(e) => {
+            finish_export(state, &components.toast_overlay, id, &pixbuf, Err(e));
+            return;
+        }
+    };
+
+    // Scripts and OCR can take a while, so they run off the main loop
+    let state = state.clone();
+    let toast_overlay = components.toast_overlay.clone();
+    let id = id.to_string();
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(job)
+            .await
+            .unwrap_or_else(|_| Err(ExportError::Failed("The export panicked".to_string())));
+        finish_export(&state, &toast_overlay, &id, &pixbuf, result);
+    });
 }
 
-pub fn connect_crop_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    components.crop_toolbar.confirm_btn.connect_clicked({
+/// Send the image with an upload job, reporting how it went like any other
+/// export
+fn start_upload(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    id: &str,
+    pixbuf: gtk::gdk_pixbuf::Pixbuf,
+    job: UploadJob,
+) {
+    run_upload(state, components, job, {
         let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        let tools_box = components.toolbar.tools_box.clone();
-        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
-        move |_| {
-            let mut s = state.borrow_mut();
-            if s.apply_editor_crop() {
-                s.exit_crop_mode();
-                tools_box.set_visible(true);
-                crop_tools_box.set_visible(false);
-                drawing_area.queue_draw();
+        let toast_overlay = components.toast_overlay.clone();
+        let id = id.to_string();
+        move |result| finish_export(&state, &toast_overlay, &id, &pixbuf, result)
+    });
+}
+
+/// Run an upload job on a worker thread, its progress shown in the banner
+/// above the editor along with a button to cancel it, then hand `finish`
+/// the result
+fn run_upload(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    job: UploadJob,
+    finish: impl FnOnce(Result<ExportOutcome, ExportError>) + 'static,
+) {
+    let banner = components.upload_banner.clone();
+    if banner.is_revealed() {
+        components
+            .toast_overlay
+            .add_toast(adw::Toast::new("Wait for the current upload to finish"));
+        return;
+    }
+
+    let transfer = Transfer::new(state.borrow().preferences.upload);
+    banner.set_title("Uploading…");
+    banner.set_revealed(true);
+    let cancel_handler = banner.connect_button_clicked({
+        let transfer = transfer.clone();
+        move |banner| {
+            banner.set_title("Cancelling…");
+            transfer.cancel();
+        }
+    });
+    let progress = glib::timeout_add_local(UPLOAD_PROGRESS_INTERVAL, {
+        let banner = banner.clone();
+        let transfer = transfer.clone();
+        move || {
+            if let (Some(fraction), false) = (transfer.fraction(), transfer.is_cancelled()) {
+                banner.set_title(&format!("Uploading… {:.0}%", fraction * 100.0));
             }
+            glib::ControlFlow::Continue
         }
     });
 
-    components.crop_toolbar.cancel_btn.connect_clicked({
-        let state = state.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        let tools_box = components.toolbar.tools_box.clone();
-        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
-        move |_| {
-            let mut s = state.borrow_mut();
-            s.exit_crop_mode();
-            tools_box.set_visible(true);
-            crop_tools_box.set_visible(false);
-            drawing_area.queue_draw();
+    glib::spawn_future_local(async move {
+        let result = gio::spawn_blocking(move || job(&transfer))
+            .await
+            .unwrap_or_else(|_| Err(ExportError::Failed("The upload panicked".to_string())));
+        progress.remove();
+        banner.disconnect(cancel_handler);
+        banner.set_revealed(false);
+        finish(result);
+    });
+}
+
+/// Report how exporting `pixbuf` went. Its URL and the target are only
+/// remembered while it is still the image being edited, as an upload may
+/// finish after another capture.
+fn finish_export(
+    state: &Rc<RefCell<AppState>>,
+    toast_overlay: &adw::ToastOverlay,
+    id: &str,
+    pixbuf: &gtk::gdk_pixbuf::Pixbuf,
+    result: Result<ExportOutcome, ExportError>,
+) {
+    let outcome = match result {
+        Ok(outcome) => outcome,
+        Err(ExportError::Cancelled) => {
+            info!("Export '{}' cancelled", id);
+            toast_overlay.add_toast(adw::Toast::new(&ExportError::Cancelled.to_string()));
+            return;
+        }
+        Err(e) => {
+            error!("Export '{}' failed: {}", id, e);
+            toast_overlay.add_toast(adw::Toast::new(&e.to_string()));
+            return;
+        }
+    };
+    info!("Export '{}' finished: {}", id, outcome.summary);
+
+    let mut s = state.borrow_mut();
+    let current = s.final_image.as_ref() == Some(pixbuf);
+    if current {
+        if outcome.url.is_some() {
+            s.upload_url = outcome.url.clone();
+        }
+        s.last_export = Some(LastExport::Target(id.to_string()));
+    }
+    drop(s);
+
+    if let (true, Some(url)) = (outcome.copy_url, &outcome.url) {
+        toast_overlay.clipboard().set_text(url);
+    }
+    let toast = if current && outcome.url.is_some() {
+        snippet_toast(state, toast_overlay, &outcome.summary)
+    } else {
+        adw::Toast::new(&outcome.summary)
+    };
+    toast_overlay.add_toast(toast);
+}
+
+/// Export with target `id`, after reading the image for sensitive text if
+/// the audit is on. Findings are shown first, and exporting anyway is up to
+/// the user.
+fn perform_export(state: Rc<RefCell<AppState>>, components: UiComponents, id: String) {
+    let audit = state.borrow().preferences.audit_before_export && tesseract_available();
+    let png = match state.borrow().final_image {
+        Some(ref pixbuf) if audit => pixbuf.save_to_bufferv("png", &[]).ok(),
+        _ => None,
+    };
+    let png = match png {
+        Some(png) => png,
+        None => {
+            run_export(&state, &components, &id);
+            return;
+        }
+    };
+
+    glib::spawn_future_local(async move {
+        let result =
+            gio::spawn_blocking(move || recognize_text(&png).map(|t| find_sensitive_text(&t)))
+                .await;
+        let findings = match result {
+            Ok(Ok(findings)) => findings,
+            Ok(Err(e)) => {
+                warn!("Skipping the sensitive text check: {}", e);
+                Vec::new()
+            }
+            Err(_) => {
+                error!("Sensitive text check panicked");
+                Vec::new()
+            }
+        };
+        if findings.is_empty() {
+            run_export(&state, &components, &id);
+            return;
+        }
+        info!("Found {} possibly sensitive strings", findings.len());
+
+        let mut lines: Vec<String> = findings
+            .iter()
+            .take(AUDIT_MAX_SHOWN)
+            .map(|f| format!("{}: {}", f.kind.label(), f.text))
+            .collect();
+        if findings.len() > AUDIT_MAX_SHOWN {
+            lines.push(format!("and {} more", findings.len() - AUDIT_MAX_SHOWN));
+        }
+        let dialog = adw::AlertDialog::new(
+            Some("Sensitive Text Found"),
+            Some(&format!(
+                "The image still shows text that may be private:\n\n{}",
+                lines.join("\n")
+            )),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("export", "Export Anyway")]);
+        dialog.set_response_appearance("export", adw::ResponseAppearance::Destructive);
+        dialog.set_default_response(Some("cancel"));
+        dialog.set_close_response("cancel");
+
+        if dialog.choose_future(&components.window).await == "export" {
+            run_export(&state, &components, &id);
         }
     });
 }
 
-pub fn connect_selection_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    components.selection_toolbar.confirm_btn.connect_clicked({
+pub fn connect_export_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let action_export = gio::SimpleAction::new("export", Some(glib::VariantTy::STRING));
+    action_export.connect_activate({
         let state = state.clone();
-        let window = components.window.clone();
-        let header_bar = components.header.header_bar.clone();
-        let tools_box = components.toolbar.tools_box.clone();
-        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
-        let selection_tools_box = components.selection_toolbar.selection_tools_box.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        move |_| {
-            let mut s = state.borrow_mut();
-            if confirm_selection(
-                &mut s,
-                &window,
-                &header_bar,
-                &tools_box,
-                &crop_tools_box,
-            ) {
-                selection_tools_box.set_visible(false);
-                drop(s);
-                drawing_area.queue_draw();
+        let components = components.clone();
+        move |_, param| {
+            if let Some(id) = param.and_then(|p| p.get::<String>()) {
+                perform_export(state.clone(), components.clone(), id);
             }
         }
     });
+    components.window.add_action(&action_export);
 
-    components.selection_toolbar.cancel_btn.connect_clicked({
+    let action_export_again = gio::SimpleAction::new("export-again", None);
+    action_export_again.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            perform_export_again(&state, &components);
+        }
+    });
+    components.window.add_action(&action_export_again);
+
+    let action_copy_snippet = gio::SimpleAction::new("copy-snippet", None);
+    action_copy_snippet.connect_activate({
+        let state = state.clone();
+        let toast_overlay = components.toast_overlay.clone();
+        move |_, _| {
+            copy_snippet(&state, &toast_overlay);
+        }
+    });
+    components.window.add_action(&action_copy_snippet);
+
+    let action_forge_settings = gio::SimpleAction::new("forge-settings", None);
+    action_forge_settings.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            let state = state.clone();
+            let components = components.clone();
+            glib::spawn_future_local(async move {
+                if ask_forge_settings(&state, &components.window).await {
+                    register_forge_target(&state);
+                    update_export_menu(&state, &components);
+                }
+            });
+        }
+    });
+    components.window.add_action(&action_forge_settings);
+
+    let action_nextcloud_settings = gio::SimpleAction::new("nextcloud-settings", None);
+    action_nextcloud_settings.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            let state = state.clone();
+            let components = components.clone();
+            glib::spawn_future_local(async move {
+                if ask_nextcloud_settings(&state, &components.window).await {
+                    register_nextcloud_target(&state);
+                    update_export_menu(&state, &components);
+                }
+            });
+        }
+    });
+    components.window.add_action(&action_nextcloud_settings);
+
+    let action_chat_settings = gio::SimpleAction::new("chat-settings", None);
+    action_chat_settings.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            let state = state.clone();
+            let components = components.clone();
+            glib::spawn_future_local(async move {
+                if ask_chat_settings(&state, &components.window).await {
+                    register_chat_target(&state);
+                    update_export_menu(&state, &components);
+                }
+            });
+        }
+    });
+    components.window.add_action(&action_chat_settings);
+
+    let action_upload_settings = gio::SimpleAction::new("upload-settings", None);
+    action_upload_settings.connect_activate({
         let state = state.clone();
         let window = components.window.clone();
-        let header_bar = components.header.header_bar.clone();
-        let tools_box = components.toolbar.tools_box.clone();
-        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
-        let selection_tools_box = components.selection_toolbar.selection_tools_box.clone();
-        let placeholder_icon = components.drawing.placeholder_icon.clone();
-        let drawing_area = components.drawing.drawing_area.clone();
-        move |_| {
-            let mut s = state.borrow_mut();
-            s.exit_capture_mode();
-            window.unfullscreen();
-            header_bar.set_visible(true);
-            tools_box.set_visible(s.final_image.is_some());
-            crop_tools_box.set_visible(false);
-            selection_tools_box.set_visible(false);
-            if s.final_image.is_none() {
-                placeholder_icon.set_visible(true);
-            }
-            drop(s);
-            drawing_area.queue_draw();
+        move |_, _| {
+            let state = state.clone();
+            let window = window.clone();
+            glib::spawn_future_local(async move {
+                ask_upload_settings(&state, &window).await;
+            });
         }
     });
-}
+    components.window.add_action(&action_upload_settings);
 
-pub fn connect_screenshot_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    components.header.take_screenshot_btn.connect_clicked({
+    let action_forget_credentials = gio::SimpleAction::new("forget-credentials", None);
+    action_forget_credentials.connect_activate({
         let state = state.clone();
         let components = components.clone();
-        move |_| {
-            let mode = state.borrow().mode;
-            capture_screen_or_selection(&state, &components, mode);
+        move |_, _| {
+            let state = state.clone();
+            let components = components.clone();
+            glib::spawn_future_local(async move {
+                forget_credentials(&state, &components).await;
+            });
         }
     });
+    components.window.add_action(&action_forget_credentials);
+
+    register_forge_target(state);
+    register_nextcloud_target(state);
+    register_chat_target(state);
+    update_export_menu(state, components);
 }
 
-pub fn connect_keyboard_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
-    let key_controller = EventControllerKey::new();
-    key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+/// List the registered targets in the export menu
+fn update_export_menu(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let targets_section = gio::Menu::new();
+    for target in state.borrow().export_targets.iter() {
+        let item = gio::MenuItem::new(Some(target.name()), None);
+        item.set_action_and_target_value(Some("win.export"), Some(&target.id().to_variant()));
+        item.set_icon(&gio::ThemedIcon::new(target.icon_name()));
+        targets_section.append_item(&item);
+    }
+    let export_menu = gio::Menu::new();
+    export_menu.append(Some("Export Again"), Some("win.export-again"));
+    export_menu.append(Some("Copy Image Snippet"), Some("win.copy-snippet"));
+    export_menu.append_section(None, &targets_section);
+    components
+        .toolbar
+        .export_btn
+        .set_menu_model(Some(&export_menu));
+}
 
-    key_controller.connect_key_pressed({
+pub fn connect_info_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let popover = match components.toolbar.info_btn.popover() {
+        Some(popover) => popover,
+        None => return,
+    };
+
+    popover.connect_show({
         let state = state.clone();
-        let components = components.clone();
+        let grid = components.toolbar.info_grid.clone();
+        move |_| {
+            while let Some(child) = grid.first_child() {
+                grid.remove(&child);
+            }
 
-        move |_, key, _code, modifier| {
-            let (action, _mode, _is_active) = {
+            // An opened file shows its own details, and what saving loses
+            let (rows, warnings) = {
                 let s = state.borrow();
-                (s.shortcuts.get_action(key, modifier), s.mode, s.is_active)
+                match (&s.file_metadata, &s.capture_metadata) {
+                    (Some(file), _) => (file.rows(), file.save_warnings()),
+                    (None, Some(capture)) => (capture.rows(), Vec::new()),
+                    (None, None) => (Vec::new(), Vec::new()),
+                }
             };
 
-            if let Some(action) = action {
-                debug!("Shortcut detected: {:?}", action);
-                match action {
-                    Action::Copy => {
-                        perform_copy(&state, &components.window);
-                        return glib::Propagation::Stop;
-                    }
-                    Action::Save => {
-                        perform_save(state.clone(), components.window.clone());
-                        return glib::Propagation::Stop;
-                    }
-                    Action::Undo => {
-                        perform_undo(&state, &components.drawing.drawing_area);
-                        return glib::Propagation::Stop;
+            if rows.is_empty() {
+                grid.attach(&gtk::Label::new(Some("No capture details")), 0, 0, 2, 1);
+                return;
+            }
+            let first_warning = rows.len() as i32;
+
+            for (i, (label, value)) in rows.into_iter().enumerate() {
+                let key = gtk::Label::builder()
+                    .label(label)
+                    .halign(gtk::Align::End)
+                    .css_classes(["dim-label"])
+                    .build();
+                let value = gtk::Label::builder()
+                    .label(value)
+                    .halign(gtk::Align::Start)
+                    .selectable(true)
+                    .max_width_chars(40)
+                    .ellipsize(gtk::pango::EllipsizeMode::Middle)
+                    .build();
+                grid.attach(&key, 0, i as i32, 1, 1);
+                grid.attach(&value, 1, i as i32, 1, 1);
+            }
+            for (i, warning) in warnings.into_iter().enumerate() {
+                let label = gtk::Label::builder()
+                    .label(warning)
+                    .halign(gtk::Align::Start)
+                    .wrap(true)
+                    .max_width_chars(40)
+                    .xalign(0.0)
+                    .css_classes(["warning"])
+                    .build();
+                grid.attach(&label, 0, first_warning + i as i32, 2, 1);
+            }
+        }
+    });
+}
+
+/// Row for a found link, with buttons to open and copy it
+fn link_row(url: &str, components: &UiComponents) -> adw::ActionRow {
+    let open_btn = gtk::Button::builder()
+        .icon_name("web-browser-symbolic")
+        .tooltip_text("Open Link")
+        .valign(gtk::Align::Center)
+        .build();
+    open_btn.add_css_class("flat");
+    open_btn.connect_clicked({
+        let url = url.to_string();
+        let window = components.window.clone();
+        move |_| {
+            let url = url.clone();
+            gtk::UriLauncher::new(&url).launch(
+                Some(&window),
+                None::<&gio::Cancellable>,
+                move |result| {
+                    if let Err(e) = result {
+                        error!("Failed to open {}: {}", url, e);
                     }
-                    Action::Cancel => {
-                        let mut s = state.borrow_mut();
-                        if s.is_active && s.mode == CaptureMode::Selection {
-                            debug!("Canceling selection via shortcut");
-                            s.exit_capture_mode();
-                            components.window.unfullscreen();
-                            components.header.header_bar.set_visible(true);
-                            components.toolbar.tools_box.set_visible(s.final_image.is_some());
-                            components.crop_toolbar.crop_tools_box.set_visible(false);
-                            components.selection_toolbar.selection_tools_box.set_visible(false);
-                            if s.final_image.is_none() {
-                                components.drawing.placeholder_icon.set_visible(true);
-                            }
-                            drop(s);
-                            components.drawing.drawing_area.queue_draw();
-                            return glib::Propagation::Stop;
-                        } else if s.is_crop_mode {
-                            s.exit_crop_mode();
-                            drop(s);
-                            components.crop_toolbar.crop_tools_box.set_visible(false);
-                            components.toolbar.tools_box.set_visible(true);
-                            components.drawing.drawing_area.queue_draw();
-                            return glib::Propagation::Stop;
-                        }
+                },
+            );
+        }
+    });
+
+    let copy_btn = gtk::Button::builder()
+        .icon_name("app-edit-copy-symbolic")
+        .tooltip_text("Copy Link")
+        .valign(gtk::Align::Center)
+        .build();
+    copy_btn.add_css_class("flat");
+    copy_btn.connect_clicked({
+        let url = url.to_string();
+        let components = components.clone();
+        move |_| {
+            components.window.clipboard().set_text(&url);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Link copied"));
+        }
+    });
+
+    let row = adw::ActionRow::builder()
+        .title(url)
+        .use_markup(false)
+        .title_lines(1)
+        .build();
+    row.add_suffix(&open_btn);
+    row.add_suffix(&copy_btn);
+    row
+}
+
+fn links_message(text: &str) -> gtk::Label {
+    gtk::Label::builder()
+        .label(text)
+        .margin_top(12)
+        .margin_bottom(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build()
+}
+
+/// Recognize the text in the image each time the links popover opens and
+/// list the web addresses in it
+pub fn connect_links_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let popover = match components.toolbar.links_btn.popover() {
+        Some(popover) => popover,
+        None => return,
+    };
+    // Bumped on every opening, so a scan finishing late doesn't fill the
+    // list of a later one
+    let generation = Rc::new(std::cell::Cell::new(0u32));
+
+    popover.connect_show({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| {
+            let list = components.toolbar.links_box.clone();
+            list.remove_all();
+            generation.set(generation.get().wrapping_add(1));
+
+            if !tesseract_available() {
+                list.append(&links_message("Install tesseract to find links"));
+                return;
+            }
+            let png = match state.borrow().final_image {
+                Some(ref pixbuf) => pixbuf.save_to_bufferv("png", &[]).ok(),
+                None => None,
+            };
+            let png = match png {
+                Some(png) => png,
+                None => {
+                    list.append(&links_message("No links found"));
+                    return;
+                }
+            };
+            list.append(&links_message("Looking for links…"));
+
+            let generation = generation.clone();
+            let started = generation.get();
+            let components = components.clone();
+            glib::spawn_future_local(async move {
+                let result =
+                    gio::spawn_blocking(move || recognize_text(&png).map(|t| find_urls(&t))).await;
+                if generation.get() != started {
+                    return;
+                }
+
+                list.remove_all();
+                match result {
+                    Ok(Ok(urls)) if urls.is_empty() => {
+                        list.append(&links_message("No links found"));
                     }
-                    Action::Confirm => {
-                        let mut s = state.borrow_mut();
-                        if s.is_active && s.mode == CaptureMode::Selection {
-                            if confirm_selection(
-                                &mut s,
-                                &components.window,
-                                &components.header.header_bar,
-                                &components.toolbar.tools_box,
-                                &components.crop_toolbar.crop_tools_box,
-                            ) {
-                                components.selection_toolbar.selection_tools_box.set_visible(false);
-                                drop(s);
-                                components.drawing.drawing_area.queue_draw();
-                            }
-                            return glib::Propagation::Stop;
+                    Ok(Ok(urls)) => {
+                        debug!("Found {} links in the image", urls.len());
+                        for url in urls {
+                            list.append(&link_row(&url, &components));
                         }
                     }
-                    Action::ToolPointer => {
-                        let mut s = state.borrow_mut();
-                        s.editor.set_tool(EditorTool::Pointer);
-                        drop(s);
-                        components.drawing.drawing_area.queue_draw();
-                        return glib::Propagation::Stop;
+                    Ok(Err(e)) => {
+                        error!("Failed to look for links: {}", e);
+                        list.append(&links_message("Failed to recognize text"));
                     }
-                    Action::ToolPencil => {
-                        let mut s = state.borrow_mut();
-                        s.editor.set_tool(EditorTool::Pencil);
-                        drop(s);
-                        components.drawing.drawing_area.queue_draw();
-                        return glib::Propagation::Stop;
+                    Err(_) => {
+                        error!("Link search task panicked");
                     }
-                    Action::ToolRectangle => {
-                        let mut s = state.borrow_mut();
-                        s.editor.set_tool(EditorTool::Rectangle);
-                        drop(s);
-                        components.drawing.drawing_area.queue_draw();
-                        return glib::Propagation::Stop;
+                }
+            });
+        }
+    });
+}
+
+/// Ask for a new filename template, see `CaptureMetadata::expand_filename_template`
+fn edit_filename_template(state: Rc<RefCell<AppState>>, window: adw::ApplicationWindow) {
+    glib::spawn_future_local(async move {
+        let entry = gtk::Entry::builder()
+            .text(state.borrow().filename_template.as_str())
+            .activates_default(true)
+            .build();
+
+        let dialog = adw::AlertDialog::new(
+            Some("Filename Template"),
+            Some(
+                "Available fields: {date}, {time}, {timestamp}, {year}, {month}, {day}, \
+                {mode}, {app}, {title}, {monitor}, {width} and {height}. \
+                Use / for subfolders.",
+            ),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+        dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("save"));
+        dialog.set_close_response("cancel");
+        dialog.set_extra_child(Some(&entry));
+
+        if dialog.choose_future(&window).await == "save" {
+            let template = entry.text().trim().to_string();
+            let mut s = state.borrow_mut();
+            s.filename_template = if template.is_empty() {
+                DEFAULT_FILENAME_TEMPLATE.to_string()
+            } else {
+                template
+            };
+            debug!("Filename template set to {:?}", s.filename_template);
+        }
+    });
+}
+
+/// Diff the current image against the clipboard image and show the result
+fn compare_with_clipboard(state: Rc<RefCell<AppState>>, components: UiComponents) {
+    let current = match state.borrow().final_image {
+        Some(ref pixbuf) => RgbaBuffer::from_pixbuf(pixbuf),
+        None => return,
+    };
+
+    glib::spawn_future_local(async move {
+        let clipboard = ClipboardManager::from_widget(&components.window);
+        let reference = match clipboard.read_image().await {
+            Ok(Some(pixbuf)) => RgbaBuffer::from_pixbuf(&pixbuf),
+            Ok(None) => {
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("No image on the clipboard"));
+                return;
+            }
+            Err(e) => {
+                error!("{}", e);
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("Failed to read clipboard image"));
+                return;
+            }
+        };
+
+        let diff = match gio::spawn_blocking(move || {
+            diff_images(&current, &reference, DEFAULT_DIFF_THRESHOLD)
+        })
+        .await
+        {
+            Ok(diff) => diff,
+            Err(_) => {
+                error!("Image diff task panicked");
+                return;
+            }
+        };
+        info!("Compared with clipboard image: {}", diff.summary());
+
+        let highlight = diff.highlight.to_pixbuf();
+        let dialog = adw::AlertDialog::new(Some("Compared with Clipboard"), Some(&diff.summary()));
+        dialog.add_response("close", "Close");
+        dialog.set_default_response(Some("close"));
+        dialog.set_close_response("close");
+        if !diff.is_identical() {
+            // Changed pixels are magenta on a faded copy of the capture
+            let picture = gtk::Picture::builder()
+                .paintable(&gtk::gdk::Texture::for_pixbuf(&highlight))
+                .content_fit(gtk::ContentFit::Contain)
+                .width_request(480)
+                .height_request(320)
+                .build();
+            dialog.set_extra_child(Some(&picture));
+            dialog.add_response("copy", "Copy Diff");
+        }
+
+        if dialog.choose_future(&components.window).await == "copy" {
+            if let Err(e) = clipboard.copy_image(&highlight, None) {
+                error!("{}", e);
+            }
+        }
+    });
+}
+
+/// A warning for the dialogs that ask for credentials when they can't go in
+/// the keyring, as they are then saved unencrypted
+fn keyring_warning(state: &Rc<RefCell<AppState>>) -> Option<gtk::Label> {
+    if state.borrow().preferences.uses_keyring() {
+        return None;
+    }
+    Some(
+        gtk::Label::builder()
+            .label(
+                "The system keyring can't be used, so credentials are saved unencrypted in the \
+                preferences file. Install secret-tool and unlock the keyring to keep them there.",
+            )
+            .wrap(true)
+            .xalign(0.0)
+            .css_classes(["warning", "caption"])
+            .build(),
+    )
+}
+
+/// Ask where recognized text is sent and which language it is translated
+/// into, and save the answer. `None` when cancelled or left blank.
+async fn ask_translation_settings(
+    state: &Rc<RefCell<AppState>>,
+    window: &adw::ApplicationWindow,
+) -> Option<TranslationSettings> {
+    let current = state.borrow().preferences.translation.clone();
+    let (backend, api_key, language) = match current {
+        Some(ref t) => (
+            t.backend.input(),
+            t.backend.api_key().unwrap_or(""),
+            t.target_language.as_str(),
+        ),
+        None => ("", "", DEFAULT_TRANSLATION_LANGUAGE),
+    };
+    let backend_entry = gtk::Entry::builder()
+        .text(backend)
+        .placeholder_text("URL or command")
+        .activates_default(true)
+        .build();
+    let api_key_entry = gtk::PasswordEntry::builder()
+        .text(api_key)
+        .placeholder_text("API key, if the URL needs one")
+        .show_peek_icon(true)
+        .activates_default(true)
+        .build();
+    let language_entry = gtk::Entry::builder()
+        .text(language)
+        .placeholder_text("Language code, such as en")
+        .activates_default(true)
+        .build();
+
+    let fields = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    fields.append(&backend_entry);
+    fields.append(&api_key_entry);
+    fields.append(&language_entry);
+    if let Some(warning) = keyring_warning(state) {
+        fields.append(&warning);
+    }
+
+    let dialog = adw::AlertDialog::new(
+        Some("Translation"),
+        Some(
+            "Recognized text is sent to a LibreTranslate compatible URL, such as \
+            http://localhost:5000/translate, or on stdin to a command that prints the \
+            translation, such as trans -brief -t {lang}.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&fields));
+
+    if dialog.choose_future(window).await != "save" {
+        return None;
+    }
+
+    let language = language_entry.text().trim().to_string();
+    let api_key = api_key_entry.text().trim().to_string();
+    let settings = TranslationBackend::parse(&backend_entry.text(), Some(api_key)).map(|backend| {
+        TranslationSettings {
+            backend,
+            target_language: if language.is_empty() {
+                DEFAULT_TRANSLATION_LANGUAGE.to_string()
+            } else {
+                language
+            },
+        }
+    });
+    debug!("Translation set to {:?}", settings);
+
+    let mut s = state.borrow_mut();
+    s.preferences.translation = settings.clone();
+    s.preferences.store_secrets();
+    s.preferences.save();
+    settings
+}
+
+/// Offer the repository upload as an export target, or stop offering it
+/// once its settings are cleared
+fn register_forge_target(state: &Rc<RefCell<AppState>>) {
+    let mut s = state.borrow_mut();
+    match s.preferences.forge_upload.clone() {
+        Some(settings) => s
+            .export_targets
+            .register(Box::new(ForgeTarget::new(settings))),
+        None => s.export_targets.unregister(ForgeTarget::ID),
+    }
+}
+
+/// Ask which GitHub or GitLab repository screenshots are uploaded to, and
+/// save the answer. `false` when cancelled.
+async fn ask_forge_settings(
+    state: &Rc<RefCell<AppState>>,
+    window: &adw::ApplicationWindow,
+) -> bool {
+    let current = state.borrow().preferences.forge_upload.clone();
+    let current = current.unwrap_or(ForgeSettings {
+        kind: ForgeKind::default(),
+        host: String::new(),
+        repository: String::new(),
+        token: String::new(),
+        branch: String::new(),
+    });
+
+    let labels: Vec<&str> = ForgeKind::ALL.iter().map(|kind| kind.label()).collect();
+    let kind_dropdown = gtk::DropDown::from_strings(&labels);
+    let selected = ForgeKind::ALL
+        .iter()
+        .position(|&kind| kind == current.kind)
+        .unwrap_or(0);
+    kind_dropdown.set_selected(selected as u32);
+    let host_entry = gtk::Entry::builder()
+        .text(current.host.as_str())
+        .placeholder_text("Server, if self-hosted")
+        .activates_default(true)
+        .build();
+    let repository_entry = gtk::Entry::builder()
+        .text(current.repository.as_str())
+        .placeholder_text("Repository, such as owner/name")
+        .activates_default(true)
+        .build();
+    let token_entry = gtk::PasswordEntry::builder()
+        .text(current.token.as_str())
+        .placeholder_text("Access token")
+        .show_peek_icon(true)
+        .activates_default(true)
+        .build();
+    let branch_entry = gtk::Entry::builder()
+        .text(current.branch.as_str())
+        .placeholder_text("Branch for images (GitHub only)")
+        .activates_default(true)
+        .build();
+
+    let fields = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    fields.append(&kind_dropdown);
+    fields.append(&host_entry);
+    fields.append(&repository_entry);
+    fields.append(&token_entry);
+    fields.append(&branch_entry);
+    if let Some(warning) = keyring_warning(state) {
+        fields.append(&warning);
+    }
+
+    let dialog = adw::AlertDialog::new(
+        Some("Repository Upload"),
+        Some(
+            "Screenshots are uploaded to the repository and linked to from issues. GitLab \
+            keeps them as project uploads and needs a token with the api scope. GitHub \
+            commits them to a branch, best one set aside for images, with a token that can \
+            write contents. Leave the repository empty to stop uploading.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&fields));
+
+    if dialog.choose_future(window).await != "save" {
+        return false;
+    }
+
+    let repository = repository_entry.text().trim().to_string();
+    let token = token_entry.text().trim().to_string();
+    let settings = if repository.is_empty() || token.is_empty() {
+        None
+    } else {
+        Some(ForgeSettings {
+            kind: ForgeKind::ALL
+                .get(kind_dropdown.selected() as usize)
+                .copied()
+                .unwrap_or_default(),
+            host: host_entry.text().trim().to_string(),
+            repository,
+            token,
+            branch: branch_entry.text().trim().to_string(),
+        })
+    };
+    debug!(
+        "Repository upload set to {:?}",
+        settings.as_ref().map(|s| (s.kind, &s.repository))
+    );
+
+    let mut s = state.borrow_mut();
+    s.preferences.forge_upload = settings;
+    s.preferences.store_secrets();
+    s.preferences.save();
+    true
+}
+
+/// Offer Nextcloud sharing as an export target, or stop offering it once its
+/// settings are cleared
+fn register_nextcloud_target(state: &Rc<RefCell<AppState>>) {
+    let mut s = state.borrow_mut();
+    match s.preferences.nextcloud.clone() {
+        Some(settings) => s
+            .export_targets
+            .register(Box::new(NextcloudTarget::new(settings))),
+        None => s.export_targets.unregister(NextcloudTarget::ID),
+    }
+}
+
+/// Ask for the Nextcloud server and account screenshots are shared from,
+/// and how the links are protected, and save the answer. `false` when
+/// cancelled.
+async fn ask_nextcloud_settings(
+    state: &Rc<RefCell<AppState>>,
+    window: &adw::ApplicationWindow,
+) -> bool {
+    let current = state.borrow().preferences.nextcloud.clone();
+    let current = current.unwrap_or_default();
+
+    let server_row = adw::EntryRow::builder()
+        .title("Server")
+        .text(current.server.as_str())
+        .build();
+    let user_row = adw::EntryRow::builder()
+        .title("User Name")
+        .text(current.user.as_str())
+        .build();
+    let app_password_row = adw::PasswordEntryRow::builder()
+        .title("App Password")
+        .text(current.app_password.as_str())
+        .build();
+    let folder_row = adw::EntryRow::builder()
+        .title("Folder")
+        .text(current.folder.as_str())
+        .build();
+    let expire_row = adw::SpinRow::with_range(0.0, NextcloudSettings::MAX_EXPIRE_DAYS as f64, 1.0);
+    expire_row.set_title("Link Expires After");
+    expire_row.set_subtitle("Days; 0 keeps the link working");
+    expire_row.set_value(current.expire_days as f64);
+    let share_password_row = adw::PasswordEntryRow::builder()
+        .title("Link Password (Optional)")
+        .text(current.share_password.as_str())
+        .build();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&server_row);
+    list.append(&user_row);
+    list.append(&app_password_row);
+    list.append(&folder_row);
+    list.append(&expire_row);
+    list.append(&share_password_row);
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(12)
+        .build();
+    content.append(&list);
+    if let Some(warning) = keyring_warning(state) {
+        content.append(&warning);
+    }
+
+    let dialog = adw::AlertDialog::new(
+        Some("Nextcloud Sharing"),
+        Some(
+            "Screenshots are uploaded to the folder and shared by a public link, which is \
+            copied to the clipboard. Create an app password under Security in the server's \
+            personal settings. Leave the server empty to stop sharing.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&content));
+
+    if dialog.choose_future(window).await != "save" {
+        return false;
+    }
+
+    let server = server_row.text().trim().to_string();
+    let user = user_row.text().trim().to_string();
+    let app_password = app_password_row.text().trim().to_string();
+    let settings = if server.is_empty() || user.is_empty() || app_password.is_empty() {
+        None
+    } else {
+        Some(NextcloudSettings {
+            server,
+            user,
+            app_password,
+            folder: folder_row.text().trim().to_string(),
+            expire_days: expire_row.value() as u32,
+            share_password: share_password_row.text().to_string(),
+        })
+    };
+    debug!(
+        "Nextcloud sharing set to {:?}",
+        settings.as_ref().map(|s| (&s.server, &s.user))
+    );
+
+    let mut s = state.borrow_mut();
+    s.preferences.nextcloud = settings;
+    s.preferences.store_secrets();
+    s.preferences.save();
+    true
+}
+
+/// Ask for the upload speed limit and how many times failed requests are
+/// made, and save the answer
+async fn ask_upload_settings(state: &Rc<RefCell<AppState>>, window: &adw::ApplicationWindow) {
+    let current = state.borrow().preferences.upload;
+
+    let rate_row = adw::SpinRow::with_range(0.0, UploadOptions::MAX_RATE_LIMIT_KIB as f64, 64.0);
+    rate_row.set_title("Speed Limit");
+    rate_row.set_subtitle("KiB/s; 0 sends as fast as the connection allows");
+    rate_row.set_value(current.rate_limit_kib as f64);
+    let attempts_row = adw::SpinRow::with_range(1.0, UploadOptions::MAX_ATTEMPTS as f64, 1.0);
+    attempts_row.set_title("Attempts");
+    attempts_row.set_subtitle("Times a request is made before the upload fails");
+    attempts_row.set_value(current.max_attempts as f64);
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&rate_row);
+    list.append(&attempts_row);
+
+    let dialog = adw::AlertDialog::new(
+        Some("Uploads"),
+        Some(
+            "Used by repository uploads, Nextcloud sharing and chat posts. A request that \
+            fails on a dropped connection or a busy server is made again after a growing wait.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&list));
+
+    if dialog.choose_future(window).await != "save" {
+        return;
+    }
+
+    let options = UploadOptions {
+        rate_limit_kib: rate_row.value() as u32,
+        max_attempts: attempts_row.value() as u32,
+    };
+    debug!("Upload options set to {:?}", options);
+    let mut s = state.borrow_mut();
+    s.preferences.upload = options;
+    s.preferences.save();
+}
+
+/// Offer posting to the chat as an export target, or stop offering it once
+/// its settings are cleared
+fn register_chat_target(state: &Rc<RefCell<AppState>>) {
+    let mut s = state.borrow_mut();
+    match s.preferences.chat.clone() {
+        Some(settings) => s
+            .export_targets
+            .register(Box::new(ChatTarget::new(settings))),
+        None => s.export_targets.unregister(ChatTarget::ID),
+    }
+}
+
+/// Ask for the Matrix room or webhook screenshots are posted to, and save
+/// the answer. `false` when cancelled.
+async fn ask_chat_settings(state: &Rc<RefCell<AppState>>, window: &adw::ApplicationWindow) -> bool {
+    let current = state.borrow().preferences.chat.clone();
+    let current = current.unwrap_or_default();
+
+    let labels: Vec<&str> = ChatKind::ALL.iter().map(|kind| kind.label()).collect();
+    let kind_dropdown = gtk::DropDown::from_strings(&labels);
+    let selected = ChatKind::ALL
+        .iter()
+        .position(|&kind| kind == current.kind)
+        .unwrap_or(0);
+    kind_dropdown.set_selected(selected as u32);
+    let url_entry = gtk::Entry::builder()
+        .text(current.url.as_str())
+        .placeholder_text("Webhook URL or Matrix homeserver")
+        .activates_default(true)
+        .build();
+    let room_entry = gtk::Entry::builder()
+        .text(current.room.as_str())
+        .placeholder_text("Room ID, such as !abc:example.org")
+        .activates_default(true)
+        .build();
+    let token_entry = gtk::PasswordEntry::builder()
+        .text(current.token.as_str())
+        .placeholder_text("Access token")
+        .show_peek_icon(true)
+        .activates_default(true)
+        .build();
+    let message_entry = gtk::Entry::builder()
+        .text(current.message.as_str())
+        .placeholder_text("Message (optional)")
+        .activates_default(true)
+        .build();
+
+    // The room and token only apply to Matrix
+    let update_fields = {
+        let room_entry = room_entry.clone();
+        let token_entry = token_entry.clone();
+        move |dropdown: &gtk::DropDown| {
+            let matrix = ChatKind::ALL.get(dropdown.selected() as usize) == Some(&ChatKind::Matrix);
+            room_entry.set_visible(matrix);
+            token_entry.set_visible(matrix);
+        }
+    };
+    update_fields(&kind_dropdown);
+    kind_dropdown.connect_selected_notify(update_fields);
+
+    let fields = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    fields.append(&kind_dropdown);
+    fields.append(&url_entry);
+    fields.append(&room_entry);
+    fields.append(&token_entry);
+    fields.append(&message_entry);
+    if let Some(warning) = keyring_warning(state) {
+        fields.append(&warning);
+    }
+
+    let dialog = adw::AlertDialog::new(
+        Some("Chat"),
+        Some(
+            "Send to Chat posts screenshots to a Matrix room, or to a webhook that takes \
+            file uploads such as a Discord channel\'s. Matrix needs the access token of an \
+            account in the room. Leave the URL empty to stop posting.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&fields));
+
+    if dialog.choose_future(window).await != "save" {
+        return false;
+    }
+
+    let kind = ChatKind::ALL
+        .get(kind_dropdown.selected() as usize)
+        .copied()
+        .unwrap_or_default();
+    let url = url_entry.text().trim().to_string();
+    let room = room_entry.text().trim().to_string();
+    let token = token_entry.text().trim().to_string();
+    let incomplete = match kind {
+        ChatKind::Webhook => url.is_empty(),
+        ChatKind::Matrix => url.is_empty() || room.is_empty() || token.is_empty(),
+    };
+    let settings = if incomplete {
+        None
+    } else {
+        Some(ChatSettings {
+            kind,
+            url,
+            room,
+            token,
+            message: message_entry.text().trim().to_string(),
+        })
+    };
+    debug!(
+        "Chat set to {:?}",
+        settings.as_ref().map(|s| (s.kind, &s.room))
+    );
+
+    let mut s = state.borrow_mut();
+    s.preferences.chat = settings;
+    s.preferences.store_secrets();
+    s.preferences.save();
+    true
+}
+
+/// Remove every saved token and password, from the keyring and the
+/// preferences, after asking. The uploads using them stop being offered.
+async fn forget_credentials(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let dialog = adw::AlertDialog::new(
+        Some("Forget Saved Credentials?"),
+        Some(
+            "The tokens and passwords for repository uploads, Nextcloud, chat and \
+            translation are removed, and those uploads are turned off until set up again.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("forget", "Forget")]);
+    dialog.set_response_appearance("forget", adw::ResponseAppearance::Destructive);
+    dialog.set_default_response(Some("cancel"));
+    dialog.set_close_response("cancel");
+
+    if dialog.choose_future(&components.window).await != "forget" {
+        return;
+    }
+
+    {
+        let mut s = state.borrow_mut();
+        s.preferences.forget_secrets();
+        s.preferences.save();
+    }
+    register_forge_target(state);
+    register_nextcloud_target(state);
+    register_chat_target(state);
+    update_export_menu(state, components);
+    components
+        .toast_overlay
+        .add_toast(adw::Toast::new("Saved credentials forgotten"));
+}
+
+/// Recognize the text in the current image, translate it and show the
+/// result, asking for a backend first if none is set up
+fn translate_image_text(state: Rc<RefCell<AppState>>, components: UiComponents) {
+    let png = match state.borrow().final_image {
+        Some(ref pixbuf) => pixbuf.save_to_bufferv("png", &[]),
+        None => return,
+    };
+    let png = match png {
+        Ok(png) => png,
+        Err(e) => {
+            error!("Failed to encode image for text recognition: {}", e);
+            return;
+        }
+    };
+    if !tesseract_available() {
+        components
+            .toast_overlay
+            .add_toast(adw::Toast::new("Install tesseract to recognize text"));
+        return;
+    }
+
+    glib::spawn_future_local(async move {
+        let configured = state.borrow().preferences.translation.clone();
+        let settings = match configured {
+            Some(settings) => settings,
+            None => match ask_translation_settings(&state, &components.window).await {
+                Some(settings) => settings,
+                None => return,
+            },
+        };
+
+        components
+            .toast_overlay
+            .add_toast(adw::Toast::new("Translating…"));
+        let result = gio::spawn_blocking(move || {
+            let text = recognize_text(&png).map_err(|e| e.to_string())?;
+            if text.is_empty() {
+                return Err("No text found in the image".to_string());
+            }
+            settings.backend.translate(&text, &settings.target_language)
+        })
+        .await;
+
+        let translation = match result {
+            Ok(Ok(translation)) => translation,
+            Ok(Err(e)) => {
+                error!("Translation failed: {}", e);
+                components.toast_overlay.add_toast(adw::Toast::new(&e));
+                return;
+            }
+            Err(_) => {
+                error!("Translation task panicked");
+                return;
+            }
+        };
+        info!("Translated {} characters", translation.chars().count());
+
+        let text_view = gtk::TextView::builder()
+            .editable(false)
+            .wrap_mode(gtk::WrapMode::WordChar)
+            .build();
+        text_view.buffer().set_text(&translation);
+        let scrolled = gtk::ScrolledWindow::builder()
+            .child(&text_view)
+            .min_content_width(420)
+            .min_content_height(200)
+            .build();
+
+        let dialog = adw::AlertDialog::new(Some("Translation"), None);
+        dialog.add_responses(&[("close", "Close"), ("copy", "Copy")]);
+        dialog.set_response_appearance("copy", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("copy"));
+        dialog.set_close_response("close");
+        dialog.set_extra_child(Some(&scrolled));
+
+        if dialog.choose_future(&components.window).await == "copy" {
+            components.window.clipboard().set_text(&translation);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Translation copied"));
+        }
+    });
+}
+
+/// Ask for a name and take a snapshot of the annotations and crop
+fn take_snapshot(state: Rc<RefCell<AppState>>, components: UiComponents) {
+    if state.borrow().final_image.is_none() {
+        return;
+    }
+    let count = state.borrow().editor.snapshots.len();
+
+    glib::spawn_future_local(async move {
+        let entry = gtk::Entry::builder()
+            .text(format!("Snapshot {}", count + 1))
+            .activates_default(true)
+            .build();
+
+        let dialog = adw::AlertDialog::new(
+            Some("Take Snapshot"),
+            Some("The annotations and crop can be brought back from Snapshots later."),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("save", "Take Snapshot")]);
+        dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("save"));
+        dialog.set_close_response("cancel");
+        dialog.set_extra_child(Some(&entry));
+
+        if dialog.choose_future(&components.window).await != "save" {
+            return;
+        }
+        let text = entry.text();
+        let name = match text.trim() {
+            "" => format!("Snapshot {}", count + 1),
+            name => name.to_string(),
+        };
+        if state.borrow_mut().take_snapshot(&name) {
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new(&format!("Took snapshot “{}”", name)));
+        }
+    });
+}
+
+/// Fill `list` with a row per snapshot, restoring it on activation
+fn fill_snapshot_list(
+    list: &gtk::ListBox,
+    dialog: &adw::AlertDialog,
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+) {
+    list.remove_all();
+    let snapshots = state.borrow().editor.snapshots.clone();
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        let annotations = snapshot.document.annotations.len();
+        let subtitle = format!(
+            "{} annotation{}{}",
+            annotations,
+            if annotations == 1 { "" } else { "s" },
+            if snapshot.crop.is_some() {
+                ", cropped"
+            } else {
+                ""
+            }
+        );
+        let row = adw::ActionRow::builder()
+            .title(snapshot.name.as_str())
+            .subtitle(subtitle)
+            .activatable(true)
+            .build();
+
+        let delete_btn = gtk::Button::builder()
+            .icon_name("user-trash-symbolic")
+            .tooltip_text("Delete Snapshot")
+            .valign(gtk::Align::Center)
+            .build();
+        delete_btn.add_css_class("flat");
+        delete_btn.connect_clicked({
+            let list = list.clone();
+            let dialog = dialog.clone();
+            let state = state.clone();
+            let components = components.clone();
+            move |_| {
+                state.borrow_mut().editor.snapshots.remove(index);
+                fill_snapshot_list(&list, &dialog, &state, &components);
+            }
+        });
+        row.add_suffix(&delete_btn);
+
+        row.connect_activated({
+            let dialog = dialog.clone();
+            let state = state.clone();
+            let components = components.clone();
+            let name = snapshot.name.clone();
+            move |_| {
+                dialog.close();
+                if state.borrow_mut().restore_snapshot(index) {
+                    components.drawing.drawing_area.queue_draw();
+                    components
+                        .toast_overlay
+                        .add_toast(adw::Toast::new(&format!("Restored “{}”", name)));
+                }
+            }
+        });
+        list.append(&row);
+    }
+}
+
+/// List the snapshots of this image, to restore or delete them
+fn show_snapshots(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    if state.borrow().editor.snapshots.is_empty() {
+        components
+            .toast_overlay
+            .add_toast(adw::Toast::new("No snapshots taken of this image"));
+        return;
+    }
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.add_css_class("boxed-list");
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(360)
+        .child(&list)
+        .build();
+
+    let dialog = adw::AlertDialog::new(
+        Some("Snapshots"),
+        Some("Restoring a snapshot replaces the current annotations and crop."),
+    );
+    dialog.add_response("close", "Close");
+    dialog.set_close_response("close");
+    dialog.set_extra_child(Some(&scrolled));
+    fill_snapshot_list(&list, &dialog, state, components);
+    dialog.present(Some(&components.window));
+}
+
+/// Ask for a name and save the current annotations as a template
+fn save_template(state: Rc<RefCell<AppState>>, components: UiComponents) {
+    {
+        let s = state.borrow();
+        if s.final_image.is_none() || s.editor.annotations.is_empty() {
+            drop(s);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Draw the annotations to save first"));
+            return;
+        }
+    }
+    let count = state.borrow().templates.len();
+
+    glib::spawn_future_local(async move {
+        let entry = gtk::Entry::builder()
+            .text(format!("Template {}", count + 1))
+            .activates_default(true)
+            .build();
+
+        let dialog = adw::AlertDialog::new(
+            Some("Save as Template"),
+            Some(
+                "The annotations can be added to any capture from Templates, in the same \
+                place. A template with the same name is replaced.",
+            ),
+        );
+        dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+        dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("save"));
+        dialog.set_close_response("cancel");
+        dialog.set_extra_child(Some(&entry));
+
+        if dialog.choose_future(&components.window).await != "save" {
+            return;
+        }
+        let text = entry.text();
+        let name = match text.trim() {
+            "" => format!("Template {}", count + 1),
+            name => name.to_string(),
+        };
+        let result = state.borrow_mut().save_template(&name);
+        match result {
+            Ok(()) => components
+                .toast_overlay
+                .add_toast(adw::Toast::new(&format!("Saved template “{}”", name))),
+            Err(e) => {
+                error!("Failed to save template: {}", e);
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("Failed to save template"));
+            }
+        }
+    });
+}
+
+/// Fill `list` with a row per template, adding its annotations on activation
+fn fill_template_list(
+    list: &gtk::ListBox,
+    dialog: &adw::AlertDialog,
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+) {
+    list.remove_all();
+    let templates: Vec<(String, usize)> = state
+        .borrow()
+        .templates
+        .iter()
+        .map(|t| (t.name.clone(), t.document.annotations.len()))
+        .collect();
+    for (index, (name, annotations)) in templates.into_iter().enumerate() {
+        let row = adw::ActionRow::builder()
+            .title(name.as_str())
+            .subtitle(format!(
+                "{} annotation{}",
+                annotations,
+                if annotations == 1 { "" } else { "s" }
+            ))
+            .activatable(true)
+            .build();
+
+        let delete_btn = gtk::Button::builder()
+            .icon_name("user-trash-symbolic")
+            .tooltip_text("Delete Template")
+            .valign(gtk::Align::Center)
+            .build();
+        delete_btn.add_css_class("flat");
+        delete_btn.connect_clicked({
+            let list = list.clone();
+            let dialog = dialog.clone();
+            let state = state.clone();
+            let components = components.clone();
+            move |_| {
+                let result = {
+                    let mut s = state.borrow_mut();
+                    s.templates.remove(index);
+                    save_templates(&s.templates)
+                };
+                if let Err(e) = result {
+                    error!("Failed to save templates: {}", e);
+                }
+                fill_template_list(&list, &dialog, &state, &components);
+            }
+        });
+        row.add_suffix(&delete_btn);
+
+        row.connect_activated({
+            let dialog = dialog.clone();
+            let state = state.clone();
+            let components = components.clone();
+            move |_| {
+                dialog.close();
+                let added = state.borrow_mut().apply_template(index);
+                components.drawing.drawing_area.queue_draw();
+                let message = if added == 0 {
+                    format!("Nothing in “{}” fits on this image", name)
+                } else {
+                    format!("Added “{}”", name)
+                };
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new(&message));
+            }
+        });
+        list.append(&row);
+    }
+}
+
+/// List the saved templates, to add one to the image or delete it
+fn show_templates(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    {
+        let s = state.borrow();
+        if s.final_image.is_none() {
+            return;
+        }
+        if s.templates.is_empty() {
+            drop(s);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("No templates saved yet"));
+            return;
+        }
+    }
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    list.add_css_class("boxed-list");
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(360)
+        .child(&list)
+        .build();
+
+    let dialog = adw::AlertDialog::new(
+        Some("Templates"),
+        Some("A template's annotations are added on top of the current ones."),
+    );
+    dialog.add_response("close", "Close");
+    dialog.set_close_response("close");
+    dialog.set_extra_child(Some(&scrolled));
+    fill_template_list(&list, &dialog, state, components);
+    dialog.present(Some(&components.window));
+}
+
+pub fn connect_drag_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let drag = GestureDrag::new();
+    drag.set_button(1); // Left mouse button
+
+    drag.connect_drag_begin({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |gesture, x, y| {
+            handle_drag_begin(&state, gesture, x, y);
+            drawing_area.queue_draw();
+        }
+    });
+
+    drag.connect_drag_update({
+        let state = state.clone();
+        let components = components.clone();
+        move |gesture, x, y| {
+            handle_drag_update(&state, gesture, x, y);
+            if let Some((start_x, start_y)) = gesture.start_point() {
+                let drawing = {
+                    let s = state.borrow();
+                    s.editor.tool_state.is_drawing || s.editor.tool_state.is_dragging_annotation
+                };
+                fade_toolbar(
+                    &components,
+                    drawing && near_toolbar(&components, start_x + x, start_y + y),
+                );
+            }
+            components.drawing.drawing_area.queue_draw();
+        }
+    });
+
+    drag.connect_drag_end({
+        let state = state.clone();
+        let components = components.clone();
+        move |gesture, x, y| {
+            handle_drag_end(&state, gesture, x, y);
+            fade_toolbar(&components, false);
+            components.drawing.drawing_area.queue_draw();
+        }
+    });
+
+    components.drawing.drawing_area.add_controller(drag);
+
+    // A faded toolbar comes back as soon as the pointer heads for it
+    let motion = gtk::EventControllerMotion::new();
+    motion.connect_motion({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, x, y| {
+            let drawing = state.borrow().editor.tool_state.is_drawing;
+            if !drawing && near_toolbar(&components, x, y) {
+                fade_toolbar(&components, false);
+            }
+        }
+    });
+    components.drawing.drawing_area.add_controller(motion);
+}
+
+fn near_toolbar(components: &UiComponents, x: f64, y: f64) -> bool {
+    let tools_box = &components.toolbar.tools_box;
+    if !tools_box.is_visible() {
+        return false;
+    }
+
+    match tools_box.compute_bounds(&components.drawing.drawing_area) {
+        Some(bounds) => {
+            let (x, y) = (x as f32, y as f32);
+            x >= bounds.x() - TOOLBAR_FADE_MARGIN
+                && x <= bounds.x() + bounds.width() + TOOLBAR_FADE_MARGIN
+                && y >= bounds.y() - TOOLBAR_FADE_MARGIN
+                && y <= bounds.y() + bounds.height() + TOOLBAR_FADE_MARGIN
+        }
+        None => false,
+    }
+}
+
+/// Fade the toolbar out while annotating underneath it
+fn fade_toolbar(components: &UiComponents, faded: bool) {
+    let tools_box = &components.toolbar.tools_box;
+    if faded {
+        tools_box.add_css_class("faded");
+    } else {
+        tools_box.remove_css_class("faded");
+    }
+    tools_box.set_can_target(!faded);
+}
+
+fn handle_drag_begin(
+    state: &Rc<RefCell<AppState>>,
+    _gesture: &GestureDrag,
+    start_x: f64,
+    start_y: f64,
+) {
+    let mut s = state.borrow_mut();
+    if s.is_selecting_area() {
+        if s.placing_selection {
+            // The click puts the preset down, and dragging on moves it
+            s.placing_selection = false;
+            s.place_selection_at(start_x, start_y);
+            s.begin_selection_move();
+        } else {
+            s.start_selection(start_x, start_y);
+            s.selection_drag_point = Some((start_x, start_y));
+        }
+    } else if s.final_image.is_some() {
+        let (img_x, img_y) = s.editor.display_to_image_coords(start_x, start_y);
+
+        // Adjust the crop already drawn rather than starting over
+        if s.is_crop_mode && !s.editor.tool_state.is_drawing {
+            if let Some(handle) = crop_handle_at(&s, img_x, img_y) {
+                s.editor.tool_state.start_crop_adjust(handle, img_x, img_y);
+                return;
+            }
+        }
+
+        if s.editor.pointer_drag_start(start_x, start_y) {
+            return;
+        }
+
+        match s.editor.current_tool() {
+            EditorTool::Pencil => {
+                s.editor.tool_state.start_drag(img_x, img_y);
+                let mut free_draw = FreeDrawAnnotation::new(
+                    s.editor.tool_state.color,
+                    s.editor.tool_state.line_width,
+                );
+                free_draw.add_point(img_x, img_y);
+                s.editor
+                    .annotations
+                    .set_current(Some(Annotation::FreeDraw(free_draw)));
+            }
+            EditorTool::Rectangle | EditorTool::Redact => {
+                s.editor.tool_state.start_drag(img_x, img_y);
+            }
+            EditorTool::Crop => {
+                // For crop, reset any existing selection when starting a new one
+                s.editor.tool_state.reset_drag();
+                s.editor.tool_state.start_drag(img_x, img_y);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn handle_drag_update(
+    state: &Rc<RefCell<AppState>>,
+    gesture: &GestureDrag,
+    offset_x: f64,
+    offset_y: f64,
+) {
+    let mut s = state.borrow_mut();
+
+    let (start_x, start_y) = match gesture.start_point() {
+        Some(p) => p,
+        None => return,
+    };
+    let current_x = start_x + offset_x;
+    let current_y = start_y + offset_y;
+
+    if s.is_selecting_area() {
+        if s.selection_move_origin.is_some() {
+            s.update_selection_move(offset_x, offset_y);
+        } else {
+            s.drag_selection_to(current_x, current_y);
+        }
+    } else if s.final_image.is_some() {
+        let (img_x, img_y) = s.editor.display_to_image_coords(current_x, current_y);
+
+        if s.editor.tool_state.is_dragging_annotation {
+            s.editor.pointer_drag_update(current_x, current_y);
+        } else if s.editor.tool_state.crop_adjust.is_some() {
+            let bounds = match s.final_image {
+                Some(ref pixbuf) => (pixbuf.width() as f64, pixbuf.height() as f64),
+                None => return,
+            };
+            s.editor.tool_state.update_crop_adjust(img_x, img_y, bounds);
+        } else if s.editor.tool_state.is_drawing {
+            s.editor.tool_state.update_drag(img_x, img_y);
+
+            if s.editor.current_tool() == EditorTool::Pencil {
+                if let Some(Annotation::FreeDraw(draw)) = s.editor.annotations.current_mut() {
+                    draw.add_point(img_x, img_y);
+                }
+            }
+        }
+    }
+}
+
+fn handle_drag_end(
+    state: &Rc<RefCell<AppState>>,
+    gesture: &GestureDrag,
+    offset_x: f64,
+    offset_y: f64,
+) {
+    let mut s = state.borrow_mut();
+    let (start_x, start_y) = match gesture.start_point() {
+        Some(p) => p,
+        None => return,
+    };
+    let current_x = start_x + offset_x;
+    let current_y = start_y + offset_y;
+
+    if s.is_selecting_area() {
+        if s.selection_move_origin.is_some() {
+            s.update_selection_move(offset_x, offset_y);
+            s.selection_move_origin = None;
+        } else {
+            s.drag_selection_to(current_x, current_y);
+            s.selection_drag_point = None;
+        }
+    } else if s.final_image.is_some() {
+        if s.editor.tool_state.is_dragging_annotation {
+            s.editor.pointer_drag_end();
+        } else if s.editor.tool_state.crop_adjust.is_some() {
+            s.editor.tool_state.crop_adjust = None;
+        } else if s.editor.tool_state.is_drawing {
+            let tool = s.editor.current_tool();
+
+            if tool == EditorTool::Pencil {
+                s.editor.tool_state.end_drag();
+                if let Some(Annotation::FreeDraw(draw)) = s.editor.annotations.current_mut() {
+                    draw.simplify(SIMPLIFY_TOLERANCE);
+                }
+                s.editor.annotations.commit_current();
+            } else if tool == EditorTool::Rectangle {
+                let drag_result = s.editor.tool_state.end_drag();
+                if let Some((start, end)) = drag_result {
+                    let color = s.editor.tool_state.color;
+                    let rect = RectangleAnnotation::new(
+                        start.0,
+                        start.1,
+                        (end.0 - start.0).abs(),
+                        (end.1 - start.1).abs(),
+                        color,
+                        3.0,
+                    );
+                    s.editor.annotations.add(Annotation::Rectangle(rect));
+                }
+            } else if tool == EditorTool::Redact {
+                // A click without dragging redacts nothing
+                let rect = s.editor.tool_state.get_drag_rect();
+                s.editor.tool_state.end_drag();
+                let style = s.preferences.redact_style;
+                if let Some(rect) = rect.filter(|&(_, _, w, h)| w >= 1.0 && h >= 1.0) {
+                    s.redact(rect, style);
+                }
+            } else if tool == EditorTool::Crop {
+                // For crop, we keep the drag coordinates in ToolState but stop drawing
+                s.editor.tool_state.is_drawing = false;
+            }
+        }
+    }
+}
+
+/// Crop handle under the image point `(img_x, img_y)`, if a crop is drawn
+fn crop_handle_at(state: &AppState, img_x: f64, img_y: f64) -> Option<CropHandle> {
+    let radius = CROP_HANDLE_RADIUS / state.editor.display_scale;
+    state
+        .editor
+        .tool_state
+        .get_drag_rect()
+        .and_then(|rect| CropHandle::at(rect, img_x, img_y, radius))
+}
+
+pub fn connect_click_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let click = GestureClick::new();
+    click.connect_pressed({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        let text_popover = components.text_popover.text_popover.clone();
+        let text_view = components.text_popover.text_view.clone();
+        let components = components.clone();
+        move |_gesture, _n_press, x, y| {
+            let mut s = state.borrow_mut();
+            if s.is_active && s.mode == CaptureMode::Window {
+                let mut covered = None;
+                if let Some(picked) = s.hover_window(x, y) {
+                    s.capture_metadata = s.capture_metadata.take().map(|m| m.with_window(&picked));
+                    let covering = occluding_windows(&s.window_targets, &picked).len();
+                    if confirm_selection(
+                        &mut s,
+                        &components.window,
+                        &components.header.header_bar,
+                        &components.toolbar.tools_box,
+                        &components.crop_toolbar.crop_tools_box,
+                    ) {
+                        s.window_targets.clear();
+                        drawing_area.set_cursor_from_name(None);
+                        if s.record_mode {
+                            s.pending_recording_window = Some(picked.clone());
+                        }
+                        if covering > 0 && !s.record_mode {
+                            covered = Some((picked, covering));
+                        }
+                    }
+                }
+                let confirmed = !s.is_active;
+                drop(s);
+                drawing_area.queue_draw();
+                if let Some((picked, covering)) = covered {
+                    report_covered_window(&state, &components, picked, covering);
+                }
+                if confirmed {
+                    finish_quick_capture(&state, &components, CaptureMode::Window);
+                }
+                return;
+            }
+
+            if s.final_image.is_some() {
+                if s.editor.current_tool() == EditorTool::Text && s.text_on_canvas {
+                    drop(s);
+                    begin_canvas_text(&state, &components.drawing, x, y);
+                } else if s.editor.current_tool() == EditorTool::Text {
+                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
+                    s.editor.pending_text = Some(crate::editor::PendingText::new(img_x, img_y));
+                    // Text view handlers borrow the state while the text changes
+                    drop(s);
+
+                    let rect = gtk::gdk::Rectangle::new(x as i32, y as i32, 1, 1);
+                    text_popover.set_pointing_to(Some(&rect));
+                    text_popover.popup();
+                    text_view.buffer().set_text("");
+                    text_view.grab_focus();
+                } else if s.editor.current_tool() == EditorTool::Counter {
+                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
+                    s.editor.place_counter(img_x, img_y);
+                } else if s.editor.current_tool() == EditorTool::ColorPicker {
+                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
+                    if let Some(ref pixbuf) = s.final_image {
+                        if let Ok(picked) =
+                            pick_color_from_pixbuf(pixbuf, img_x as i32, img_y as i32)
+                        {
+                            s.editor.set_color(picked.color);
+                        }
+                    }
+                }
+            }
+            drawing_area.queue_draw();
+        }
+    });
+    components.drawing.drawing_area.add_controller(click);
+
+    let motion = gtk::EventControllerMotion::new();
+    motion.connect_motion({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |_, x, y| {
+            let mut s = state.borrow_mut();
+            if s.is_active && s.mode == CaptureMode::Window {
+                s.hover_window(x, y);
+                drop(s);
+                drawing_area.queue_draw();
+            } else if s.is_active && s.placing_selection {
+                s.place_selection_at(x, y);
+                drop(s);
+                drawing_area.queue_draw();
+            } else if !s.is_active {
+                let cursor = if s.is_crop_mode {
+                    let (img_x, img_y) = s.editor.display_to_image_coords(x, y);
+                    crop_handle_at(&s, img_x, img_y).map(CropHandle::cursor_name)
+                } else {
+                    None
+                };
+                drawing_area.set_cursor_from_name(cursor);
+            }
+        }
+    });
+    components.drawing.drawing_area.add_controller(motion);
+}
+
+fn confirm_selection(
+    state: &mut AppState,
+    window: &adw::ApplicationWindow,
+    header_bar: &adw::HeaderBar,
+    tools_box: &gtk::Box,
+    crop_tools_box: &gtk::Box,
+) -> bool {
+    update_overlay_geometry(state, window);
+    if state.record_mode {
+        // Recorded by `finish_quick_capture`, once the overlay is gone
+        let area = match state.selection_recording_area() {
+            Some(area) => area,
+            None => return false,
+        };
+        state.pending_recording = Some(area);
+        state.pending_recording_window = None;
+        state.exit_capture_mode();
+        state.final_image = None;
+        window.unfullscreen();
+        header_bar.set_visible(true);
+        crop_tools_box.set_visible(false);
+        return true;
+    }
+    if state.apply_selection_crop() {
+        state.is_active = false;
+        state.selection = None;
+        state.placing_selection = false;
+        if let Some(ref image) = state.final_image {
+            state.preferences.last_selection_size = Some((image.width(), image.height()));
+            state.preferences.save();
+        }
+        state.auto_save_capture();
+        window.unfullscreen();
+        header_bar.set_visible(true);
+        tools_box.set_visible(true);
+        crop_tools_box.set_visible(false);
+        return true;
+    }
+    false
+}
+
+/// Record the overlay's width and scale, which map the selection to
+/// screenshot pixels
+fn update_overlay_geometry(state: &mut AppState, window: &adw::ApplicationWindow) {
+    state.overlay_geometry = window
+        .surface()
+        .map(|surface| (window.width(), surface.scale()));
+}
+
+/// Copy or save a capture just taken in `mode` and hide the window, when the
+/// preferences say so for that mode. A notification offers to open the
+/// editor after all; the process stays around so the clipboard keeps its
+/// contents.
+fn finish_quick_capture(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    mode: CaptureMode,
+) {
+    let (pending_recording, window) = {
+        let mut s = state.borrow_mut();
+        (
+            s.pending_recording.take(),
+            s.pending_recording_window.take(),
+        )
+    };
+    if let Some(area) = pending_recording {
+        start_recording(state, components, area, window);
+        return;
+    }
+
+    let quick_capture = state.borrow().preferences.quick_capture(mode);
+    let title = match quick_capture {
+        QuickCapture::Off => return,
+        QuickCapture::Copy => {
+            perform_copy(state, &components.window);
+            "Screenshot copied to clipboard".to_string()
+        }
+        QuickCapture::Save => match save_quick_capture(state) {
+            Ok(path) => format!("Screenshot saved to {}", path.display()),
+            Err(e) => {
+                // Stay in the editor, so the capture is not lost
+                error!("Failed to save capture: {}", e);
+                components.window.present();
+                components
+                    .toast_overlay
+                    .add_toast(adw::Toast::new("Failed to save capture"));
+                return;
+            }
+        },
+    };
+    info!("Quick capture finished: {}", title);
+    components.window.set_visible(false);
+
+    if let Some(app) = components.window.application() {
+        let notification = gio::Notification::new(&title);
+        notification.set_body(Some("Open it in the editor to annotate or crop it"));
+        notification.add_button("Edit", "app.edit-capture");
+        notification.set_default_action("app.edit-capture");
+        app.send_notification(Some(QUICK_CAPTURE_NOTIFICATION), &notification);
+    }
+}
+
+/// The auto-saved file if there is one, otherwise a new file in the
+/// screenshots folder
+fn save_quick_capture(state: &Rc<RefCell<AppState>>) -> Result<std::path::PathBuf, String> {
+    let mut s = state.borrow_mut();
+    if let Some(ref path) = s.auto_saved_path {
+        return Ok(path.clone());
+    }
+    let pixbuf = s
+        .final_image
+        .clone()
+        .ok_or_else(|| "No capture to save".to_string())?;
+    let metadata = s
+        .capture_metadata
+        .clone()
+        .unwrap_or_else(|| CaptureMetadata::new(CaptureSource::Screen));
+    let path = auto_save(
+        &pixbuf,
+        &metadata,
+        &s.filename_template,
+        AutoSaveLayout::Flat,
+    )
+    .map_err(|e| e.to_string())?;
+    s.auto_saved_path = Some(path.clone());
+    Ok(path)
+}
+
+/// Point out that windows stacked over the one picked by clicking are in the
+/// capture, offering to grab it again once raised where the compositor can
+fn report_covered_window(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    picked: WindowInfo,
+    covering: usize,
+) {
+    warn!(
+        "'{}' is covered by {} other window(s)",
+        picked.display_label(),
+        covering
+    );
+    let toast = adw::Toast::new(&format!(
+        "Other windows cover part of \"{}\"",
+        if picked.title.is_empty() {
+            &picked.app_name
+        } else {
+            &picked.title
+        }
+    ));
+    if DesktopSession::detect()
+        .window_list_backend()
+        .raises_window()
+    {
+        toast.set_button_label(Some("Raise and Capture"));
+        toast.connect_button_clicked({
+            let state = state.clone();
+            let components = components.clone();
+            move |_| raise_and_capture(&state, &components, picked.clone())
+        });
+    }
+    components.toast_overlay.add_toast(toast);
+}
+
+pub fn connect_crop_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.crop_toolbar.confirm_btn.connect_clicked({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        let tools_box = components.toolbar.tools_box.clone();
+        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
+        move |_| {
+            let mut s = state.borrow_mut();
+            if s.apply_editor_crop() {
+                s.exit_crop_mode();
+                tools_box.set_visible(true);
+                crop_tools_box.set_visible(false);
+                drawing_area.queue_draw();
+            }
+        }
+    });
+
+    components.crop_toolbar.cancel_btn.connect_clicked({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        let tools_box = components.toolbar.tools_box.clone();
+        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
+        move |_| {
+            let mut s = state.borrow_mut();
+            s.exit_crop_mode();
+            tools_box.set_visible(true);
+            crop_tools_box.set_visible(false);
+            drawing_area.queue_draw();
+        }
+    });
+
+    // Show the whole image again, with the old crop ready to adjust
+    components.crop_toolbar.reveal_btn.connect_clicked({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |btn| {
+            let mut s = state.borrow_mut();
+            if let Some((x, y, w, h)) = s.reveal_original() {
+                s.editor.tool_state.start_drag(x as f64, y as f64);
+                s.editor
+                    .tool_state
+                    .update_drag((x + w) as f64, (y + h) as f64);
+                s.editor.tool_state.is_drawing = false;
+                btn.set_sensitive(false);
+                drop(s);
+                drawing_area.queue_draw();
+            }
+        }
+    });
+
+    components.crop_toolbar.crop_tools_box.connect_show({
+        let state = state.clone();
+        let reveal_btn = components.crop_toolbar.reveal_btn.clone();
+        move |_| {
+            reveal_btn.set_sensitive(state.borrow().editor.crop.is_some());
+        }
+    });
+}
+
+/// Fixed-size selections from the selection toolbar, as `win.selection-preset`
+/// with a `WIDTHxHEIGHT` target
+fn connect_selection_presets(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let action_preset = gio::SimpleAction::new("selection-preset", Some(glib::VariantTy::STRING));
+    action_preset.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, param| {
+            let size = param.and_then(|v| v.get::<String>()).and_then(|id| {
+                let (width, height) = id.split_once('x')?;
+                Some((width.parse::<i32>().ok()?, height.parse::<i32>().ok()?))
+            });
+            let size = match size {
+                Some(size) => size,
+                None => return,
+            };
+
+            let mut s = state.borrow_mut();
+            if !s.is_active || s.mode != CaptureMode::Selection {
+                return;
+            }
+            update_overlay_geometry(&mut s, &components.window);
+            s.select_preset(size, None);
+            s.placing_selection = s.preferences.place_presets_with_pointer;
+            drop(s);
+
+            // Clicking has to put the preset down, not draw
+            components.selection_toolbar.select_btn.set_active(true);
+            components.drawing.drawing_area.queue_draw();
+        }
+    });
+    components.window.add_action(&action_preset);
+
+    let place_with_pointer = state.borrow().preferences.place_presets_with_pointer;
+    let action_place_with_pointer = gio::SimpleAction::new_stateful(
+        "place-presets-with-pointer",
+        None,
+        &place_with_pointer.to_variant(),
+    );
+    action_place_with_pointer.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.place_presets_with_pointer = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_place_with_pointer);
+
+    // Rebuilt on opening, for the size of the latest selection
+    let size_btn = &components.selection_toolbar.size_btn;
+    size_btn.set_create_popup_func({
+        let state = state.clone();
+        move |btn| {
+            let last_used = state.borrow().preferences.last_selection_size;
+            btn.set_menu_model(Some(&selection_preset_menu(last_used)));
+        }
+    });
+}
+
+fn selection_preset_menu(last_used: Option<(i32, i32)>) -> gio::Menu {
+    let sizes = gio::Menu::new();
+    let presets = SELECTION_PRESETS
+        .iter()
+        .map(|&(width, height)| (format!("{} × {}", width, height), (width, height)))
+        .chain(last_used.map(|(width, height)| {
+            (
+                format!("Last Used ({} × {})", width, height),
+                (width, height),
+            )
+        }));
+    for (label, (width, height)) in presets {
+        let item = gio::MenuItem::new(Some(&label), None);
+        item.set_action_and_target_value(
+            Some("win.selection-preset"),
+            Some(&format!("{}x{}", width, height).to_variant()),
+        );
+        sizes.append_item(&item);
+    }
+
+    let menu = gio::Menu::new();
+    menu.append_section(None, &sizes);
+    menu.append(
+        Some("Place with Pointer"),
+        Some("win.place-presets-with-pointer"),
+    );
+    menu
+}
+
+pub fn connect_selection_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    // Overlay tools go through the editor's own, so the editor opens with
+    // the last one picked
+    let selection_toolbar = &components.selection_toolbar;
+    let toolbar = &components.toolbar;
+    for (button, tool_button) in [
+        (&selection_toolbar.select_btn, &toolbar.tool_pointer_btn),
+        (&selection_toolbar.pencil_btn, &toolbar.tool_pencil_btn),
+        (
+            &selection_toolbar.rectangle_btn,
+            &toolbar.tool_rectangle_btn,
+        ),
+    ] {
+        button.connect_toggled({
+            let tool_button = tool_button.clone();
+            move |btn| {
+                if btn.is_active() {
+                    tool_button.set_active(true);
+                }
+            }
+        });
+    }
+
+    connect_selection_presets(state, components);
+
+    components.selection_toolbar.confirm_btn.connect_clicked({
+        let state = state.clone();
+        let window = components.window.clone();
+        let header_bar = components.header.header_bar.clone();
+        let tools_box = components.toolbar.tools_box.clone();
+        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
+        let selection_tools_box = components.selection_toolbar.selection_tools_box.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        let components = components.clone();
+        move |_| {
+            let mut s = state.borrow_mut();
+            if confirm_selection(
+                &mut s,
+                &window,
+                &header_bar,
+                &tools_box,
+                &crop_tools_box,
+            ) {
+                selection_tools_box.set_visible(false);
+                drop(s);
+                drawing_area.queue_draw();
+                finish_quick_capture(&state, &components, CaptureMode::Selection);
+            }
+        }
+    });
+
+    components.selection_toolbar.cancel_btn.connect_clicked({
+        let state = state.clone();
+        let window = components.window.clone();
+        let header_bar = components.header.header_bar.clone();
+        let tools_box = components.toolbar.tools_box.clone();
+        let crop_tools_box = components.crop_toolbar.crop_tools_box.clone();
+        let selection_tools_box = components.selection_toolbar.selection_tools_box.clone();
+        let placeholder_icon = components.drawing.placeholder_icon.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |_| {
+            let mut s = state.borrow_mut();
+            s.exit_capture_mode();
+            window.unfullscreen();
+            header_bar.set_visible(true);
+            tools_box.set_visible(s.final_image.is_some());
+            crop_tools_box.set_visible(false);
+            selection_tools_box.set_visible(false);
+            if s.final_image.is_none() {
+                placeholder_icon.set_visible(true);
+            }
+            drop(s);
+            drawing_area.queue_draw();
+        }
+    });
+}
+
+pub fn connect_screenshot_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.header.take_screenshot_btn.connect_clicked({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| {
+            let mode = state.borrow().mode;
+            capture_screen_or_selection(&state, &components, mode);
+        }
+    });
+}
+
+/// Set the drawing color to a palette slot and name it on the canvas for a moment
+fn switch_to_palette_color(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    slot: usize,
+) -> bool {
+    {
+        let s = state.borrow();
+        // Digits typed into a text annotation are text
+        if s.is_active || s.final_image.is_none() || s.editor.pending_text.is_some() {
+            return false;
+        }
+    }
+    let preset = state.borrow().preferences.palette;
+    let (name, color) = match palette_colors(preset).get(slot) {
+        Some(entry) => *entry,
+        None => return false,
+    };
+
+    // The color button's notify handler updates the editor
+    components.toolbar.color_button.set_rgba(&color);
+    show_color_hint(state, components, name.to_string(), color);
+    true
+}
+
+/// Name `color` on the canvas for a moment, replacing any hint showing
+fn show_color_hint(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    label: String,
+    color: gtk::gdk::RGBA,
+) {
+    let mut s = state.borrow_mut();
+    s.color_hint = Some((label, color));
+    if let Some(timeout) = s.color_hint_timeout.take() {
+        timeout.remove();
+    }
+    s.color_hint_timeout = Some(glib::timeout_add_local_once(Duration::from_millis(1200), {
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move || {
+            let mut s = state.borrow_mut();
+            s.color_hint = None;
+            s.color_hint_timeout = None;
+            drop(s);
+            drawing_area.queue_draw();
+        }
+    }));
+    drop(s);
+
+    components.drawing.drawing_area.queue_draw();
+}
+
+/// Ctrl+scroll over the canvas changes the line width or font size of the
+/// active tool, shown next to a swatch of the drawing color
+pub fn connect_scroll_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let scroll = gtk::EventControllerScroll::new(
+        gtk::EventControllerScrollFlags::VERTICAL | gtk::EventControllerScrollFlags::DISCRETE,
+    );
+    scroll.connect_scroll({
+        let state = state.clone();
+        let components = components.clone();
+        move |controller, _, dy| {
+            if !controller
+                .current_event_state()
+                .contains(gtk::gdk::ModifierType::CONTROL_MASK)
+            {
+                return glib::Propagation::Proceed;
+            }
+            let (readout, color) = {
+                let mut s = state.borrow_mut();
+                if s.is_active || s.final_image.is_none() {
+                    return glib::Propagation::Proceed;
+                }
+                let color = s.editor.tool_state.color;
+                // Scrolling up grows
+                match s.editor.tool_state.adjust_size(-dy.signum()) {
+                    Some(readout) => (readout, color),
+                    None => return glib::Propagation::Proceed,
+                }
+            };
+            show_color_hint(&state, &components, readout, color);
+            glib::Propagation::Stop
+        }
+    });
+    components.drawing.drawing_area.add_controller(scroll);
+}
+
+/// Direction of an arrow key as `(dx, dy)`
+fn arrow_offset(key: gtk::gdk::Key) -> Option<(f64, f64)> {
+    match key {
+        gtk::gdk::Key::Left | gtk::gdk::Key::KP_Left => Some((-1.0, 0.0)),
+        gtk::gdk::Key::Right | gtk::gdk::Key::KP_Right => Some((1.0, 0.0)),
+        gtk::gdk::Key::Up | gtk::gdk::Key::KP_Up => Some((0.0, -1.0)),
+        gtk::gdk::Key::Down | gtk::gdk::Key::KP_Down => Some((0.0, 1.0)),
+        _ => None,
+    }
+}
+
+pub fn connect_keyboard_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let key_controller = EventControllerKey::new();
+    key_controller.set_propagation_phase(gtk::PropagationPhase::Capture);
+
+    key_controller.connect_key_pressed({
+        let state = state.clone();
+        let components = components.clone();
+
+        move |_, key, _code, modifier| {
+            // Keys belong to the text being typed on the canvas
+            if {
+                let s = state.borrow();
+                s.text_on_canvas && s.editor.pending_text.is_some()
+            } {
+                return glib::Propagation::Proceed;
+            }
+
+            // Held Space turns dragging the selection into moving it
+            if key == gtk::gdk::Key::space {
+                let mut s = state.borrow_mut();
+                if s.is_active && s.mode == CaptureMode::Selection {
+                    s.selection_space_held = true;
+                    return glib::Propagation::Stop;
+                }
+            }
+
+            // Delete and Backspace remove the annotation picked with the pointer
+            if matches!(
+                key,
+                gtk::gdk::Key::Delete | gtk::gdk::Key::KP_Delete | gtk::gdk::Key::BackSpace
+            ) {
+                let mut s = state.borrow_mut();
+                if !s.is_active && s.editor.delete_selected() {
+                    drop(s);
+                    components.drawing.drawing_area.queue_draw();
+                    return glib::Propagation::Stop;
+                }
+            }
+
+            // Arrows nudge the selection, by a larger step with Shift
+            if let Some((dx, dy)) = arrow_offset(key) {
+                let mut s = state.borrow_mut();
+                if s.is_active && s.mode == CaptureMode::Selection && s.selection.is_some() {
+                    let step = if modifier.contains(gtk::gdk::ModifierType::SHIFT_MASK) {
+                        SELECTION_NUDGE_STEP_LARGE
+                    } else {
+                        1.0
+                    };
+                    s.nudge_selection(dx * step, dy * step);
+                    drop(s);
+                    components.drawing.drawing_area.queue_draw();
+                    return glib::Propagation::Stop;
+                }
+            }
+
+            let (action, _mode, _is_active) = {
+                let s = state.borrow();
+                (s.shortcuts.get_action(key, modifier), s.mode, s.is_active)
+            };
+
+            if let Some(action) = action {
+                debug!("Shortcut detected: {:?}", action);
+                match action {
+                    Action::Copy => {
+                        perform_copy(&state, &components.window);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::Save => {
+                        perform_save(
+                            state.clone(),
+                            components.window.clone(),
+                            components.toast_overlay.clone(),
+                        );
+                        return glib::Propagation::Stop;
+                    }
+                    Action::Undo => {
+                        perform_undo(&state, &components.drawing.drawing_area);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::Redo => {
+                        perform_redo(&state, &components.drawing.drawing_area);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ExportAgain => {
+                        perform_export_again(&state, &components);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::SendToChat => {
+                        send_to_chat(&state, &components);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::Cancel => {
+                        if cancel_capture(&state, &components) {
+                            return glib::Propagation::Stop;
+                        }
+                        let mut s = state.borrow_mut();
+                        if s.is_active {
+                            debug!("Canceling selection via shortcut");
+                            s.exit_capture_mode();
+                            components.drawing.drawing_area.set_cursor_from_name(None);
+                            components.window.unfullscreen();
+                            components.header.header_bar.set_visible(true);
+                            components.toolbar.tools_box.set_visible(s.final_image.is_some());
+                            components.crop_toolbar.crop_tools_box.set_visible(false);
+                            components.selection_toolbar.selection_tools_box.set_visible(false);
+                            if s.final_image.is_none() {
+                                components.drawing.placeholder_icon.set_visible(true);
+                            }
+                            drop(s);
+                            components.drawing.drawing_area.queue_draw();
+                            return glib::Propagation::Stop;
+                        } else if s.is_crop_mode {
+                            s.exit_crop_mode();
+                            drop(s);
+                            components.crop_toolbar.crop_tools_box.set_visible(false);
+                            components.toolbar.tools_box.set_visible(true);
+                            components.drawing.drawing_area.queue_draw();
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                    Action::Confirm => {
+                        let mut s = state.borrow_mut();
+                        if s.is_active && s.mode == CaptureMode::Selection {
+                            if confirm_selection(
+                                &mut s,
+                                &components.window,
+                                &components.header.header_bar,
+                                &components.toolbar.tools_box,
+                                &components.crop_toolbar.crop_tools_box,
+                            ) {
+                                components.selection_toolbar.selection_tools_box.set_visible(false);
+                                drop(s);
+                                components.drawing.drawing_area.queue_draw();
+                                finish_quick_capture(&state, &components, CaptureMode::Selection);
+                            }
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                    Action::ToolPointer => {
+                        let mut s = state.borrow_mut();
+                        s.editor.set_tool(EditorTool::Pointer);
+                        drop(s);
+                        components.drawing.drawing_area.queue_draw();
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ToolPencil => {
+                        let mut s = state.borrow_mut();
+                        s.editor.set_tool(EditorTool::Pencil);
+                        drop(s);
+                        components.drawing.drawing_area.queue_draw();
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ToolRectangle => {
+                        let mut s = state.borrow_mut();
+                        s.editor.set_tool(EditorTool::Rectangle);
+                        drop(s);
+                        components.drawing.drawing_area.queue_draw();
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ToolText => {
+                        let mut s = state.borrow_mut();
+                        s.editor.set_tool(EditorTool::Text);
+                        drop(s);
+                        components.drawing.drawing_area.queue_draw();
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ToolCounter => {
+                        let mut s = state.borrow_mut();
+                        s.editor.set_tool(EditorTool::Counter);
+                        drop(s);
+                        components.drawing.drawing_area.queue_draw();
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ToolCrop => {
+                        let mut s = state.borrow_mut();
+                        if s.final_image.is_some() {
+                            s.is_crop_mode = true;
+                            s.editor.set_tool(EditorTool::Crop);
+                            drop(s);
+                            components.toolbar.tools_box.set_visible(false);
+                            components.crop_toolbar.crop_tools_box.set_visible(true);
+                            components.drawing.drawing_area.queue_draw();
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                    Action::SwitchToSelection => {
+                        let mut s = state.borrow_mut();
+                        s.mode = CaptureMode::Selection;
+                        components.header.mode_selection_btn.set_active(true);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::SwitchToWindow => {
+                        let mut s = state.borrow_mut();
+                        s.mode = CaptureMode::Window;
+                        components.header.mode_window_btn.set_active(true);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::SwitchToScreen => {
+                        let mut s = state.borrow_mut();
+                        s.mode = CaptureMode::Screen;
+                        components.header.mode_screen_btn.set_active(true);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::TakeScreenshot => {
+                        let mode = state.borrow().mode;
+                        capture_screen_or_selection(&state, &components, mode);
+                        return glib::Propagation::Stop;
+                    }
+                    Action::ColorSlot(slot) => {
+                        if switch_to_palette_color(&state, &components, slot) {
+                            return glib::Propagation::Stop;
+                        }
+                    }
+                }
+            }
+            glib::Propagation::Proceed
+        }
+    });
+
+    key_controller.connect_key_released({
+        let state = state.clone();
+        move |_, key, _, _| {
+            if key == gtk::gdk::Key::space {
+                state.borrow_mut().selection_space_held = false;
+            }
+        }
+    });
+
+    components.window.add_controller(key_controller);
+}
+
+/// Shrink the window to the header bar's capture controls, or bring the
+/// editor back at the size it had
+fn set_compact_mode(state: &Rc<RefCell<AppState>>, components: &UiComponents, compact: bool) {
+    let window = &components.window;
+    let mut s = state.borrow_mut();
+    if compact == s.compact_size.is_some() {
+        return;
+    }
+
+    if compact {
+        s.compact_size = Some(window.default_size());
+        drop(s);
+        debug!("Entering compact mode");
+        window.unmaximize();
+        components.toast_overlay.set_visible(false);
+        // The smallest size the header bar allows
+        window.set_default_size(1, 1);
+    } else {
+        let (width, height) = s.compact_size.take().unwrap_or(DEFAULT_WINDOW_SIZE);
+        drop(s);
+        debug!("Leaving compact mode");
+        components.toast_overlay.set_visible(true);
+        window.set_default_size(width, height);
+    }
+
+    if let Some(action) = window
+        .lookup_action("compact-mode")
+        .and_downcast::<gio::SimpleAction>()
+    {
+        action.set_state(&compact.to_variant());
+    }
+}
+
+pub fn capture_screen_or_selection(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    mode: CaptureMode,
+) {
+    // Selecting needs the canvas, and the capture lands in the editor
+    set_compact_mode(state, components, false);
+
+    {
+        let mut s = state.borrow_mut();
+        let invocation = s.invocation;
+        s.preferences.remember_capture_mode(invocation, mode);
+        s.preferences.save();
+    }
+
+    let record = state.borrow().record_mode;
+    if record {
+        if state.borrow().recording.is_some() {
+            debug!("A recording is already running");
+            return;
+        }
+        if !recording_available() {
+            warn!("gst-launch-1.0 is not installed");
+            components.toast_overlay.add_toast(adw::Toast::new(
+                "Recording needs GStreamer's gst-launch-1.0",
+            ));
+            return;
+        }
+    }
+
+    if mode == CaptureMode::Selection && state.borrow().use_portal_picker && !record {
+        capture_with_portal(state, components);
+        return;
+    }
+
+    // A recorded window is picked on the overlay, which gives its area on screen
+    if mode == CaptureMode::Window && !state.borrow().pick_window_by_click && !record {
+        show_window_selector(
+            state,
+            &components.window,
+            &components.drawing.drawing_area,
+            &components.drawing.placeholder_icon,
+            &components.toolbar.tools_box,
+            Rc::new({
+                let state = state.clone();
+                let components = components.clone();
+                move || finish_quick_capture(&state, &components, CaptureMode::Window)
+            }),
+        );
+        return;
+    }
+
+    // Recording a screen needs no screenshot to pick from
+    let needs_screenshot = !(record && mode == CaptureMode::Screen);
+    if let Some(tools) = missing_screen_capture_tools().filter(|_| needs_screenshot) {
+        report_missing_tools(state, components, tools);
+        return;
+    }
+
+    if state.borrow().capture_cancellable.is_some() {
+        debug!("A capture is already pending");
+        return;
+    }
+    let cancellable = gio::Cancellable::new();
+    state.borrow_mut().capture_cancellable = Some(cancellable.clone());
+
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        // With several monitors, Screen mode asks which one to capture
+        let monitors = display_monitors(&components.window.display());
+        let (invocation, last_monitor, chosen_monitor, all_monitors) = {
+            let s = state.borrow();
+            let position = |connector: Option<&str>| {
+                monitors
+                    .iter()
+                    .position(|m| connector.is_some() && m.connector().as_deref() == connector)
+            };
+            let last_monitor = position(s.preferences.capture_monitor(s.invocation));
+            let chosen_monitor = position(s.preferences.screen_monitor.as_deref());
+            let all_monitors =
+                mode == CaptureMode::Screen && monitors.len() > 1 && s.preferences.all_monitors;
+            (s.invocation, last_monitor, chosen_monitor, all_monitors)
+        };
+        let mut picker = None;
+        let mut monitor = None;
+        if mode == CaptureMode::Screen && monitors.len() > 1 && !all_monitors {
+            if chosen_monitor.is_some() {
+                // Picked in the header, which stands until changed there
+                monitor = chosen_monitor;
+            } else if invocation == InvocationSource::Hotkey && last_monitor.is_some() {
+                // The hotkey repeats its last capture without asking
+                monitor = last_monitor;
+            } else {
+                let shown = MonitorPicker::show(&monitors, last_monitor);
+                monitor = shown.choice().await;
+                if monitor.is_none() {
+                    shown.close();
+                    state.borrow_mut().capture_cancellable = None;
+                    info!("Capture cancelled while picking a monitor");
+                    components
+                        .toast_overlay
+                        .add_toast(adw::Toast::new("Capture cancelled"));
+                    return;
+                }
+                picker = Some(shown);
+            }
+
+            if let Some(index) = monitor {
+                let mut s = state.borrow_mut();
+                let connector = monitors[index].connector().map(|c| c.to_string());
+                s.preferences
+                    .remember_capture_monitor(invocation, connector);
+                s.preferences.save();
+            }
+        }
+
+        let (delay_seconds, hide_window) = {
+            let s = state.borrow();
+            // Selecting happens on a still of the screen without us on it
+            let hide_window = mode != CaptureMode::Screen || s.preferences.hide_own_window;
+            (s.delay_seconds, hide_window)
+        };
+        if hide_window {
+            components.window.set_visible(false);
+        }
+        count_down(&components, delay_seconds, &cancellable).await;
+        state.borrow_mut().capture_cancellable = None;
+
+        if cancellable.is_cancelled() {
+            info!("Capture cancelled");
+            if let Some(picker) = picker {
+                picker.close();
+            }
+            components.window.set_visible(true);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Capture cancelled"));
+            return;
+        }
+
+        if !needs_screenshot {
+            if let Some(picker) = picker {
+                picker.close();
+            }
+            let targets: Vec<MonitorTarget> = monitors.iter().map(monitor_target).collect();
+            let target = if all_monitors {
+                desktop_target(&targets)
+            } else {
+                targets.get(monitor.unwrap_or(0)).cloned()
+            };
+            match target {
+                Some(target) => {
+                    let area = RecordingArea {
+                        x: target.x,
+                        y: target.y,
+                        width: target.width,
+                        height: target.height,
+                    };
+                    start_recording(&state, &components, area, None);
+                }
+                None => {
+                    error!("No monitor to record");
+                    components.window.set_visible(true);
+                }
+            }
+            return;
+        }
+
+        let main_window: &gtk::Window = components.window.upcast_ref();
+        let picker_windows = picker.as_ref().map(|p| p.windows()).unwrap_or_default();
+        let mut hidden = vec![main_window, &components.countdown.window];
+        hidden.extend(picker_windows.iter());
+        wait_until_hidden(&hidden).await;
+        if let Some(picker) = picker {
+            picker.close();
+        }
+        grab_screen(&state, &components, mode, monitor, all_monitors);
+    });
+}
+
+/// The area spanning all of the `monitors`, to record them all at once
+fn desktop_target(monitors: &[MonitorTarget]) -> Option<MonitorTarget> {
+    let left = monitors.iter().map(|m| m.x).min()?;
+    let top = monitors.iter().map(|m| m.y).min()?;
+    let right = monitors.iter().map(|m| m.x + m.width).max()?;
+    let bottom = monitors.iter().map(|m| m.y + m.height).max()?;
+    Some(MonitorTarget {
+        connector: None,
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Resolve once none of the hidden `windows` is on screen any more, so
+/// they can't end up in the capture
+async fn wait_until_hidden(windows: &[&gtk::Window]) {
+    for window in windows {
+        if !surface_unmapped(window).await {
+            warn!("Capturing although our window is still on screen");
+        }
+    }
+
+    let window = match windows.first() {
+        Some(window) => window,
+        None => return,
+    };
+    // Round trip, so the compositor has handled every request made so far,
+    // then let it repaint the screen without us
+    let display = window.display();
+    display.sync();
+    glib::timeout_future(frame_interval(&display) * 2).await;
+}
+
+/// Resolve as soon as the surface of a hidden `window` reports it is
+/// unmapped. False if that didn't happen within `HIDE_TIMEOUT`.
+async fn surface_unmapped(window: &gtk::Window) -> bool {
+    let surface = match window.surface() {
+        Some(surface) if !window.is_visible() && surface.is_mapped() => surface,
+        _ => return true,
+    };
+
+    // Cancelled from the notify handler, as a one-shot signal to await
+    let unmapped = gio::Cancellable::new();
+    let handler = surface.connect_mapped_notify({
+        let unmapped = unmapped.clone();
+        move |surface| {
+            if !surface.is_mapped() {
+                unmapped.cancel();
+            }
+        }
+    });
+    let result = glib::future_with_timeout(HIDE_TIMEOUT, unmapped.future()).await;
+    surface.disconnect(handler);
+    result.is_ok()
+}
+
+/// Time between two frames of the primary monitor, 60 Hz if unknown
+fn frame_interval(display: &gtk::gdk::Display) -> Duration {
+    let refresh_rate = display
+        .monitors()
+        .item(0)
+        .and_downcast::<gtk::gdk::Monitor>()
+        .map(|monitor| monitor.refresh_rate())
+        .filter(|&millihertz| millihertz > 0)
+        .unwrap_or(60_000);
+    Duration::from_micros(1_000_000_000 / refresh_rate as u64)
+}
+
+/// Show the countdown for the capture delay, resolving once it is over or
+/// `cancellable` is cancelled
+async fn count_down(components: &UiComponents, delay_seconds: u32, cancellable: &gio::Cancellable) {
+    // Keep the screen from blanking or locking before the delay is over
+    let inhibitor = match components.window.application() {
+        Some(app) if delay_seconds > 0 => Some((
+            app.inhibit(
+                None::<&gtk::Window>,
+                gtk::ApplicationInhibitFlags::IDLE,
+                Some("Waiting to take a screenshot"),
+            ),
+            app,
+        )),
+        _ => None,
+    };
+    for remaining in (1..=delay_seconds).rev() {
+        components.countdown.show_remaining(remaining);
+        // Checked often so a cancel takes effect right away
+        for _ in 0..10 {
+            glib::timeout_future(Duration::from_millis(100)).await;
+            if cancellable.is_cancelled() {
+                break;
+            }
+        }
+        if cancellable.is_cancelled() {
+            break;
+        }
+    }
+    components.countdown.hide();
+    if let Some((cookie, app)) = inhibitor {
+        app.uninhibit(cookie);
+    }
+}
+
+/// Abort a capture still waiting out its delay. Returns false when there
+/// is none.
+fn cancel_capture(state: &Rc<RefCell<AppState>>, components: &UiComponents) -> bool {
+    let cancellable = state.borrow().capture_cancellable.clone();
+    match cancellable {
+        Some(cancellable) => {
+            components.countdown.show_cancelling();
+            cancellable.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// List the windows to pick from on a worker, as asking the compositor can
+/// block, then show the overlay. They are listed while our window is hidden,
+/// so it never shows up as a target.
+fn show_window_targets(
+    state: &Rc<RefCell<AppState>>,
+    window: &adw::ApplicationWindow,
+    drawing_area: &gtk::DrawingArea,
+) {
+    let state = state.clone();
+    let window = window.clone();
+    let drawing_area = drawing_area.clone();
+    glib::spawn_future_local(async move {
+        let windows = match gio::spawn_blocking(list_capturable_windows).await {
+            Ok(Ok(windows)) => windows,
+            Ok(Err(e)) => {
+                error!("Failed to list windows: {}", e);
+                Vec::new()
+            }
+            Err(_) => {
+                error!("Window listing task panicked");
+                Vec::new()
+            }
+        };
+
+        let mut s = state.borrow_mut();
+        let (monitor_x, monitor_y) = (s.monitor_x, s.monitor_y);
+        s.window_targets = windows
+            .into_iter()
+            .map(|mut w| {
+                w.x -= monitor_x;
+                w.y -= monitor_y;
+                w
+            })
+            .collect();
+        drop(s);
+
+        window.set_visible(true);
+        window.fullscreen();
+        drawing_area.queue_draw();
+    });
+}
+
+/// Take the screenshot once the window is hidden, then open the editor or
+/// the selection overlay. `monitor` indexes `display_monitors`; without one
+/// the primary monitor is captured, or with `all_monitors` every monitor
+/// stitched into one image.
+fn grab_screen(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    mode: CaptureMode,
+    monitor: Option<usize>,
+    all_monitors: bool,
+) {
+    let window = &components.window;
+    let header_bar = &components.header.header_bar;
+    let tools_box = &components.toolbar.tools_box;
+    let crop_tools_box = &components.crop_toolbar.crop_tools_box;
+    let selection_tools_box = &components.selection_toolbar.selection_tools_box;
+    let drawing_area = &components.drawing.drawing_area;
+    let placeholder_icon = &components.drawing.placeholder_icon;
+
+    let targets: Vec<MonitorTarget> = display_monitors(&window.display())
+        .iter()
+        .map(monitor_target)
+        .collect();
+    let result = match monitor.and_then(|index| targets.get(index)) {
+        _ if all_monitors => capture_all_monitors(&targets),
+        Some(target) => capture_monitor(target, &targets),
+        None => capture_primary_monitor(),
+    };
+
+    match result {
+        Ok(result) => {
+            let mut s = state.borrow_mut();
+            s.upload_url = None;
+            s.last_export = None;
+            s.saved_path = None;
+            s.file_metadata = None;
+            s.editor.crop = None;
+            s.original_screenshot = Some(result.pixbuf.clone());
+            s.monitor_x = result.monitor_info.x;
+            s.monitor_y = result.monitor_info.y;
+
+            let source = match mode {
+                CaptureMode::Screen => CaptureSource::Screen,
+                CaptureMode::Window => CaptureSource::Window,
+                CaptureMode::Selection => CaptureSource::Region,
+            };
+            let metadata = CaptureMetadata::new(source).with_region(
+                s.monitor_x,
+                s.monitor_y,
+                result.pixbuf.width(),
+                result.pixbuf.height(),
+            );
+            s.capture_metadata = Some(describe_monitor(window, metadata, s.monitor_x, s.monitor_y));
+
+            if mode == CaptureMode::Screen {
+                s.final_image = Some(result.pixbuf);
+                s.is_active = false;
+                s.auto_save_capture();
+                placeholder_icon.set_visible(false);
+                tools_box.set_visible(true);
+                // A quick capture never shows the window
+                if s.preferences.quick_capture(mode) == QuickCapture::Off {
+                    window.set_visible(true);
+                }
+            } else if mode == CaptureMode::Window {
+                s.window_targets = Vec::new();
+                s.is_active = true;
+                s.selection = None;
+                s.final_image = None;
+
+                show_window_targets(state, window, drawing_area);
+                header_bar.set_visible(false);
+                tools_box.set_visible(false);
+                crop_tools_box.set_visible(false);
+                selection_tools_box.set_visible(false);
+                placeholder_icon.set_visible(false);
+                drawing_area.set_cursor_from_name(Some("crosshair"));
+            } else {
+                s.is_active = true;
+                s.mode = CaptureMode::Selection;
+                s.final_image = Some(result.pixbuf);
+                s.editor.reset();
+
+                window.set_visible(true);
+                window.fullscreen();
+                header_bar.set_visible(false);
+                tools_box.set_visible(false);
+                crop_tools_box.set_visible(false);
+                selection_tools_box.set_visible(true);
+                placeholder_icon.set_visible(false);
+            }
+            drop(s);
+            if mode == CaptureMode::Selection {
+                // Dragging selects until another overlay tool is picked
+                components.selection_toolbar.select_btn.set_active(true);
+                components.toolbar.tool_pointer_btn.set_active(true);
+            }
+            drawing_area.queue_draw();
+            capture_feedback(state, drawing_area);
+            if mode == CaptureMode::Screen {
+                finish_quick_capture(state, components, mode);
+            }
+        }
+        Err(e) => {
+            error!("Capture failed: {}", e);
+            window.set_visible(true);
+        }
+    }
+}
+
+/// Record `area` into the Videos folder, with the recording controls in
+/// place of the editor's toolbar
+/// Record `area`, following `window` as it moves when one was picked
+fn start_recording(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    area: RecordingArea,
+    window: Option<WindowInfo>,
+) {
+    let (format, animation, audio, limits) = {
+        let s = state.borrow();
+        (
+            s.preferences.recording_format,
+            s.preferences.animation,
+            s.preferences.recording_audio.clone(),
+            s.preferences.recording_limits,
+        )
+    };
+    let output = default_output_path(format);
+    components.window.set_visible(true);
+    components.toolbar.tools_box.set_visible(false);
+    components.drawing.placeholder_icon.set_visible(true);
+    components.drawing.drawing_area.queue_draw();
+    components.header.take_screenshot_btn.set_sensitive(false);
+
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        let mut recording = match Recording::start(area, format, audio, output).await {
+            Ok(recording) => recording,
+            Err(e) => {
+                error!("Failed to start recording: {}", e);
+                components.header.take_screenshot_btn.set_sensitive(true);
+                components.toast_overlay.add_toast(adw::Toast::new(&e));
+                return;
+            }
+        };
+        recording.set_animation_settings(animation);
+        recording.set_limits(limits);
+        state.borrow_mut().recording = Some(recording);
+        update_recording_controls(&state, &components);
+        components
+            .recording_toolbar
+            .recording_tools_box
+            .set_visible(true);
+        update_recording_indicator(&state, &components);
+        if let Some(window) = window {
+            follow_recorded_window(&state, window);
+        }
+
+        let mut overlay_revision = None;
+        glib::timeout_add_local(Duration::from_millis(250), move || {
+            let limit = match state.borrow().recording {
+                Some(ref recording) => recording.limit_reached(),
+                None => return glib::ControlFlow::Break,
+            };
+            if let Some(limit) = limit {
+                let message = match limit {
+                    RecordingLimit::Duration => "Recording stopped at its maximum length",
+                    RecordingLimit::Size => "Recording stopped at its maximum size",
+                };
+                info!("{}", message);
+                components.toast_overlay.add_toast(adw::Toast::new(message));
+                stop_recording(&state, &components);
+                return glib::ControlFlow::Break;
+            }
+            update_recording_overlay(&state, &mut overlay_revision);
+            update_recording_controls(&state, &components);
+            glib::ControlFlow::Continue
+        });
+    });
+}
+
+/// Move the recorded area along with `window` whenever the compositor reports
+/// windows changing, for as long as the recording runs. The watcher is
+/// dropped, stopping its thread, once the timeout ends.
+fn follow_recorded_window(state: &Rc<RefCell<AppState>>, window: WindowInfo) {
+    info!("Recording follows window '{}'", window.display_label());
+    let watcher = WindowWatcher::start(&DesktopSession::detect());
+    let looking_up = Rc::new(Cell::new(false));
+    let mut changed = false;
+    let state = state.clone();
+    glib::timeout_add_local(Duration::from_millis(250), move || {
+        if state.borrow().recording.is_none() {
+            debug!("Recording over, no longer following its window");
+            return glib::ControlFlow::Break;
+        }
+        // Changes seen during a lookup are picked up by the next one
+        changed |= watcher.poll_changed();
+        if !changed || looking_up.get() {
+            return glib::ControlFlow::Continue;
+        }
+        changed = false;
+        looking_up.set(true);
+
+        let state = state.clone();
+        let looking_up = looking_up.clone();
+        let window = window.clone();
+        glib::spawn_future_local(async move {
+            let located = gio::spawn_blocking(move || locate_window(&window)).await;
+            looking_up.set(false);
+            let current = match located {
+                Ok(Ok(current)) => current,
+                Ok(Err(e)) => {
+                    debug!("Recorded window not found, keeping its last area: {}", e);
+                    return;
+                }
+                Err(_) => {
+                    error!("Looking up the recorded window panicked");
+                    return;
+                }
+            };
+            // A minimized window keeps the area it had
+            if current.is_minimized || current.width == 0 || current.height == 0 {
+                return;
+            }
+            let area = RecordingArea {
+                x: current.x,
+                y: current.y,
+                width: current.width as i32,
+                height: current.height as i32,
+            };
+            if let Some(ref mut recording) = state.borrow_mut().recording {
+                if let Err(e) = recording.set_area(area) {
+                    warn!("Failed to follow the recorded window: {}", e);
+                }
+            }
+        });
+        glib::ControlFlow::Continue
+    });
+}
+
+/// Lay the annotations over the recording again if they changed since
+/// `shown_revision`
+fn update_recording_overlay(state: &Rc<RefCell<AppState>>, shown_revision: &mut Option<u64>) {
+    let s = state.borrow();
+    let recording = match s.recording {
+        Some(ref recording) if recording.has_overlay() => recording,
+        _ => return,
+    };
+    let revision = s.editor.annotations.revision();
+    if *shown_revision == Some(revision) {
+        return;
+    }
+    *shown_revision = Some(revision);
+    if let Err(e) = recording.update_overlay(&s.editor.annotations) {
+        warn!("Failed to update the recording's annotations: {}", e);
+    }
+}
+
+/// Put a transparent canvas the size of the recording in the editor, so
+/// what is drawn on it lines up with the recorded area
+fn begin_recording_annotations(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let mut s = state.borrow_mut();
+    let (width, height) = match s.recording {
+        Some(ref recording) => recording.frame_size(),
+        None => return,
+    };
+    let canvas = match gtk::gdk_pixbuf::Pixbuf::new(
+        gtk::gdk_pixbuf::Colorspace::Rgb,
+        true,
+        8,
+        width,
+        height,
+    ) {
+        Some(canvas) => canvas,
+        None => {
+            error!("Failed to create a {}x{} canvas", width, height);
+            return;
+        }
+    };
+    canvas.fill(0);
+    s.upload_url = None;
+    s.last_export = None;
+    s.saved_path = None;
+    s.file_metadata = None;
+    s.capture_metadata = None;
+    s.original_screenshot = Some(canvas.clone());
+    s.final_image = Some(canvas);
+    s.is_active = false;
+    s.editor.reset();
+    drop(s);
+
+    components.toolbar.tool_rectangle_btn.set_active(true);
+}
+
+/// Show the recording's duration, whether it is paused and which of its
+/// audio tracks are muted, in the toolbar and the indicator
+fn update_recording_controls(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let (elapsed, paused, tracks) = match state.borrow().recording {
+        Some(ref recording) => (
+            recording.elapsed(),
+            recording.is_paused(),
+            recording
+                .audio_tracks()
+                .into_iter()
+                .map(|track| (track, recording.is_muted(track)))
+                .collect::<Vec<_>>(),
+        ),
+        None => return,
+    };
+    let toolbar = &components.recording_toolbar;
+    let indicator = &components.recording_indicator;
+    let elapsed = format_elapsed(elapsed);
+    toolbar.duration_label.set_label(&elapsed);
+    indicator.duration_label.set_label(&elapsed);
+    toolbar.show_audio_tracks(&tracks);
+
+    let (icon_name, tooltip) = if paused {
+        ("media-playback-start-symbolic", "Resume")
+    } else {
+        ("media-playback-pause-symbolic", "Pause")
+    };
+    for button in [&toolbar.pause_btn, &indicator.pause_btn] {
+        button.set_icon_name(icon_name);
+        button.set_tooltip_text(Some(tooltip));
+    }
+}
+
+/// Show the indicator while a recording runs and the main window is out of
+/// sight, hidden or minimized
+fn update_recording_indicator(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let recording = state.borrow().recording.is_some();
+    let window = &components.window;
+    let out_of_sight = !window.is_visible() || window.is_suspended();
+    if recording && out_of_sight {
+        components.recording_indicator.window.present();
+    } else {
+        components.recording_indicator.window.set_visible(false);
+    }
+}
+
+fn toggle_recording_pause(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let result = match state.borrow_mut().recording {
+        Some(ref mut recording) if recording.is_paused() => recording.resume(),
+        Some(ref mut recording) => {
+            recording.pause();
+            Ok(())
+        }
+        None => return,
+    };
+    if let Err(e) = result {
+        error!("Failed to resume recording: {}", e);
+        components.toast_overlay.add_toast(adw::Toast::new(&e));
+    }
+    update_recording_controls(state, components);
+}
+
+/// Finish the recording and offer to open the file
+fn stop_recording(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let recording = match state.borrow_mut().recording.take() {
+        Some(recording) => recording,
+        None => return,
+    };
+    components
+        .recording_toolbar
+        .recording_tools_box
+        .set_visible(false);
+    components.recording_toolbar.annotate_btn.set_active(false);
+    components.recording_indicator.window.set_visible(false);
+    // Brought back so the result can be seen
+    components.window.present();
+
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        let result = recording.stop().await;
+        components.header.take_screenshot_btn.set_sensitive(true);
+        match result {
+            Ok(path) => {
+                let toast = adw::Toast::new(&format!("Recording saved to {}", path.display()));
+                toast.set_button_label(Some("Preview"));
+                toast.connect_button_clicked({
+                    let state = state.clone();
+                    let components = components.clone();
+                    move |_| show_recording_preview(&state, &components, &path)
+                });
+                components.toast_overlay.add_toast(toast);
+            }
+            Err(e) => {
+                error!("Recording failed: {}", e);
+                components.toast_overlay.add_toast(adw::Toast::new(&e));
+            }
+        }
+    });
+}
+
+/// Play back the recording at `path`, offering to trim it, share it, open it
+/// in the video player or take the frame shown into the editor
+fn show_recording_preview(state: &Rc<RefCell<AppState>>, components: &UiComponents, path: &Path) {
+    let preview = create_recording_preview(path);
+
+    let can_share = state.borrow().preferences.nextcloud.is_some();
+    preview.share_btn.set_visible(can_share);
+    preview.share_btn.connect_clicked({
+        let state = state.clone();
+        let components = components.clone();
+        let path = path.to_path_buf();
+        move |_| share_recording(&state, &components, &path)
+    });
+
+    preview.open_btn.connect_clicked({
+        let window = components.window.clone();
+        let path = path.to_path_buf();
+        move |_| {
+            let path = path.clone();
+            gtk::FileLauncher::new(Some(&gio::File::for_path(&path))).launch(
+                Some(&window),
+                None::<&gio::Cancellable>,
+                move |result| {
+                    if let Err(e) = result {
+                        error!("Failed to open {:?}: {}", path, e);
                     }
-                    Action::ToolText => {
-                        let mut s = state.borrow_mut();
-                        s.editor.set_tool(EditorTool::Text);
-                        drop(s);
-                        components.drawing.drawing_area.queue_draw();
-                        return glib::Propagation::Stop;
+                },
+            );
+        }
+    });
+
+    preview.grab_frame_btn.connect_clicked({
+        let state = state.clone();
+        let components = components.clone();
+        let preview = preview.clone();
+        move |_| {
+            let frame = match preview.current_frame() {
+                Some(frame) => frame,
+                None => {
+                    warn!("The recording shows no frame to take");
+                    components
+                        .toast_overlay
+                        .add_toast(adw::Toast::new("No frame to take yet"));
+                    return;
+                }
+            };
+            info!(
+                "Editing a {}x{} frame of the recording",
+                frame.width(),
+                frame.height()
+            );
+            preview.dialog.close();
+            edit_recording_frame(&state, &components, frame);
+        }
+    });
+
+    preview.trim_btn.connect_clicked({
+        let components = components.clone();
+        let preview = preview.clone();
+        move |button| {
+            let (start, end) = match preview.trim_range() {
+                Some(range) => range,
+                None => return,
+            };
+            preview.media.pause();
+            button.set_sensitive(false);
+            let has_audio = preview.media.has_audio();
+            let components = components.clone();
+            let preview = preview.clone();
+            glib::spawn_future_local(async move {
+                match trim_recording(preview.path(), start, end, has_audio).await {
+                    Ok(()) => {
+                        preview.reload();
+                        components
+                            .toast_overlay
+                            .add_toast(adw::Toast::new("Recording trimmed"));
                     }
-                    Action::ToolCrop => {
-                        let mut s = state.borrow_mut();
-                        if s.final_image.is_some() {
-                            s.is_crop_mode = true;
-                            s.editor.set_tool(EditorTool::Crop);
-                            components.toolbar.tools_box.set_visible(false);
-                            components.crop_toolbar.crop_tools_box.set_visible(true);
-                            drop(s);
-                            components.drawing.drawing_area.queue_draw();
-                            return glib::Propagation::Stop;
-                        }
+                    Err(e) => {
+                        error!("Trimming {:?} failed: {}", preview.path(), e);
+                        preview.trim_btn.set_sensitive(true);
+                        components.toast_overlay.add_toast(adw::Toast::new(&e));
                     }
-                    Action::SwitchToSelection => {
-                        let mut s = state.borrow_mut();
-                        s.mode = CaptureMode::Selection;
-                        components.header.mode_selection_btn.set_active(true);
-                        return glib::Propagation::Stop;
+                }
+            });
+        }
+    });
+
+    preview.dialog.present(Some(&components.window));
+}
+
+/// Upload the recording at `path` to Nextcloud through the same upload
+/// banner as images, copying its share link when done
+fn share_recording(state: &Rc<RefCell<AppState>>, components: &UiComponents, path: &Path) {
+    let settings = match state.borrow().preferences.nextcloud.clone() {
+        Some(settings) => settings,
+        None => return,
+    };
+    let job = match NextcloudTarget::new(settings).recording_job(path) {
+        Ok(job) => job,
+        Err(e) => {
+            error!("Failed to share {:?}: {}", path, e);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new(&e.to_string()));
+            return;
+        }
+    };
+
+    run_upload(state, components, job, {
+        let toast_overlay = components.toast_overlay.clone();
+        let path = path.to_path_buf();
+        move |result| match result {
+            Ok(outcome) => {
+                info!("Shared {:?}: {}", path, outcome.summary);
+                if let (true, Some(url)) = (outcome.copy_url, &outcome.url) {
+                    toast_overlay.clipboard().set_text(url);
+                }
+                toast_overlay.add_toast(adw::Toast::new(&outcome.summary));
+            }
+            Err(ExportError::Cancelled) => {
+                info!("Sharing {:?} cancelled", path);
+                toast_overlay.add_toast(adw::Toast::new(&ExportError::Cancelled.to_string()));
+            }
+            Err(e) => {
+                error!("Sharing {:?} failed: {}", path, e);
+                toast_overlay.add_toast(adw::Toast::new(&e.to_string()));
+            }
+        }
+    });
+}
+
+/// Put a frame taken from a recording in the editor, in place of a capture
+fn edit_recording_frame(
+    state: &Rc<RefCell<AppState>>,
+    components: &UiComponents,
+    frame: gtk::gdk_pixbuf::Pixbuf,
+) {
+    set_compact_mode(state, components, false);
+    let mut s = state.borrow_mut();
+    s.upload_url = None;
+    s.last_export = None;
+    s.saved_path = None;
+    s.file_metadata = None;
+    s.auto_saved_path = None;
+    s.capture_metadata = None;
+    s.original_screenshot = Some(frame.clone());
+    s.final_image = Some(frame);
+    s.is_active = false;
+    s.editor.reset();
+    drop(s);
+
+    components.drawing.placeholder_icon.set_visible(false);
+    components.toolbar.tools_box.set_visible(true);
+    components.drawing.drawing_area.queue_draw();
+}
+
+/// Ask for the frame rate, maximum length and looping of animated
+/// recordings, and save the answer
+async fn ask_animation_settings(state: &Rc<RefCell<AppState>>, window: &adw::ApplicationWindow) {
+    let current = state.borrow().preferences.animation;
+    let fps_row = adw::SpinRow::with_range(1.0, AnimationSettings::MAX_FPS as f64, 1.0);
+    fps_row.set_title("Frames per Second");
+    fps_row.set_value(current.fps as f64);
+    let duration_row =
+        adw::SpinRow::with_range(1.0, AnimationSettings::MAX_DURATION_SECS as f64, 1.0);
+    duration_row.set_title("Maximum Length");
+    duration_row.set_subtitle("Seconds; recording stops after this");
+    duration_row.set_value(current.max_duration_secs as f64);
+    let plays_row = adw::SpinRow::with_range(0.0, AnimationSettings::MAX_PLAYS as f64, 1.0);
+    plays_row.set_title("Times Played");
+    plays_row.set_subtitle("0 to loop forever");
+    plays_row.set_value(current.plays as f64);
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&fps_row);
+    list.append(&duration_row);
+    list.append(&plays_row);
+
+    let dialog = adw::AlertDialog::new(
+        Some("Animated Recordings"),
+        Some(
+            "For GIF, PNG and WebP recordings. Fewer frames and shorter recordings make \
+             smaller files.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&list));
+
+    if dialog.choose_future(window).await != "save" {
+        return;
+    }
+
+    let settings = AnimationSettings {
+        fps: fps_row.value() as u32,
+        max_duration_secs: duration_row.value() as u32,
+        plays: plays_row.value() as u32,
+    };
+    debug!("Animated recordings set to {:?}", settings);
+    let mut s = state.borrow_mut();
+    s.preferences.animation = settings;
+    s.preferences.save();
+}
+
+/// Ask how long and how big recordings may get, and save the answer
+async fn ask_recording_limits(state: &Rc<RefCell<AppState>>, window: &adw::ApplicationWindow) {
+    let current = state.borrow().preferences.recording_limits;
+    let duration_row =
+        adw::SpinRow::with_range(0.0, RecordingLimits::MAX_DURATION_MINS as f64, 1.0);
+    duration_row.set_title("Maximum Length");
+    duration_row.set_subtitle("Minutes; 0 for no limit");
+    duration_row.set_value(current.max_duration_mins as f64);
+    let size_row = adw::SpinRow::with_range(0.0, RecordingLimits::MAX_SIZE_MIB as f64, 10.0);
+    size_row.set_title("Maximum Size");
+    size_row.set_subtitle("MiB; 0 for no limit");
+    size_row.set_value(current.max_size_mib as f64);
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&duration_row);
+    list.append(&size_row);
+
+    let dialog = adw::AlertDialog::new(
+        Some("Recording Limits"),
+        Some("Recordings stop on their own when they reach either limit."),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&list));
+
+    if dialog.choose_future(window).await != "save" {
+        return;
+    }
+
+    let limits = RecordingLimits {
+        max_duration_mins: duration_row.value() as u32,
+        max_size_mib: size_row.value() as u32,
+    };
+    debug!("Recording limits set to {:?}", limits);
+    let mut s = state.borrow_mut();
+    s.preferences.recording_limits = limits;
+    s.preferences.save();
+}
+
+/// Ask which sound to record with videos, and save the answer
+async fn ask_recording_audio(state: &Rc<RefCell<AppState>>, window: &adw::ApplicationWindow) {
+    let current = state.borrow().preferences.recording_audio.clone();
+    let microphones = gio::spawn_blocking(list_microphones)
+        .await
+        .unwrap_or_default();
+
+    let system_row = adw::SwitchRow::builder()
+        .title(AudioTrack::System.label())
+        .subtitle("What the computer plays")
+        .active(current.system)
+        .build();
+    let microphone_row = adw::SwitchRow::builder()
+        .title(AudioTrack::Microphone.label())
+        .active(current.microphone)
+        .build();
+
+    // The default input first, then the ones PipeWire listed
+    let mut device_names = vec![None];
+    let mut device_labels = vec!["Default".to_string()];
+    for microphone in &microphones {
+        device_names.push(Some(microphone.name.clone()));
+        device_labels.push(microphone.description.clone());
+    }
+    if let Some(ref saved) = current.microphone_device {
+        if !device_names.contains(&Some(saved.clone())) {
+            device_names.push(Some(saved.clone()));
+            device_labels.push(format!("{} (not connected)", saved));
+        }
+    }
+    let labels: Vec<&str> = device_labels.iter().map(String::as_str).collect();
+    let device_row = adw::ComboRow::builder()
+        .title("Input")
+        .model(&gtk::StringList::new(&labels))
+        .build();
+    let selected = device_names
+        .iter()
+        .position(|name| *name == current.microphone_device)
+        .unwrap_or(0);
+    device_row.set_selected(selected as u32);
+    microphone_row
+        .bind_property("active", &device_row, "sensitive")
+        .sync_create()
+        .build();
+
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .css_classes(["boxed-list"])
+        .build();
+    list.append(&system_row);
+    list.append(&microphone_row);
+    list.append(&device_row);
+
+    let dialog = adw::AlertDialog::new(
+        Some("Recording Audio"),
+        Some(
+            "Sound is recorded with MP4 and WebM videos, not animations. Each track can be \
+            muted from the recording controls.",
+        ),
+    );
+    dialog.add_responses(&[("cancel", "Cancel"), ("save", "Save")]);
+    dialog.set_response_appearance("save", adw::ResponseAppearance::Suggested);
+    dialog.set_default_response(Some("save"));
+    dialog.set_close_response("cancel");
+    dialog.set_extra_child(Some(&list));
+
+    if dialog.choose_future(window).await != "save" {
+        return;
+    }
+
+    let audio = RecordingAudio {
+        system: system_row.is_active(),
+        microphone: microphone_row.is_active(),
+        microphone_device: device_names
+            .get(device_row.selected() as usize)
+            .cloned()
+            .flatten(),
+    };
+    debug!("Recording audio set to {:?}", audio);
+    let mut s = state.borrow_mut();
+    s.preferences.recording_audio = audio;
+    s.preferences.save();
+}
+
+pub fn connect_recording_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    for pause_btn in [
+        &components.recording_toolbar.pause_btn,
+        &components.recording_indicator.pause_btn,
+    ] {
+        pause_btn.connect_clicked({
+            let state = state.clone();
+            let components = components.clone();
+            move |_| toggle_recording_pause(&state, &components)
+        });
+    }
+
+    for stop_btn in [
+        &components.recording_toolbar.stop_btn,
+        &components.recording_indicator.stop_btn,
+    ] {
+        stop_btn.connect_clicked({
+            let state = state.clone();
+            let components = components.clone();
+            move |_| stop_recording(&state, &components)
+        });
+    }
+
+    components.recording_toolbar.hide_btn.connect_clicked({
+        let window = components.window.clone();
+        move |_| window.set_visible(false)
+    });
+    components.recording_indicator.show_btn.connect_clicked({
+        let window = components.window.clone();
+        move |_| window.present()
+    });
+    components.window.connect_visible_notify({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| update_recording_indicator(&state, &components)
+    });
+    components.window.connect_suspended_notify({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| update_recording_indicator(&state, &components)
+    });
+
+    components.recording_toolbar.annotate_btn.connect_toggled({
+        let state = state.clone();
+        let components = components.clone();
+        move |button| {
+            if button.is_active() {
+                let started = match state.borrow_mut().recording {
+                    Some(ref mut recording) => {
+                        let first = !recording.has_overlay();
+                        recording.start_overlay().map(|()| first)
                     }
-                    Action::SwitchToWindow => {
-                        let mut s = state.borrow_mut();
-                        s.mode = CaptureMode::Window;
-                        components.header.mode_window_btn.set_active(true);
-                        return glib::Propagation::Stop;
+                    None => return,
+                };
+                match started {
+                    Ok(first) => {
+                        if first {
+                            begin_recording_annotations(&state, &components);
+                        }
+                        components.drawing.placeholder_icon.set_visible(false);
+                        components.toolbar.tools_box.set_visible(true);
                     }
-                    Action::SwitchToScreen => {
-                        let mut s = state.borrow_mut();
-                        s.mode = CaptureMode::Screen;
-                        components.header.mode_screen_btn.set_active(true);
-                        return glib::Propagation::Stop;
+                    Err(e) => {
+                        error!("Failed to annotate the recording: {}", e);
+                        components.toast_overlay.add_toast(adw::Toast::new(&e));
+                        button.set_active(false);
                     }
-                    Action::TakeScreenshot => {
-                        let mode = state.borrow().mode;
-                        capture_screen_or_selection(&state, &components, mode);
-                        return glib::Propagation::Stop;
+                }
+            } else {
+                // What was drawn stays in the video until it is erased
+                components.toolbar.tools_box.set_visible(false);
+                components.drawing.placeholder_icon.set_visible(true);
+            }
+            components.drawing.drawing_area.queue_draw();
+            update_recording_controls(&state, &components);
+        }
+    });
+
+    for track in AudioTrack::ALL {
+        components
+            .recording_toolbar
+            .audio_button(track)
+            .connect_toggled({
+                let state = state.clone();
+                let components = components.clone();
+                move |button| {
+                    let result = match state.borrow_mut().recording {
+                        Some(ref mut recording) => recording.set_muted(track, !button.is_active()),
+                        None => return,
+                    };
+                    if let Err(e) = result {
+                        error!("Failed to mute {}: {}", track.label(), e);
+                        components.toast_overlay.add_toast(adw::Toast::new(&e));
                     }
+                    update_recording_controls(&state, &components);
+                }
+            });
+    }
+
+    let recording_format = state.borrow().preferences.recording_format;
+    let action_recording_format = gio::SimpleAction::new_stateful(
+        "recording-format",
+        Some(glib::VariantTy::STRING),
+        &recording_format.id().to_variant(),
+    );
+    action_recording_format.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(format) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| RecordingFormat::from_id(&id))
+            {
+                debug!("Recording format set to {:?}", format);
+                action.set_state(&format.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.recording_format = format;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_recording_format);
+
+    let action_animation_settings = gio::SimpleAction::new("animation-settings", None);
+    action_animation_settings.connect_activate({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |_, _| {
+            let state = state.clone();
+            let window = window.clone();
+            glib::spawn_future_local(async move {
+                ask_animation_settings(&state, &window).await;
+            });
+        }
+    });
+    components.window.add_action(&action_animation_settings);
+
+    let action_recording_audio = gio::SimpleAction::new("recording-audio", None);
+    action_recording_audio.connect_activate({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |_, _| {
+            let state = state.clone();
+            let window = window.clone();
+            glib::spawn_future_local(async move {
+                ask_recording_audio(&state, &window).await;
+            });
+        }
+    });
+    components.window.add_action(&action_recording_audio);
+
+    let action_recording_limits = gio::SimpleAction::new("recording-limits", None);
+    action_recording_limits.connect_activate({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |_, _| {
+            let state = state.clone();
+            let window = window.clone();
+            glib::spawn_future_local(async move {
+                ask_recording_limits(&state, &window).await;
+            });
+        }
+    });
+    components.window.add_action(&action_recording_limits);
+}
+
+/// Fill in name, scale and DPI of the GDK monitor containing the point
+fn describe_monitor(
+    window: &adw::ApplicationWindow,
+    metadata: CaptureMetadata,
+    x: i32,
+    y: i32,
+) -> CaptureMetadata {
+    let monitors = display_monitors(&window.display());
+    let monitor = monitors
+        .iter()
+        .find(|m| {
+            let g = m.geometry();
+            x >= g.x() && y >= g.y() && x < g.x() + g.width() && y < g.y() + g.height()
+        })
+        .or(monitors.first());
+
+    match monitor {
+        Some(monitor) => {
+            let scale = monitor.scale_factor() as f64;
+            let width_mm = monitor.width_mm();
+            let dpi = (width_mm > 0)
+                .then(|| monitor.geometry().width() as f64 * scale / (width_mm as f64 / 25.4));
+            let name = monitor
+                .connector()
+                .or_else(|| monitor.model())
+                .map(|n| n.to_string());
+            metadata.with_monitor(name, Some(scale), dpi)
+        }
+        None => metadata,
+    }
+}
+
+/// Name the screenshot tools to install, offering the portal for this capture
+fn report_missing_tools(state: &Rc<RefCell<AppState>>, components: &UiComponents, tools: &[&str]) {
+    warn!("None of the screenshot tools {:?} is installed", tools);
+    let list = tools
+        .iter()
+        .map(|tool| format!("• {} ({})", tool, tool_description(tool)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let portal = portal_available();
+    let body = format!(
+        "Capturing the screen in this session needs one of these programs:\n\n{}\n\n\
+        Install one with your distribution's package manager{}.",
+        list,
+        if portal {
+            ", or use the system screenshot dialog for now"
+        } else {
+            ""
+        }
+    );
+
+    let dialog = adw::AlertDialog::new(Some("Screenshot Tool Not Installed"), Some(&body));
+    dialog.add_response("close", "Close");
+    dialog.set_close_response("close");
+    if portal {
+        dialog.add_response("portal", "Use System Dialog");
+        dialog.set_response_appearance("portal", adw::ResponseAppearance::Suggested);
+        dialog.set_default_response(Some("portal"));
+    }
+
+    let state = state.clone();
+    let components = components.clone();
+    glib::spawn_future_local(async move {
+        if dialog.choose_future(&components.window).await == "portal" {
+            capture_with_portal(&state, &components);
+        }
+    });
+}
+
+/// Let the compositor's own picker choose the region and load the result into the editor
+fn capture_with_portal(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    if state.borrow().capture_cancellable.is_some() {
+        debug!("A capture is already pending");
+        return;
+    }
+    let cancellable = gio::Cancellable::new();
+    let delay_seconds = {
+        let mut s = state.borrow_mut();
+        s.capture_cancellable = Some(cancellable.clone());
+        s.delay_seconds
+    };
+
+    let state = state.clone();
+    let components = components.clone();
+    components.window.set_visible(false);
+
+    glib::spawn_future_local(async move {
+        // The portal's picker freezes the screen as it opens, so the delay
+        // has to be over by then for menus opened meanwhile to be in it
+        count_down(&components, delay_seconds, &cancellable).await;
+        state.borrow_mut().capture_cancellable = None;
+        if cancellable.is_cancelled() {
+            info!("Capture cancelled");
+            components.window.set_visible(true);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Capture cancelled"));
+            return;
+        }
+
+        let result = gio::spawn_blocking(request_interactive_screenshot).await;
+        components.window.set_visible(true);
+
+        match result {
+            Ok(Ok(path)) => match load_portal_screenshot(&path) {
+                Ok(pixbuf) => {
+                    let mut s = state.borrow_mut();
+                    s.upload_url = None;
+                    s.last_export = None;
+                    s.saved_path = None;
+                    s.file_metadata = None;
+                    s.capture_metadata = Some(describe_monitor(
+                        &components.window,
+                        CaptureMetadata::new(CaptureSource::Portal),
+                        0,
+                        0,
+                    ));
+                    s.original_screenshot = Some(pixbuf.clone());
+                    s.final_image = Some(pixbuf);
+                    s.is_active = false;
+                    s.editor.reset();
+                    s.auto_save_capture();
+                    drop(s);
+
+                    components.drawing.placeholder_icon.set_visible(false);
+                    components.toolbar.tools_box.set_visible(true);
+                    components.drawing.drawing_area.queue_draw();
+                    capture_feedback(&state, &components.drawing.drawing_area);
+                }
+                Err(e) => {
+                    error!("Failed to load portal screenshot: {}", e);
+                    components
+                        .toast_overlay
+                        .add_toast(adw::Toast::new("Failed to load screenshot"));
                 }
+            },
+            Ok(Err(e)) => {
+                info!("Portal screenshot not taken: {}", e);
+                components.toast_overlay.add_toast(adw::Toast::new(&e));
+            }
+            Err(_) => {
+                error!("Portal screenshot task panicked");
+            }
+        }
+    });
+}
+
+/// Load an image from disk into the editor, in place of a capture
+pub fn open_image_file(state: &Rc<RefCell<AppState>>, components: &UiComponents, path: &Path) {
+    let pixbuf = match gtk::gdk_pixbuf::Pixbuf::from_file(path) {
+        Ok(pixbuf) => pixbuf,
+        Err(e) => {
+            error!("Failed to open {:?}: {}", path, e);
+            components
+                .toast_overlay
+                .add_toast(adw::Toast::new("Failed to open image"));
+            return;
+        }
+    };
+    info!("Opened {:?}", path);
+    set_compact_mode(state, components, false);
+    let file_metadata = match read_file_metadata(path) {
+        Ok(metadata) => Some(metadata),
+        Err(e) => {
+            warn!("Failed to read the details of {:?}: {}", path, e);
+            None
+        }
+    };
+    let warnings = file_metadata
+        .as_ref()
+        .map(|m| m.save_warnings())
+        .unwrap_or_default();
+
+    let mut s = state.borrow_mut();
+    s.upload_url = None;
+    s.last_export = None;
+    s.saved_path = None;
+    s.file_metadata = file_metadata;
+    s.auto_saved_path = None;
+    s.capture_metadata = None;
+    s.original_screenshot = Some(pixbuf.clone());
+    s.final_image = Some(pixbuf);
+    s.is_active = false;
+    s.editor.reset();
+    drop(s);
+
+    components.drawing.placeholder_icon.set_visible(false);
+    components.toolbar.tools_box.set_visible(true);
+    components.drawing.drawing_area.queue_draw();
+
+    if !warnings.is_empty() {
+        debug!("Saving {:?} would lose: {:?}", path, warnings);
+        let toast = adw::Toast::new("Saving will not keep all of this file's details");
+        toast.set_button_label(Some("Details"));
+        toast.connect_button_clicked({
+            let info_btn = components.toolbar.info_btn.clone();
+            move |_| info_btn.popup()
+        });
+        components.toast_overlay.add_toast(toast);
+    }
+}
+
+/// Explain, the first time the redaction tool is picked, that it destroys
+/// pixels and why blurring isn't used
+pub fn connect_redact_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.toolbar.tool_redact_btn.connect_toggled({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |btn| {
+            if !btn.is_active() || state.borrow().preferences.redaction_notice_shown {
+                return;
+            }
+            {
+                let mut s = state.borrow_mut();
+                s.preferences.redaction_notice_shown = true;
+                s.preferences.save();
+            }
+
+            let dialog = adw::AlertDialog::new(
+                Some("Redaction Is Permanent"),
+                Some(
+                    "Dragging with this tool overwrites the pixels underneath with solid \
+                    black or noise. It can't be undone, and the auto-saved file is rewritten \
+                    too.\n\nBlurring or pixelating text is not a safe way to hide it: the \
+                    original can sometimes be reconstructed. Use this tool for passwords, \
+                    keys and personal data.",
+                ),
+            );
+            dialog.add_response("ok", "Got It");
+            dialog.set_default_response(Some("ok"));
+            dialog.set_close_response("ok");
+            dialog.present(Some(&window));
+        }
+    });
+
+    let action_redact_style = gio::SimpleAction::new_stateful(
+        "redact-style",
+        Some(glib::VariantTy::STRING),
+        &state.borrow().preferences.redact_style.id().to_variant(),
+    );
+    action_redact_style.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(style) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| RedactStyle::from_id(&id))
+            {
+                debug!("Redaction fill set to {:?}", style);
+                action.set_state(&style.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.redact_style = style;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_redact_style);
+}
+
+pub fn connect_countdown_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.countdown.cancel_btn.connect_clicked({
+        let state = state.clone();
+        let components = components.clone();
+        move |_| {
+            cancel_capture(&state, &components);
+        }
+    });
+
+    let key_controller = EventControllerKey::new();
+    key_controller.connect_key_pressed({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, key, _code, modifier| {
+            let action = state.borrow().shortcuts.get_action(key, modifier);
+            if action == Some(Action::Cancel) && cancel_capture(&state, &components) {
+                return glib::Propagation::Stop;
+            }
+            glib::Propagation::Proceed
+        }
+    });
+    components.countdown.window.add_controller(key_controller);
+}
+
+/// Remember the window's size and maximized state for the next start
+fn connect_window_geometry(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    components.window.connect_close_request({
+        let state = state.clone();
+        move |window| {
+            let mut s = state.borrow_mut();
+            // The selection overlay and compact mode are not sizes to keep
+            if !window.is_fullscreen() {
+                let size = s.compact_size.unwrap_or_else(|| window.default_size());
+                s.preferences.window_size = Some(size);
+                s.preferences.window_maximized = window.is_maximized();
+                s.preferences.save();
             }
             glib::Propagation::Proceed
         }
     });
-
-    components.window.add_controller(key_controller);
 }
 
-pub fn capture_screen_or_selection(
+/// Show or hide the layers panel from the header bar, as it was last left
+fn connect_layers_handler(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    connect_layers_panel(state, &components.layers, &components.drawing.drawing_area);
+
+    components.header.layers_btn.connect_toggled({
+        let state = state.clone();
+        let revealer = components.layers.revealer.clone();
+        move |btn| {
+            revealer.set_reveal_child(btn.is_active());
+            let mut s = state.borrow_mut();
+            s.preferences.layers_panel_open = btn.is_active();
+            s.preferences.save();
+        }
+    });
+    let open = state.borrow().preferences.layers_panel_open;
+    components.header.layers_btn.set_active(open);
+}
+
+/// Fill the header's monitor dropdown with the display's monitors, again
+/// whenever one is plugged in or out, and show it in Screen mode when there
+/// is more than one to pick from
+fn fill_monitor_dropdown(
     state: &Rc<RefCell<AppState>>,
-    components: &UiComponents,
-    mode: CaptureMode,
+    header: &HeaderComponents,
+    filling: &Cell<bool>,
 ) {
-    let window = &components.window;
-    let header_bar = &components.header.header_bar;
-    let tools_box = &components.toolbar.tools_box;
-    let crop_tools_box = &components.crop_toolbar.crop_tools_box;
-    let selection_tools_box = &components.selection_toolbar.selection_tools_box;
-    let drawing_area = &components.drawing.drawing_area;
-    let placeholder_icon = &components.drawing.placeholder_icon;
+    let monitors = display_monitors(&header.monitor_dropdown.display());
+    let mut labels = vec![ASK_FOR_MONITOR.to_string(), ALL_MONITORS.to_string()];
+    labels.extend(
+        monitors
+            .iter()
+            .enumerate()
+            .map(|(index, monitor)| monitor_label(index, monitor)),
+    );
+    let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
 
-    if mode == CaptureMode::Window {
-        show_window_selector(
-            state,
-            window,
-            drawing_area,
-            placeholder_icon,
-            tools_box,
-        );
-        return;
-    }
+    let selected = {
+        let s = state.borrow();
+        let connector = s.preferences.screen_monitor.as_deref();
+        if s.preferences.all_monitors {
+            1
+        } else {
+            monitors
+                .iter()
+                .position(|m| connector.is_some() && m.connector().as_deref() == connector)
+                .map_or(0, |index| index as u32 + 2)
+        }
+    };
 
-    window.set_visible(false);
-    let delay_seconds = state.borrow().delay_seconds;
-    let context = gtk::glib::MainContext::default();
-    while context.pending() {
-        context.iteration(false);
-    }
-    std::thread::sleep(Duration::from_millis(200 + (delay_seconds as u64 * 1000)));
+    // A new model selects its first entry, which must not clear the choice
+    filling.set(true);
+    header
+        .monitor_dropdown
+        .set_model(Some(&gtk::StringList::new(&labels)));
+    header.monitor_dropdown.set_selected(selected);
+    filling.set(false);
 
-    match capture_primary_monitor() {
-        Ok(result) => {
-            let mut s = state.borrow_mut();
-            s.original_screenshot = Some(result.pixbuf.clone());
-            s.monitor_x = result.monitor_info.x;
-            s.monitor_y = result.monitor_info.y;
+    header
+        .monitor_dropdown
+        .set_visible(monitors.len() > 1 && header.mode_screen_btn.is_active());
+}
 
-            if mode == CaptureMode::Screen {
-                s.final_image = Some(result.pixbuf);
-                s.is_active = false;
-                placeholder_icon.set_visible(false);
-                tools_box.set_visible(true);
-                window.set_visible(true);
-            } else {
-                s.is_active = true;
-                s.mode = CaptureMode::Selection;
-                s.final_image = Some(result.pixbuf);
+fn connect_monitor_dropdown(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    let header = &components.header;
+    let filling = Rc::new(Cell::new(false));
+    fill_monitor_dropdown(state, header, &filling);
 
-                window.set_visible(true);
-                window.fullscreen();
-                header_bar.set_visible(false);
-                tools_box.set_visible(false);
-                crop_tools_box.set_visible(false);
-                selection_tools_box.set_visible(true);
-                placeholder_icon.set_visible(false);
+    header
+        .monitor_dropdown
+        .display()
+        .monitors()
+        .connect_items_changed({
+            let state = state.clone();
+            let header = header.clone();
+            let filling = filling.clone();
+            move |_, _, _, _| {
+                fill_monitor_dropdown(&state, &header, &filling);
             }
-            drop(s);
-            drawing_area.queue_draw();
+        });
+
+    header.monitor_dropdown.connect_selected_notify({
+        let state = state.clone();
+        move |dropdown| {
+            if filling.get() {
+                return;
+            }
+            let monitors = display_monitors(&dropdown.display());
+            // The first entry asks each time and the second takes them all
+            let connector = match dropdown.selected() {
+                0 | 1 | gtk::INVALID_LIST_POSITION => None,
+                index => monitors
+                    .get(index as usize - 2)
+                    .and_then(|monitor| monitor.connector())
+                    .map(|connector| connector.to_string()),
+            };
+            let mut s = state.borrow_mut();
+            s.preferences.screen_monitor = connector;
+            s.preferences.all_monitors = dropdown.selected() == 1;
+            s.preferences.save();
         }
-        Err(e) => {
-            error!("Capture failed: {}", e);
-            window.set_visible(true);
+    });
+
+    header.mode_screen_btn.connect_toggled({
+        let header = header.clone();
+        move |button| {
+            let monitors = header.monitor_dropdown.display().monitors().n_items();
+            header
+                .monitor_dropdown
+                .set_visible(monitors > 1 && button.is_active());
         }
-    }
+    });
 }
 
 pub fn connect_all_handlers(state: &Rc<RefCell<AppState>>, components: &UiComponents) {
+    connect_countdown_handlers(state, components);
     connect_undo_handler(state, components);
     connect_copy_handler(state, components);
+    connect_discard_handler(state, components);
     connect_save_handler(state, components);
+    connect_export_handlers(state, components);
+    connect_info_handler(state, components);
+    connect_links_handler(state, components);
+    connect_history_handler(state, components);
+    connect_layers_handler(state, components);
+    connect_redact_handlers(state, components);
+    connect_recording_handlers(state, components);
     connect_drag_handlers(state, components);
     connect_click_handlers(state, components);
     connect_crop_handlers(state, components);
     connect_selection_handlers(state, components);
     connect_screenshot_handler(state, components);
+    connect_monitor_dropdown(state, components);
     connect_keyboard_handlers(state, components);
+    connect_scroll_handlers(state, components);
+    connect_canvas_text(state, &components.drawing);
+    connect_window_geometry(state, components);
 
     let action_shortcuts = gio::SimpleAction::new("shortcuts", None);
     action_shortcuts.connect_activate({
@@ -653,6 +5022,25 @@ pub fn connect_all_handlers(state: &Rc<RefCell<AppState>>, components: &UiCompon
     });
     components.window.add_action(&action_shortcuts);
 
+    let action_customize_toolbar = gio::SimpleAction::new("customize-toolbar", None);
+    action_customize_toolbar.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            show_customize_toolbar_dialog(&state, &components.toolbar, &components.window);
+        }
+    });
+    components.window.add_action(&action_customize_toolbar);
+
+    let action_show_log = gio::SimpleAction::new("show-log", None);
+    action_show_log.connect_activate({
+        let window = components.window.clone();
+        move |_, _| {
+            show_log_viewer(&window);
+        }
+    });
+    components.window.add_action(&action_show_log);
+
     let action_about = gio::SimpleAction::new("about", None);
     action_about.connect_activate({
         let window = components.window.clone();
@@ -662,8 +5050,678 @@ pub fn connect_all_handlers(state: &Rc<RefCell<AppState>>, components: &UiCompon
     });
     components.window.add_action(&action_about);
 
+    let action_optimize_png = gio::SimpleAction::new_stateful(
+        "optimize-png",
+        None,
+        &state.borrow().preferences.optimize_png.to_variant(),
+    );
+    action_optimize_png.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action
+                .state()
+                .and_then(|v| v.get::<bool>())
+                .unwrap_or(false);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.optimize_png = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_optimize_png);
+
+    let action_filename_template = gio::SimpleAction::new("filename-template", None);
+    action_filename_template.connect_activate({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |_, _| {
+            edit_filename_template(state.clone(), window.clone());
+        }
+    });
+    components.window.add_action(&action_filename_template);
+
+    let action_translate = gio::SimpleAction::new("translate", None);
+    action_translate.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            translate_image_text(state.clone(), components.clone());
+        }
+    });
+    components.window.add_action(&action_translate);
+
+    let action_translation_settings = gio::SimpleAction::new("translation-settings", None);
+    action_translation_settings.connect_activate({
+        let state = state.clone();
+        let window = components.window.clone();
+        move |_, _| {
+            let state = state.clone();
+            let window = window.clone();
+            glib::spawn_future_local(async move {
+                ask_translation_settings(&state, &window).await;
+            });
+        }
+    });
+    components.window.add_action(&action_translation_settings);
+
+    let action_take_snapshot = gio::SimpleAction::new("take-snapshot", None);
+    action_take_snapshot.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            take_snapshot(state.clone(), components.clone());
+        }
+    });
+    components.window.add_action(&action_take_snapshot);
+
+    let action_snapshots = gio::SimpleAction::new("snapshots", None);
+    action_snapshots.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            show_snapshots(&state, &components);
+        }
+    });
+    components.window.add_action(&action_snapshots);
+
+    let action_save_template = gio::SimpleAction::new("save-template", None);
+    action_save_template.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            save_template(state.clone(), components.clone());
+        }
+    });
+    components.window.add_action(&action_save_template);
+
+    let action_templates = gio::SimpleAction::new("templates", None);
+    action_templates.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            show_templates(&state, &components);
+        }
+    });
+    components.window.add_action(&action_templates);
+
+    let action_spell_check =
+        gio::SimpleAction::new_stateful("spell-check", None, &false.to_variant());
+    action_spell_check.connect_activate({
+        let state = state.clone();
+        let text_view = components.text_popover.text_view.clone();
+        move |action, _| {
+            let enabled = !action
+                .state()
+                .and_then(|v| v.get::<bool>())
+                .unwrap_or(false);
+            action.set_state(&enabled.to_variant());
+            state.borrow_mut().spell_check = enabled;
+            if !enabled {
+                let buffer = text_view.buffer();
+                buffer.remove_all_tags(&buffer.start_iter(), &buffer.end_iter());
+            }
+        }
+    });
+    components.window.add_action(&action_spell_check);
+
+    let action_compact = gio::SimpleAction::new_stateful("compact-mode", None, &false.to_variant());
+    action_compact.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |action, _| {
+            let compact = !action
+                .state()
+                .and_then(|v| v.get::<bool>())
+                .unwrap_or(false);
+            set_compact_mode(&state, &components, compact);
+        }
+    });
+    components.window.add_action(&action_compact);
+
+    let action_text_on_canvas =
+        gio::SimpleAction::new_stateful("text-on-canvas", None, &true.to_variant());
+    action_text_on_canvas.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            components.text_popover.text_popover.popdown();
+
+            let mut s = state.borrow_mut();
+            s.editor.commit_pending_text();
+            s.text_on_canvas = enabled;
+            drop(s);
+            components.drawing.drawing_area.queue_draw();
+        }
+    });
+    components.window.add_action(&action_text_on_canvas);
+
+    let action_compare = gio::SimpleAction::new("compare-clipboard", None);
+    action_compare.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |_, _| {
+            compare_with_clipboard(state.clone(), components.clone());
+        }
+    });
+    components.window.add_action(&action_compare);
+
+    let action_pick_window =
+        gio::SimpleAction::new_stateful("pick-window-by-click", None, &false.to_variant());
+    action_pick_window.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action
+                .state()
+                .and_then(|v| v.get::<bool>())
+                .unwrap_or(false);
+            action.set_state(&enabled.to_variant());
+            state.borrow_mut().pick_window_by_click = enabled;
+        }
+    });
+    components.window.add_action(&action_pick_window);
+
+    let action_portal_picker =
+        gio::SimpleAction::new_stateful("use-portal-picker", None, &false.to_variant());
+    action_portal_picker.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action
+                .state()
+                .and_then(|v| v.get::<bool>())
+                .unwrap_or(false);
+            action.set_state(&enabled.to_variant());
+            state.borrow_mut().use_portal_picker = enabled;
+        }
+    });
+    components.window.add_action(&action_portal_picker);
+
+    let capture_feedback_enabled = state.borrow().preferences.capture_feedback;
+    let action_capture_feedback = gio::SimpleAction::new_stateful(
+        "capture-feedback",
+        None,
+        &capture_feedback_enabled.to_variant(),
+    );
+    action_capture_feedback.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.capture_feedback = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_capture_feedback);
+
+    let hide_own_window = state.borrow().preferences.hide_own_window;
+    let action_hide_own_window =
+        gio::SimpleAction::new_stateful("hide-own-window", None, &hide_own_window.to_variant());
+    action_hide_own_window.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.hide_own_window = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_hide_own_window);
+
+    let audit_before_export = state.borrow().preferences.audit_before_export;
+    let action_audit = gio::SimpleAction::new_stateful(
+        "audit-before-export",
+        None,
+        &audit_before_export.to_variant(),
+    );
+    action_audit.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.audit_before_export = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_audit);
+
+    let snippet_format = state.borrow().preferences.snippet_format;
+    let action_snippet_format = gio::SimpleAction::new_stateful(
+        "snippet-format",
+        Some(glib::VariantTy::STRING),
+        &snippet_format.id().to_variant(),
+    );
+    action_snippet_format.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(format) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| SnippetFormat::from_id(&id))
+            {
+                debug!("Snippet format set to {:?}", format);
+                action.set_state(&format.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.snippet_format = format;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_snippet_format);
+
+    let snippet_alt_text = state.borrow().preferences.snippet_alt_text;
+    let action_snippet_alt_text =
+        gio::SimpleAction::new_stateful("snippet-alt-text", None, &snippet_alt_text.to_variant());
+    action_snippet_alt_text.connect_activate({
+        let state = state.clone();
+        move |action, _| {
+            let enabled = !action.state().and_then(|v| v.get::<bool>()).unwrap_or(true);
+            action.set_state(&enabled.to_variant());
+            let mut s = state.borrow_mut();
+            s.preferences.snippet_alt_text = enabled;
+            s.preferences.save();
+        }
+    });
+    components.window.add_action(&action_snippet_alt_text);
+
+    let quick_capture_menu = gio::Menu::new();
+    for mode in CaptureMode::ALL {
+        let action_name = format!("quick-capture-{}", mode.id());
+        let action_quick_capture = gio::SimpleAction::new_stateful(
+            &action_name,
+            Some(glib::VariantTy::STRING),
+            &state
+                .borrow()
+                .preferences
+                .quick_capture(mode)
+                .id()
+                .to_variant(),
+        );
+        action_quick_capture.connect_activate({
+            let state = state.clone();
+            move |action, param| {
+                if let Some(quick_capture) = param
+                    .and_then(|v| v.get::<String>())
+                    .and_then(|id| QuickCapture::from_id(&id))
+                {
+                    debug!("After capturing {:?}: {:?}", mode, quick_capture);
+                    action.set_state(&quick_capture.id().to_variant());
+                    let mut s = state.borrow_mut();
+                    s.preferences.set_quick_capture(mode, quick_capture);
+                    s.preferences.save();
+                }
+            }
+        });
+        components.window.add_action(&action_quick_capture);
+
+        let section = gio::Menu::new();
+        for quick_capture in QuickCapture::ALL {
+            let item = gio::MenuItem::new(Some(quick_capture.label()), None);
+            item.set_action_and_target_value(
+                Some(&format!("win.{}", action_name)),
+                Some(&quick_capture.id().to_variant()),
+            );
+            section.append_item(&item);
+        }
+        quick_capture_menu.append_section(Some(mode.label()), &section);
+    }
+
+    // Raised from the quick capture notification, which outlives the window
+    if let Some(app) = components.window.application() {
+        let action_edit_capture = gio::SimpleAction::new("edit-capture", None);
+        action_edit_capture.connect_activate({
+            let window = components.window.clone();
+            move |_, _| {
+                if let Some(app) = window.application() {
+                    app.withdraw_notification(QUICK_CAPTURE_NOTIFICATION);
+                }
+                window.present();
+            }
+        });
+        app.add_action(&action_edit_capture);
+    }
+
+    let action_tonemap = gio::SimpleAction::new_stateful(
+        "tonemap",
+        Some(glib::VariantTy::STRING),
+        &state
+            .borrow()
+            .preferences
+            .tonemap_operator
+            .id()
+            .to_variant(),
+    );
+    action_tonemap.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(operator) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| TonemapOperator::from_id(&id))
+            {
+                debug!("HDR tonemapping operator set to {:?}", operator);
+                set_tonemap_operator(operator);
+                action.set_state(&operator.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.tonemap_operator = operator;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_tonemap);
+
+    let action_auto_save = gio::SimpleAction::new_stateful(
+        "auto-save",
+        Some(glib::VariantTy::STRING),
+        &"off".to_variant(),
+    );
+    action_auto_save.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(id) = param.and_then(|v| v.get::<String>()) {
+                let layout = AutoSaveLayout::from_id(&id);
+                debug!("Auto-save layout set to {:?}", layout);
+                state.borrow_mut().auto_save = layout;
+                action.set_state(&id.to_variant());
+            }
+        }
+    });
+    components.window.add_action(&action_auto_save);
+
+    let auto_save_menu = gio::Menu::new();
+    let off_item = gio::MenuItem::new(Some("Off"), None);
+    off_item.set_action_and_target_value(Some("win.auto-save"), Some(&"off".to_variant()));
+    auto_save_menu.append_item(&off_item);
+    for layout in AutoSaveLayout::ALL {
+        let item = gio::MenuItem::new(Some(layout.label()), None);
+        item.set_action_and_target_value(Some("win.auto-save"), Some(&layout.id().to_variant()));
+        auto_save_menu.append_item(&item);
+    }
+
+    let action_appearance = gio::SimpleAction::new_stateful(
+        "appearance",
+        Some(glib::VariantTy::STRING),
+        &state.borrow().preferences.appearance.id().to_variant(),
+    );
+    action_appearance.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(appearance) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| Appearance::from_id(&id))
+            {
+                debug!("Appearance set to {:?}", appearance);
+                appearance.apply();
+                action.set_state(&appearance.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.appearance = appearance;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_appearance);
+
+    // Canvas chrome follows the style, whether changed here or by the desktop
+    adw::StyleManager::default().connect_dark_notify({
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |_| drawing_area.queue_draw()
+    });
+
+    let action_backdrop = gio::SimpleAction::new_stateful(
+        "canvas-backdrop",
+        Some(glib::VariantTy::STRING),
+        &state.borrow().preferences.canvas_backdrop.id().to_variant(),
+    );
+    action_backdrop.connect_activate({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |action, param| {
+            if let Some(backdrop) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| CanvasBackdrop::from_id(&id))
+            {
+                debug!("Canvas backdrop set to {:?}", backdrop);
+                action.set_state(&backdrop.id().to_variant());
+                let mut s = state.borrow_mut();
+                s.preferences.canvas_backdrop = backdrop;
+                s.preferences.save();
+                drop(s);
+                drawing_area.queue_draw();
+            }
+        }
+    });
+    components.window.add_action(&action_backdrop);
+
+    let backdrop_menu = gio::Menu::new();
+    for backdrop in CanvasBackdrop::ALL {
+        let item = gio::MenuItem::new(Some(backdrop.label()), None);
+        item.set_action_and_target_value(
+            Some("win.canvas-backdrop"),
+            Some(&backdrop.id().to_variant()),
+        );
+        backdrop_menu.append_item(&item);
+    }
+
+    let action_color_vision = gio::SimpleAction::new_stateful(
+        "color-vision",
+        Some(glib::VariantTy::STRING),
+        &ColorVision::default().id().to_variant(),
+    );
+    action_color_vision.connect_activate({
+        let state = state.clone();
+        let drawing_area = components.drawing.drawing_area.clone();
+        move |action, param| {
+            if let Some(vision) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| ColorVision::from_id(&id))
+            {
+                debug!("Simulating {:?} on the canvas", vision);
+                state.borrow_mut().color_vision = vision;
+                action.set_state(&vision.id().to_variant());
+                drawing_area.queue_draw();
+            }
+        }
+    });
+    components.window.add_action(&action_color_vision);
+
+    let color_vision_menu = gio::Menu::new();
+    for vision in ColorVision::ALL {
+        let item = gio::MenuItem::new(Some(vision.label()), None);
+        item.set_action_and_target_value(Some("win.color-vision"), Some(&vision.id().to_variant()));
+        color_vision_menu.append_item(&item);
+    }
+
+    let action_palette = gio::SimpleAction::new_stateful(
+        "palette",
+        Some(glib::VariantTy::STRING),
+        &state.borrow().preferences.palette.id().to_variant(),
+    );
+    action_palette.connect_activate({
+        let state = state.clone();
+        move |action, param| {
+            if let Some(preset) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| PalettePreset::from_id(&id))
+            {
+                debug!("Annotation palette set to {:?}", preset);
+                action.set_state(&preset.id().to_variant());
+
+                let mut s = state.borrow_mut();
+                s.preferences.palette = preset;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_palette);
+
+    let palette_menu = gio::Menu::new();
+    for preset in PalettePreset::ALL {
+        let item = gio::MenuItem::new(Some(preset.label()), None);
+        item.set_action_and_target_value(Some("win.palette"), Some(&preset.id().to_variant()));
+        palette_menu.append_item(&item);
+    }
+
+    let toolbar_position = state.borrow().preferences.toolbar_position;
+    let action_toolbar_position = gio::SimpleAction::new_stateful(
+        "toolbar-position",
+        Some(glib::VariantTy::STRING),
+        &toolbar_position.id().to_variant(),
+    );
+    action_toolbar_position.connect_activate({
+        let state = state.clone();
+        let components = components.clone();
+        move |action, param| {
+            if let Some(position) = param
+                .and_then(|v| v.get::<String>())
+                .and_then(|id| ToolbarPosition::from_id(&id))
+            {
+                debug!("Toolbar position set to {:?}", position);
+                set_toolbar_position(
+                    &components.toolbar,
+                    &components.crop_toolbar,
+                    &components.selection_toolbar,
+                    &components.recording_toolbar,
+                    position,
+                );
+                action.set_state(&position.id().to_variant());
+
+                let mut s = state.borrow_mut();
+                s.preferences.toolbar_position = position;
+                s.preferences.save();
+            }
+        }
+    });
+    components.window.add_action(&action_toolbar_position);
+
+    let toolbar_position_menu = gio::Menu::new();
+    for position in ToolbarPosition::ALL {
+        let item = gio::MenuItem::new(Some(position.label()), None);
+        item.set_action_and_target_value(
+            Some("win.toolbar-position"),
+            Some(&position.id().to_variant()),
+        );
+        toolbar_position_menu.append_item(&item);
+    }
+
+    let redact_style_menu = gio::Menu::new();
+    for style in RedactStyle::ALL {
+        let item = gio::MenuItem::new(Some(style.label()), None);
+        item.set_action_and_target_value(Some("win.redact-style"), Some(&style.id().to_variant()));
+        redact_style_menu.append_item(&item);
+    }
+
+    let snippet_menu = gio::Menu::new();
+    for format in SnippetFormat::ALL {
+        let item = gio::MenuItem::new(Some(format.label()), None);
+        item.set_action_and_target_value(
+            Some("win.snippet-format"),
+            Some(&format.id().to_variant()),
+        );
+        snippet_menu.append_item(&item);
+    }
+    snippet_menu.append(
+        Some("Alt Text from Recognized Text"),
+        Some("win.snippet-alt-text"),
+    );
+
+    let recording_format_menu = gio::Menu::new();
+    for format in RecordingFormat::ALL {
+        let item = gio::MenuItem::new(Some(format.label()), None);
+        item.set_action_and_target_value(
+            Some("win.recording-format"),
+            Some(&format.id().to_variant()),
+        );
+        recording_format_menu.append_item(&item);
+    }
+
+    let appearance_menu = gio::Menu::new();
+    for appearance in Appearance::ALL {
+        let item = gio::MenuItem::new(Some(appearance.label()), None);
+        item.set_action_and_target_value(
+            Some("win.appearance"),
+            Some(&appearance.id().to_variant()),
+        );
+        appearance_menu.append_item(&item);
+    }
+
+    let tonemap_menu = gio::Menu::new();
+    for operator in TonemapOperator::ALL {
+        let item = gio::MenuItem::new(Some(operator.label()), None);
+        item.set_action_and_target_value(Some("win.tonemap"), Some(&operator.id().to_variant()));
+        tonemap_menu.append_item(&item);
+    }
+
     let menu_model = gio::Menu::new();
+    menu_model.append(Some("Compact Mode"), Some("win.compact-mode"));
+    menu_model.append(
+        Some("Compare with Clipboard Image"),
+        Some("win.compare-clipboard"),
+    );
+    menu_model.append(Some("Take Snapshot…"), Some("win.take-snapshot"));
+    menu_model.append(Some("Snapshots…"), Some("win.snapshots"));
+    menu_model.append(Some("Save as Template…"), Some("win.save-template"));
+    menu_model.append(Some("Templates…"), Some("win.templates"));
+    menu_model.append(Some("Translate Text…"), Some("win.translate"));
+    menu_model.append(
+        Some("Translation Settings…"),
+        Some("win.translation-settings"),
+    );
+    menu_model.append(
+        Some("Repository Upload Settings…"),
+        Some("win.forge-settings"),
+    );
+    menu_model.append(
+        Some("Nextcloud Sharing Settings…"),
+        Some("win.nextcloud-settings"),
+    );
+    menu_model.append(Some("Chat Settings…"), Some("win.chat-settings"));
+    menu_model.append(Some("Upload Settings…"), Some("win.upload-settings"));
+    menu_model.append(
+        Some("Forget Saved Credentials…"),
+        Some("win.forget-credentials"),
+    );
+    menu_model.append(Some("Optimize PNG on Save"), Some("win.optimize-png"));
+    menu_model.append(
+        Some("Check for Sensitive Text Before Export"),
+        Some("win.audit-before-export"),
+    );
+    menu_model.append(Some("Filename Template…"), Some("win.filename-template"));
+    menu_model.append(Some("Type Text on Canvas"), Some("win.text-on-canvas"));
+    menu_model.append(Some("Check Spelling"), Some("win.spell-check"));
+    menu_model.append(
+        Some("Pick Windows by Clicking"),
+        Some("win.pick-window-by-click"),
+    );
+    menu_model.append(
+        Some("Use System Region Picker"),
+        Some("win.use-portal-picker"),
+    );
+    menu_model.append(
+        Some("Capture Sound and Flash"),
+        Some("win.capture-feedback"),
+    );
+    menu_model.append(
+        Some("Hide Window in Screen Captures"),
+        Some("win.hide-own-window"),
+    );
+    menu_model.append_submenu(Some("After Capturing"), &quick_capture_menu);
+    menu_model.append_submenu(Some("Auto-save Captures"), &auto_save_menu);
+    menu_model.append_submenu(Some("Redaction Fill"), &redact_style_menu);
+    menu_model.append_submenu(Some("Image Snippets"), &snippet_menu);
+    menu_model.append_submenu(Some("Recording Format"), &recording_format_menu);
+    menu_model.append(Some("Animation Settings…"), Some("win.animation-settings"));
+    menu_model.append(Some("Recording Audio…"), Some("win.recording-audio"));
+    menu_model.append(Some("Recording Limits…"), Some("win.recording-limits"));
+    menu_model.append_submenu(Some("HDR Tonemapping"), &tonemap_menu);
+    menu_model.append_submenu(Some("Appearance"), &appearance_menu);
+    menu_model.append_submenu(Some("Canvas Background"), &backdrop_menu);
+    menu_model.append_submenu(Some("Simulate Color Blindness"), &color_vision_menu);
+    menu_model.append_submenu(Some("Annotation Palette"), &palette_menu);
+    menu_model.append_submenu(Some("Toolbar Position"), &toolbar_position_menu);
+    menu_model.append(Some("Customize Toolbar…"), Some("win.customize-toolbar"));
     menu_model.append(Some("Keyboard Shortcuts"), Some("win.shortcuts"));
+    menu_model.append(Some("Show Log"), Some("win.show-log"));
     menu_model.append(Some("About Screenshot Tool"), Some("win.about"));
     components.header.menu_btn.set_menu_model(Some(&menu_model));
 }
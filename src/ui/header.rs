@@ -16,8 +16,35 @@ pub struct HeaderComponents {
     pub mode_selection_btn: gtk::ToggleButton,
     pub mode_window_btn: gtk::ToggleButton,
     pub mode_screen_btn: gtk::ToggleButton,
+
+    /// Monitor Screen mode captures, shown when there are several
+    pub monitor_dropdown: gtk::DropDown,
+
+    /// Records a video of the mode's area instead of taking a screenshot
+    pub record_btn: gtk::ToggleButton,
+
+    /// Shows the layers panel
+    pub layers_btn: gtk::ToggleButton,
+}
+
+impl HeaderComponents {
+    /// Activate the toggle for `mode`, whose handler updates the state
+    pub fn set_mode(&self, mode: CaptureMode) {
+        match mode {
+            CaptureMode::Selection => self.mode_selection_btn.set_active(true),
+            CaptureMode::Window => self.mode_window_btn.set_active(true),
+            CaptureMode::Screen => self.mode_screen_btn.set_active(true),
+        }
+    }
 }
 
+/// First entry of the monitor dropdown, which picks on each capture
+pub const ASK_FOR_MONITOR: &str = "Ask Each Time";
+
+/// Second entry of the monitor dropdown, which stitches all monitors into
+/// one image
+pub const ALL_MONITORS: &str = "All Displays";
+
 pub fn create_header_bar(state: &Rc<RefCell<AppState>>) -> HeaderComponents {
     let take_screenshot_btn = gtk::Button::builder()
         .label("Take Screenshot")
@@ -51,12 +78,26 @@ pub fn create_header_bar(state: &Rc<RefCell<AppState>>) -> HeaderComponents {
 
     connect_mode_toggles(state, &mode_selection, &mode_window, &mode_screen);
 
+    // Filled with the monitors once the window is on a display
+    let monitor_dropdown = gtk::DropDown::from_strings(&[ASK_FOR_MONITOR]);
+    monitor_dropdown.set_tooltip_text(Some("Monitor to Capture"));
+    monitor_dropdown.set_visible(false);
+
+    let record_btn = gtk::ToggleButton::builder()
+        .icon_name("media-record-symbolic")
+        .tooltip_text("Record Video")
+        .build();
+
+    connect_record_toggle(state, &record_btn, &take_screenshot_btn);
+
     let title_box = gtk::Box::builder()
         .orientation(Orientation::Horizontal)
         .spacing(12)
         .build();
     title_box.append(&mode_label);
     title_box.append(&mode_box);
+    title_box.append(&monitor_dropdown);
+    title_box.append(&record_btn);
 
     let delay_label = gtk::Label::new(Some("Delay:"));
     delay_label.add_css_class("dim-label");
@@ -79,6 +120,11 @@ pub fn create_header_bar(state: &Rc<RefCell<AppState>>) -> HeaderComponents {
 
     connect_delay_controls(state, &delay_value, &delay_minus, &delay_plus);
 
+    let layers_btn = gtk::ToggleButton::builder()
+        .icon_name("view-list-symbolic")
+        .tooltip_text("Layers")
+        .build();
+
     let menu_btn = gtk::MenuButton::builder()
         .icon_name("open-menu-symbolic")
         .build();
@@ -90,6 +136,7 @@ pub fn create_header_bar(state: &Rc<RefCell<AppState>>) -> HeaderComponents {
     end_box.append(&delay_label);
     end_box.append(&delay_value);
     end_box.append(&delay_controls);
+    end_box.append(&layers_btn);
     end_box.append(&menu_btn);
 
     let header_bar = adw::HeaderBar::builder().title_widget(&title_box).build();
@@ -103,6 +150,9 @@ pub fn create_header_bar(state: &Rc<RefCell<AppState>>) -> HeaderComponents {
         mode_selection_btn: mode_selection,
         mode_window_btn: mode_window,
         mode_screen_btn: mode_screen,
+        monitor_dropdown,
+        record_btn,
+        layers_btn,
     }
 }
 
@@ -140,6 +190,28 @@ fn connect_mode_toggles(
     });
 }
 
+fn connect_record_toggle(
+    state: &Rc<RefCell<AppState>>,
+    record_btn: &gtk::ToggleButton,
+    take_screenshot_btn: &gtk::Button,
+) {
+    record_btn.connect_toggled({
+        let state = state.clone();
+        let take_screenshot_btn = take_screenshot_btn.clone();
+        move |btn| {
+            let record = btn.is_active();
+            state.borrow_mut().record_mode = record;
+            if record {
+                take_screenshot_btn.set_label("Start Recording");
+                take_screenshot_btn.set_icon_name("media-record-symbolic");
+            } else {
+                take_screenshot_btn.set_label("Take Screenshot");
+                take_screenshot_btn.set_icon_name("camera-photo-symbolic");
+            }
+        }
+    });
+}
+
 fn connect_delay_controls(
     state: &Rc<RefCell<AppState>>,
     delay_value: &gtk::Label,
@@ -0,0 +1,313 @@
+//! Side panel listing the annotations, topmost first, to select them, hide
+//! them and drag them into another stacking order.
+
+use gtk::prelude::*;
+use gtk::{gdk, pango};
+use gtk4 as gtk;
+use log::debug;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::app::AppState;
+use crate::editor::Annotation;
+
+/// Width of the panel's list
+const PANEL_WIDTH: i32 = 220;
+
+/// Longest text annotation shown in full as a row's title, in characters
+const MAX_TITLE_TEXT_LENGTH: usize = 40;
+
+#[derive(Clone)]
+pub struct LayersPanel {
+    pub revealer: gtk::Revealer,
+    list: gtk::ListBox,
+
+    /// `AnnotationList::revision` of the annotations the rows show
+    shown_revision: Rc<Cell<Option<u64>>>,
+
+    /// Set while the rows are rebuilt, when selecting one isn't the user's
+    /// doing
+    refreshing: Rc<Cell<bool>>,
+}
+
+/// One annotation as the panel lists it
+struct Layer {
+    index: usize,
+    icon_name: &'static str,
+    title: String,
+    hidden: bool,
+}
+
+impl Layer {
+    fn new(index: usize, annotation: &Annotation) -> Self {
+        let (icon_name, title) = match annotation {
+            Annotation::Rectangle(_) => ("app-tool-rectangle-symbolic", "Rectangle".to_string()),
+            Annotation::FreeDraw(_) => ("app-tool-pencil-symbolic", "Free Draw".to_string()),
+            Annotation::Text(text) => {
+                let line = text.text.lines().next().unwrap_or_default();
+                let mut title: String = line.chars().take(MAX_TITLE_TEXT_LENGTH).collect();
+                if title.len() < text.text.len() {
+                    title.push('…');
+                }
+                ("app-tool-text-symbolic", title)
+            }
+            Annotation::Counter(counter) => (
+                "app-tool-counter-symbolic",
+                format!("Counter {}", counter.number),
+            ),
+        };
+        Self {
+            index,
+            icon_name,
+            title,
+            hidden: annotation.is_hidden(),
+        }
+    }
+}
+
+pub fn create_layers_panel() -> LayersPanel {
+    let list = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::Single)
+        .css_classes(["navigation-sidebar"])
+        .build();
+    list.set_placeholder(Some(
+        &gtk::Label::builder()
+            .label("No annotations")
+            .css_classes(["dim-label"])
+            .margin_top(12)
+            .build(),
+    ));
+
+    let title = gtk::Label::builder()
+        .label("Layers")
+        .css_classes(["heading"])
+        .xalign(0.0)
+        .margin_top(12)
+        .margin_start(12)
+        .margin_end(12)
+        .build();
+    let scrolled = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .vexpand(true)
+        .width_request(PANEL_WIDTH)
+        .child(&list)
+        .build();
+
+    let content = gtk::Box::builder()
+        .orientation(gtk::Orientation::Vertical)
+        .spacing(6)
+        .build();
+    content.append(&title);
+    content.append(&scrolled);
+
+    let panel = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .build();
+    panel.append(&gtk::Separator::new(gtk::Orientation::Vertical));
+    panel.append(&content);
+
+    let revealer = gtk::Revealer::builder()
+        .transition_type(gtk::RevealerTransitionType::SlideLeft)
+        .child(&panel)
+        .build();
+
+    LayersPanel {
+        revealer,
+        list,
+        shown_revision: Rc::new(Cell::new(None)),
+        refreshing: Rc::new(Cell::new(false)),
+    }
+}
+
+/// Keep the panel's rows in step with the annotations, and select the
+/// annotation of a row clicked
+pub fn connect_layers_panel(
+    state: &Rc<RefCell<AppState>>,
+    panel: &LayersPanel,
+    drawing_area: &gtk::DrawingArea,
+) {
+    // Every change to the annotations redraws the canvas, so the rows are
+    // checked after each paint rather than wherever annotations change
+    drawing_area.connect_realize({
+        let state = state.clone();
+        let panel = panel.clone();
+        move |area| {
+            let clock = match area.frame_clock() {
+                Some(clock) => clock,
+                None => return,
+            };
+            clock.connect_after_paint({
+                let state = state.clone();
+                let panel = panel.clone();
+                let drawing_area = area.clone();
+                move |_| {
+                    if panel.revealer.reveals_child() {
+                        refresh_layers(&state, &panel, &drawing_area);
+                    }
+                }
+            });
+        }
+    });
+
+    panel.revealer.connect_reveal_child_notify({
+        let state = state.clone();
+        let panel = panel.clone();
+        let drawing_area = drawing_area.clone();
+        move |revealer| {
+            if revealer.reveals_child() {
+                refresh_layers(&state, &panel, &drawing_area);
+            }
+        }
+    });
+
+    panel.list.connect_row_selected({
+        let state = state.clone();
+        let refreshing = panel.refreshing.clone();
+        let drawing_area = drawing_area.clone();
+        move |_, row| {
+            let row = match row {
+                Some(row) if !refreshing.get() => row,
+                _ => return,
+            };
+            let mut s = state.borrow_mut();
+            // Rows are listed topmost first
+            let count = s.editor.annotations.len();
+            let index = match count.checked_sub(row.index() as usize + 1) {
+                Some(index) => index,
+                None => return,
+            };
+            s.editor.annotations.set_selected(Some(index));
+            drop(s);
+            drawing_area.queue_draw();
+        }
+    });
+}
+
+/// Rebuild the rows if the annotations changed since they were last built
+fn refresh_layers(
+    state: &Rc<RefCell<AppState>>,
+    panel: &LayersPanel,
+    drawing_area: &gtk::DrawingArea,
+) {
+    let (layers, selected) = {
+        let s = state.borrow();
+        let annotations = &s.editor.annotations;
+        if panel.shown_revision.get() == Some(annotations.revision()) {
+            return;
+        }
+        panel.shown_revision.set(Some(annotations.revision()));
+
+        let layers: Vec<Layer> = annotations
+            .iter()
+            .enumerate()
+            .map(|(index, annotation)| Layer::new(index, annotation))
+            .collect();
+        (layers, annotations.selected())
+    };
+    debug!("Listing {} layers", layers.len());
+
+    panel.refreshing.set(true);
+    panel.list.remove_all();
+    for layer in layers.into_iter().rev() {
+        let row = layer_row(state, drawing_area, &layer);
+        panel.list.append(&row);
+        if selected == Some(layer.index) {
+            panel.list.select_row(Some(&row));
+        }
+    }
+    panel.refreshing.set(false);
+}
+
+fn layer_row(
+    state: &Rc<RefCell<AppState>>,
+    drawing_area: &gtk::DrawingArea,
+    layer: &Layer,
+) -> gtk::ListBoxRow {
+    let index = layer.index;
+    let icon = gtk::Image::from_icon_name(layer.icon_name);
+    let label = gtk::Label::builder()
+        .label(layer.title.as_str())
+        .xalign(0.0)
+        .hexpand(true)
+        .ellipsize(pango::EllipsizeMode::End)
+        .build();
+    if layer.hidden {
+        icon.add_css_class("dim-label");
+        label.add_css_class("dim-label");
+    }
+
+    let (visibility_icon, visibility_tooltip) = if layer.hidden {
+        ("view-conceal-symbolic", "Show")
+    } else {
+        ("view-reveal-symbolic", "Hide")
+    };
+    let visibility_btn = gtk::Button::builder()
+        .icon_name(visibility_icon)
+        .tooltip_text(visibility_tooltip)
+        .css_classes(["flat"])
+        .valign(gtk::Align::Center)
+        .build();
+    visibility_btn.connect_clicked({
+        let state = state.clone();
+        let drawing_area = drawing_area.clone();
+        let hidden = !layer.hidden;
+        move |_| {
+            let changed = state
+                .borrow_mut()
+                .editor
+                .annotations
+                .set_hidden(index, hidden);
+            if changed {
+                drawing_area.queue_draw();
+            }
+        }
+    });
+
+    let row_box = gtk::Box::builder()
+        .orientation(gtk::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    row_box.append(&icon);
+    row_box.append(&label);
+    row_box.append(&visibility_btn);
+    let row = gtk::ListBoxRow::builder().child(&row_box).build();
+
+    // Dragged onto another row, the annotation takes that one's place
+    let drag_source = gtk::DragSource::builder()
+        .actions(gdk::DragAction::MOVE)
+        .build();
+    drag_source.connect_prepare(move |_, _, _| {
+        Some(gdk::ContentProvider::for_value(&(index as u32).to_value()))
+    });
+    drag_source.connect_drag_begin({
+        let row = row.clone();
+        move |source, _| {
+            source.set_icon(Some(&gtk::WidgetPaintable::new(Some(&row))), 0, 0);
+        }
+    });
+    row.add_controller(drag_source);
+
+    let drop_target = gtk::DropTarget::new(u32::static_type(), gdk::DragAction::MOVE);
+    drop_target.connect_drop({
+        let state = state.clone();
+        let drawing_area = drawing_area.clone();
+        move |_, value, _, _| {
+            let from = match value.get::<u32>() {
+                Ok(from) => from as usize,
+                Err(_) => return false,
+            };
+            let moved = state.borrow_mut().editor.annotations.reorder(from, index);
+            if moved {
+                debug!(
+                    "Moved annotation {} to {} in the stacking order",
+                    from, index
+                );
+                drawing_area.queue_draw();
+            }
+            moved
+        }
+    });
+    row.add_controller(drop_target);
+
+    row
+}
@@ -0,0 +1,82 @@
+use adw::prelude::*;
+use gtk4 as gtk;
+use libadwaita as adw;
+
+use crate::app::logging::{recent_lines, MAX_LOG_LINES};
+
+/// Show the latest log lines, ready to be copied into an issue report
+pub fn show_log_viewer(parent: &impl IsA<gtk::Window>) {
+    let buffer = gtk::TextBuffer::new(None);
+    fill_buffer(&buffer);
+
+    let text_view = gtk::TextView::builder()
+        .buffer(&buffer)
+        .editable(false)
+        .monospace(true)
+        .wrap_mode(gtk::WrapMode::WordChar)
+        .top_margin(12)
+        .bottom_margin(12)
+        .left_margin(12)
+        .right_margin(12)
+        .build();
+
+    let scrolled = gtk::ScrolledWindow::builder()
+        .child(&text_view)
+        .vexpand(true)
+        .build();
+
+    let toast_overlay = adw::ToastOverlay::builder().child(&scrolled).build();
+
+    let copy_btn = gtk::Button::builder()
+        .icon_name("edit-copy-symbolic")
+        .tooltip_text("Copy Log")
+        .build();
+    let refresh_btn = gtk::Button::builder()
+        .icon_name("view-refresh-symbolic")
+        .tooltip_text("Refresh")
+        .build();
+
+    let header_bar = adw::HeaderBar::new();
+    header_bar.pack_start(&refresh_btn);
+    header_bar.pack_end(&copy_btn);
+
+    let toolbar_view = adw::ToolbarView::new();
+    toolbar_view.add_top_bar(&header_bar);
+    toolbar_view.set_content(Some(&toast_overlay));
+
+    let dialog = adw::Dialog::builder()
+        .title(format!("Log (last {} lines)", MAX_LOG_LINES))
+        .content_width(700)
+        .content_height(500)
+        .child(&toolbar_view)
+        .build();
+
+    refresh_btn.connect_clicked({
+        let buffer = buffer.clone();
+        move |_| {
+            fill_buffer(&buffer);
+        }
+    });
+
+    copy_btn.connect_clicked({
+        let buffer = buffer.clone();
+        let toast_overlay = toast_overlay.clone();
+        move |btn| {
+            let (start, end) = buffer.bounds();
+            btn.clipboard().set_text(&buffer.text(&start, &end, false));
+            toast_overlay.add_toast(adw::Toast::new("Log copied to clipboard"));
+        }
+    });
+
+    let parent: &gtk::Window = parent.upcast_ref();
+    dialog.present(Some(parent));
+
+    // Start at the newest lines
+    text_view.scroll_to_mark(&buffer.get_insert(), 0.0, false, 0.0, 0.0);
+}
+
+/// Replace the text with the current log, cursor on the newest line
+fn fill_buffer(buffer: &gtk::TextBuffer) {
+    buffer.set_text(&recent_lines().join("\n"));
+    buffer.place_cursor(&buffer.end_iter());
+}
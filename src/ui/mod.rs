@@ -1,8 +1,18 @@
+pub mod canvas_text;
+pub mod countdown;
+pub mod customize_toolbar;
 pub mod dialogs;
 pub mod drawing;
+pub mod feedback;
 pub mod handlers;
 pub mod header;
+pub mod layers;
+pub mod log_viewer;
+pub mod monitor_picker;
+pub mod recording_indicator;
+pub mod recording_preview;
 pub mod shortcuts;
+pub mod theme;
 pub mod toolbar;
 
 use gtk4 as gtk;
@@ -12,21 +22,118 @@ use adw::prelude::*;
 use gtk::Orientation;
 use log::info;
 use std::cell::RefCell;
+use std::path::PathBuf;
 use std::rc::Rc;
 
-use crate::app::{AppState, CaptureMode};
+use crate::app::{AppState, CaptureMode, InvocationSource};
+use crate::capture::set_tonemap_operator;
+use crate::export;
+
+/// Size of the main window until it has been closed at another
+pub const DEFAULT_WINDOW_SIZE: (i32, i32) = (900, 600);
+
+/// What a launch asks for, from the first instance or forwarded to it by a
+/// later one
+#[derive(Clone, Debug, Default)]
+pub struct LaunchRequest {
+    pub invocation: InvocationSource,
+    pub start_mode: Option<CaptureMode>,
+
+    /// Image to open in the editor instead of capturing
+    pub file: Option<PathBuf>,
+}
+
+/// The main window, kept so later launches reuse it instead of opening
+/// another
+#[derive(Clone)]
+pub struct MainWindow {
+    state: Rc<RefCell<AppState>>,
+    components: handlers::UiComponents,
+}
+
+impl MainWindow {
+    /// Whether the window is still open; closing it destroys it
+    pub fn is_open(&self) -> bool {
+        self.components.window.application().is_some()
+    }
+
+    /// Raise the window and carry out `request`. A capture already under way
+    /// is left alone.
+    pub fn launch(&self, request: &LaunchRequest) {
+        let (state, components) = (&self.state, &self.components);
+        let invocation = request.invocation;
+
+        let busy = {
+            let s = state.borrow();
+            s.is_active || s.capture_cancellable.is_some() || s.recording.is_some()
+        };
+        if busy {
+            info!("Capture in progress, ignoring {:?}", request);
+            components.window.present();
+            return;
+        }
+        state.borrow_mut().invocation = invocation;
+
+        // A hotkey captures right away, in the mode it last used
+        let start_mode = match (request.start_mode, invocation) {
+            (None, InvocationSource::Hotkey) => {
+                Some(state.borrow().preferences.capture_mode(invocation))
+            }
+            (start_mode, _) => start_mode,
+        };
+        let mode =
+            start_mode.unwrap_or_else(|| state.borrow().preferences.capture_mode(invocation));
+        components.header.set_mode(mode);
+
+        info!("Presenting main window");
+        components.window.present();
+
+        if let Some(ref path) = request.file {
+            handlers::open_image_file(state, components, path);
+        } else if let Some(mode) = start_mode {
+            info!("Starting with mode: {:?} ({:?})", mode, invocation);
+            handlers::capture_screen_or_selection(state, components, mode);
+        }
+    }
+}
 
 fn load_custom_css() {
     let provider = gtk::CssProvider::new();
-    provider.load_from_string("
+    provider.load_from_string(
+        "
         .custom-toolbar {
             background-color: @window_bg_color;
             border: 1px solid @borders;
             border-radius: 12px;
             padding: 6px;
             box-shadow: 0 2px 8px rgba(0,0,0,0.15);
+            transition: opacity 200ms ease-out;
+        }
+        .custom-toolbar.faded {
+            opacity: 0;
+        }
+        .countdown {
+            border-radius: 18px;
         }
-    ");
+        .countdown-seconds {
+            font-size: 64pt;
+            font-weight: bold;
+        }
+        .monitor-picker {
+            background-color: alpha(black, 0.45);
+        }
+        .monitor-badge {
+            background-color: @window_bg_color;
+            border-radius: 24px;
+            padding: 24px 48px;
+            box-shadow: 0 2px 12px rgba(0,0,0,0.3);
+        }
+        .monitor-badge-number {
+            font-size: 96pt;
+            font-weight: bold;
+        }
+    ",
+    );
     if let Some(display) = gtk::gdk::Display::default() {
         gtk::style_context_add_provider_for_display(
             &display,
@@ -36,17 +143,23 @@ fn load_custom_css() {
     }
 }
 
-pub fn build_ui(app: &adw::Application, start_mode: Option<CaptureMode>) {
+pub fn build_ui(app: &adw::Application) -> MainWindow {
     info!("Building UI...");
     load_custom_css();
     let state = Rc::new(RefCell::new(AppState::new()));
+    export::register_builtin_targets(&mut state.borrow_mut().export_targets);
+    // Before the window exists, so it never shows in the other style
+    state.borrow().preferences.appearance.apply();
+    set_tonemap_operator(state.borrow().preferences.tonemap_operator);
 
     let header = header::create_header_bar(&state);
     let toolbar = toolbar::create_toolbar(&state);
     let crop_toolbar = toolbar::create_crop_toolbar();
     let selection_toolbar = toolbar::create_selection_toolbar();
+    let recording_toolbar = toolbar::create_recording_toolbar();
     let drawing = drawing::create_drawing_area(&state);
     let text_popover = dialogs::create_text_popover(&drawing.drawing_area);
+    let layers = layers::create_layers_panel();
 
     dialogs::connect_text_popover(&state, &drawing.drawing_area, &text_popover);
 
@@ -58,26 +171,50 @@ pub fn build_ui(app: &adw::Application, start_mode: Option<CaptureMode>) {
     );
 
     toolbar.tools_box.set_visible(false);
+    toolbar::set_toolbar_position(
+        &toolbar,
+        &crop_toolbar,
+        &selection_toolbar,
+        &recording_toolbar,
+        state.borrow().preferences.toolbar_position,
+    );
 
     let overlay = gtk::Overlay::builder().child(&drawing.drawing_area).build();
     overlay.add_overlay(&drawing.placeholder_icon);
     overlay.add_overlay(&toolbar.tools_box);
     overlay.add_overlay(&crop_toolbar.crop_tools_box);
     overlay.add_overlay(&selection_toolbar.selection_tools_box);
+    overlay.add_overlay(&recording_toolbar.recording_tools_box);
     overlay.add_overlay(&drawing.picked_color_label);
 
+    let editor_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .build();
+    editor_box.append(&overlay);
+    editor_box.append(&layers.revealer);
+
+    let toast_overlay = adw::ToastOverlay::builder().child(&editor_box).build();
+    let upload_banner = adw::Banner::builder().button_label("Cancel").build();
+
     let content = gtk::Box::builder()
         .orientation(Orientation::Vertical)
         .build();
     content.append(&header.header_bar);
-    content.append(&overlay);
+    content.append(&upload_banner);
+    content.append(&toast_overlay);
 
+    let (width, height, maximized) = {
+        let preferences = &state.borrow().preferences;
+        let (width, height) = preferences.window_size.unwrap_or(DEFAULT_WINDOW_SIZE);
+        (width, height, preferences.window_maximized)
+    };
     let window = adw::ApplicationWindow::builder()
         .application(app)
         .title("Screenshot Tool")
         .content(&content)
-        .default_width(900)
-        .default_height(600)
+        .default_width(width)
+        .default_height(height)
+        .maximized(maximized)
         .build();
 
     let components = handlers::UiComponents {
@@ -86,34 +223,17 @@ pub fn build_ui(app: &adw::Application, start_mode: Option<CaptureMode>) {
         toolbar,
         crop_toolbar,
         selection_toolbar,
+        recording_toolbar,
         drawing,
         text_popover,
+        layers,
+        toast_overlay,
+        upload_banner,
+        countdown: countdown::create_countdown_overlay(&window),
+        recording_indicator: recording_indicator::create_recording_indicator(&window),
     };
 
     handlers::connect_all_handlers(&state, &components);
 
-    info!("Presenting main window");
-    window.present();
-
-    if let Some(mode) = start_mode {
-        info!("Starting with mode: {:?}", mode);
-        match mode {
-            CaptureMode::Selection | CaptureMode::Screen => {
-                handlers::capture_screen_or_selection(
-                    &state,
-                    &components,
-                    mode,
-                );
-            }
-            CaptureMode::Window => {
-                dialogs::show_window_selector(
-                    &state,
-                    &components.window,
-                    &components.drawing.drawing_area,
-                    &components.drawing.placeholder_icon,
-                    &components.toolbar.tools_box,
-                );
-            }
-        }
-    }
+    MainWindow { state, components }
 }
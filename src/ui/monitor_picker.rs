@@ -0,0 +1,210 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::{gdk, gio, glib, Align, Orientation};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::capture::MonitorTarget;
+
+/// Monitors of `display`, numbered by their position in this list
+pub fn display_monitors(display: &gdk::Display) -> Vec<gdk::Monitor> {
+    let monitors = display.monitors();
+    (0..monitors.n_items())
+        .filter_map(|i| monitors.item(i).and_downcast::<gdk::Monitor>())
+        .collect()
+}
+
+pub fn monitor_target(monitor: &gdk::Monitor) -> MonitorTarget {
+    let geometry = monitor.geometry();
+    MonitorTarget {
+        connector: monitor.connector().map(|c| c.to_string()),
+        x: geometry.x(),
+        y: geometry.y(),
+        width: geometry.width(),
+        height: geometry.height(),
+    }
+}
+
+/// How the monitor numbered `index` is listed: its number, model and
+/// connector, and its size, such as `2: Dell U2720Q (DP-1), 2560×1440`
+pub fn monitor_label(index: usize, monitor: &gdk::Monitor) -> String {
+    let geometry = monitor.geometry();
+    let name = monitor
+        .description()
+        .or_else(|| monitor.model())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "Monitor".to_string());
+    let name = match monitor.connector() {
+        Some(connector) if !name.contains(connector.as_str()) => {
+            format!("{} ({})", name, connector)
+        }
+        _ => name,
+    };
+    format!(
+        "{}: {}, {}×{}",
+        index + 1,
+        name,
+        geometry.width(),
+        geometry.height()
+    )
+}
+
+/// A number badge covering each monitor, so Screen mode can capture the one
+/// clicked or whose number is typed
+pub struct MonitorPicker {
+    windows: Rc<RefCell<Vec<gtk::Window>>>,
+    picked: Rc<Cell<Option<usize>>>,
+
+    /// Cancelled once the choice is made, as a one-shot signal to await
+    done: gio::Cancellable,
+}
+
+impl MonitorPicker {
+    /// Enter picks `last_used`, which is marked on its badge
+    pub fn show(monitors: &[gdk::Monitor], last_used: Option<usize>) -> Self {
+        let picked = Rc::new(Cell::new(None));
+        let done = gio::Cancellable::new();
+        let windows: Rc<RefCell<Vec<gtk::Window>>> = Rc::new(RefCell::new(Vec::new()));
+
+        // Hidden rather than closed, so the capture can wait until they are off screen
+        let finish = {
+            let picked = picked.clone();
+            let done = done.clone();
+            let windows = Rc::downgrade(&windows);
+            move |choice: Option<usize>| {
+                if done.is_cancelled() {
+                    return;
+                }
+                picked.set(choice);
+                if let Some(windows) = windows.upgrade() {
+                    for window in windows.borrow().iter() {
+                        window.set_visible(false);
+                    }
+                }
+                done.cancel();
+            }
+        };
+
+        for (index, monitor) in monitors.iter().enumerate() {
+            let window = badge_window(index, monitors.len(), monitor, last_used == Some(index));
+
+            let click = gtk::GestureClick::new();
+            click.connect_released({
+                let finish = finish.clone();
+                move |_, _, _, _| finish(Some(index))
+            });
+            window.add_controller(click);
+
+            let keys = gtk::EventControllerKey::new();
+            keys.connect_key_pressed({
+                let finish = finish.clone();
+                let count = monitors.len();
+                move |_, key, _, _| {
+                    if key == gdk::Key::Escape {
+                        finish(None);
+                        return glib::Propagation::Stop;
+                    }
+                    if matches!(key, gdk::Key::Return | gdk::Key::KP_Enter) && last_used.is_some() {
+                        finish(last_used);
+                        return glib::Propagation::Stop;
+                    }
+                    match key.to_unicode().and_then(|c| c.to_digit(10)) {
+                        Some(digit) if digit >= 1 && digit as usize <= count => {
+                            finish(Some(digit as usize - 1));
+                            glib::Propagation::Stop
+                        }
+                        _ => glib::Propagation::Proceed,
+                    }
+                }
+            });
+            window.add_controller(keys);
+
+            window.connect_close_request({
+                let finish = finish.clone();
+                move |_| {
+                    finish(None);
+                    glib::Propagation::Proceed
+                }
+            });
+
+            window.fullscreen_on_monitor(monitor);
+            window.present();
+            windows.borrow_mut().push(window);
+        }
+
+        Self {
+            windows,
+            picked,
+            done,
+        }
+    }
+
+    /// Index of the monitor picked, once there is one; `None` when the
+    /// picker was dismissed
+    pub async fn choice(&self) -> Option<usize> {
+        if !self.done.is_cancelled() {
+            let _ = self.done.future().await;
+        }
+        self.picked.get()
+    }
+
+    /// The badge windows, hidden once the choice is made
+    pub fn windows(&self) -> Vec<gtk::Window> {
+        self.windows.borrow().clone()
+    }
+
+    pub fn close(&self) {
+        for window in self.windows.borrow_mut().drain(..) {
+            window.destroy();
+        }
+    }
+}
+
+fn badge_window(
+    index: usize,
+    count: usize,
+    monitor: &gdk::Monitor,
+    last_used: bool,
+) -> gtk::Window {
+    let number = gtk::Label::new(Some(&(index + 1).to_string()));
+    number.add_css_class("monitor-badge-number");
+
+    let geometry = monitor.geometry();
+    let name = match monitor.connector() {
+        Some(connector) => format!("{} • {}×{}", connector, geometry.width(), geometry.height()),
+        None => format!("{}×{}", geometry.width(), geometry.height()),
+    };
+    let name_label = gtk::Label::new(Some(&name));
+    name_label.add_css_class("title-4");
+
+    let hint = gtk::Label::new(Some(&format!(
+        "Click or press 1–{} to capture a screen, Escape to cancel",
+        count
+    )));
+    hint.add_css_class("dim-label");
+
+    let badge = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(12)
+        .halign(Align::Center)
+        .valign(Align::Center)
+        .build();
+    badge.add_css_class("monitor-badge");
+    badge.append(&number);
+    badge.append(&name_label);
+    if last_used {
+        let last_used_label = gtk::Label::new(Some("Last used, press Enter to capture it again"));
+        last_used_label.add_css_class("accent");
+        badge.append(&last_used_label);
+    }
+    badge.append(&hint);
+
+    let window = gtk::Window::builder()
+        .decorated(false)
+        .title("Pick a Screen")
+        .child(&badge)
+        .build();
+    window.add_css_class("monitor-picker");
+    window
+}
@@ -0,0 +1,74 @@
+use gtk4 as gtk;
+
+use gtk::prelude::*;
+use gtk::Orientation;
+
+/// Small undecorated window showing a recording in progress while the main
+/// window is hidden or minimized, with its time and controls
+#[derive(Clone)]
+pub struct RecordingIndicator {
+    pub window: gtk::Window,
+    pub duration_label: gtk::Label,
+    pub pause_btn: gtk::Button,
+    pub stop_btn: gtk::Button,
+
+    /// Brings the main window back
+    pub show_btn: gtk::Button,
+}
+
+pub fn create_recording_indicator(parent: &impl IsA<gtk::Window>) -> RecordingIndicator {
+    let dot = gtk::Image::from_icon_name("media-record-symbolic");
+    dot.add_css_class("error");
+
+    let duration_label = gtk::Label::builder().label("0:00").width_chars(7).build();
+    duration_label.add_css_class("numeric");
+
+    let pause_btn = gtk::Button::builder()
+        .icon_name("media-playback-pause-symbolic")
+        .tooltip_text("Pause")
+        .build();
+    pause_btn.add_css_class("flat");
+
+    let stop_btn = gtk::Button::builder()
+        .icon_name("media-playback-stop-symbolic")
+        .tooltip_text("Stop Recording")
+        .build();
+    stop_btn.add_css_class("flat");
+
+    let show_btn = gtk::Button::builder()
+        .icon_name("view-restore-symbolic")
+        .tooltip_text("Show Window")
+        .build();
+    show_btn.add_css_class("flat");
+
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(6)
+        .margin_top(6)
+        .margin_bottom(6)
+        .margin_start(12)
+        .margin_end(6)
+        .build();
+    content.append(&dot);
+    content.append(&duration_label);
+    content.append(&pause_btn);
+    content.append(&stop_btn);
+    content.append(&show_btn);
+
+    let window = gtk::Window::builder()
+        .transient_for(parent)
+        .decorated(false)
+        .resizable(false)
+        .title("Recording")
+        .child(&content)
+        .build();
+    window.add_css_class("osd");
+
+    RecordingIndicator {
+        window,
+        duration_label,
+        pause_btn,
+        stop_btn,
+        show_btn,
+    }
+}
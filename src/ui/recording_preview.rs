@@ -0,0 +1,482 @@
+use gtk4 as gtk;
+use libadwaita as adw;
+
+use adw::prelude::*;
+use gtk::gdk;
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::gio;
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::capture::recording::{format_elapsed, trim_available, RecordingFormat};
+
+/// How close to a trim handle, in pixels, a drag on the timeline grabs it
+const HANDLE_REACH: f64 = 12.0;
+
+const HANDLE_WIDTH: f64 = 6.0;
+
+const TIMELINE_HEIGHT: i32 = 32;
+
+/// Shortest part of a recording a trim keeps, in microseconds
+const MIN_TRIM_LENGTH: i64 = 200_000;
+
+/// The part of the timeline a trim keeps is drawn in the accent blue
+const KEPT_COLOR: (f64, f64, f64) = (0.208, 0.518, 0.894);
+
+/// Part of a recording a trim keeps, in microseconds as media streams count
+/// time. An empty range is one not set yet, which keeps everything.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TrimRange {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl TrimRange {
+    /// Whether anything is cut off a recording `duration` long
+    pub fn cuts(&self, duration: i64) -> bool {
+        duration > 0 && self.end > self.start && (self.start > 0 || self.end < duration)
+    }
+
+    /// The range with the end `grab` moved to `time`, keeping the ends at
+    /// least `MIN_TRIM_LENGTH` apart
+    fn moved(self, grab: TimelineGrab, time: i64, duration: i64) -> Self {
+        match grab {
+            TimelineGrab::Start => Self {
+                start: time.clamp(0, (self.end - MIN_TRIM_LENGTH).max(0)),
+                ..self
+            },
+            TimelineGrab::End => Self {
+                end: time.clamp((self.start + MIN_TRIM_LENGTH).min(duration), duration),
+                ..self
+            },
+            TimelineGrab::Playhead => self,
+        }
+    }
+}
+
+/// What a drag on the timeline moves
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TimelineGrab {
+    Start,
+    End,
+    Playhead,
+}
+
+fn x_at(time: i64, width: f64, duration: i64) -> f64 {
+    if duration <= 0 {
+        return 0.0;
+    }
+    time.clamp(0, duration) as f64 / duration as f64 * width
+}
+
+fn time_at(x: f64, width: f64, duration: i64) -> i64 {
+    if width <= 0.0 {
+        return 0;
+    }
+    ((x / width).clamp(0.0, 1.0) * duration as f64).round() as i64
+}
+
+/// What a drag starting at `x` on a timeline `width` pixels wide moves: the
+/// nearer trim handle when it is within reach, the playhead otherwise
+fn grab_at(x: f64, width: f64, duration: i64, trim: TrimRange) -> TimelineGrab {
+    let to_start = (x - x_at(trim.start, width, duration)).abs();
+    let to_end = (x - x_at(trim.end, width, duration)).abs();
+    if to_start.min(to_end) > HANDLE_REACH {
+        TimelineGrab::Playhead
+    } else if to_start < to_end {
+        TimelineGrab::Start
+    } else {
+        TimelineGrab::End
+    }
+}
+
+/// Dialog playing back a finished recording, from which it can be trimmed
+/// or a frame taken into the editor
+#[derive(Clone)]
+pub struct RecordingPreview {
+    pub dialog: adw::Dialog,
+    pub media: gtk::MediaFile,
+    picture: gtk::Picture,
+    timeline: gtk::DrawingArea,
+    trim: Rc<Cell<TrimRange>>,
+    path: PathBuf,
+
+    /// Cuts the recording down to the part between the trim handles
+    pub trim_btn: gtk::Button,
+
+    /// Takes the frame shown into the editor
+    pub grab_frame_btn: gtk::Button,
+
+    /// Opens the recording in the default video player
+    pub open_btn: gtk::Button,
+
+    /// Uploads the recording to Nextcloud and copies a link to it; shown
+    /// once Nextcloud sharing is set up
+    pub share_btn: gtk::Button,
+}
+
+impl RecordingPreview {
+    /// The frame being shown, paused there, at the recording's own size
+    pub fn current_frame(&self) -> Option<Pixbuf> {
+        self.media.pause();
+        let image = self.media.current_image();
+        let (width, height) = (image.intrinsic_width(), image.intrinsic_height());
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+
+        // Frames are usually textures already; anything else is drawn into one
+        let texture = match image.downcast::<gdk::Texture>() {
+            Ok(texture) => texture,
+            Err(image) => {
+                let snapshot = gtk::Snapshot::new();
+                image.snapshot(&snapshot, width as f64, height as f64);
+                let node = snapshot.to_node()?;
+                let renderer = self.picture.native()?.renderer()?;
+                renderer.render_texture(node, None)
+            }
+        };
+        Some(texture_to_pixbuf(&texture))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the trim handles are, when they cut anything off
+    pub fn trim_range(&self) -> Option<(Duration, Duration)> {
+        let trim = self.trim.get();
+        if !trim.cuts(self.media.duration()) {
+            return None;
+        }
+        Some((
+            Duration::from_micros(trim.start as u64),
+            Duration::from_micros(trim.end as u64),
+        ))
+    }
+
+    /// Load the recording again, such as once it is trimmed, with the trim
+    /// handles back at its ends
+    pub fn reload(&self) {
+        self.trim.set(TrimRange::default());
+        self.trim_btn.set_sensitive(false);
+        self.media.set_file(Some(&gio::File::for_path(&self.path)));
+        self.media.play();
+        self.timeline.queue_draw();
+    }
+}
+
+fn texture_to_pixbuf(texture: &gdk::Texture) -> Pixbuf {
+    let mut downloader = gdk::TextureDownloader::new(texture);
+    downloader.set_format(gdk::MemoryFormat::R8g8b8a8);
+    let (bytes, stride) = downloader.download_bytes();
+    Pixbuf::from_bytes(
+        &bytes,
+        Colorspace::Rgb,
+        true,
+        8,
+        texture.width(),
+        texture.height(),
+        stride as i32,
+    )
+}
+
+fn draw_timeline(
+    area: &gtk::DrawingArea,
+    cr: &gtk::cairo::Context,
+    (width, height): (f64, f64),
+    media: &gtk::MediaFile,
+    trim: TrimRange,
+) {
+    let color = area.color();
+    let (red, green, blue) = (
+        color.red() as f64,
+        color.green() as f64,
+        color.blue() as f64,
+    );
+    let duration = media.duration();
+
+    cr.set_source_rgba(red, green, blue, 0.12);
+    cr.rectangle(0.0, 0.0, width, height);
+    let _ = cr.fill();
+    if duration <= 0 {
+        return;
+    }
+
+    let start = x_at(trim.start, width, duration);
+    let end = x_at(trim.end, width, duration);
+    let (kept_red, kept_green, kept_blue) = KEPT_COLOR;
+    cr.set_source_rgba(kept_red, kept_green, kept_blue, 0.3);
+    cr.rectangle(start, 0.0, end - start, height);
+    let _ = cr.fill();
+
+    cr.set_source_rgb(kept_red, kept_green, kept_blue);
+    cr.rectangle(start, 0.0, HANDLE_WIDTH, height);
+    cr.rectangle(end - HANDLE_WIDTH, 0.0, HANDLE_WIDTH, height);
+    let _ = cr.fill();
+
+    let playhead = x_at(media.timestamp(), width, duration);
+    cr.set_source_rgb(red, green, blue);
+    cr.rectangle(playhead - 1.0, 0.0, 2.0, height);
+    let _ = cr.fill();
+}
+
+fn update_time_label(label: &gtk::Label, media: &gtk::MediaFile) {
+    let time = |micros: i64| format_elapsed(Duration::from_micros(micros.max(0) as u64));
+    label.set_label(&format!(
+        "{} / {}",
+        time(media.timestamp()),
+        time(media.duration())
+    ));
+}
+
+pub fn create_recording_preview(path: &Path) -> RecordingPreview {
+    let media = gtk::MediaFile::for_filename(path);
+    media.play();
+    let picture = gtk::Picture::builder()
+        .paintable(&media)
+        .vexpand(true)
+        .hexpand(true)
+        .build();
+
+    let play_btn = gtk::Button::builder()
+        .icon_name("media-playback-pause-symbolic")
+        .tooltip_text("Play or Pause")
+        .valign(gtk::Align::Center)
+        .build();
+    play_btn.add_css_class("flat");
+    let timeline = gtk::DrawingArea::builder()
+        .content_height(TIMELINE_HEIGHT)
+        .hexpand(true)
+        .valign(gtk::Align::Center)
+        .tooltip_text("Drag the ends to trim the recording")
+        .build();
+    let time_label = gtk::Label::new(Some("0:00 / 0:00"));
+    time_label.add_css_class("numeric");
+
+    let controls = gtk::Box::builder()
+        .spacing(12)
+        .margin_start(12)
+        .margin_end(12)
+        .margin_top(6)
+        .margin_bottom(6)
+        .build();
+    controls.append(&play_btn);
+    controls.append(&timeline);
+    controls.append(&time_label);
+
+    let trim_btn = gtk::Button::builder()
+        .icon_name("edit-cut-symbolic")
+        .tooltip_text("Trim to the Marked Part")
+        .sensitive(false)
+        .build();
+    // GIFs are cut by their maximum length instead
+    let trimmable = path
+        .extension()
+        .and_then(|extension| RecordingFormat::from_id(&extension.to_string_lossy()))
+        .is_some_and(|format| !format.is_animation());
+    trim_btn.set_visible(trimmable && trim_available());
+    let grab_frame_btn = gtk::Button::builder()
+        .icon_name("camera-photo-symbolic")
+        .tooltip_text("Edit This Frame")
+        .build();
+    let open_btn = gtk::Button::builder()
+        .icon_name("external-link-symbolic")
+        .tooltip_text("Open in Video Player")
+        .build();
+    let share_btn = gtk::Button::builder()
+        .icon_name("emblem-shared-symbolic")
+        .tooltip_text("Share with Nextcloud")
+        .visible(false)
+        .build();
+
+    let header_bar = adw::HeaderBar::new();
+    header_bar.pack_start(&grab_frame_btn);
+    header_bar.pack_start(&trim_btn);
+    header_bar.pack_end(&open_btn);
+    header_bar.pack_end(&share_btn);
+
+    let toolbar_view = adw::ToolbarView::new();
+    toolbar_view.add_top_bar(&header_bar);
+    toolbar_view.add_bottom_bar(&controls);
+    toolbar_view.set_content(Some(&picture));
+
+    let title = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Recording".to_string());
+    let dialog = adw::Dialog::builder()
+        .title(title)
+        .content_width(800)
+        .content_height(560)
+        .child(&toolbar_view)
+        .build();
+
+    let trim = Rc::new(Cell::new(TrimRange::default()));
+    timeline.set_draw_func({
+        let media = media.clone();
+        let trim = trim.clone();
+        move |area, cr, width, height| {
+            draw_timeline(area, cr, (width as f64, height as f64), &media, trim.get());
+        }
+    });
+
+    // Dragging near a handle moves it, showing the frame it is at, and
+    // dragging anywhere else seeks
+    let drag = gtk::GestureDrag::new();
+    let grab = Rc::new(Cell::new(TimelineGrab::Playhead));
+    let drag_to = {
+        let media = media.clone();
+        let timeline = timeline.clone();
+        let trim = trim.clone();
+        let trim_btn = trim_btn.clone();
+        let grab = grab.clone();
+        move |x: f64| {
+            let duration = media.duration();
+            let time = time_at(x, timeline.width() as f64, duration);
+            if grab.get() != TimelineGrab::Playhead {
+                media.pause();
+                trim.set(trim.get().moved(grab.get(), time, duration));
+                trim_btn.set_sensitive(trim.get().cuts(duration));
+            }
+            if media.is_seekable() {
+                media.seek(time);
+            }
+            timeline.queue_draw();
+        }
+    };
+    drag.connect_drag_begin({
+        let media = media.clone();
+        let timeline = timeline.clone();
+        let trim = trim.clone();
+        let grab = grab.clone();
+        let drag_to = drag_to.clone();
+        move |_, x, _| {
+            let width = timeline.width() as f64;
+            grab.set(grab_at(x, width, media.duration(), trim.get()));
+            drag_to(x);
+        }
+    });
+    drag.connect_drag_update(move |drag, offset_x, _| {
+        if let Some((start_x, _)) = drag.start_point() {
+            drag_to(start_x + offset_x);
+        }
+    });
+    timeline.add_controller(drag);
+
+    play_btn.connect_clicked({
+        let media = media.clone();
+        let trim = trim.clone();
+        move |_| {
+            if media.is_playing() {
+                media.pause();
+                return;
+            }
+            // Playing starts over from the first kept frame once past the
+            // last one
+            let range = trim.get();
+            if range.cuts(media.duration())
+                && (media.timestamp() < range.start || media.timestamp() >= range.end)
+            {
+                media.seek(range.start);
+            } else if media.is_ended() {
+                media.seek(0);
+            }
+            media.play();
+        }
+    });
+    media.connect_playing_notify({
+        let play_btn = play_btn.clone();
+        move |media| {
+            play_btn.set_icon_name(if media.is_playing() {
+                "media-playback-pause-symbolic"
+            } else {
+                "media-playback-start-symbolic"
+            });
+        }
+    });
+    media.connect_duration_notify({
+        let timeline = timeline.clone();
+        let trim = trim.clone();
+        let time_label = time_label.clone();
+        move |media| {
+            if trim.get().end == 0 {
+                trim.set(TrimRange {
+                    start: 0,
+                    end: media.duration(),
+                });
+            }
+            update_time_label(&time_label, media);
+            timeline.queue_draw();
+        }
+    });
+    media.connect_timestamp_notify({
+        let timeline = timeline.clone();
+        let trim = trim.clone();
+        move |media| {
+            // Playback stops at the end of the part kept
+            let range = trim.get();
+            if media.is_playing() && range.cuts(media.duration()) && media.timestamp() >= range.end
+            {
+                media.pause();
+            }
+            update_time_label(&time_label, media);
+            timeline.queue_draw();
+        }
+    });
+
+    // Nothing is left playing once the dialog is gone
+    dialog.connect_closed({
+        let media = media.clone();
+        move |_| media.pause()
+    });
+
+    RecordingPreview {
+        dialog,
+        media,
+        picture,
+        timeline,
+        trim,
+        path: path.to_path_buf(),
+        trim_btn,
+        grab_frame_btn,
+        open_btn,
+        share_btn,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_handles() {
+        let duration = 10_000_000;
+        let trim = TrimRange {
+            start: 0,
+            end: duration,
+        };
+        assert!(!trim.cuts(duration));
+
+        // On a 500 pixel timeline, 1 s is 50 pixels
+        assert_eq!(time_at(125.0, 500.0, duration), 2_500_000);
+        assert_eq!(grab_at(5.0, 500.0, duration, trim), TimelineGrab::Start);
+        assert_eq!(grab_at(492.0, 500.0, duration, trim), TimelineGrab::End);
+        assert_eq!(
+            grab_at(250.0, 500.0, duration, trim),
+            TimelineGrab::Playhead
+        );
+
+        let trim = trim.moved(TimelineGrab::Start, 2_500_000, duration);
+        assert_eq!(trim.start, 2_500_000);
+        assert!(trim.cuts(duration));
+
+        // The ends never cross
+        let trim = trim.moved(TimelineGrab::End, 1_000_000, duration);
+        assert_eq!(trim.end, 2_500_000 + MIN_TRIM_LENGTH);
+        let trim = trim.moved(TimelineGrab::Start, duration, duration);
+        assert_eq!(trim.start, 2_500_000);
+    }
+}
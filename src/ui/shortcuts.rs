@@ -6,6 +6,7 @@ use std::rc::Rc;
 
 use crate::app::config::Action;
 use crate::app::AppState;
+use crate::editor::palette_colors;
 
 pub fn show_shortcuts_dialog(state: &Rc<RefCell<AppState>>, parent: &impl IsA<gtk::Window>) {
     let window = adw::PreferencesWindow::builder()
@@ -22,7 +23,10 @@ pub fn show_shortcuts_dialog(state: &Rc<RefCell<AppState>>, parent: &impl IsA<gt
     let group_general = adw::PreferencesGroup::builder().title("General").build();
     add_action_row(state, &group_general, Action::Copy, "Copy to Clipboard");
     add_action_row(state, &group_general, Action::Save, "Save to File");
+    add_action_row(state, &group_general, Action::ExportAgain, "Export Again");
+    add_action_row(state, &group_general, Action::SendToChat, "Send to Chat");
     add_action_row(state, &group_general, Action::Undo, "Undo");
+    add_action_row(state, &group_general, Action::Redo, "Redo");
     add_action_row(state, &group_general, Action::Cancel, "Cancel / Exit");
     add_action_row(state, &group_general, Action::Confirm, "Confirm Selection");
     add_action_row(
@@ -38,6 +42,7 @@ pub fn show_shortcuts_dialog(state: &Rc<RefCell<AppState>>, parent: &impl IsA<gt
     add_action_row(state, &group_tools, Action::ToolPencil, "Pencil");
     add_action_row(state, &group_tools, Action::ToolRectangle, "Rectangle");
     add_action_row(state, &group_tools, Action::ToolText, "Text");
+    add_action_row(state, &group_tools, Action::ToolCounter, "Counter");
     add_action_row(state, &group_tools, Action::ToolCrop, "Crop");
     page.add(&group_tools);
 
@@ -54,6 +59,15 @@ pub fn show_shortcuts_dialog(state: &Rc<RefCell<AppState>>, parent: &impl IsA<gt
     add_action_row(state, &group_modes, Action::SwitchToScreen, "Screen Mode");
     page.add(&group_modes);
 
+    let group_colors = adw::PreferencesGroup::builder()
+        .title("Palette Colors")
+        .build();
+    let preset = state.borrow().preferences.palette;
+    for (slot, (name, _)) in palette_colors(preset).into_iter().enumerate() {
+        add_action_row(state, &group_colors, Action::ColorSlot(slot), name);
+    }
+    page.add(&group_colors);
+
     window.present();
 }
 
@@ -0,0 +1,297 @@
+use libadwaita as adw;
+
+use adw::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Appearance {
+    /// Whatever the desktop prefers
+    #[default]
+    System,
+
+    Light,
+
+    Dark,
+}
+
+impl Appearance {
+    pub const ALL: [Appearance; 3] = [Self::System, Self::Light, Self::Dark];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::System => "system",
+            Self::Light => "light",
+            Self::Dark => "dark",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::System => "Follow System",
+            Self::Light => "Light",
+            Self::Dark => "Dark",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|appearance| appearance.id() == id)
+    }
+
+    pub fn apply(&self) {
+        let scheme = match self {
+            Self::System => adw::ColorScheme::Default,
+            Self::Light => adw::ColorScheme::ForceLight,
+            Self::Dark => adw::ColorScheme::ForceDark,
+        };
+        adw::StyleManager::default().set_color_scheme(scheme);
+    }
+}
+
+/// What the canvas shows behind the image
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanvasBackdrop {
+    /// Dark or light, following the style
+    #[default]
+    Theme,
+
+    Dark,
+
+    Light,
+
+    /// Checkerboard under the image, so transparent pixels show as they
+    /// will be exported
+    Checkerboard,
+}
+
+impl CanvasBackdrop {
+    pub const ALL: [CanvasBackdrop; 4] = [Self::Theme, Self::Dark, Self::Light, Self::Checkerboard];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Theme => "theme",
+            Self::Dark => "dark",
+            Self::Light => "light",
+            Self::Checkerboard => "checkerboard",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Theme => "Match Appearance",
+            Self::Dark => "Dark",
+            Self::Light => "Light",
+            Self::Checkerboard => "Checkerboard",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|backdrop| backdrop.id() == id)
+    }
+}
+
+/// Colors for what the canvas draws around and on top of the image, picked
+/// to contrast with the current light or dark style
+#[derive(Clone, Copy, Debug)]
+pub struct CanvasChrome {
+    /// Backdrop behind the image
+    pub background: (f64, f64, f64),
+
+    /// Crop border, text caret and hint text
+    pub foreground: (f64, f64, f64),
+
+    /// Wash over what the crop leaves out
+    pub dim: (f64, f64, f64, f64),
+
+    /// Fill of on-canvas hints
+    pub hint_background: (f64, f64, f64, f64),
+}
+
+impl CanvasChrome {
+    /// Chrome contrasting with `backdrop`, which outside the image of a
+    /// checkerboard is the style's own
+    pub fn new(backdrop: CanvasBackdrop) -> Self {
+        let dark = match backdrop {
+            CanvasBackdrop::Theme | CanvasBackdrop::Checkerboard => {
+                adw::StyleManager::default().is_dark()
+            }
+            CanvasBackdrop::Dark => true,
+            CanvasBackdrop::Light => false,
+        };
+
+        if dark {
+            Self {
+                background: (0.14, 0.14, 0.14),
+                foreground: (1.0, 1.0, 1.0),
+                dim: (0.0, 0.0, 0.0, 0.5),
+                hint_background: (0.0, 0.0, 0.0, 0.75),
+            }
+        } else {
+            Self {
+                background: (0.87, 0.87, 0.87),
+                foreground: (0.12, 0.12, 0.12),
+                dim: (1.0, 1.0, 1.0, 0.6),
+                hint_background: (1.0, 1.0, 1.0, 0.9),
+            }
+        }
+    }
+}
+
+/// Color vision the canvas is shown with, so markup can be checked to stay
+/// distinguishable for readers with red-green color blindness. Only the
+/// canvas is affected, never what is exported.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorVision {
+    #[default]
+    Normal,
+
+    /// Missing green cones, the most common kind
+    Deuteranopia,
+
+    /// Missing red cones
+    Protanopia,
+}
+
+impl ColorVision {
+    pub const ALL: [ColorVision; 3] = [Self::Normal, Self::Deuteranopia, Self::Protanopia];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Deuteranopia => "deuteranopia",
+            Self::Protanopia => "protanopia",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Normal => "Normal Vision",
+            Self::Deuteranopia => "Deuteranopia",
+            Self::Protanopia => "Protanopia",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|vision| vision.id() == id)
+    }
+
+    /// Machado, Oliveira and Fernandes' matrices for complete dichromacy,
+    /// applied to linear RGB
+    fn matrix(&self) -> Option<[[f32; 3]; 3]> {
+        match self {
+            Self::Normal => None,
+            Self::Deuteranopia => Some([
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ]),
+            Self::Protanopia => Some([
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ]),
+        }
+    }
+
+    /// Recolor the pixels of a Cairo `ARgb32` surface, premultiplied words
+    /// in native byte order, as they would look with this color vision
+    pub fn simulate(&self, data: &mut [u8]) {
+        let matrix = match self.matrix() {
+            Some(matrix) => matrix,
+            None => return,
+        };
+
+        let to_linear: Vec<f32> = (0..256)
+            .map(|value| {
+                let value = value as f32 / 255.0;
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            })
+            .collect();
+        // Finer steps than 8 bits, as linear dark shades are close together
+        const STEPS: usize = 4096;
+        let to_srgb: Vec<u8> = (0..STEPS)
+            .map(|step| {
+                let value = step as f32 / (STEPS - 1) as f32;
+                let value = if value <= 0.0031308 {
+                    value * 12.92
+                } else {
+                    1.055 * value.powf(1.0 / 2.4) - 0.055
+                };
+                (value * 255.0).round() as u8
+            })
+            .collect();
+
+        for pixel in data.chunks_exact_mut(4) {
+            let word = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            let alpha = word >> 24;
+            if alpha == 0 {
+                continue;
+            }
+            let unpremultiply = |shift: u32| (((word >> shift) & 0xff) * 255 / alpha).min(255);
+            let rgb = [
+                to_linear[unpremultiply(16) as usize],
+                to_linear[unpremultiply(8) as usize],
+                to_linear[unpremultiply(0) as usize],
+            ];
+
+            let mut out = alpha << 24;
+            for (row, shift) in matrix.iter().zip([16, 8, 0]) {
+                let value = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                let step = (value.clamp(0.0, 1.0) * (STEPS - 1) as f32).round() as usize;
+                out |= (to_srgb[step] as u32 * alpha / 255) << shift;
+            }
+            pixel.copy_from_slice(&out.to_ne_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simulated(vision: ColorVision, argb: u32) -> [u32; 4] {
+        let mut data = argb.to_ne_bytes();
+        vision.simulate(&mut data);
+        let word = u32::from_ne_bytes(data);
+        [
+            word >> 24,
+            (word >> 16) & 0xff,
+            (word >> 8) & 0xff,
+            word & 0xff,
+        ]
+    }
+
+    #[test]
+    fn test_simulate_color_vision() {
+        assert_eq!(
+            simulated(ColorVision::Normal, 0xffe01b24),
+            [0xff, 0xe0, 0x1b, 0x24]
+        );
+
+        for vision in [ColorVision::Deuteranopia, ColorVision::Protanopia] {
+            // Grays look the same to everyone
+            for gray in [0xffffffff, 0xff000000, 0xff808080] {
+                let [a, r, g, b] = simulated(vision, gray);
+                assert_eq!(a, 0xff);
+                assert!(r.abs_diff(gray & 0xff) <= 1 && g.abs_diff(r) <= 1 && b.abs_diff(r) <= 1);
+            }
+
+            // Pure red turns to a dark yellow
+            let [_, r, g, b] = simulated(vision, 0xffff0000);
+            assert!(r.abs_diff(g) < 24, "{:?}: {} {}", vision, r, g);
+            assert_eq!(b, 0);
+
+            // Translucent pixels keep their alpha and stay premultiplied
+            let [a, r, g, _] = simulated(vision, 0x80800000);
+            assert_eq!(a, 0x80);
+            assert!(r <= a && g <= a);
+        }
+    }
+}
@@ -1,28 +1,47 @@
 use gtk4 as gtk;
 
+use gtk::gdk::RGBA;
 use gtk::{Align, Orientation};
 use gtk4::prelude::*;
+use log::warn;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use crate::app::AppState;
-use crate::editor::EditorTool;
+use crate::app::{AppState, ToolbarItem, ToolbarLayout, ToolbarPosition};
+use crate::capture::recording::AudioTrack;
+use crate::editor::{palette_colors, remember_custom_color, save_custom_colors, EditorTool};
 
 #[derive(Clone)]
 pub struct ToolbarComponents {
     pub tools_box: gtk::Box,
+    pub tool_buttons_box: gtk::Box,
     pub tool_pointer_btn: gtk::ToggleButton,
     pub tool_pencil_btn: gtk::ToggleButton,
     pub tool_rectangle_btn: gtk::ToggleButton,
+    pub tool_redact_btn: gtk::ToggleButton,
     pub tool_crop_btn: gtk::ToggleButton,
     pub tool_text_btn: gtk::ToggleButton,
+    pub tool_counter_btn: gtk::ToggleButton,
     pub tool_color_picker_btn: gtk::ToggleButton,
-    #[allow(dead_code)]
     pub color_button: gtk::ColorDialogButton,
+    pub palette_btn: gtk::MenuButton,
     #[allow(dead_code)]
     pub color_picker_circle: gtk::DrawingArea,
     pub undo_btn: gtk::Button,
+    pub redo_btn: gtk::Button,
+
+    /// Steps that can be undone, listed when the popover opens
+    pub history_btn: gtk::MenuButton,
+    pub history_box: gtk::ListBox,
+    pub info_btn: gtk::MenuButton,
+    pub info_grid: gtk::Grid,
+
+    /// Links found in the image, listed when the popover opens
+    pub links_btn: gtk::MenuButton,
+    pub links_box: gtk::ListBox,
+    pub discard_btn: gtk::Button,
     pub copy_btn: gtk::Button,
+    pub export_btn: gtk::MenuButton,
     pub save_btn: gtk::Button,
 }
 
@@ -31,6 +50,9 @@ pub struct CropToolbarComponents {
     pub crop_tools_box: gtk::Box,
     pub confirm_btn: gtk::Button,
     pub cancel_btn: gtk::Button,
+
+    /// Undoes an earlier crop to adjust it on the whole image
+    pub reveal_btn: gtk::Button,
 }
 
 #[derive(Clone)]
@@ -38,11 +60,44 @@ pub struct SelectionToolbarComponents {
     pub selection_tools_box: gtk::Box,
     pub confirm_btn: gtk::Button,
     pub cancel_btn: gtk::Button,
+
+    /// Drags draw the selection; the other tools annotate the overlay
+    pub select_btn: gtk::ToggleButton,
+    pub pencil_btn: gtk::ToggleButton,
+    pub rectangle_btn: gtk::ToggleButton,
+
+    /// Fixed selection sizes
+    pub size_btn: gtk::MenuButton,
+}
+
+#[derive(Clone)]
+pub struct RecordingToolbarComponents {
+    pub recording_tools_box: gtk::Box,
+
+    /// Time recorded so far, not counting pauses
+    pub duration_label: gtk::Label,
+    pub pause_btn: gtk::Button,
+    pub stop_btn: gtk::Button,
+
+    /// Pressed while the track is heard; shown for the tracks recorded
+    pub system_audio_btn: gtk::ToggleButton,
+    pub microphone_btn: gtk::ToggleButton,
+
+    /// Shows the editor's canvas for drawing over the recording
+    pub annotate_btn: gtk::ToggleButton,
+
+    /// Hides the main window, leaving the recording indicator
+    pub hide_btn: gtk::Button,
 }
 
 pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
     let color_button = gtk::ColorDialogButton::builder()
-        .dialog(&gtk::ColorDialog::new())
+        .dialog(
+            &gtk::ColorDialog::builder()
+                .title("Annotation Color")
+                .with_alpha(true)
+                .build(),
+        )
         .rgba(&gtk::gdk::RGBA::new(1.0, 0.0, 0.0, 1.0))
         .tooltip_text("Select Color")
         .build();
@@ -50,6 +105,7 @@ pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
     let color_picker_circle = create_color_picker_circle(state);
 
     connect_color_button(state, &color_button, &color_picker_circle);
+    let palette_btn = create_palette_button(state, &color_button);
 
     let tool_pointer_btn = gtk::ToggleButton::builder()
         .icon_name("app-tool-pointer-symbolic")
@@ -72,6 +128,13 @@ pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
         .build();
     tool_rectangle_btn.add_css_class("flat");
 
+    let tool_redact_btn = gtk::ToggleButton::builder()
+        .icon_name("media-playback-stop-symbolic")
+        .tooltip_text("Redact")
+        .group(&tool_pointer_btn)
+        .build();
+    tool_redact_btn.add_css_class("flat");
+
     let tool_crop_btn = gtk::ToggleButton::builder()
         .icon_name("app-tool-crop-symbolic")
         .tooltip_text("Crop")
@@ -86,6 +149,13 @@ pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
         .build();
     tool_text_btn.add_css_class("flat");
 
+    let tool_counter_btn = gtk::ToggleButton::builder()
+        .icon_name("app-tool-counter-symbolic")
+        .tooltip_text("Counter")
+        .group(&tool_pointer_btn)
+        .build();
+    tool_counter_btn.add_css_class("flat");
+
     let tool_color_picker_btn = gtk::ToggleButton::builder()
         .icon_name("app-tool-color-picker-symbolic")
         .tooltip_text("Pick Color")
@@ -100,26 +170,80 @@ pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
         .build();
     tool_buttons_box.add_css_class("tool-buttons");
 
-    tool_buttons_box.append(&tool_pointer_btn);
-    tool_buttons_box.append(&tool_pencil_btn);
-    tool_buttons_box.append(&tool_rectangle_btn);
-    tool_buttons_box.append(&tool_crop_btn);
-    tool_buttons_box.append(&tool_text_btn);
-    tool_buttons_box.append(&tool_color_picker_btn);
-    tool_buttons_box.append(&color_button);
-
     let undo_btn = gtk::Button::builder()
         .icon_name("app-edit-undo-symbolic")
         .tooltip_text("Undo")
         .build();
     undo_btn.add_css_class("flat");
 
+    let redo_btn = gtk::Button::builder()
+        .icon_name("app-edit-redo-symbolic")
+        .tooltip_text("Redo")
+        .build();
+    redo_btn.add_css_class("flat");
+
+    let history_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    history_box.add_css_class("boxed-list");
+    let history_scroll = gtk::ScrolledWindow::builder()
+        .hscrollbar_policy(gtk::PolicyType::Never)
+        .propagate_natural_height(true)
+        .max_content_height(420)
+        .child(&history_box)
+        .build();
+    let history_btn = gtk::MenuButton::builder()
+        .icon_name("document-open-recent-symbolic")
+        .tooltip_text("Undo History")
+        .popover(&gtk::Popover::builder().child(&history_scroll).build())
+        .build();
+    history_btn.add_css_class("flat");
+
+    let info_grid = gtk::Grid::builder()
+        .row_spacing(6)
+        .column_spacing(12)
+        .margin_top(6)
+        .margin_bottom(6)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+
+    let info_btn = gtk::MenuButton::builder()
+        .icon_name("dialog-information-symbolic")
+        .tooltip_text("Capture Info")
+        .popover(&gtk::Popover::builder().child(&info_grid).build())
+        .build();
+    info_btn.add_css_class("flat");
+
+    let links_box = gtk::ListBox::builder()
+        .selection_mode(gtk::SelectionMode::None)
+        .build();
+    links_box.add_css_class("boxed-list");
+    let links_btn = gtk::MenuButton::builder()
+        .icon_name("insert-link-symbolic")
+        .tooltip_text("Find Links")
+        .popover(&gtk::Popover::builder().child(&links_box).build())
+        .build();
+    links_btn.add_css_class("flat");
+
+    let discard_btn = gtk::Button::builder()
+        .icon_name("user-trash-symbolic")
+        .tooltip_text("Discard Capture")
+        .build();
+    discard_btn.add_css_class("flat");
+
     let copy_btn = gtk::Button::builder()
         .icon_name("app-edit-copy-symbolic")
         .tooltip_text("Copy to Clipboard")
         .build();
     copy_btn.add_css_class("flat");
 
+    let export_btn = gtk::MenuButton::builder()
+        .icon_name("document-send-symbolic")
+        .tooltip_text("Export")
+        .build();
+    export_btn.add_css_class("flat");
+
     let save_btn = gtk::Button::builder()
         .icon_name("app-document-save-symbolic")
         .tooltip_text("Save")
@@ -136,25 +260,38 @@ pub fn create_toolbar(state: &Rc<RefCell<AppState>>) -> ToolbarComponents {
     tools_box.add_css_class("custom-toolbar");
     tools_box.add_css_class("toolbar");
 
+    // Buttons are added by `arrange`, in the user's order
     tools_box.append(&tool_buttons_box);
-    tools_box.append(&undo_btn);
-    tools_box.append(&copy_btn);
-    tools_box.append(&save_btn);
 
-    ToolbarComponents {
+    let toolbar = ToolbarComponents {
         tools_box,
+        tool_buttons_box,
         tool_pointer_btn,
         tool_pencil_btn,
         tool_rectangle_btn,
+        tool_redact_btn,
         tool_crop_btn,
         tool_text_btn,
+        tool_counter_btn,
         tool_color_picker_btn,
         color_button,
+        palette_btn,
         color_picker_circle,
         undo_btn,
+        redo_btn,
+        history_btn,
+        history_box,
+        info_btn,
+        info_grid,
+        links_btn,
+        links_box,
+        discard_btn,
         copy_btn,
+        export_btn,
         save_btn,
-    }
+    };
+    toolbar.arrange(&state.borrow().preferences.toolbar_layout);
+    toolbar
 }
 
 pub fn create_crop_toolbar() -> CropToolbarComponents {
@@ -181,6 +318,12 @@ pub fn create_crop_toolbar() -> CropToolbarComponents {
         .build();
     confirm_btn.add_css_class("suggested-action");
 
+    let reveal_btn = gtk::Button::builder()
+        .icon_name("view-reveal-symbolic")
+        .tooltip_text("Reveal Original")
+        .build();
+
+    crop_tools_box.append(&reveal_btn);
     crop_tools_box.append(&cancel_btn);
     crop_tools_box.append(&confirm_btn);
 
@@ -188,6 +331,7 @@ pub fn create_crop_toolbar() -> CropToolbarComponents {
         crop_tools_box,
         confirm_btn,
         cancel_btn,
+        reveal_btn,
     }
 }
 
@@ -203,6 +347,33 @@ pub fn create_selection_toolbar() -> SelectionToolbarComponents {
     selection_tools_box.add_css_class("custom-toolbar");
     selection_tools_box.add_css_class("toolbar");
 
+    let select_btn = gtk::ToggleButton::builder()
+        .icon_name("app-tool-crop-symbolic")
+        .tooltip_text("Select Area")
+        .active(true)
+        .build();
+    select_btn.add_css_class("flat");
+
+    let pencil_btn = gtk::ToggleButton::builder()
+        .icon_name("app-tool-pencil-symbolic")
+        .tooltip_text("Free Draw")
+        .group(&select_btn)
+        .build();
+    pencil_btn.add_css_class("flat");
+
+    let rectangle_btn = gtk::ToggleButton::builder()
+        .icon_name("app-tool-rectangle-symbolic")
+        .tooltip_text("Rectangle")
+        .group(&select_btn)
+        .build();
+    rectangle_btn.add_css_class("flat");
+
+    let size_btn = gtk::MenuButton::builder()
+        .label("Size")
+        .tooltip_text("Select a Fixed Size")
+        .build();
+    size_btn.add_css_class("flat");
+
     let cancel_btn = gtk::Button::builder()
         .icon_name("app-process-stop-symbolic")
         .tooltip_text("Cancel")
@@ -215,6 +386,10 @@ pub fn create_selection_toolbar() -> SelectionToolbarComponents {
         .build();
     confirm_btn.add_css_class("suggested-action");
 
+    selection_tools_box.append(&size_btn);
+    selection_tools_box.append(&select_btn);
+    selection_tools_box.append(&pencil_btn);
+    selection_tools_box.append(&rectangle_btn);
     selection_tools_box.append(&cancel_btn);
     selection_tools_box.append(&confirm_btn);
 
@@ -222,9 +397,221 @@ pub fn create_selection_toolbar() -> SelectionToolbarComponents {
         selection_tools_box,
         confirm_btn,
         cancel_btn,
+        select_btn,
+        pencil_btn,
+        rectangle_btn,
+        size_btn,
+    }
+}
+
+pub fn create_recording_toolbar() -> RecordingToolbarComponents {
+    let recording_tools_box = gtk::Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(12)
+        .halign(Align::Center)
+        .valign(Align::End)
+        .margin_bottom(24)
+        .visible(false)
+        .build();
+    recording_tools_box.add_css_class("custom-toolbar");
+    recording_tools_box.add_css_class("toolbar");
+
+    let indicator = gtk::Image::from_icon_name("media-record-symbolic");
+    indicator.add_css_class("error");
+
+    let duration_label = gtk::Label::builder().label("0:00").width_chars(7).build();
+    duration_label.add_css_class("numeric");
+
+    let pause_btn = gtk::Button::builder()
+        .icon_name("media-playback-pause-symbolic")
+        .tooltip_text("Pause")
+        .build();
+    pause_btn.add_css_class("flat");
+
+    let stop_btn = gtk::Button::builder()
+        .icon_name("media-playback-stop-symbolic")
+        .tooltip_text("Stop Recording")
+        .build();
+    stop_btn.add_css_class("destructive-action");
+
+    let system_audio_btn = gtk::ToggleButton::builder()
+        .icon_name("audio-speakers-symbolic")
+        .active(true)
+        .visible(false)
+        .build();
+    system_audio_btn.add_css_class("flat");
+
+    let microphone_btn = gtk::ToggleButton::builder()
+        .icon_name("audio-input-microphone-symbolic")
+        .active(true)
+        .visible(false)
+        .build();
+    microphone_btn.add_css_class("flat");
+
+    let annotate_btn = gtk::ToggleButton::builder()
+        .icon_name("app-tool-pencil-symbolic")
+        .tooltip_text("Annotate")
+        .build();
+    annotate_btn.add_css_class("flat");
+
+    let hide_btn = gtk::Button::builder()
+        .icon_name("window-minimize-symbolic")
+        .tooltip_text("Hide Window")
+        .build();
+    hide_btn.add_css_class("flat");
+
+    recording_tools_box.append(&indicator);
+    recording_tools_box.append(&duration_label);
+    recording_tools_box.append(&system_audio_btn);
+    recording_tools_box.append(&microphone_btn);
+    recording_tools_box.append(&annotate_btn);
+    recording_tools_box.append(&hide_btn);
+    recording_tools_box.append(&pause_btn);
+    recording_tools_box.append(&stop_btn);
+
+    RecordingToolbarComponents {
+        recording_tools_box,
+        duration_label,
+        pause_btn,
+        stop_btn,
+        system_audio_btn,
+        microphone_btn,
+        annotate_btn,
+        hide_btn,
+    }
+}
+
+impl RecordingToolbarComponents {
+    pub fn audio_button(&self, track: AudioTrack) -> &gtk::ToggleButton {
+        match track {
+            AudioTrack::System => &self.system_audio_btn,
+            AudioTrack::Microphone => &self.microphone_btn,
+        }
+    }
+
+    /// Show the buttons of the `tracks` recorded, each saying whether it is
+    /// muted
+    pub fn show_audio_tracks(&self, tracks: &[(AudioTrack, bool)]) {
+        for track in AudioTrack::ALL {
+            let button = self.audio_button(track);
+            let muted = match tracks.iter().find(|(t, _)| *t == track) {
+                Some((_, muted)) => *muted,
+                None => {
+                    button.set_visible(false);
+                    continue;
+                }
+            };
+            let icon_name = match (track, muted) {
+                (AudioTrack::System, false) => "audio-speakers-symbolic",
+                (AudioTrack::System, true) => "audio-volume-muted-symbolic",
+                (AudioTrack::Microphone, false) => "audio-input-microphone-symbolic",
+                (AudioTrack::Microphone, true) => "microphone-disabled-symbolic",
+            };
+            let tooltip = format!(
+                "{} {}",
+                if muted { "Unmute" } else { "Mute" },
+                track.label()
+            );
+            button.set_visible(true);
+            button.set_active(!muted);
+            button.set_icon_name(icon_name);
+            button.set_tooltip_text(Some(&tooltip));
+        }
     }
 }
 
+impl ToolbarComponents {
+    fn item_widget(&self, item: ToolbarItem) -> gtk::Widget {
+        match item {
+            ToolbarItem::Pointer => self.tool_pointer_btn.clone().upcast(),
+            ToolbarItem::Pencil => self.tool_pencil_btn.clone().upcast(),
+            ToolbarItem::Rectangle => self.tool_rectangle_btn.clone().upcast(),
+            ToolbarItem::Redact => self.tool_redact_btn.clone().upcast(),
+            ToolbarItem::Crop => self.tool_crop_btn.clone().upcast(),
+            ToolbarItem::Text => self.tool_text_btn.clone().upcast(),
+            ToolbarItem::Counter => self.tool_counter_btn.clone().upcast(),
+            ToolbarItem::ColorPicker => self.tool_color_picker_btn.clone().upcast(),
+            ToolbarItem::Color => self.color_button.clone().upcast(),
+            ToolbarItem::Palette => self.palette_btn.clone().upcast(),
+            ToolbarItem::Undo => self.undo_btn.clone().upcast(),
+            ToolbarItem::Redo => self.redo_btn.clone().upcast(),
+            ToolbarItem::History => self.history_btn.clone().upcast(),
+            ToolbarItem::Info => self.info_btn.clone().upcast(),
+            ToolbarItem::Links => self.links_btn.clone().upcast(),
+            ToolbarItem::Discard => self.discard_btn.clone().upcast(),
+            ToolbarItem::Copy => self.copy_btn.clone().upcast(),
+            ToolbarItem::Export => self.export_btn.clone().upcast(),
+            ToolbarItem::Save => self.save_btn.clone().upcast(),
+        }
+    }
+
+    /// Show the buttons of `layout` in its order. Tools stay grouped at the
+    /// start; hidden buttons keep working through their shortcuts.
+    pub fn arrange(&self, layout: &ToolbarLayout) {
+        for item in ToolbarItem::ALL {
+            let widget = self.item_widget(item);
+            if let Some(parent) = widget.parent().and_downcast::<gtk::Box>() {
+                parent.remove(&widget);
+            }
+        }
+
+        let mut has_tools = false;
+        for entry in layout.entries().iter().filter(|e| e.visible) {
+            let widget = self.item_widget(entry.item);
+            if entry.item.is_tool() {
+                self.tool_buttons_box.append(&widget);
+                has_tools = true;
+            } else {
+                self.tools_box.append(&widget);
+            }
+        }
+        self.tool_buttons_box.set_visible(has_tools);
+    }
+}
+
+/// Dock a floating toolbar to one edge of the canvas
+fn place_toolbar(toolbar: &gtk::Box, position: ToolbarPosition) {
+    let orientation = if position.is_vertical() {
+        Orientation::Vertical
+    } else {
+        Orientation::Horizontal
+    };
+    toolbar.set_orientation(orientation);
+
+    let (halign, valign) = match position {
+        ToolbarPosition::Top => (Align::Center, Align::Start),
+        ToolbarPosition::Bottom => (Align::Center, Align::End),
+        ToolbarPosition::Left => (Align::Start, Align::Center),
+        ToolbarPosition::Right => (Align::End, Align::Center),
+    };
+    toolbar.set_halign(halign);
+    toolbar.set_valign(valign);
+
+    let margin = |edge: ToolbarPosition| if edge == position { 24 } else { 0 };
+    toolbar.set_margin_top(margin(ToolbarPosition::Top));
+    toolbar.set_margin_bottom(margin(ToolbarPosition::Bottom));
+    toolbar.set_margin_start(margin(ToolbarPosition::Left));
+    toolbar.set_margin_end(margin(ToolbarPosition::Right));
+}
+
+/// Move the editor, crop, selection and recording toolbars, which take each
+/// other's place, to the same edge
+pub fn set_toolbar_position(
+    toolbar: &ToolbarComponents,
+    crop_toolbar: &CropToolbarComponents,
+    selection_toolbar: &SelectionToolbarComponents,
+    recording_toolbar: &RecordingToolbarComponents,
+    position: ToolbarPosition,
+) {
+    place_toolbar(&toolbar.tools_box, position);
+    toolbar
+        .tool_buttons_box
+        .set_orientation(toolbar.tools_box.orientation());
+    place_toolbar(&crop_toolbar.crop_tools_box, position);
+    place_toolbar(&selection_toolbar.selection_tools_box, position);
+    place_toolbar(&recording_toolbar.recording_tools_box, position);
+}
+
 fn create_color_picker_circle(state: &Rc<RefCell<AppState>>) -> gtk::DrawingArea {
     let color_picker_circle = gtk::DrawingArea::builder()
         .width_request(20)
@@ -260,6 +647,127 @@ fn create_color_picker_circle(state: &Rc<RefCell<AppState>>) -> gtk::DrawingArea
     color_picker_circle
 }
 
+fn create_swatch(color: RGBA, tooltip: &str) -> gtk::Button {
+    let swatch = gtk::DrawingArea::builder()
+        .width_request(20)
+        .height_request(20)
+        .build();
+    swatch.set_draw_func(move |_, cr, width, height| {
+        cr.arc(
+            width as f64 / 2.0,
+            height as f64 / 2.0,
+            (width as f64 / 2.0) - 2.0,
+            0.0,
+            2.0 * std::f64::consts::PI,
+        );
+        cr.set_source_rgba(
+            color.red() as f64,
+            color.green() as f64,
+            color.blue() as f64,
+            color.alpha() as f64,
+        );
+        let _ = cr.fill_preserve();
+        cr.set_source_rgba(0.0, 0.0, 0.0, 0.5);
+        cr.set_line_width(1.0);
+        let _ = cr.stroke();
+    });
+
+    let button = gtk::Button::builder()
+        .child(&swatch)
+        .tooltip_text(tooltip)
+        .build();
+    button.add_css_class("flat");
+    button
+}
+
+/// Popover with the GNOME palette and the user's own colors, so common
+/// annotation colors are one click away instead of behind the color dialog
+fn create_palette_button(
+    state: &Rc<RefCell<AppState>>,
+    color_button: &gtk::ColorDialogButton,
+) -> gtk::MenuButton {
+    let palette_grid = gtk::Grid::builder()
+        .row_spacing(2)
+        .column_spacing(2)
+        .build();
+    let custom_grid = gtk::Grid::builder()
+        .row_spacing(2)
+        .column_spacing(2)
+        .build();
+    let custom_label = gtk::Label::builder()
+        .label("Custom")
+        .halign(Align::Start)
+        .build();
+    custom_label.add_css_class("heading");
+
+    let content = gtk::Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(6)
+        .margin_top(6)
+        .margin_bottom(6)
+        .margin_start(6)
+        .margin_end(6)
+        .build();
+    content.append(&palette_grid);
+    content.append(&custom_label);
+    content.append(&custom_grid);
+
+    let popover = gtk::Popover::builder().child(&content).build();
+    let palette_btn = gtk::MenuButton::builder()
+        .icon_name("pan-down-symbolic")
+        .tooltip_text("Color Palette")
+        .popover(&popover)
+        .build();
+    palette_btn.add_css_class("flat");
+
+    // Custom colors change whenever the color dialog is used, and the
+    // palette whenever another preset is picked
+    popover.connect_show({
+        let state = state.clone();
+        let color_button = color_button.clone();
+        move |popover| {
+            while let Some(child) = palette_grid.first_child() {
+                palette_grid.remove(&child);
+            }
+
+            let preset = state.borrow().preferences.palette;
+            for (i, (name, color)) in palette_colors(preset).into_iter().enumerate() {
+                let swatch = create_swatch(color, name);
+                swatch.connect_clicked({
+                    let color_button = color_button.clone();
+                    let popover = popover.clone();
+                    move |_| {
+                        color_button.set_rgba(&color);
+                        popover.popdown();
+                    }
+                });
+                palette_grid.attach(&swatch, (i % 5) as i32, (i / 5) as i32, 1, 1);
+            }
+
+            while let Some(child) = custom_grid.first_child() {
+                custom_grid.remove(&child);
+            }
+
+            let colors = state.borrow().custom_colors.clone();
+            custom_label.set_visible(!colors.is_empty());
+            for (i, color) in colors.into_iter().enumerate() {
+                let swatch = create_swatch(color, &color.to_str());
+                swatch.connect_clicked({
+                    let color_button = color_button.clone();
+                    let popover = popover.clone();
+                    move |_| {
+                        color_button.set_rgba(&color);
+                        popover.popdown();
+                    }
+                });
+                custom_grid.attach(&swatch, (i % 5) as i32, (i / 5) as i32, 1, 1);
+            }
+        }
+    });
+
+    palette_btn
+}
+
 fn connect_color_button(
     state: &Rc<RefCell<AppState>>,
     color_button: &gtk::ColorDialogButton,
@@ -270,7 +778,14 @@ fn connect_color_button(
         let color_picker_circle = color_picker_circle.clone();
         move |btn| {
             let color = btn.rgba();
-            state.borrow_mut().editor.set_color(color);
+            let mut s = state.borrow_mut();
+            s.editor.set_color(color);
+            if remember_custom_color(&mut s.custom_colors, color) {
+                if let Err(e) = save_custom_colors(&s.custom_colors) {
+                    warn!("Failed to save custom colors: {}", e);
+                }
+            }
+            drop(s);
             color_picker_circle.queue_draw();
         }
     });
@@ -315,6 +830,17 @@ pub fn connect_tool_buttons(
         }
     });
 
+    components.tool_redact_btn.connect_toggled({
+        let state = state.clone();
+        move |btn| {
+            if btn.is_active() {
+                let mut s = state.borrow_mut();
+                s.editor.set_tool(EditorTool::Redact);
+                s.is_crop_mode = false;
+            }
+        }
+    });
+
     components.tool_crop_btn.connect_toggled({
         let state = state.clone();
         let tools_box = tools_box.clone();
@@ -342,6 +868,17 @@ pub fn connect_tool_buttons(
         }
     });
 
+    components.tool_counter_btn.connect_toggled({
+        let state = state.clone();
+        move |btn| {
+            if btn.is_active() {
+                let mut s = state.borrow_mut();
+                s.editor.set_tool(EditorTool::Counter);
+                s.is_crop_mode = false;
+            }
+        }
+    });
+
     components.tool_color_picker_btn.connect_toggled({
         let state = state.clone();
         move |btn| {